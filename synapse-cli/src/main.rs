@@ -380,16 +380,22 @@ async fn start_dashboard() -> Result<()> {
 async fn start_mcp_server(transport: McpTransport, port: u16) -> Result<()> {
     use synapse_mcp::{create_server, Database, Config};
 
-    // MCP server should ALWAYS use the global database at ~/.synapse/synapse.db
-    let database_path = get_global_database_path();
-    let db_url = format!("sqlite://{}", database_path.to_string_lossy());
-    
-    // Ensure global data directory exists
-    let global_data_dir = database_path.parent().unwrap();
-    std::fs::create_dir_all(global_data_dir)?;
-    
-    tracing::info!("MCP server using global database: {}", database_path.display());
-    let db = Database::new(&db_url).await?;
+    // A shared deployment sets DATABASE_URL (e.g. postgres://...), matching the web
+    // dashboard; otherwise the MCP server uses the global database at ~/.synapse/synapse.db
+    let db = if let Ok(db_url) = std::env::var("DATABASE_URL") {
+        tracing::info!("MCP server using database from DATABASE_URL");
+        Database::new(&db_url).await?
+    } else {
+        let database_path = get_global_database_path();
+        let db_url = format!("sqlite://{}", database_path.to_string_lossy());
+
+        // Ensure global data directory exists
+        let global_data_dir = database_path.parent().unwrap();
+        std::fs::create_dir_all(global_data_dir)?;
+
+        tracing::info!("MCP server using global database: {}", database_path.display());
+        Database::new(&db_url).await?
+    };
     
     // Create server configuration
     let config = Config::default();
@@ -0,0 +1,7 @@
+-- Unify the CLI, MCP and web views of projects and analyses
+
+-- The CLI and web models have always called this column synapse_config
+ALTER TABLE projects RENAME COLUMN loglens_config TO synapse_config;
+
+-- Log file path or command an analysis read from when no uploaded log file is attached
+ALTER TABLE analyses ADD COLUMN source TEXT;
@@ -0,0 +1,34 @@
+-- Full-text search over the knowledge base
+--
+-- External-content FTS5 index kept in sync with knowledge_base by triggers. The
+-- PostgreSQL schema provides the same search through a generated tsvector column.
+
+CREATE VIRTUAL TABLE knowledge_base_fts USING fts5(
+    title,
+    tags,
+    problem_description,
+    solution,
+    content='knowledge_base',
+    content_rowid='rowid',
+    tokenize='porter unicode61'
+);
+
+INSERT INTO knowledge_base_fts (rowid, title, tags, problem_description, solution)
+SELECT rowid, title, coalesce(tags, ''), problem_description, solution FROM knowledge_base;
+
+CREATE TRIGGER knowledge_base_fts_insert AFTER INSERT ON knowledge_base BEGIN
+    INSERT INTO knowledge_base_fts (rowid, title, tags, problem_description, solution)
+    VALUES (new.rowid, new.title, coalesce(new.tags, ''), new.problem_description, new.solution);
+END;
+
+CREATE TRIGGER knowledge_base_fts_delete AFTER DELETE ON knowledge_base BEGIN
+    INSERT INTO knowledge_base_fts (knowledge_base_fts, rowid, title, tags, problem_description, solution)
+    VALUES ('delete', old.rowid, old.title, coalesce(old.tags, ''), old.problem_description, old.solution);
+END;
+
+CREATE TRIGGER knowledge_base_fts_update AFTER UPDATE OF title, tags, problem_description, solution ON knowledge_base BEGIN
+    INSERT INTO knowledge_base_fts (knowledge_base_fts, rowid, title, tags, problem_description, solution)
+    VALUES ('delete', old.rowid, old.title, coalesce(old.tags, ''), old.problem_description, old.solution);
+    INSERT INTO knowledge_base_fts (rowid, title, tags, problem_description, solution)
+    VALUES (new.rowid, new.title, coalesce(new.tags, ''), new.problem_description, new.solution);
+END;
@@ -0,0 +1,34 @@
+-- Project membership with per-project roles, and a history of membership changes
+
+CREATE TABLE project_members (
+    project_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    role TEXT NOT NULL CHECK (role IN ('viewer', 'analyst', 'admin')),
+    added_by TEXT,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (project_id, user_id),
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (added_by) REFERENCES users(id) ON DELETE SET NULL
+);
+
+CREATE INDEX idx_project_members_user_id ON project_members(user_id);
+
+-- old_role is NULL when a member is added, new_role is NULL when one is removed
+CREATE TABLE project_member_changes (
+    id TEXT PRIMARY KEY,
+    project_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    changed_by TEXT,
+    old_role TEXT,
+    new_role TEXT,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_project_member_changes_project ON project_member_changes(project_id, created_at);
+
+-- Creators of existing projects become their administrators
+INSERT INTO project_members (project_id, user_id, role, added_by)
+SELECT id, created_by, 'admin', created_by FROM projects WHERE created_by IS NOT NULL;
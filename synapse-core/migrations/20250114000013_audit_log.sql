@@ -0,0 +1,29 @@
+-- Append-only record of who did what: project, file, analysis, settings, share and
+-- streaming source changes. Rows are never updated or deleted, even with their project.
+
+CREATE TABLE audit_log (
+    id TEXT PRIMARY KEY,
+    occurred_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    actor_id TEXT,
+    actor_name TEXT,
+    action TEXT NOT NULL,
+    project_id TEXT,
+    target_type TEXT,
+    target_id TEXT,
+    details TEXT, -- JSON object
+    ip_address TEXT
+);
+
+CREATE INDEX idx_audit_log_occurred_at ON audit_log(occurred_at);
+CREATE INDEX idx_audit_log_project ON audit_log(project_id, occurred_at);
+CREATE INDEX idx_audit_log_actor ON audit_log(actor_id, occurred_at);
+
+CREATE TRIGGER audit_log_no_update BEFORE UPDATE ON audit_log
+BEGIN
+    SELECT RAISE(ABORT, 'audit_log is append-only');
+END;
+
+CREATE TRIGGER audit_log_no_delete BEFORE DELETE ON audit_log
+BEGIN
+    SELECT RAISE(ABORT, 'audit_log is append-only');
+END;
@@ -0,0 +1,36 @@
+-- Public links to a single analysis, with optional password, expiry, revocation and
+-- redaction of what the shared view shows. Passwords are stored as argon2 hashes.
+
+CREATE TABLE shares (
+    id TEXT PRIMARY KEY,
+    project_id TEXT NOT NULL,
+    analysis_id TEXT NOT NULL,
+    created_by TEXT,
+    password_hash TEXT, -- NULL when the link is not password protected
+    expires_at DATETIME NOT NULL,
+    allow_download BOOLEAN NOT NULL DEFAULT TRUE,
+    redact_sensitive BOOLEAN NOT NULL DEFAULT TRUE, -- mask emails, IP addresses and secrets
+    include_correlations BOOLEAN NOT NULL DEFAULT TRUE,
+    include_metrics BOOLEAN NOT NULL DEFAULT TRUE,
+    access_count INTEGER NOT NULL DEFAULT 0,
+    last_accessed_at DATETIME,
+    revoked_at DATETIME,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE,
+    FOREIGN KEY (analysis_id) REFERENCES analyses(id) ON DELETE CASCADE,
+    FOREIGN KEY (created_by) REFERENCES users(id) ON DELETE SET NULL
+);
+
+CREATE INDEX idx_shares_project ON shares(project_id, created_at);
+
+-- Share links used to be stored as knowledge base entries titled
+-- "Share Link for Analysis <id>"; move them here, unchanged: public and unredacted
+INSERT INTO shares (id, project_id, analysis_id, expires_at, allow_download, redact_sensitive, created_at)
+SELECT id, project_id, substr(title, 25), json_extract(solution, '$.expires_at'),
+       coalesce(json_extract(solution, '$.allow_download'), 1), 0, created_at
+FROM knowledge_base
+WHERE tags LIKE '%"share"%' AND title LIKE 'Share Link for Analysis %'
+  AND substr(title, 25) IN (SELECT id FROM analyses);
+
+DELETE FROM knowledge_base
+WHERE tags LIKE '%"share"%' AND title LIKE 'Share Link for Analysis %';
@@ -0,0 +1,42 @@
+-- Durable queue of analyses to run, so queued and interrupted work survives a restart.
+-- Each process claims jobs from its own queue ('web' for the dashboard, 'mcp' for the
+-- MCP server) and holds a lease while running them; a job whose lease lapses is requeued.
+
+CREATE TABLE analysis_jobs (
+    id TEXT PRIMARY KEY,
+    analysis_id TEXT NOT NULL,
+    project_id TEXT NOT NULL,
+    queue TEXT NOT NULL DEFAULT 'web',
+    status TEXT NOT NULL DEFAULT 'queued', -- queued, running, completed, failed or cancelled
+    priority INTEGER NOT NULL DEFAULT 0, -- higher runs first
+    attempts INTEGER NOT NULL DEFAULT 0,
+    max_attempts INTEGER NOT NULL DEFAULT 3,
+    timeout_secs INTEGER NOT NULL DEFAULT 300,
+    payload TEXT, -- JSON run options; API keys in it are sealed with the master key
+    run_after DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    worker_id TEXT,
+    lease_expires_at DATETIME,
+    last_error TEXT,
+    created_by TEXT,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    started_at DATETIME,
+    finished_at DATETIME,
+    FOREIGN KEY (analysis_id) REFERENCES analyses(id) ON DELETE CASCADE,
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE,
+    FOREIGN KEY (created_by) REFERENCES users(id) ON DELETE SET NULL
+);
+
+CREATE INDEX idx_analysis_jobs_claim ON analysis_jobs(queue, status, priority, run_after);
+CREATE INDEX idx_analysis_jobs_analysis ON analysis_jobs(analysis_id);
+CREATE INDEX idx_analysis_jobs_project ON analysis_jobs(project_id, created_at);
+
+-- File analyses left pending or running by earlier releases were only ever picked up by
+-- the dashboard's polling task; queue them for the dashboard
+INSERT INTO analysis_jobs (id, analysis_id, project_id, timeout_secs, created_by)
+SELECT lower(hex(randomblob(16))), id, project_id,
+       coalesce((SELECT analysis_timeout_seconds FROM settings WHERE id = 1), 300), created_by
+FROM analyses
+WHERE status IN (0, 1) AND analysis_type = 'file' AND log_file_id IS NOT NULL;
+
+UPDATE analyses SET status = 0
+WHERE status = 1 AND id IN (SELECT analysis_id FROM analysis_jobs);
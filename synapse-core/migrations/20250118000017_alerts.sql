@@ -0,0 +1,49 @@
+-- Alert rules evaluated against streaming log batches, and the alerts they fire.
+-- An open alert absorbs further matches with the same fingerprint until it is resolved.
+
+CREATE TABLE alert_rules (
+    id TEXT PRIMARY KEY,
+    project_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    description TEXT,
+    condition TEXT NOT NULL, -- JSON, tagged by "type"
+    severity TEXT NOT NULL DEFAULT 'warning', -- info, warning or critical
+    source TEXT, -- only evaluate batches from this streaming source; NULL for all
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    cooldown_secs INTEGER NOT NULL DEFAULT 300, -- minimum time between new alerts
+    silenced_until DATETIME,
+    silence_reason TEXT,
+    last_fired_at DATETIME,
+    created_by TEXT,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE,
+    FOREIGN KEY (created_by) REFERENCES users(id) ON DELETE SET NULL
+);
+
+CREATE INDEX idx_alert_rules_project ON alert_rules(project_id);
+
+CREATE TABLE alerts (
+    id TEXT PRIMARY KEY,
+    rule_id TEXT NOT NULL,
+    project_id TEXT NOT NULL,
+    rule_name TEXT NOT NULL,
+    severity TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'firing', -- firing, acknowledged or resolved
+    fingerprint TEXT NOT NULL, -- what a repeat match must share to be deduplicated
+    source TEXT,
+    summary TEXT NOT NULL,
+    details TEXT, -- JSON: measured values and sample entries
+    occurrences INTEGER NOT NULL DEFAULT 1,
+    first_seen_at DATETIME NOT NULL,
+    last_seen_at DATETIME NOT NULL,
+    acknowledged_by TEXT,
+    acknowledged_at DATETIME,
+    resolved_at DATETIME,
+    FOREIGN KEY (rule_id) REFERENCES alert_rules(id) ON DELETE CASCADE,
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE,
+    FOREIGN KEY (acknowledged_by) REFERENCES users(id) ON DELETE SET NULL
+);
+
+CREATE INDEX idx_alerts_project ON alerts(project_id, first_seen_at);
+CREATE INDEX idx_alerts_fingerprint ON alerts(rule_id, fingerprint, status);
@@ -0,0 +1,29 @@
+-- Schedules running AI analysis over a streaming source's recent entries, on a cron
+-- expression or whenever an alert rule fires. The realtime analyses they start record
+-- the alert that triggered them.
+
+CREATE TABLE analysis_schedules (
+    id TEXT PRIMARY KEY,
+    project_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    source TEXT, -- streaming source to read; NULL for all
+    cron TEXT, -- five-field cron expression, evaluated in UTC
+    trigger_rule_id TEXT, -- alert rule whose alerts start a run
+    window_secs INTEGER NOT NULL DEFAULT 900, -- how far back the analyzed entries reach
+    provider TEXT NOT NULL,
+    level_filter TEXT NOT NULL DEFAULT 'ERROR',
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    last_run_at DATETIME,
+    last_analysis_id TEXT,
+    created_by TEXT,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE,
+    FOREIGN KEY (trigger_rule_id) REFERENCES alert_rules(id) ON DELETE SET NULL,
+    FOREIGN KEY (last_analysis_id) REFERENCES analyses(id) ON DELETE SET NULL,
+    FOREIGN KEY (created_by) REFERENCES users(id) ON DELETE SET NULL
+);
+
+CREATE INDEX idx_analysis_schedules_project ON analysis_schedules(project_id);
+
+ALTER TABLE analyses ADD COLUMN alert_id TEXT REFERENCES alerts(id) ON DELETE SET NULL;
@@ -0,0 +1,4 @@
+-- HTTP endpoint streaming sources authenticate ingest requests with a token of their own;
+-- only its SHA-256 digest is stored.
+
+ALTER TABLE streaming_sources ADD COLUMN ingest_token_hash TEXT;
@@ -0,0 +1,69 @@
+-- Durable storage for streaming log entries, replacing the unused streaming_logs table
+--
+-- Entries are grouped into hourly partitions by when they were received, so retention
+-- drops whole hours at a time; streaming_log_partitions keeps each partition's entry
+-- count and size for the per-project size limit. streaming_log_counts holds per-minute
+-- entry counts by source and level for dashboards. Messages are searchable through an
+-- external-content FTS5 index; the PostgreSQL schema uses a generated tsvector column.
+
+DROP TABLE IF EXISTS streaming_logs;
+
+CREATE TABLE streaming_logs (
+    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+    id TEXT NOT NULL,
+    project_id TEXT NOT NULL,
+    source TEXT NOT NULL, -- streaming source id
+    partition_start DATETIME NOT NULL, -- hour the entry was received in
+    timestamp DATETIME NOT NULL, -- the entry's own time, or when it was received
+    level TEXT,
+    severity INTEGER, -- 0 (TRACE) to 5 (FATAL); NULL when the level is not recognised
+    message TEXT NOT NULL,
+    line_number INTEGER,
+    metadata TEXT, -- JSON object
+    size_bytes INTEGER NOT NULL,
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_streaming_logs_time ON streaming_logs(project_id, timestamp, seq);
+CREATE INDEX idx_streaming_logs_source ON streaming_logs(project_id, source, timestamp);
+CREATE INDEX idx_streaming_logs_severity ON streaming_logs(project_id, severity, timestamp);
+CREATE INDEX idx_streaming_logs_partition ON streaming_logs(project_id, partition_start);
+
+CREATE VIRTUAL TABLE streaming_logs_fts USING fts5(
+    message,
+    content='streaming_logs',
+    content_rowid='seq',
+    tokenize='unicode61'
+);
+
+CREATE TRIGGER streaming_logs_fts_insert AFTER INSERT ON streaming_logs BEGIN
+    INSERT INTO streaming_logs_fts (rowid, message) VALUES (new.seq, new.message);
+END;
+
+CREATE TRIGGER streaming_logs_fts_delete AFTER DELETE ON streaming_logs BEGIN
+    INSERT INTO streaming_logs_fts (streaming_logs_fts, rowid, message) VALUES ('delete', old.seq, old.message);
+END;
+
+CREATE TABLE streaming_log_partitions (
+    project_id TEXT NOT NULL,
+    partition_start DATETIME NOT NULL,
+    entries INTEGER NOT NULL,
+    size_bytes INTEGER NOT NULL,
+    PRIMARY KEY (project_id, partition_start),
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+);
+
+CREATE TABLE streaming_log_counts (
+    project_id TEXT NOT NULL,
+    minute DATETIME NOT NULL, -- by the entries' own time
+    source TEXT NOT NULL,
+    level TEXT NOT NULL, -- normalised level, or UNKNOWN
+    entries INTEGER NOT NULL,
+    PRIMARY KEY (project_id, minute, source, level),
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+);
+
+-- Per-project retention of stored streaming entries. NULL uses the server default
+-- (SYNAPSE_LOG_RETENTION_DAYS / SYNAPSE_LOG_RETENTION_MB); 0 means no limit.
+ALTER TABLE projects ADD COLUMN log_retention_days INTEGER;
+ALTER TABLE projects ADD COLUMN log_retention_mb INTEGER;
@@ -0,0 +1,179 @@
+-- PostgreSQL schema equivalent to the SQLite migrations up to 20250109000008
+--
+-- Column types follow what the Rust models bind and decode: timestamps written by
+-- the repository are TIMESTAMPTZ, while the streaming tables keep the RFC 3339
+-- strings they are written with as TEXT.
+
+CREATE TABLE projects (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    description TEXT,
+    root_path TEXT,
+    synapse_config TEXT,
+    project_type TEXT DEFAULT 'unknown',
+    last_accessed TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_projects_root_path ON projects(root_path);
+CREATE INDEX idx_projects_last_accessed ON projects(last_accessed DESC);
+
+CREATE TABLE log_files (
+    id TEXT PRIMARY KEY,
+    project_id TEXT NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    filename TEXT NOT NULL,
+    file_size BIGINT NOT NULL,
+    line_count BIGINT NOT NULL,
+    upload_path TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_log_files_project_id ON log_files(project_id);
+
+CREATE TABLE analyses (
+    id TEXT PRIMARY KEY,
+    project_id TEXT NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    log_file_id TEXT REFERENCES log_files(id) ON DELETE SET NULL,
+    analysis_type TEXT NOT NULL, -- 'file', 'command' or 'realtime'
+    provider TEXT NOT NULL,
+    level_filter TEXT NOT NULL,
+    status INTEGER NOT NULL DEFAULT 0, -- 0=pending, 1=running, 2=completed, 3=failed
+    result TEXT, -- JSON serialized AnalysisResponse
+    error_message TEXT,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    completed_at TIMESTAMPTZ,
+    source TEXT
+);
+
+CREATE INDEX idx_analyses_project_id ON analyses(project_id);
+CREATE INDEX idx_analyses_log_file_id ON analyses(log_file_id);
+CREATE INDEX idx_analyses_status ON analyses(status);
+CREATE INDEX idx_analyses_started_at ON analyses(started_at DESC);
+CREATE INDEX idx_analyses_project_started ON analyses(project_id, started_at DESC);
+
+CREATE TABLE error_patterns (
+    id TEXT PRIMARY KEY,
+    project_id TEXT NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    pattern TEXT NOT NULL, -- Regex pattern for matching errors
+    category TEXT NOT NULL, -- code, infrastructure, config, external
+    description TEXT,
+    frequency INTEGER NOT NULL DEFAULT 1,
+    last_seen TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    suggested_solution TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_error_patterns_project_id ON error_patterns(project_id);
+CREATE INDEX idx_error_patterns_category ON error_patterns(category);
+CREATE INDEX idx_error_patterns_frequency ON error_patterns(frequency DESC);
+
+CREATE TABLE knowledge_base (
+    id TEXT PRIMARY KEY,
+    project_id TEXT NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    title TEXT NOT NULL,
+    problem_description TEXT NOT NULL,
+    solution TEXT NOT NULL,
+    tags TEXT, -- JSON array of tags
+    severity TEXT NOT NULL DEFAULT 'medium',
+    is_public BOOLEAN NOT NULL DEFAULT FALSE,
+    usage_count INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    -- Full-text search document, the counterpart of the SQLite knowledge_base_fts table
+    search_vector TSVECTOR GENERATED ALWAYS AS (
+        setweight(to_tsvector('english', coalesce(title, '')), 'A') ||
+        setweight(to_tsvector('english', coalesce(tags, '')), 'B') ||
+        setweight(to_tsvector('english', coalesce(problem_description, '')), 'C') ||
+        setweight(to_tsvector('english', coalesce(solution, '')), 'D')
+    ) STORED
+);
+
+CREATE INDEX idx_knowledge_base_project_id ON knowledge_base(project_id);
+CREATE INDEX idx_knowledge_base_severity ON knowledge_base(severity);
+CREATE INDEX idx_knowledge_base_usage ON knowledge_base(usage_count DESC);
+CREATE INDEX idx_knowledge_base_search ON knowledge_base USING GIN (search_vector);
+
+CREATE TABLE error_correlations (
+    id TEXT PRIMARY KEY,
+    project_id TEXT NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    primary_error_id TEXT NOT NULL REFERENCES analyses(id) ON DELETE CASCADE,
+    correlated_error_id TEXT NOT NULL REFERENCES analyses(id) ON DELETE CASCADE,
+    correlation_strength DOUBLE PRECISION NOT NULL,
+    correlation_type TEXT NOT NULL, -- temporal, causal, contextual
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_error_correlations_project_id ON error_correlations(project_id);
+CREATE INDEX idx_error_correlations_primary_error ON error_correlations(primary_error_id);
+CREATE INDEX idx_error_correlations_strength ON error_correlations(correlation_strength DESC);
+
+CREATE TABLE performance_metrics (
+    id TEXT PRIMARY KEY,
+    analysis_id TEXT NOT NULL REFERENCES analyses(id) ON DELETE CASCADE,
+    metric_name TEXT NOT NULL,
+    metric_value DOUBLE PRECISION NOT NULL,
+    unit TEXT NOT NULL,
+    threshold_value DOUBLE PRECISION,
+    is_bottleneck BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_performance_metrics_analysis_id ON performance_metrics(analysis_id);
+CREATE INDEX idx_performance_metrics_bottleneck ON performance_metrics(is_bottleneck);
+
+CREATE TABLE settings (
+    id INTEGER PRIMARY KEY,
+    default_provider TEXT NOT NULL DEFAULT 'openai',
+    api_key TEXT NOT NULL DEFAULT '',
+    max_lines INTEGER NOT NULL DEFAULT 1000,
+    default_level TEXT NOT NULL DEFAULT 'INFO',
+    show_timestamps BOOLEAN NOT NULL DEFAULT TRUE,
+    show_line_numbers BOOLEAN NOT NULL DEFAULT TRUE,
+    selected_model TEXT,
+    available_models TEXT, -- JSON array cache
+    models_last_fetched TEXT, -- ISO datetime
+    analysis_timeout_seconds INTEGER DEFAULT 300,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO settings (id, default_provider, api_key, max_lines, default_level, show_timestamps, show_line_numbers)
+VALUES (1, 'openrouter', '', 1000, 'ERROR', TRUE, TRUE);
+
+CREATE TABLE streaming_sources (
+    id TEXT PRIMARY KEY,
+    project_id TEXT NOT NULL REFERENCES projects(id),
+    name TEXT NOT NULL,
+    source_type TEXT NOT NULL,
+    config TEXT NOT NULL, -- JSON
+    parser_config TEXT, -- JSON
+    buffer_size INTEGER DEFAULT 100,
+    batch_timeout_seconds INTEGER DEFAULT 2,
+    restart_on_error BOOLEAN DEFAULT TRUE,
+    max_restarts INTEGER,
+    status TEXT DEFAULT 'active',
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+CREATE INDEX idx_streaming_sources_project ON streaming_sources(project_id);
+CREATE INDEX idx_streaming_sources_status ON streaming_sources(status);
+
+CREATE TABLE streaming_logs (
+    id TEXT PRIMARY KEY,
+    project_id TEXT NOT NULL REFERENCES projects(id),
+    source_id TEXT NOT NULL REFERENCES streaming_sources(id),
+    timestamp TEXT NOT NULL,
+    level TEXT,
+    message TEXT NOT NULL,
+    source TEXT NOT NULL,
+    line_number BIGINT,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX idx_streaming_logs_project ON streaming_logs(project_id);
+CREATE INDEX idx_streaming_logs_source ON streaming_logs(source_id);
+CREATE INDEX idx_streaming_logs_timestamp ON streaming_logs(timestamp);
+CREATE INDEX idx_streaming_logs_level ON streaming_logs(level);
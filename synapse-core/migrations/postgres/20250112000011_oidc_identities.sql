@@ -0,0 +1,6 @@
+-- Link accounts provisioned through OpenID Connect single sign-on to their provider identity
+
+ALTER TABLE users ADD COLUMN oidc_issuer TEXT;
+ALTER TABLE users ADD COLUMN oidc_subject TEXT;
+
+CREATE UNIQUE INDEX idx_users_oidc_identity ON users(oidc_issuer, oidc_subject);
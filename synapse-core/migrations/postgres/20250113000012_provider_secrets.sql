@@ -0,0 +1,12 @@
+-- Provider API keys, one per provider, encrypted with AES-256-GCM under the master key.
+-- The plaintext settings.api_key is moved here on startup and then left empty.
+
+CREATE TABLE provider_secrets (
+    provider TEXT PRIMARY KEY,
+    key_id TEXT NOT NULL, -- fingerprint of the master key that sealed the value
+    nonce TEXT NOT NULL,
+    ciphertext TEXT NOT NULL,
+    hint TEXT NOT NULL, -- masked key for display, e.g. ****abcd
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,28 @@
+-- Append-only record of who did what: project, file, analysis, settings, share and
+-- streaming source changes. Rows are never updated or deleted, even with their project.
+
+CREATE TABLE audit_log (
+    id TEXT PRIMARY KEY,
+    occurred_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    actor_id TEXT,
+    actor_name TEXT,
+    action TEXT NOT NULL,
+    project_id TEXT,
+    target_type TEXT,
+    target_id TEXT,
+    details TEXT, -- JSON object
+    ip_address TEXT
+);
+
+CREATE INDEX idx_audit_log_occurred_at ON audit_log(occurred_at);
+CREATE INDEX idx_audit_log_project ON audit_log(project_id, occurred_at);
+CREATE INDEX idx_audit_log_actor ON audit_log(actor_id, occurred_at);
+
+CREATE FUNCTION audit_log_append_only() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'audit_log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_log_append_only BEFORE UPDATE OR DELETE ON audit_log
+FOR EACH ROW EXECUTE FUNCTION audit_log_append_only();
@@ -0,0 +1,33 @@
+-- Public links to a single analysis, with optional password, expiry, revocation and
+-- redaction of what the shared view shows. Passwords are stored as argon2 hashes.
+
+CREATE TABLE shares (
+    id TEXT PRIMARY KEY,
+    project_id TEXT NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    analysis_id TEXT NOT NULL REFERENCES analyses(id) ON DELETE CASCADE,
+    created_by TEXT REFERENCES users(id) ON DELETE SET NULL,
+    password_hash TEXT, -- NULL when the link is not password protected
+    expires_at TIMESTAMPTZ NOT NULL,
+    allow_download BOOLEAN NOT NULL DEFAULT TRUE,
+    redact_sensitive BOOLEAN NOT NULL DEFAULT TRUE, -- mask emails, IP addresses and secrets
+    include_correlations BOOLEAN NOT NULL DEFAULT TRUE,
+    include_metrics BOOLEAN NOT NULL DEFAULT TRUE,
+    access_count BIGINT NOT NULL DEFAULT 0,
+    last_accessed_at TIMESTAMPTZ,
+    revoked_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_shares_project ON shares(project_id, created_at);
+
+-- Share links used to be stored as knowledge base entries titled
+-- "Share Link for Analysis <id>"; move them here, unchanged: public and unredacted
+INSERT INTO shares (id, project_id, analysis_id, expires_at, allow_download, redact_sensitive, created_at)
+SELECT id, project_id, substr(title, 25), (solution::json ->> 'expires_at')::timestamptz,
+       coalesce((solution::json ->> 'allow_download')::boolean, TRUE), FALSE, created_at
+FROM knowledge_base
+WHERE tags LIKE '%"share"%' AND title LIKE 'Share Link for Analysis %'
+  AND substr(title, 25) IN (SELECT id FROM analyses);
+
+DELETE FROM knowledge_base
+WHERE tags LIKE '%"share"%' AND title LIKE 'Share Link for Analysis %';
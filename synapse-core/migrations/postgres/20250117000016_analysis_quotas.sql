@@ -0,0 +1,4 @@
+-- Per-project cap on analyses started per UTC day. NULL uses the server default
+-- (SYNAPSE_DAILY_ANALYSIS_QUOTA); 0 means no limit for this project.
+
+ALTER TABLE projects ADD COLUMN daily_analysis_quota INTEGER;
@@ -0,0 +1,44 @@
+-- Alert rules evaluated against streaming log batches, and the alerts they fire.
+-- An open alert absorbs further matches with the same fingerprint until it is resolved.
+
+CREATE TABLE alert_rules (
+    id TEXT PRIMARY KEY,
+    project_id TEXT NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    description TEXT,
+    condition TEXT NOT NULL, -- JSON, tagged by "type"
+    severity TEXT NOT NULL DEFAULT 'warning', -- info, warning or critical
+    source TEXT, -- only evaluate batches from this streaming source; NULL for all
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    cooldown_secs BIGINT NOT NULL DEFAULT 300, -- minimum time between new alerts
+    silenced_until TIMESTAMPTZ,
+    silence_reason TEXT,
+    last_fired_at TIMESTAMPTZ,
+    created_by TEXT REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_alert_rules_project ON alert_rules(project_id);
+
+CREATE TABLE alerts (
+    id TEXT PRIMARY KEY,
+    rule_id TEXT NOT NULL REFERENCES alert_rules(id) ON DELETE CASCADE,
+    project_id TEXT NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    rule_name TEXT NOT NULL,
+    severity TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'firing', -- firing, acknowledged or resolved
+    fingerprint TEXT NOT NULL, -- what a repeat match must share to be deduplicated
+    source TEXT,
+    summary TEXT NOT NULL,
+    details TEXT, -- JSON: measured values and sample entries
+    occurrences BIGINT NOT NULL DEFAULT 1,
+    first_seen_at TIMESTAMPTZ NOT NULL,
+    last_seen_at TIMESTAMPTZ NOT NULL,
+    acknowledged_by TEXT REFERENCES users(id) ON DELETE SET NULL,
+    acknowledged_at TIMESTAMPTZ,
+    resolved_at TIMESTAMPTZ
+);
+
+CREATE INDEX idx_alerts_project ON alerts(project_id, first_seen_at);
+CREATE INDEX idx_alerts_fingerprint ON alerts(rule_id, fingerprint, status);
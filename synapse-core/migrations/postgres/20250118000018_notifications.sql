@@ -0,0 +1,42 @@
+-- Notification channels and the log of messages delivered through them.
+-- Deliveries stay pending until they succeed or run out of attempts, so retries survive a restart.
+
+CREATE TABLE notification_channels (
+    id TEXT PRIMARY KEY,
+    project_id TEXT NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    channel_type TEXT NOT NULL, -- webhook, slack, email or command
+    config TEXT NOT NULL, -- JSON; secrets sealed with the master key
+    notify_alerts BOOLEAN NOT NULL DEFAULT TRUE,
+    notify_analyses BOOLEAN NOT NULL DEFAULT FALSE,
+    min_severity TEXT NOT NULL DEFAULT 'info', -- alerts below this severity are not sent
+    title_template TEXT,
+    body_template TEXT,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_by TEXT REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_notification_channels_project ON notification_channels(project_id);
+
+CREATE TABLE notification_deliveries (
+    id TEXT PRIMARY KEY,
+    channel_id TEXT NOT NULL REFERENCES notification_channels(id) ON DELETE CASCADE,
+    project_id TEXT NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    event TEXT NOT NULL, -- alert.fired, analysis.completed, ...
+    event_id TEXT, -- the alert or analysis the message is about
+    status TEXT NOT NULL DEFAULT 'pending', -- pending, delivered or failed
+    attempts INTEGER NOT NULL DEFAULT 0,
+    max_attempts INTEGER NOT NULL,
+    payload TEXT NOT NULL, -- JSON: the rendered message
+    last_error TEXT,
+    response_status INTEGER, -- HTTP status or exit code of the last attempt
+    next_attempt_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    delivered_at TIMESTAMPTZ
+);
+
+CREATE INDEX idx_notification_deliveries_project ON notification_deliveries(project_id, created_at);
+CREATE INDEX idx_notification_deliveries_due ON notification_deliveries(status, next_attempt_at);
@@ -0,0 +1,25 @@
+-- Schedules running AI analysis over a streaming source's recent entries, on a cron
+-- expression or whenever an alert rule fires. The realtime analyses they start record
+-- the alert that triggered them.
+
+CREATE TABLE analysis_schedules (
+    id TEXT PRIMARY KEY,
+    project_id TEXT NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    source TEXT, -- streaming source to read; NULL for all
+    cron TEXT, -- five-field cron expression, evaluated in UTC
+    trigger_rule_id TEXT REFERENCES alert_rules(id) ON DELETE SET NULL, -- alert rule whose alerts start a run
+    window_secs BIGINT NOT NULL DEFAULT 900, -- how far back the analyzed entries reach
+    provider TEXT NOT NULL,
+    level_filter TEXT NOT NULL DEFAULT 'ERROR',
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    last_run_at TIMESTAMPTZ,
+    last_analysis_id TEXT REFERENCES analyses(id) ON DELETE SET NULL,
+    created_by TEXT REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_analysis_schedules_project ON analysis_schedules(project_id);
+
+ALTER TABLE analyses ADD COLUMN alert_id TEXT REFERENCES alerts(id) ON DELETE SET NULL;
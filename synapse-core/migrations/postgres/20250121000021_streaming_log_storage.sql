@@ -0,0 +1,53 @@
+-- Durable storage for streaming log entries, replacing the unused streaming_logs table
+--
+-- Entries are grouped into hourly partitions by when they were received, so retention
+-- drops whole hours at a time; streaming_log_partitions keeps each partition's entry
+-- count and size for the per-project size limit. streaming_log_counts holds per-minute
+-- entry counts by source and level for dashboards. Messages are searchable through a
+-- generated tsvector column, the counterpart of the SQLite streaming_logs_fts table.
+
+DROP TABLE IF EXISTS streaming_logs;
+
+CREATE TABLE streaming_logs (
+    seq BIGSERIAL PRIMARY KEY,
+    id TEXT NOT NULL,
+    project_id TEXT NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    source TEXT NOT NULL, -- streaming source id
+    partition_start TIMESTAMPTZ NOT NULL, -- hour the entry was received in
+    timestamp TIMESTAMPTZ NOT NULL, -- the entry's own time, or when it was received
+    level TEXT,
+    severity INTEGER, -- 0 (TRACE) to 5 (FATAL); NULL when the level is not recognised
+    message TEXT NOT NULL,
+    line_number BIGINT,
+    metadata TEXT, -- JSON object
+    size_bytes BIGINT NOT NULL,
+    search_vector TSVECTOR GENERATED ALWAYS AS (to_tsvector('simple', message)) STORED
+);
+
+CREATE INDEX idx_streaming_logs_time ON streaming_logs(project_id, timestamp, seq);
+CREATE INDEX idx_streaming_logs_source ON streaming_logs(project_id, source, timestamp);
+CREATE INDEX idx_streaming_logs_severity ON streaming_logs(project_id, severity, timestamp);
+CREATE INDEX idx_streaming_logs_partition ON streaming_logs(project_id, partition_start);
+CREATE INDEX idx_streaming_logs_search ON streaming_logs USING GIN (search_vector);
+
+CREATE TABLE streaming_log_partitions (
+    project_id TEXT NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    partition_start TIMESTAMPTZ NOT NULL,
+    entries BIGINT NOT NULL,
+    size_bytes BIGINT NOT NULL,
+    PRIMARY KEY (project_id, partition_start)
+);
+
+CREATE TABLE streaming_log_counts (
+    project_id TEXT NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    minute TIMESTAMPTZ NOT NULL, -- by the entries' own time
+    source TEXT NOT NULL,
+    level TEXT NOT NULL, -- normalised level, or UNKNOWN
+    entries BIGINT NOT NULL,
+    PRIMARY KEY (project_id, minute, source, level)
+);
+
+-- Per-project retention of stored streaming entries. NULL uses the server default
+-- (SYNAPSE_LOG_RETENTION_DAYS / SYNAPSE_LOG_RETENTION_MB); 0 means no limit.
+ALTER TABLE projects ADD COLUMN log_retention_days INTEGER;
+ALTER TABLE projects ADD COLUMN log_retention_mb INTEGER;
@@ -31,6 +31,20 @@ pub enum AIError {
     UnsupportedProvider(String),
 }
 
+impl AIError {
+    /// Short name for the kind of failure, for metrics and logs
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AIError::RequestError(e) if e.is_timeout() => "timeout",
+            AIError::RequestError(_) => "request",
+            AIError::InvalidResponse(_) => "invalid_response",
+            AIError::AuthenticationError => "authentication",
+            AIError::RateLimited => "rate_limited",
+            AIError::UnsupportedProvider(_) => "unsupported_provider",
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AnalysisRequest {
     pub payload: AIAnalysisPayload,
@@ -1,5 +1,5 @@
 use crate::ai_provider::{
-    AIProvider, AnalysisFocus, AnalysisRequest, AnalysisResponse, AnomalyAnalysisSimple,
+    AIError, AIProvider, AnalysisFocus, AnalysisRequest, AnalysisResponse, AnomalyAnalysisSimple,
     ErrorAnalysis, PatternAnalysisSimple, PerformanceAnalysisSimple, RootCauseAnalysis,
 };
 use crate::classification::ErrorCategory;
@@ -8,7 +8,7 @@ use crate::input::LogEntry;
 use crate::slimmer::{slim_logs_with_mode, SlimmingMode};
 use anyhow::Result;
 use std::collections::HashMap;
-use tracing::{debug, info, warn};
+use tracing::{debug, field::Empty, info, info_span, warn, Instrument};
 
 #[derive(Debug, Clone, PartialEq)]
 enum ProcessingStrategy {
@@ -118,7 +118,25 @@ impl Analyzer {
         }
     }
 
+    /// Send one request to the AI provider, in a span of its own
+    async fn call_provider(&self, request: AnalysisRequest) -> Result<AnalysisResponse, AIError> {
+        let span = info_span!(
+            "ai.provider.analyze",
+            provider = self.provider.get_provider_name(),
+            otel.kind = "client",
+            otel.status_code = Empty,
+            error.kind = Empty,
+        );
+        let result = self.provider.analyze(request).instrument(span.clone()).await;
+        if let Err(e) = &result {
+            span.record("otel.status_code", "ERROR");
+            span.record("error.kind", e.kind());
+        }
+        result
+    }
+
     /// Split logs into manageable chunks for processing
+    #[tracing::instrument(name = "logs.chunk", skip_all, fields(entries = entries.len()))]
     fn create_chunks(&self, entries: Vec<LogEntry>) -> Result<Vec<LogChunk>> {
         if entries.is_empty() {
             return Ok(vec![]);
@@ -267,7 +285,7 @@ impl Analyzer {
             analysis_focus: AnalysisFocus::RootCause,
         };
 
-        let response = self.provider.analyze(analysis_request).await?;
+        let response = self.call_provider(analysis_request).await?;
 
         // Enhance with analytics
         let enhanced_response = self.enhance_with_analytics(response, &entries);
@@ -323,7 +341,7 @@ impl Analyzer {
             analysis_focus: AnalysisFocus::RootCause,
         };
 
-        let mut response = self.provider.analyze(analysis_request).await?;
+        let mut response = self.call_provider(analysis_request).await?;
 
         // Add note about aggressive slimming
         response.sequence_of_events = format!(
@@ -500,7 +518,7 @@ impl Analyzer {
             analysis_focus: AnalysisFocus::RootCause,
         };
 
-        let response = self.provider.analyze(analysis_request).await?;
+        let response = self.call_provider(analysis_request).await?;
 
         Ok(ChunkAnalysisResult {
             chunk_id: chunk.chunk_id,
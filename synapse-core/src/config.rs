@@ -4,13 +4,15 @@ use std::fs;
 use std::path::PathBuf;
 use anyhow::Result;
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::secrets::{self, Keyring};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub providers: ProviderConfig,
     pub defaults: DefaultConfig,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProviderConfig {
     pub openrouter: Option<ProviderSettings>,
     pub openai: Option<ProviderSettings>,
@@ -25,10 +27,10 @@ pub struct ProviderSettings {
     pub timeout: Option<u64>,
     pub max_tokens: Option<u32>,
     pub temperature: Option<f32>,
-    pub api_key: Option<String>,
+    pub api_key: Option<String>, // sealed with the master key when saved (see `crate::secrets`)
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DefaultConfig {
     pub provider: Option<String>,
     pub log_level: Option<String>,
@@ -112,16 +114,27 @@ impl Config {
             return Some(key);
         }
         
-        match provider.to_lowercase().as_str() {
-            "openrouter" => self.providers.openrouter.as_ref().and_then(|p| p.api_key.clone()),
-            "openai" => self.providers.openai.as_ref().and_then(|p| p.api_key.clone()),
-            "claude" | "anthropic" => {
-                self.providers.claude.as_ref()
-                    .or(self.providers.anthropic.as_ref())
-                    .and_then(|p| p.api_key.clone())
+        let (section, settings) = match provider.to_lowercase().as_str() {
+            "openrouter" => ("openrouter", self.providers.openrouter.as_ref()),
+            "openai" => ("openai", self.providers.openai.as_ref()),
+            "claude" | "anthropic" => match self.providers.claude.as_ref() {
+                Some(settings) => ("claude", Some(settings)),
+                None => ("anthropic", self.providers.anthropic.as_ref()),
             },
-            "gemini" => self.providers.gemini.as_ref().and_then(|p| p.api_key.clone()),
-            _ => None,
+            "gemini" => ("gemini", self.providers.gemini.as_ref()),
+            _ => return None,
+        };
+        let api_key = settings?.api_key.clone()?;
+
+        if !secrets::is_sealed_string(&api_key) {
+            return Some(api_key);
+        }
+        match Keyring::load().and_then(|keyring| keyring.open_string(section, &api_key)) {
+            Ok(api_key) => Some(api_key),
+            Err(e) => {
+                tracing::warn!("Failed to decrypt the {} API key from the config file: {}", section, e);
+                None
+            }
         }
     }
     
@@ -194,6 +207,35 @@ impl Config {
         }
     }
     
+    /// Copy of the config with every plaintext API key sealed under the master key
+    fn with_sealed_api_keys(&self) -> Result<Self> {
+        let mut sealed = self.clone();
+        let providers = &mut sealed.providers;
+        let sections = [
+            ("openrouter", &mut providers.openrouter),
+            ("openai", &mut providers.openai),
+            ("claude", &mut providers.claude),
+            ("gemini", &mut providers.gemini),
+            ("anthropic", &mut providers.anthropic),
+        ];
+
+        let mut keyring = None;
+        for (section, settings) in sections {
+            let Some(api_key) = settings.as_mut().and_then(|p| p.api_key.as_mut()) else {
+                continue;
+            };
+            if secrets::is_sealed_string(api_key) {
+                continue;
+            }
+            let keyring = match &keyring {
+                Some(keyring) => keyring,
+                None => keyring.insert(Keyring::load()?),
+            };
+            *api_key = keyring.seal_to_string(section, api_key)?;
+        }
+        Ok(sealed)
+    }
+
     pub fn save(&self) -> Result<()> {
         // Determine where to save the config
         let config_path = Self::get_config_path()
@@ -211,8 +253,8 @@ impl Config {
             fs::create_dir_all(parent)?;
         }
         
-        // Serialize config to TOML
-        let toml_content = toml::to_string_pretty(self)?;
+        // Serialize config to TOML, never writing API keys in plaintext
+        let toml_content = toml::to_string_pretty(&self.with_sealed_api_keys()?)?;
         
         // Write to file
         fs::write(&config_path, toml_content)?;
@@ -226,8 +268,8 @@ impl Config {
             fs::create_dir_all(parent)?;
         }
         
-        // Serialize config to TOML
-        let toml_content = toml::to_string_pretty(self)?;
+        // Serialize config to TOML, never writing API keys in plaintext
+        let toml_content = toml::to_string_pretty(&self.with_sealed_api_keys()?)?;
         
         // Write to file
         fs::write(path, toml_content)?;
@@ -320,4 +362,25 @@ mod tests {
         assert!(openrouter_settings.is_some());
         assert_eq!(openrouter_settings.unwrap().model, Some("deepseek/deepseek-chat-v3.1:free".to_string()));
     }
+
+    #[test]
+    fn test_saved_api_keys_are_sealed() {
+        let dir = tempfile::tempdir().unwrap();
+        env::set_var(secrets::MASTER_KEY_FILE_ENV, dir.path().join("master.key"));
+
+        let mut config = Config::default();
+        config.set_api_key("gemini", "gm-plaintext-key".to_string());
+        let path = dir.path().join("config.toml");
+        config.save_to_path(&path).unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(!content.contains("gm-plaintext-key"));
+        let loaded: Config = toml::from_str(&content).unwrap();
+        assert!(secrets::is_sealed_string(loaded.providers.gemini.as_ref().unwrap().api_key.as_ref().unwrap()));
+        if env::var("GEMINI_API_KEY").is_err() {
+            assert_eq!(loaded.get_api_key("gemini").as_deref(), Some("gm-plaintext-key"));
+        }
+
+        env::remove_var(secrets::MASTER_KEY_FILE_ENV);
+    }
 }
\ No newline at end of file
@@ -13,6 +13,7 @@ pub struct LogEntry {
     pub line_number: Option<usize>,
 }
 
+#[tracing::instrument(name = "file.decode", skip_all, fields(bytes = tracing::field::Empty, encoding = tracing::field::Empty))]
 pub async fn read_log_file(file_path: &str) -> Result<Vec<String>> {
     info!("Reading log file: {}", file_path);
     
@@ -20,6 +21,7 @@ pub async fn read_log_file(file_path: &str) -> Result<Vec<String>> {
     let data = match fs::read(file_path) {
         Ok(data) => {
             debug!("Read {} bytes from file {}", data.len(), file_path);
+            tracing::Span::current().record("bytes", data.len());
             data
         }
         Err(e) => {
@@ -31,6 +33,7 @@ pub async fn read_log_file(file_path: &str) -> Result<Vec<String>> {
     // Detect encoding and create decoder
     let (encoding, _confidence, decoder) = detect_and_create_decoder(&data);
     info!("Detected encoding {} for file {}", encoding.name(), file_path);
+    tracing::Span::current().record("encoding", encoding.name());
     
     // Process line by line for better error recovery
     let lines = decode_lines_robust(&data, &decoder)?;
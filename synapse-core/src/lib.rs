@@ -3,7 +3,7 @@
 // This library provides the core functionality for log analysis that can be
 // used both by the CLI binary and MCP integrations.
 
-use tracing::{info, error, debug, warn};
+use tracing::{info, info_span, error, debug, warn};
 
 pub mod ai_provider;
 pub mod analyzer;
@@ -16,6 +16,7 @@ pub mod filter;
 pub mod input;
 pub mod output;
 pub mod parser;
+pub mod secrets;
 pub mod slimmer;
 
 #[cfg(feature = "project-management")]
@@ -33,7 +34,7 @@ pub use filter::filter_logs_by_level;
 pub use input::{execute_and_capture, read_log_file, LogEntry};
 pub use output::{generate_report, save_report, OutputFormat, AnalysisReport};
 pub use parser::parse_log_lines;
-pub use slimmer::{slim_logs, slim_logs_with_mode, SlimmingMode};
+pub use slimmer::{extract_pattern, slim_logs, slim_logs_with_mode, SlimmingMode};
 
 // Convenience functions for direct usage (backward compatibility)
 
@@ -244,36 +245,10 @@ impl Synapse {
         api_key: Option<&str>,
         selected_model: Option<&str>,
     ) -> Result<AnalysisResponse> {
-        // Parse logs
-        let parsed_entries = parse_log_lines(&raw_lines);
-
-        // Filter by level
-        let filtered_entries = filter_logs_by_level(parsed_entries, level)?;
-
-        if filtered_entries.is_empty() {
-            return Ok(AnalysisResponse {
-                sequence_of_events: "No log entries found matching the specified level.".to_string(),
-                root_cause: RootCauseAnalysis {
-                    category: ErrorCategory::UnknownRelated,
-                    description: "No errors to analyze".to_string(),
-                    file_location: None,
-                    line_number: None,
-                    function_name: None,
-                    confidence: 0.0,
-                },
-                recommendations: vec!["Provide valid log entries for analysis".to_string()],
-                confidence: 0.0,
-                related_errors: vec![],
-                unrelated_errors: vec![],
-                errors_found: None,
-                patterns: None,
-                performance: None,
-                anomalies: None,
-            });
-        }
-
-        // Slim logs
-        let slimmed_entries = slim_logs(filtered_entries);
+        let slimmed_entries = match Self::prepare_entries(&raw_lines, level)? {
+            Some(entries) => entries,
+            None => return Ok(Self::no_entries_response()),
+        };
 
         // Get API key with precedence: parameter > config > env
         let api_key = match api_key {
@@ -294,6 +269,49 @@ impl Synapse {
             selected_model.map(|s| s.to_string())
         )?;
 
+        Self::run_analyzer(provider, slimmed_entries).await
+    }
+
+    /// Analyze raw log lines with an already constructed provider
+    ///
+    /// Used by integrations that supply their own model access, such as MCP
+    /// sampling, where no Synapse-side API key is involved.
+    pub async fn analyze_lines_with_provider(
+        &self,
+        raw_lines: Vec<String>,
+        level: &str,
+        provider: Box<dyn AIProvider>,
+    ) -> Result<AnalysisResponse> {
+        let slimmed_entries = match Self::prepare_entries(&raw_lines, level)? {
+            Some(entries) => entries,
+            None => return Ok(Self::no_entries_response()),
+        };
+
+        Self::run_analyzer(provider, slimmed_entries).await
+    }
+
+    /// Parse, filter and slim raw lines; `None` when nothing matches the level
+    fn prepare_entries(raw_lines: &[String], level: &str) -> Result<Option<Vec<LogEntry>>> {
+        // Parse logs
+        let parsed_entries = info_span!("logs.parse", lines = raw_lines.len())
+            .in_scope(|| parse_log_lines(raw_lines));
+
+        // Filter by level
+        let filtered_entries = info_span!("logs.filter", level, entries = parsed_entries.len())
+            .in_scope(|| filter_logs_by_level(parsed_entries, level))?;
+
+        if filtered_entries.is_empty() {
+            return Ok(None);
+        }
+
+        // Slim logs
+        let slimmed_entries = info_span!("logs.slim", entries = filtered_entries.len())
+            .in_scope(|| slim_logs(filtered_entries));
+        Ok(Some(slimmed_entries))
+    }
+
+    /// Run the chunking analyzer over prepared entries
+    async fn run_analyzer(provider: Box<dyn AIProvider>, slimmed_entries: Vec<LogEntry>) -> Result<AnalysisResponse> {
         // Configure analyzer for large logs
         let analysis_config = Self::create_analysis_config(slimmed_entries.len(), false);
 
@@ -301,6 +319,29 @@ impl Synapse {
         analyzer.analyze_logs(slimmed_entries).await
     }
 
+    /// Response returned when no log entries match the requested level
+    fn no_entries_response() -> AnalysisResponse {
+        AnalysisResponse {
+            sequence_of_events: "No log entries found matching the specified level.".to_string(),
+            root_cause: RootCauseAnalysis {
+                category: ErrorCategory::UnknownRelated,
+                description: "No errors to analyze".to_string(),
+                file_location: None,
+                line_number: None,
+                function_name: None,
+                confidence: 0.0,
+            },
+            recommendations: vec!["Provide valid log entries for analysis".to_string()],
+            confidence: 0.0,
+            related_errors: vec![],
+            unrelated_errors: vec![],
+            errors_found: None,
+            patterns: None,
+            performance: None,
+            anomalies: None,
+        }
+    }
+
     /// Generate a full analysis report
     pub async fn generate_full_report(
         &self,
@@ -38,6 +38,9 @@ pub struct McpSection {
     pub enabled: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub server_port: Option<u16>,
+    /// Command prefixes MCP clients may run for analysis (e.g. "kubectl logs")
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_commands: Vec<String>,
 }
 
 fn default_auto_analyze() -> bool {
@@ -76,6 +79,7 @@ impl ProjectConfig {
             mcp: McpSection {
                 enabled: default_mcp_enabled(),
                 server_port: None,
+                allowed_commands: Vec::new(),
             },
         }
     }
@@ -96,6 +100,15 @@ impl ProjectConfig {
         Self::from_toml_str(&content)
     }
 
+    /// Load the configuration of the project rooted at `root_path`; `None` when it has none
+    pub async fn load_from_root<P: AsRef<Path>>(root_path: P) -> Result<Option<Self>> {
+        let path = root_path.as_ref().join(".synapse").join("config.toml");
+        if !tokio::fs::try_exists(&path).await? {
+            return Ok(None);
+        }
+        Self::load(path).await.map(Some)
+    }
+
     /// Save configuration to file
     pub async fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
         let toml_string = self.to_toml_string()?;
@@ -168,6 +181,28 @@ server_port = 3000
         assert_eq!(config.synapse.default_provider, "claude");
         assert_eq!(config.synapse.default_level, "WARN");
         assert_eq!(config.mcp.server_port, Some(3000));
+        assert!(config.mcp.allowed_commands.is_empty());
+    }
+
+    #[test]
+    fn test_config_allowed_commands() {
+        let toml_str = r#"
+[project]
+name = "k8s-service"
+type = "node"
+root_path = "/srv/k8s-service"
+created_at = "2025-10-06T12:00:00Z"
+
+[synapse]
+
+[mcp]
+enabled = true
+allowed_commands = ["kubectl logs", "docker logs"]
+"#;
+
+        let config = ProjectConfig::from_toml_str(toml_str).unwrap();
+
+        assert_eq!(config.mcp.allowed_commands, vec!["kubectl logs", "docker logs"]);
     }
 
     #[test]
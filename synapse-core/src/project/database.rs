@@ -34,53 +34,142 @@ pub async fn create_pool<P: AsRef<Path>>(database_path: P) -> Result<SqlitePool>
     Ok(pool)
 }
 
-/// Create database schema from migration SQL
+/// Versioned migrations shared by the CLI, web backend and MCP server
 #[cfg(feature = "project-management")]
-pub async fn create_schema(pool: &SqlitePool) -> Result<()> {
-    info!("Creating database schema");
+pub static MIGRATOR: sqlx::migrate::Migrator = sqlx::migrate!("./migrations");
 
-    let schema_sql = include_str!("../../migrations/001_initial_schema.sql");
+/// PostgreSQL counterpart of [`MIGRATOR`], kept schema-equivalent to the SQLite migrations
+#[cfg(feature = "postgres")]
+pub static POSTGRES_MIGRATOR: sqlx::migrate::Migrator = sqlx::migrate!("./migrations/postgres");
 
-    // Parse SQL statements more carefully - account for PRAGMA and multi-line statements
-    let mut statements = Vec::new();
-    let mut current_statement = String::new();
+/// Bring a PostgreSQL database up to the current schema
+#[cfg(feature = "postgres")]
+pub async fn run_postgres_migrations(pool: &sqlx::PgPool) -> Result<()> {
+    POSTGRES_MIGRATOR
+        .run(pool)
+        .await
+        .context("Failed to run PostgreSQL migrations")?;
 
-    for line in schema_sql.lines() {
-        let trimmed = line.trim();
+    Ok(())
+}
 
-        // Skip empty lines and standalone comments
-        if trimmed.is_empty() || (trimmed.starts_with("--") && current_statement.is_empty()) {
-            continue;
-        }
+/// Bring a database up to the current schema
+///
+/// Databases created by older CLI versions used a separate schema (string
+/// statuses, `log_file_path`, `analysis_results`). Those are detected and
+/// their projects and analyses are carried over into the versioned schema.
+#[cfg(feature = "project-management")]
+pub async fn run_migrations(pool: &SqlitePool) -> Result<()> {
+    let legacy = has_legacy_schema(pool).await?;
+    if legacy {
+        info!("Upgrading legacy project database to the versioned schema");
+        set_aside_legacy_tables(pool).await?;
+    }
 
-        // Add line to current statement (skip inline comments)
-        if let Some(pos) = trimmed.find("--") {
-            current_statement.push_str(&trimmed[..pos]);
-        } else {
-            current_statement.push_str(trimmed);
-        }
-        current_statement.push(' ');
-
-        // If line ends with semicolon, statement is complete
-        if trimmed.ends_with(';') {
-            let stmt = current_statement.trim().trim_end_matches(';').trim().to_string();
-            if !stmt.is_empty() {
-                statements.push(stmt);
-            }
-            current_statement.clear();
-        }
+    MIGRATOR
+        .run(pool)
+        .await
+        .context("Failed to run database migrations")?;
+
+    if legacy {
+        import_legacy_tables(pool).await?;
     }
 
-    // Execute each statement
-    for statement in statements {
-        debug!("Executing: {}", &statement[..statement.len().min(80)]);
-        sqlx::query(&statement)
-            .execute(pool)
-            .await
-            .with_context(|| format!("Failed to execute schema statement: {}", &statement[..statement.len().min(200)]))?;
+    debug!("Database migrations applied");
+    Ok(())
+}
+
+/// Check for the pre-migration CLI schema
+#[cfg(feature = "project-management")]
+async fn has_legacy_schema(pool: &SqlitePool) -> Result<bool> {
+    let count: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM pragma_table_info('analyses') WHERE name = 'log_file_path'"
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to inspect analyses table")?;
+
+    Ok(count > 0)
+}
+
+/// Rename the legacy tables out of the way and drop their indexes, whose names
+/// collide with the ones created by the migrations
+#[cfg(feature = "project-management")]
+async fn set_aside_legacy_tables(pool: &SqlitePool) -> Result<()> {
+    let mut tx = pool.begin().await?;
+
+    let indexes: Vec<(String,)> = sqlx::query_as(
+        "SELECT name FROM sqlite_master
+         WHERE type = 'index' AND sql IS NOT NULL
+         AND tbl_name IN ('projects', 'analyses', 'analysis_results')"
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    for (index,) in indexes {
+        sqlx::query(&format!("DROP INDEX IF EXISTS \"{}\"", index))
+            .execute(&mut *tx)
+            .await?;
     }
 
-    info!("Database schema created successfully");
+    for table in ["analysis_results", "analyses", "projects"] {
+        sqlx::query(&format!("ALTER TABLE {} RENAME TO legacy_{}", table, table))
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Copy legacy projects and analyses into the migrated tables, then drop the
+/// legacy tables
+#[cfg(feature = "project-management")]
+async fn import_legacy_tables(pool: &SqlitePool) -> Result<()> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        "INSERT INTO projects (id, name, description, root_path, project_type, created_at, updated_at)
+         SELECT id, name, description, root_path, 'unknown', created_at, updated_at
+         FROM legacy_projects"
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Legacy results were free-form summaries rather than an AnalysisResponse,
+    // so they are kept as a JSON object in the result column
+    sqlx::query(
+        "INSERT INTO analyses (id, project_id, log_file_id, analysis_type, provider, level_filter,
+                               status, result, started_at, completed_at, source)
+         SELECT a.id, a.project_id, NULL, 'file', a.provider, a.level,
+                CASE lower(a.status)
+                    WHEN 'running' THEN 1
+                    WHEN 'completed' THEN 2
+                    WHEN 'failed' THEN 3
+                    ELSE 0
+                END,
+                CASE WHEN r.analysis_id IS NULL THEN NULL ELSE json_object(
+                    'summary', r.summary,
+                    'full_report', r.full_report,
+                    'patterns_detected', json(COALESCE(r.patterns_detected, '[]')),
+                    'issues_found', r.issues_found
+                ) END,
+                COALESCE(a.started_at, a.created_at), a.completed_at, a.log_file_path
+         FROM legacy_analyses a
+         LEFT JOIN legacy_analysis_results r ON r.analysis_id = a.id
+         WHERE a.project_id IN (SELECT id FROM projects)"
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    for table in ["legacy_analysis_results", "legacy_analyses", "legacy_projects"] {
+        sqlx::query(&format!("DROP TABLE {}", table))
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+    info!("Imported legacy projects and analyses");
     Ok(())
 }
 
@@ -91,7 +180,7 @@ pub async fn verify_schema(pool: &SqlitePool) -> Result<bool> {
 
     // Check if all required tables exist
     let tables: Vec<(String,)> = sqlx::query_as(
-        "SELECT name FROM sqlite_master WHERE type='table' AND name IN ('projects', 'analyses', 'analysis_results')"
+        "SELECT name FROM sqlite_master WHERE type='table' AND name IN ('projects', 'log_files', 'analyses')"
     )
     .fetch_all(pool)
     .await
@@ -112,7 +201,7 @@ pub async fn verify_schema(pool: &SqlitePool) -> Result<bool> {
 #[cfg(feature = "project-management")]
 pub async fn initialize_database<P: AsRef<Path>>(database_path: P) -> Result<SqlitePool> {
     let pool = create_pool(&database_path).await?;
-    create_schema(&pool).await?;
+    run_migrations(&pool).await?;
 
     // Verify schema was created correctly
     if !verify_schema(&pool).await? {
@@ -123,6 +212,22 @@ pub async fn initialize_database<P: AsRef<Path>>(database_path: P) -> Result<Sql
     Ok(pool)
 }
 
+/// Open the central database shared with the web dashboard and MCP server,
+/// applying migrations so it can be used before the web server has ever run
+#[cfg(feature = "project-management")]
+pub async fn open_central_database() -> Result<SqlitePool> {
+    let db_path = crate::db_path::get_database_path();
+
+    // Ensure database directory exists
+    if let Some(parent) = db_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let pool = create_pool(&db_path).await?;
+    run_migrations(&pool).await?;
+    Ok(pool)
+}
+
 /// Register project in SQLite database for web dashboard visibility
 ///
 /// This function registers a project in the central SQLite database that the web
@@ -134,80 +239,24 @@ pub async fn register_in_database(
     root_path: &Path,
     synapse_dir: &Path,
 ) -> Result<()> {
-    use sqlx::sqlite::SqliteConnectOptions;
-    use sqlx::ConnectOptions;
-    use std::str::FromStr;
-
-    // Get the unified database path
-    let db_path = crate::db_path::get_database_path();
-
-    // Ensure database directory exists
-    if let Some(parent) = db_path.parent() {
-        std::fs::create_dir_all(parent)?;
-    }
-
-    // Connect to database
-    // Note: We don't create_if_missing because the database should be initialized
-    // by the web server's migration system. If it doesn't exist, that's an error.
-    let db_url = format!("sqlite://{}", db_path.to_string_lossy());
-    let mut conn = SqliteConnectOptions::from_str(&db_url)?
-        .create_if_missing(false)
-        .connect()
-        .await
-        .context("Failed to connect to database. Please start the web server at least once to initialize the database.")?;
-
-    // Check if project already exists
-    // Note: The projects table should exist from migrations. If it doesn't, this will fail.
-    let exists: bool = sqlx::query_scalar(
-        "SELECT COUNT(*) > 0 FROM projects WHERE id = ? OR root_path = ?"
-    )
-    .bind(&metadata.project_id)
-    .bind(root_path.to_string_lossy().as_ref())
-    .fetch_one(&mut conn)
-    .await?;
-
-    if exists {
-        // Update existing project
-        sqlx::query(
-            "UPDATE projects SET
-                name = ?,
-                description = NULL,
-                root_path = ?,
-                synapse_config = ?,
-                project_type = ?,
-                last_accessed = CURRENT_TIMESTAMP,
-                updated_at = CURRENT_TIMESTAMP
-            WHERE id = ? OR root_path = ?"
-        )
-        .bind(&metadata.project_name)
-        .bind(root_path.to_string_lossy().as_ref())
-        .bind(synapse_dir.to_string_lossy().as_ref())
-        .bind(&metadata.project_type)
-        .bind(&metadata.project_id)
-        .bind(root_path.to_string_lossy().as_ref())
-        .execute(&mut conn)
-        .await?;
-
-        debug!("Updated project {} in database", metadata.project_id);
-    } else {
-        // Insert new project
-        sqlx::query(
-            "INSERT INTO projects (
-                id, name, description, root_path, synapse_config,
-                project_type, last_accessed, created_at, updated_at
-            ) VALUES (?, ?, NULL, ?, ?, ?, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)"
-        )
-        .bind(&metadata.project_id)
-        .bind(&metadata.project_name)
-        .bind(root_path.to_string_lossy().as_ref())
-        .bind(synapse_dir.to_string_lossy().as_ref())
-        .bind(&metadata.project_type)
-        .execute(&mut conn)
-        .await?;
-
+    let pool = open_central_database().await?;
+
+    let project = crate::project::models::Project::new_cli_project(
+        metadata.project_id.clone(),
+        metadata.project_name.clone(),
+        None,
+        root_path.to_string_lossy().to_string(),
+        Some(synapse_dir.to_string_lossy().to_string()),
+        metadata.project_type.clone(),
+    );
+
+    if crate::project::queries::upsert_cli_project(&pool, &project).await? {
         info!("Registered project {} in database", metadata.project_id);
+    } else {
+        debug!("Updated project {} in database", metadata.project_id);
     }
 
+    pool.close().await;
     Ok(())
 }
 
@@ -217,10 +266,6 @@ pub async fn register_in_database(
 /// called when unlinking a project to ensure it no longer appears in the web dashboard.
 #[cfg(feature = "project-management")]
 pub async fn unregister_from_database(project_id: &str) -> Result<()> {
-    use sqlx::sqlite::SqliteConnectOptions;
-    use sqlx::ConnectOptions;
-    use std::str::FromStr;
-
     let db_path = crate::db_path::get_database_path();
 
     // Skip if database doesn't exist
@@ -229,15 +274,9 @@ pub async fn unregister_from_database(project_id: &str) -> Result<()> {
         return Ok(());
     }
 
-    let db_url = format!("sqlite://{}", db_path.to_string_lossy());
-    let mut conn = SqliteConnectOptions::from_str(&db_url)?
-        .connect()
-        .await?;
-
-    sqlx::query("DELETE FROM projects WHERE id = ?")
-        .bind(project_id)
-        .execute(&mut conn)
-        .await?;
+    let pool = create_pool(&db_path).await?;
+    crate::project::queries::delete_project(&pool, project_id).await?;
+    pool.close().await;
 
     info!("Unregistered project {} from database", project_id);
     Ok(())
@@ -259,12 +298,12 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_create_schema() {
+    async fn test_run_migrations() {
         let temp_dir = tempdir().unwrap();
         let db_path = temp_dir.path().join("test.db");
 
         let pool = create_pool(&db_path).await.unwrap();
-        create_schema(&pool).await.unwrap();
+        run_migrations(&pool).await.unwrap();
 
         // Verify tables exist
         let tables: Vec<(String,)> = sqlx::query_as(
@@ -276,8 +315,12 @@ mod tests {
 
         let table_names: Vec<String> = tables.into_iter().map(|t| t.0).collect();
         assert!(table_names.contains(&"projects".to_string()));
+        assert!(table_names.contains(&"log_files".to_string()));
         assert!(table_names.contains(&"analyses".to_string()));
-        assert!(table_names.contains(&"analysis_results".to_string()));
+        assert!(table_names.contains(&"knowledge_base".to_string()));
+
+        // Running again is a no-op
+        run_migrations(&pool).await.unwrap();
     }
 
     #[tokio::test]
@@ -292,11 +335,62 @@ mod tests {
         assert!(!verified);
 
         // Should succeed after schema creation
-        create_schema(&pool).await.unwrap();
+        run_migrations(&pool).await.unwrap();
         let verified = verify_schema(&pool).await.unwrap();
         assert!(verified);
     }
 
+    #[tokio::test]
+    async fn test_upgrade_legacy_schema() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("legacy.db");
+        let pool = create_pool(&db_path).await.unwrap();
+
+        // Schema written by CLI versions before the versioned migrations
+        for statement in [
+            "CREATE TABLE projects (id TEXT PRIMARY KEY, name TEXT NOT NULL, root_path TEXT UNIQUE NOT NULL,
+                description TEXT, created_at TIMESTAMP NOT NULL, updated_at TIMESTAMP NOT NULL, metadata TEXT)",
+            "CREATE TABLE analyses (id TEXT PRIMARY KEY, project_id TEXT NOT NULL, log_file_path TEXT NOT NULL,
+                provider TEXT NOT NULL, level TEXT NOT NULL, status TEXT NOT NULL, created_at TIMESTAMP NOT NULL,
+                started_at TIMESTAMP, completed_at TIMESTAMP, metadata TEXT,
+                FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE)",
+            "CREATE TABLE analysis_results (analysis_id TEXT PRIMARY KEY, summary TEXT, full_report TEXT,
+                patterns_detected TEXT, issues_found INTEGER, metadata TEXT,
+                FOREIGN KEY (analysis_id) REFERENCES analyses(id) ON DELETE CASCADE)",
+            "CREATE INDEX idx_analyses_status ON analyses(status)",
+            "CREATE INDEX idx_projects_root_path ON projects(root_path)",
+            "INSERT INTO projects VALUES ('p1', 'legacy', '/legacy', NULL, '2024-01-01T00:00:00Z', '2024-01-01T00:00:00Z', NULL)",
+            "INSERT INTO analyses VALUES ('a1', 'p1', '/legacy/app.log', 'openrouter', 'ERROR', 'completed',
+                '2024-01-01T00:00:00Z', NULL, '2024-01-01T00:01:00Z', NULL)",
+            "INSERT INTO analysis_results VALUES ('a1', 'Disk full', NULL, '[]', 2, NULL)",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+
+        run_migrations(&pool).await.unwrap();
+        assert!(verify_schema(&pool).await.unwrap());
+
+        let project = crate::project::queries::get_project(&pool, "p1").await.unwrap().unwrap();
+        assert_eq!(project.root_path.as_deref(), Some("/legacy"));
+
+        let analysis = crate::project::queries::get_analysis_by_id(&pool, "a1").await.unwrap().unwrap();
+        assert_eq!(analysis.status, crate::project::models::AnalysisStatus::Completed);
+        assert_eq!(analysis.level_filter, "ERROR");
+        assert_eq!(analysis.source.as_deref(), Some("/legacy/app.log"));
+
+        let result: serde_json::Value = serde_json::from_str(&analysis.result.unwrap()).unwrap();
+        assert_eq!(result["summary"], "Disk full");
+        assert_eq!(result["issues_found"], 2);
+
+        let legacy_tables: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type='table' AND (name LIKE 'legacy_%' OR name = 'analysis_results')"
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(legacy_tables, 0);
+    }
+
     #[tokio::test]
     async fn test_initialize_database() {
         let temp_dir = tempdir().unwrap();
@@ -331,7 +331,7 @@ mod tests {
         let table_names: Vec<String> = tables.into_iter().map(|t| t.0).collect();
         assert!(table_names.contains(&"projects".to_string()));
         assert!(table_names.contains(&"analyses".to_string()));
-        assert!(table_names.contains(&"analysis_results".to_string()));
+        assert!(table_names.contains(&"log_files".to_string()));
 
         // Cleanup
         let mut registry = crate::project::registry::ProjectRegistry::load().unwrap();
@@ -0,0 +1,575 @@
+// Persistent job queue that runs analyses on a pool of workers
+//
+// Jobs live in the `analysis_jobs` table, so queued work survives a restart. A worker
+// claims the highest priority runnable job in its process's queue under a lease,
+// renews the lease while the job runs and records the outcome on both the job and
+// its analysis. Failed and timed-out runs are retried with exponential backoff until
+// the job's `max_attempts`. Cancelling goes through the database, so it reaches the
+// worker on its next lease renewal wherever the job runs. Running jobs whose lease
+// lapsed, because their process stopped, are requeued at startup and periodically;
+// analyses left running without a job are failed at startup.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use tokio::sync::{broadcast, Notify};
+use tracing::{debug, error, info, info_span, warn, Instrument, Span};
+use uuid::Uuid;
+
+use crate::project::models::{AnalysisJob, JobStatus};
+use crate::project::storage::Storage;
+
+/// Longest error message stored on a job or analysis
+const MAX_ERROR_LEN: usize = 1000;
+
+/// Error recorded on analyses that stopped with their process
+pub const INTERRUPTED_ANALYSIS_ERROR: &str = "Interrupted by a restart before it finished";
+
+/// Settings for one process's pool of workers
+#[derive(Debug, Clone)]
+pub struct JobQueueConfig {
+    /// Queue this process runs jobs from
+    pub queue: String,
+    pub workers: usize,
+    /// How often idle workers look for jobs due for a retry or queued by another process
+    pub poll_interval: Duration,
+    /// Delay before the first retry, doubled for each later one
+    pub retry_backoff: Duration,
+    pub max_retry_backoff: Duration,
+    /// How long a job stays claimed by a worker that stops renewing its lease
+    pub lease: Duration,
+}
+
+impl JobQueueConfig {
+    pub fn new(queue: &str, workers: usize) -> Self {
+        Self {
+            queue: queue.to_string(),
+            workers: workers.max(1),
+            poll_interval: Duration::from_secs(2),
+            retry_backoff: Duration::from_secs(10),
+            max_retry_backoff: Duration::from_secs(10 * 60),
+            lease: Duration::from_secs(60),
+        }
+    }
+
+    /// Delay before retrying a job that has failed `attempts` times
+    pub fn retry_delay(&self, attempts: i32) -> Duration {
+        let exponent = attempts.saturating_sub(1).clamp(0, 16) as u32;
+        self.retry_backoff
+            .saturating_mul(2u32.pow(exponent))
+            .min(self.max_retry_backoff)
+    }
+}
+
+/// Progress and outcome of jobs run by this process
+#[derive(Debug, Clone)]
+pub enum JobEvent {
+    Progress {
+        job_id: String,
+        analysis_id: String,
+        stage: String,
+        progress: f32, // 0.0 to 1.0
+        message: String,
+    },
+    /// The job will not run again
+    Finished {
+        job_id: String,
+        analysis_id: String,
+        status: JobStatus,
+        error: Option<String>,
+        /// Extra detail from the handler, such as pipeline statistics
+        details: Option<serde_json::Value>,
+    },
+}
+
+impl JobEvent {
+    pub fn job_id(&self) -> &str {
+        match self {
+            JobEvent::Progress { job_id, .. } | JobEvent::Finished { job_id, .. } => job_id,
+        }
+    }
+}
+
+/// What a successful run produced
+#[derive(Debug, Clone)]
+pub struct JobOutput {
+    /// Serialized `AnalysisResponse`, stored on the analysis
+    pub result_json: String,
+    pub details: Option<serde_json::Value>,
+}
+
+/// Runs the analysis behind a job
+#[async_trait]
+pub trait JobHandler: Send + Sync {
+    async fn run(&self, job: &AnalysisJob, progress: &JobProgress) -> Result<JobOutput>;
+
+    /// Attach the span a job runs in to the trace it was queued from, before it starts
+    fn link_span(&self, _job: &AnalysisJob, _span: &Span) {}
+}
+
+/// Lets a running job report its progress to subscribers
+pub struct JobProgress {
+    job_id: String,
+    analysis_id: String,
+    events: broadcast::Sender<JobEvent>,
+}
+
+impl JobProgress {
+    pub fn report(&self, stage: &str, progress: f32, message: impl Into<String>) {
+        let _ = self.events.send(JobEvent::Progress {
+            job_id: self.job_id.clone(),
+            analysis_id: self.analysis_id.clone(),
+            stage: stage.to_string(),
+            progress,
+            message: message.into(),
+        });
+    }
+}
+
+/// How a run ended, before it is recorded
+enum RunOutcome {
+    Finished(Result<JobOutput>),
+    /// Cancelled through this process
+    Cancelled,
+    /// The lease could not be renewed: cancelled elsewhere, or handed to another worker
+    Released,
+}
+
+/// The job queue and this process's workers
+pub struct JobQueue {
+    storage: Arc<dyn Storage>,
+    handler: Arc<dyn JobHandler>,
+    config: JobQueueConfig,
+    events: broadcast::Sender<JobEvent>,
+    wake: Notify,
+    /// Jobs running in this process, signalled when they are cancelled
+    running: Mutex<HashMap<String, Arc<Notify>>>,
+    /// Distinguishes this process's workers from those of other processes
+    instance: String,
+}
+
+impl JobQueue {
+    pub fn new(storage: Arc<dyn Storage>, handler: Arc<dyn JobHandler>, config: JobQueueConfig) -> Arc<Self> {
+        let (events, _) = broadcast::channel(256);
+        Arc::new(Self {
+            storage,
+            handler,
+            config,
+            events,
+            wake: Notify::new(),
+            running: Mutex::new(HashMap::new()),
+            instance: Uuid::new_v4().simple().to_string()[..8].to_string(),
+        })
+    }
+
+    pub fn config(&self) -> &JobQueueConfig {
+        &self.config
+    }
+
+    /// Receive progress and outcomes of the jobs this process runs
+    pub fn subscribe(&self) -> broadcast::Receiver<JobEvent> {
+        self.events.subscribe()
+    }
+
+    /// Store a job and wake a worker for it
+    pub async fn enqueue(&self, job: AnalysisJob) -> Result<AnalysisJob> {
+        self.storage.enqueue_job(&job).await?;
+        debug!("Queued job {} for analysis {} on {}", job.id, job.analysis_id, job.queue);
+        self.wake.notify_one();
+        Ok(job)
+    }
+
+    /// Cancel a queued or running job and fail its analysis
+    ///
+    /// Returns `false` when the job had already finished. A job running in this process
+    /// stops at once; one running elsewhere stops at its worker's next lease renewal.
+    pub async fn cancel(&self, job: &AnalysisJob) -> Result<bool> {
+        if !self.storage.cancel_job(&job.id).await? {
+            return Ok(false);
+        }
+        self.storage.fail_analysis(&job.analysis_id, "Analysis cancelled").await?;
+        info!("Cancelled job {} for analysis {}", job.id, job.analysis_id);
+
+        let running = self.running.lock().unwrap().get(&job.id).cloned();
+        match running {
+            // The worker reports the cancellation when it stops
+            Some(cancelled) => cancelled.notify_one(),
+            None => self.finished(job, JobStatus::Cancelled, None, None),
+        }
+        Ok(true)
+    }
+
+    /// Recover interrupted jobs, then start the workers and the periodic recovery
+    pub fn start(self: &Arc<Self>) {
+        info!(
+            "Starting {} analysis workers on the {} queue",
+            self.config.workers, self.config.queue
+        );
+
+        let queue = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(queue.config.lease);
+            loop {
+                interval.tick().await;
+                match queue.recover_expired().await {
+                    Ok(0) => {}
+                    Ok(recovered) => {
+                        info!("Recovered {} interrupted jobs on the {} queue", recovered, queue.config.queue);
+                        queue.wake.notify_one();
+                    }
+                    Err(e) => error!("Failed to recover interrupted jobs: {}", e),
+                }
+            }
+        });
+
+        for worker in 0..self.config.workers {
+            let queue = Arc::clone(self);
+            let worker_id = format!("{}-{}-{}", self.config.queue, self.instance, worker);
+            tokio::spawn(async move { queue.work(worker_id).await });
+        }
+    }
+
+    /// Requeue running jobs whose lease lapsed, or fail them when out of attempts
+    pub async fn recover_expired(&self) -> Result<usize> {
+        let expired = self.storage.list_expired_jobs(&self.config.queue).await?;
+        let mut recovered = 0;
+
+        for job in expired {
+            let Some(worker_id) = job.worker_id.as_deref() else {
+                continue;
+            };
+            let error = "Interrupted before finishing; the server stopped or lost its lease";
+
+            if job.attempts < job.max_attempts {
+                if self.storage.retry_job(&job.id, worker_id, Utc::now(), error).await? {
+                    self.storage.mark_analysis_pending(&job.analysis_id).await?;
+                    warn!("Requeued interrupted job {} for analysis {}", job.id, job.analysis_id);
+                    recovered += 1;
+                }
+            } else if self.storage.fail_job(&job.id, worker_id, error).await? {
+                self.storage.fail_analysis(&job.analysis_id, error).await?;
+                warn!("Failed interrupted job {} after {} attempts", job.id, job.attempts);
+                recovered += 1;
+            }
+        }
+
+        Ok(recovered)
+    }
+
+    async fn work(self: Arc<Self>, worker_id: String) {
+        loop {
+            let lease_expires_at = Utc::now() + self.lease();
+            match self
+                .storage
+                .claim_next_job(&self.config.queue, &worker_id, lease_expires_at)
+                .await
+            {
+                Ok(Some(job)) => {
+                    let span = info_span!(
+                        "analysis.job",
+                        job.id = %job.id,
+                        analysis.id = %job.analysis_id,
+                        job.attempt = job.attempts,
+                    );
+                    self.handler.link_span(&job, &span);
+                    self.run(job, &worker_id).instrument(span).await;
+                    continue;
+                }
+                Ok(None) => {}
+                Err(e) => error!("Worker {} failed to claim a job: {}", worker_id, e),
+            }
+
+            tokio::select! {
+                _ = self.wake.notified() => {}
+                _ = tokio::time::sleep(self.config.poll_interval) => {}
+            }
+        }
+    }
+
+    async fn run(&self, job: AnalysisJob, worker_id: &str) {
+        info!(
+            "Worker {} running job {} for analysis {} (attempt {} of {})",
+            worker_id, job.id, job.analysis_id, job.attempts, job.max_attempts
+        );
+        if let Err(e) = self.storage.mark_analysis_running(&job.analysis_id).await {
+            error!("Failed to mark analysis {} as running: {}", job.analysis_id, e);
+        }
+
+        let cancelled = Arc::new(Notify::new());
+        self.running.lock().unwrap().insert(job.id.clone(), Arc::clone(&cancelled));
+
+        let progress = JobProgress {
+            job_id: job.id.clone(),
+            analysis_id: job.analysis_id.clone(),
+            events: self.events.clone(),
+        };
+        progress.report("running", 0.0, format!("Attempt {} of {}", job.attempts, job.max_attempts));
+
+        let timeout = Duration::from_secs(job.timeout_secs.max(1) as u64);
+        let outcome = tokio::select! {
+            result = tokio::time::timeout(timeout, self.handler.run(&job, &progress)) => {
+                RunOutcome::Finished(result.unwrap_or_else(|_| {
+                    Err(anyhow!("Analysis timed out after {} seconds", job.timeout_secs))
+                }))
+            }
+            _ = cancelled.notified() => RunOutcome::Cancelled,
+            _ = self.keep_lease(&job.id, worker_id) => RunOutcome::Released,
+        };
+
+        self.running.lock().unwrap().remove(&job.id);
+        if let Err(e) = self.record(&job, worker_id, outcome).await {
+            error!("Failed to record the outcome of job {}: {}", job.id, e);
+        }
+    }
+
+    /// Renew the lease until it can't be, because the job was cancelled or taken over
+    async fn keep_lease(&self, job_id: &str, worker_id: &str) {
+        let mut interval = tokio::time::interval(self.config.lease / 3);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let lease_expires_at = Utc::now() + self.lease();
+            match self.storage.renew_job_lease(job_id, worker_id, lease_expires_at).await {
+                Ok(true) => {}
+                Ok(false) => return,
+                Err(e) => warn!("Failed to renew the lease on job {}: {}", job_id, e),
+            }
+        }
+    }
+
+    async fn record(&self, job: &AnalysisJob, worker_id: &str, outcome: RunOutcome) -> Result<()> {
+        match outcome {
+            RunOutcome::Finished(Ok(output)) => {
+                if self.storage.complete_job(&job.id, worker_id).await? {
+                    self.storage.complete_analysis(&job.analysis_id, &output.result_json).await?;
+                    info!("Job {} completed analysis {}", job.id, job.analysis_id);
+                    self.finished(job, JobStatus::Completed, None, output.details);
+                } else {
+                    self.released(job).await?;
+                }
+            }
+            RunOutcome::Finished(Err(e)) => {
+                let error = truncate_error(format!("{:#}", e));
+                if job.attempts < job.max_attempts {
+                    let delay = self.config.retry_delay(job.attempts);
+                    let run_after = Utc::now() + chrono::Duration::from_std(delay)?;
+                    if self.storage.retry_job(&job.id, worker_id, run_after, &error).await? {
+                        self.storage.mark_analysis_pending(&job.analysis_id).await?;
+                        warn!("Job {} failed, retrying in {:?}: {}", job.id, delay, error);
+                        let _ = self.events.send(JobEvent::Progress {
+                            job_id: job.id.clone(),
+                            analysis_id: job.analysis_id.clone(),
+                            stage: "retrying".to_string(),
+                            progress: 0.0,
+                            message: format!("Attempt {} failed, retrying in {}s: {}", job.attempts, delay.as_secs(), error),
+                        });
+                    } else {
+                        self.released(job).await?;
+                    }
+                } else if self.storage.fail_job(&job.id, worker_id, &error).await? {
+                    self.storage.fail_analysis(&job.analysis_id, &error).await?;
+                    warn!("Job {} failed after {} attempts: {}", job.id, job.attempts, error);
+                    self.finished(job, JobStatus::Failed, Some(error), None);
+                } else {
+                    self.released(job).await?;
+                }
+            }
+            RunOutcome::Cancelled => self.finished(job, JobStatus::Cancelled, None, None),
+            RunOutcome::Released => self.released(job).await?,
+        }
+        Ok(())
+    }
+
+    /// Report a job this worker stopped owning before it finished
+    async fn released(&self, job: &AnalysisJob) -> Result<()> {
+        match self.storage.get_job(&job.id).await? {
+            Some(current) if current.status == JobStatus::Cancelled => {
+                info!("Job {} was cancelled while running", job.id);
+                self.finished(job, JobStatus::Cancelled, None, None);
+            }
+            _ => warn!("Lost the lease on job {}; its result was discarded", job.id),
+        }
+        Ok(())
+    }
+
+    fn finished(
+        &self,
+        job: &AnalysisJob,
+        status: JobStatus,
+        error: Option<String>,
+        details: Option<serde_json::Value>,
+    ) {
+        let _ = self.events.send(JobEvent::Finished {
+            job_id: job.id.clone(),
+            analysis_id: job.analysis_id.clone(),
+            status,
+            error,
+            details,
+        });
+    }
+
+    fn lease(&self) -> chrono::Duration {
+        chrono::Duration::from_std(self.config.lease).unwrap_or_else(|_| chrono::Duration::seconds(60))
+    }
+}
+
+/// Fail analyses left pending or running by a stopped process with no job to finish them
+///
+/// Run at startup. Analyses that run outside the queue, such as MCP sampling runs, would
+/// otherwise stay running forever; queued ones are left to [`JobQueue::recover_expired`].
+pub async fn fail_interrupted_analyses(storage: &dyn Storage) -> Result<u64> {
+    let failed = storage.fail_interrupted_analyses(INTERRUPTED_ANALYSIS_ERROR).await?;
+    if failed > 0 {
+        info!("Marked {} analyses interrupted by a restart as failed", failed);
+    }
+    Ok(failed)
+}
+
+fn truncate_error(error: String) -> String {
+    if error.len() <= MAX_ERROR_LEN {
+        return error;
+    }
+    let mut end = MAX_ERROR_LEN;
+    while !error.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}... (truncated)", &error[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::models::{Analysis, AnalysisStatus, JobQuery, Project};
+    use crate::project::storage::connect_storage;
+    use tempfile::TempDir;
+
+    /// Succeeds, fails or hangs depending on the job's payload
+    struct TestHandler;
+
+    #[async_trait]
+    impl JobHandler for TestHandler {
+        async fn run(&self, job: &AnalysisJob, progress: &JobProgress) -> Result<JobOutput> {
+            progress.report("testing", 0.5, "halfway");
+            match job.payload.as_deref() {
+                Some("fail") => Err(anyhow!("provider unavailable")),
+                Some("hang") => std::future::pending().await,
+                _ => Ok(JobOutput {
+                    result_json: r#"{"ok":true}"#.to_string(),
+                    details: Some(serde_json::json!({ "lines": 3 })),
+                }),
+            }
+        }
+    }
+
+    fn test_config() -> JobQueueConfig {
+        JobQueueConfig {
+            poll_interval: Duration::from_millis(20),
+            retry_backoff: Duration::from_millis(10),
+            max_retry_backoff: Duration::from_millis(40),
+            lease: Duration::from_millis(300),
+            ..JobQueueConfig::new("test", 2)
+        }
+    }
+
+    async fn new_job(storage: &dyn Storage, project_id: &str, payload: Option<&str>) -> AnalysisJob {
+        let analysis = Analysis::new(
+            project_id.to_string(),
+            None,
+            "file".to_string(),
+            "mock".to_string(),
+            "ERROR".to_string(),
+        );
+        storage.create_analysis(&analysis).await.unwrap();
+        let job = AnalysisJob::new(analysis.id, project_id.to_string(), "test", 5);
+        match payload {
+            Some(payload) => job.with_payload(payload.to_string()),
+            None => job,
+        }
+    }
+
+    async fn wait_for(events: &mut broadcast::Receiver<JobEvent>, job_id: &str) -> (JobStatus, Option<String>) {
+        tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                if let JobEvent::Finished { job_id: id, status, error, .. } = events.recv().await.unwrap() {
+                    if id == job_id {
+                        return (status, error);
+                    }
+                }
+            }
+        })
+        .await
+        .expect("job did not finish")
+    }
+
+    #[test]
+    fn test_retry_delay_backs_off() {
+        let config = JobQueueConfig::new("web", 2);
+        assert_eq!(config.retry_delay(1), Duration::from_secs(10));
+        assert_eq!(config.retry_delay(2), Duration::from_secs(20));
+        assert_eq!(config.retry_delay(3), Duration::from_secs(40));
+        assert_eq!(config.retry_delay(30), Duration::from_secs(600));
+        assert_eq!(truncate_error("é".repeat(600)).len(), MAX_ERROR_LEN + "... (truncated)".len());
+    }
+
+    #[tokio::test]
+    async fn test_queue_runs_retries_cancels_and_recovers() {
+        let temp_dir = TempDir::new().unwrap();
+        let url = format!("sqlite://{}", temp_dir.path().join("jobs.db").display());
+        let storage = connect_storage(&url, 5).await.unwrap();
+
+        let project = Project::new("jobs".to_string(), None);
+        storage.insert_project(&project).await.unwrap();
+        let new_job = |payload| new_job(storage.as_ref(), &project.id, payload);
+
+        // A job left running by a process that stopped is picked up again
+        let interrupted = new_job(None).await;
+        storage.enqueue_job(&interrupted).await.unwrap();
+        let lapsed = Utc::now() - chrono::Duration::seconds(1);
+        storage.claim_next_job("test", "old-process", lapsed).await.unwrap().unwrap();
+
+        let queue = JobQueue::new(Arc::clone(&storage), Arc::new(TestHandler), test_config());
+        let mut events = queue.subscribe();
+        queue.start();
+        assert_eq!(wait_for(&mut events, &interrupted.id).await.0, JobStatus::Completed);
+        let recovered = storage.get_job(&interrupted.id).await.unwrap().unwrap();
+        assert_eq!(recovered.attempts, 2);
+
+        let succeeding = queue.enqueue(new_job(None).await).await.unwrap();
+        assert_eq!(wait_for(&mut events, &succeeding.id).await.0, JobStatus::Completed);
+        let analysis = storage.get_analysis(&succeeding.analysis_id).await.unwrap().unwrap();
+        assert_eq!(analysis.status, AnalysisStatus::Completed);
+        assert_eq!(analysis.result.as_deref(), Some(r#"{"ok":true}"#));
+
+        let failing = queue.enqueue(new_job(Some("fail")).await.with_max_attempts(2)).await.unwrap();
+        let (status, error) = wait_for(&mut events, &failing.id).await;
+        assert_eq!(status, JobStatus::Failed);
+        assert!(error.unwrap().contains("provider unavailable"));
+        assert_eq!(storage.get_job(&failing.id).await.unwrap().unwrap().attempts, 2);
+        let analysis = storage.get_analysis(&failing.analysis_id).await.unwrap().unwrap();
+        assert_eq!(analysis.status, AnalysisStatus::Failed);
+
+        let mut timing_out = new_job(Some("hang")).await.with_max_attempts(1);
+        timing_out.timeout_secs = 1;
+        let timing_out = queue.enqueue(timing_out).await.unwrap();
+        let (status, error) = wait_for(&mut events, &timing_out.id).await;
+        assert_eq!(status, JobStatus::Failed);
+        assert!(error.unwrap().contains("timed out after 1 seconds"));
+
+        let hanging = queue.enqueue(new_job(Some("hang")).await).await.unwrap();
+        while storage.get_job(&hanging.id).await.unwrap().unwrap().status != JobStatus::Running {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(queue.cancel(&hanging).await.unwrap());
+        assert_eq!(wait_for(&mut events, &hanging.id).await.0, JobStatus::Cancelled);
+        assert!(!queue.cancel(&hanging).await.unwrap());
+        let analysis = storage.get_analysis(&hanging.analysis_id).await.unwrap().unwrap();
+        assert_eq!(analysis.error_message.as_deref(), Some("Analysis cancelled"));
+
+        let filter = JobQuery { status: Some(JobStatus::Completed), ..Default::default() };
+        assert_eq!(storage.list_jobs(&filter).await.unwrap().len(), 2);
+    }
+}
@@ -11,6 +11,7 @@ pub mod link;
 pub mod metadata;
 pub mod models;
 pub mod registry;
+pub mod sandbox;
 pub mod validate;
 
 #[cfg(feature = "project-management")]
@@ -19,18 +20,48 @@ pub mod database;
 #[cfg(feature = "project-management")]
 pub mod queries;
 
+#[cfg(feature = "project-management")]
+pub mod storage;
+
+#[cfg(feature = "project-management")]
+pub mod jobs;
+
 // Re-export main types and functions
 pub use config::ProjectConfig;
 pub use detect::{detect_project_type, get_suggested_log_paths, ProjectType};
 pub use init::{initialize_project, InitializationResult};
 pub use link::{link_project, unlink_project, LinkResult, UnlinkResult};
 pub use metadata::ProjectMetadata;
-pub use models::{Analysis, AnalysisResult, AnalysisStatus, Pattern, Project};
+pub use models::{
+    Alert, AlertQuery, AlertRule, AlertSeverity, AlertStatus, Analysis, AnalysisJob,
+    AnalysisSchedule, AnalysisStatus, ApiToken, AuditEvent, AuditQuery, ChannelType,
+    DeliveryQuery, DeliveryStatus, ErrorCorrelation, ErrorPattern, JobQuery, JobStatus,
+    KnowledgeBaseEntry, LogCount, LogCursor, LogFile, LogPartition, LogRetention, NotificationChannel,
+    NotificationDelivery, PerformanceMetric, Project, ProjectMember, ProjectMemberChange, ProjectRole,
+    ProjectSummary, ProviderSecret, Session, Settings, Share, StoredLogEntry, StreamingLogQuery,
+    StreamingSource, User,
+};
 pub use registry::{ProjectRegistry, RegistryEntry};
+pub use sandbox::{discover_log_files, is_command_allowed, resolve_in_root};
 pub use validate::{validate_links, validate_and_repair, validate_project, ValidationReport, ProjectValidation};
 
 #[cfg(feature = "project-management")]
-pub use database::{create_pool, create_schema, initialize_database, verify_schema};
+pub use database::{create_pool, initialize_database, open_central_database, run_migrations, verify_schema, MIGRATOR};
 
 #[cfg(feature = "project-management")]
 pub use queries::*;
+
+#[cfg(feature = "project-management")]
+pub use jobs::{
+    fail_interrupted_analyses, JobEvent, JobHandler, JobOutput, JobProgress, JobQueue, JobQueueConfig,
+    INTERRUPTED_ANALYSIS_ERROR,
+};
+
+#[cfg(feature = "project-management")]
+pub use storage::{connect_storage, SqliteStorage, Storage, StorageBackend};
+
+#[cfg(feature = "postgres")]
+pub use storage::PostgresStorage;
+
+#[cfg(feature = "postgres")]
+pub use database::{run_postgres_migrations, POSTGRES_MIGRATOR};
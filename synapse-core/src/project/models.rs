@@ -1,187 +1,1437 @@
 // Data models for Synapse project management and analysis tracking
+//
+// These mirror the tables created by the versioned migrations in
+// `synapse-core/migrations` and are shared by the CLI, web backend and MCP server.
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-/// Represents a Synapse-initialized project
+/// A project known to Synapse, created from the web dashboard or linked from the CLI
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[cfg_attr(feature = "project-management", derive(sqlx::FromRow))]
 pub struct Project {
     pub id: String,
     pub name: String,
-    pub root_path: String,
     pub description: Option<String>,
+    pub root_path: Option<String>,            // CLI project root
+    pub synapse_config: Option<String>,       // CLI project .synapse directory
+    pub project_type: String,                 // web, cli or a detected project type
+    pub last_accessed: Option<DateTime<Utc>>, // CLI project access tracking
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
-    pub metadata: Option<String>,
+    /// User who created this record, when accounts are enabled
+    #[cfg_attr(feature = "project-management", sqlx(default))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_by: Option<String>,
 }
 
 impl Project {
-    pub fn new(name: String, root_path: String) -> Self {
+    pub fn new(name: String, description: Option<String>) -> Self {
         let now = Utc::now();
         Self {
             id: Uuid::new_v4().to_string(),
             name,
-            root_path,
-            description: None,
+            description,
+            root_path: None,
+            synapse_config: None,
+            project_type: "web".to_string(),
+            last_accessed: None,
+            created_at: now,
+            updated_at: now,
+            created_by: None,
+        }
+    }
+
+    /// Attribute the project to the user creating it
+    pub fn with_created_by(mut self, user_id: Option<String>) -> Self {
+        self.created_by = user_id;
+        self
+    }
+
+    pub fn new_cli_project(
+        id: String,
+        name: String,
+        description: Option<String>,
+        root_path: String,
+        synapse_config: Option<String>,
+        project_type: String,
+    ) -> Self {
+        let now = Utc::now();
+        Self {
+            id,
+            name,
+            description,
+            root_path: Some(root_path),
+            synapse_config,
+            project_type,
+            last_accessed: Some(now),
+            created_at: now,
+            updated_at: now,
+            created_by: None,
+        }
+    }
+}
+
+/// A project with counts of its log files and analyses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "project-management", derive(sqlx::FromRow))]
+pub struct ProjectSummary {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub file_count: i64,
+    pub analysis_count: i64,
+    pub last_analysis_date: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A log file uploaded to a project
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "project-management", derive(sqlx::FromRow))]
+pub struct LogFile {
+    pub id: String,
+    pub project_id: String,
+    pub filename: String,
+    pub file_size: i64,
+    pub line_count: i64,
+    pub upload_path: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl LogFile {
+    pub fn new(
+        project_id: String,
+        filename: String,
+        file_size: i64,
+        line_count: i64,
+        upload_path: String,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            project_id,
+            filename,
+            file_size,
+            line_count,
+            upload_path,
+            created_at: Utc::now(),
+        }
+    }
+}
+
+/// Status of a log analysis operation, stored as an integer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "project-management", derive(sqlx::Type))]
+#[serde(rename_all = "lowercase")]
+#[repr(i32)]
+pub enum AnalysisStatus {
+    #[default]
+    Pending = 0,
+    Running = 1,
+    Completed = 2,
+    Failed = 3,
+}
+
+impl AnalysisStatus {
+    /// Map a stored status code back to a status, treating unknown codes as failed
+    pub fn from_code(code: i32) -> Self {
+        match code {
+            0 => AnalysisStatus::Pending,
+            1 => AnalysisStatus::Running,
+            2 => AnalysisStatus::Completed,
+            _ => AnalysisStatus::Failed,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnalysisStatus::Pending => "pending",
+            AnalysisStatus::Running => "running",
+            AnalysisStatus::Completed => "completed",
+            AnalysisStatus::Failed => "failed",
+        }
+    }
+}
+
+impl std::fmt::Display for AnalysisStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for AnalysisStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "pending" => Ok(AnalysisStatus::Pending),
+            "running" => Ok(AnalysisStatus::Running),
+            "completed" => Ok(AnalysisStatus::Completed),
+            "failed" => Ok(AnalysisStatus::Failed),
+            _ => Err(format!("Invalid analysis status: {}", s)),
+        }
+    }
+}
+
+impl PartialEq<&str> for AnalysisStatus {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+/// Represents a log analysis operation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "project-management", derive(sqlx::FromRow))]
+pub struct Analysis {
+    pub id: String,
+    pub project_id: String,
+    pub log_file_id: Option<String>,
+    pub analysis_type: String, // "file", "command" or "realtime"
+    pub provider: String,
+    pub level_filter: String,
+    pub status: AnalysisStatus,
+    pub result: Option<String>, // JSON serialized AnalysisResponse
+    pub error_message: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    /// Log file path or command analyzed when no uploaded log file is attached
+    #[cfg_attr(feature = "project-management", sqlx(default))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// User who created this record, when accounts are enabled
+    #[cfg_attr(feature = "project-management", sqlx(default))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_by: Option<String>,
+    /// Alert that triggered a realtime analysis
+    #[cfg_attr(feature = "project-management", sqlx(default))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alert_id: Option<String>,
+}
+
+impl Analysis {
+    pub fn new(
+        project_id: String,
+        log_file_id: Option<String>,
+        analysis_type: String,
+        provider: String,
+        level_filter: String,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            project_id,
+            log_file_id,
+            analysis_type,
+            provider,
+            level_filter,
+            status: AnalysisStatus::Pending,
+            result: None,
+            error_message: None,
+            started_at: Utc::now(),
+            completed_at: None,
+            source: None,
+            created_by: None,
+            alert_id: None,
+        }
+    }
+
+    /// Record the log file path or command this analysis reads from
+    pub fn with_source(mut self, source: String) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// Attribute the analysis to the user starting it
+    pub fn with_created_by(mut self, user_id: Option<String>) -> Self {
+        self.created_by = user_id;
+        self
+    }
+
+    /// Link a realtime analysis to the alert that triggered it
+    pub fn with_alert_id(mut self, alert_id: Option<String>) -> Self {
+        self.alert_id = alert_id;
+        self
+    }
+}
+
+/// A reusable problem/solution entry in a project's knowledge base
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "project-management", derive(sqlx::FromRow))]
+pub struct KnowledgeBaseEntry {
+    pub id: String,
+    pub project_id: String,
+    pub title: String,
+    pub problem_description: String,
+    pub solution: String,
+    pub tags: Option<String>, // JSON array of tags
+    pub severity: String,
+    pub is_public: bool,
+    pub usage_count: i32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// User who created this record, when accounts are enabled
+    #[cfg_attr(feature = "project-management", sqlx(default))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_by: Option<String>,
+}
+
+impl KnowledgeBaseEntry {
+    pub fn new(
+        project_id: String,
+        title: String,
+        problem_description: String,
+        solution: String,
+        tags: Option<String>,
+        severity: String,
+        is_public: bool,
+    ) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            project_id,
+            title,
+            problem_description,
+            solution,
+            tags,
+            severity,
+            is_public,
+            usage_count: 0,
+            created_at: now,
+            updated_at: now,
+            created_by: None,
+        }
+    }
+
+    /// Attribute the entry to the user writing it
+    pub fn with_created_by(mut self, user_id: Option<String>) -> Self {
+        self.created_by = user_id;
+        self
+    }
+}
+
+/// A recurring error pattern recognised in a project's logs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "project-management", derive(sqlx::FromRow))]
+pub struct ErrorPattern {
+    pub id: String,
+    pub project_id: String,
+    pub pattern: String, // Regex pattern for matching errors
+    pub category: String,
+    pub description: Option<String>,
+    pub frequency: i32,
+    pub last_seen: DateTime<Utc>,
+    pub suggested_solution: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A measurement taken during an analysis, flagged as a bottleneck past its threshold
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "project-management", derive(sqlx::FromRow))]
+pub struct PerformanceMetric {
+    pub id: String,
+    pub analysis_id: String,
+    pub metric_name: String,
+    pub metric_value: f64,
+    pub unit: String,
+    pub threshold_value: Option<f64>,
+    pub is_bottleneck: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+impl PerformanceMetric {
+    pub fn new(
+        analysis_id: String,
+        metric_name: String,
+        metric_value: f64,
+        unit: String,
+        threshold_value: Option<f64>,
+    ) -> Self {
+        let is_bottleneck = threshold_value.is_some_and(|threshold| metric_value > threshold);
+        Self {
+            id: Uuid::new_v4().to_string(),
+            analysis_id,
+            metric_name,
+            metric_value,
+            unit,
+            threshold_value,
+            is_bottleneck,
+            created_at: Utc::now(),
+        }
+    }
+}
+
+/// A link between the errors found by two analyses of the same project
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "project-management", derive(sqlx::FromRow))]
+pub struct ErrorCorrelation {
+    pub id: String,
+    pub project_id: String,
+    pub primary_error_id: String,    // analysis ID
+    pub correlated_error_id: String, // analysis ID
+    pub correlation_strength: f64,   // 0.0 to 1.0
+    pub correlation_type: String,    // temporal, causal or contextual
+    pub created_at: DateTime<Utc>,
+}
+
+impl ErrorCorrelation {
+    pub fn new(
+        project_id: String,
+        primary_error_id: String,
+        correlated_error_id: String,
+        correlation_strength: f64,
+        correlation_type: String,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            project_id,
+            primary_error_id,
+            correlated_error_id,
+            correlation_strength,
+            correlation_type,
+            created_at: Utc::now(),
+        }
+    }
+}
+
+/// Dashboard preferences and AI provider configuration, stored as a single row
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "project-management", derive(sqlx::FromRow))]
+pub struct Settings {
+    pub default_provider: String,
+    pub api_key: String,
+    pub max_lines: i32,
+    pub default_level: String,
+    pub show_timestamps: bool,
+    pub show_line_numbers: bool,
+    pub selected_model: Option<String>,
+    pub available_models: Option<String>,    // JSON array cache
+    pub models_last_fetched: Option<String>, // ISO datetime
+    pub analysis_timeout_seconds: Option<i32>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            default_provider: "openrouter".to_string(),
+            api_key: String::new(),
+            max_lines: 1000,
+            default_level: "ERROR".to_string(),
+            show_timestamps: true,
+            show_line_numbers: true,
+            selected_model: None,
+            available_models: None,
+            models_last_fetched: None,
+            analysis_timeout_seconds: Some(300), // 5 minutes default
+        }
+    }
+}
+
+/// A provider API key encrypted under the master key (see `crate::secrets`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "project-management", derive(sqlx::FromRow))]
+pub struct ProviderSecret {
+    pub provider: String,
+    pub key_id: String, // fingerprint of the master key that sealed it
+    #[serde(skip)]
+    pub nonce: String,
+    #[serde(skip)]
+    pub ciphertext: String,
+    pub hint: String, // masked key, safe to display
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl ProviderSecret {
+    pub fn sealed(&self) -> crate::secrets::SealedSecret {
+        crate::secrets::SealedSecret {
+            key_id: self.key_id.clone(),
+            nonce: self.nonce.clone(),
+            ciphertext: self.ciphertext.clone(),
+        }
+    }
+}
+
+/// A dashboard user account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "project-management", derive(sqlx::FromRow))]
+pub struct User {
+    pub id: String,
+    pub username: String,
+    pub email: Option<String>,
+    pub display_name: Option<String>,
+    #[serde(skip)]
+    pub password_hash: Option<String>, // argon2 PHC string
+    pub is_admin: bool,
+    pub is_active: bool,
+    pub last_login_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// Identity provider and subject of accounts provisioned through single sign-on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oidc_issuer: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oidc_subject: Option<String>,
+}
+
+impl User {
+    pub fn new(username: String, password_hash: Option<String>, is_admin: bool) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            username,
+            email: None,
+            display_name: None,
+            password_hash,
+            is_admin,
+            is_active: true,
+            last_login_at: None,
+            created_at: now,
+            updated_at: now,
+            oidc_issuer: None,
+            oidc_subject: None,
+        }
+    }
+
+    pub fn with_oidc_identity(mut self, issuer: String, subject: String) -> Self {
+        self.oidc_issuer = Some(issuer);
+        self.oidc_subject = Some(subject);
+        self
+    }
+}
+
+/// A browser login session, keyed by the SHA-256 digest of its cookie
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "project-management", derive(sqlx::FromRow))]
+pub struct Session {
+    #[serde(skip)]
+    pub id: String,
+    pub user_id: String,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+}
+
+/// A personal API token, stored as the SHA-256 digest of the secret
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "project-management", derive(sqlx::FromRow))]
+pub struct ApiToken {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    #[serde(skip)]
+    pub token_hash: String,
+    pub token_prefix: String, // first characters of the secret, for listings
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// A user's role within a project; each role includes the permissions of the ones before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "project-management", derive(sqlx::Type))]
+#[cfg_attr(feature = "project-management", sqlx(type_name = "TEXT", rename_all = "lowercase"))]
+#[serde(rename_all = "lowercase")]
+pub enum ProjectRole {
+    /// Read projects, files, analyses and reports
+    Viewer,
+    /// Also upload logs, run analyses and add knowledge
+    Analyst,
+    /// Also delete data, share, manage streaming sources and members
+    Admin,
+}
+
+impl ProjectRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProjectRole::Viewer => "viewer",
+            ProjectRole::Analyst => "analyst",
+            ProjectRole::Admin => "admin",
+        }
+    }
+}
+
+impl std::fmt::Display for ProjectRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for ProjectRole {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "viewer" => Ok(ProjectRole::Viewer),
+            "analyst" => Ok(ProjectRole::Analyst),
+            "admin" => Ok(ProjectRole::Admin),
+            _ => Err(format!("Invalid project role: {}", s)),
+        }
+    }
+}
+
+/// A user's membership in a project
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "project-management", derive(sqlx::FromRow))]
+pub struct ProjectMember {
+    pub project_id: String,
+    pub user_id: String,
+    pub username: String,
+    pub role: ProjectRole,
+    pub added_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A recorded change to a project's membership
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "project-management", derive(sqlx::FromRow))]
+pub struct ProjectMemberChange {
+    pub id: String,
+    pub project_id: String,
+    pub user_id: String,
+    pub changed_by: Option<String>,
+    pub old_role: Option<ProjectRole>, // None when the member was added
+    pub new_role: Option<ProjectRole>, // None when the member was removed
+    pub created_at: DateTime<Utc>,
+}
+
+/// An entry in the append-only audit log of user and system actions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "project-management", derive(sqlx::FromRow))]
+pub struct AuditEvent {
+    pub id: String,
+    pub occurred_at: DateTime<Utc>,
+    pub actor_id: Option<String>,   // None for the system and the local operator
+    pub actor_name: Option<String>, // username at the time, kept after the account is gone
+    pub action: String,             // dotted verb, e.g. "project.create"
+    pub project_id: Option<String>,
+    pub target_type: Option<String>,
+    pub target_id: Option<String>,
+    #[serde(default, with = "json_text")]
+    pub details: Option<String>, // JSON object
+    pub ip_address: Option<String>,
+}
+
+impl AuditEvent {
+    pub fn new(action: impl Into<String>) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            occurred_at: Utc::now(),
+            actor_id: None,
+            actor_name: None,
+            action: action.into(),
+            project_id: None,
+            target_type: None,
+            target_id: None,
+            details: None,
+            ip_address: None,
+        }
+    }
+
+    /// Attribute the event to the user who performed it
+    pub fn with_actor(mut self, actor_id: Option<String>, actor_name: Option<String>) -> Self {
+        self.actor_id = actor_id;
+        self.actor_name = actor_name;
+        self
+    }
+
+    pub fn with_project(mut self, project_id: impl Into<String>) -> Self {
+        self.project_id = Some(project_id.into());
+        self
+    }
+
+    /// The object acted on, e.g. `("log_file", file_id)`
+    pub fn with_target(mut self, target_type: impl Into<String>, target_id: impl Into<String>) -> Self {
+        self.target_type = Some(target_type.into());
+        self.target_id = Some(target_id.into());
+        self
+    }
+
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details.to_string());
+        self
+    }
+
+    pub fn with_ip_address(mut self, ip_address: Option<String>) -> Self {
+        self.ip_address = ip_address;
+        self
+    }
+}
+
+/// Filters for reading the audit log; every field is optional
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuditQuery {
+    pub actor_id: Option<String>,
+    /// Exact action, or a prefix ending in `*` such as `project.*`
+    pub action: Option<String>,
+    pub project_id: Option<String>,
+    pub target_type: Option<String>,
+    pub target_id: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// A public link to one analysis
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "project-management", derive(sqlx::FromRow))]
+pub struct Share {
+    pub id: String,
+    pub project_id: String,
+    pub analysis_id: String,
+    pub created_by: Option<String>,
+    #[serde(skip)]
+    pub password_hash: Option<String>, // argon2 hash, None when the link is open
+    pub expires_at: DateTime<Utc>,
+    pub allow_download: bool,
+    pub redact_sensitive: bool, // mask emails, IP addresses and secrets in the shared view
+    pub include_correlations: bool,
+    pub include_metrics: bool,
+    pub access_count: i64,
+    pub last_accessed_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Share {
+    pub fn new(
+        project_id: String,
+        analysis_id: String,
+        created_by: Option<String>,
+        expires_at: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            project_id,
+            analysis_id,
+            created_by,
+            password_hash: None,
+            expires_at,
+            allow_download: true,
+            redact_sensitive: true,
+            include_correlations: true,
+            include_metrics: true,
+            access_count: 0,
+            last_accessed_at: None,
+            revoked_at: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    pub fn is_password_protected(&self) -> bool {
+        self.password_hash.is_some()
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= Utc::now()
+    }
+
+    /// Whether the link can still be opened
+    pub fn is_active(&self) -> bool {
+        self.revoked_at.is_none() && !self.is_expired()
+    }
+}
+
+/// State of a job in the analysis queue, stored as text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "project-management", derive(sqlx::Type))]
+#[cfg_attr(feature = "project-management", sqlx(type_name = "TEXT", rename_all = "lowercase"))]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    /// Waiting for a worker, possibly until `run_after` for a retry
+    Queued,
+    Running,
+    Completed,
+    /// Failed on its last allowed attempt
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Completed => "completed",
+            JobStatus::Failed => "failed",
+            JobStatus::Cancelled => "cancelled",
+        }
+    }
+
+    /// Whether the job will not run again
+    pub fn is_finished(&self) -> bool {
+        matches!(self, JobStatus::Completed | JobStatus::Failed | JobStatus::Cancelled)
+    }
+}
+
+impl std::fmt::Display for JobStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for JobStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "queued" => Ok(JobStatus::Queued),
+            "running" => Ok(JobStatus::Running),
+            "completed" => Ok(JobStatus::Completed),
+            "failed" => Ok(JobStatus::Failed),
+            "cancelled" => Ok(JobStatus::Cancelled),
+            _ => Err(format!("Invalid job status: {}", s)),
+        }
+    }
+}
+
+/// One run of an analysis through the persistent job queue
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "project-management", derive(sqlx::FromRow))]
+pub struct AnalysisJob {
+    pub id: String,
+    pub analysis_id: String,
+    pub project_id: String,
+    pub queue: String, // "web" or "mcp"; each process only runs its own queue
+    pub status: JobStatus,
+    pub priority: i32, // higher runs first
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub timeout_secs: i64,
+    #[serde(skip)]
+    pub payload: Option<String>, // JSON run options, may hold sealed API keys
+    pub run_after: DateTime<Utc>,
+    pub worker_id: Option<String>,
+    pub lease_expires_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+impl AnalysisJob {
+    pub fn new(analysis_id: String, project_id: String, queue: &str, timeout_secs: i64) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            analysis_id,
+            project_id,
+            queue: queue.to_string(),
+            status: JobStatus::Queued,
+            priority: 0,
+            attempts: 0,
+            max_attempts: 3,
+            timeout_secs,
+            payload: None,
+            run_after: now,
+            worker_id: None,
+            lease_expires_at: None,
+            last_error: None,
+            created_by: None,
             created_at: now,
-            updated_at: now,
-            metadata: None,
+            started_at: None,
+            finished_at: None,
         }
     }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_max_attempts(mut self, max_attempts: i32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    pub fn with_payload(mut self, payload: String) -> Self {
+        self.payload = Some(payload);
+        self
+    }
+
+    /// Attribute the job to the user queueing it
+    pub fn with_created_by(mut self, user_id: Option<String>) -> Self {
+        self.created_by = user_id;
+        self
+    }
 }
 
-/// Status of a log analysis operation
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// Filters for listing queued and finished jobs; every field is optional
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct JobQuery {
+    pub status: Option<JobStatus>,
+    pub queue: Option<String>,
+    pub project_id: Option<String>,
+    /// Restrict to these projects, for users who only see some
+    pub project_ids: Option<Vec<String>>,
+    pub analysis_id: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// How urgent an alert rule's alerts are, stored as text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "project-management", derive(sqlx::Type))]
+#[cfg_attr(feature = "project-management", sqlx(type_name = "TEXT", rename_all = "lowercase"))]
 #[serde(rename_all = "lowercase")]
-pub enum AnalysisStatus {
-    Pending,
-    Completed,
-    Failed,
+pub enum AlertSeverity {
+    Info,
+    #[default]
+    Warning,
+    Critical,
 }
 
-impl std::fmt::Display for AnalysisStatus {
+impl AlertSeverity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlertSeverity::Info => "info",
+            AlertSeverity::Warning => "warning",
+            AlertSeverity::Critical => "critical",
+        }
+    }
+}
+
+impl std::fmt::Display for AlertSeverity {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for AlertSeverity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "info" => Ok(AlertSeverity::Info),
+            "warning" => Ok(AlertSeverity::Warning),
+            "critical" => Ok(AlertSeverity::Critical),
+            _ => Err(format!("Invalid alert severity: {}", s)),
+        }
+    }
+}
+
+/// State of a fired alert, stored as text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "project-management", derive(sqlx::Type))]
+#[cfg_attr(feature = "project-management", sqlx(type_name = "TEXT", rename_all = "lowercase"))]
+#[serde(rename_all = "lowercase")]
+pub enum AlertStatus {
+    Firing,
+    /// Someone is looking into it; repeat matches are still counted
+    Acknowledged,
+    Resolved,
+}
+
+impl AlertStatus {
+    pub fn as_str(&self) -> &'static str {
         match self {
-            AnalysisStatus::Pending => write!(f, "pending"),
-            AnalysisStatus::Completed => write!(f, "completed"),
-            AnalysisStatus::Failed => write!(f, "failed"),
+            AlertStatus::Firing => "firing",
+            AlertStatus::Acknowledged => "acknowledged",
+            AlertStatus::Resolved => "resolved",
         }
     }
 }
 
-impl std::str::FromStr for AnalysisStatus {
+impl std::fmt::Display for AlertStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for AlertStatus {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.to_lowercase().as_str() {
-            "pending" => Ok(AnalysisStatus::Pending),
-            "completed" => Ok(AnalysisStatus::Completed),
-            "failed" => Ok(AnalysisStatus::Failed),
-            _ => Err(format!("Invalid analysis status: {}", s)),
+            "firing" => Ok(AlertStatus::Firing),
+            "acknowledged" => Ok(AlertStatus::Acknowledged),
+            "resolved" => Ok(AlertStatus::Resolved),
+            _ => Err(format!("Invalid alert status: {}", s)),
         }
     }
 }
 
-/// Represents a log analysis operation
+/// A condition evaluated against a project's streaming logs
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Analysis {
+#[cfg_attr(feature = "project-management", derive(sqlx::FromRow))]
+pub struct AlertRule {
     pub id: String,
     pub project_id: String,
-    pub log_file_path: String,
-    pub provider: String,
-    pub level: String,
-    pub status: AnalysisStatus,
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(with = "json_text::required")]
+    pub condition: String, // JSON object tagged by "type"; interpreted by the alert engine
+    pub severity: AlertSeverity,
+    pub source: Option<String>, // streaming source name; None evaluates every source
+    pub enabled: bool,
+    pub cooldown_secs: i64, // minimum time between two new alerts from the rule
+    pub silenced_until: Option<DateTime<Utc>>,
+    pub silence_reason: Option<String>,
+    pub last_fired_at: Option<DateTime<Utc>>,
+    pub created_by: Option<String>,
     pub created_at: DateTime<Utc>,
-    pub started_at: Option<DateTime<Utc>>,
-    pub completed_at: Option<DateTime<Utc>>,
-    pub metadata: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl AlertRule {
+    pub fn new(project_id: String, name: String, condition: String) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            project_id,
+            name,
+            description: None,
+            condition,
+            severity: AlertSeverity::Warning,
+            source: None,
+            enabled: true,
+            cooldown_secs: 300,
+            silenced_until: None,
+            silence_reason: None,
+            last_fired_at: None,
+            created_by: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub fn is_silenced(&self) -> bool {
+        self.silenced_until.is_some_and(|until| until > Utc::now())
+    }
+}
+
+/// An alert fired by a rule; repeat matches while it is open are counted on it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "project-management", derive(sqlx::FromRow))]
+pub struct Alert {
+    pub id: String,
+    pub rule_id: String,
+    pub project_id: String,
+    pub rule_name: String,
+    pub severity: AlertSeverity,
+    pub status: AlertStatus,
+    pub fingerprint: String, // rule-specific key, e.g. the source or the error template
+    pub source: Option<String>,
+    pub summary: String,
+    #[serde(default, with = "json_text")]
+    pub details: Option<String>, // JSON object: measured values and sample entries
+    pub occurrences: i64,
+    pub first_seen_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub acknowledged_by: Option<String>,
+    pub acknowledged_at: Option<DateTime<Utc>>,
+    pub resolved_at: Option<DateTime<Utc>>,
 }
 
-#[cfg(feature = "project-management")]
-impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for Analysis {
-    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
-        use sqlx::Row;
+impl Alert {
+    pub fn new(rule: &AlertRule, fingerprint: String, summary: String) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            rule_id: rule.id.clone(),
+            project_id: rule.project_id.clone(),
+            rule_name: rule.name.clone(),
+            severity: rule.severity,
+            status: AlertStatus::Firing,
+            fingerprint,
+            source: None,
+            summary,
+            details: None,
+            occurrences: 1,
+            first_seen_at: now,
+            last_seen_at: now,
+            acknowledged_by: None,
+            acknowledged_at: None,
+            resolved_at: None,
+        }
+    }
+
+    pub fn with_source(mut self, source: impl Into<String>) -> Self {
+        self.source = Some(source.into());
+        self
+    }
 
-        let status_str: String = row.try_get("status")?;
-        let status = status_str
-            .parse::<AnalysisStatus>()
-            .map_err(|e| sqlx::Error::Decode(Box::new(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                e,
-            ))))?;
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details.to_string());
+        self
+    }
 
-        Ok(Analysis {
-            id: row.try_get("id")?,
-            project_id: row.try_get("project_id")?,
-            log_file_path: row.try_get("log_file_path")?,
-            provider: row.try_get("provider")?,
-            level: row.try_get("level")?,
-            status,
-            created_at: row.try_get("created_at")?,
-            started_at: row.try_get("started_at")?,
-            completed_at: row.try_get("completed_at")?,
-            metadata: row.try_get("metadata")?,
-        })
+    /// Whether repeat matches are still counted on this alert
+    pub fn is_open(&self) -> bool {
+        self.status != AlertStatus::Resolved
     }
 }
 
-impl Analysis {
-    pub fn new(
-        project_id: String,
-        log_file_path: String,
-        provider: String,
-        level: String,
-    ) -> Self {
+/// Filters for reading alert history; every field is optional
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AlertQuery {
+    pub project_id: Option<String>,
+    pub rule_id: Option<String>,
+    pub status: Option<AlertStatus>,
+    pub severity: Option<AlertSeverity>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// How a notification channel delivers messages, stored as text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "project-management", derive(sqlx::Type))]
+#[cfg_attr(feature = "project-management", sqlx(type_name = "TEXT", rename_all = "lowercase"))]
+#[serde(rename_all = "lowercase")]
+pub enum ChannelType {
+    /// JSON POST to any URL, optionally signed with HMAC-SHA256
+    Webhook,
+    /// Slack or Mattermost incoming webhook
+    Slack,
+    Email,
+    /// A program on the server, given the message on stdin
+    Command,
+}
+
+impl ChannelType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChannelType::Webhook => "webhook",
+            ChannelType::Slack => "slack",
+            ChannelType::Email => "email",
+            ChannelType::Command => "command",
+        }
+    }
+}
+
+impl std::fmt::Display for ChannelType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Where a project's alerts and finished analyses are sent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "project-management", derive(sqlx::FromRow))]
+pub struct NotificationChannel {
+    pub id: String,
+    pub project_id: String,
+    pub name: String,
+    pub channel_type: ChannelType,
+    #[serde(with = "json_text::required")]
+    pub config: String, // JSON object tagged by "type"; secrets in it are sealed with the master key
+    pub notify_alerts: bool,
+    pub notify_analyses: bool,
+    pub min_severity: AlertSeverity, // alerts below this severity are not sent
+    pub title_template: Option<String>, // `{{placeholder}}` templates replacing the default title and text
+    pub body_template: Option<String>,
+    pub enabled: bool,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl NotificationChannel {
+    pub fn new(project_id: String, name: String, channel_type: ChannelType, config: String) -> Self {
+        let now = Utc::now();
         Self {
             id: Uuid::new_v4().to_string(),
             project_id,
-            log_file_path,
-            provider,
-            level,
-            status: AnalysisStatus::Pending,
-            created_at: Utc::now(),
-            started_at: None,
-            completed_at: None,
-            metadata: None,
+            name,
+            channel_type,
+            config,
+            notify_alerts: true,
+            notify_analyses: false,
+            min_severity: AlertSeverity::Info,
+            title_template: None,
+            body_template: None,
+            enabled: true,
+            created_by: None,
+            created_at: now,
+            updated_at: now,
         }
     }
 }
 
-/// Pattern detected in log analysis
+/// Progress of one message to one channel, stored as text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "project-management", derive(sqlx::Type))]
+#[cfg_attr(feature = "project-management", sqlx(type_name = "TEXT", rename_all = "lowercase"))]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    /// Waiting for its first attempt or a retry
+    Pending,
+    Delivered,
+    /// Gave up: attempts ran out or the channel rejected the message
+    Failed,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Delivered => "delivered",
+            DeliveryStatus::Failed => "failed",
+        }
+    }
+}
+
+impl std::fmt::Display for DeliveryStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A rendered message queued for a channel, with the outcome of its attempts
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Pattern {
-    pub pattern: String,
-    pub count: usize,
-    pub examples: Vec<String>,
-    pub severity: String,
-    pub confidence: f64,
+#[cfg_attr(feature = "project-management", derive(sqlx::FromRow))]
+pub struct NotificationDelivery {
+    pub id: String,
+    pub channel_id: String,
+    pub project_id: String,
+    pub event: String, // e.g. alert.fired, analysis.completed, test
+    pub event_id: Option<String>, // the alert or analysis the message is about
+    pub status: DeliveryStatus,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    #[serde(with = "json_text::required")]
+    pub payload: String, // JSON: the rendered message, resent as is on retries
+    pub last_error: Option<String>,
+    pub response_status: Option<i32>, // HTTP status or exit code of the last attempt
+    pub next_attempt_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+impl NotificationDelivery {
+    pub fn new(channel: &NotificationChannel, event: &str, payload: String, max_attempts: i32) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            channel_id: channel.id.clone(),
+            project_id: channel.project_id.clone(),
+            event: event.to_string(),
+            event_id: None,
+            status: DeliveryStatus::Pending,
+            attempts: 0,
+            max_attempts: max_attempts.max(1),
+            payload,
+            last_error: None,
+            response_status: None,
+            next_attempt_at: now,
+            created_at: now,
+            updated_at: now,
+            delivered_at: None,
+        }
+    }
+
+    pub fn with_event_id(mut self, event_id: impl Into<String>) -> Self {
+        self.event_id = Some(event_id.into());
+        self
+    }
+}
+
+/// Filters for reading the delivery log; every field is optional
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeliveryQuery {
+    pub project_id: Option<String>,
+    pub channel_id: Option<String>,
+    pub status: Option<DeliveryStatus>,
+    pub event: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
 }
 
-/// Results of a completed log analysis
+/// Runs AI analysis over a streaming source's recent entries, on a cron schedule,
+/// whenever an alert rule fires, or both
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AnalysisResult {
-    pub analysis_id: String,
-    pub summary: Option<String>,
-    pub full_report: Option<String>,
-    pub patterns_detected: serde_json::Value,
-    pub issues_found: Option<i64>,
-    pub metadata: Option<String>,
-}
-
-#[cfg(feature = "project-management")]
-impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for AnalysisResult {
-    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
-        use sqlx::Row;
-
-        let patterns_str: String = row.try_get("patterns_detected")?;
-        let patterns_detected = serde_json::from_str(&patterns_str)
-            .map_err(|e| sqlx::Error::Decode(Box::new(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                e.to_string(),
-            ))))?;
-
-        Ok(AnalysisResult {
-            analysis_id: row.try_get("analysis_id")?,
-            summary: row.try_get("summary")?,
-            full_report: row.try_get("full_report")?,
-            patterns_detected,
-            issues_found: row.try_get("issues_found")?,
-            metadata: row.try_get("metadata")?,
-        })
-    }
-}
-
-impl AnalysisResult {
-    pub fn new(analysis_id: String) -> Self {
+#[cfg_attr(feature = "project-management", derive(sqlx::FromRow))]
+pub struct AnalysisSchedule {
+    pub id: String,
+    pub project_id: String,
+    pub name: String,
+    pub source: Option<String>, // streaming source name; None reads every source
+    pub cron: Option<String>, // five-field cron expression, evaluated in UTC
+    pub trigger_rule_id: Option<String>, // alert rule whose alerts start a run
+    pub window_secs: i64, // how far back the analyzed entries reach
+    pub provider: String,
+    pub level_filter: String,
+    pub enabled: bool,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub last_analysis_id: Option<String>,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl AnalysisSchedule {
+    pub fn new(project_id: String, name: String, provider: String) -> Self {
+        let now = Utc::now();
         Self {
-            analysis_id,
-            summary: None,
-            full_report: None,
-            patterns_detected: serde_json::json!([]),
-            issues_found: None,
-            metadata: None,
+            id: Uuid::new_v4().to_string(),
+            project_id,
+            name,
+            source: None,
+            cron: None,
+            trigger_rule_id: None,
+            window_secs: 900,
+            provider,
+            level_filter: "ERROR".to_string(),
+            enabled: true,
+            last_run_at: None,
+            last_analysis_id: None,
+            created_by: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+/// A streaming source's stored configuration, started again when the server restarts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "project-management", derive(sqlx::FromRow))]
+pub struct StreamingSource {
+    pub id: String,
+    pub project_id: String,
+    pub name: String,
+    pub source_type: String,
+    pub config: String,                // JSON
+    pub parser_config: Option<String>, // JSON
+    pub buffer_size: Option<i32>,
+    pub batch_timeout_seconds: Option<i32>,
+    pub restart_on_error: Option<bool>,
+    pub max_restarts: Option<i32>,
+    pub ingest_token_hash: Option<String>, // HTTP endpoint and OTLP sources only
+    pub status: String,
+    pub created_at: String, // RFC 3339
+    pub updated_at: String, // RFC 3339
+}
+
+/// A streaming log entry kept in durable storage
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "project-management", derive(sqlx::FromRow))]
+pub struct StoredLogEntry {
+    pub seq: i64, // assigned by the database; ignored when inserting
+    pub id: String,
+    pub project_id: String,
+    pub source: String, // streaming source id
+    pub partition_start: DateTime<Utc>, // hour the entry was received in
+    pub timestamp: DateTime<Utc>, // the entry's own time, or when it was received
+    pub level: Option<String>,
+    pub severity: Option<i32>, // 0 (TRACE) to 5 (FATAL); None when the level is not recognised
+    pub message: String,
+    pub line_number: Option<i64>,
+    #[serde(default, with = "json_text")]
+    pub metadata: Option<String>, // JSON object
+    pub size_bytes: i64,
+}
+
+impl StoredLogEntry {
+    /// Level names by severity
+    pub const LEVELS: [&'static str; 6] = ["TRACE", "DEBUG", "INFO", "WARN", "ERROR", "FATAL"];
+
+    /// The partition of entries received at `received_at`
+    pub fn partition_for(received_at: DateTime<Utc>) -> DateTime<Utc> {
+        received_at.duration_trunc(TimeDelta::hours(1)).unwrap_or(received_at)
+    }
+
+    /// The minute the entry is counted in
+    pub fn minute(&self) -> DateTime<Utc> {
+        self.timestamp.duration_trunc(TimeDelta::minutes(1)).unwrap_or(self.timestamp)
+    }
+
+    /// The level the entry is counted under
+    pub fn normalized_level(&self) -> &'static str {
+        self.severity
+            .and_then(|severity| Self::LEVELS.get(usize::try_from(severity).ok()?))
+            .copied()
+            .unwrap_or("UNKNOWN")
+    }
+}
+
+/// Where a page of stored log entries ended; the next page starts after it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogCursor {
+    pub timestamp: DateTime<Utc>,
+    pub seq: i64,
+}
+
+/// Filters for searching stored streaming logs, newest first
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StreamingLogQuery {
+    pub project_id: String,
+    pub source: Option<String>,
+    /// Entries at or above this severity
+    pub min_severity: Option<i32>,
+    /// Words that must all appear in the message, each matched as a prefix
+    pub text: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    /// Continue after the last entry of the previous page
+    pub before: Option<LogCursor>,
+    pub limit: i64,
+}
+
+/// Stored streaming entries of one project received in one hour
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "project-management", derive(sqlx::FromRow))]
+pub struct LogPartition {
+    pub project_id: String,
+    pub partition_start: DateTime<Utc>,
+    pub entries: i64,
+    pub size_bytes: i64,
+}
+
+/// Entries at one level stamped within one minute
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "project-management", derive(sqlx::FromRow))]
+pub struct LogCount {
+    pub minute: DateTime<Utc>,
+    pub level: String, // normalised level, or UNKNOWN
+    pub entries: i64,
+}
+
+/// A project's own limits on stored streaming entries; `None` uses the server default,
+/// 0 means no limit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "project-management", derive(sqlx::FromRow))]
+pub struct LogRetention {
+    #[cfg_attr(feature = "project-management", sqlx(rename = "log_retention_days"))]
+    pub max_age_days: Option<i32>,
+    #[cfg_attr(feature = "project-management", sqlx(rename = "log_retention_mb"))]
+    pub max_size_mb: Option<i32>,
+}
+
+/// Serialize a JSON document stored as text as the document itself
+mod json_text {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+        value
+            .as_deref()
+            .map(|text| serde_json::from_str::<serde_json::Value>(text).unwrap_or_else(|_| text.into()))
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+        Ok(Option::<serde_json::Value>::deserialize(deserializer)?.map(|value| value.to_string()))
+    }
+
+    /// The same for a document that is always present
+    pub mod required {
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+        pub fn serialize<S: Serializer>(value: &str, serializer: S) -> Result<S::Ok, S::Error> {
+            serde_json::from_str::<serde_json::Value>(value)
+                .unwrap_or_else(|_| value.into())
+                .serialize(serializer)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+            Ok(serde_json::Value::deserialize(deserializer)?.to_string())
         }
     }
 }
@@ -194,20 +1444,40 @@ mod tests {
     fn test_project_creation() {
         let project = Project::new(
             "test-project".to_string(),
-            "/path/to/project".to_string(),
+            Some("A test project".to_string()),
         );
 
         assert!(!project.id.is_empty());
         assert_eq!(project.name, "test-project");
-        assert_eq!(project.root_path, "/path/to/project");
-        assert!(project.description.is_none());
+        assert_eq!(project.project_type, "web");
+        assert!(project.root_path.is_none());
+    }
+
+    #[test]
+    fn test_cli_project_creation() {
+        let project = Project::new_cli_project(
+            "project-id".to_string(),
+            "cli-project".to_string(),
+            None,
+            "/path/to/project".to_string(),
+            Some("/path/to/project/.synapse".to_string()),
+            "rust".to_string(),
+        );
+
+        assert_eq!(project.root_path.as_deref(), Some("/path/to/project"));
+        assert!(project.last_accessed.is_some());
     }
 
     #[test]
     fn test_analysis_status_serialization() {
         assert_eq!(AnalysisStatus::Pending.to_string(), "pending");
+        assert_eq!(AnalysisStatus::Running.to_string(), "running");
         assert_eq!(AnalysisStatus::Completed.to_string(), "completed");
         assert_eq!(AnalysisStatus::Failed.to_string(), "failed");
+        assert_eq!(
+            serde_json::to_string(&AnalysisStatus::Completed).unwrap(),
+            "\"completed\""
+        );
     }
 
     #[test]
@@ -225,41 +1495,38 @@ mod tests {
         assert!(AnalysisStatus::from_str("invalid").is_err());
     }
 
+    #[test]
+    fn test_analysis_status_codes() {
+        assert_eq!(AnalysisStatus::Completed as i32, 2);
+        assert_eq!(AnalysisStatus::from_code(1), AnalysisStatus::Running);
+        assert_eq!(AnalysisStatus::from_code(42), AnalysisStatus::Failed);
+    }
+
+    #[test]
+    fn test_project_role_ordering() {
+        use std::str::FromStr;
+
+        assert!(ProjectRole::Admin > ProjectRole::Analyst);
+        assert!(ProjectRole::Analyst > ProjectRole::Viewer);
+        assert_eq!(ProjectRole::from_str("Analyst").unwrap(), ProjectRole::Analyst);
+        assert!(ProjectRole::from_str("owner").is_err());
+        assert_eq!(serde_json::to_string(&ProjectRole::Viewer).unwrap(), "\"viewer\"");
+    }
+
     #[test]
     fn test_analysis_creation() {
         let analysis = Analysis::new(
             "project-id".to_string(),
-            "/path/to/log.log".to_string(),
+            None,
+            "file".to_string(),
             "openrouter".to_string(),
             "ERROR".to_string(),
-        );
+        )
+        .with_source("/path/to/log.log".to_string());
 
         assert!(!analysis.id.is_empty());
         assert_eq!(analysis.status, AnalysisStatus::Pending);
+        assert_eq!(analysis.source.as_deref(), Some("/path/to/log.log"));
         assert!(analysis.completed_at.is_none());
     }
-
-    #[test]
-    fn test_pattern_serialization() {
-        let pattern = Pattern {
-            pattern: "NullPointerException".to_string(),
-            count: 5,
-            examples: vec!["Error example 1".to_string()],
-            severity: "high".to_string(),
-            confidence: 0.9,
-        };
-
-        let json = serde_json::to_string(&pattern).unwrap();
-        assert!(json.contains("NullPointerException"));
-        assert!(json.contains("5"));
-    }
-
-    #[test]
-    fn test_analysis_result_creation() {
-        let result = AnalysisResult::new("analysis-id".to_string());
-
-        assert_eq!(result.analysis_id, "analysis-id");
-        assert!(result.summary.is_none());
-        assert_eq!(result.patterns_detected, serde_json::json!([]));
-    }
 }
@@ -1,222 +1,3250 @@
+// Repository functions shared by the CLI, web backend and MCP server
+//
+// The SQL is written once for both backends: `$N` placeholders are understood by
+// SQLite and PostgreSQL alike, and dynamic filters go through `QueryBuilder`, which
+// emits each backend's own placeholder syntax. The `repository!` macro expands the
+// functions for `SqlitePool` here and for `PgPool` in the `postgres` module; only
+// full-text search and the locking for the first account differ between the two.
+
+use std::collections::BTreeMap;
+
 use anyhow::Result;
 use chrono::{DateTime, Utc};
-use sqlx::{query, SqlitePool};
+use sqlx::{FromRow, Row, SqlitePool};
 use uuid::Uuid;
 
-use crate::project::models::{Analysis, AnalysisResult, AnalysisStatus, Pattern, Project};
+use crate::project::models::{
+    Alert, AlertQuery, AlertRule, AlertStatus, Analysis, AnalysisSchedule, AnalysisStatus, ApiToken,
+    AuditEvent, AuditQuery, DeliveryQuery, DeliveryStatus, ErrorCorrelation, ErrorPattern,
+    KnowledgeBaseEntry, AnalysisJob, JobQuery, JobStatus, LogCount, LogFile, LogPartition, LogRetention,
+    NotificationChannel, NotificationDelivery, PerformanceMetric, Project, ProjectMember,
+    ProjectMemberChange, ProjectRole, ProjectSummary, ProviderSecret, Session, Settings, Share,
+    StoredLogEntry, StreamingLogQuery, StreamingSource, User,
+};
+
+const PROJECT_COLUMNS: &str =
+    "id, name, description, root_path, synapse_config, project_type, last_accessed, created_at, \
+     updated_at, created_by";
+
+const PROJECT_SUMMARY_SELECT: &str =
+    "SELECT p.id, p.name, p.description, p.created_at, p.updated_at,
+            COUNT(DISTINCT f.id) AS file_count,
+            COUNT(DISTINCT a.id) AS analysis_count,
+            MAX(a.started_at) AS last_analysis_date
+     FROM projects p
+     LEFT JOIN log_files f ON p.id = f.project_id
+     LEFT JOIN analyses a ON p.id = a.project_id";
+
+const PROJECT_SUMMARY_GROUP_BY: &str =
+    " GROUP BY p.id, p.name, p.description, p.created_at, p.updated_at";
+
+const LOG_FILE_COLUMNS: &str =
+    "id, project_id, filename, file_size, line_count, upload_path, created_at";
+
+const ANALYSIS_COLUMNS: &str =
+    "id, project_id, log_file_id, analysis_type, provider, level_filter, status, result, \
+     error_message, started_at, completed_at, source, created_by, alert_id";
+
+const KNOWLEDGE_COLUMNS: &str =
+    "id, project_id, title, problem_description, solution, tags, severity, is_public, \
+     usage_count, created_at, updated_at, created_by";
+
+const ERROR_PATTERN_COLUMNS: &str =
+    "id, project_id, pattern, category, description, frequency, last_seen, suggested_solution, \
+     created_at, updated_at";
+
+const PERFORMANCE_METRIC_COLUMNS: &str =
+    "id, analysis_id, metric_name, metric_value, unit, threshold_value, is_bottleneck, created_at";
+
+const ERROR_CORRELATION_COLUMNS: &str =
+    "id, project_id, primary_error_id, correlated_error_id, correlation_strength, correlation_type, \
+     created_at";
+
+const USER_COLUMNS: &str =
+    "id, username, email, display_name, password_hash, is_admin, is_active, last_login_at, \
+     created_at, updated_at, oidc_issuer, oidc_subject";
+
+const SESSION_COLUMNS: &str =
+    "id, user_id, user_agent, ip_address, created_at, expires_at, last_seen_at";
+
+const API_TOKEN_COLUMNS: &str =
+    "id, user_id, name, token_hash, token_prefix, created_at, last_used_at, expires_at";
+
+const PROVIDER_SECRET_COLUMNS: &str =
+    "provider, key_id, nonce, ciphertext, hint, created_at, updated_at";
+
+const AUDIT_COLUMNS: &str =
+    "id, occurred_at, actor_id, actor_name, action, project_id, target_type, target_id, details, \
+     ip_address";
+
+const SHARE_COLUMNS: &str =
+    "id, project_id, analysis_id, created_by, password_hash, expires_at, allow_download, \
+     redact_sensitive, include_correlations, include_metrics, access_count, last_accessed_at, \
+     revoked_at, created_at";
+
+const JOB_COLUMNS: &str =
+    "id, analysis_id, project_id, queue, status, priority, attempts, max_attempts, timeout_secs, \
+     payload, run_after, worker_id, lease_expires_at, last_error, created_by, created_at, \
+     started_at, finished_at";
+
+const ALERT_RULE_COLUMNS: &str =
+    "id, project_id, name, description, condition, severity, source, enabled, cooldown_secs, \
+     silenced_until, silence_reason, last_fired_at, created_by, created_at, updated_at";
+
+const ALERT_COLUMNS: &str =
+    "id, rule_id, project_id, rule_name, severity, status, fingerprint, source, summary, details, \
+     occurrences, first_seen_at, last_seen_at, acknowledged_by, acknowledged_at, resolved_at";
+
+const CHANNEL_COLUMNS: &str =
+    "id, project_id, name, channel_type, config, notify_alerts, notify_analyses, min_severity, \
+     title_template, body_template, enabled, created_by, created_at, updated_at";
+
+const SCHEDULE_COLUMNS: &str =
+    "id, project_id, name, source, cron, trigger_rule_id, window_secs, provider, level_filter, enabled, \
+     last_run_at, last_analysis_id, created_by, created_at, updated_at";
+
+const DELIVERY_COLUMNS: &str =
+    "id, channel_id, project_id, event, event_id, status, attempts, max_attempts, payload, last_error, \
+     response_status, next_attempt_at, created_at, updated_at, delivered_at";
+
+const STREAMING_SOURCE_COLUMNS: &str =
+    "id, project_id, name, source_type, config, parser_config, buffer_size, batch_timeout_seconds, \
+     restart_on_error, max_restarts, ingest_token_hash, status, created_at, updated_at";
+
+const STREAMING_LOG_COLUMNS: &str =
+    "seq, id, project_id, source, partition_start, timestamp, level, severity, message, line_number, \
+     metadata, size_bytes";
+
+const STREAMING_LOG_INSERT_COLUMNS: &str =
+    "id, project_id, source, partition_start, timestamp, level, severity, message, line_number, metadata, \
+     size_bytes";
+
+/// Streaming entries inserted per statement, well under both backends' bind parameter limits
+const STREAMING_LOG_INSERT_CHUNK: usize = 500;
+
+const SETTINGS_COLUMNS: &str =
+    "default_provider, api_key, max_lines, default_level, show_timestamps, show_line_numbers, \
+     selected_model, available_models, models_last_fetched, analysis_timeout_seconds";
+
+/// Split a free-text search into the word tokens both full-text engines index
+///
+/// Punctuation (including regex syntax from error patterns) is dropped so the
+/// tokens can be quoted safely into an FTS5 or tsquery expression.
+pub fn search_terms(query: &str) -> Vec<String> {
+    query
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|term| !term.is_empty())
+        .map(|term| term.to_lowercase())
+        .collect()
+}
+
+/// Push the project/public visibility filter for a knowledge base query
+///
+/// Without a project only public entries are visible, so other projects' private
+/// entries are never returned.
+fn push_knowledge_scope<'a, DB: sqlx::Database>(
+    query_builder: &mut sqlx::QueryBuilder<'a, DB>,
+    column_prefix: &str,
+    project_id: Option<&'a str>,
+    include_public: bool,
+) where
+    &'a str: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+{
+    if let Some(project_id) = project_id {
+        query_builder.push(format!(" AND ({}project_id = ", column_prefix));
+        query_builder.push_bind(project_id);
+        if include_public {
+            query_builder.push(format!(" OR {}is_public = TRUE", column_prefix));
+        }
+        query_builder.push(")");
+    } else {
+        query_builder.push(format!(" AND {}is_public = TRUE", column_prefix));
+    }
+}
+
+/// Insert a user only while the table is empty, binding the same values as `create_user`
+fn first_user_insert() -> String {
+    format!(
+        "INSERT INTO users ({}) SELECT $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12
+         WHERE NOT EXISTS (SELECT 1 FROM users)",
+        USER_COLUMNS
+    )
+}
+
+/// Push everything of a streaming log search but its text: filters, order and page size
+fn push_streaming_log_filters<'a, DB: sqlx::Database>(
+    query_builder: &mut sqlx::QueryBuilder<'a, DB>,
+    column_prefix: &str,
+    query: &'a StreamingLogQuery,
+) where
+    &'a str: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    i32: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    i64: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    DateTime<Utc>: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+{
+    query_builder.push(format!(" AND {}project_id = ", column_prefix));
+    query_builder.push_bind(query.project_id.as_str());
+
+    if let Some(source) = &query.source {
+        query_builder.push(format!(" AND {}source = ", column_prefix));
+        query_builder.push_bind(source.as_str());
+    }
+
+    if let Some(min_severity) = query.min_severity {
+        query_builder.push(format!(" AND {}severity >= ", column_prefix));
+        query_builder.push_bind(min_severity);
+    }
+
+    if let Some(since) = query.since {
+        query_builder.push(format!(" AND {}timestamp >= ", column_prefix));
+        query_builder.push_bind(since);
+    }
+
+    if let Some(until) = query.until {
+        query_builder.push(format!(" AND {}timestamp < ", column_prefix));
+        query_builder.push_bind(until);
+    }
+
+    if let Some(before) = query.before {
+        query_builder.push(format!(" AND ({}timestamp < ", column_prefix));
+        query_builder.push_bind(before.timestamp);
+        query_builder.push(format!(" OR ({}timestamp = ", column_prefix));
+        query_builder.push_bind(before.timestamp);
+        query_builder.push(format!(" AND {}seq < ", column_prefix));
+        query_builder.push_bind(before.seq);
+        query_builder.push("))");
+    }
+
+    query_builder.push(format!(" ORDER BY {0}timestamp DESC, {0}seq DESC LIMIT ", column_prefix));
+    query_builder.push_bind(query.limit);
+}
+
+macro_rules! repository {
+    ($pool:ty) => {
+/// List all projects, most recently updated first
+pub async fn list_projects(pool: &$pool) -> Result<Vec<Project>> {
+    let projects = sqlx::query_as::<_, Project>(&format!(
+        "SELECT {} FROM projects ORDER BY updated_at DESC",
+        PROJECT_COLUMNS
+    ))
+    .fetch_all(pool)
+    .await?;
+
+    Ok(projects)
+}
+
+/// Count all projects
+pub async fn count_projects(pool: &$pool) -> Result<i64> {
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM projects")
+        .fetch_one(pool)
+        .await?;
+
+    Ok(count)
+}
+
+/// Retrieve a project by id
+pub async fn get_project(pool: &$pool, project_id: &str) -> Result<Option<Project>> {
+    let project = sqlx::query_as::<_, Project>(&format!(
+        "SELECT {} FROM projects WHERE id = $1",
+        PROJECT_COLUMNS
+    ))
+    .bind(project_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(project)
+}
+
+/// Resolve project by path
+pub async fn get_project_by_path(
+    pool: &$pool,
+    root_path: &str,
+) -> Result<Option<Project>> {
+    let project = sqlx::query_as::<_, Project>(&format!(
+        "SELECT {} FROM projects WHERE root_path = $1",
+        PROJECT_COLUMNS
+    ))
+    .bind(root_path)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(project)
+}
+
+/// List projects with file and analysis counts, optionally only those with the given names
+pub async fn list_project_summaries(
+    pool: &$pool,
+    names: Option<&[String]>,
+) -> Result<Vec<ProjectSummary>> {
+    let mut query_builder = sqlx::QueryBuilder::new(PROJECT_SUMMARY_SELECT);
+
+    if let Some(names) = names {
+        if names.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        query_builder.push(" WHERE p.name IN (");
+        let mut separated = query_builder.separated(", ");
+        for name in names {
+            separated.push_bind(name);
+        }
+        separated.push_unseparated(")");
+    }
+
+    query_builder.push(PROJECT_SUMMARY_GROUP_BY);
+    query_builder.push(" ORDER BY p.updated_at DESC");
+
+    let summaries = query_builder
+        .build_query_as::<ProjectSummary>()
+        .fetch_all(pool)
+        .await?;
+
+    Ok(summaries)
+}
+
+/// Retrieve a project with file and analysis counts
+pub async fn get_project_summary(
+    pool: &$pool,
+    project_id: &str,
+) -> Result<Option<ProjectSummary>> {
+    let summary = sqlx::query_as::<_, ProjectSummary>(&format!(
+        "{} WHERE p.id = $1{}",
+        PROJECT_SUMMARY_SELECT, PROJECT_SUMMARY_GROUP_BY
+    ))
+    .bind(project_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(summary)
+}
+
+/// Check whether a project is registered under either its id or its root path
+pub async fn project_exists(pool: &$pool, project_id: &str, root_path: &str) -> Result<bool> {
+    let count: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM projects WHERE id = $1 OR root_path = $2"
+    )
+    .bind(project_id)
+    .bind(root_path)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(count > 0)
+}
+
+/// Insert a new project
+pub async fn insert_project(pool: &$pool, project: &Project) -> Result<()> {
+    sqlx::query(&format!(
+        "INSERT INTO projects ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+        PROJECT_COLUMNS
+    ))
+    .bind(&project.id)
+    .bind(&project.name)
+    .bind(&project.description)
+    .bind(&project.root_path)
+    .bind(&project.synapse_config)
+    .bind(&project.project_type)
+    .bind(project.last_accessed)
+    .bind(project.created_at)
+    .bind(project.updated_at)
+    .bind(&project.created_by)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Insert a CLI project, or refresh the row already registered under its id or root path
+///
+/// Returns `true` when a new row was inserted.
+pub async fn upsert_cli_project(pool: &$pool, project: &Project) -> Result<bool> {
+    let updated = sqlx::query(
+        "UPDATE projects SET
+            name = $1,
+            root_path = $2,
+            synapse_config = $3,
+            project_type = $4,
+            last_accessed = CURRENT_TIMESTAMP,
+            updated_at = CURRENT_TIMESTAMP
+         WHERE id = $5 OR root_path = $2"
+    )
+    .bind(&project.name)
+    .bind(&project.root_path)
+    .bind(&project.synapse_config)
+    .bind(&project.project_type)
+    .bind(&project.id)
+    .execute(pool)
+    .await?;
+
+    if updated.rows_affected() > 0 {
+        return Ok(false);
+    }
+
+    insert_project(pool, project).await?;
+    Ok(true)
+}
+
+/// Create or get project by path
+pub async fn get_or_create_project(
+    pool: &$pool,
+    root_path: &str,
+) -> Result<String> {
+    // Try to get existing project
+    if let Some(project) = get_project_by_path(pool, root_path).await? {
+        return Ok(project.id);
+    }
+
+    // Create new project
+    let name = std::path::Path::new(root_path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("Unknown")
+        .to_string();
+
+    let project = Project::new_cli_project(
+        Uuid::new_v4().to_string(),
+        name,
+        None,
+        root_path.to_string(),
+        None,
+        "unknown".to_string(),
+    );
+    insert_project(pool, &project).await?;
+
+    Ok(project.id)
+}
+
+/// A project's own cap on analyses per day; `None` when it uses the server default
+pub async fn get_project_analysis_quota(pool: &$pool, project_id: &str) -> Result<Option<i32>> {
+    let quota: Option<Option<i32>> =
+        sqlx::query_scalar("SELECT daily_analysis_quota FROM projects WHERE id = $1")
+            .bind(project_id)
+            .fetch_optional(pool)
+            .await?;
+
+    Ok(quota.flatten())
+}
+
+/// Set or clear a project's cap on analyses per day
+///
+/// Returns `false` when no such project exists.
+pub async fn set_project_analysis_quota(
+    pool: &$pool,
+    project_id: &str,
+    quota: Option<i32>,
+) -> Result<bool> {
+    let result = sqlx::query("UPDATE projects SET daily_analysis_quota = $1, updated_at = $2 WHERE id = $3")
+        .bind(quota)
+        .bind(Utc::now())
+        .bind(project_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// A project's own limits on stored streaming entries; `None` when no such project exists
+pub async fn get_project_log_retention(pool: &$pool, project_id: &str) -> Result<Option<LogRetention>> {
+    let retention = sqlx::query_as::<_, LogRetention>(
+        "SELECT log_retention_days, log_retention_mb FROM projects WHERE id = $1"
+    )
+    .bind(project_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(retention)
+}
+
+/// Set or clear a project's limits on stored streaming entries
+///
+/// Returns `false` when no such project exists.
+pub async fn set_project_log_retention(
+    pool: &$pool,
+    project_id: &str,
+    retention: &LogRetention,
+) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE projects SET log_retention_days = $1, log_retention_mb = $2, updated_at = $3 WHERE id = $4"
+    )
+    .bind(retention.max_age_days)
+    .bind(retention.max_size_mb)
+    .bind(Utc::now())
+    .bind(project_id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Delete a project and, through cascading foreign keys, its files and analyses
+///
+/// Returns `false` when no such project exists.
+pub async fn delete_project(pool: &$pool, project_id: &str) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM projects WHERE id = $1")
+        .bind(project_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// List a project's uploaded log files, newest first
+pub async fn list_log_files(pool: &$pool, project_id: &str) -> Result<Vec<LogFile>> {
+    let files = sqlx::query_as::<_, LogFile>(&format!(
+        "SELECT {} FROM log_files WHERE project_id = $1 ORDER BY created_at DESC",
+        LOG_FILE_COLUMNS
+    ))
+    .bind(project_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(files)
+}
+
+/// Retrieve a log file that belongs to the given project
+pub async fn get_log_file(
+    pool: &$pool,
+    project_id: &str,
+    file_id: &str,
+) -> Result<Option<LogFile>> {
+    let file = sqlx::query_as::<_, LogFile>(&format!(
+        "SELECT {} FROM log_files WHERE id = $1 AND project_id = $2",
+        LOG_FILE_COLUMNS
+    ))
+    .bind(file_id)
+    .bind(project_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(file)
+}
+
+/// Record an uploaded log file
+#[tracing::instrument(name = "db.write", skip_all, fields(db.operation = "insert_log_file"))]
+pub async fn insert_log_file(pool: &$pool, log_file: &LogFile) -> Result<()> {
+    sqlx::query(&format!(
+        "INSERT INTO log_files ({}) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        LOG_FILE_COLUMNS
+    ))
+    .bind(&log_file.id)
+    .bind(&log_file.project_id)
+    .bind(&log_file.filename)
+    .bind(log_file.file_size)
+    .bind(log_file.line_count)
+    .bind(&log_file.upload_path)
+    .bind(log_file.created_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Delete a log file record, detaching the analyses that referenced it
+///
+/// Returns `false` when no such log file exists.
+pub async fn delete_log_file(pool: &$pool, file_id: &str) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM log_files WHERE id = $1")
+        .bind(file_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Count the analyses run against a log file, optionally only those still pending or running
+pub async fn count_log_file_analyses(
+    pool: &$pool,
+    file_id: &str,
+    active_only: bool,
+) -> Result<i64> {
+    let sql = if active_only {
+        "SELECT COUNT(*) FROM analyses WHERE log_file_id = $1 AND status IN (0, 1)"
+    } else {
+        "SELECT COUNT(*) FROM analyses WHERE log_file_id = $1"
+    };
+
+    let count: i64 = sqlx::query_scalar(sql)
+        .bind(file_id)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(count)
+}
+
+/// Create new analysis record
+#[tracing::instrument(name = "db.write", skip_all, fields(db.operation = "create_analysis"))]
+pub async fn create_analysis(pool: &$pool, analysis: &Analysis) -> Result<()> {
+    sqlx::query(&format!(
+        "INSERT INTO analyses ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)",
+        ANALYSIS_COLUMNS
+    ))
+    .bind(&analysis.id)
+    .bind(&analysis.project_id)
+    .bind(&analysis.log_file_id)
+    .bind(&analysis.analysis_type)
+    .bind(&analysis.provider)
+    .bind(&analysis.level_filter)
+    .bind(analysis.status)
+    .bind(&analysis.result)
+    .bind(&analysis.error_message)
+    .bind(analysis.started_at)
+    .bind(analysis.completed_at)
+    .bind(&analysis.source)
+    .bind(&analysis.created_by)
+    .bind(&analysis.alert_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Retrieve an analysis, including its stored result
+pub async fn get_analysis_by_id(
+    pool: &$pool,
+    analysis_id: &str,
+) -> Result<Option<Analysis>> {
+    let analysis = sqlx::query_as::<_, Analysis>(&format!(
+        "SELECT {} FROM analyses WHERE id = $1",
+        ANALYSIS_COLUMNS
+    ))
+    .bind(analysis_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(analysis)
+}
+
+/// Query analyses with filters
+pub async fn query_analyses(
+    pool: &$pool,
+    project_id: Option<&str>,
+    status: Option<AnalysisStatus>,
+    limit: Option<i64>,
+    since: Option<DateTime<Utc>>,
+) -> Result<Vec<Analysis>> {
+    let mut query_builder = sqlx::QueryBuilder::new(format!(
+        "SELECT {} FROM analyses WHERE 1=1",
+        ANALYSIS_COLUMNS
+    ));
+
+    if let Some(project_id) = project_id {
+        query_builder.push(" AND project_id = ");
+        query_builder.push_bind(project_id);
+    }
+
+    if let Some(status) = status {
+        query_builder.push(" AND status = ");
+        query_builder.push_bind(status);
+    }
+
+    if let Some(since) = since {
+        query_builder.push(" AND started_at >= ");
+        query_builder.push_bind(since);
+    }
+
+    query_builder.push(" ORDER BY started_at DESC");
+
+    if let Some(limit) = limit {
+        query_builder.push(" LIMIT ");
+        query_builder.push_bind(limit);
+    }
+
+    let analyses = query_builder
+        .build_query_as::<Analysis>()
+        .fetch_all(pool)
+        .await?;
+
+    Ok(analyses)
+}
+
+/// The most recent analysis of an uploaded log with the given provider and level filter
+pub async fn find_latest_file_analysis(
+    pool: &$pool,
+    project_id: &str,
+    log_file_id: &str,
+    provider: &str,
+    level_filter: &str,
+) -> Result<Option<Analysis>> {
+    let analysis = sqlx::query_as::<_, Analysis>(&format!(
+        "SELECT {} FROM analyses
+         WHERE project_id = $1 AND log_file_id = $2 AND provider = $3 AND level_filter = $4
+         ORDER BY started_at DESC LIMIT 1",
+        ANALYSIS_COLUMNS
+    ))
+    .bind(project_id)
+    .bind(log_file_id)
+    .bind(provider)
+    .bind(level_filter)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(analysis)
+}
+
+/// Page through a project's analyses with the filename of the uploaded log they read
+pub async fn list_analyses_with_files(
+    pool: &$pool,
+    project_id: &str,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<(Analysis, Option<String>)>> {
+    let rows = sqlx::query(
+        "SELECT a.id, a.project_id, a.log_file_id, a.analysis_type, a.provider, a.level_filter,
+                a.status, a.result, a.error_message, a.started_at, a.completed_at, a.source,
+                a.created_by, a.alert_id, f.filename
+         FROM analyses a
+         LEFT JOIN log_files f ON a.log_file_id = f.id
+         WHERE a.project_id = $1
+         ORDER BY a.started_at DESC
+         LIMIT $2 OFFSET $3"
+    )
+    .bind(project_id)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await?;
+
+    let mut analyses = Vec::with_capacity(rows.len());
+    for row in &rows {
+        analyses.push((Analysis::from_row(row)?, row.try_get("filename")?));
+    }
+
+    Ok(analyses)
+}
+
+/// Count a project's analyses
+pub async fn count_analyses(pool: &$pool, project_id: &str) -> Result<i64> {
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM analyses WHERE project_id = $1")
+        .bind(project_id)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(count)
+}
+
+/// Count analyses started since the given time, optionally only those with one status
+pub async fn count_analyses_since(
+    pool: &$pool,
+    status: Option<AnalysisStatus>,
+    since: DateTime<Utc>,
+) -> Result<i64> {
+    let mut query_builder =
+        sqlx::QueryBuilder::new("SELECT COUNT(*) FROM analyses WHERE started_at >= ");
+    query_builder.push_bind(since);
+
+    if let Some(status) = status {
+        query_builder.push(" AND status = ");
+        query_builder.push_bind(status);
+    }
+
+    let count: i64 = query_builder
+        .build_query_scalar()
+        .fetch_one(pool)
+        .await?;
+
+    Ok(count)
+}
+
+/// Count a project's analyses started since the given time
+pub async fn count_project_analyses_since(
+    pool: &$pool,
+    project_id: &str,
+    since: DateTime<Utc>,
+) -> Result<i64> {
+    let count: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM analyses WHERE project_id = $1 AND started_at >= $2"
+    )
+    .bind(project_id)
+    .bind(since)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(count)
+}
+
+/// Average minutes between start and completion of analyses completed since the given time
+pub async fn average_analysis_minutes(
+    pool: &$pool,
+    since: DateTime<Utc>,
+) -> Result<Option<f64>> {
+    // Averaged here rather than in SQL, where date arithmetic differs per backend
+    let durations: Vec<(DateTime<Utc>, DateTime<Utc>)> = sqlx::query_as(
+        "SELECT started_at, completed_at FROM analyses
+         WHERE status = $1 AND completed_at IS NOT NULL AND started_at >= $2"
+    )
+    .bind(AnalysisStatus::Completed)
+    .bind(since)
+    .fetch_all(pool)
+    .await?;
+
+    if durations.is_empty() {
+        return Ok(None);
+    }
+
+    let total_minutes: f64 = durations
+        .iter()
+        .map(|(started, completed)| (*completed - *started).num_milliseconds() as f64 / 60_000.0)
+        .sum();
+
+    Ok(Some(total_minutes / durations.len() as f64))
+}
+
+/// Update analysis status
+pub async fn update_analysis_status(
+    pool: &$pool,
+    analysis_id: &str,
+    status: AnalysisStatus,
+    completed_at: Option<DateTime<Utc>>,
+) -> Result<()> {
+    sqlx::query(
+        "UPDATE analyses SET status = $1, completed_at = $2 WHERE id = $3"
+    )
+    .bind(status)
+    .bind(completed_at)
+    .bind(analysis_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Mark an analysis as running
+#[tracing::instrument(name = "db.write", skip_all, fields(db.operation = "mark_analysis_running"))]
+pub async fn mark_analysis_running(pool: &$pool, analysis_id: &str) -> Result<()> {
+    update_analysis_status(pool, analysis_id, AnalysisStatus::Running, None).await
+}
+
+/// Mark an analysis as pending again, while it waits to be retried
+#[tracing::instrument(name = "db.write", skip_all, fields(db.operation = "mark_analysis_pending"))]
+pub async fn mark_analysis_pending(pool: &$pool, analysis_id: &str) -> Result<()> {
+    update_analysis_status(pool, analysis_id, AnalysisStatus::Pending, None).await
+}
+
+/// Store a completed analysis result (JSON serialized AnalysisResponse)
+#[tracing::instrument(name = "db.write", skip_all, fields(db.operation = "complete_analysis"))]
+pub async fn complete_analysis(
+    pool: &$pool,
+    analysis_id: &str,
+    result_json: &str,
+) -> Result<()> {
+    sqlx::query(
+        "UPDATE analyses SET status = $1, result = $2, completed_at = CURRENT_TIMESTAMP WHERE id = $3"
+    )
+    .bind(AnalysisStatus::Completed)
+    .bind(result_json)
+    .bind(analysis_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Mark an analysis as failed with an error message
+#[tracing::instrument(name = "db.write", skip_all, fields(db.operation = "fail_analysis"))]
+pub async fn fail_analysis(pool: &$pool, analysis_id: &str, error: &str) -> Result<()> {
+    sqlx::query(
+        "UPDATE analyses SET status = $1, error_message = $2, completed_at = CURRENT_TIMESTAMP WHERE id = $3"
+    )
+    .bind(AnalysisStatus::Failed)
+    .bind(error)
+    .bind(analysis_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Fail analyses left pending or running without a queued or running job to finish them
+///
+/// Analyses run outside the job queue stop with the process, so this is run on startup.
+/// Returns how many analyses were failed.
+#[tracing::instrument(name = "db.write", skip_all, fields(db.operation = "fail_interrupted_analyses"))]
+pub async fn fail_interrupted_analyses(pool: &$pool, error: &str) -> Result<u64> {
+    let result = sqlx::query(
+        "UPDATE analyses SET status = $1, error_message = $2, completed_at = CURRENT_TIMESTAMP
+         WHERE status IN ($3, $4) AND NOT EXISTS (
+             SELECT 1 FROM analysis_jobs
+             WHERE analysis_jobs.analysis_id = analyses.id AND analysis_jobs.status IN ($5, $6)
+         )"
+    )
+    .bind(AnalysisStatus::Failed)
+    .bind(error)
+    .bind(AnalysisStatus::Pending)
+    .bind(AnalysisStatus::Running)
+    .bind(JobStatus::Queued)
+    .bind(JobStatus::Running)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Add an entry to a project's knowledge base
+pub async fn create_knowledge_entry(pool: &$pool, entry: &KnowledgeBaseEntry) -> Result<()> {
+    sqlx::query(&format!(
+        "INSERT INTO knowledge_base ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)",
+        KNOWLEDGE_COLUMNS
+    ))
+    .bind(&entry.id)
+    .bind(&entry.project_id)
+    .bind(&entry.title)
+    .bind(&entry.problem_description)
+    .bind(&entry.solution)
+    .bind(&entry.tags)
+    .bind(&entry.severity)
+    .bind(entry.is_public)
+    .bind(entry.usage_count)
+    .bind(entry.created_at)
+    .bind(entry.updated_at)
+    .bind(&entry.created_by)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Retrieve a knowledge base entry by id
+pub async fn get_knowledge_entry(
+    pool: &$pool,
+    entry_id: &str,
+) -> Result<Option<KnowledgeBaseEntry>> {
+    let entry = sqlx::query_as::<_, KnowledgeBaseEntry>(&format!(
+        "SELECT {} FROM knowledge_base WHERE id = $1",
+        KNOWLEDGE_COLUMNS
+    ))
+    .bind(entry_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(entry)
+}
+
+/// List a project's knowledge base, most used first
+pub async fn list_knowledge_entries(
+    pool: &$pool,
+    project_id: &str,
+) -> Result<Vec<KnowledgeBaseEntry>> {
+    let entries = sqlx::query_as::<_, KnowledgeBaseEntry>(&format!(
+        "SELECT {} FROM knowledge_base WHERE project_id = $1
+         ORDER BY usage_count DESC, created_at DESC",
+        KNOWLEDGE_COLUMNS
+    ))
+    .bind(project_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(entries)
+}
+
+/// List entries shared across projects, most used first
+pub async fn list_public_knowledge(
+    pool: &$pool,
+    limit: i64,
+) -> Result<Vec<KnowledgeBaseEntry>> {
+    let entries = sqlx::query_as::<_, KnowledgeBaseEntry>(&format!(
+        "SELECT {} FROM knowledge_base WHERE is_public = TRUE
+         ORDER BY usage_count DESC, created_at DESC LIMIT $1",
+        KNOWLEDGE_COLUMNS
+    ))
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(entries)
+}
+
+/// Record that a knowledge base entry has been referenced
+pub async fn increment_knowledge_usage(pool: &$pool, entry_id: &str) -> Result<()> {
+    sqlx::query("UPDATE knowledge_base SET usage_count = usage_count + 1 WHERE id = $1")
+        .bind(entry_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// List a project's error patterns, most frequent first, optionally within one category
+pub async fn list_error_patterns(
+    pool: &$pool,
+    project_id: &str,
+    category: Option<&str>,
+) -> Result<Vec<ErrorPattern>> {
+    let mut query_builder = sqlx::QueryBuilder::new(format!(
+        "SELECT {} FROM error_patterns WHERE project_id = ",
+        ERROR_PATTERN_COLUMNS
+    ));
+    query_builder.push_bind(project_id);
+
+    if let Some(category) = category {
+        query_builder.push(" AND category = ");
+        query_builder.push_bind(category);
+    }
+
+    query_builder.push(" ORDER BY frequency DESC, last_seen DESC");
+
+    let patterns = query_builder
+        .build_query_as::<ErrorPattern>()
+        .fetch_all(pool)
+        .await?;
+
+    Ok(patterns)
+}
+
+/// Add a recognised error pattern to a project
+pub async fn create_error_pattern(pool: &$pool, pattern: &ErrorPattern) -> Result<()> {
+    sqlx::query(&format!(
+        "INSERT INTO error_patterns ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+        ERROR_PATTERN_COLUMNS
+    ))
+    .bind(&pattern.id)
+    .bind(&pattern.project_id)
+    .bind(&pattern.pattern)
+    .bind(&pattern.category)
+    .bind(&pattern.description)
+    .bind(pattern.frequency)
+    .bind(pattern.last_seen)
+    .bind(&pattern.suggested_solution)
+    .bind(pattern.created_at)
+    .bind(pattern.updated_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Record that an error pattern has been seen again
+///
+/// Returns `false` when no such pattern exists.
+pub async fn record_error_pattern_match(pool: &$pool, pattern_id: &str) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE error_patterns
+         SET frequency = frequency + 1, last_seen = CURRENT_TIMESTAMP, updated_at = CURRENT_TIMESTAMP
+         WHERE id = $1"
+    )
+    .bind(pattern_id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Record a measurement taken during an analysis
+pub async fn create_performance_metric(pool: &$pool, metric: &PerformanceMetric) -> Result<()> {
+    sqlx::query(&format!(
+        "INSERT INTO performance_metrics ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        PERFORMANCE_METRIC_COLUMNS
+    ))
+    .bind(&metric.id)
+    .bind(&metric.analysis_id)
+    .bind(&metric.metric_name)
+    .bind(metric.metric_value)
+    .bind(&metric.unit)
+    .bind(metric.threshold_value)
+    .bind(metric.is_bottleneck)
+    .bind(metric.created_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// List an analysis's measurements, bottlenecks first
+pub async fn list_performance_metrics(pool: &$pool, analysis_id: &str) -> Result<Vec<PerformanceMetric>> {
+    let metrics = sqlx::query_as::<_, PerformanceMetric>(&format!(
+        "SELECT {} FROM performance_metrics WHERE analysis_id = $1 ORDER BY is_bottleneck DESC, metric_name",
+        PERFORMANCE_METRIC_COLUMNS
+    ))
+    .bind(analysis_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(metrics)
+}
+
+/// Record a correlation between two of a project's analyses
+pub async fn create_error_correlation(pool: &$pool, correlation: &ErrorCorrelation) -> Result<()> {
+    sqlx::query(&format!(
+        "INSERT INTO error_correlations ({}) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        ERROR_CORRELATION_COLUMNS
+    ))
+    .bind(&correlation.id)
+    .bind(&correlation.project_id)
+    .bind(&correlation.primary_error_id)
+    .bind(&correlation.correlated_error_id)
+    .bind(correlation.correlation_strength)
+    .bind(&correlation.correlation_type)
+    .bind(correlation.created_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// List the correlations a project's analysis takes part in, strongest first
+pub async fn list_error_correlations(
+    pool: &$pool,
+    project_id: &str,
+    analysis_id: &str,
+) -> Result<Vec<ErrorCorrelation>> {
+    let correlations = sqlx::query_as::<_, ErrorCorrelation>(&format!(
+        "SELECT {} FROM error_correlations
+         WHERE project_id = $1 AND (primary_error_id = $2 OR correlated_error_id = $2)
+         ORDER BY correlation_strength DESC",
+        ERROR_CORRELATION_COLUMNS
+    ))
+    .bind(project_id)
+    .bind(analysis_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(correlations)
+}
+
+/// Load the dashboard settings row
+pub async fn get_settings(pool: &$pool) -> Result<Option<Settings>> {
+    let settings = sqlx::query_as::<_, Settings>(&format!(
+        "SELECT {} FROM settings WHERE id = 1",
+        SETTINGS_COLUMNS
+    ))
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(settings)
+}
+
+/// Store the dashboard settings row
+///
+/// `api_key` is always cleared: provider keys are kept encrypted in `provider_secrets`.
+pub async fn update_settings(pool: &$pool, settings: &Settings) -> Result<()> {
+    sqlx::query(
+        "UPDATE settings
+         SET default_provider = $1, api_key = '', max_lines = $2, default_level = $3,
+             show_timestamps = $4, show_line_numbers = $5, selected_model = $6,
+             available_models = $7, models_last_fetched = $8, analysis_timeout_seconds = $9,
+             updated_at = CURRENT_TIMESTAMP
+         WHERE id = 1"
+    )
+    .bind(&settings.default_provider)
+    .bind(settings.max_lines)
+    .bind(&settings.default_level)
+    .bind(settings.show_timestamps)
+    .bind(settings.show_line_numbers)
+    .bind(&settings.selected_model)
+    .bind(&settings.available_models)
+    .bind(&settings.models_last_fetched)
+    .bind(settings.analysis_timeout_seconds)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Replace the cached provider model list and the time it was fetched
+pub async fn update_model_cache(
+    pool: &$pool,
+    available_models: Option<&str>,
+    models_last_fetched: Option<&str>,
+) -> Result<()> {
+    sqlx::query(
+        "UPDATE settings SET available_models = $1, models_last_fetched = $2 WHERE id = 1"
+    )
+    .bind(available_models)
+    .bind(models_last_fetched)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// List the encrypted provider API keys
+pub async fn list_provider_secrets(pool: &$pool) -> Result<Vec<ProviderSecret>> {
+    let secrets = sqlx::query_as::<_, ProviderSecret>(&format!(
+        "SELECT {} FROM provider_secrets ORDER BY provider",
+        PROVIDER_SECRET_COLUMNS
+    ))
+    .fetch_all(pool)
+    .await?;
+
+    Ok(secrets)
+}
+
+/// Get the encrypted API key for a provider
+pub async fn get_provider_secret(pool: &$pool, provider: &str) -> Result<Option<ProviderSecret>> {
+    let secret = sqlx::query_as::<_, ProviderSecret>(&format!(
+        "SELECT {} FROM provider_secrets WHERE provider = $1",
+        PROVIDER_SECRET_COLUMNS
+    ))
+    .bind(provider)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(secret)
+}
+
+/// Store the encrypted API key for a provider, replacing any existing one
+pub async fn set_provider_secret(pool: &$pool, secret: &ProviderSecret) -> Result<()> {
+    sqlx::query(&format!(
+        "INSERT INTO provider_secrets ({}) VALUES ($1, $2, $3, $4, $5, $6, $7)
+         ON CONFLICT (provider) DO UPDATE
+         SET key_id = excluded.key_id, nonce = excluded.nonce, ciphertext = excluded.ciphertext,
+             hint = excluded.hint, updated_at = excluded.updated_at",
+        PROVIDER_SECRET_COLUMNS
+    ))
+    .bind(&secret.provider)
+    .bind(&secret.key_id)
+    .bind(&secret.nonce)
+    .bind(&secret.ciphertext)
+    .bind(&secret.hint)
+    .bind(secret.created_at)
+    .bind(secret.updated_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Delete the API key for a provider
+pub async fn delete_provider_secret(pool: &$pool, provider: &str) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM provider_secrets WHERE provider = $1")
+        .bind(provider)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Append an event to the audit log
+pub async fn record_audit_event(pool: &$pool, event: &AuditEvent) -> Result<()> {
+    sqlx::query(&format!(
+        "INSERT INTO audit_log ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+        AUDIT_COLUMNS
+    ))
+    .bind(&event.id)
+    .bind(event.occurred_at)
+    .bind(&event.actor_id)
+    .bind(&event.actor_name)
+    .bind(&event.action)
+    .bind(&event.project_id)
+    .bind(&event.target_type)
+    .bind(&event.target_id)
+    .bind(&event.details)
+    .bind(&event.ip_address)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Read audit events matching the filters, newest first
+pub async fn query_audit_events(pool: &$pool, filter: &AuditQuery) -> Result<Vec<AuditEvent>> {
+    let mut query_builder = sqlx::QueryBuilder::new(format!(
+        "SELECT {} FROM audit_log WHERE 1=1",
+        AUDIT_COLUMNS
+    ));
+
+    if let Some(actor_id) = &filter.actor_id {
+        query_builder.push(" AND actor_id = ");
+        query_builder.push_bind(actor_id);
+    }
+
+    if let Some(action) = &filter.action {
+        match action.strip_suffix('*') {
+            Some(prefix) => {
+                // Escape LIKE wildcards so only the trailing * matches anything
+                let pattern = prefix.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+                query_builder.push(" AND action LIKE ");
+                query_builder.push_bind(format!("{}%", pattern));
+                query_builder.push(" ESCAPE '\\'");
+            }
+            None => {
+                query_builder.push(" AND action = ");
+                query_builder.push_bind(action);
+            }
+        }
+    }
+
+    if let Some(project_id) = &filter.project_id {
+        query_builder.push(" AND project_id = ");
+        query_builder.push_bind(project_id);
+    }
+
+    if let Some(target_type) = &filter.target_type {
+        query_builder.push(" AND target_type = ");
+        query_builder.push_bind(target_type);
+    }
+
+    if let Some(target_id) = &filter.target_id {
+        query_builder.push(" AND target_id = ");
+        query_builder.push_bind(target_id);
+    }
+
+    if let Some(since) = filter.since {
+        query_builder.push(" AND occurred_at >= ");
+        query_builder.push_bind(since);
+    }
+
+    if let Some(until) = filter.until {
+        query_builder.push(" AND occurred_at < ");
+        query_builder.push_bind(until);
+    }
+
+    query_builder.push(" ORDER BY occurred_at DESC, id");
+
+    if let Some(limit) = filter.limit {
+        query_builder.push(" LIMIT ");
+        query_builder.push_bind(limit);
+        query_builder.push(" OFFSET ");
+        query_builder.push_bind(filter.offset.unwrap_or(0));
+    }
+
+    let events = query_builder
+        .build_query_as::<AuditEvent>()
+        .fetch_all(pool)
+        .await?;
+
+    Ok(events)
+}
+
+/// Store a new share link
+pub async fn create_share(pool: &$pool, share: &Share) -> Result<()> {
+    sqlx::query(&format!(
+        "INSERT INTO shares ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)",
+        SHARE_COLUMNS
+    ))
+    .bind(&share.id)
+    .bind(&share.project_id)
+    .bind(&share.analysis_id)
+    .bind(&share.created_by)
+    .bind(&share.password_hash)
+    .bind(share.expires_at)
+    .bind(share.allow_download)
+    .bind(share.redact_sensitive)
+    .bind(share.include_correlations)
+    .bind(share.include_metrics)
+    .bind(share.access_count)
+    .bind(share.last_accessed_at)
+    .bind(share.revoked_at)
+    .bind(share.created_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Retrieve a share link, including expired and revoked ones
+pub async fn get_share(pool: &$pool, share_id: &str) -> Result<Option<Share>> {
+    let share = sqlx::query_as::<_, Share>(&format!(
+        "SELECT {} FROM shares WHERE id = $1",
+        SHARE_COLUMNS
+    ))
+    .bind(share_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(share)
+}
+
+/// List a project's share links, newest first
+pub async fn list_shares(pool: &$pool, project_id: &str) -> Result<Vec<Share>> {
+    let shares = sqlx::query_as::<_, Share>(&format!(
+        "SELECT {} FROM shares WHERE project_id = $1 ORDER BY created_at DESC",
+        SHARE_COLUMNS
+    ))
+    .bind(project_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(shares)
+}
+
+/// Revoke a project's share link
+///
+/// Returns `false` when the project has no such link or it was already revoked.
+pub async fn revoke_share(pool: &$pool, project_id: &str, share_id: &str) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE shares SET revoked_at = $1 WHERE id = $2 AND project_id = $3 AND revoked_at IS NULL"
+    )
+    .bind(Utc::now())
+    .bind(share_id)
+    .bind(project_id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Count an opening of a share link
+pub async fn record_share_access(pool: &$pool, share_id: &str) -> Result<()> {
+    sqlx::query("UPDATE shares SET access_count = access_count + 1, last_accessed_at = $1 WHERE id = $2")
+        .bind(Utc::now())
+        .bind(share_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Add a job to the analysis queue
+#[tracing::instrument(name = "db.write", skip_all, fields(db.operation = "enqueue_job"))]
+pub async fn enqueue_job(pool: &$pool, job: &AnalysisJob) -> Result<()> {
+    sqlx::query(&format!(
+        "INSERT INTO analysis_jobs ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18)",
+        JOB_COLUMNS
+    ))
+    .bind(&job.id)
+    .bind(&job.analysis_id)
+    .bind(&job.project_id)
+    .bind(&job.queue)
+    .bind(job.status)
+    .bind(job.priority)
+    .bind(job.attempts)
+    .bind(job.max_attempts)
+    .bind(job.timeout_secs)
+    .bind(&job.payload)
+    .bind(job.run_after)
+    .bind(&job.worker_id)
+    .bind(job.lease_expires_at)
+    .bind(&job.last_error)
+    .bind(&job.created_by)
+    .bind(job.created_at)
+    .bind(job.started_at)
+    .bind(job.finished_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Retrieve a job by ID
+pub async fn get_job(pool: &$pool, job_id: &str) -> Result<Option<AnalysisJob>> {
+    let job = sqlx::query_as::<_, AnalysisJob>(&format!(
+        "SELECT {} FROM analysis_jobs WHERE id = $1",
+        JOB_COLUMNS
+    ))
+    .bind(job_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(job)
+}
+
+/// List jobs matching a filter, newest first
+pub async fn list_jobs(pool: &$pool, filter: &JobQuery) -> Result<Vec<AnalysisJob>> {
+    let mut query_builder = sqlx::QueryBuilder::new(format!(
+        "SELECT {} FROM analysis_jobs WHERE 1=1",
+        JOB_COLUMNS
+    ));
+
+    if let Some(status) = filter.status {
+        query_builder.push(" AND status = ");
+        query_builder.push_bind(status);
+    }
+
+    if let Some(queue) = &filter.queue {
+        query_builder.push(" AND queue = ");
+        query_builder.push_bind(queue);
+    }
+
+    if let Some(project_id) = &filter.project_id {
+        query_builder.push(" AND project_id = ");
+        query_builder.push_bind(project_id);
+    }
+
+    if let Some(project_ids) = &filter.project_ids {
+        if project_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        query_builder.push(" AND project_id IN (");
+        let mut separated = query_builder.separated(", ");
+        for project_id in project_ids {
+            separated.push_bind(project_id);
+        }
+        separated.push_unseparated(")");
+    }
+
+    if let Some(analysis_id) = &filter.analysis_id {
+        query_builder.push(" AND analysis_id = ");
+        query_builder.push_bind(analysis_id);
+    }
+
+    query_builder.push(" ORDER BY created_at DESC, id");
+
+    if let Some(limit) = filter.limit {
+        query_builder.push(" LIMIT ");
+        query_builder.push_bind(limit);
+        query_builder.push(" OFFSET ");
+        query_builder.push_bind(filter.offset.unwrap_or(0));
+    }
+
+    let jobs = query_builder
+        .build_query_as::<AnalysisJob>()
+        .fetch_all(pool)
+        .await?;
+
+    Ok(jobs)
+}
+
+/// Claim the next runnable job in a queue for a worker
+///
+/// Takes the highest priority queued job whose `run_after` has passed, oldest first,
+/// marks it running under the worker's lease and counts the attempt. Another process
+/// may claim the same row between the read and the update; the update only succeeds
+/// for one of them, and the loser tries the next job.
+pub async fn claim_next_job(
+    pool: &$pool,
+    queue: &str,
+    worker_id: &str,
+    lease_expires_at: DateTime<Utc>,
+) -> Result<Option<AnalysisJob>> {
+    loop {
+        let candidate: Option<String> = sqlx::query_scalar(
+            "SELECT id FROM analysis_jobs WHERE queue = $1 AND status = $2 AND run_after <= $3
+             ORDER BY priority DESC, created_at, id LIMIT 1"
+        )
+        .bind(queue)
+        .bind(JobStatus::Queued)
+        .bind(Utc::now())
+        .fetch_optional(pool)
+        .await?;
+
+        let Some(job_id) = candidate else {
+            return Ok(None);
+        };
+
+        let result = sqlx::query(
+            "UPDATE analysis_jobs SET status = $1, attempts = attempts + 1, worker_id = $2,
+                    lease_expires_at = $3, started_at = $4
+             WHERE id = $5 AND status = $6"
+        )
+        .bind(JobStatus::Running)
+        .bind(worker_id)
+        .bind(lease_expires_at)
+        .bind(Utc::now())
+        .bind(&job_id)
+        .bind(JobStatus::Queued)
+        .execute(pool)
+        .await?;
+
+        if result.rows_affected() > 0 {
+            return get_job(pool, &job_id).await;
+        }
+    }
+}
+
+/// Extend a worker's lease on a running job
+///
+/// Returns `false` when the job is no longer running under that worker: it was
+/// cancelled, or its lease lapsed and it was handed to someone else.
+pub async fn renew_job_lease(
+    pool: &$pool,
+    job_id: &str,
+    worker_id: &str,
+    lease_expires_at: DateTime<Utc>,
+) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE analysis_jobs SET lease_expires_at = $1 WHERE id = $2 AND worker_id = $3 AND status = $4"
+    )
+    .bind(lease_expires_at)
+    .bind(job_id)
+    .bind(worker_id)
+    .bind(JobStatus::Running)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Mark a worker's running job as completed
+#[tracing::instrument(name = "db.write", skip_all, fields(db.operation = "complete_job"))]
+pub async fn complete_job(pool: &$pool, job_id: &str, worker_id: &str) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE analysis_jobs SET status = $1, lease_expires_at = NULL, last_error = NULL, finished_at = $2
+         WHERE id = $3 AND worker_id = $4 AND status = $5"
+    )
+    .bind(JobStatus::Completed)
+    .bind(Utc::now())
+    .bind(job_id)
+    .bind(worker_id)
+    .bind(JobStatus::Running)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Put a worker's running job back in the queue, to run again after `run_after`
+#[tracing::instrument(name = "db.write", skip_all, fields(db.operation = "retry_job"))]
+pub async fn retry_job(
+    pool: &$pool,
+    job_id: &str,
+    worker_id: &str,
+    run_after: DateTime<Utc>,
+    error: &str,
+) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE analysis_jobs SET status = $1, run_after = $2, last_error = $3, worker_id = NULL,
+                lease_expires_at = NULL
+         WHERE id = $4 AND worker_id = $5 AND status = $6"
+    )
+    .bind(JobStatus::Queued)
+    .bind(run_after)
+    .bind(error)
+    .bind(job_id)
+    .bind(worker_id)
+    .bind(JobStatus::Running)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Mark a worker's running job as failed for good
+#[tracing::instrument(name = "db.write", skip_all, fields(db.operation = "fail_job"))]
+pub async fn fail_job(pool: &$pool, job_id: &str, worker_id: &str, error: &str) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE analysis_jobs SET status = $1, last_error = $2, lease_expires_at = NULL, finished_at = $3
+         WHERE id = $4 AND worker_id = $5 AND status = $6"
+    )
+    .bind(JobStatus::Failed)
+    .bind(error)
+    .bind(Utc::now())
+    .bind(job_id)
+    .bind(worker_id)
+    .bind(JobStatus::Running)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Cancel a queued or running job
+///
+/// Returns `false` when the job does not exist or has already finished. A worker
+/// running the job notices on its next lease renewal.
+pub async fn cancel_job(pool: &$pool, job_id: &str) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE analysis_jobs SET status = $1, lease_expires_at = NULL, finished_at = $2
+         WHERE id = $3 AND status IN ($4, $5)"
+    )
+    .bind(JobStatus::Cancelled)
+    .bind(Utc::now())
+    .bind(job_id)
+    .bind(JobStatus::Queued)
+    .bind(JobStatus::Running)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// List a queue's running jobs whose lease has lapsed, left by a worker that stopped
+pub async fn list_expired_jobs(pool: &$pool, queue: &str) -> Result<Vec<AnalysisJob>> {
+    let jobs = sqlx::query_as::<_, AnalysisJob>(&format!(
+        "SELECT {} FROM analysis_jobs WHERE queue = $1 AND status = $2 AND lease_expires_at < $3
+         ORDER BY created_at",
+        JOB_COLUMNS
+    ))
+    .bind(queue)
+    .bind(JobStatus::Running)
+    .bind(Utc::now())
+    .fetch_all(pool)
+    .await?;
+
+    Ok(jobs)
+}
+
+/// Store a new alert rule
+pub async fn create_alert_rule(pool: &$pool, rule: &AlertRule) -> Result<()> {
+    sqlx::query(&format!(
+        "INSERT INTO alert_rules ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)",
+        ALERT_RULE_COLUMNS
+    ))
+    .bind(&rule.id)
+    .bind(&rule.project_id)
+    .bind(&rule.name)
+    .bind(&rule.description)
+    .bind(&rule.condition)
+    .bind(rule.severity)
+    .bind(&rule.source)
+    .bind(rule.enabled)
+    .bind(rule.cooldown_secs)
+    .bind(rule.silenced_until)
+    .bind(&rule.silence_reason)
+    .bind(rule.last_fired_at)
+    .bind(&rule.created_by)
+    .bind(rule.created_at)
+    .bind(rule.updated_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Retrieve an alert rule by ID
+pub async fn get_alert_rule(pool: &$pool, rule_id: &str) -> Result<Option<AlertRule>> {
+    let rule = sqlx::query_as::<_, AlertRule>(&format!(
+        "SELECT {} FROM alert_rules WHERE id = $1",
+        ALERT_RULE_COLUMNS
+    ))
+    .bind(rule_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(rule)
+}
+
+/// List a project's alert rules, oldest first
+pub async fn list_alert_rules(pool: &$pool, project_id: &str) -> Result<Vec<AlertRule>> {
+    let rules = sqlx::query_as::<_, AlertRule>(&format!(
+        "SELECT {} FROM alert_rules WHERE project_id = $1 ORDER BY created_at, id",
+        ALERT_RULE_COLUMNS
+    ))
+    .bind(project_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rules)
+}
+
+/// List every project's enabled alert rules, for the alert engine
+pub async fn list_enabled_alert_rules(pool: &$pool) -> Result<Vec<AlertRule>> {
+    let rules = sqlx::query_as::<_, AlertRule>(&format!(
+        "SELECT {} FROM alert_rules WHERE enabled = TRUE ORDER BY created_at, id",
+        ALERT_RULE_COLUMNS
+    ))
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rules)
+}
+
+/// Save changes to a project's alert rule, including its silence
+///
+/// Returns `false` when the project has no such rule.
+pub async fn update_alert_rule(pool: &$pool, rule: &AlertRule) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE alert_rules SET name = $1, description = $2, condition = $3, severity = $4, source = $5,
+                enabled = $6, cooldown_secs = $7, silenced_until = $8, silence_reason = $9, updated_at = $10
+         WHERE id = $11 AND project_id = $12"
+    )
+    .bind(&rule.name)
+    .bind(&rule.description)
+    .bind(&rule.condition)
+    .bind(rule.severity)
+    .bind(&rule.source)
+    .bind(rule.enabled)
+    .bind(rule.cooldown_secs)
+    .bind(rule.silenced_until)
+    .bind(&rule.silence_reason)
+    .bind(Utc::now())
+    .bind(&rule.id)
+    .bind(&rule.project_id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Delete a project's alert rule along with its alerts
+pub async fn delete_alert_rule(pool: &$pool, project_id: &str, rule_id: &str) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM alert_rules WHERE id = $1 AND project_id = $2")
+        .bind(rule_id)
+        .bind(project_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Note when a rule last fired a new alert, which starts its cooldown
+pub async fn record_alert_rule_fired(pool: &$pool, rule_id: &str, fired_at: DateTime<Utc>) -> Result<()> {
+    sqlx::query("UPDATE alert_rules SET last_fired_at = $1 WHERE id = $2")
+        .bind(fired_at)
+        .bind(rule_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Store a newly fired alert
+pub async fn create_alert(pool: &$pool, alert: &Alert) -> Result<()> {
+    sqlx::query(&format!(
+        "INSERT INTO alerts ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)",
+        ALERT_COLUMNS
+    ))
+    .bind(&alert.id)
+    .bind(&alert.rule_id)
+    .bind(&alert.project_id)
+    .bind(&alert.rule_name)
+    .bind(alert.severity)
+    .bind(alert.status)
+    .bind(&alert.fingerprint)
+    .bind(&alert.source)
+    .bind(&alert.summary)
+    .bind(&alert.details)
+    .bind(alert.occurrences)
+    .bind(alert.first_seen_at)
+    .bind(alert.last_seen_at)
+    .bind(&alert.acknowledged_by)
+    .bind(alert.acknowledged_at)
+    .bind(alert.resolved_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Retrieve an alert by ID
+pub async fn get_alert(pool: &$pool, alert_id: &str) -> Result<Option<Alert>> {
+    let alert = sqlx::query_as::<_, Alert>(&format!(
+        "SELECT {} FROM alerts WHERE id = $1",
+        ALERT_COLUMNS
+    ))
+    .bind(alert_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(alert)
+}
+
+/// The most recent alert a rule fired with a fingerprint, open or resolved
+pub async fn find_latest_alert(pool: &$pool, rule_id: &str, fingerprint: &str) -> Result<Option<Alert>> {
+    let alert = sqlx::query_as::<_, Alert>(&format!(
+        "SELECT {} FROM alerts WHERE rule_id = $1 AND fingerprint = $2
+         ORDER BY first_seen_at DESC, id LIMIT 1",
+        ALERT_COLUMNS
+    ))
+    .bind(rule_id)
+    .bind(fingerprint)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(alert)
+}
+
+/// Count repeat matches on an open alert
+///
+/// Returns `false` when the alert has been resolved in the meantime.
+pub async fn record_alert_occurrences(
+    pool: &$pool,
+    alert_id: &str,
+    occurrences: i64,
+    seen_at: DateTime<Utc>,
+) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE alerts SET occurrences = occurrences + $1, last_seen_at = $2 WHERE id = $3 AND status <> $4"
+    )
+    .bind(occurrences)
+    .bind(seen_at)
+    .bind(alert_id)
+    .bind(AlertStatus::Resolved)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// List alerts matching a filter, newest first
+pub async fn query_alerts(pool: &$pool, filter: &AlertQuery) -> Result<Vec<Alert>> {
+    let mut query_builder = sqlx::QueryBuilder::new(format!(
+        "SELECT {} FROM alerts WHERE 1=1",
+        ALERT_COLUMNS
+    ));
+
+    if let Some(project_id) = &filter.project_id {
+        query_builder.push(" AND project_id = ");
+        query_builder.push_bind(project_id);
+    }
+
+    if let Some(rule_id) = &filter.rule_id {
+        query_builder.push(" AND rule_id = ");
+        query_builder.push_bind(rule_id);
+    }
+
+    if let Some(status) = filter.status {
+        query_builder.push(" AND status = ");
+        query_builder.push_bind(status);
+    }
+
+    if let Some(severity) = filter.severity {
+        query_builder.push(" AND severity = ");
+        query_builder.push_bind(severity);
+    }
+
+    if let Some(since) = filter.since {
+        query_builder.push(" AND first_seen_at >= ");
+        query_builder.push_bind(since);
+    }
+
+    if let Some(until) = filter.until {
+        query_builder.push(" AND first_seen_at < ");
+        query_builder.push_bind(until);
+    }
+
+    query_builder.push(" ORDER BY first_seen_at DESC, id");
+
+    if let Some(limit) = filter.limit {
+        query_builder.push(" LIMIT ");
+        query_builder.push_bind(limit);
+        query_builder.push(" OFFSET ");
+        query_builder.push_bind(filter.offset.unwrap_or(0));
+    }
+
+    let alerts = query_builder
+        .build_query_as::<Alert>()
+        .fetch_all(pool)
+        .await?;
+
+    Ok(alerts)
+}
+
+/// Acknowledge a project's firing alert
+///
+/// Returns `false` when the project has no such alert or it is not firing.
+pub async fn acknowledge_alert(
+    pool: &$pool,
+    project_id: &str,
+    alert_id: &str,
+    user_id: Option<&str>,
+) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE alerts SET status = $1, acknowledged_by = $2, acknowledged_at = $3
+         WHERE id = $4 AND project_id = $5 AND status = $6"
+    )
+    .bind(AlertStatus::Acknowledged)
+    .bind(user_id)
+    .bind(Utc::now())
+    .bind(alert_id)
+    .bind(project_id)
+    .bind(AlertStatus::Firing)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Resolve a project's open alert; the next match fires a new one
+///
+/// Returns `false` when the project has no such alert or it is already resolved.
+pub async fn resolve_alert(pool: &$pool, project_id: &str, alert_id: &str) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE alerts SET status = $1, resolved_at = $2 WHERE id = $3 AND project_id = $4 AND status <> $5"
+    )
+    .bind(AlertStatus::Resolved)
+    .bind(Utc::now())
+    .bind(alert_id)
+    .bind(project_id)
+    .bind(AlertStatus::Resolved)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Store a new notification channel
+pub async fn create_notification_channel(pool: &$pool, channel: &NotificationChannel) -> Result<()> {
+    sqlx::query(&format!(
+        "INSERT INTO notification_channels ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)",
+        CHANNEL_COLUMNS
+    ))
+    .bind(&channel.id)
+    .bind(&channel.project_id)
+    .bind(&channel.name)
+    .bind(channel.channel_type)
+    .bind(&channel.config)
+    .bind(channel.notify_alerts)
+    .bind(channel.notify_analyses)
+    .bind(channel.min_severity)
+    .bind(&channel.title_template)
+    .bind(&channel.body_template)
+    .bind(channel.enabled)
+    .bind(&channel.created_by)
+    .bind(channel.created_at)
+    .bind(channel.updated_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Retrieve a notification channel by ID
+pub async fn get_notification_channel(pool: &$pool, channel_id: &str) -> Result<Option<NotificationChannel>> {
+    let channel = sqlx::query_as::<_, NotificationChannel>(&format!(
+        "SELECT {} FROM notification_channels WHERE id = $1",
+        CHANNEL_COLUMNS
+    ))
+    .bind(channel_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(channel)
+}
+
+/// List a project's notification channels, oldest first
+pub async fn list_notification_channels(pool: &$pool, project_id: &str) -> Result<Vec<NotificationChannel>> {
+    let channels = sqlx::query_as::<_, NotificationChannel>(&format!(
+        "SELECT {} FROM notification_channels WHERE project_id = $1 ORDER BY created_at, id",
+        CHANNEL_COLUMNS
+    ))
+    .bind(project_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(channels)
+}
+
+/// List every project's notification channels, for re-encrypting their secrets
+pub async fn list_all_notification_channels(pool: &$pool) -> Result<Vec<NotificationChannel>> {
+    let channels = sqlx::query_as::<_, NotificationChannel>(&format!(
+        "SELECT {} FROM notification_channels ORDER BY created_at, id",
+        CHANNEL_COLUMNS
+    ))
+    .fetch_all(pool)
+    .await?;
+
+    Ok(channels)
+}
+
+/// Save changes to a project's notification channel
+///
+/// Returns `false` when the project has no such channel.
+pub async fn update_notification_channel(pool: &$pool, channel: &NotificationChannel) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE notification_channels SET name = $1, channel_type = $2, config = $3, notify_alerts = $4,
+                notify_analyses = $5, min_severity = $6, title_template = $7, body_template = $8, enabled = $9,
+                updated_at = $10
+         WHERE id = $11 AND project_id = $12"
+    )
+    .bind(&channel.name)
+    .bind(channel.channel_type)
+    .bind(&channel.config)
+    .bind(channel.notify_alerts)
+    .bind(channel.notify_analyses)
+    .bind(channel.min_severity)
+    .bind(&channel.title_template)
+    .bind(&channel.body_template)
+    .bind(channel.enabled)
+    .bind(Utc::now())
+    .bind(&channel.id)
+    .bind(&channel.project_id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Delete a project's notification channel along with its delivery log
+pub async fn delete_notification_channel(pool: &$pool, project_id: &str, channel_id: &str) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM notification_channels WHERE id = $1 AND project_id = $2")
+        .bind(channel_id)
+        .bind(project_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Queue a rendered message for a channel
+pub async fn create_notification_delivery(pool: &$pool, delivery: &NotificationDelivery) -> Result<()> {
+    sqlx::query(&format!(
+        "INSERT INTO notification_deliveries ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)",
+        DELIVERY_COLUMNS
+    ))
+    .bind(&delivery.id)
+    .bind(&delivery.channel_id)
+    .bind(&delivery.project_id)
+    .bind(&delivery.event)
+    .bind(&delivery.event_id)
+    .bind(delivery.status)
+    .bind(delivery.attempts)
+    .bind(delivery.max_attempts)
+    .bind(&delivery.payload)
+    .bind(&delivery.last_error)
+    .bind(delivery.response_status)
+    .bind(delivery.next_attempt_at)
+    .bind(delivery.created_at)
+    .bind(delivery.updated_at)
+    .bind(delivery.delivered_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Retrieve a delivery by ID
+pub async fn get_notification_delivery(pool: &$pool, delivery_id: &str) -> Result<Option<NotificationDelivery>> {
+    let delivery = sqlx::query_as::<_, NotificationDelivery>(&format!(
+        "SELECT {} FROM notification_deliveries WHERE id = $1",
+        DELIVERY_COLUMNS
+    ))
+    .bind(delivery_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(delivery)
+}
+
+/// Pending deliveries whose next attempt is due, longest waiting first
+pub async fn list_due_notification_deliveries(
+    pool: &$pool,
+    now: DateTime<Utc>,
+    limit: i64,
+) -> Result<Vec<NotificationDelivery>> {
+    let deliveries = sqlx::query_as::<_, NotificationDelivery>(&format!(
+        "SELECT {} FROM notification_deliveries WHERE status = $1 AND next_attempt_at <= $2
+         ORDER BY next_attempt_at, id LIMIT $3",
+        DELIVERY_COLUMNS
+    ))
+    .bind(DeliveryStatus::Pending)
+    .bind(now)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(deliveries)
+}
+
+/// Take a due delivery for an attempt, pushing its next attempt to `lease_until` so no
+/// other worker picks it up meanwhile
+///
+/// Returns `false` when the delivery is no longer due.
+pub async fn claim_notification_delivery(
+    pool: &$pool,
+    delivery_id: &str,
+    now: DateTime<Utc>,
+    lease_until: DateTime<Utc>,
+) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE notification_deliveries SET next_attempt_at = $1, updated_at = $2
+         WHERE id = $3 AND status = $4 AND next_attempt_at <= $5"
+    )
+    .bind(lease_until)
+    .bind(now)
+    .bind(delivery_id)
+    .bind(DeliveryStatus::Pending)
+    .bind(now)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Record the outcome of an attempt: status, attempts, error and when to retry
+pub async fn update_notification_delivery(pool: &$pool, delivery: &NotificationDelivery) -> Result<()> {
+    sqlx::query(
+        "UPDATE notification_deliveries SET status = $1, attempts = $2, last_error = $3, response_status = $4,
+                next_attempt_at = $5, updated_at = $6, delivered_at = $7
+         WHERE id = $8"
+    )
+    .bind(delivery.status)
+    .bind(delivery.attempts)
+    .bind(&delivery.last_error)
+    .bind(delivery.response_status)
+    .bind(delivery.next_attempt_at)
+    .bind(Utc::now())
+    .bind(delivery.delivered_at)
+    .bind(&delivery.id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// List deliveries matching a filter, newest first
+pub async fn query_notification_deliveries(pool: &$pool, filter: &DeliveryQuery) -> Result<Vec<NotificationDelivery>> {
+    let mut query_builder = sqlx::QueryBuilder::new(format!(
+        "SELECT {} FROM notification_deliveries WHERE 1=1",
+        DELIVERY_COLUMNS
+    ));
+
+    if let Some(project_id) = &filter.project_id {
+        query_builder.push(" AND project_id = ");
+        query_builder.push_bind(project_id);
+    }
+
+    if let Some(channel_id) = &filter.channel_id {
+        query_builder.push(" AND channel_id = ");
+        query_builder.push_bind(channel_id);
+    }
+
+    if let Some(status) = filter.status {
+        query_builder.push(" AND status = ");
+        query_builder.push_bind(status);
+    }
+
+    if let Some(event) = &filter.event {
+        query_builder.push(" AND event = ");
+        query_builder.push_bind(event);
+    }
+
+    query_builder.push(" ORDER BY created_at DESC, id");
+
+    if let Some(limit) = filter.limit {
+        query_builder.push(" LIMIT ");
+        query_builder.push_bind(limit);
+        query_builder.push(" OFFSET ");
+        query_builder.push_bind(filter.offset.unwrap_or(0));
+    }
+
+    let deliveries = query_builder
+        .build_query_as::<NotificationDelivery>()
+        .fetch_all(pool)
+        .await?;
+
+    Ok(deliveries)
+}
+
+/// Store a new analysis schedule
+pub async fn create_analysis_schedule(pool: &$pool, schedule: &AnalysisSchedule) -> Result<()> {
+    sqlx::query(&format!(
+        "INSERT INTO analysis_schedules ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)",
+        SCHEDULE_COLUMNS
+    ))
+    .bind(&schedule.id)
+    .bind(&schedule.project_id)
+    .bind(&schedule.name)
+    .bind(&schedule.source)
+    .bind(&schedule.cron)
+    .bind(&schedule.trigger_rule_id)
+    .bind(schedule.window_secs)
+    .bind(&schedule.provider)
+    .bind(&schedule.level_filter)
+    .bind(schedule.enabled)
+    .bind(schedule.last_run_at)
+    .bind(&schedule.last_analysis_id)
+    .bind(&schedule.created_by)
+    .bind(schedule.created_at)
+    .bind(schedule.updated_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Retrieve an analysis schedule by ID
+pub async fn get_analysis_schedule(pool: &$pool, schedule_id: &str) -> Result<Option<AnalysisSchedule>> {
+    let schedule = sqlx::query_as::<_, AnalysisSchedule>(&format!(
+        "SELECT {} FROM analysis_schedules WHERE id = $1",
+        SCHEDULE_COLUMNS
+    ))
+    .bind(schedule_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(schedule)
+}
+
+/// List a project's analysis schedules, oldest first
+pub async fn list_analysis_schedules(pool: &$pool, project_id: &str) -> Result<Vec<AnalysisSchedule>> {
+    let schedules = sqlx::query_as::<_, AnalysisSchedule>(&format!(
+        "SELECT {} FROM analysis_schedules WHERE project_id = $1 ORDER BY created_at, id",
+        SCHEDULE_COLUMNS
+    ))
+    .bind(project_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(schedules)
+}
+
+/// List every project's enabled analysis schedules, for the scheduler
+pub async fn list_enabled_analysis_schedules(pool: &$pool) -> Result<Vec<AnalysisSchedule>> {
+    let schedules = sqlx::query_as::<_, AnalysisSchedule>(&format!(
+        "SELECT {} FROM analysis_schedules WHERE enabled = TRUE ORDER BY created_at, id",
+        SCHEDULE_COLUMNS
+    ))
+    .fetch_all(pool)
+    .await?;
+
+    Ok(schedules)
+}
+
+/// Save changes to a project's analysis schedule
+///
+/// Returns `false` when the project has no such schedule.
+pub async fn update_analysis_schedule(pool: &$pool, schedule: &AnalysisSchedule) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE analysis_schedules SET name = $1, source = $2, cron = $3, trigger_rule_id = $4, window_secs = $5,
+                provider = $6, level_filter = $7, enabled = $8, updated_at = $9
+         WHERE id = $10 AND project_id = $11"
+    )
+    .bind(&schedule.name)
+    .bind(&schedule.source)
+    .bind(&schedule.cron)
+    .bind(&schedule.trigger_rule_id)
+    .bind(schedule.window_secs)
+    .bind(&schedule.provider)
+    .bind(&schedule.level_filter)
+    .bind(schedule.enabled)
+    .bind(Utc::now())
+    .bind(&schedule.id)
+    .bind(&schedule.project_id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Delete a project's analysis schedule; the analyses it started are kept
+pub async fn delete_analysis_schedule(pool: &$pool, project_id: &str, schedule_id: &str) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM analysis_schedules WHERE id = $1 AND project_id = $2")
+        .bind(schedule_id)
+        .bind(project_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Note the analysis a schedule last started
+pub async fn record_analysis_schedule_run(
+    pool: &$pool,
+    schedule_id: &str,
+    analysis_id: &str,
+    ran_at: DateTime<Utc>,
+) -> Result<()> {
+    sqlx::query("UPDATE analysis_schedules SET last_run_at = $1, last_analysis_id = $2 WHERE id = $3")
+        .bind(ran_at)
+        .bind(analysis_id)
+        .bind(schedule_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Create a user account
+pub async fn create_user(pool: &$pool, user: &User) -> Result<()> {
+    sqlx::query(&format!(
+        "INSERT INTO users ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)",
+        USER_COLUMNS
+    ))
+    .bind(&user.id)
+    .bind(&user.username)
+    .bind(&user.email)
+    .bind(&user.display_name)
+    .bind(&user.password_hash)
+    .bind(user.is_admin)
+    .bind(user.is_active)
+    .bind(user.last_login_at)
+    .bind(user.created_at)
+    .bind(user.updated_at)
+    .bind(&user.oidc_issuer)
+    .bind(&user.oidc_subject)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Retrieve a user by id
+pub async fn get_user(pool: &$pool, user_id: &str) -> Result<Option<User>> {
+    let user = sqlx::query_as::<_, User>(&format!(
+        "SELECT {} FROM users WHERE id = $1",
+        USER_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(user)
+}
+
+/// Retrieve a user by username
+pub async fn get_user_by_username(pool: &$pool, username: &str) -> Result<Option<User>> {
+    let user = sqlx::query_as::<_, User>(&format!(
+        "SELECT {} FROM users WHERE username = $1",
+        USER_COLUMNS
+    ))
+    .bind(username)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(user)
+}
+
+/// Retrieve the account provisioned for an identity provider subject
+pub async fn get_user_by_oidc_identity(
+    pool: &$pool,
+    issuer: &str,
+    subject: &str,
+) -> Result<Option<User>> {
+    let user = sqlx::query_as::<_, User>(&format!(
+        "SELECT {} FROM users WHERE oidc_issuer = $1 AND oidc_subject = $2",
+        USER_COLUMNS
+    ))
+    .bind(issuer)
+    .bind(subject)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(user)
+}
+
+/// Update the profile and administrator flag of an account
+pub async fn update_user_profile(
+    pool: &$pool,
+    user_id: &str,
+    email: Option<&str>,
+    display_name: Option<&str>,
+    is_admin: bool,
+) -> Result<()> {
+    sqlx::query(
+        "UPDATE users SET email = $1, display_name = $2, is_admin = $3, updated_at = CURRENT_TIMESTAMP
+         WHERE id = $4"
+    )
+    .bind(email)
+    .bind(display_name)
+    .bind(is_admin)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// List all user accounts by username
+pub async fn list_users(pool: &$pool) -> Result<Vec<User>> {
+    let users = sqlx::query_as::<_, User>(&format!(
+        "SELECT {} FROM users ORDER BY username",
+        USER_COLUMNS
+    ))
+    .fetch_all(pool)
+    .await?;
+
+    Ok(users)
+}
+
+/// Count user accounts
+pub async fn count_users(pool: &$pool) -> Result<i64> {
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users")
+        .fetch_one(pool)
+        .await?;
+
+    Ok(count)
+}
+
+/// Record a successful login
+pub async fn record_user_login(pool: &$pool, user_id: &str) -> Result<()> {
+    sqlx::query("UPDATE users SET last_login_at = CURRENT_TIMESTAMP WHERE id = $1")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Enable or disable a user account; disabling also ends the user's sessions
+///
+/// Returns `false` when no such user exists.
+pub async fn set_user_active(pool: &$pool, user_id: &str, is_active: bool) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE users SET is_active = $1, updated_at = CURRENT_TIMESTAMP WHERE id = $2"
+    )
+    .bind(is_active)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    if !is_active {
+        sqlx::query("DELETE FROM sessions WHERE user_id = $1")
+            .bind(user_id)
+            .execute(pool)
+            .await?;
+    }
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Replace a user's password hash
+pub async fn update_user_password(pool: &$pool, user_id: &str, password_hash: &str) -> Result<()> {
+    sqlx::query(
+        "UPDATE users SET password_hash = $1, updated_at = CURRENT_TIMESTAMP WHERE id = $2"
+    )
+    .bind(password_hash)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Store a new login session
+pub async fn create_session(pool: &$pool, session: &Session) -> Result<()> {
+    sqlx::query(&format!(
+        "INSERT INTO sessions ({}) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        SESSION_COLUMNS
+    ))
+    .bind(&session.id)
+    .bind(&session.user_id)
+    .bind(&session.user_agent)
+    .bind(&session.ip_address)
+    .bind(session.created_at)
+    .bind(session.expires_at)
+    .bind(session.last_seen_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Retrieve an unexpired session by the digest of its cookie
+pub async fn get_session(pool: &$pool, session_id: &str) -> Result<Option<Session>> {
+    let session = sqlx::query_as::<_, Session>(&format!(
+        "SELECT {} FROM sessions WHERE id = $1 AND expires_at > $2",
+        SESSION_COLUMNS
+    ))
+    .bind(session_id)
+    .bind(Utc::now())
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(session)
+}
+
+/// Record activity on a session
+pub async fn touch_session(pool: &$pool, session_id: &str) -> Result<()> {
+    sqlx::query("UPDATE sessions SET last_seen_at = CURRENT_TIMESTAMP WHERE id = $1")
+        .bind(session_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// End a session
+pub async fn delete_session(pool: &$pool, session_id: &str) -> Result<()> {
+    sqlx::query("DELETE FROM sessions WHERE id = $1")
+        .bind(session_id)
+        .execute(pool)
+        .await?;
 
-/// Create new analysis record
-pub async fn create_analysis(
-    pool: &SqlitePool,
-    project_id: String,
-    log_file_path: String,
-    provider: String,
-    level: String,
-) -> Result<String> {
-    let analysis_id = Uuid::new_v4().to_string();
-    let created_at = Utc::now();
+    Ok(())
+}
 
-    sqlx::query(
-        r#"
-        INSERT INTO analyses (id, project_id, log_file_path, provider, level, status, created_at)
-        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
-        "#
-    )
-    .bind(&analysis_id)
-    .bind(&project_id)
-    .bind(&log_file_path)
-    .bind(&provider)
-    .bind(&level)
-    .bind(AnalysisStatus::Pending.to_string())
-    .bind(&created_at)
+/// Remove expired sessions, returning how many were deleted
+pub async fn delete_expired_sessions(pool: &$pool) -> Result<u64> {
+    let result = sqlx::query("DELETE FROM sessions WHERE expires_at <= $1")
+        .bind(Utc::now())
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Store a new personal API token
+pub async fn create_api_token(pool: &$pool, token: &ApiToken) -> Result<()> {
+    sqlx::query(&format!(
+        "INSERT INTO api_tokens ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        API_TOKEN_COLUMNS
+    ))
+    .bind(&token.id)
+    .bind(&token.user_id)
+    .bind(&token.name)
+    .bind(&token.token_hash)
+    .bind(&token.token_prefix)
+    .bind(token.created_at)
+    .bind(token.last_used_at)
+    .bind(token.expires_at)
     .execute(pool)
     .await?;
 
-    Ok(analysis_id)
+    Ok(())
 }
 
-/// Retrieve analysis with optional results
-pub async fn get_analysis_by_id(
-    pool: &SqlitePool,
-    analysis_id: &str,
-) -> Result<Option<(Analysis, Option<AnalysisResult>)>> {
-    // Query analysis record
-    let analysis = sqlx::query_as::<_, Analysis>(
-        "SELECT id, project_id, log_file_path, provider, level, status,
-                created_at, started_at, completed_at, metadata
-         FROM analyses WHERE id = ?1"
-    )
-    .bind(analysis_id)
+/// List a user's API tokens, newest first
+pub async fn list_api_tokens(pool: &$pool, user_id: &str) -> Result<Vec<ApiToken>> {
+    let tokens = sqlx::query_as::<_, ApiToken>(&format!(
+        "SELECT {} FROM api_tokens WHERE user_id = $1 ORDER BY created_at DESC",
+        API_TOKEN_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(tokens)
+}
+
+/// Retrieve an unexpired API token by the digest of its secret
+pub async fn find_api_token(pool: &$pool, token_hash: &str) -> Result<Option<ApiToken>> {
+    let token = sqlx::query_as::<_, ApiToken>(&format!(
+        "SELECT {} FROM api_tokens
+         WHERE token_hash = $1 AND (expires_at IS NULL OR expires_at > $2)",
+        API_TOKEN_COLUMNS
+    ))
+    .bind(token_hash)
+    .bind(Utc::now())
     .fetch_optional(pool)
     .await?;
 
-    if let Some(analysis) = analysis {
-        // Query analysis result if it exists
-        let result = sqlx::query_as::<_, AnalysisResult>(
-            "SELECT analysis_id, summary, full_report, patterns_detected, issues_found, metadata
-             FROM analysis_results WHERE analysis_id = ?1"
-        )
-        .bind(analysis_id)
-        .fetch_optional(pool)
+    Ok(token)
+}
+
+/// Record that an API token was used
+pub async fn touch_api_token(pool: &$pool, token_id: &str) -> Result<()> {
+    sqlx::query("UPDATE api_tokens SET last_used_at = CURRENT_TIMESTAMP WHERE id = $1")
+        .bind(token_id)
+        .execute(pool)
         .await?;
 
-        Ok(Some((analysis, result)))
-    } else {
-        Ok(None)
-    }
+    Ok(())
 }
 
-/// Query analyses with filters
-pub async fn query_analyses(
-    pool: &SqlitePool,
-    project_id: Option<&str>,
-    status: Option<AnalysisStatus>,
-    limit: Option<i64>,
-    since: Option<DateTime<Utc>>,
-) -> Result<Vec<Analysis>> {
-    let mut query_builder = sqlx::QueryBuilder::new(
-        "SELECT id, project_id, log_file_path, provider, level, status, \
-         created_at, started_at, completed_at, metadata \
-         FROM analyses \
-         WHERE 1=1"
-    );
+/// Revoke one of a user's API tokens
+///
+/// Returns `false` when the user has no such token.
+pub async fn delete_api_token(pool: &$pool, user_id: &str, token_id: &str) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM api_tokens WHERE id = $1 AND user_id = $2")
+        .bind(token_id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
 
-    if let Some(project_id) = project_id {
-        query_builder.push(" AND project_id = ");
-        query_builder.push_bind(project_id);
-    }
+    Ok(result.rows_affected() > 0)
+}
 
-    if let Some(status) = status {
-        query_builder.push(" AND status = ");
-        query_builder.push_bind(status.to_string());
-    }
+/// List the projects a user is a member of, most recently updated first
+pub async fn list_projects_for_user(pool: &$pool, user_id: &str) -> Result<Vec<Project>> {
+    let projects = sqlx::query_as::<_, Project>(&format!(
+        "SELECT {} FROM projects
+         WHERE id IN (SELECT project_id FROM project_members WHERE user_id = $1)
+         ORDER BY updated_at DESC",
+        PROJECT_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
 
-    if let Some(since) = since {
-        query_builder.push(" AND started_at >= ");
-        query_builder.push_bind(since);
-    }
+    Ok(projects)
+}
 
-    query_builder.push(" ORDER BY started_at DESC");
+/// A user's role in a project, or `None` when they are not a member
+pub async fn get_project_role(
+    pool: &$pool,
+    project_id: &str,
+    user_id: &str,
+) -> Result<Option<ProjectRole>> {
+    let role = sqlx::query_scalar::<_, ProjectRole>(
+        "SELECT role FROM project_members WHERE project_id = $1 AND user_id = $2"
+    )
+    .bind(project_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
 
-    if let Some(limit) = limit {
-        query_builder.push(" LIMIT ");
-        query_builder.push_bind(limit);
-    }
+    Ok(role)
+}
 
-    let analyses = query_builder
-        .build_query_as::<Analysis>()
-        .fetch_all(pool)
-        .await?;
+/// List a project's members with their usernames, highest role first
+pub async fn list_project_members(pool: &$pool, project_id: &str) -> Result<Vec<ProjectMember>> {
+    let members = sqlx::query_as::<_, ProjectMember>(
+        "SELECT m.project_id, m.user_id, u.username, m.role, m.added_by, m.created_at, m.updated_at
+         FROM project_members m
+         JOIN users u ON u.id = m.user_id
+         WHERE m.project_id = $1
+         ORDER BY CASE m.role WHEN 'admin' THEN 0 WHEN 'analyst' THEN 1 ELSE 2 END, u.username"
+    )
+    .bind(project_id)
+    .fetch_all(pool)
+    .await?;
 
-    Ok(analyses)
+    Ok(members)
 }
 
-/// Store analysis results
-pub async fn store_analysis_results(
-    pool: &SqlitePool,
-    analysis_id: &str,
-    summary: Option<String>,
-    full_report: Option<String>,
-    patterns: Vec<Pattern>,
-    issues_found: Option<i64>,
-) -> Result<()> {
-    let patterns_json = serde_json::to_value(patterns)?.to_string();
+/// Add a member to a project or change their role, recording the change
+///
+/// Returns the member's previous role, if they already belonged to the project.
+pub async fn set_project_member(
+    pool: &$pool,
+    project_id: &str,
+    user_id: &str,
+    role: ProjectRole,
+    changed_by: Option<&str>,
+) -> Result<Option<ProjectRole>> {
+    let mut tx = pool.begin().await?;
+
+    let old_role = sqlx::query_scalar::<_, ProjectRole>(
+        "SELECT role FROM project_members WHERE project_id = $1 AND user_id = $2"
+    )
+    .bind(project_id)
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if old_role == Some(role) {
+        return Ok(old_role);
+    }
 
-    // Insert or replace results in analysis_results table
     sqlx::query(
-        r#"
-        INSERT OR REPLACE INTO analysis_results
-        (analysis_id, summary, full_report, patterns_detected, issues_found)
-        VALUES (?1, ?2, ?3, ?4, ?5)
-        "#
+        "INSERT INTO project_members (project_id, user_id, role, added_by, created_at, updated_at)
+         VALUES ($1, $2, $3, $4, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)
+         ON CONFLICT (project_id, user_id)
+         DO UPDATE SET role = excluded.role, updated_at = CURRENT_TIMESTAMP"
     )
-    .bind(analysis_id)
-    .bind(summary)
-    .bind(full_report)
-    .bind(patterns_json)
-    .bind(issues_found)
-    .execute(pool)
+    .bind(project_id)
+    .bind(user_id)
+    .bind(role)
+    .bind(changed_by)
+    .execute(&mut *tx)
     .await?;
 
-    Ok(())
+    sqlx::query(
+        "INSERT INTO project_member_changes (id, project_id, user_id, changed_by, old_role, new_role, created_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7)"
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(project_id)
+    .bind(user_id)
+    .bind(changed_by)
+    .bind(old_role)
+    .bind(Some(role))
+    .bind(Utc::now())
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(old_role)
 }
 
-/// Update analysis status
-pub async fn update_analysis_status(
-    pool: &SqlitePool,
-    analysis_id: &str,
-    status: AnalysisStatus,
-    completed_at: Option<DateTime<Utc>>,
-) -> Result<()> {
-    sqlx::query(
-        "UPDATE analyses SET status = ?1, completed_at = ?2 WHERE id = ?3"
+/// Remove a member from a project, recording the change
+///
+/// Returns the role the member had, or `None` when they were not a member.
+pub async fn remove_project_member(
+    pool: &$pool,
+    project_id: &str,
+    user_id: &str,
+    changed_by: Option<&str>,
+) -> Result<Option<ProjectRole>> {
+    let mut tx = pool.begin().await?;
+
+    let old_role = sqlx::query_scalar::<_, ProjectRole>(
+        "DELETE FROM project_members WHERE project_id = $1 AND user_id = $2 RETURNING role"
     )
-    .bind(status.to_string())
-    .bind(completed_at)
-    .bind(analysis_id)
+    .bind(project_id)
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if old_role.is_some() {
+        sqlx::query(
+            "INSERT INTO project_member_changes (id, project_id, user_id, changed_by, old_role, new_role, created_at)
+             VALUES ($1, $2, $3, $4, $5, NULL, $6)"
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(project_id)
+        .bind(user_id)
+        .bind(changed_by)
+        .bind(old_role)
+        .bind(Utc::now())
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(old_role)
+}
+
+/// List changes to a project's membership, newest first
+pub async fn list_project_member_changes(
+    pool: &$pool,
+    project_id: &str,
+    limit: i64,
+) -> Result<Vec<ProjectMemberChange>> {
+    let changes = sqlx::query_as::<_, ProjectMemberChange>(
+        "SELECT id, project_id, user_id, changed_by, old_role, new_role, created_at
+         FROM project_member_changes
+         WHERE project_id = $1
+         ORDER BY created_at DESC
+         LIMIT $2"
+    )
+    .bind(project_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(changes)
+}
+
+/// Store a streaming source's configuration
+pub async fn create_streaming_source(pool: &$pool, source: &StreamingSource) -> Result<()> {
+    sqlx::query(&format!(
+        "INSERT INTO streaming_sources ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)",
+        STREAMING_SOURCE_COLUMNS
+    ))
+    .bind(&source.id)
+    .bind(&source.project_id)
+    .bind(&source.name)
+    .bind(&source.source_type)
+    .bind(&source.config)
+    .bind(&source.parser_config)
+    .bind(source.buffer_size)
+    .bind(source.batch_timeout_seconds)
+    .bind(source.restart_on_error)
+    .bind(source.max_restarts)
+    .bind(&source.ingest_token_hash)
+    .bind(&source.status)
+    .bind(&source.created_at)
+    .bind(&source.updated_at)
     .execute(pool)
     .await?;
 
     Ok(())
 }
 
-/// Resolve project by path
-pub async fn get_project_by_path(
-    pool: &SqlitePool,
-    root_path: &str,
-) -> Result<Option<Project>> {
-    let project = sqlx::query_as::<_, Project>(
-        "SELECT id, name, root_path, description, created_at, updated_at, metadata
-        FROM projects
-        WHERE root_path = ?1"
-    )
-    .bind(root_path)
+/// Retrieve a project's streaming source
+pub async fn get_streaming_source(
+    pool: &$pool,
+    project_id: &str,
+    source_id: &str,
+) -> Result<Option<StreamingSource>> {
+    let source = sqlx::query_as::<_, StreamingSource>(&format!(
+        "SELECT {} FROM streaming_sources WHERE id = $1 AND project_id = $2",
+        STREAMING_SOURCE_COLUMNS
+    ))
+    .bind(source_id)
+    .bind(project_id)
     .fetch_optional(pool)
     .await?;
 
-    Ok(project)
+    Ok(source)
 }
 
-/// Create or get project by path
-pub async fn get_or_create_project(
-    pool: &SqlitePool,
-    root_path: &str,
-) -> Result<String> {
-    // Try to get existing project
-    if let Some(project) = get_project_by_path(pool, root_path).await? {
-        return Ok(project.id);
+/// List a project's active streaming sources, oldest first
+pub async fn list_streaming_sources(pool: &$pool, project_id: &str) -> Result<Vec<StreamingSource>> {
+    let sources = sqlx::query_as::<_, StreamingSource>(&format!(
+        "SELECT {} FROM streaming_sources WHERE project_id = $1 AND status = 'active' ORDER BY created_at, id",
+        STREAMING_SOURCE_COLUMNS
+    ))
+    .bind(project_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(sources)
+}
+
+/// List every project's active streaming sources, to start them on startup
+pub async fn list_active_streaming_sources(pool: &$pool) -> Result<Vec<StreamingSource>> {
+    let sources = sqlx::query_as::<_, StreamingSource>(&format!(
+        "SELECT {} FROM streaming_sources WHERE status = 'active' ORDER BY created_at, id",
+        STREAMING_SOURCE_COLUMNS
+    ))
+    .fetch_all(pool)
+    .await?;
+
+    Ok(sources)
+}
+
+/// Delete a project's streaming source
+///
+/// Returns `false` when the project has no such source.
+pub async fn delete_streaming_source(pool: &$pool, project_id: &str, source_id: &str) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM streaming_sources WHERE id = $1 AND project_id = $2")
+        .bind(source_id)
+        .bind(project_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Store a batch of streaming entries, adding them to their partitions' totals and the
+/// per-minute counts
+pub async fn insert_streaming_logs(pool: &$pool, entries: &[StoredLogEntry]) -> Result<()> {
+    if entries.is_empty() {
+        return Ok(());
     }
 
-    // Create new project
-    let project_id = Uuid::new_v4().to_string();
-    let name = std::path::Path::new(root_path)
-        .file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("Unknown")
-        .to_string();
+    let mut partitions: BTreeMap<(&str, DateTime<Utc>), (i64, i64)> = BTreeMap::new();
+    let mut counts: BTreeMap<(&str, DateTime<Utc>, &str, &str), i64> = BTreeMap::new();
+    for entry in entries {
+        let partition = partitions.entry((&entry.project_id, entry.partition_start)).or_default();
+        partition.0 += 1;
+        partition.1 += entry.size_bytes;
+        *counts
+            .entry((&entry.project_id, entry.minute(), &entry.source, entry.normalized_level()))
+            .or_default() += 1;
+    }
 
-    let now = Utc::now();
-    query!(
-        r#"
-        INSERT INTO projects (id, name, root_path, created_at, updated_at)
-        VALUES (?1, ?2, ?3, ?4, ?5)
-        "#,
-        project_id,
-        name,
-        root_path,
-        now,
-        now
+    let mut tx = pool.begin().await?;
+
+    for chunk in entries.chunks(STREAMING_LOG_INSERT_CHUNK) {
+        let mut query_builder = sqlx::QueryBuilder::new(format!(
+            "INSERT INTO streaming_logs ({}) ",
+            STREAMING_LOG_INSERT_COLUMNS
+        ));
+        query_builder.push_values(chunk, |mut row, entry| {
+            row.push_bind(&entry.id)
+                .push_bind(&entry.project_id)
+                .push_bind(&entry.source)
+                .push_bind(entry.partition_start)
+                .push_bind(entry.timestamp)
+                .push_bind(&entry.level)
+                .push_bind(entry.severity)
+                .push_bind(&entry.message)
+                .push_bind(entry.line_number)
+                .push_bind(&entry.metadata)
+                .push_bind(entry.size_bytes);
+        });
+        query_builder.build().execute(&mut *tx).await?;
+    }
+
+    for ((project_id, partition_start), (count, size_bytes)) in partitions {
+        sqlx::query(
+            "INSERT INTO streaming_log_partitions (project_id, partition_start, entries, size_bytes)
+             VALUES ($1, $2, $3, $4)
+             ON CONFLICT (project_id, partition_start) DO UPDATE
+             SET entries = streaming_log_partitions.entries + excluded.entries,
+                 size_bytes = streaming_log_partitions.size_bytes + excluded.size_bytes"
+        )
+        .bind(project_id)
+        .bind(partition_start)
+        .bind(count)
+        .bind(size_bytes)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    for ((project_id, minute, source, level), count) in counts {
+        sqlx::query(
+            "INSERT INTO streaming_log_counts (project_id, minute, source, level, entries)
+             VALUES ($1, $2, $3, $4, $5)
+             ON CONFLICT (project_id, minute, source, level) DO UPDATE
+             SET entries = streaming_log_counts.entries + excluded.entries"
+        )
+        .bind(project_id)
+        .bind(minute)
+        .bind(source)
+        .bind(level)
+        .bind(count)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Entries per minute and level within `[since, until)`, oldest first
+pub async fn count_streaming_logs_by_minute(
+    pool: &$pool,
+    project_id: &str,
+    source: Option<&str>,
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+) -> Result<Vec<LogCount>> {
+    let mut query_builder = sqlx::QueryBuilder::new(
+        "SELECT minute, level, CAST(SUM(entries) AS BIGINT) AS entries
+         FROM streaming_log_counts WHERE project_id = ",
+    );
+    query_builder.push_bind(project_id);
+    query_builder.push(" AND minute >= ");
+    query_builder.push_bind(since);
+    query_builder.push(" AND minute < ");
+    query_builder.push_bind(until);
+    if let Some(source) = source {
+        query_builder.push(" AND source = ");
+        query_builder.push_bind(source);
+    }
+    query_builder.push(" GROUP BY minute, level ORDER BY minute, level");
+
+    let counts = query_builder
+        .build_query_as::<LogCount>()
+        .fetch_all(pool)
+        .await?;
+
+    Ok(counts)
+}
+
+/// Every project's partitions of stored streaming entries, oldest first per project
+pub async fn list_streaming_log_partitions(pool: &$pool) -> Result<Vec<LogPartition>> {
+    let partitions = sqlx::query_as::<_, LogPartition>(
+        "SELECT project_id, partition_start, entries, size_bytes
+         FROM streaming_log_partitions ORDER BY project_id, partition_start"
     )
-    .execute(pool)
+    .fetch_all(pool)
     .await?;
 
-    Ok(project_id)
+    Ok(partitions)
+}
+
+/// Delete one partition of a project's stored streaming entries
+///
+/// Returns the number of entries deleted.
+pub async fn drop_streaming_log_partition(
+    pool: &$pool,
+    project_id: &str,
+    partition_start: DateTime<Utc>,
+) -> Result<u64> {
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query("DELETE FROM streaming_logs WHERE project_id = $1 AND partition_start = $2")
+        .bind(project_id)
+        .bind(partition_start)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query("DELETE FROM streaming_log_partitions WHERE project_id = $1 AND partition_start = $2")
+        .bind(project_id)
+        .bind(partition_start)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(result.rows_affected())
+}
+
+/// Delete a project's per-minute counts from before `before`
+pub async fn delete_streaming_log_counts_before(
+    pool: &$pool,
+    project_id: &str,
+    before: DateTime<Utc>,
+) -> Result<u64> {
+    let result = sqlx::query("DELETE FROM streaming_log_counts WHERE project_id = $1 AND minute < $2")
+        .bind(project_id)
+        .bind(before)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+    };
+}
+
+repository!(SqlitePool);
+
+/// Search the knowledge base with the FTS5 index, best matches first
+///
+/// An empty query lists the visible entries by usage instead.
+pub async fn search_knowledge(
+    pool: &SqlitePool,
+    query: &str,
+    project_id: Option<&str>,
+    include_public: bool,
+    limit: i64,
+) -> Result<Vec<KnowledgeBaseEntry>> {
+    let terms = search_terms(query);
+    let columns = KNOWLEDGE_COLUMNS
+        .split(", ")
+        .map(|column| format!("kb.{}", column.trim()))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let mut query_builder = sqlx::QueryBuilder::new(format!("SELECT {} FROM knowledge_base kb", columns));
+
+    if terms.is_empty() {
+        query_builder.push(" WHERE 1=1");
+        push_knowledge_scope(&mut query_builder, "kb.", project_id, include_public);
+        query_builder.push(" ORDER BY kb.usage_count DESC, kb.created_at DESC");
+    } else {
+        // Every term must match, each as a prefix of an indexed word
+        let match_expression = terms
+            .iter()
+            .map(|term| format!("\"{}\"*", term))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        query_builder.push(
+            " JOIN knowledge_base_fts ON knowledge_base_fts.rowid = kb.rowid
+             WHERE knowledge_base_fts MATCH ",
+        );
+        query_builder.push_bind(match_expression);
+        push_knowledge_scope(&mut query_builder, "kb.", project_id, include_public);
+        query_builder.push(" ORDER BY bm25(knowledge_base_fts), kb.usage_count DESC");
+    }
+
+    query_builder.push(" LIMIT ");
+    query_builder.push_bind(limit);
+
+    let entries = query_builder
+        .build_query_as::<KnowledgeBaseEntry>()
+        .fetch_all(pool)
+        .await?;
+
+    Ok(entries)
+}
+
+/// Search a project's stored streaming entries, newest first, with the FTS5 index for text
+pub async fn search_streaming_logs(pool: &SqlitePool, query: &StreamingLogQuery) -> Result<Vec<StoredLogEntry>> {
+    let terms = query.text.as_deref().map(search_terms).unwrap_or_default();
+    let columns = STREAMING_LOG_COLUMNS
+        .split(", ")
+        .map(|column| format!("l.{}", column.trim()))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let mut query_builder = sqlx::QueryBuilder::new(format!("SELECT {} FROM streaming_logs l", columns));
+
+    if terms.is_empty() {
+        query_builder.push(" WHERE 1=1");
+    } else {
+        // Every term must match, each as a prefix of an indexed word
+        let match_expression = terms
+            .iter()
+            .map(|term| format!("\"{}\"*", term))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        query_builder.push(
+            " JOIN streaming_logs_fts ON streaming_logs_fts.rowid = l.seq
+             WHERE streaming_logs_fts MATCH ",
+        );
+        query_builder.push_bind(match_expression);
+    }
+    push_streaming_log_filters(&mut query_builder, "l.", query);
+
+    let entries = query_builder
+        .build_query_as::<StoredLogEntry>()
+        .fetch_all(pool)
+        .await?;
+
+    Ok(entries)
+}
+
+/// Create the first user account, unless one exists already
+///
+/// Returns whether the user was created. SQLite runs one write at a time, so checking for
+/// accounts in the insert itself keeps concurrent registrations from both succeeding.
+pub async fn create_first_user(pool: &SqlitePool, user: &User) -> Result<bool> {
+    let result = sqlx::query(&first_user_insert())
+        .bind(&user.id)
+        .bind(&user.username)
+        .bind(&user.email)
+        .bind(&user.display_name)
+        .bind(&user.password_hash)
+        .bind(user.is_admin)
+        .bind(user.is_active)
+        .bind(user.last_login_at)
+        .bind(user.created_at)
+        .bind(user.updated_at)
+        .bind(&user.oidc_issuer)
+        .bind(&user.oidc_subject)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// The same repository functions over a PostgreSQL pool
+#[cfg(feature = "postgres")]
+pub mod postgres {
+    use super::*;
+    use sqlx::PgPool;
+
+    repository!(PgPool);
+
+    /// Search the knowledge base with the generated tsvector column, best matches first
+    ///
+    /// An empty query lists the visible entries by usage instead.
+    pub async fn search_knowledge(
+        pool: &PgPool,
+        query: &str,
+        project_id: Option<&str>,
+        include_public: bool,
+        limit: i64,
+    ) -> Result<Vec<KnowledgeBaseEntry>> {
+        let terms = search_terms(query);
+        let mut query_builder = sqlx::QueryBuilder::new(format!(
+            "SELECT {} FROM knowledge_base WHERE 1=1",
+            KNOWLEDGE_COLUMNS
+        ));
+
+        if terms.is_empty() {
+            push_knowledge_scope(&mut query_builder, "", project_id, include_public);
+            query_builder.push(" ORDER BY usage_count DESC, created_at DESC");
+        } else {
+            // Every term must match, each as a prefix of an indexed lexeme
+            let ts_query = terms
+                .iter()
+                .map(|term| format!("{}:*", term))
+                .collect::<Vec<_>>()
+                .join(" & ");
+
+            query_builder.push(" AND search_vector @@ to_tsquery('english', ");
+            query_builder.push_bind(ts_query.clone());
+            query_builder.push(")");
+            push_knowledge_scope(&mut query_builder, "", project_id, include_public);
+            query_builder.push(" ORDER BY ts_rank(search_vector, to_tsquery('english', ");
+            query_builder.push_bind(ts_query);
+            query_builder.push(")) DESC, usage_count DESC");
+        }
+
+        query_builder.push(" LIMIT ");
+        query_builder.push_bind(limit);
+
+        let entries = query_builder
+            .build_query_as::<KnowledgeBaseEntry>()
+            .fetch_all(pool)
+            .await?;
+
+        Ok(entries)
+    }
+
+    /// Create the first user account, unless one exists already
+    ///
+    /// Returns whether the user was created. The table lock makes concurrent registrations
+    /// wait for each other, so only the first of them finds no accounts.
+    pub async fn create_first_user(pool: &PgPool, user: &User) -> Result<bool> {
+        let mut tx = pool.begin().await?;
+
+        sqlx::query("LOCK TABLE users IN SHARE ROW EXCLUSIVE MODE")
+            .execute(&mut *tx)
+            .await?;
+
+        let result = sqlx::query(&first_user_insert())
+            .bind(&user.id)
+            .bind(&user.username)
+            .bind(&user.email)
+            .bind(&user.display_name)
+            .bind(&user.password_hash)
+            .bind(user.is_admin)
+            .bind(user.is_active)
+            .bind(user.last_login_at)
+            .bind(user.created_at)
+            .bind(user.updated_at)
+            .bind(&user.oidc_issuer)
+            .bind(&user.oidc_subject)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Search a project's stored streaming entries, newest first, with the tsvector column for text
+    pub async fn search_streaming_logs(pool: &PgPool, query: &StreamingLogQuery) -> Result<Vec<StoredLogEntry>> {
+        let terms = query.text.as_deref().map(search_terms).unwrap_or_default();
+        let mut query_builder = sqlx::QueryBuilder::new(format!(
+            "SELECT {} FROM streaming_logs WHERE 1=1",
+            STREAMING_LOG_COLUMNS
+        ));
+
+        if !terms.is_empty() {
+            // Every term must match, each as a prefix of an indexed lexeme
+            let ts_query = terms
+                .iter()
+                .map(|term| format!("{}:*", term))
+                .collect::<Vec<_>>()
+                .join(" & ");
+
+            query_builder.push(" AND search_vector @@ to_tsquery('simple', ");
+            query_builder.push_bind(ts_query);
+            query_builder.push(")");
+        }
+        push_streaming_log_filters(&mut query_builder, "", query);
+
+        let entries = query_builder
+            .build_query_as::<StoredLogEntry>()
+            .fetch_all(pool)
+            .await?;
+
+        Ok(entries)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use sqlx::{SqlitePool, query_as};
+    use sqlx::SqlitePool;
     use tempfile::TempDir;
 
     async fn setup_test_db() -> (SqlitePool, TempDir) {
@@ -231,6 +3259,16 @@ mod tests {
         (pool, temp_dir)
     }
 
+    fn new_analysis(project_id: &str, provider: &str, level: &str) -> Analysis {
+        Analysis::new(
+            project_id.to_string(),
+            None,
+            "file".to_string(),
+            provider.to_string(),
+            level.to_string(),
+        )
+    }
+
     #[tokio::test]
     async fn test_create_analysis() {
         let (pool, _temp) = setup_test_db().await;
@@ -238,36 +3276,18 @@ mod tests {
         // Create a project first (foreign key constraint)
         let project_id = get_or_create_project(&pool, "/test/project").await.unwrap();
 
-        let log_file_path = "/test/path.log".to_string();
-        let provider = "openrouter".to_string();
-        let level = "ERROR".to_string();
-
-        let analysis_id = create_analysis(
-            &pool,
-            project_id.clone(),
-            log_file_path.clone(),
-            provider.clone(),
-            level.clone(),
-        ).await.unwrap();
-
-        assert!(!analysis_id.is_empty());
+        let analysis = new_analysis(&project_id, "openrouter", "ERROR")
+            .with_source("/test/path.log".to_string());
+        create_analysis(&pool, &analysis).await.unwrap();
 
         // Verify the analysis was created
-        let analysis = query_as::<_, Analysis>(
-            "SELECT id, project_id, log_file_path, provider, level, status,
-                    created_at, started_at, completed_at, metadata
-             FROM analyses WHERE id = ?1"
-        )
-        .bind(&analysis_id)
-        .fetch_one(&pool)
-        .await
-        .unwrap();
+        let stored = get_analysis_by_id(&pool, &analysis.id).await.unwrap().unwrap();
 
-        assert_eq!(analysis.project_id, project_id);
-        assert_eq!(analysis.log_file_path, log_file_path);
-        assert_eq!(analysis.provider, provider);
-        assert_eq!(analysis.level, level);
-        assert_eq!(analysis.status, AnalysisStatus::Pending);
+        assert_eq!(stored.project_id, project_id);
+        assert_eq!(stored.source.as_deref(), Some("/test/path.log"));
+        assert_eq!(stored.provider, "openrouter");
+        assert_eq!(stored.level_filter, "ERROR");
+        assert_eq!(stored.status, AnalysisStatus::Pending);
     }
 
     #[tokio::test]
@@ -278,22 +3298,17 @@ mod tests {
         let project_id = get_or_create_project(&pool, "/test/project").await.unwrap();
 
         // Create an analysis first
-        let analysis_id = create_analysis(
-            &pool,
-            project_id.clone(),
-            "/test/path.log".to_string(),
-            "openrouter".to_string(),
-            "ERROR".to_string(),
-        ).await.unwrap();
+        let analysis = new_analysis(&project_id, "openrouter", "ERROR");
+        create_analysis(&pool, &analysis).await.unwrap();
 
         // Get the analysis
-        let result = get_analysis_by_id(&pool, &analysis_id).await.unwrap();
-        assert!(result.is_some());
-        
-        let (analysis, result_opt) = result.unwrap();
-        assert_eq!(analysis.id, analysis_id);
-        assert_eq!(analysis.project_id, project_id);
-        assert!(result_opt.is_none()); // No results yet
+        let stored = get_analysis_by_id(&pool, &analysis.id).await.unwrap();
+        assert!(stored.is_some());
+
+        let stored = stored.unwrap();
+        assert_eq!(stored.id, analysis.id);
+        assert_eq!(stored.project_id, project_id);
+        assert!(stored.result.is_none()); // No results yet
 
         // Test non-existent analysis
         let result = get_analysis_by_id(&pool, "non-existent").await.unwrap();
@@ -309,16 +3324,20 @@ mod tests {
         let project_id2 = get_or_create_project(&pool, "/test/project2").await.unwrap();
 
         // Create multiple analyses
-        let _id1 = create_analysis(&pool, project_id1.clone(), "/test1.log".to_string(), "openrouter".to_string(), "ERROR".to_string()).await.unwrap();
-        let id2 = create_analysis(&pool, project_id1.clone(), "/test2.log".to_string(), "openai".to_string(), "WARN".to_string()).await.unwrap();
-        let _id3 = create_analysis(&pool, project_id2.clone(), "/test3.log".to_string(), "claude".to_string(), "INFO".to_string()).await.unwrap();
+        let analysis1 = new_analysis(&project_id1, "openrouter", "ERROR");
+        let analysis2 = new_analysis(&project_id1, "openai", "WARN");
+        let analysis3 = new_analysis(&project_id2, "claude", "INFO");
+        for analysis in [&analysis1, &analysis2, &analysis3] {
+            create_analysis(&pool, analysis).await.unwrap();
+        }
 
         // Update one analysis to completed
-        update_analysis_status(&pool, &id2, AnalysisStatus::Completed, Some(Utc::now())).await.unwrap();
+        update_analysis_status(&pool, &analysis2.id, AnalysisStatus::Completed, Some(Utc::now())).await.unwrap();
 
         // Test query by project_id
         let analyses = query_analyses(&pool, Some(&project_id1), None, None, None).await.unwrap();
         assert_eq!(analyses.len(), 2);
+        assert_eq!(count_analyses(&pool, &project_id1).await.unwrap(), 2);
 
         // Test query by status
         let analyses = query_analyses(&pool, None, Some(AnalysisStatus::Pending), None, None).await.unwrap();
@@ -333,59 +3352,31 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_store_analysis_results() {
+    async fn test_complete_and_fail_analysis() {
         let (pool, _temp) = setup_test_db().await;
 
-        // Create a project first
         let project_id = get_or_create_project(&pool, "/test/project").await.unwrap();
 
-        let analysis_id = create_analysis(
-            &pool,
-            project_id,
-            "/test/path.log".to_string(),
-            "openrouter".to_string(),
-            "ERROR".to_string(),
-        ).await.unwrap();
-
-        let summary = Some("Test summary".to_string());
-        let full_report = Some("Test full report".to_string());
-        let patterns = vec![
-            Pattern {
-                pattern: "Error pattern".to_string(),
-                count: 5,
-                examples: vec!["Error example 1".to_string()],
-                severity: "high".to_string(),
-                confidence: 0.9,
-            }
-        ];
-        let issues_found = Some(3);
-
-        store_analysis_results(
-            &pool,
-            &analysis_id,
-            summary.clone(),
-            full_report.clone(),
-            patterns.clone(),
-            issues_found,
-        ).await.unwrap();
-
-        // Verify results were stored
-        let result = query_as::<_, AnalysisResult>(
-            "SELECT analysis_id, summary, full_report, patterns_detected, issues_found, metadata
-             FROM analysis_results WHERE analysis_id = ?1"
-        )
-        .bind(&analysis_id)
-        .fetch_one(&pool)
-        .await
-        .unwrap();
+        let completed = new_analysis(&project_id, "openrouter", "ERROR");
+        let failed = new_analysis(&project_id, "openrouter", "ERROR");
+        create_analysis(&pool, &completed).await.unwrap();
+        create_analysis(&pool, &failed).await.unwrap();
 
-        assert_eq!(result.summary, summary);
-        assert_eq!(result.full_report, full_report);
-        assert_eq!(result.issues_found, issues_found);
-        
-        let stored_patterns: Vec<Pattern> = serde_json::from_value(result.patterns_detected).unwrap();
-        assert_eq!(stored_patterns.len(), 1);
-        assert_eq!(stored_patterns[0].pattern, "Error pattern");
+        mark_analysis_running(&pool, &completed.id).await.unwrap();
+        let running = get_analysis_by_id(&pool, &completed.id).await.unwrap().unwrap();
+        assert_eq!(running.status, AnalysisStatus::Running);
+
+        complete_analysis(&pool, &completed.id, r#"{"sequence_of_events":"boom"}"#).await.unwrap();
+        fail_analysis(&pool, &failed.id, "provider unavailable").await.unwrap();
+
+        let completed = get_analysis_by_id(&pool, &completed.id).await.unwrap().unwrap();
+        assert_eq!(completed.status, AnalysisStatus::Completed);
+        assert!(completed.result.unwrap().contains("boom"));
+        assert!(completed.completed_at.is_some());
+
+        let failed = get_analysis_by_id(&pool, &failed.id).await.unwrap().unwrap();
+        assert_eq!(failed.status, AnalysisStatus::Failed);
+        assert_eq!(failed.error_message.as_deref(), Some("provider unavailable"));
     }
 
     #[tokio::test]
@@ -395,29 +3386,22 @@ mod tests {
         // Create a project first
         let project_id = get_or_create_project(&pool, "/test/project").await.unwrap();
 
-        let analysis_id = create_analysis(
-            &pool,
-            project_id,
-            "/test/path.log".to_string(),
-            "openrouter".to_string(),
-            "ERROR".to_string(),
-        ).await.unwrap();
+        let analysis = new_analysis(&project_id, "openrouter", "ERROR");
+        create_analysis(&pool, &analysis).await.unwrap();
 
         // Update to completed
         let completed_at = Utc::now();
-        update_analysis_status(&pool, &analysis_id, AnalysisStatus::Completed, Some(completed_at)).await.unwrap();
+        update_analysis_status(&pool, &analysis.id, AnalysisStatus::Completed, Some(completed_at)).await.unwrap();
 
-        // Verify status was updated
-        let analysis = query_as::<_, Analysis>(
-            "SELECT id, project_id, log_file_path, provider, level, status,
-                    created_at, started_at, completed_at, metadata
-             FROM analyses WHERE id = ?1"
-        )
-        .bind(&analysis_id)
-        .fetch_one(&pool)
-        .await
-        .unwrap();
+        // Verify status was stored as its integer code
+        let status: i32 = sqlx::query_scalar("SELECT status FROM analyses WHERE id = ?1")
+            .bind(&analysis.id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(status, 2);
 
+        let analysis = get_analysis_by_id(&pool, &analysis.id).await.unwrap().unwrap();
         assert_eq!(analysis.status, AnalysisStatus::Completed);
         assert!(analysis.completed_at.is_some());
     }
@@ -447,4 +3431,31 @@ mod tests {
 
         assert_eq!(count.0, 1);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_upsert_cli_project() {
+        let (pool, _temp) = setup_test_db().await;
+
+        let mut project = Project::new_cli_project(
+            "cli-project".to_string(),
+            "my-app".to_string(),
+            None,
+            "/test/my-app".to_string(),
+            Some("/test/my-app/.synapse".to_string()),
+            "rust".to_string(),
+        );
+
+        assert!(upsert_cli_project(&pool, &project).await.unwrap());
+
+        project.name = "renamed".to_string();
+        assert!(!upsert_cli_project(&pool, &project).await.unwrap());
+
+        let stored = get_project(&pool, "cli-project").await.unwrap().unwrap();
+        assert_eq!(stored.name, "renamed");
+        assert_eq!(stored.synapse_config.as_deref(), Some("/test/my-app/.synapse"));
+        assert_eq!(list_projects(&pool).await.unwrap().len(), 1);
+
+        assert!(delete_project(&pool, "cli-project").await.unwrap());
+        assert!(get_project(&pool, "cli-project").await.unwrap().is_none());
+    }
+}
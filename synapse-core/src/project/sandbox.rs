@@ -0,0 +1,225 @@
+// Sandboxing helpers for analyzing project-local logs and commands
+//
+// This module keeps externally requested analyses (e.g. from MCP clients)
+// confined to a linked project's root directory and to the commands the
+// project explicitly allows in its .synapse/config.toml.
+
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+
+use crate::project::detect::{get_suggested_log_paths, ProjectType};
+
+/// Characters that would change meaning if a command ever reached a shell
+const SHELL_METACHARACTERS: &[char] = &[';', '|', '&', '$', '`', '>', '<', '\n', '\r', '(', ')'];
+
+/// Resolve a requested path against a project root, rejecting anything outside it
+///
+/// Relative paths are joined onto `root`; absolute paths are accepted only if
+/// they already point inside it. Symlinks are resolved before the check, so a
+/// link pointing outside the project is rejected as well.
+pub fn resolve_in_root(root: &Path, requested: &str) -> Result<PathBuf> {
+    let root = root
+        .canonicalize()
+        .with_context(|| format!("Project root not found: {}", root.display()))?;
+
+    let candidate = Path::new(requested);
+    let joined = if candidate.is_absolute() {
+        candidate.to_path_buf()
+    } else {
+        root.join(candidate)
+    };
+
+    let resolved = joined
+        .canonicalize()
+        .with_context(|| format!("Log path not found: {}", requested))?;
+
+    if !resolved.starts_with(&root) {
+        bail!("Path {} is outside the project root", requested);
+    }
+
+    if !resolved.is_file() {
+        bail!("Path {} is not a regular file", requested);
+    }
+
+    Ok(resolved)
+}
+
+/// Check whether a command line is permitted by a project's allowlist
+///
+/// An allowlist entry matches when its whitespace-separated words are a prefix
+/// of the command's words, so `"kubectl logs"` allows `kubectl logs my-pod -n prod`
+/// but not `kubectl delete pod my-pod`.
+pub fn is_command_allowed(command: &str, allowlist: &[String]) -> bool {
+    if command.contains(SHELL_METACHARACTERS) {
+        return false;
+    }
+
+    let words: Vec<&str> = command.split_whitespace().collect();
+    if words.is_empty() {
+        return false;
+    }
+
+    allowlist.iter().any(|entry| {
+        let allowed: Vec<&str> = entry.split_whitespace().collect();
+        !allowed.is_empty() && words.len() >= allowed.len() && words[..allowed.len()] == allowed[..]
+    })
+}
+
+/// Discover existing log files under a project root using the suggested paths
+/// for its project type
+pub fn discover_log_files(root: &Path, project_type: ProjectType) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+
+    for pattern in get_suggested_log_paths(project_type) {
+        let (dir, file_pattern) = match pattern.rsplit_once('/') {
+            Some((dir, file)) => (root.join(dir), file),
+            None => (root.to_path_buf(), pattern),
+        };
+
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let matches = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map(|name| wildcard_match(file_pattern, name))
+                .unwrap_or(false);
+
+            if matches && path.is_file() && !found.contains(&path) {
+                found.push(path);
+            }
+        }
+    }
+
+    found.sort();
+    found
+}
+
+/// Match a file name against a pattern where `*` matches any run of characters
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == name;
+    }
+
+    let first = parts[0];
+    let last = parts[parts.len() - 1];
+    if !name.starts_with(first) || name.len() < first.len() + last.len() {
+        return false;
+    }
+
+    let mut remaining = &name[first.len()..name.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match remaining.find(part) {
+            Some(pos) => remaining = &remaining[pos + part.len()..],
+            None => return false,
+        }
+    }
+
+    name.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_resolve_relative_path_inside_root() {
+        let temp_dir = tempdir().unwrap();
+        std::fs::create_dir(temp_dir.path().join("logs")).unwrap();
+        std::fs::write(temp_dir.path().join("logs/app.log"), "ERROR boom").unwrap();
+
+        let resolved = resolve_in_root(temp_dir.path(), "logs/app.log").unwrap();
+        assert!(resolved.ends_with("logs/app.log"));
+    }
+
+    #[test]
+    fn test_resolve_rejects_parent_traversal() {
+        let temp_dir = tempdir().unwrap();
+        let project = temp_dir.path().join("project");
+        std::fs::create_dir(&project).unwrap();
+        std::fs::write(temp_dir.path().join("secret.log"), "nope").unwrap();
+
+        let result = resolve_in_root(&project, "../secret.log");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_resolve_rejects_absolute_path_outside_root() {
+        let temp_dir = tempdir().unwrap();
+        let project = temp_dir.path().join("project");
+        std::fs::create_dir(&project).unwrap();
+        let outside = temp_dir.path().join("outside.log");
+        std::fs::write(&outside, "nope").unwrap();
+
+        let result = resolve_in_root(&project, outside.to_str().unwrap());
+        assert!(result.is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_rejects_symlink_escape() {
+        let temp_dir = tempdir().unwrap();
+        let project = temp_dir.path().join("project");
+        std::fs::create_dir(&project).unwrap();
+        let outside = temp_dir.path().join("outside.log");
+        std::fs::write(&outside, "nope").unwrap();
+        std::os::unix::fs::symlink(&outside, project.join("link.log")).unwrap();
+
+        let result = resolve_in_root(&project, "link.log");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_command_allowlist_prefix_match() {
+        let allowlist = vec!["kubectl logs".to_string(), "journalctl".to_string()];
+
+        assert!(is_command_allowed("kubectl logs my-pod -n prod", &allowlist));
+        assert!(is_command_allowed("journalctl -u nginx --since today", &allowlist));
+        assert!(!is_command_allowed("kubectl delete pod my-pod", &allowlist));
+        assert!(!is_command_allowed("kubectl", &allowlist));
+        assert!(!is_command_allowed("", &allowlist));
+    }
+
+    #[test]
+    fn test_command_allowlist_rejects_shell_metacharacters() {
+        let allowlist = vec!["docker logs".to_string()];
+
+        assert!(!is_command_allowed("docker logs web; rm -rf /", &allowlist));
+        assert!(!is_command_allowed("docker logs $(whoami)", &allowlist));
+        assert!(!is_command_allowed("docker logs web | sh", &allowlist));
+    }
+
+    #[test]
+    fn test_empty_allowlist_denies_everything() {
+        assert!(!is_command_allowed("docker logs web", &[]));
+    }
+
+    #[test]
+    fn test_discover_log_files() {
+        let temp_dir = tempdir().unwrap();
+        std::fs::create_dir(temp_dir.path().join("logs")).unwrap();
+        std::fs::write(temp_dir.path().join("logs/app.log"), "").unwrap();
+        std::fs::write(temp_dir.path().join("npm-debug.log"), "").unwrap();
+        std::fs::write(temp_dir.path().join("notes.txt"), "").unwrap();
+
+        let found = discover_log_files(temp_dir.path(), ProjectType::Node);
+        assert_eq!(found.len(), 2);
+        assert!(found.iter().any(|p| p.ends_with("logs/app.log")));
+        assert!(found.iter().any(|p| p.ends_with("npm-debug.log")));
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("*.log", "app.log"));
+        assert!(wildcard_match("npm-debug.log", "npm-debug.log"));
+        assert!(wildcard_match("app-*.log", "app-2024.log"));
+        assert!(!wildcard_match("*.log", "app.txt"));
+        assert!(!wildcard_match("app-*.log", "web-2024.log"));
+    }
+}
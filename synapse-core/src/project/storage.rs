@@ -0,0 +1,2052 @@
+// Storage backends for projects, log files, analyses and the knowledge base
+//
+// SQLite is the default single-user backend; PostgreSQL serves shared team
+// deployments. Both implement `Storage` over the same repository functions in
+// `queries`, and the backend is selected from the database URL.
+
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions};
+use tracing::info;
+
+use crate::project::database::run_migrations;
+use crate::project::models::{
+    Alert, AlertQuery, AlertRule, Analysis, AnalysisSchedule, AnalysisStatus, ApiToken, AuditEvent,
+    AuditQuery, DeliveryQuery, ErrorCorrelation, ErrorPattern, KnowledgeBaseEntry, AnalysisJob,
+    JobQuery, LogCount, LogFile, LogPartition, LogRetention, NotificationChannel, NotificationDelivery,
+    PerformanceMetric, Project, ProjectMember, ProjectMemberChange, ProjectRole, ProjectSummary,
+    ProviderSecret, Session, Settings, Share, StoredLogEntry, StreamingLogQuery, StreamingSource, User,
+};
+use crate::project::queries as sqlite_queries;
+
+/// Which database a storage backend talks to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageBackend {
+    Sqlite,
+    Postgres,
+}
+
+impl StorageBackend {
+    /// Select the backend from a database URL scheme
+    ///
+    /// Plain paths and `:memory:` are treated as SQLite databases.
+    pub fn from_url(database_url: &str) -> Result<Self> {
+        match database_url.split_once("://").map(|(scheme, _)| scheme) {
+            Some("postgres") | Some("postgresql") => Ok(StorageBackend::Postgres),
+            Some("sqlite") | None => Ok(StorageBackend::Sqlite),
+            Some(_) => anyhow::bail!(
+                "Unsupported database URL '{}': expected sqlite:// or postgres://",
+                database_url
+            ),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StorageBackend::Sqlite => "sqlite",
+            StorageBackend::Postgres => "postgres",
+        }
+    }
+}
+
+impl std::fmt::Display for StorageBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Persistence for the data shared by the CLI, web backend and MCP server
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// The database this storage talks to
+    fn backend(&self) -> StorageBackend;
+
+    /// The underlying SQLite pool, for subsystems that only run on the SQLite backend
+    fn sqlite_pool(&self) -> Option<&SqlitePool> {
+        None
+    }
+
+    /// Bring the database up to the current schema
+    async fn migrate(&self) -> Result<()>;
+
+    /// Check that the database is reachable
+    async fn ping(&self) -> Result<()>;
+
+    // Projects
+    async fn list_projects(&self) -> Result<Vec<Project>>;
+    async fn count_projects(&self) -> Result<i64>;
+    async fn get_project(&self, project_id: &str) -> Result<Option<Project>>;
+    async fn get_project_by_path(&self, root_path: &str) -> Result<Option<Project>>;
+    async fn list_project_summaries(&self, names: Option<&[String]>) -> Result<Vec<ProjectSummary>>;
+    async fn get_project_summary(&self, project_id: &str) -> Result<Option<ProjectSummary>>;
+    async fn project_exists(&self, project_id: &str, root_path: &str) -> Result<bool>;
+    async fn insert_project(&self, project: &Project) -> Result<()>;
+    async fn upsert_cli_project(&self, project: &Project) -> Result<bool>;
+    async fn get_or_create_project(&self, root_path: &str) -> Result<String>;
+    async fn delete_project(&self, project_id: &str) -> Result<bool>;
+    async fn get_project_analysis_quota(&self, project_id: &str) -> Result<Option<i32>>;
+    async fn set_project_analysis_quota(&self, project_id: &str, quota: Option<i32>) -> Result<bool>;
+    async fn get_project_log_retention(&self, project_id: &str) -> Result<Option<LogRetention>>;
+    async fn set_project_log_retention(&self, project_id: &str, retention: &LogRetention) -> Result<bool>;
+
+    // Log files
+    async fn list_log_files(&self, project_id: &str) -> Result<Vec<LogFile>>;
+    async fn get_log_file(&self, project_id: &str, file_id: &str) -> Result<Option<LogFile>>;
+    async fn insert_log_file(&self, log_file: &LogFile) -> Result<()>;
+    async fn delete_log_file(&self, file_id: &str) -> Result<bool>;
+    async fn count_log_file_analyses(&self, file_id: &str, active_only: bool) -> Result<i64>;
+
+    // Analyses
+    async fn create_analysis(&self, analysis: &Analysis) -> Result<()>;
+    async fn get_analysis(&self, analysis_id: &str) -> Result<Option<Analysis>>;
+    async fn query_analyses(
+        &self,
+        project_id: Option<&str>,
+        status: Option<AnalysisStatus>,
+        limit: Option<i64>,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Analysis>>;
+    async fn list_analyses_with_files(
+        &self,
+        project_id: &str,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<(Analysis, Option<String>)>>;
+    async fn find_latest_file_analysis(
+        &self,
+        project_id: &str,
+        log_file_id: &str,
+        provider: &str,
+        level_filter: &str,
+    ) -> Result<Option<Analysis>>;
+    async fn count_analyses(&self, project_id: &str) -> Result<i64>;
+    async fn count_analyses_since(
+        &self,
+        status: Option<AnalysisStatus>,
+        since: DateTime<Utc>,
+    ) -> Result<i64>;
+    async fn count_project_analyses_since(&self, project_id: &str, since: DateTime<Utc>) -> Result<i64>;
+    async fn average_analysis_minutes(&self, since: DateTime<Utc>) -> Result<Option<f64>>;
+    async fn mark_analysis_running(&self, analysis_id: &str) -> Result<()>;
+    async fn mark_analysis_pending(&self, analysis_id: &str) -> Result<()>;
+    async fn complete_analysis(&self, analysis_id: &str, result_json: &str) -> Result<()>;
+    async fn fail_analysis(&self, analysis_id: &str, error: &str) -> Result<()>;
+    async fn fail_interrupted_analyses(&self, error: &str) -> Result<u64>;
+
+    // Knowledge base
+    async fn create_knowledge_entry(&self, entry: &KnowledgeBaseEntry) -> Result<()>;
+    async fn get_knowledge_entry(&self, entry_id: &str) -> Result<Option<KnowledgeBaseEntry>>;
+    async fn list_knowledge_entries(&self, project_id: &str) -> Result<Vec<KnowledgeBaseEntry>>;
+    async fn list_public_knowledge(&self, limit: i64) -> Result<Vec<KnowledgeBaseEntry>>;
+    async fn increment_knowledge_usage(&self, entry_id: &str) -> Result<()>;
+
+    /// Full-text search over titles, tags, problems and solutions
+    ///
+    /// Entries are limited to `project_id` (plus public entries when
+    /// `include_public` is set); without a project only public entries are searched.
+    async fn search_knowledge(
+        &self,
+        query: &str,
+        project_id: Option<&str>,
+        include_public: bool,
+        limit: i64,
+    ) -> Result<Vec<KnowledgeBaseEntry>>;
+
+    // Error patterns
+    async fn list_error_patterns(
+        &self,
+        project_id: &str,
+        category: Option<&str>,
+    ) -> Result<Vec<ErrorPattern>>;
+    async fn create_error_pattern(&self, pattern: &ErrorPattern) -> Result<()>;
+    async fn record_error_pattern_match(&self, pattern_id: &str) -> Result<bool>;
+
+    // Performance metrics and error correlations
+    async fn create_performance_metric(&self, metric: &PerformanceMetric) -> Result<()>;
+    async fn list_performance_metrics(&self, analysis_id: &str) -> Result<Vec<PerformanceMetric>>;
+    async fn create_error_correlation(&self, correlation: &ErrorCorrelation) -> Result<()>;
+    async fn list_error_correlations(&self, project_id: &str, analysis_id: &str) -> Result<Vec<ErrorCorrelation>>;
+
+    // Settings
+    async fn get_settings(&self) -> Result<Option<Settings>>;
+    async fn update_settings(&self, settings: &Settings) -> Result<()>;
+    async fn update_model_cache(
+        &self,
+        available_models: Option<&str>,
+        models_last_fetched: Option<&str>,
+    ) -> Result<()>;
+
+    // Encrypted provider API keys
+    async fn list_provider_secrets(&self) -> Result<Vec<ProviderSecret>>;
+    async fn get_provider_secret(&self, provider: &str) -> Result<Option<ProviderSecret>>;
+    async fn set_provider_secret(&self, secret: &ProviderSecret) -> Result<()>;
+    async fn delete_provider_secret(&self, provider: &str) -> Result<bool>;
+
+    // Audit log
+    async fn record_audit_event(&self, event: &AuditEvent) -> Result<()>;
+    async fn query_audit_events(&self, filter: &AuditQuery) -> Result<Vec<AuditEvent>>;
+
+    // Share links
+    async fn create_share(&self, share: &Share) -> Result<()>;
+    async fn get_share(&self, share_id: &str) -> Result<Option<Share>>;
+    async fn list_shares(&self, project_id: &str) -> Result<Vec<Share>>;
+    async fn revoke_share(&self, project_id: &str, share_id: &str) -> Result<bool>;
+    async fn record_share_access(&self, share_id: &str) -> Result<()>;
+
+    // Analysis job queue
+    async fn enqueue_job(&self, job: &AnalysisJob) -> Result<()>;
+    async fn get_job(&self, job_id: &str) -> Result<Option<AnalysisJob>>;
+    async fn list_jobs(&self, filter: &JobQuery) -> Result<Vec<AnalysisJob>>;
+    async fn claim_next_job(
+        &self,
+        queue: &str,
+        worker_id: &str,
+        lease_expires_at: DateTime<Utc>,
+    ) -> Result<Option<AnalysisJob>>;
+    async fn renew_job_lease(
+        &self,
+        job_id: &str,
+        worker_id: &str,
+        lease_expires_at: DateTime<Utc>,
+    ) -> Result<bool>;
+    async fn complete_job(&self, job_id: &str, worker_id: &str) -> Result<bool>;
+    async fn retry_job(
+        &self,
+        job_id: &str,
+        worker_id: &str,
+        run_after: DateTime<Utc>,
+        error: &str,
+    ) -> Result<bool>;
+    async fn fail_job(&self, job_id: &str, worker_id: &str, error: &str) -> Result<bool>;
+    async fn cancel_job(&self, job_id: &str) -> Result<bool>;
+    async fn list_expired_jobs(&self, queue: &str) -> Result<Vec<AnalysisJob>>;
+
+    // Alert rules and fired alerts
+    async fn create_alert_rule(&self, rule: &AlertRule) -> Result<()>;
+    async fn get_alert_rule(&self, rule_id: &str) -> Result<Option<AlertRule>>;
+    async fn list_alert_rules(&self, project_id: &str) -> Result<Vec<AlertRule>>;
+    async fn list_enabled_alert_rules(&self) -> Result<Vec<AlertRule>>;
+    async fn update_alert_rule(&self, rule: &AlertRule) -> Result<bool>;
+    async fn delete_alert_rule(&self, project_id: &str, rule_id: &str) -> Result<bool>;
+    async fn record_alert_rule_fired(&self, rule_id: &str, fired_at: DateTime<Utc>) -> Result<()>;
+    async fn create_alert(&self, alert: &Alert) -> Result<()>;
+    async fn get_alert(&self, alert_id: &str) -> Result<Option<Alert>>;
+    async fn find_latest_alert(&self, rule_id: &str, fingerprint: &str) -> Result<Option<Alert>>;
+    async fn record_alert_occurrences(
+        &self,
+        alert_id: &str,
+        occurrences: i64,
+        seen_at: DateTime<Utc>,
+    ) -> Result<bool>;
+    async fn query_alerts(&self, filter: &AlertQuery) -> Result<Vec<Alert>>;
+    async fn acknowledge_alert(&self, project_id: &str, alert_id: &str, user_id: Option<&str>) -> Result<bool>;
+    async fn resolve_alert(&self, project_id: &str, alert_id: &str) -> Result<bool>;
+
+    // Notification channels and their delivery log
+    async fn create_notification_channel(&self, channel: &NotificationChannel) -> Result<()>;
+    async fn get_notification_channel(&self, channel_id: &str) -> Result<Option<NotificationChannel>>;
+    async fn list_notification_channels(&self, project_id: &str) -> Result<Vec<NotificationChannel>>;
+    async fn list_all_notification_channels(&self) -> Result<Vec<NotificationChannel>>;
+    async fn update_notification_channel(&self, channel: &NotificationChannel) -> Result<bool>;
+    async fn delete_notification_channel(&self, project_id: &str, channel_id: &str) -> Result<bool>;
+    async fn create_notification_delivery(&self, delivery: &NotificationDelivery) -> Result<()>;
+    async fn get_notification_delivery(&self, delivery_id: &str) -> Result<Option<NotificationDelivery>>;
+    async fn list_due_notification_deliveries(
+        &self,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<NotificationDelivery>>;
+    async fn claim_notification_delivery(
+        &self,
+        delivery_id: &str,
+        now: DateTime<Utc>,
+        lease_until: DateTime<Utc>,
+    ) -> Result<bool>;
+    async fn update_notification_delivery(&self, delivery: &NotificationDelivery) -> Result<()>;
+    async fn query_notification_deliveries(&self, filter: &DeliveryQuery) -> Result<Vec<NotificationDelivery>>;
+
+    // Scheduled and alert-triggered realtime analyses
+    async fn create_analysis_schedule(&self, schedule: &AnalysisSchedule) -> Result<()>;
+    async fn get_analysis_schedule(&self, schedule_id: &str) -> Result<Option<AnalysisSchedule>>;
+    async fn list_analysis_schedules(&self, project_id: &str) -> Result<Vec<AnalysisSchedule>>;
+    async fn list_enabled_analysis_schedules(&self) -> Result<Vec<AnalysisSchedule>>;
+    async fn update_analysis_schedule(&self, schedule: &AnalysisSchedule) -> Result<bool>;
+    async fn delete_analysis_schedule(&self, project_id: &str, schedule_id: &str) -> Result<bool>;
+    async fn record_analysis_schedule_run(
+        &self,
+        schedule_id: &str,
+        analysis_id: &str,
+        ran_at: DateTime<Utc>,
+    ) -> Result<()>;
+
+    // Streaming sources
+    async fn create_streaming_source(&self, source: &StreamingSource) -> Result<()>;
+    async fn get_streaming_source(&self, project_id: &str, source_id: &str) -> Result<Option<StreamingSource>>;
+    async fn list_streaming_sources(&self, project_id: &str) -> Result<Vec<StreamingSource>>;
+    async fn list_active_streaming_sources(&self) -> Result<Vec<StreamingSource>>;
+    async fn delete_streaming_source(&self, project_id: &str, source_id: &str) -> Result<bool>;
+
+    // Durable streaming log storage
+    async fn insert_streaming_logs(&self, entries: &[StoredLogEntry]) -> Result<()>;
+    async fn search_streaming_logs(&self, query: &StreamingLogQuery) -> Result<Vec<StoredLogEntry>>;
+    async fn count_streaming_logs_by_minute(
+        &self,
+        project_id: &str,
+        source: Option<&str>,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Result<Vec<LogCount>>;
+    async fn list_streaming_log_partitions(&self) -> Result<Vec<LogPartition>>;
+    async fn drop_streaming_log_partition(&self, project_id: &str, partition_start: DateTime<Utc>) -> Result<u64>;
+    async fn delete_streaming_log_counts_before(&self, project_id: &str, before: DateTime<Utc>) -> Result<u64>;
+
+    // Users, sessions and API tokens
+    async fn create_user(&self, user: &User) -> Result<()>;
+    /// Create the account only if no other exists yet, returning whether it was created
+    async fn create_first_user(&self, user: &User) -> Result<bool>;
+    async fn get_user(&self, user_id: &str) -> Result<Option<User>>;
+    async fn get_user_by_username(&self, username: &str) -> Result<Option<User>>;
+    async fn get_user_by_oidc_identity(&self, issuer: &str, subject: &str) -> Result<Option<User>>;
+    async fn update_user_profile(
+        &self,
+        user_id: &str,
+        email: Option<&str>,
+        display_name: Option<&str>,
+        is_admin: bool,
+    ) -> Result<()>;
+    async fn list_users(&self) -> Result<Vec<User>>;
+    async fn count_users(&self) -> Result<i64>;
+    async fn record_user_login(&self, user_id: &str) -> Result<()>;
+    async fn set_user_active(&self, user_id: &str, is_active: bool) -> Result<bool>;
+    async fn update_user_password(&self, user_id: &str, password_hash: &str) -> Result<()>;
+    async fn create_session(&self, session: &Session) -> Result<()>;
+    async fn get_session(&self, session_id: &str) -> Result<Option<Session>>;
+    async fn touch_session(&self, session_id: &str) -> Result<()>;
+    async fn delete_session(&self, session_id: &str) -> Result<()>;
+    async fn delete_expired_sessions(&self) -> Result<u64>;
+    async fn create_api_token(&self, token: &ApiToken) -> Result<()>;
+    async fn list_api_tokens(&self, user_id: &str) -> Result<Vec<ApiToken>>;
+    async fn find_api_token(&self, token_hash: &str) -> Result<Option<ApiToken>>;
+    async fn touch_api_token(&self, token_id: &str) -> Result<()>;
+    async fn delete_api_token(&self, user_id: &str, token_id: &str) -> Result<bool>;
+
+    // Project membership
+    async fn list_projects_for_user(&self, user_id: &str) -> Result<Vec<Project>>;
+    async fn get_project_role(&self, project_id: &str, user_id: &str) -> Result<Option<ProjectRole>>;
+    async fn list_project_members(&self, project_id: &str) -> Result<Vec<ProjectMember>>;
+    async fn set_project_member(
+        &self,
+        project_id: &str,
+        user_id: &str,
+        role: ProjectRole,
+        changed_by: Option<&str>,
+    ) -> Result<Option<ProjectRole>>;
+    async fn remove_project_member(
+        &self,
+        project_id: &str,
+        user_id: &str,
+        changed_by: Option<&str>,
+    ) -> Result<Option<ProjectRole>>;
+    async fn list_project_member_changes(
+        &self,
+        project_id: &str,
+        limit: i64,
+    ) -> Result<Vec<ProjectMemberChange>>;
+}
+
+/// Implement `Storage` for a backend by delegating to its repository module
+macro_rules! impl_storage {
+    ($storage:ty, $repo:ident, $backend:expr, $migrate:path, { $($extra:item)* }) => {
+        #[async_trait]
+        impl Storage for $storage {
+            fn backend(&self) -> StorageBackend {
+                $backend
+            }
+
+            $($extra)*
+
+            async fn migrate(&self) -> Result<()> {
+                $migrate(&self.pool).await
+            }
+
+            async fn ping(&self) -> Result<()> {
+                sqlx::query("SELECT 1").execute(&self.pool).await?;
+                Ok(())
+            }
+
+            async fn list_projects(&self) -> Result<Vec<Project>> {
+                $repo::list_projects(&self.pool).await
+            }
+
+            async fn count_projects(&self) -> Result<i64> {
+                $repo::count_projects(&self.pool).await
+            }
+
+            async fn get_project(&self, project_id: &str) -> Result<Option<Project>> {
+                $repo::get_project(&self.pool, project_id).await
+            }
+
+            async fn get_project_by_path(&self, root_path: &str) -> Result<Option<Project>> {
+                $repo::get_project_by_path(&self.pool, root_path).await
+            }
+
+            async fn list_project_summaries(&self, names: Option<&[String]>) -> Result<Vec<ProjectSummary>> {
+                $repo::list_project_summaries(&self.pool, names).await
+            }
+
+            async fn get_project_summary(&self, project_id: &str) -> Result<Option<ProjectSummary>> {
+                $repo::get_project_summary(&self.pool, project_id).await
+            }
+
+            async fn project_exists(&self, project_id: &str, root_path: &str) -> Result<bool> {
+                $repo::project_exists(&self.pool, project_id, root_path).await
+            }
+
+            async fn insert_project(&self, project: &Project) -> Result<()> {
+                $repo::insert_project(&self.pool, project).await
+            }
+
+            async fn upsert_cli_project(&self, project: &Project) -> Result<bool> {
+                $repo::upsert_cli_project(&self.pool, project).await
+            }
+
+            async fn get_or_create_project(&self, root_path: &str) -> Result<String> {
+                $repo::get_or_create_project(&self.pool, root_path).await
+            }
+
+            async fn delete_project(&self, project_id: &str) -> Result<bool> {
+                $repo::delete_project(&self.pool, project_id).await
+            }
+
+            async fn get_project_analysis_quota(&self, project_id: &str) -> Result<Option<i32>> {
+                $repo::get_project_analysis_quota(&self.pool, project_id).await
+            }
+
+            async fn set_project_analysis_quota(&self, project_id: &str, quota: Option<i32>) -> Result<bool> {
+                $repo::set_project_analysis_quota(&self.pool, project_id, quota).await
+            }
+
+            async fn get_project_log_retention(&self, project_id: &str) -> Result<Option<LogRetention>> {
+                $repo::get_project_log_retention(&self.pool, project_id).await
+            }
+
+            async fn set_project_log_retention(&self, project_id: &str, retention: &LogRetention) -> Result<bool> {
+                $repo::set_project_log_retention(&self.pool, project_id, retention).await
+            }
+
+            async fn list_log_files(&self, project_id: &str) -> Result<Vec<LogFile>> {
+                $repo::list_log_files(&self.pool, project_id).await
+            }
+
+            async fn get_log_file(&self, project_id: &str, file_id: &str) -> Result<Option<LogFile>> {
+                $repo::get_log_file(&self.pool, project_id, file_id).await
+            }
+
+            async fn insert_log_file(&self, log_file: &LogFile) -> Result<()> {
+                $repo::insert_log_file(&self.pool, log_file).await
+            }
+
+            async fn delete_log_file(&self, file_id: &str) -> Result<bool> {
+                $repo::delete_log_file(&self.pool, file_id).await
+            }
+
+            async fn count_log_file_analyses(&self, file_id: &str, active_only: bool) -> Result<i64> {
+                $repo::count_log_file_analyses(&self.pool, file_id, active_only).await
+            }
+
+            async fn create_analysis(&self, analysis: &Analysis) -> Result<()> {
+                $repo::create_analysis(&self.pool, analysis).await
+            }
+
+            async fn get_analysis(&self, analysis_id: &str) -> Result<Option<Analysis>> {
+                $repo::get_analysis_by_id(&self.pool, analysis_id).await
+            }
+
+            async fn query_analyses(
+                &self,
+                project_id: Option<&str>,
+                status: Option<AnalysisStatus>,
+                limit: Option<i64>,
+                since: Option<DateTime<Utc>>,
+            ) -> Result<Vec<Analysis>> {
+                $repo::query_analyses(&self.pool, project_id, status, limit, since).await
+            }
+
+            async fn list_analyses_with_files(
+                &self,
+                project_id: &str,
+                limit: i64,
+                offset: i64,
+            ) -> Result<Vec<(Analysis, Option<String>)>> {
+                $repo::list_analyses_with_files(&self.pool, project_id, limit, offset).await
+            }
+
+            async fn find_latest_file_analysis(
+                &self,
+                project_id: &str,
+                log_file_id: &str,
+                provider: &str,
+                level_filter: &str,
+            ) -> Result<Option<Analysis>> {
+                $repo::find_latest_file_analysis(&self.pool, project_id, log_file_id, provider, level_filter).await
+            }
+
+            async fn count_analyses(&self, project_id: &str) -> Result<i64> {
+                $repo::count_analyses(&self.pool, project_id).await
+            }
+
+            async fn count_analyses_since(
+                &self,
+                status: Option<AnalysisStatus>,
+                since: DateTime<Utc>,
+            ) -> Result<i64> {
+                $repo::count_analyses_since(&self.pool, status, since).await
+            }
+
+            async fn count_project_analyses_since(&self, project_id: &str, since: DateTime<Utc>) -> Result<i64> {
+                $repo::count_project_analyses_since(&self.pool, project_id, since).await
+            }
+
+            async fn average_analysis_minutes(&self, since: DateTime<Utc>) -> Result<Option<f64>> {
+                $repo::average_analysis_minutes(&self.pool, since).await
+            }
+
+            async fn mark_analysis_running(&self, analysis_id: &str) -> Result<()> {
+                $repo::mark_analysis_running(&self.pool, analysis_id).await
+            }
+
+            async fn mark_analysis_pending(&self, analysis_id: &str) -> Result<()> {
+                $repo::mark_analysis_pending(&self.pool, analysis_id).await
+            }
+
+            async fn complete_analysis(&self, analysis_id: &str, result_json: &str) -> Result<()> {
+                $repo::complete_analysis(&self.pool, analysis_id, result_json).await
+            }
+
+            async fn fail_analysis(&self, analysis_id: &str, error: &str) -> Result<()> {
+                $repo::fail_analysis(&self.pool, analysis_id, error).await
+            }
+
+            async fn fail_interrupted_analyses(&self, error: &str) -> Result<u64> {
+                $repo::fail_interrupted_analyses(&self.pool, error).await
+            }
+
+            async fn create_knowledge_entry(&self, entry: &KnowledgeBaseEntry) -> Result<()> {
+                $repo::create_knowledge_entry(&self.pool, entry).await
+            }
+
+            async fn get_knowledge_entry(&self, entry_id: &str) -> Result<Option<KnowledgeBaseEntry>> {
+                $repo::get_knowledge_entry(&self.pool, entry_id).await
+            }
+
+            async fn list_knowledge_entries(&self, project_id: &str) -> Result<Vec<KnowledgeBaseEntry>> {
+                $repo::list_knowledge_entries(&self.pool, project_id).await
+            }
+
+            async fn list_public_knowledge(&self, limit: i64) -> Result<Vec<KnowledgeBaseEntry>> {
+                $repo::list_public_knowledge(&self.pool, limit).await
+            }
+
+            async fn increment_knowledge_usage(&self, entry_id: &str) -> Result<()> {
+                $repo::increment_knowledge_usage(&self.pool, entry_id).await
+            }
+
+            async fn search_knowledge(
+                &self,
+                query: &str,
+                project_id: Option<&str>,
+                include_public: bool,
+                limit: i64,
+            ) -> Result<Vec<KnowledgeBaseEntry>> {
+                $repo::search_knowledge(&self.pool, query, project_id, include_public, limit).await
+            }
+
+            async fn list_error_patterns(
+                &self,
+                project_id: &str,
+                category: Option<&str>,
+            ) -> Result<Vec<ErrorPattern>> {
+                $repo::list_error_patterns(&self.pool, project_id, category).await
+            }
+
+            async fn create_error_pattern(&self, pattern: &ErrorPattern) -> Result<()> {
+                $repo::create_error_pattern(&self.pool, pattern).await
+            }
+
+            async fn record_error_pattern_match(&self, pattern_id: &str) -> Result<bool> {
+                $repo::record_error_pattern_match(&self.pool, pattern_id).await
+            }
+
+            async fn create_performance_metric(&self, metric: &PerformanceMetric) -> Result<()> {
+                $repo::create_performance_metric(&self.pool, metric).await
+            }
+
+            async fn list_performance_metrics(&self, analysis_id: &str) -> Result<Vec<PerformanceMetric>> {
+                $repo::list_performance_metrics(&self.pool, analysis_id).await
+            }
+
+            async fn create_error_correlation(&self, correlation: &ErrorCorrelation) -> Result<()> {
+                $repo::create_error_correlation(&self.pool, correlation).await
+            }
+
+            async fn list_error_correlations(
+                &self,
+                project_id: &str,
+                analysis_id: &str,
+            ) -> Result<Vec<ErrorCorrelation>> {
+                $repo::list_error_correlations(&self.pool, project_id, analysis_id).await
+            }
+
+            async fn get_settings(&self) -> Result<Option<Settings>> {
+                $repo::get_settings(&self.pool).await
+            }
+
+            async fn update_settings(&self, settings: &Settings) -> Result<()> {
+                $repo::update_settings(&self.pool, settings).await
+            }
+
+            async fn update_model_cache(
+                &self,
+                available_models: Option<&str>,
+                models_last_fetched: Option<&str>,
+            ) -> Result<()> {
+                $repo::update_model_cache(&self.pool, available_models, models_last_fetched).await
+            }
+
+            async fn list_provider_secrets(&self) -> Result<Vec<ProviderSecret>> {
+                $repo::list_provider_secrets(&self.pool).await
+            }
+
+            async fn get_provider_secret(&self, provider: &str) -> Result<Option<ProviderSecret>> {
+                $repo::get_provider_secret(&self.pool, provider).await
+            }
+
+            async fn set_provider_secret(&self, secret: &ProviderSecret) -> Result<()> {
+                $repo::set_provider_secret(&self.pool, secret).await
+            }
+
+            async fn delete_provider_secret(&self, provider: &str) -> Result<bool> {
+                $repo::delete_provider_secret(&self.pool, provider).await
+            }
+
+            async fn record_audit_event(&self, event: &AuditEvent) -> Result<()> {
+                $repo::record_audit_event(&self.pool, event).await
+            }
+
+            async fn query_audit_events(&self, filter: &AuditQuery) -> Result<Vec<AuditEvent>> {
+                $repo::query_audit_events(&self.pool, filter).await
+            }
+
+            async fn create_share(&self, share: &Share) -> Result<()> {
+                $repo::create_share(&self.pool, share).await
+            }
+
+            async fn get_share(&self, share_id: &str) -> Result<Option<Share>> {
+                $repo::get_share(&self.pool, share_id).await
+            }
+
+            async fn list_shares(&self, project_id: &str) -> Result<Vec<Share>> {
+                $repo::list_shares(&self.pool, project_id).await
+            }
+
+            async fn revoke_share(&self, project_id: &str, share_id: &str) -> Result<bool> {
+                $repo::revoke_share(&self.pool, project_id, share_id).await
+            }
+
+            async fn record_share_access(&self, share_id: &str) -> Result<()> {
+                $repo::record_share_access(&self.pool, share_id).await
+            }
+
+            async fn enqueue_job(&self, job: &AnalysisJob) -> Result<()> {
+                $repo::enqueue_job(&self.pool, job).await
+            }
+
+            async fn get_job(&self, job_id: &str) -> Result<Option<AnalysisJob>> {
+                $repo::get_job(&self.pool, job_id).await
+            }
+
+            async fn list_jobs(&self, filter: &JobQuery) -> Result<Vec<AnalysisJob>> {
+                $repo::list_jobs(&self.pool, filter).await
+            }
+
+            async fn claim_next_job(
+                &self,
+                queue: &str,
+                worker_id: &str,
+                lease_expires_at: DateTime<Utc>,
+            ) -> Result<Option<AnalysisJob>> {
+                $repo::claim_next_job(&self.pool, queue, worker_id, lease_expires_at).await
+            }
+
+            async fn renew_job_lease(
+                &self,
+                job_id: &str,
+                worker_id: &str,
+                lease_expires_at: DateTime<Utc>,
+            ) -> Result<bool> {
+                $repo::renew_job_lease(&self.pool, job_id, worker_id, lease_expires_at).await
+            }
+
+            async fn complete_job(&self, job_id: &str, worker_id: &str) -> Result<bool> {
+                $repo::complete_job(&self.pool, job_id, worker_id).await
+            }
+
+            async fn retry_job(
+                &self,
+                job_id: &str,
+                worker_id: &str,
+                run_after: DateTime<Utc>,
+                error: &str,
+            ) -> Result<bool> {
+                $repo::retry_job(&self.pool, job_id, worker_id, run_after, error).await
+            }
+
+            async fn fail_job(&self, job_id: &str, worker_id: &str, error: &str) -> Result<bool> {
+                $repo::fail_job(&self.pool, job_id, worker_id, error).await
+            }
+
+            async fn cancel_job(&self, job_id: &str) -> Result<bool> {
+                $repo::cancel_job(&self.pool, job_id).await
+            }
+
+            async fn list_expired_jobs(&self, queue: &str) -> Result<Vec<AnalysisJob>> {
+                $repo::list_expired_jobs(&self.pool, queue).await
+            }
+
+            async fn create_alert_rule(&self, rule: &AlertRule) -> Result<()> {
+                $repo::create_alert_rule(&self.pool, rule).await
+            }
+
+            async fn get_alert_rule(&self, rule_id: &str) -> Result<Option<AlertRule>> {
+                $repo::get_alert_rule(&self.pool, rule_id).await
+            }
+
+            async fn list_alert_rules(&self, project_id: &str) -> Result<Vec<AlertRule>> {
+                $repo::list_alert_rules(&self.pool, project_id).await
+            }
+
+            async fn list_enabled_alert_rules(&self) -> Result<Vec<AlertRule>> {
+                $repo::list_enabled_alert_rules(&self.pool).await
+            }
+
+            async fn update_alert_rule(&self, rule: &AlertRule) -> Result<bool> {
+                $repo::update_alert_rule(&self.pool, rule).await
+            }
+
+            async fn delete_alert_rule(&self, project_id: &str, rule_id: &str) -> Result<bool> {
+                $repo::delete_alert_rule(&self.pool, project_id, rule_id).await
+            }
+
+            async fn record_alert_rule_fired(&self, rule_id: &str, fired_at: DateTime<Utc>) -> Result<()> {
+                $repo::record_alert_rule_fired(&self.pool, rule_id, fired_at).await
+            }
+
+            async fn create_alert(&self, alert: &Alert) -> Result<()> {
+                $repo::create_alert(&self.pool, alert).await
+            }
+
+            async fn get_alert(&self, alert_id: &str) -> Result<Option<Alert>> {
+                $repo::get_alert(&self.pool, alert_id).await
+            }
+
+            async fn find_latest_alert(&self, rule_id: &str, fingerprint: &str) -> Result<Option<Alert>> {
+                $repo::find_latest_alert(&self.pool, rule_id, fingerprint).await
+            }
+
+            async fn record_alert_occurrences(
+                &self,
+                alert_id: &str,
+                occurrences: i64,
+                seen_at: DateTime<Utc>,
+            ) -> Result<bool> {
+                $repo::record_alert_occurrences(&self.pool, alert_id, occurrences, seen_at).await
+            }
+
+            async fn query_alerts(&self, filter: &AlertQuery) -> Result<Vec<Alert>> {
+                $repo::query_alerts(&self.pool, filter).await
+            }
+
+            async fn acknowledge_alert(
+                &self,
+                project_id: &str,
+                alert_id: &str,
+                user_id: Option<&str>,
+            ) -> Result<bool> {
+                $repo::acknowledge_alert(&self.pool, project_id, alert_id, user_id).await
+            }
+
+            async fn resolve_alert(&self, project_id: &str, alert_id: &str) -> Result<bool> {
+                $repo::resolve_alert(&self.pool, project_id, alert_id).await
+            }
+
+            async fn create_notification_channel(&self, channel: &NotificationChannel) -> Result<()> {
+                $repo::create_notification_channel(&self.pool, channel).await
+            }
+
+            async fn get_notification_channel(&self, channel_id: &str) -> Result<Option<NotificationChannel>> {
+                $repo::get_notification_channel(&self.pool, channel_id).await
+            }
+
+            async fn list_notification_channels(&self, project_id: &str) -> Result<Vec<NotificationChannel>> {
+                $repo::list_notification_channels(&self.pool, project_id).await
+            }
+
+            async fn list_all_notification_channels(&self) -> Result<Vec<NotificationChannel>> {
+                $repo::list_all_notification_channels(&self.pool).await
+            }
+
+            async fn update_notification_channel(&self, channel: &NotificationChannel) -> Result<bool> {
+                $repo::update_notification_channel(&self.pool, channel).await
+            }
+
+            async fn delete_notification_channel(&self, project_id: &str, channel_id: &str) -> Result<bool> {
+                $repo::delete_notification_channel(&self.pool, project_id, channel_id).await
+            }
+
+            async fn create_notification_delivery(&self, delivery: &NotificationDelivery) -> Result<()> {
+                $repo::create_notification_delivery(&self.pool, delivery).await
+            }
+
+            async fn get_notification_delivery(&self, delivery_id: &str) -> Result<Option<NotificationDelivery>> {
+                $repo::get_notification_delivery(&self.pool, delivery_id).await
+            }
+
+            async fn list_due_notification_deliveries(
+                &self,
+                now: DateTime<Utc>,
+                limit: i64,
+            ) -> Result<Vec<NotificationDelivery>> {
+                $repo::list_due_notification_deliveries(&self.pool, now, limit).await
+            }
+
+            async fn claim_notification_delivery(
+                &self,
+                delivery_id: &str,
+                now: DateTime<Utc>,
+                lease_until: DateTime<Utc>,
+            ) -> Result<bool> {
+                $repo::claim_notification_delivery(&self.pool, delivery_id, now, lease_until).await
+            }
+
+            async fn update_notification_delivery(&self, delivery: &NotificationDelivery) -> Result<()> {
+                $repo::update_notification_delivery(&self.pool, delivery).await
+            }
+
+            async fn query_notification_deliveries(&self, filter: &DeliveryQuery) -> Result<Vec<NotificationDelivery>> {
+                $repo::query_notification_deliveries(&self.pool, filter).await
+            }
+
+            async fn create_analysis_schedule(&self, schedule: &AnalysisSchedule) -> Result<()> {
+                $repo::create_analysis_schedule(&self.pool, schedule).await
+            }
+
+            async fn get_analysis_schedule(&self, schedule_id: &str) -> Result<Option<AnalysisSchedule>> {
+                $repo::get_analysis_schedule(&self.pool, schedule_id).await
+            }
+
+            async fn list_analysis_schedules(&self, project_id: &str) -> Result<Vec<AnalysisSchedule>> {
+                $repo::list_analysis_schedules(&self.pool, project_id).await
+            }
+
+            async fn list_enabled_analysis_schedules(&self) -> Result<Vec<AnalysisSchedule>> {
+                $repo::list_enabled_analysis_schedules(&self.pool).await
+            }
+
+            async fn update_analysis_schedule(&self, schedule: &AnalysisSchedule) -> Result<bool> {
+                $repo::update_analysis_schedule(&self.pool, schedule).await
+            }
+
+            async fn delete_analysis_schedule(&self, project_id: &str, schedule_id: &str) -> Result<bool> {
+                $repo::delete_analysis_schedule(&self.pool, project_id, schedule_id).await
+            }
+
+            async fn record_analysis_schedule_run(
+                &self,
+                schedule_id: &str,
+                analysis_id: &str,
+                ran_at: DateTime<Utc>,
+            ) -> Result<()> {
+                $repo::record_analysis_schedule_run(&self.pool, schedule_id, analysis_id, ran_at).await
+            }
+
+            async fn create_streaming_source(&self, source: &StreamingSource) -> Result<()> {
+                $repo::create_streaming_source(&self.pool, source).await
+            }
+
+            async fn get_streaming_source(
+                &self,
+                project_id: &str,
+                source_id: &str,
+            ) -> Result<Option<StreamingSource>> {
+                $repo::get_streaming_source(&self.pool, project_id, source_id).await
+            }
+
+            async fn list_streaming_sources(&self, project_id: &str) -> Result<Vec<StreamingSource>> {
+                $repo::list_streaming_sources(&self.pool, project_id).await
+            }
+
+            async fn list_active_streaming_sources(&self) -> Result<Vec<StreamingSource>> {
+                $repo::list_active_streaming_sources(&self.pool).await
+            }
+
+            async fn delete_streaming_source(&self, project_id: &str, source_id: &str) -> Result<bool> {
+                $repo::delete_streaming_source(&self.pool, project_id, source_id).await
+            }
+
+            async fn insert_streaming_logs(&self, entries: &[StoredLogEntry]) -> Result<()> {
+                $repo::insert_streaming_logs(&self.pool, entries).await
+            }
+
+            async fn search_streaming_logs(&self, query: &StreamingLogQuery) -> Result<Vec<StoredLogEntry>> {
+                $repo::search_streaming_logs(&self.pool, query).await
+            }
+
+            async fn count_streaming_logs_by_minute(
+                &self,
+                project_id: &str,
+                source: Option<&str>,
+                since: DateTime<Utc>,
+                until: DateTime<Utc>,
+            ) -> Result<Vec<LogCount>> {
+                $repo::count_streaming_logs_by_minute(&self.pool, project_id, source, since, until).await
+            }
+
+            async fn list_streaming_log_partitions(&self) -> Result<Vec<LogPartition>> {
+                $repo::list_streaming_log_partitions(&self.pool).await
+            }
+
+            async fn drop_streaming_log_partition(
+                &self,
+                project_id: &str,
+                partition_start: DateTime<Utc>,
+            ) -> Result<u64> {
+                $repo::drop_streaming_log_partition(&self.pool, project_id, partition_start).await
+            }
+
+            async fn delete_streaming_log_counts_before(
+                &self,
+                project_id: &str,
+                before: DateTime<Utc>,
+            ) -> Result<u64> {
+                $repo::delete_streaming_log_counts_before(&self.pool, project_id, before).await
+            }
+
+            async fn create_user(&self, user: &User) -> Result<()> {
+                $repo::create_user(&self.pool, user).await
+            }
+
+            async fn create_first_user(&self, user: &User) -> Result<bool> {
+                $repo::create_first_user(&self.pool, user).await
+            }
+
+            async fn get_user(&self, user_id: &str) -> Result<Option<User>> {
+                $repo::get_user(&self.pool, user_id).await
+            }
+
+            async fn get_user_by_username(&self, username: &str) -> Result<Option<User>> {
+                $repo::get_user_by_username(&self.pool, username).await
+            }
+
+            async fn get_user_by_oidc_identity(
+                &self,
+                issuer: &str,
+                subject: &str,
+            ) -> Result<Option<User>> {
+                $repo::get_user_by_oidc_identity(&self.pool, issuer, subject).await
+            }
+
+            async fn update_user_profile(
+                &self,
+                user_id: &str,
+                email: Option<&str>,
+                display_name: Option<&str>,
+                is_admin: bool,
+            ) -> Result<()> {
+                $repo::update_user_profile(&self.pool, user_id, email, display_name, is_admin).await
+            }
+
+            async fn list_users(&self) -> Result<Vec<User>> {
+                $repo::list_users(&self.pool).await
+            }
+
+            async fn count_users(&self) -> Result<i64> {
+                $repo::count_users(&self.pool).await
+            }
+
+            async fn record_user_login(&self, user_id: &str) -> Result<()> {
+                $repo::record_user_login(&self.pool, user_id).await
+            }
+
+            async fn set_user_active(&self, user_id: &str, is_active: bool) -> Result<bool> {
+                $repo::set_user_active(&self.pool, user_id, is_active).await
+            }
+
+            async fn update_user_password(&self, user_id: &str, password_hash: &str) -> Result<()> {
+                $repo::update_user_password(&self.pool, user_id, password_hash).await
+            }
+
+            async fn create_session(&self, session: &Session) -> Result<()> {
+                $repo::create_session(&self.pool, session).await
+            }
+
+            async fn get_session(&self, session_id: &str) -> Result<Option<Session>> {
+                $repo::get_session(&self.pool, session_id).await
+            }
+
+            async fn touch_session(&self, session_id: &str) -> Result<()> {
+                $repo::touch_session(&self.pool, session_id).await
+            }
+
+            async fn delete_session(&self, session_id: &str) -> Result<()> {
+                $repo::delete_session(&self.pool, session_id).await
+            }
+
+            async fn delete_expired_sessions(&self) -> Result<u64> {
+                $repo::delete_expired_sessions(&self.pool).await
+            }
+
+            async fn create_api_token(&self, token: &ApiToken) -> Result<()> {
+                $repo::create_api_token(&self.pool, token).await
+            }
+
+            async fn list_api_tokens(&self, user_id: &str) -> Result<Vec<ApiToken>> {
+                $repo::list_api_tokens(&self.pool, user_id).await
+            }
+
+            async fn find_api_token(&self, token_hash: &str) -> Result<Option<ApiToken>> {
+                $repo::find_api_token(&self.pool, token_hash).await
+            }
+
+            async fn touch_api_token(&self, token_id: &str) -> Result<()> {
+                $repo::touch_api_token(&self.pool, token_id).await
+            }
+
+            async fn delete_api_token(&self, user_id: &str, token_id: &str) -> Result<bool> {
+                $repo::delete_api_token(&self.pool, user_id, token_id).await
+            }
+
+            async fn list_projects_for_user(&self, user_id: &str) -> Result<Vec<Project>> {
+                $repo::list_projects_for_user(&self.pool, user_id).await
+            }
+
+            async fn get_project_role(
+                &self,
+                project_id: &str,
+                user_id: &str,
+            ) -> Result<Option<ProjectRole>> {
+                $repo::get_project_role(&self.pool, project_id, user_id).await
+            }
+
+            async fn list_project_members(&self, project_id: &str) -> Result<Vec<ProjectMember>> {
+                $repo::list_project_members(&self.pool, project_id).await
+            }
+
+            async fn set_project_member(
+                &self,
+                project_id: &str,
+                user_id: &str,
+                role: ProjectRole,
+                changed_by: Option<&str>,
+            ) -> Result<Option<ProjectRole>> {
+                $repo::set_project_member(&self.pool, project_id, user_id, role, changed_by).await
+            }
+
+            async fn remove_project_member(
+                &self,
+                project_id: &str,
+                user_id: &str,
+                changed_by: Option<&str>,
+            ) -> Result<Option<ProjectRole>> {
+                $repo::remove_project_member(&self.pool, project_id, user_id, changed_by).await
+            }
+
+            async fn list_project_member_changes(
+                &self,
+                project_id: &str,
+                limit: i64,
+            ) -> Result<Vec<ProjectMemberChange>> {
+                $repo::list_project_member_changes(&self.pool, project_id, limit).await
+            }
+        }
+    };
+}
+
+/// SQLite storage, the default single-user backend
+#[derive(Clone)]
+pub struct SqliteStorage {
+    pool: SqlitePool,
+}
+
+impl SqliteStorage {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Open (creating if missing) the database at a `sqlite:` URL or path
+    pub async fn open(database_url: &str, max_connections: u32) -> Result<Self> {
+        let connect_options = SqliteConnectOptions::from_str(database_url)?
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal);
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(max_connections)
+            .connect_with(connect_options)
+            .await
+            .context("Failed to create SQLite connection pool")?;
+
+        Ok(Self::new(pool))
+    }
+
+    pub fn pool(&self) -> &SqlitePool {
+        &self.pool
+    }
+}
+
+impl_storage!(SqliteStorage, sqlite_queries, StorageBackend::Sqlite, run_migrations, {
+    fn sqlite_pool(&self) -> Option<&SqlitePool> {
+        Some(&self.pool)
+    }
+});
+
+#[cfg(feature = "postgres")]
+pub use self::postgres_storage::PostgresStorage;
+
+#[cfg(feature = "postgres")]
+mod postgres_storage {
+    use super::*;
+    use crate::project::database::run_postgres_migrations;
+    use crate::project::queries::postgres as pg_queries;
+    use sqlx::postgres::{PgPool, PgPoolOptions};
+
+    /// PostgreSQL storage for shared team deployments
+    #[derive(Clone)]
+    pub struct PostgresStorage {
+        pool: PgPool,
+    }
+
+    impl PostgresStorage {
+        pub fn new(pool: PgPool) -> Self {
+            Self { pool }
+        }
+
+        /// Connect to the database at a `postgres://` URL
+        pub async fn open(database_url: &str, max_connections: u32) -> Result<Self> {
+            let pool = PgPoolOptions::new()
+                .max_connections(max_connections)
+                .connect(database_url)
+                .await
+                .context("Failed to connect to PostgreSQL")?;
+
+            Ok(Self::new(pool))
+        }
+
+        pub fn pool(&self) -> &PgPool {
+            &self.pool
+        }
+    }
+
+    impl_storage!(PostgresStorage, pg_queries, StorageBackend::Postgres, run_postgres_migrations, {});
+}
+
+/// Open and migrate the storage backend selected by `database_url`
+///
+/// `sqlite:` URLs and plain paths (including `:memory:`) use SQLite; `postgres://` and
+/// `postgresql://` URLs use PostgreSQL when built with the `postgres` feature.
+pub async fn connect_storage(database_url: &str, max_connections: u32) -> Result<Arc<dyn Storage>> {
+    let backend = StorageBackend::from_url(database_url)?;
+    info!("Opening {} storage", backend);
+
+    let storage: Arc<dyn Storage> = match backend {
+        StorageBackend::Sqlite => Arc::new(SqliteStorage::open(database_url, max_connections).await?),
+        #[cfg(feature = "postgres")]
+        StorageBackend::Postgres => Arc::new(PostgresStorage::open(database_url, max_connections).await?),
+        #[cfg(not(feature = "postgres"))]
+        StorageBackend::Postgres => {
+            anyhow::bail!("PostgreSQL support is not enabled; rebuild with the `postgres` feature")
+        }
+    };
+
+    storage.migrate().await?;
+    Ok(storage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::models::{JobStatus, LogCursor};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_backend_from_url() {
+        assert_eq!(StorageBackend::from_url("sqlite://data/synapse.db").unwrap(), StorageBackend::Sqlite);
+        assert_eq!(StorageBackend::from_url("sqlite::memory:").unwrap(), StorageBackend::Sqlite);
+        assert_eq!(StorageBackend::from_url("/tmp/synapse.db").unwrap(), StorageBackend::Sqlite);
+        assert_eq!(StorageBackend::from_url("postgres://synapse@db/synapse").unwrap(), StorageBackend::Postgres);
+        assert_eq!(StorageBackend::from_url("postgresql://db/synapse").unwrap(), StorageBackend::Postgres);
+        assert!(StorageBackend::from_url("mysql://db/synapse").is_err());
+    }
+
+    /// Exercise a backend through the trait; shared by the SQLite and PostgreSQL tests
+    async fn exercise_storage(storage: &dyn Storage) {
+        let project = Project::new("storage-test".to_string(), Some("Storage test".to_string()));
+        storage.insert_project(&project).await.unwrap();
+        assert!(storage.get_project(&project.id).await.unwrap().is_some());
+
+        let log_file = LogFile::new(project.id.clone(), "app.log".to_string(), 42, 3, "/tmp/app.log".to_string());
+        storage.insert_log_file(&log_file).await.unwrap();
+
+        let analysis = Analysis::new(
+            project.id.clone(),
+            Some(log_file.id.clone()),
+            "file".to_string(),
+            "mock".to_string(),
+            "ERROR".to_string(),
+        );
+        storage.create_analysis(&analysis).await.unwrap();
+        storage.mark_analysis_running(&analysis.id).await.unwrap();
+        assert_eq!(storage.count_log_file_analyses(&log_file.id, true).await.unwrap(), 1);
+        storage.complete_analysis(&analysis.id, r#"{"ok":true}"#).await.unwrap();
+
+        let stored = storage.get_analysis(&analysis.id).await.unwrap().unwrap();
+        assert_eq!(stored.status, AnalysisStatus::Completed);
+        let summary = storage.get_project_summary(&project.id).await.unwrap().unwrap();
+        assert_eq!((summary.file_count, summary.analysis_count), (1, 1));
+        assert!(summary.last_analysis_date.is_some());
+        let names = vec!["storage-test".to_string()];
+        assert_eq!(storage.list_project_summaries(Some(&names)).await.unwrap().len(), 1);
+
+        let listed = storage.list_analyses_with_files(&project.id, 10, 0).await.unwrap();
+        assert_eq!(listed[0].1.as_deref(), Some("app.log"));
+        assert!(storage.average_analysis_minutes(Utc::now() - chrono::Duration::days(1)).await.unwrap().is_some());
+        let today = Utc::now() - chrono::Duration::hours(1);
+        assert_eq!(storage.count_project_analyses_since(&project.id, today).await.unwrap(), 1);
+        assert_eq!(storage.count_project_analyses_since("other-project", today).await.unwrap(), 0);
+
+        assert_eq!(storage.get_project_analysis_quota(&project.id).await.unwrap(), None);
+        assert!(storage.set_project_analysis_quota(&project.id, Some(25)).await.unwrap());
+        assert_eq!(storage.get_project_analysis_quota(&project.id).await.unwrap(), Some(25));
+        assert!(!storage.set_project_analysis_quota("other-project", Some(25)).await.unwrap());
+
+        let entry = KnowledgeBaseEntry::new(
+            project.id.clone(),
+            "Connection pool exhausted under load".to_string(),
+            "Requests time out waiting for a database connection".to_string(),
+            "Raise max_connections".to_string(),
+            Some(r#"["database","pool"]"#.to_string()),
+            "high".to_string(),
+            false,
+        );
+        storage.create_knowledge_entry(&entry).await.unwrap();
+
+        let found = storage.search_knowledge("connections timing", Some(&project.id), true, 10).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].id, entry.id);
+        assert!(storage.search_knowledge("kubernetes", Some(&project.id), true, 10).await.unwrap().is_empty());
+        assert!(storage.search_knowledge("pool", Some("other-project"), false, 10).await.unwrap().is_empty());
+        assert_eq!(storage.search_knowledge("", Some(&project.id), false, 10).await.unwrap().len(), 1);
+
+        // Without a project, only public entries are searched, whichever project they belong to
+        let other_project = Project::new("knowledge neighbour".to_string(), None);
+        storage.insert_project(&other_project).await.unwrap();
+        let marker = format!("marker{}", uuid::Uuid::new_v4().simple());
+        let entry_in = |project_id: &str, is_public| {
+            KnowledgeBaseEntry::new(
+                project_id.to_string(),
+                format!("Disk full {}", marker),
+                "Writes fail with ENOSPC".to_string(),
+                "Rotate logs".to_string(),
+                None,
+                "medium".to_string(),
+                is_public,
+            )
+        };
+        let private_entry = entry_in(&other_project.id, false);
+        let public_entry = entry_in(&other_project.id, true);
+        storage.create_knowledge_entry(&private_entry).await.unwrap();
+        storage.create_knowledge_entry(&public_entry).await.unwrap();
+        let found = storage.search_knowledge(&marker, None, true, 10).await.unwrap();
+        assert_eq!(found.iter().map(|entry| entry.id.as_str()).collect::<Vec<_>>(), [public_entry.id.as_str()]);
+        assert!(storage.search_knowledge("", None, true, 1000).await.unwrap().iter().all(|entry| entry.is_public));
+        let found = storage.search_knowledge(&marker, Some(&project.id), true, 10).await.unwrap();
+        assert_eq!(found.iter().map(|entry| entry.id.as_str()).collect::<Vec<_>>(), [public_entry.id.as_str()]);
+
+        let mut settings = storage.get_settings().await.unwrap().unwrap();
+        settings.max_lines = 2000;
+        storage.update_settings(&settings).await.unwrap();
+        assert_eq!(storage.get_settings().await.unwrap().unwrap().max_lines, 2000);
+
+        let mut share = Share::new(
+            project.id.clone(),
+            analysis.id.clone(),
+            None,
+            Utc::now() + chrono::Duration::hours(1),
+        );
+        share.password_hash = Some("$argon2id$test".to_string());
+        share.include_metrics = false;
+        storage.create_share(&share).await.unwrap();
+        storage.record_share_access(&share.id).await.unwrap();
+        let stored = storage.get_share(&share.id).await.unwrap().unwrap();
+        assert!(stored.is_active() && stored.is_password_protected() && !stored.include_metrics);
+        assert_eq!(stored.access_count, 1);
+        assert!(stored.last_accessed_at.is_some());
+        assert_eq!(storage.list_shares(&project.id).await.unwrap().len(), 1);
+        assert!(!storage.revoke_share("other-project", &share.id).await.unwrap());
+        assert!(storage.revoke_share(&project.id, &share.id).await.unwrap());
+        assert!(!storage.revoke_share(&project.id, &share.id).await.unwrap());
+        assert!(!storage.get_share(&share.id).await.unwrap().unwrap().is_active());
+
+        let latest = storage
+            .find_latest_file_analysis(&project.id, &log_file.id, "mock", "ERROR")
+            .await
+            .unwrap();
+        assert_eq!(latest.map(|a| a.id), Some(analysis.id.clone()));
+        assert!(storage
+            .find_latest_file_analysis(&project.id, &log_file.id, "openai", "ERROR")
+            .await
+            .unwrap()
+            .is_none());
+
+        let slow = PerformanceMetric::new(analysis.id.clone(), "response_time".to_string(), 900.0, "ms".to_string(), Some(500.0));
+        let errors = PerformanceMetric::new(analysis.id.clone(), "error_rate".to_string(), 1.0, "percent".to_string(), Some(5.0));
+        storage.create_performance_metric(&errors).await.unwrap();
+        storage.create_performance_metric(&slow).await.unwrap();
+        let metrics = storage.list_performance_metrics(&analysis.id).await.unwrap();
+        assert_eq!(metrics.iter().map(|m| m.id.as_str()).collect::<Vec<_>>(), [slow.id.as_str(), errors.id.as_str()]);
+        assert!(metrics[0].is_bottleneck && !metrics[1].is_bottleneck);
+
+        let other = Analysis::new(project.id.clone(), None, "file".to_string(), "mock".to_string(), "ERROR".to_string());
+        storage.create_analysis(&other).await.unwrap();
+        let correlation = ErrorCorrelation::new(
+            project.id.clone(),
+            other.id.clone(),
+            analysis.id.clone(),
+            0.8,
+            "temporal".to_string(),
+        );
+        storage.create_error_correlation(&correlation).await.unwrap();
+        assert_eq!(storage.list_error_correlations(&project.id, &analysis.id).await.unwrap().len(), 1);
+        assert!(storage.list_error_correlations("other-project", &analysis.id).await.unwrap().is_empty());
+
+        assert!(storage.delete_project(&project.id).await.unwrap());
+        assert!(storage.get_analysis(&analysis.id).await.unwrap().is_none());
+        assert!(storage.get_share(&share.id).await.unwrap().is_none());
+
+        exercise_accounts(storage).await;
+        exercise_secrets(storage).await;
+        exercise_audit_log(storage).await;
+        exercise_job_queue(storage).await;
+        exercise_alerts(storage).await;
+        exercise_notifications(storage).await;
+        exercise_analysis_schedules(storage).await;
+        exercise_streaming_sources(storage).await;
+        exercise_streaming_logs(storage).await;
+    }
+
+    async fn exercise_accounts(storage: &dyn Storage) {
+        // Of two registrations racing for the first account, at most one gets it
+        let existing = storage.count_users().await.unwrap();
+        let first = User::new(format!("first-{}", uuid::Uuid::new_v4()), None, true);
+        let second = User::new(format!("first-{}", uuid::Uuid::new_v4()), None, true);
+        let (first_created, second_created) =
+            tokio::join!(storage.create_first_user(&first), storage.create_first_user(&second));
+        let created = [first_created.unwrap(), second_created.unwrap()];
+        assert_eq!(created.iter().filter(|created| **created).count(), usize::from(existing == 0));
+        assert_eq!(storage.count_users().await.unwrap(), existing.max(1));
+
+        let username = format!("user-{}", uuid::Uuid::new_v4());
+        let user = User::new(username.clone(), Some("$argon2id$test".to_string()), false);
+        storage.create_user(&user).await.unwrap();
+        assert!(storage.count_users().await.unwrap() >= 1);
+        assert_eq!(storage.get_user_by_username(&username).await.unwrap().unwrap().id, user.id);
+
+        let project = Project::new("owned".to_string(), None).with_created_by(Some(user.id.clone()));
+        storage.insert_project(&project).await.unwrap();
+        let stored = storage.get_project(&project.id).await.unwrap().unwrap();
+        assert_eq!(stored.created_by.as_deref(), Some(user.id.as_str()));
+
+        let now = Utc::now();
+        let session = Session {
+            id: format!("session-{}", user.id),
+            user_id: user.id.clone(),
+            user_agent: None,
+            ip_address: None,
+            created_at: now,
+            expires_at: now + chrono::Duration::hours(1),
+            last_seen_at: now,
+        };
+        storage.create_session(&session).await.unwrap();
+        storage.touch_session(&session.id).await.unwrap();
+        assert!(storage.get_session(&session.id).await.unwrap().is_some());
+
+        let token = ApiToken {
+            id: uuid::Uuid::new_v4().to_string(),
+            user_id: user.id.clone(),
+            name: "ci".to_string(),
+            token_hash: format!("hash-{}", user.id),
+            token_prefix: "syn_abcd".to_string(),
+            created_at: now,
+            last_used_at: None,
+            expires_at: None,
+        };
+        storage.create_api_token(&token).await.unwrap();
+        assert_eq!(storage.find_api_token(&token.token_hash).await.unwrap().unwrap().id, token.id);
+        storage.touch_api_token(&token.id).await.unwrap();
+        assert_eq!(storage.list_api_tokens(&user.id).await.unwrap().len(), 1);
+        assert!(storage.delete_api_token(&user.id, &token.id).await.unwrap());
+        assert!(storage.find_api_token(&token.token_hash).await.unwrap().is_none());
+
+        // Single sign-on accounts are found by their provider identity
+        let sso_user = User::new(format!("sso-{}", uuid::Uuid::new_v4()), None, false)
+            .with_oidc_identity("https://idp.example.com".to_string(), user.id.clone());
+        storage.create_user(&sso_user).await.unwrap();
+        storage
+            .update_user_profile(&sso_user.id, Some("sso@example.com"), Some("SSO User"), true)
+            .await
+            .unwrap();
+        let found = storage
+            .get_user_by_oidc_identity("https://idp.example.com", &user.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.id, sso_user.id);
+        assert!(found.is_admin);
+        assert_eq!(found.email.as_deref(), Some("sso@example.com"));
+        assert!(storage.get_user_by_oidc_identity("https://other.example.com", &user.id).await.unwrap().is_none());
+
+        // The creator administers the project; others see only projects they belong to
+        let viewer = User::new(format!("viewer-{}", uuid::Uuid::new_v4()), None, false);
+        storage.create_user(&viewer).await.unwrap();
+        let owner = Some(user.id.as_str());
+        assert_eq!(
+            storage.set_project_member(&project.id, &user.id, ProjectRole::Admin, owner).await.unwrap(),
+            None
+        );
+        assert!(storage.list_projects_for_user(&viewer.id).await.unwrap().is_empty());
+        storage
+            .set_project_member(&project.id, &viewer.id, ProjectRole::Viewer, owner)
+            .await
+            .unwrap();
+        assert_eq!(
+            storage.set_project_member(&project.id, &viewer.id, ProjectRole::Analyst, owner).await.unwrap(),
+            Some(ProjectRole::Viewer)
+        );
+        assert_eq!(
+            storage.get_project_role(&project.id, &viewer.id).await.unwrap(),
+            Some(ProjectRole::Analyst)
+        );
+        assert_eq!(storage.list_projects_for_user(&viewer.id).await.unwrap().len(), 1);
+
+        let members = storage.list_project_members(&project.id).await.unwrap();
+        assert_eq!(members.len(), 2);
+        assert!(members.iter().any(|m| m.username == viewer.username && m.role == ProjectRole::Analyst));
+
+        assert_eq!(
+            storage.remove_project_member(&project.id, &viewer.id, owner).await.unwrap(),
+            Some(ProjectRole::Analyst)
+        );
+        assert_eq!(storage.remove_project_member(&project.id, &viewer.id, owner).await.unwrap(), None);
+        assert!(storage.get_project_role(&project.id, &viewer.id).await.unwrap().is_none());
+
+        let changes = storage.list_project_member_changes(&project.id, 10).await.unwrap();
+        assert_eq!(changes.len(), 4);
+        assert_eq!(changes[0].old_role, Some(ProjectRole::Analyst));
+        assert_eq!(changes[0].new_role, None);
+        assert_eq!(changes[0].changed_by.as_deref(), owner);
+
+        // Disabling an account ends its sessions
+        assert!(storage.set_user_active(&user.id, false).await.unwrap());
+        assert!(storage.get_session(&session.id).await.unwrap().is_none());
+        assert!(storage.delete_project(&project.id).await.unwrap());
+    }
+
+    async fn exercise_secrets(storage: &dyn Storage) {
+        use crate::secrets::{self, Keyring, MasterKey};
+
+        let provider = format!("provider-{}", uuid::Uuid::new_v4());
+        let mut keyring = Keyring::new(MasterKey::generate().unwrap(), Vec::new());
+
+        let stored = secrets::store_api_key(storage, &keyring, &provider, "sk-first-key-0001").await.unwrap();
+        assert_eq!(stored.hint, "****0001");
+        let row = storage.get_provider_secret(&provider).await.unwrap().unwrap();
+        assert!(!row.ciphertext.contains("sk-first"));
+        assert_eq!(
+            secrets::load_api_key(storage, &keyring, &provider).await.unwrap().as_deref(),
+            Some("sk-first-key-0001")
+        );
+
+        // Replacing the key keeps one row per provider
+        secrets::store_api_key(storage, &keyring, &provider, "sk-second-key-0002").await.unwrap();
+        let listed = storage.list_provider_secrets().await.unwrap();
+        assert_eq!(listed.iter().filter(|secret| secret.provider == provider).count(), 1);
+
+        keyring.rotate().unwrap();
+        assert!(secrets::reseal_api_keys(storage, &keyring).await.unwrap() >= 1);
+        let row = storage.get_provider_secret(&provider).await.unwrap().unwrap();
+        assert_eq!(row.key_id, keyring.key_id());
+        assert_eq!(
+            secrets::load_api_key(storage, &keyring, &provider).await.unwrap().as_deref(),
+            Some("sk-second-key-0002")
+        );
+
+        assert!(storage.delete_provider_secret(&provider).await.unwrap());
+        assert!(secrets::load_api_key(storage, &keyring, &provider).await.unwrap().is_none());
+    }
+
+    async fn exercise_audit_log(storage: &dyn Storage) {
+        // Scoped to a fresh project id, since the PostgreSQL test database keeps old events
+        let project_id = uuid::Uuid::new_v4().to_string();
+        let started = Utc::now() - chrono::Duration::seconds(1);
+
+        let created = AuditEvent::new("project.create")
+            .with_actor(Some("user-1".to_string()), Some("alice".to_string()))
+            .with_project(project_id.clone())
+            .with_target("project", project_id.clone())
+            .with_details(serde_json::json!({ "name": "audited" }));
+        storage.record_audit_event(&created).await.unwrap();
+        let uploaded = AuditEvent::new("file.upload")
+            .with_actor(Some("user-2".to_string()), Some("bob".to_string()))
+            .with_project(project_id.clone())
+            .with_target("log_file", "file-1");
+        storage.record_audit_event(&uploaded).await.unwrap();
+        let started_analysis = AuditEvent::new("analysis_start")
+            .with_project(project_id.clone());
+        storage.record_audit_event(&started_analysis).await.unwrap();
+
+        let scoped = |filter: AuditQuery| AuditQuery { project_id: Some(project_id.clone()), ..filter };
+        let count = |filter: AuditQuery| {
+            let filter = scoped(filter);
+            async move { storage.query_audit_events(&filter).await.unwrap().len() }
+        };
+
+        assert_eq!(count(AuditQuery::default()).await, 3);
+        let by_actor = storage
+            .query_audit_events(&scoped(AuditQuery { actor_id: Some("user-1".to_string()), ..Default::default() }))
+            .await
+            .unwrap();
+        assert_eq!(by_actor.len(), 1);
+        assert_eq!(by_actor[0].details.as_deref(), Some(r#"{"name":"audited"}"#));
+        assert_eq!(count(AuditQuery { action: Some("file.upload".to_string()), ..Default::default() }).await, 1);
+        // The prefix match treats "_" literally
+        assert_eq!(count(AuditQuery { action: Some("analysis_*".to_string()), ..Default::default() }).await, 1);
+        assert_eq!(count(AuditQuery { action: Some("project.*".to_string()), ..Default::default() }).await, 1);
+        assert_eq!(count(AuditQuery { target_type: Some("log_file".to_string()), ..Default::default() }).await, 1);
+        assert_eq!(count(AuditQuery { since: Some(started), ..Default::default() }).await, 3);
+        assert_eq!(count(AuditQuery { until: Some(started), ..Default::default() }).await, 0);
+        assert_eq!(count(AuditQuery { limit: Some(2), offset: Some(2), ..Default::default() }).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_audit_log_is_append_only() {
+        let temp_dir = TempDir::new().unwrap();
+        let url = format!("sqlite://{}", temp_dir.path().join("storage.db").display());
+        let storage = connect_storage(&url, 1).await.unwrap();
+        storage.record_audit_event(&AuditEvent::new("settings.update")).await.unwrap();
+
+        let pool = storage.sqlite_pool().unwrap();
+        assert!(sqlx::query("UPDATE audit_log SET action = 'x'").execute(pool).await.is_err());
+        assert!(sqlx::query("DELETE FROM audit_log").execute(pool).await.is_err());
+        assert_eq!(storage.query_audit_events(&AuditQuery::default()).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_settings_api_key_migration() {
+        use crate::secrets::{self, Keyring, MasterKey};
+
+        let temp_dir = TempDir::new().unwrap();
+        let url = format!("sqlite://{}", temp_dir.path().join("storage.db").display());
+        let storage = connect_storage(&url, 1).await.unwrap();
+        let keyring = Keyring::new(MasterKey::generate().unwrap(), Vec::new());
+
+        sqlx::query("UPDATE settings SET default_provider = 'openai', api_key = 'sk-legacy-plaintext'")
+            .execute(storage.sqlite_pool().unwrap())
+            .await
+            .unwrap();
+
+        assert!(secrets::migrate_settings_api_key(storage.as_ref(), &keyring).await.unwrap());
+        assert!(storage.get_settings().await.unwrap().unwrap().api_key.is_empty());
+        assert_eq!(
+            secrets::load_api_key(storage.as_ref(), &keyring, "openai").await.unwrap().as_deref(),
+            Some("sk-legacy-plaintext")
+        );
+        assert!(!secrets::migrate_settings_api_key(storage.as_ref(), &keyring).await.unwrap());
+    }
+
+    async fn exercise_job_queue(storage: &dyn Storage) {
+        let project = Project::new("job-queue".to_string(), None);
+        storage.insert_project(&project).await.unwrap();
+        let analysis = Analysis::new(
+            project.id.clone(),
+            None,
+            "file".to_string(),
+            "mock".to_string(),
+            "ERROR".to_string(),
+        );
+        storage.create_analysis(&analysis).await.unwrap();
+
+        // A queue of its own, so jobs left by earlier runs on a shared database don't interfere
+        let queue = format!("test-{}", uuid::Uuid::new_v4());
+        let new_job = || AnalysisJob::new(analysis.id.clone(), project.id.clone(), &queue, 60);
+        let low = new_job();
+        let high = new_job().with_priority(10).with_payload(r#"{"model":"m"}"#.to_string());
+        let mut later = new_job().with_priority(20);
+        later.run_after = Utc::now() + chrono::Duration::hours(1);
+        for job in [&low, &high, &later] {
+            storage.enqueue_job(job).await.unwrap();
+        }
+
+        let lease = Utc::now() + chrono::Duration::seconds(30);
+        let claimed = storage.claim_next_job(&queue, "worker-1", lease).await.unwrap().unwrap();
+        assert_eq!(claimed.id, high.id);
+        assert_eq!((claimed.status, claimed.attempts), (JobStatus::Running, 1));
+        assert_eq!(claimed.payload, high.payload);
+        assert!(storage.renew_job_lease(&high.id, "worker-1", lease).await.unwrap());
+        assert!(!storage.renew_job_lease(&high.id, "worker-2", lease).await.unwrap());
+
+        let retry_at = Utc::now() - chrono::Duration::seconds(1);
+        assert!(storage.retry_job(&high.id, "worker-1", retry_at, "provider timeout").await.unwrap());
+        let retried = storage.claim_next_job(&queue, "worker-1", lease).await.unwrap().unwrap();
+        assert_eq!((retried.id.as_str(), retried.attempts), (high.id.as_str(), 2));
+        assert_eq!(retried.last_error.as_deref(), Some("provider timeout"));
+        assert!(!storage.complete_job(&high.id, "worker-2").await.unwrap());
+        assert!(storage.complete_job(&high.id, "worker-1").await.unwrap());
+        assert!(!storage.cancel_job(&high.id).await.unwrap());
+
+        // A worker that stops renewing its lease leaves the job for recovery
+        let abandoned = storage.claim_next_job(&queue, "worker-1", lease).await.unwrap().unwrap();
+        assert_eq!(abandoned.id, low.id);
+        let lapsed = Utc::now() - chrono::Duration::seconds(1);
+        storage.renew_job_lease(&low.id, "worker-1", lapsed).await.unwrap();
+        let expired = storage.list_expired_jobs(&queue).await.unwrap();
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].worker_id.as_deref(), Some("worker-1"));
+        assert!(storage.fail_job(&low.id, "worker-1", "gave up").await.unwrap());
+        assert!(storage.list_expired_jobs(&queue).await.unwrap().is_empty());
+
+        // Jobs waiting for their retry time are not claimed, but can be cancelled
+        assert!(storage.claim_next_job(&queue, "worker-1", lease).await.unwrap().is_none());
+        assert!(storage.cancel_job(&later.id).await.unwrap());
+        assert!(!storage.cancel_job(&later.id).await.unwrap());
+
+        let filter = JobQuery { queue: Some(queue.clone()), ..Default::default() };
+        assert_eq!(storage.list_jobs(&filter).await.unwrap().len(), 3);
+        let failed = JobQuery { status: Some(JobStatus::Failed), ..filter.clone() };
+        assert_eq!(storage.list_jobs(&failed).await.unwrap()[0].id, low.id);
+        let scoped = JobQuery { project_ids: Some(vec![project.id.clone()]), limit: Some(1), ..filter.clone() };
+        assert_eq!(storage.list_jobs(&scoped).await.unwrap().len(), 1);
+        let none = JobQuery { project_ids: Some(Vec::new()), ..filter };
+        assert!(storage.list_jobs(&none).await.unwrap().is_empty());
+
+        storage.mark_analysis_running(&analysis.id).await.unwrap();
+        storage.mark_analysis_pending(&analysis.id).await.unwrap();
+        assert_eq!(storage.get_analysis(&analysis.id).await.unwrap().unwrap().status, AnalysisStatus::Pending);
+
+        // After a restart, analyses no job will finish are failed; queued ones are left to their job
+        let new_analysis = || Analysis::new(project.id.clone(), None, "command".to_string(), "mock".to_string(), "ERROR".to_string());
+        let (orphaned, queued) = (new_analysis(), new_analysis());
+        for created in [&orphaned, &queued] {
+            storage.create_analysis(created).await.unwrap();
+        }
+        storage.mark_analysis_running(&orphaned.id).await.unwrap();
+        storage.enqueue_job(&AnalysisJob::new(queued.id.clone(), project.id.clone(), &queue, 60)).await.unwrap();
+        assert!(storage.fail_interrupted_analyses("Interrupted by a restart").await.unwrap() >= 2);
+        for (id, status) in [(&orphaned.id, AnalysisStatus::Failed), (&analysis.id, AnalysisStatus::Failed), (&queued.id, AnalysisStatus::Pending)] {
+            assert_eq!(storage.get_analysis(id).await.unwrap().unwrap().status, status);
+        }
+        let orphaned = storage.get_analysis(&orphaned.id).await.unwrap().unwrap();
+        assert_eq!(orphaned.error_message.as_deref(), Some("Interrupted by a restart"));
+
+        assert!(storage.delete_project(&project.id).await.unwrap());
+        assert!(storage.get_job(&high.id).await.unwrap().is_none());
+    }
+
+    async fn exercise_alerts(storage: &dyn Storage) {
+        use crate::project::models::{AlertSeverity, AlertStatus};
+
+        let project = Project::new("alerts".to_string(), None);
+        storage.insert_project(&project).await.unwrap();
+        let mut rule = AlertRule::new(
+            project.id.clone(),
+            "Errors".to_string(),
+            r#"{"type":"level_threshold","level":"ERROR"}"#.to_string(),
+        );
+        rule.severity = AlertSeverity::Critical;
+        storage.create_alert_rule(&rule).await.unwrap();
+        let mut disabled = AlertRule::new(project.id.clone(), "Off".to_string(), "{}".to_string());
+        disabled.enabled = false;
+        storage.create_alert_rule(&disabled).await.unwrap();
+
+        assert_eq!(storage.list_alert_rules(&project.id).await.unwrap().len(), 2);
+        let enabled = storage.list_enabled_alert_rules().await.unwrap();
+        assert!(enabled.iter().any(|r| r.id == rule.id) && !enabled.iter().any(|r| r.id == disabled.id));
+
+        rule.silenced_until = Some(Utc::now() + chrono::Duration::minutes(10));
+        rule.source = Some("api".to_string());
+        assert!(storage.update_alert_rule(&rule).await.unwrap());
+        let stored = storage.get_alert_rule(&rule.id).await.unwrap().unwrap();
+        assert!(stored.is_silenced());
+        assert_eq!((stored.severity, stored.source.as_deref()), (AlertSeverity::Critical, Some("api")));
+        assert_eq!(stored.condition, rule.condition);
+        let fired_at = Utc::now();
+        storage.record_alert_rule_fired(&rule.id, fired_at).await.unwrap();
+        assert!(storage.get_alert_rule(&rule.id).await.unwrap().unwrap().last_fired_at.is_some());
+
+        let alert = Alert::new(&rule, "api".to_string(), "3 ERROR entries".to_string())
+            .with_source("api")
+            .with_details(serde_json::json!({ "count": 3 }));
+        storage.create_alert(&alert).await.unwrap();
+        assert!(storage.record_alert_occurrences(&alert.id, 2, Utc::now()).await.unwrap());
+        let latest = storage.find_latest_alert(&rule.id, "api").await.unwrap().unwrap();
+        assert_eq!((latest.id.as_str(), latest.occurrences), (alert.id.as_str(), 3));
+        assert_eq!(latest.details.as_deref(), Some(r#"{"count":3}"#));
+        assert!(storage.find_latest_alert(&rule.id, "worker").await.unwrap().is_none());
+
+        assert!(!storage.acknowledge_alert("other-project", &alert.id, None).await.unwrap());
+        assert!(storage.acknowledge_alert(&project.id, &alert.id, None).await.unwrap());
+        assert!(!storage.acknowledge_alert(&project.id, &alert.id, None).await.unwrap());
+        assert!(storage.resolve_alert(&project.id, &alert.id).await.unwrap());
+        assert!(!storage.resolve_alert(&project.id, &alert.id).await.unwrap());
+        let resolved = storage.get_alert(&alert.id).await.unwrap().unwrap();
+        assert_eq!(resolved.status, AlertStatus::Resolved);
+        assert!(resolved.acknowledged_at.is_some() && resolved.resolved_at.is_some());
+        // A resolved alert no longer counts repeat matches
+        assert!(!storage.record_alert_occurrences(&alert.id, 1, Utc::now()).await.unwrap());
+
+        let scoped = AlertQuery { project_id: Some(project.id.clone()), ..Default::default() };
+        assert_eq!(storage.query_alerts(&scoped).await.unwrap().len(), 1);
+        let firing = AlertQuery { status: Some(AlertStatus::Firing), ..scoped.clone() };
+        assert!(storage.query_alerts(&firing).await.unwrap().is_empty());
+        let critical = AlertQuery { severity: Some(AlertSeverity::Critical), ..scoped.clone() };
+        assert_eq!(storage.query_alerts(&critical).await.unwrap().len(), 1);
+        let later = AlertQuery { since: Some(Utc::now() + chrono::Duration::seconds(1)), ..scoped.clone() };
+        assert!(storage.query_alerts(&later).await.unwrap().is_empty());
+
+        assert!(!storage.delete_alert_rule("other-project", &rule.id).await.unwrap());
+        assert!(storage.delete_alert_rule(&project.id, &rule.id).await.unwrap());
+        assert!(storage.get_alert(&alert.id).await.unwrap().is_none());
+        assert!(storage.delete_project(&project.id).await.unwrap());
+        assert!(storage.get_alert_rule(&disabled.id).await.unwrap().is_none());
+    }
+
+    async fn exercise_notifications(storage: &dyn Storage) {
+        use crate::project::models::{AlertSeverity, ChannelType, DeliveryStatus};
+
+        let project = Project::new("notifications".to_string(), None);
+        storage.insert_project(&project).await.unwrap();
+        let mut channel = NotificationChannel::new(
+            project.id.clone(),
+            "On call".to_string(),
+            ChannelType::Webhook,
+            r#"{"type":"webhook","url":"http://localhost/hook"}"#.to_string(),
+        );
+        storage.create_notification_channel(&channel).await.unwrap();
+
+        channel.min_severity = AlertSeverity::Critical;
+        channel.notify_analyses = true;
+        assert!(storage.update_notification_channel(&channel).await.unwrap());
+        let stored = storage.get_notification_channel(&channel.id).await.unwrap().unwrap();
+        assert_eq!(stored.min_severity, AlertSeverity::Critical);
+        assert_eq!(stored.channel_type, ChannelType::Webhook);
+        assert!(stored.notify_analyses);
+        assert_eq!(storage.list_notification_channels(&project.id).await.unwrap().len(), 1);
+        assert!(!storage.list_all_notification_channels().await.unwrap().is_empty());
+        channel.project_id = "other-project".to_string();
+        assert!(!storage.update_notification_channel(&channel).await.unwrap());
+        channel.project_id = project.id.clone();
+
+        let delivery = NotificationDelivery::new(&channel, "alert.fired", r#"{"title":"Errors"}"#.to_string(), 3)
+            .with_event_id("alert-1");
+        storage.create_notification_delivery(&delivery).await.unwrap();
+        let now = Utc::now() + chrono::Duration::seconds(1);
+        let due = storage.list_due_notification_deliveries(now, 10).await.unwrap();
+        assert!(due.iter().any(|due| due.id == delivery.id));
+
+        // A claimed delivery isn't due again until its lease runs out
+        let lease_until = now + chrono::Duration::minutes(5);
+        assert!(storage.claim_notification_delivery(&delivery.id, now, lease_until).await.unwrap());
+        assert!(!storage.claim_notification_delivery(&delivery.id, now, lease_until).await.unwrap());
+        assert!(storage.list_due_notification_deliveries(now, 10).await.unwrap().iter().all(|due| due.id != delivery.id));
+
+        let mut attempted = storage.get_notification_delivery(&delivery.id).await.unwrap().unwrap();
+        attempted.attempts = 1;
+        attempted.status = DeliveryStatus::Delivered;
+        attempted.response_status = Some(204);
+        attempted.delivered_at = Some(Utc::now());
+        storage.update_notification_delivery(&attempted).await.unwrap();
+        let stored = storage.get_notification_delivery(&delivery.id).await.unwrap().unwrap();
+        assert_eq!((stored.status, stored.attempts, stored.response_status), (DeliveryStatus::Delivered, 1, Some(204)));
+        assert_eq!(stored.event_id.as_deref(), Some("alert-1"));
+
+        let scoped = DeliveryQuery { project_id: Some(project.id.clone()), ..Default::default() };
+        assert_eq!(storage.query_notification_deliveries(&scoped).await.unwrap().len(), 1);
+        let failed = DeliveryQuery { status: Some(DeliveryStatus::Failed), ..scoped.clone() };
+        assert!(storage.query_notification_deliveries(&failed).await.unwrap().is_empty());
+        let tests = DeliveryQuery { event: Some("test".to_string()), ..scoped.clone() };
+        assert!(storage.query_notification_deliveries(&tests).await.unwrap().is_empty());
+
+        assert!(!storage.delete_notification_channel("other-project", &channel.id).await.unwrap());
+        assert!(storage.delete_notification_channel(&project.id, &channel.id).await.unwrap());
+        assert!(storage.get_notification_delivery(&delivery.id).await.unwrap().is_none());
+        assert!(storage.delete_project(&project.id).await.unwrap());
+    }
+
+    async fn exercise_analysis_schedules(storage: &dyn Storage) {
+        let project = Project::new("schedules".to_string(), None);
+        storage.insert_project(&project).await.unwrap();
+        let rule = AlertRule::new(
+            project.id.clone(),
+            "Error rate".to_string(),
+            r#"{"type":"rate","per_minute":10,"level":"ERROR"}"#.to_string(),
+        );
+        storage.create_alert_rule(&rule).await.unwrap();
+
+        let mut schedule = AnalysisSchedule::new(project.id.clone(), "Hourly".to_string(), "openrouter".to_string());
+        schedule.cron = Some("0 * * * *".to_string());
+        schedule.trigger_rule_id = Some(rule.id.clone());
+        storage.create_analysis_schedule(&schedule).await.unwrap();
+        let mut disabled = AnalysisSchedule::new(project.id.clone(), "Off".to_string(), "openrouter".to_string());
+        disabled.enabled = false;
+        storage.create_analysis_schedule(&disabled).await.unwrap();
+
+        assert_eq!(storage.list_analysis_schedules(&project.id).await.unwrap().len(), 2);
+        let enabled = storage.list_enabled_analysis_schedules().await.unwrap();
+        assert!(enabled.iter().any(|s| s.id == schedule.id) && !enabled.iter().any(|s| s.id == disabled.id));
+
+        schedule.source = Some("api".to_string());
+        schedule.window_secs = 300;
+        assert!(storage.update_analysis_schedule(&schedule).await.unwrap());
+        let stored = storage.get_analysis_schedule(&schedule.id).await.unwrap().unwrap();
+        assert_eq!((stored.source.as_deref(), stored.window_secs), (Some("api"), 300));
+        assert_eq!(stored.cron.as_deref(), Some("0 * * * *"));
+        schedule.project_id = "other-project".to_string();
+        assert!(!storage.update_analysis_schedule(&schedule).await.unwrap());
+        schedule.project_id = project.id.clone();
+
+        // A realtime analysis records the alert that triggered it
+        let alert = Alert::new(&rule, "api".to_string(), "12 ERROR entries a minute".to_string());
+        storage.create_alert(&alert).await.unwrap();
+        let analysis = Analysis::new(
+            project.id.clone(),
+            None,
+            "realtime".to_string(),
+            "openrouter".to_string(),
+            "ERROR".to_string(),
+        )
+        .with_alert_id(Some(alert.id.clone()));
+        storage.create_analysis(&analysis).await.unwrap();
+        storage.record_analysis_schedule_run(&schedule.id, &analysis.id, Utc::now()).await.unwrap();
+        let stored = storage.get_analysis_schedule(&schedule.id).await.unwrap().unwrap();
+        assert_eq!(stored.last_analysis_id.as_deref(), Some(analysis.id.as_str()));
+        assert!(stored.last_run_at.is_some());
+        let stored = storage.get_analysis(&analysis.id).await.unwrap().unwrap();
+        assert_eq!(stored.alert_id.as_deref(), Some(alert.id.as_str()));
+        let (listed, _) = storage.list_analyses_with_files(&project.id, 10, 0).await.unwrap().remove(0);
+        assert_eq!(listed.alert_id.as_deref(), Some(alert.id.as_str()));
+
+        // Deleting the rule keeps the schedule and the analysis, unlinked
+        assert!(storage.delete_alert_rule(&project.id, &rule.id).await.unwrap());
+        assert!(storage.get_analysis_schedule(&schedule.id).await.unwrap().unwrap().trigger_rule_id.is_none());
+        assert!(storage.get_analysis(&analysis.id).await.unwrap().unwrap().alert_id.is_none());
+
+        assert!(!storage.delete_analysis_schedule("other-project", &schedule.id).await.unwrap());
+        assert!(storage.delete_analysis_schedule(&project.id, &schedule.id).await.unwrap());
+        assert!(storage.get_analysis(&analysis.id).await.unwrap().is_some());
+        assert!(storage.delete_project(&project.id).await.unwrap());
+        assert!(storage.get_analysis_schedule(&disabled.id).await.unwrap().is_none());
+    }
+
+    async fn exercise_streaming_sources(storage: &dyn Storage) {
+        let project = Project::new("streaming-sources".to_string(), None);
+        storage.insert_project(&project).await.unwrap();
+        let now = Utc::now().to_rfc3339();
+        let source = StreamingSource {
+            id: uuid::Uuid::new_v4().to_string(),
+            project_id: project.id.clone(),
+            name: "api".to_string(),
+            source_type: "http".to_string(),
+            config: r#"{"path":"api"}"#.to_string(),
+            parser_config: None,
+            buffer_size: Some(100),
+            batch_timeout_seconds: None,
+            restart_on_error: Some(true),
+            max_restarts: None,
+            ingest_token_hash: Some("digest".to_string()),
+            status: "active".to_string(),
+            created_at: now.clone(),
+            updated_at: now,
+        };
+        storage.create_streaming_source(&source).await.unwrap();
+
+        let stored = storage.get_streaming_source(&project.id, &source.id).await.unwrap().unwrap();
+        assert_eq!((stored.buffer_size, stored.ingest_token_hash.as_deref()), (Some(100), Some("digest")));
+        assert!(storage.get_streaming_source("other-project", &source.id).await.unwrap().is_none());
+        assert_eq!(storage.list_streaming_sources(&project.id).await.unwrap().len(), 1);
+        assert!(storage.list_active_streaming_sources().await.unwrap().iter().any(|s| s.id == source.id));
+
+        assert!(!storage.delete_streaming_source("other-project", &source.id).await.unwrap());
+        assert!(storage.delete_streaming_source(&project.id, &source.id).await.unwrap());
+        assert!(storage.list_streaming_sources(&project.id).await.unwrap().is_empty());
+        assert!(storage.delete_project(&project.id).await.unwrap());
+    }
+
+    async fn exercise_streaming_logs(storage: &dyn Storage) {
+        use chrono::{DurationRound, TimeDelta};
+
+        let project = Project::new("streaming-logs".to_string(), None);
+        storage.insert_project(&project).await.unwrap();
+
+        assert_eq!(storage.get_project_log_retention(&project.id).await.unwrap(), Some(LogRetention::default()));
+        let retention = LogRetention { max_age_days: Some(7), max_size_mb: Some(0) };
+        assert!(storage.set_project_log_retention(&project.id, &retention).await.unwrap());
+        assert_eq!(storage.get_project_log_retention(&project.id).await.unwrap(), Some(retention));
+        assert!(!storage.set_project_log_retention("other-project", &retention).await.unwrap());
+        assert!(storage.get_project_log_retention("other-project").await.unwrap().is_none());
+
+        let now = Utc::now().duration_trunc(TimeDelta::seconds(1)).unwrap();
+        let earlier = now - chrono::Duration::hours(2);
+        let entry = |source: &str, received_at: DateTime<Utc>, severity: Option<i32>, message: &str| StoredLogEntry {
+            seq: 0,
+            id: uuid::Uuid::new_v4().to_string(),
+            project_id: project.id.clone(),
+            source: source.to_string(),
+            partition_start: StoredLogEntry::partition_for(received_at),
+            timestamp: received_at,
+            level: severity.map(|severity| StoredLogEntry::LEVELS[severity as usize].to_string()),
+            severity,
+            message: message.to_string(),
+            line_number: None,
+            metadata: Some(r#"{"host":"web-1"}"#.to_string()),
+            size_bytes: 100,
+        };
+        storage
+            .insert_streaming_logs(&[
+                entry("api", earlier, Some(4), "Connection refused by upstream"),
+                entry("worker", earlier + chrono::Duration::seconds(1), Some(2), "Request served"),
+            ])
+            .await
+            .unwrap();
+        storage
+            .insert_streaming_logs(&[
+                entry("api", now, Some(3), "Slow upstream response"),
+                entry("api", now, None, "plain line"),
+            ])
+            .await
+            .unwrap();
+
+        let query = |text: Option<&str>| StreamingLogQuery {
+            project_id: project.id.clone(),
+            text: text.map(str::to_string),
+            limit: 10,
+            ..Default::default()
+        };
+        let messages = |entries: Vec<StoredLogEntry>| entries.into_iter().map(|entry| entry.message).collect::<Vec<_>>();
+
+        let all = storage.search_streaming_logs(&query(None)).await.unwrap();
+        assert_eq!(all.len(), 4);
+        assert_eq!(all[3].message, "Connection refused by upstream");
+        assert_eq!(all[3].metadata.as_deref(), Some(r#"{"host":"web-1"}"#));
+        assert_eq!(messages(storage.search_streaming_logs(&query(Some("upstream"))).await.unwrap()).len(), 2);
+        assert_eq!(
+            messages(storage.search_streaming_logs(&query(Some("conn REFUS"))).await.unwrap()),
+            ["Connection refused by upstream"]
+        );
+        let errors = StreamingLogQuery { min_severity: Some(3), ..query(None) };
+        assert_eq!(
+            messages(storage.search_streaming_logs(&errors).await.unwrap()),
+            ["Slow upstream response", "Connection refused by upstream"]
+        );
+        let worker = StreamingLogQuery { source: Some("worker".to_string()), ..query(None) };
+        assert_eq!(messages(storage.search_streaming_logs(&worker).await.unwrap()), ["Request served"]);
+        let window = StreamingLogQuery { since: Some(earlier), until: Some(now), ..query(None) };
+        assert_eq!(storage.search_streaming_logs(&window).await.unwrap().len(), 2);
+        let other = StreamingLogQuery { project_id: "other-project".to_string(), ..query(None) };
+        assert!(storage.search_streaming_logs(&other).await.unwrap().is_empty());
+
+        // Pages continue after the last entry of the previous one, even among equal timestamps
+        let first = storage.search_streaming_logs(&StreamingLogQuery { limit: 1, ..query(None) }).await.unwrap();
+        let cursor = LogCursor { timestamp: first[0].timestamp, seq: first[0].seq };
+        let rest = storage
+            .search_streaming_logs(&StreamingLogQuery { before: Some(cursor), ..query(None) })
+            .await
+            .unwrap();
+        assert_eq!(rest.len(), 3);
+        assert!(rest.iter().all(|entry| entry.seq != first[0].seq));
+
+        let minute = |time: DateTime<Utc>| time.duration_trunc(TimeDelta::minutes(1)).unwrap();
+        let counts = storage
+            .count_streaming_logs_by_minute(&project.id, None, minute(earlier), now + chrono::Duration::minutes(1))
+            .await
+            .unwrap();
+        assert_eq!(counts.iter().map(|count| count.entries).sum::<i64>(), 4);
+        assert!(counts.contains(&LogCount { minute: minute(now), level: "UNKNOWN".to_string(), entries: 1 }));
+        let api_counts = storage
+            .count_streaming_logs_by_minute(&project.id, Some("api"), minute(earlier), now + chrono::Duration::minutes(1))
+            .await
+            .unwrap();
+        assert_eq!(api_counts.iter().map(|count| count.entries).sum::<i64>(), 3);
+
+        let partitions: Vec<_> = storage
+            .list_streaming_log_partitions()
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|partition| partition.project_id == project.id)
+            .collect();
+        assert_eq!(partitions.len(), 2);
+        assert_eq!(partitions[0].partition_start, StoredLogEntry::partition_for(earlier));
+        assert_eq!((partitions[0].entries, partitions[0].size_bytes), (2, 200));
+
+        // Dropping a partition removes its entries from search, including text search
+        assert_eq!(storage.drop_streaming_log_partition(&project.id, partitions[0].partition_start).await.unwrap(), 2);
+        assert_eq!(storage.search_streaming_logs(&query(None)).await.unwrap().len(), 2);
+        assert!(storage.search_streaming_logs(&query(Some("refused"))).await.unwrap().is_empty());
+        assert!(storage.delete_streaming_log_counts_before(&project.id, minute(now)).await.unwrap() >= 2);
+        let counts = storage
+            .count_streaming_logs_by_minute(&project.id, None, minute(earlier), now + chrono::Duration::minutes(1))
+            .await
+            .unwrap();
+        assert_eq!(counts.iter().map(|count| count.entries).sum::<i64>(), 2);
+
+        assert!(storage.delete_project(&project.id).await.unwrap());
+        assert!(!storage
+            .list_streaming_log_partitions()
+            .await
+            .unwrap()
+            .iter()
+            .any(|partition| partition.project_id == project.id));
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_storage() {
+        let temp_dir = TempDir::new().unwrap();
+        let url = format!("sqlite://{}", temp_dir.path().join("storage.db").display());
+
+        let storage = connect_storage(&url, 5).await.unwrap();
+        assert_eq!(storage.backend(), StorageBackend::Sqlite);
+        assert!(storage.sqlite_pool().is_some());
+        storage.ping().await.unwrap();
+
+        exercise_storage(storage.as_ref()).await;
+    }
+
+    /// Runs against the database in `SYNAPSE_TEST_POSTGRES_URL`, e.g. a local container:
+    /// `docker run -e POSTGRES_PASSWORD=synapse -p 5432:5432 postgres:16`
+    #[cfg(feature = "postgres")]
+    #[tokio::test]
+    async fn test_postgres_storage() {
+        let Ok(url) = std::env::var("SYNAPSE_TEST_POSTGRES_URL") else {
+            eprintln!("SYNAPSE_TEST_POSTGRES_URL not set, skipping PostgreSQL storage test");
+            return;
+        };
+
+        let storage = connect_storage(&url, 5).await.unwrap();
+        assert_eq!(storage.backend(), StorageBackend::Postgres);
+        assert!(storage.sqlite_pool().is_none());
+        storage.ping().await.unwrap();
+
+        exercise_storage(storage.as_ref()).await;
+    }
+}
@@ -0,0 +1,523 @@
+//! Encryption of provider API keys at rest
+//!
+//! Secrets are sealed with AES-256-GCM under a master key. The master key comes from
+//! `SYNAPSE_MASTER_KEY` (base64, 32 bytes) or, when that is unset, from a key file in the
+//! data directory that is created with owner-only permissions on first use. Every sealed
+//! value records the id of the key that sealed it, so keys can be rotated: the new key
+//! seals, and previous keys stay available to open values until they are re-encrypted.
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Base64-encoded master key; takes precedence over the key file
+pub const MASTER_KEY_ENV: &str = "SYNAPSE_MASTER_KEY";
+/// Comma-separated base64 keys that were replaced, still used to open older secrets
+pub const PREVIOUS_MASTER_KEYS_ENV: &str = "SYNAPSE_PREVIOUS_MASTER_KEYS";
+/// Overrides the location of the master key file
+pub const MASTER_KEY_FILE_ENV: &str = "SYNAPSE_MASTER_KEY_FILE";
+
+const MASTER_KEY_FILE: &str = "master.key";
+const MASTER_KEY_LEN: usize = 32;
+/// Prefix of secrets sealed into a single string, as stored in config files
+const SEALED_PREFIX: &str = "enc:v1:";
+const MASK: &str = "****";
+
+/// A 256-bit key used to seal secrets
+#[derive(Clone)]
+pub struct MasterKey {
+    id: String,
+    bytes: [u8; MASTER_KEY_LEN],
+}
+
+impl MasterKey {
+    /// Generate a new random key
+    pub fn generate() -> Result<Self> {
+        let mut bytes = [0u8; MASTER_KEY_LEN];
+        SystemRandom::new()
+            .fill(&mut bytes)
+            .map_err(|_| anyhow!("Failed to generate a master key"))?;
+        Ok(Self::from_bytes(bytes))
+    }
+
+    /// Parse a base64-encoded key
+    pub fn parse(encoded: &str) -> Result<Self> {
+        let decoded = STANDARD
+            .decode(encoded.trim())
+            .context("Master key is not valid base64")?;
+        let bytes: [u8; MASTER_KEY_LEN] = decoded
+            .try_into()
+            .map_err(|_| anyhow!("Master key must be {} bytes", MASTER_KEY_LEN))?;
+        Ok(Self::from_bytes(bytes))
+    }
+
+    fn from_bytes(bytes: [u8; MASTER_KEY_LEN]) -> Self {
+        // The id is a fingerprint, so it can be stored next to ciphertexts without revealing the key
+        let digest = ring::digest::digest(&ring::digest::SHA256, &bytes);
+        let id = digest.as_ref()[..6].iter().map(|b| format!("{:02x}", b)).collect();
+        Self { id, bytes }
+    }
+
+    /// Fingerprint identifying the key in sealed secrets
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The key in the base64 form accepted by [`MasterKey::parse`]
+    pub fn encode(&self) -> String {
+        STANDARD.encode(self.bytes)
+    }
+
+    fn aead_key(&self) -> LessSafeKey {
+        LessSafeKey::new(
+            UnboundKey::new(&AES_256_GCM, &self.bytes).expect("master key has the AES-256 key length"),
+        )
+    }
+}
+
+impl std::fmt::Debug for MasterKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MasterKey").field("id", &self.id).finish_non_exhaustive()
+    }
+}
+
+/// Where the master keys were loaded from, which decides how they can be rotated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeySource {
+    Environment,
+    File(PathBuf),
+    Memory,
+}
+
+/// A secret encrypted under a master key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SealedSecret {
+    pub key_id: String,
+    pub nonce: String,      // base64
+    pub ciphertext: String, // base64, including the GCM tag
+}
+
+/// The current master key plus the keys it replaced
+#[derive(Debug, Clone)]
+pub struct Keyring {
+    current: MasterKey,
+    previous: Vec<MasterKey>,
+    source: KeySource,
+}
+
+impl Keyring {
+    /// Keys held only in memory, for tests and one-off tools
+    pub fn new(current: MasterKey, previous: Vec<MasterKey>) -> Self {
+        Self { current, previous, source: KeySource::Memory }
+    }
+
+    /// Load the master keys from the environment, falling back to the key file
+    pub fn load() -> Result<Self> {
+        if let Ok(encoded) = env::var(MASTER_KEY_ENV) {
+            let current = MasterKey::parse(&encoded)
+                .with_context(|| format!("Invalid {}", MASTER_KEY_ENV))?;
+            let previous = env::var(PREVIOUS_MASTER_KEYS_ENV)
+                .unwrap_or_default()
+                .split(',')
+                .filter(|key| !key.trim().is_empty())
+                .map(MasterKey::parse)
+                .collect::<Result<Vec<_>>>()
+                .with_context(|| format!("Invalid {}", PREVIOUS_MASTER_KEYS_ENV))?;
+            return Ok(Self { current, previous, source: KeySource::Environment });
+        }
+
+        let path = env::var(MASTER_KEY_FILE_ENV)
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| crate::db_path::get_data_dir().join(MASTER_KEY_FILE));
+        Self::load_file(&path)
+    }
+
+    /// Load keys from a key file, creating it with a new key if it does not exist
+    ///
+    /// The file holds one base64 key per line, the current key first.
+    pub fn load_file(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            let keyring = Self {
+                current: MasterKey::generate()?,
+                previous: Vec::new(),
+                source: KeySource::File(path.to_path_buf()),
+            };
+            keyring.write_file(path)?;
+            tracing::info!("Created master key file {}", path.display());
+            return Ok(keyring);
+        }
+
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read master key file {}", path.display()))?;
+        let mut keys = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(MasterKey::parse);
+        let current = keys
+            .next()
+            .ok_or_else(|| anyhow!("Master key file {} is empty", path.display()))??;
+        let previous = keys.collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { current, previous, source: KeySource::File(path.to_path_buf()) })
+    }
+
+    fn write_file(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut content = String::from("# Synapse master keys, current key first. Keep this file private.\n");
+        for key in std::iter::once(&self.current).chain(&self.previous) {
+            content.push_str(&key.encode());
+            content.push('\n');
+        }
+
+        // Write next to the target and rename, so a crash never leaves a truncated key file
+        let tmp_path = path.with_extension("tmp");
+        write_private(&tmp_path, &content)?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Id of the key that seals new secrets
+    pub fn key_id(&self) -> &str {
+        self.current.id()
+    }
+
+    pub fn source(&self) -> &KeySource {
+        &self.source
+    }
+
+    /// Number of replaced keys still available for opening secrets
+    pub fn previous_key_count(&self) -> usize {
+        self.previous.len()
+    }
+
+    /// Replace the current key with a new one, keeping the old key for opening secrets
+    ///
+    /// Only file-backed keyrings rotate themselves; with `SYNAPSE_MASTER_KEY` the operator
+    /// sets the new key and lists the old one in `SYNAPSE_PREVIOUS_MASTER_KEYS` instead.
+    pub fn rotate(&mut self) -> Result<()> {
+        let path = match &self.source {
+            KeySource::File(path) => Some(path.clone()),
+            KeySource::Memory => None,
+            KeySource::Environment => bail!(
+                "The master key comes from {}; set a new key there and move the old one to {}",
+                MASTER_KEY_ENV,
+                PREVIOUS_MASTER_KEYS_ENV
+            ),
+        };
+
+        let new_key = MasterKey::generate()?;
+        let old_key = std::mem::replace(&mut self.current, new_key);
+        self.previous.insert(0, old_key);
+
+        if let Some(path) = path {
+            self.write_file(&path)?;
+        }
+        Ok(())
+    }
+
+    /// Whether a secret was sealed under an older key and should be re-encrypted
+    pub fn needs_reseal(&self, sealed: &SealedSecret) -> bool {
+        sealed.key_id != self.current.id
+    }
+
+    /// [`Keyring::needs_reseal`] for a string produced by [`Keyring::seal_to_string`]
+    pub fn needs_reseal_string(&self, value: &str) -> bool {
+        value
+            .strip_prefix(SEALED_PREFIX)
+            .and_then(|sealed| sealed.split(':').next())
+            .is_some_and(|key_id| key_id != self.current.id)
+    }
+
+    /// Encrypt a secret, binding it to `context` (e.g. the provider name)
+    pub fn seal(&self, context: &str, plaintext: &str) -> Result<SealedSecret> {
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| anyhow!("Failed to generate a nonce"))?;
+
+        let mut in_out = plaintext.as_bytes().to_vec();
+        self.current
+            .aead_key()
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(context.as_bytes()),
+                &mut in_out,
+            )
+            .map_err(|_| anyhow!("Failed to encrypt secret"))?;
+
+        Ok(SealedSecret {
+            key_id: self.current.id.clone(),
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(in_out),
+        })
+    }
+
+    /// Decrypt a secret sealed under the current or a previous key
+    pub fn open(&self, context: &str, sealed: &SealedSecret) -> Result<String> {
+        let key = std::iter::once(&self.current)
+            .chain(&self.previous)
+            .find(|key| key.id == sealed.key_id)
+            .ok_or_else(|| anyhow!("Secret was sealed with unknown master key {}", sealed.key_id))?;
+
+        let nonce: [u8; NONCE_LEN] = STANDARD
+            .decode(&sealed.nonce)?
+            .try_into()
+            .map_err(|_| anyhow!("Invalid secret nonce"))?;
+        let mut in_out = STANDARD.decode(&sealed.ciphertext)?;
+        let plaintext = key
+            .aead_key()
+            .open_in_place(Nonce::assume_unique_for_key(nonce), Aad::from(context.as_bytes()), &mut in_out)
+            .map_err(|_| anyhow!("Failed to decrypt secret; it was altered or sealed for something else"))?;
+
+        Ok(String::from_utf8(plaintext.to_vec())?)
+    }
+
+    /// Seal a secret into one string, for storage in text files
+    pub fn seal_to_string(&self, context: &str, plaintext: &str) -> Result<String> {
+        let sealed = self.seal(context, plaintext)?;
+        Ok(format!("{}{}:{}:{}", SEALED_PREFIX, sealed.key_id, sealed.nonce, sealed.ciphertext))
+    }
+
+    /// Open a string produced by [`Keyring::seal_to_string`]
+    pub fn open_string(&self, context: &str, value: &str) -> Result<String> {
+        let parts: Vec<&str> = value
+            .strip_prefix(SEALED_PREFIX)
+            .ok_or_else(|| anyhow!("Value is not a sealed secret"))?
+            .split(':')
+            .collect();
+        let [key_id, nonce, ciphertext] = parts[..] else {
+            bail!("Malformed sealed secret");
+        };
+
+        self.open(
+            context,
+            &SealedSecret {
+                key_id: key_id.to_string(),
+                nonce: nonce.to_string(),
+                ciphertext: ciphertext.to_string(),
+            },
+        )
+    }
+}
+
+/// Whether a string was produced by [`Keyring::seal_to_string`]
+pub fn is_sealed_string(value: &str) -> bool {
+    value.starts_with(SEALED_PREFIX)
+}
+
+/// Hide all but the last four characters of a secret
+pub fn mask(secret: &str) -> String {
+    let chars: Vec<char> = secret.chars().collect();
+    // Short keys would be mostly revealed by their suffix
+    if chars.len() < 12 {
+        return MASK.to_string();
+    }
+    let suffix: String = chars[chars.len() - 4..].iter().collect();
+    format!("{}{}", MASK, suffix)
+}
+
+/// Whether a value is a masked secret echoed back by a client rather than a new key
+pub fn is_masked(value: &str) -> bool {
+    value.starts_with(MASK)
+}
+
+fn write_private(path: &Path, content: &str) -> Result<()> {
+    use std::io::Write;
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Failed to write master key file {}", path.display()))?;
+    file.write_all(content.as_bytes())?;
+    file.sync_all()?;
+    Ok(())
+}
+
+#[cfg(feature = "project-management")]
+mod store {
+    use super::{mask, Keyring};
+    use crate::project::{ProviderSecret, Storage};
+    use anyhow::Result;
+    use chrono::Utc;
+
+    /// Encrypt and store the API key for a provider, replacing any previous key
+    pub async fn store_api_key(
+        storage: &dyn Storage,
+        keyring: &Keyring,
+        provider: &str,
+        api_key: &str,
+    ) -> Result<ProviderSecret> {
+        let sealed = keyring.seal(provider, api_key)?;
+        let now = Utc::now();
+        let secret = ProviderSecret {
+            provider: provider.to_string(),
+            key_id: sealed.key_id,
+            nonce: sealed.nonce,
+            ciphertext: sealed.ciphertext,
+            hint: mask(api_key),
+            created_at: now,
+            updated_at: now,
+        };
+        storage.set_provider_secret(&secret).await?;
+        Ok(secret)
+    }
+
+    /// Decrypt the stored API key for a provider
+    pub async fn load_api_key(
+        storage: &dyn Storage,
+        keyring: &Keyring,
+        provider: &str,
+    ) -> Result<Option<String>> {
+        match storage.get_provider_secret(provider).await? {
+            Some(secret) => Ok(Some(keyring.open(provider, &secret.sealed())?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Re-encrypt every stored key that was sealed under an older master key
+    pub async fn reseal_api_keys(storage: &dyn Storage, keyring: &Keyring) -> Result<usize> {
+        let mut resealed = 0;
+        for secret in storage.list_provider_secrets().await? {
+            if !keyring.needs_reseal(&secret.sealed()) {
+                continue;
+            }
+            let api_key = keyring.open(&secret.provider, &secret.sealed())?;
+            let sealed = keyring.seal(&secret.provider, &api_key)?;
+            storage
+                .set_provider_secret(&ProviderSecret {
+                    key_id: sealed.key_id,
+                    nonce: sealed.nonce,
+                    ciphertext: sealed.ciphertext,
+                    updated_at: Utc::now(),
+                    ..secret
+                })
+                .await?;
+            resealed += 1;
+        }
+        Ok(resealed)
+    }
+
+    /// Move a plaintext key left in the settings row into the encrypted store
+    ///
+    /// Older releases kept one `api_key` in settings for whichever provider was selected.
+    /// It is stored for that provider, unless the provider already has a key, and then
+    /// cleared from settings. Returns whether a key was moved.
+    pub async fn migrate_settings_api_key(storage: &dyn Storage, keyring: &Keyring) -> Result<bool> {
+        let Some(settings) = storage.get_settings().await? else {
+            return Ok(false);
+        };
+        if settings.api_key.is_empty() {
+            return Ok(false);
+        }
+
+        let provider = settings.default_provider.as_str();
+        if storage.get_provider_secret(provider).await?.is_none() {
+            store_api_key(storage, keyring, provider, &settings.api_key).await?;
+        }
+        // update_settings never writes the key back
+        storage.update_settings(&settings).await?;
+        Ok(true)
+    }
+}
+
+#[cfg(feature = "project-management")]
+pub use store::{load_api_key, migrate_settings_api_key, reseal_api_keys, store_api_key};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_open() {
+        let keyring = Keyring::new(MasterKey::generate().unwrap(), Vec::new());
+        let sealed = keyring.seal("openai", "sk-test-1234567890").unwrap();
+        assert_eq!(sealed.key_id, keyring.key_id());
+        assert!(!sealed.ciphertext.contains("sk-test"));
+        assert_eq!(keyring.open("openai", &sealed).unwrap(), "sk-test-1234567890");
+
+        // The context is authenticated, so a key can't be moved to another provider
+        assert!(keyring.open("gemini", &sealed).is_err());
+
+        let other = Keyring::new(MasterKey::generate().unwrap(), Vec::new());
+        assert!(other.open("openai", &sealed).is_err());
+    }
+
+    #[test]
+    fn test_sealed_string_round_trip() {
+        let keyring = Keyring::new(MasterKey::generate().unwrap(), Vec::new());
+        let value = keyring.seal_to_string("claude", "secret-value").unwrap();
+        assert!(is_sealed_string(&value));
+        assert_eq!(keyring.open_string("claude", &value).unwrap(), "secret-value");
+        assert!(keyring.open_string("claude", "enc:v1:broken").is_err());
+    }
+
+    #[test]
+    fn test_rotation_keeps_previous_keys() {
+        let mut keyring = Keyring::new(MasterKey::generate().unwrap(), Vec::new());
+        let old = keyring.seal("openai", "sk-old").unwrap();
+        let old_string = keyring.seal_to_string("openai", "sk-old").unwrap();
+        assert!(!keyring.needs_reseal_string(&old_string));
+        let old_id = keyring.key_id().to_string();
+
+        keyring.rotate().unwrap();
+        assert_ne!(keyring.key_id(), old_id);
+        assert_eq!(keyring.previous_key_count(), 1);
+        assert!(keyring.needs_reseal(&old));
+        assert!(keyring.needs_reseal_string(&old_string));
+        assert_eq!(keyring.open("openai", &old).unwrap(), "sk-old");
+        assert!(!keyring.needs_reseal(&keyring.seal("openai", "sk-new").unwrap()));
+    }
+
+    #[test]
+    fn test_key_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("keys").join(MASTER_KEY_FILE);
+
+        let mut created = Keyring::load_file(&path).unwrap();
+        let sealed = created.seal("openai", "sk-file").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let loaded = Keyring::load_file(&path).unwrap();
+        assert_eq!(loaded.key_id(), created.key_id());
+
+        created.rotate().unwrap();
+        let reloaded = Keyring::load_file(&path).unwrap();
+        assert_eq!(reloaded.key_id(), created.key_id());
+        assert_eq!(reloaded.open("openai", &sealed).unwrap(), "sk-file");
+    }
+
+    #[test]
+    fn test_parse_rejects_wrong_length() {
+        assert!(MasterKey::parse("c2hvcnQ=").is_err());
+        let key = MasterKey::generate().unwrap();
+        assert_eq!(MasterKey::parse(&key.encode()).unwrap().id(), key.id());
+    }
+
+    #[test]
+    fn test_mask() {
+        assert_eq!(mask("sk-or-v1-abcdef123456"), "****3456");
+        assert_eq!(mask("short"), "****");
+        assert!(is_masked(&mask("sk-or-v1-abcdef123456")));
+        assert!(!is_masked("sk-or-v1-abcdef123456"));
+    }
+}
@@ -330,7 +330,9 @@ fn slim_message(message: &str, mode: SlimmingMode) -> String {
     slimmed.trim().to_string()
 }
 
-fn extract_pattern(message: &str) -> String {
+/// The template of a message: timestamps, UUIDs, paths, IP addresses and numbers
+/// replaced with placeholders, so repeats of the same event compare equal
+pub fn extract_pattern(message: &str) -> String {
     // Extract pattern by replacing variable parts with placeholders
     // Ensure the message is valid UTF-8 first
     let sanitized_message = sanitize_string_for_utf8(message);
@@ -1,6 +1,9 @@
-use sqlx::{SqlitePool, Row};
+use std::sync::Arc;
+
+use synapse_core::project::{connect_storage, Storage};
 
 pub mod tools;
+pub mod sampling;
 pub mod server;
 pub mod transport;
 pub mod schema;
@@ -9,74 +12,28 @@ pub mod validation;
 /// Database wrapper for MCP server
 #[derive(Clone)]
 pub struct Database {
-    pub pool: SqlitePool,
+    pub storage: Arc<dyn Storage>,
 }
 
 impl Database {
+    /// Open the storage backend selected by `database_url`
+    ///
+    /// SQLite paths and `sqlite:` URLs are created if missing; `postgres://` URLs
+    /// connect to a shared server. Either way the versioned migrations are applied,
+    /// upgrading legacy SQLite databases.
     pub async fn new(database_url: &str) -> anyhow::Result<Self> {
-        use sqlx::sqlite::SqliteConnectOptions;
-        use std::str::FromStr;
-
         tracing::debug!("Creating database with URL: {}", database_url);
 
-        // Parse database URL
-        let opts = SqliteConnectOptions::from_str(database_url)?
-            .create_if_missing(true);
-
-        // Create connection pool
-        let pool = SqlitePool::connect_with(opts).await?;
+        let storage = connect_storage(database_url, 5)
+            .await
+            .map_err(|e| anyhow::anyhow!("Database initialization failed: {:#}", e))?;
+        tracing::info!("Database migrations completed successfully");
 
-        // Try to run migrations with better error handling
-        tracing::debug!("Running database migrations...");
-        match sqlx::migrate!("../synapse-web/migrations")
-            .run(&pool)
-            .await 
-        {
-            Ok(_) => {
-                tracing::info!("Database migrations completed successfully");
-            }
-            Err(e) => {
-                let error_msg = e.to_string();
-                tracing::error!("Migration error: {}", error_msg);
-                
-                // If it's a table already exists error, check if we can continue
-                if error_msg.contains("already exists") {
-                    tracing::warn!("Database tables already exist. This is likely okay if schema is compatible.");
-                    
-                    // Verify essential tables exist
-                    let table_count_result = sqlx::query(
-                        "SELECT COUNT(*) as count FROM sqlite_master WHERE type='table' AND name IN ('projects', 'analyses', 'log_files')"
-                    )
-                    .fetch_one(&pool)
-                    .await;
-                    
-                    let tables_exist = match table_count_result {
-                        Ok(row) => {
-                            let count = row.get::<i64, _>("count");
-                            tracing::debug!("Table count query result: {}", count);
-                            count
-                        },
-                        Err(e) => {
-                            tracing::error!("Failed to check table existence: {}", e);
-                            return Err(anyhow::anyhow!("Failed to verify database schema: {}", e));
-                        }
-                    };
-                    
-                    tracing::debug!("Found {} essential tables", tables_exist);
-                    tracing::info!("Expected 3 essential tables (projects, analyses, log_files), found {}", tables_exist);
-                    
-                    if tables_exist >= 3 {
-                        tracing::info!("Essential database tables exist, proceeding...");
-                    } else {
-                        return Err(anyhow::anyhow!("Database is in inconsistent state: expected 3 essential tables, found {}", tables_exist));
-                    }
-                } else {
-                    return Err(anyhow::anyhow!("Database migration failed: {}", e));
-                }
-            }
-        }
+        Ok(Self { storage })
+    }
 
-        Ok(Self { pool })
+    pub fn storage(&self) -> &dyn Storage {
+        self.storage.as_ref()
     }
 }
 
@@ -0,0 +1,184 @@
+use rmcp::{
+    model::{
+        Content, ContextInclusion, CreateMessageRequestParam, ModelHint, ModelPreferences,
+        Role, SamplingMessage,
+    },
+    service::{Peer, RoleServer},
+};
+use synapse_core::ai_provider::prompts::SystemPromptGenerator;
+use synapse_core::{AIError, AIProvider, AnalysisRequest, AnalysisResponse};
+use synapse_core::ai_provider::ModelInfo;
+
+/// Provider name recorded on analyses that ran through MCP sampling
+pub const SAMPLING_PROVIDER_NAME: &str = "sampling";
+
+/// Default token budget for a sampled analysis response
+const DEFAULT_MAX_TOKENS: u32 = 4000;
+
+/// AI provider that asks the connected MCP client's model to run the analysis
+///
+/// Uses `sampling/createMessage` with the same prompts as the HTTP providers, so
+/// analyses need no Synapse-side API key when the client supports sampling.
+pub struct SamplingProvider {
+    peer: Peer<RoleServer>,
+    model_hint: Option<String>,
+    max_tokens: u32,
+}
+
+impl SamplingProvider {
+    pub fn new(peer: Peer<RoleServer>) -> Self {
+        Self {
+            peer,
+            model_hint: None,
+            max_tokens: DEFAULT_MAX_TOKENS,
+        }
+    }
+
+    /// Suggest a model name or family to the client (it may ignore the hint)
+    pub fn with_model_hint(mut self, model: String) -> Self {
+        self.model_hint = Some(model);
+        self
+    }
+
+    pub fn with_max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+}
+
+/// Check whether the connected client advertised the sampling capability
+pub fn client_supports_sampling(peer: &Peer<RoleServer>) -> bool {
+    peer.peer_info()
+        .map(|info| info.capabilities.sampling.is_some())
+        .unwrap_or(false)
+}
+
+/// Parse a sampled model response into an analysis
+///
+/// Client models are not constrained to JSON output, so surrounding prose and
+/// Markdown code fences are stripped before parsing.
+pub fn parse_sampled_analysis(content: &str) -> Result<AnalysisResponse, AIError> {
+    let trimmed = content.trim();
+    let json = match (trimmed.find('{'), trimmed.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &trimmed[start..=end],
+        _ => trimmed,
+    };
+
+    serde_json::from_str(json).map_err(|e| {
+        AIError::InvalidResponse(format!(
+            "Failed to parse sampled response as JSON: {}. Content: {}",
+            e, content
+        ))
+    })
+}
+
+#[async_trait::async_trait]
+impl AIProvider for SamplingProvider {
+    async fn analyze(&self, request: AnalysisRequest) -> Result<AnalysisResponse, AIError> {
+        // Generate system prompt based on analysis focus
+        let system_prompt = SystemPromptGenerator::generate_system_prompt(
+            &request.payload,
+            request.user_context.as_deref(),
+            &request.analysis_focus,
+        );
+
+        // Generate user prompt with log analysis
+        let user_prompt = SystemPromptGenerator::create_analysis_prompt(&request.payload);
+
+        let params = CreateMessageRequestParam {
+            messages: vec![SamplingMessage {
+                role: Role::User,
+                content: Content::text(user_prompt),
+            }],
+            model_preferences: Some(ModelPreferences {
+                hints: self.model_hint.as_ref().map(|name| vec![ModelHint { name: Some(name.clone()) }]),
+                cost_priority: None,
+                speed_priority: None,
+                intelligence_priority: Some(0.8),
+            }),
+            system_prompt: Some(system_prompt),
+            include_context: Some(ContextInclusion::None),
+            temperature: Some(0.1), // Low temperature for factual responses
+            max_tokens: self.max_tokens,
+            stop_sequences: None,
+            metadata: None,
+        };
+
+        let result = self.peer.create_message(params).await
+            .map_err(|e| AIError::InvalidResponse(format!("Sampling request failed: {}", e)))?;
+
+        let text = result.message.content
+            .as_text()
+            .map(|t| t.text.clone())
+            .ok_or_else(|| AIError::InvalidResponse(
+                "Sampled response contained no text".to_string(),
+            ))?;
+
+        parse_sampled_analysis(&text)
+    }
+
+    async fn get_available_models(&self) -> Result<Vec<ModelInfo>, AIError> {
+        // The client chooses the model; expose a single placeholder entry
+        Ok(vec![ModelInfo {
+            id: SAMPLING_PROVIDER_NAME.to_string(),
+            name: "MCP client model".to_string(),
+            description: Some("Model selected by the connected MCP client via sampling".to_string()),
+            context_length: None,
+            pricing_tier: None,
+            capabilities: vec!["chat".to_string(), "analysis".to_string()],
+            supports_streaming: false,
+            provider: SAMPLING_PROVIDER_NAME.to_string(),
+        }])
+    }
+
+    fn get_provider_name(&self) -> &str {
+        SAMPLING_PROVIDER_NAME
+    }
+}
+
+/// Where the AI analysis for a tool call runs
+#[derive(Clone)]
+pub enum AnalysisBackend {
+    /// A Synapse provider using Synapse's own API key
+    Provider(String),
+    /// The connected MCP client's model through sampling
+    Sampling(Peer<RoleServer>),
+}
+
+impl AnalysisBackend {
+    /// Provider name recorded on the analysis row
+    pub fn name(&self) -> &str {
+        match self {
+            AnalysisBackend::Provider(name) => name,
+            AnalysisBackend::Sampling(_) => SAMPLING_PROVIDER_NAME,
+        }
+    }
+}
+
+/// Decide whether an analysis runs on a Synapse provider or through client sampling
+///
+/// `"sampling"` requires a client with the sampling capability. Any other provider
+/// falls back to sampling when Synapse has no API key for it but the client can sample.
+pub fn resolve_backend(provider: &str, peer: Option<&Peer<RoleServer>>) -> anyhow::Result<AnalysisBackend> {
+    let sampling_peer = peer.filter(|p| client_supports_sampling(p));
+
+    if provider == SAMPLING_PROVIDER_NAME {
+        return sampling_peer
+            .cloned()
+            .map(AnalysisBackend::Sampling)
+            .ok_or_else(|| anyhow::anyhow!("The connected MCP client does not support sampling"));
+    }
+
+    if let Some(peer) = sampling_peer {
+        let has_api_key = synapse_core::Config::load()
+            .ok()
+            .and_then(|config| config.get_api_key(provider))
+            .is_some();
+
+        if !has_api_key {
+            return Ok(AnalysisBackend::Sampling(peer.clone()));
+        }
+    }
+
+    Ok(AnalysisBackend::Provider(provider.to_string()))
+}
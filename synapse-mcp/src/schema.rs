@@ -89,11 +89,155 @@ pub fn analyze_file_schema() -> Map<String, Value> {
     properties.insert("file_id".to_string(), json!({"type": "string"}));
     properties.insert("provider".to_string(), json!({
         "type": "string",
-        "enum": ["openrouter", "openai", "claude", "gemini"],
+        "enum": ["openrouter", "openai", "claude", "gemini", "sampling"],
         "default": "openrouter"
     }));
     
     schema.insert("properties".to_string(), Value::Object(properties));
     schema.insert("required".to_string(), json!(["project_id", "file_id"]));
     schema
-}
\ No newline at end of file
+}
+
+/// JSON schema for list_project_logs tool
+pub fn list_project_logs_schema() -> Map<String, Value> {
+    let mut schema = Map::new();
+    schema.insert("type".to_string(), json!("object"));
+    
+    let mut properties = Map::new();
+    properties.insert("project_id".to_string(), json!({"type": "string"}));
+    
+    schema.insert("properties".to_string(), Value::Object(properties));
+    schema.insert("required".to_string(), json!(["project_id"]));
+    schema
+}
+
+/// JSON schema for analyze_project_log tool
+pub fn analyze_project_log_schema() -> Map<String, Value> {
+    let mut schema = Map::new();
+    schema.insert("type".to_string(), json!("object"));
+    
+    let mut properties = Map::new();
+    properties.insert("project_id".to_string(), json!({"type": "string"}));
+    properties.insert("path".to_string(), json!({
+        "type": "string",
+        "description": "Log file path, relative to the project root"
+    }));
+    properties.insert("provider".to_string(), json!({
+        "type": "string",
+        "enum": ["openrouter", "openai", "claude", "gemini", "sampling"]
+    }));
+    properties.insert("level".to_string(), json!({
+        "type": "string",
+        "enum": ["ERROR", "WARN", "INFO", "DEBUG"]
+    }));
+    
+    schema.insert("properties".to_string(), Value::Object(properties));
+    schema.insert("required".to_string(), json!(["project_id", "path"]));
+    schema
+}
+
+/// JSON schema for analyze_command tool
+pub fn analyze_command_schema() -> Map<String, Value> {
+    let mut schema = Map::new();
+    schema.insert("type".to_string(), json!("object"));
+    
+    let mut properties = Map::new();
+    properties.insert("project_id".to_string(), json!({"type": "string"}));
+    properties.insert("command".to_string(), json!({
+        "type": "string",
+        "description": "Command whose output to analyze; must match the project's allowed_commands"
+    }));
+    properties.insert("provider".to_string(), json!({
+        "type": "string",
+        "enum": ["openrouter", "openai", "claude", "gemini", "sampling"]
+    }));
+    properties.insert("level".to_string(), json!({
+        "type": "string",
+        "enum": ["ERROR", "WARN", "INFO", "DEBUG"]
+    }));
+    
+    schema.insert("properties".to_string(), Value::Object(properties));
+    schema.insert("required".to_string(), json!(["project_id", "command"]));
+    schema
+}
+
+/// JSON schema for search_knowledge tool
+pub fn search_knowledge_schema() -> Map<String, Value> {
+    let mut schema = Map::new();
+    schema.insert("type".to_string(), json!("object"));
+    
+    let mut properties = Map::new();
+    properties.insert("query".to_string(), json!({
+        "type": "string",
+        "description": "Text to search for in titles, problem descriptions, solutions and tags"
+    }));
+    properties.insert("project_id".to_string(), json!({
+        "type": "string",
+        "description": "Project whose entries to search; without one, only public entries are searched"
+    }));
+    properties.insert("include_public".to_string(), json!({
+        "type": "boolean",
+        "default": true,
+        "description": "Also return public entries shared by other projects"
+    }));
+    properties.insert("limit".to_string(), json!({
+        "type": "integer",
+        "default": 20,
+        "maximum": 100,
+        "minimum": 1
+    }));
+    
+    schema.insert("properties".to_string(), Value::Object(properties));
+    schema.insert("required".to_string(), json!(["query"]));
+    schema
+}
+
+/// JSON schema for match_error_patterns tool
+pub fn match_error_patterns_schema() -> Map<String, Value> {
+    let mut schema = Map::new();
+    schema.insert("type".to_string(), json!("object"));
+    
+    let mut properties = Map::new();
+    properties.insert("project_id".to_string(), json!({"type": "string"}));
+    properties.insert("error_message".to_string(), json!({"type": "string"}));
+    properties.insert("error_messages".to_string(), json!({
+        "type": "array",
+        "items": {"type": "string"}
+    }));
+    
+    schema.insert("properties".to_string(), Value::Object(properties));
+    schema.insert("required".to_string(), json!(["project_id"]));
+    schema
+}
+
+/// JSON schema for create_knowledge_entry tool
+pub fn create_knowledge_entry_schema() -> Map<String, Value> {
+    let mut schema = Map::new();
+    schema.insert("type".to_string(), json!("object"));
+    
+    let mut properties = Map::new();
+    properties.insert("project_id".to_string(), json!({"type": "string"}));
+    properties.insert("analysis_id".to_string(), json!({
+        "type": "string",
+        "description": "Completed analysis to derive title, problem and solution from"
+    }));
+    properties.insert("title".to_string(), json!({"type": "string"}));
+    properties.insert("problem_description".to_string(), json!({"type": "string"}));
+    properties.insert("solution".to_string(), json!({"type": "string"}));
+    properties.insert("tags".to_string(), json!({
+        "type": "array",
+        "items": {"type": "string"}
+    }));
+    properties.insert("severity".to_string(), json!({
+        "type": "string",
+        "enum": ["low", "medium", "high", "critical"],
+        "default": "medium"
+    }));
+    properties.insert("is_public".to_string(), json!({
+        "type": "boolean",
+        "default": false
+    }));
+    
+    schema.insert("properties".to_string(), Value::Object(properties));
+    schema
+}
@@ -7,7 +7,11 @@ use rmcp::{
 };
 use std::sync::Arc;
 use crate::{Database, Config};
-use crate::tools::{list_projects, get_project, list_analyses, get_analysis, get_analysis_status, analyze_file};
+use crate::tools::{
+    list_projects, get_project, list_analyses, get_analysis, get_analysis_status, analyze_file,
+    list_project_logs, analyze_project_log, analyze_command,
+    search_knowledge, match_error_patterns, create_knowledge_entry, start_job_queue,
+};
 
 /// Main MCP server structure
 #[derive(Clone)]
@@ -59,7 +63,7 @@ impl Default for SynapseMcpHandler {
 impl ServerHandler for SynapseMcpHandler {
     fn get_info(&self) -> ServerInfo {
         ServerInfo {
-            instructions: Some("AI-powered log analysis tool with project management. Discover projects, trigger analyses, and retrieve comprehensive log analysis results.".into()),
+            instructions: Some("AI-powered log analysis tool with project management. Discover projects, trigger analyses, and retrieve comprehensive log analysis results. Use provider \"sampling\" to run analyses on your own model without a Synapse API key.".into()),
             capabilities: rmcp::model::ServerCapabilities::builder()
                 .enable_tools()
                 .build(),
@@ -109,6 +113,42 @@ impl ServerHandler for SynapseMcpHandler {
                 input_schema: Arc::new(crate::schema::analyze_file_schema()),
                 annotations: Default::default(),
             },
+            Tool {
+                name: "list_project_logs".into(),
+                description: Some("List log files discovered under a linked project's root and the commands it allows".into()),
+                input_schema: Arc::new(crate::schema::list_project_logs_schema()),
+                annotations: Default::default(),
+            },
+            Tool {
+                name: "analyze_project_log".into(),
+                description: Some("Trigger analysis of a log file inside a linked project's root directory".into()),
+                input_schema: Arc::new(crate::schema::analyze_project_log_schema()),
+                annotations: Default::default(),
+            },
+            Tool {
+                name: "analyze_command".into(),
+                description: Some("Trigger analysis of a command's output (e.g. kubectl logs) allowed by the project's config.toml".into()),
+                input_schema: Arc::new(crate::schema::analyze_command_schema()),
+                annotations: Default::default(),
+            },
+            Tool {
+                name: "search_knowledge".into(),
+                description: Some("Search the problem/solution knowledge base, including public entries from other projects".into()),
+                input_schema: Arc::new(crate::schema::search_knowledge_schema()),
+                annotations: Default::default(),
+            },
+            Tool {
+                name: "match_error_patterns".into(),
+                description: Some("Match error messages against a project's known error patterns and return suggested solutions".into()),
+                input_schema: Arc::new(crate::schema::match_error_patterns_schema()),
+                annotations: Default::default(),
+            },
+            Tool {
+                name: "create_knowledge_entry".into(),
+                description: Some("Add a knowledge base entry, either written directly or derived from a completed analysis".into()),
+                input_schema: Arc::new(crate::schema::create_knowledge_entry_schema()),
+                annotations: Default::default(),
+            },
         ];
 
         Ok(ListToolsResult {
@@ -120,7 +160,7 @@ impl ServerHandler for SynapseMcpHandler {
     async fn call_tool(
         &self,
         request: CallToolRequestParam,
-        _context: rmcp::service::RequestContext<rmcp::service::RoleServer>,
+        context: rmcp::service::RequestContext<rmcp::service::RoleServer>,
     ) -> Result<CallToolResult, rmcp::Error> {
         let tool_name = request.name.as_ref();
         let arguments = request.arguments.unwrap_or_default();
@@ -151,7 +191,25 @@ impl ServerHandler for SynapseMcpHandler {
                 get_analysis_status(self.server.db(), arguments_value).await
             }
             "analyze_file" => {
-                analyze_file(self.server.db(), arguments_value).await
+                analyze_file(self.server.db(), arguments_value, Some(context.peer.clone())).await
+            }
+            "list_project_logs" => {
+                list_project_logs(self.server.db(), arguments_value).await
+            }
+            "analyze_project_log" => {
+                analyze_project_log(self.server.db(), arguments_value, Some(context.peer.clone())).await
+            }
+            "analyze_command" => {
+                analyze_command(self.server.db(), arguments_value, Some(context.peer.clone())).await
+            }
+            "search_knowledge" => {
+                search_knowledge(self.server.db(), arguments_value).await
+            }
+            "match_error_patterns" => {
+                match_error_patterns(self.server.db(), arguments_value).await
+            }
+            "create_knowledge_entry" => {
+                create_knowledge_entry(self.server.db(), arguments_value).await
             }
             _ => {
                 return Err(rmcp::Error::invalid_request(format!("Unknown tool: {}", tool_name), None));
@@ -184,6 +242,7 @@ impl McpServer {
         use crate::transport::{TransportType, create_and_run_transport};
 
         let handler = Arc::new(self.create_handler());
+        let _jobs = start_job_queue(&self.db).await;
 
         // NO LOGGING - stdio transport requires pure JSON-RPC on stdout
         // Any log output will corrupt the protocol and break MCP clients
@@ -196,11 +255,12 @@ impl McpServer {
         use crate::transport::{TransportType, create_and_run_transport};
         
         let handler = Arc::new(self.create_handler());
+        let _jobs = start_job_queue(&self.db).await;
         
         tracing::info!("Starting Synapse MCP server with HTTP transport on port {}", port);
         tracing::info!("Server name: {}", self.config.server_name);
         tracing::info!("Server version: {}", self.config.server_version);
-        tracing::info!("Available tools: list_projects, get_project, list_analyses, get_analysis, get_analysis_status, analyze_file");
+        tracing::info!("Available tools: list_projects, get_project, list_analyses, get_analysis, get_analysis_status, analyze_file, list_project_logs, analyze_project_log, analyze_command, search_knowledge, match_error_patterns, create_knowledge_entry");
 
         create_and_run_transport(TransportType::Http { port }, handler).await
     }
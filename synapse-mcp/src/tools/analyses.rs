@@ -1,7 +1,7 @@
 use serde_json::Value;
 use crate::Database;
 use anyhow::Result;
-use sqlx::Row;
+use synapse_core::project::AnalysisStatus;
 
 /// List analyses for a specific project with pagination
 pub async fn list_analyses(db: &Database, params: Value) -> Result<Value> {
@@ -17,52 +17,19 @@ pub async fn list_analyses(db: &Database, params: Value) -> Result<Value> {
         .and_then(|v| v.as_i64())
         .unwrap_or(0);
 
-    let rows = sqlx::query(
-        "SELECT id, project_id, log_file_id, status, started_at, completed_at, error_message
-         FROM analyses
-         WHERE project_id = ?
-         ORDER BY started_at DESC
-         LIMIT ? OFFSET ?"
-    )
-    .bind(&project_id)
-    .bind(limit)
-    .bind(offset)
-    .fetch_all(&db.pool)
-    .await?;
-
-    let total_row: i64 = sqlx::query_scalar(
-        "SELECT COUNT(*) FROM analyses WHERE project_id = ?"
-    )
-    .bind(&project_id)
-    .fetch_one(&db.pool)
-    .await?;
-
-    let analyses_json: Vec<Value> = rows.into_iter().map(|row| {
-        let id: String = row.get("id");
-        let project_id: String = row.get("project_id");
-        let log_file_id: Option<String> = row.get("log_file_id");
-        let status: i32 = row.get("status");
-        let started_at: chrono::DateTime<chrono::Utc> = row.get("started_at");
-        let completed_at: Option<chrono::DateTime<chrono::Utc>> = row.get("completed_at");
-        let error_message: Option<String> = row.get("error_message");
-
-        // Convert status code to string
-        let status_str = match status {
-            0 => "pending",
-            1 => "running",
-            2 => "completed",
-            3 => "failed",
-            _ => "unknown"
-        };
+    let analyses = db.storage.list_analyses_with_files(&project_id, limit, offset).await?;
+    let total_row = db.storage.count_analyses(&project_id).await?;
 
+    let analyses_json: Vec<Value> = analyses.into_iter().map(|(analysis, _filename)| {
         serde_json::json!({
-            "id": id,
-            "project_id": project_id,
-            "log_file_id": log_file_id,
-            "status": status_str,
-            "started_at": started_at,
-            "completed_at": completed_at,
-            "error_message": error_message
+            "id": analysis.id,
+            "project_id": analysis.project_id,
+            "log_file_id": analysis.log_file_id,
+            "source": analysis.source,
+            "status": analysis.status.to_string(),
+            "started_at": analysis.started_at,
+            "completed_at": analysis.completed_at,
+            "error_message": analysis.error_message
         })
     }).collect();
 
@@ -77,51 +44,25 @@ pub async fn get_analysis(db: &Database, params: Value) -> Result<Value> {
     let analysis_id: String = serde_json::from_value(params["analysis_id"].clone())
         .map_err(|_| anyhow::anyhow!("Invalid analysis_id parameter"))?;
 
-    let row = sqlx::query(
-        "SELECT id, project_id, log_file_id, status, started_at, completed_at,
-         error_message, result
-         FROM analyses
-         WHERE id = ?"
-    )
-    .bind(&analysis_id)
-    .fetch_optional(&db.pool)
-    .await?;
-
-    match row {
-        Some(row) => {
-            let id: String = row.get("id");
-            let project_id: String = row.get("project_id");
-            let log_file_id: Option<String> = row.get("log_file_id");
-            let status: i32 = row.get("status");
-            let started_at: chrono::DateTime<chrono::Utc> = row.get("started_at");
-            let completed_at: Option<chrono::DateTime<chrono::Utc>> = row.get("completed_at");
-            let error_message: Option<String> = row.get("error_message");
-            let result: Option<String> = row.get("result");
-
-            // Convert status code to string
-            let status_str = match status {
-                0 => "pending",
-                1 => "running",
-                2 => "completed",
-                3 => "failed",
-                _ => "unknown"
-            };
-
+    match db.storage.get_analysis(&analysis_id).await? {
+        Some(analysis) => {
             // Parse the result JSON if available
-            let result_value: Value = result
+            let result_value: Value = analysis.result
                 .as_ref()
                 .and_then(|s| serde_json::from_str(s).ok())
                 .unwrap_or(Value::Object(serde_json::Map::new()));
 
             Ok(serde_json::json!({
-                "id": id,
-                "project_id": project_id,
-                "log_file_id": log_file_id,
-                "status": status_str,
+                "id": analysis.id,
+                "project_id": analysis.project_id,
+                "log_file_id": analysis.log_file_id,
+                "analysis_type": analysis.analysis_type,
+                "source": analysis.source,
+                "status": analysis.status.to_string(),
                 "result": result_value,
-                "started_at": started_at,
-                "completed_at": completed_at,
-                "error_message": error_message
+                "started_at": analysis.started_at,
+                "completed_at": analysis.completed_at,
+                "error_message": analysis.error_message
             }))
         }
         None => Err(anyhow::anyhow!("Analysis not found: {}", analysis_id))
@@ -133,33 +74,20 @@ pub async fn get_analysis_status(db: &Database, params: Value) -> Result<Value>
     let analysis_id: String = serde_json::from_value(params["analysis_id"].clone())
         .map_err(|_| anyhow::anyhow!("Invalid analysis_id parameter"))?;
 
-    let row = sqlx::query(
-        "SELECT id, status, error_message FROM analyses WHERE id = ?"
-    )
-    .bind(&analysis_id)
-    .fetch_optional(&db.pool)
-    .await?;
-
-    match row {
-        Some(row) => {
-            let id: String = row.get("id");
-            let status: i32 = row.get("status");
-            let error_message: Option<String> = row.get("error_message");
-
-            // Convert status code to string and calculate progress
-            let (status_str, progress) = match status {
-                0 => ("pending", 0),
-                1 => ("running", 50),
-                2 => ("completed", 100),
-                3 => ("failed", 100),
-                _ => ("unknown", 0)
+    match db.storage.get_analysis(&analysis_id).await? {
+        Some(analysis) => {
+            // Calculate progress from the status
+            let progress = match analysis.status {
+                AnalysisStatus::Pending => 0,
+                AnalysisStatus::Running => 50,
+                AnalysisStatus::Completed | AnalysisStatus::Failed => 100,
             };
 
             Ok(serde_json::json!({
-                "id": id,
-                "status": status_str,
+                "id": analysis.id,
+                "status": analysis.status.to_string(),
                 "progress": progress,
-                "error_message": error_message
+                "error_message": analysis.error_message
             }))
         }
         None => Err(anyhow::anyhow!("Analysis not found: {}", analysis_id))
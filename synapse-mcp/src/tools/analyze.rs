@@ -1,11 +1,31 @@
+use std::sync::Arc;
+
 use serde_json::Value;
 use crate::Database;
+use crate::sampling::{resolve_backend, AnalysisBackend, SamplingProvider};
+use crate::tools::project_logs::read_source_lines;
 use anyhow::Result;
-use uuid::Uuid;
-use sqlx::Row;
+use async_trait::async_trait;
+use rmcp::service::{Peer, RoleServer};
+use synapse_core::project::{
+    fail_interrupted_analyses, Analysis, AnalysisJob, JobHandler, JobOutput, JobProgress, JobQueue,
+    JobQueueConfig,
+};
+use synapse_core::AnalysisResponse;
+
+/// Queue the MCP server's workers run
+pub const MCP_QUEUE: &str = "mcp";
+
+/// Workers running queued analyses
+const JOB_WORKERS: usize = 2;
+
+/// Seconds a queued analysis may run before it's retried
+const JOB_TIMEOUT_SECS: i64 = 300;
 
 /// Trigger new analysis on existing file
-pub async fn analyze_file(db: &Database, params: Value) -> Result<Value> {
+///
+/// `peer` is the calling MCP client, used for sampling-based analysis when available.
+pub async fn analyze_file(db: &Database, params: Value, peer: Option<Peer<RoleServer>>) -> Result<Value> {
     let project_id: String = serde_json::from_value(params["project_id"].clone())
         .map_err(|_| anyhow::anyhow!("Invalid project_id parameter"))?;
     
@@ -16,64 +36,45 @@ pub async fn analyze_file(db: &Database, params: Value) -> Result<Value> {
         .and_then(|v| v.as_str())
         .unwrap_or("openrouter")
         .to_string();
+    let backend = resolve_backend(&provider, peer.as_ref())?;
 
     // Validate project exists
-    let project_count: i64 = sqlx::query_scalar(
-        "SELECT COUNT(*) FROM projects WHERE id = ?"
-    )
-    .bind(&project_id)
-    .fetch_one(&db.pool)
-    .await?;
-
-    if project_count == 0 {
+    if db.storage.get_project(&project_id).await?.is_none() {
         return Err(anyhow::anyhow!("Project not found: {}", project_id));
     }
 
     // Validate file exists and belongs to project
-    let file_row = sqlx::query(
-        "SELECT id, project_id, upload_path, filename FROM log_files WHERE id = ? AND project_id = ?"
-    )
-    .bind(&file_id)
-    .bind(&project_id)
-    .fetch_optional(&db.pool)
-    .await?;
-
-    let row = match file_row {
-        Some(row) => row,
-        None => return Err(anyhow::anyhow!("File not found: {}", file_id))
-    };
-
-    let file_id: String = row.get("id");
-    let project_id: String = row.get("project_id");
-    let file_path: String = row.get("upload_path");
-    let _original_name: String = row.get("filename");
+    let log_file = db.storage.get_log_file(&project_id, &file_id).await?
+        .ok_or_else(|| anyhow::anyhow!("File not found: {}", file_id))?;
+    let file_path = log_file.upload_path;
 
     // Create analysis record
-    let analysis_id = Uuid::new_v4().to_string();
-    
-    sqlx::query(
-        "INSERT INTO analyses (id, project_id, log_file_id, analysis_type, status, provider, level_filter, started_at)
-         VALUES (?, ?, ?, 'file', 0, ?, 'ERROR', CURRENT_TIMESTAMP)"
-    )
-    .bind(&analysis_id)
-    .bind(&project_id)
-    .bind(&file_id)
-    .bind(&provider)
-    .execute(&db.pool)
-    .await?;
-
-    // Spawn background analysis task
-    let db_clone = db.clone();
-    let file_path_clone = file_path.clone();
-    let analysis_id_clone = analysis_id.clone();
-    let provider_clone = provider.clone();
-
-    tokio::spawn(async move {
-        if let Err(e) = run_analysis(&db_clone, &analysis_id_clone, &file_path_clone, &provider_clone).await {
-            // Log errors to file only, not stdout/stderr to avoid stdio contamination
-            eprintln!("[BACKGROUND ERROR] Analysis task failed: {}", e);
+    let analysis = Analysis::new(
+        log_file.project_id,
+        Some(log_file.id),
+        "file".to_string(),
+        backend.name().to_string(),
+        "ERROR".to_string(),
+    );
+    db.storage.create_analysis(&analysis).await?;
+
+    match backend {
+        AnalysisBackend::Provider(_) => enqueue_analysis(db, &analysis).await?,
+        AnalysisBackend::Sampling(_) => {
+            // Sampling goes through the calling client's connection, which a queued job
+            // could outlive, so it runs in this process only
+            let db_clone = db.clone();
+            let analysis_id = analysis.id.clone();
+
+            tokio::spawn(async move {
+                if let Err(e) = run_analysis(&db_clone, &analysis_id, &file_path, &backend).await {
+                    // Through tracing, which has no output in stdio mode, so stdout stays JSON-RPC
+                    tracing::error!("Analysis {} failed: {}", analysis_id, e);
+                }
+            });
         }
-    });
+    }
+    let analysis_id = analysis.id;
 
     Ok(serde_json::json!({
         "analysis_id": analysis_id,
@@ -81,78 +82,148 @@ pub async fn analyze_file(db: &Database, params: Value) -> Result<Value> {
     }))
 }
 
+/// Queue an analysis for the MCP server's workers, so it is retried on failure and
+/// survives a restart
+pub(crate) async fn enqueue_analysis(db: &Database, analysis: &Analysis) -> Result<()> {
+    let job = AnalysisJob::new(
+        analysis.id.clone(),
+        analysis.project_id.clone(),
+        MCP_QUEUE,
+        JOB_TIMEOUT_SECS,
+    );
+    db.storage.enqueue_job(&job).await
+}
+
 /// Run analysis in background task
 async fn run_analysis(
     db: &Database,
     analysis_id: &str,
     file_path: &str,
-    provider: &str
+    backend: &AnalysisBackend
 ) -> Result<()> {
-    // Update status to running (1=running in web schema)
-    sqlx::query(
-        "UPDATE analyses SET status = 1 WHERE id = ?"
-    )
-    .bind(analysis_id)
-    .execute(&db.pool)
-    .await?;
+    // Update status to running
+    mark_analysis_running(db, analysis_id).await?;
 
     // Read the log file
     let raw_lines = match synapse_core::input::read_log_file(file_path).await {
         Ok(lines) => lines,
         Err(e) => {
-            // Update status to failed (3=failed in web schema)
-            sqlx::query(
-                "UPDATE analyses SET status = 3, error_message = ?, completed_at = CURRENT_TIMESTAMP
-                 WHERE id = ?"
-            )
-            .bind(e.to_string())
-            .bind(analysis_id)
-            .execute(&db.pool)
-            .await?;
+            mark_analysis_failed(db, analysis_id, &e.to_string()).await?;
             return Err(e);
         }
     };
 
-    // Call synapse_core analysis function
-    let result = synapse_core::analyze_lines(
-        raw_lines, 
-        "ERROR", // Default level for MCP analysis
-        provider,
-        None, // API key will be resolved from config
-        None // Use default model
-    ).await;
+    run_analysis_on_lines(db, analysis_id, raw_lines, "ERROR", backend).await
+}
+
+/// Update an analysis row to running
+pub(crate) async fn mark_analysis_running(db: &Database, analysis_id: &str) -> Result<()> {
+    db.storage.mark_analysis_running(analysis_id).await
+}
+
+/// Update an analysis row to failed
+pub(crate) async fn mark_analysis_failed(db: &Database, analysis_id: &str, error: &str) -> Result<()> {
+    db.storage.fail_analysis(analysis_id, error).await
+}
+
+/// Analyze already collected log lines and store the outcome on the analysis row
+pub(crate) async fn run_analysis_on_lines(
+    db: &Database,
+    analysis_id: &str,
+    raw_lines: Vec<String>,
+    level: &str,
+    backend: &AnalysisBackend
+) -> Result<()> {
+    let result = analyze_raw_lines(raw_lines, level, backend).await;
 
     match result {
         Ok(analysis) => {
             // Serialize the entire analysis result as JSON for storage in the result column
             let result_json = serde_json::to_string(&analysis)?;
 
-            // Update status to completed (2=completed in web schema) and store result
-            sqlx::query(
-                "UPDATE analyses SET status = 2, result = ?, completed_at = CURRENT_TIMESTAMP
-                 WHERE id = ?"
-            )
-            .bind(result_json)
-            .bind(analysis_id)
-            .execute(&db.pool)
-            .await?;
+            // Update status to completed and store result
+            db.storage.complete_analysis(analysis_id, &result_json).await?;
 
             // Success - no logging to avoid stdio contamination
         }
         Err(e) => {
-            // Update status to failed (3=failed in web schema)
-            sqlx::query(
-                "UPDATE analyses SET status = 3, error_message = ?, completed_at = CURRENT_TIMESTAMP
-                 WHERE id = ?"
-            )
-            .bind(e.to_string())
-            .bind(analysis_id)
-            .execute(&db.pool)
-            .await?;
+            mark_analysis_failed(db, analysis_id, &e.to_string()).await?;
 
             // Error info is already in database - no logging to avoid stdio contamination
         }
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Analyze log lines with the given backend
+async fn analyze_raw_lines(
+    raw_lines: Vec<String>,
+    level: &str,
+    backend: &AnalysisBackend
+) -> Result<AnalysisResponse> {
+    match backend {
+        AnalysisBackend::Provider(provider) => synapse_core::analyze_lines(
+            raw_lines,
+            level,
+            provider,
+            None, // API key will be resolved from config
+            None // Use default model
+        ).await,
+        AnalysisBackend::Sampling(peer) => {
+            // No provider config is needed when the client's model does the work
+            synapse_core::Synapse::with_config(synapse_core::Config::default())
+                .analyze_lines_with_provider(
+                    raw_lines,
+                    level,
+                    Box::new(SamplingProvider::new(peer.clone())),
+                ).await
+        }
+    }
+}
+
+/// Start the workers running queued analyses, first failing analyses that stopped with
+/// the last run, such as sampling runs, and recovering jobs interrupted by a restart
+pub async fn start_job_queue(db: &Database) -> Arc<JobQueue> {
+    if let Err(e) = fail_interrupted_analyses(db.storage.as_ref()).await {
+        tracing::warn!("Failed to mark interrupted analyses as failed: {}", e);
+    }
+
+    let queue = JobQueue::new(
+        db.storage.clone(),
+        Arc::new(AnalysisJobHandler { db: db.clone() }),
+        JobQueueConfig::new(MCP_QUEUE, JOB_WORKERS),
+    );
+    queue.start();
+    queue
+}
+
+/// Runs analyses queued by `analyze_file`, `analyze_project_log` and `analyze_command`
+struct AnalysisJobHandler {
+    db: Database,
+}
+
+#[async_trait]
+impl JobHandler for AnalysisJobHandler {
+    async fn run(&self, job: &AnalysisJob, _progress: &JobProgress) -> Result<JobOutput> {
+        let analysis = self.db.storage.get_analysis(&job.analysis_id).await?
+            .ok_or_else(|| anyhow::anyhow!("Analysis {} no longer exists", job.analysis_id))?;
+
+        let raw_lines = match analysis.log_file_id.as_deref() {
+            Some(log_file_id) => {
+                let log_file = self.db.storage.get_log_file(&analysis.project_id, log_file_id).await?
+                    .ok_or_else(|| anyhow::anyhow!("File not found: {}", log_file_id))?;
+                synapse_core::input::read_log_file(&log_file.upload_path).await?
+            }
+            // A log file or command in a linked project, recorded as the analysis source
+            None => read_source_lines(&analysis).await?,
+        };
+        let backend = AnalysisBackend::Provider(analysis.provider.clone());
+        let response = analyze_raw_lines(raw_lines, &analysis.level_filter, &backend).await?;
+
+        Ok(JobOutput {
+            result_json: serde_json::to_string(&response)?,
+            details: None,
+        })
+    }
+}
@@ -0,0 +1,241 @@
+use serde_json::Value;
+use crate::Database;
+use anyhow::Result;
+use synapse_core::project::{AnalysisStatus, KnowledgeBaseEntry};
+
+/// Convert a knowledge base entry into its JSON representation
+fn knowledge_entry_json(entry: &KnowledgeBaseEntry) -> Value {
+    let tags_value: Value = entry.tags
+        .as_deref()
+        .and_then(|s| serde_json::from_str(s).ok())
+        .unwrap_or(Value::Array(vec![]));
+
+    serde_json::json!({
+        "id": entry.id,
+        "project_id": entry.project_id,
+        "title": entry.title,
+        "problem_description": entry.problem_description,
+        "solution": entry.solution,
+        "tags": tags_value,
+        "severity": entry.severity,
+        "is_public": entry.is_public,
+        "usage_count": entry.usage_count,
+        "created_at": entry.created_at,
+        "updated_at": entry.updated_at
+    })
+}
+
+/// Search knowledge base entries by text, optionally scoped to a project
+pub async fn search_knowledge(db: &Database, params: Value) -> Result<Value> {
+    let query: String = params.get("query")
+        .and_then(|v| v.as_str())
+        .unwrap_or("")
+        .to_string();
+
+    let project_id: Option<String> = params.get("project_id")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string());
+
+    let include_public = params.get("include_public")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(true);
+
+    let limit: i64 = params.get("limit")
+        .and_then(|v| v.as_i64())
+        .unwrap_or(20)
+        .min(100);
+
+    // Full-text search; every word of the query must match
+    let entries = db.storage
+        .search_knowledge(&query, project_id.as_deref(), include_public, limit)
+        .await?;
+    let entries: Vec<Value> = entries.iter().map(knowledge_entry_json).collect();
+
+    Ok(serde_json::json!({
+        "entries": entries,
+        "total": entries.len()
+    }))
+}
+
+/// Match error messages against a project's known error patterns
+pub async fn match_error_patterns(db: &Database, params: Value) -> Result<Value> {
+    let project_id: String = serde_json::from_value(params["project_id"].clone())
+        .map_err(|_| anyhow::anyhow!("Invalid project_id parameter"))?;
+
+    let mut messages: Vec<String> = params.get("error_messages")
+        .and_then(|v| serde_json::from_value(v.clone()).ok())
+        .unwrap_or_default();
+
+    if let Some(message) = params.get("error_message").and_then(|v| v.as_str()) {
+        messages.push(message.to_string());
+    }
+
+    if messages.is_empty() {
+        return Err(anyhow::anyhow!("error_message or error_messages is required"));
+    }
+
+    let patterns = db.storage.list_error_patterns(&project_id, None).await?;
+
+    let mut matches = Vec::new();
+    let mut matched_ids = Vec::new();
+
+    for message in &messages {
+        for pattern in &patterns {
+            let regex = match regex::Regex::new(&pattern.pattern) {
+                Ok(regex) => regex,
+                Err(_) => continue,
+            };
+
+            if !regex.is_match(message) {
+                continue;
+            }
+
+            let id = pattern.id.clone();
+            matches.push(serde_json::json!({
+                "error_message": message,
+                "pattern_id": pattern.id,
+                "pattern": pattern.pattern,
+                "category": pattern.category,
+                "description": pattern.description,
+                "frequency": pattern.frequency,
+                "suggested_solution": pattern.suggested_solution
+            }));
+
+            if !matched_ids.contains(&id) {
+                matched_ids.push(id);
+            }
+        }
+    }
+
+    // Record that each matched pattern has been seen again
+    for id in &matched_ids {
+        db.storage.record_error_pattern_match(id).await?;
+    }
+
+    // Surface knowledge entries that mention any of the matched patterns
+    let mut related: Vec<Value> = Vec::new();
+    for pattern in matches.iter().filter_map(|m| m["pattern"].as_str()) {
+        let found = search_knowledge(db, serde_json::json!({
+            "query": pattern,
+            "project_id": project_id,
+            "limit": 5
+        })).await?;
+
+        for entry in found["entries"].as_array().into_iter().flatten() {
+            if related.len() < 5 && !related.iter().any(|r| r["id"] == entry["id"]) {
+                related.push(entry.clone());
+            }
+        }
+    }
+
+    Ok(serde_json::json!({
+        "matches": matches,
+        "matched_patterns": matched_ids.len(),
+        "related_knowledge": related
+    }))
+}
+
+/// Create a knowledge base entry, optionally deriving its content from an analysis
+pub async fn create_knowledge_entry(db: &Database, params: Value) -> Result<Value> {
+    let mut project_id: Option<String> = params.get("project_id")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string());
+
+    let mut title = params.get("title").and_then(|v| v.as_str()).map(|s| s.to_string());
+    let mut problem = params.get("problem_description").and_then(|v| v.as_str()).map(|s| s.to_string());
+    let mut solution = params.get("solution").and_then(|v| v.as_str()).map(|s| s.to_string());
+
+    if let Some(analysis_id) = params.get("analysis_id").and_then(|v| v.as_str()) {
+        let stored = db.storage.get_analysis(analysis_id).await?
+            .ok_or_else(|| anyhow::anyhow!("Analysis not found: {}", analysis_id))?;
+
+        if stored.status != AnalysisStatus::Completed {
+            return Err(anyhow::anyhow!("Analysis {} has not completed", analysis_id));
+        }
+
+        if project_id.as_deref().is_some_and(|p| p != stored.project_id) {
+            return Err(anyhow::anyhow!("Analysis {} belongs to a different project", analysis_id));
+        }
+        project_id = Some(stored.project_id);
+
+        let analysis: synapse_core::AnalysisResponse = stored.result
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("Analysis {} has no stored result", analysis_id))
+            .and_then(|s| serde_json::from_str(s).map_err(Into::into))?;
+
+        let (derived_title, derived_problem, derived_solution) = entry_from_analysis(&analysis);
+        title.get_or_insert(derived_title);
+        problem.get_or_insert(derived_problem);
+        solution.get_or_insert(derived_solution);
+    }
+
+    let project_id = project_id
+        .ok_or_else(|| anyhow::anyhow!("project_id or analysis_id is required"))?;
+    let title = title.filter(|s| !s.trim().is_empty())
+        .ok_or_else(|| anyhow::anyhow!("title is required"))?;
+    let problem = problem.filter(|s| !s.trim().is_empty())
+        .ok_or_else(|| anyhow::anyhow!("problem_description is required"))?;
+    let solution = solution.filter(|s| !s.trim().is_empty())
+        .ok_or_else(|| anyhow::anyhow!("solution is required"))?;
+
+    let severity = params.get("severity")
+        .and_then(|v| v.as_str())
+        .unwrap_or("medium")
+        .to_string();
+    let is_public = params.get("is_public")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let tags: Option<String> = params.get("tags")
+        .filter(|v| v.is_array())
+        .map(|v| v.to_string());
+
+    // Validate project exists
+    if db.storage.get_project(&project_id).await?.is_none() {
+        return Err(anyhow::anyhow!("Project not found: {}", project_id));
+    }
+
+    let entry = KnowledgeBaseEntry::new(
+        project_id,
+        title,
+        problem,
+        solution,
+        tags,
+        severity,
+        is_public,
+    );
+    db.storage.create_knowledge_entry(&entry).await?;
+
+    Ok(serde_json::json!({
+        "id": entry.id,
+        "project_id": entry.project_id,
+        "title": entry.title,
+        "problem_description": entry.problem_description,
+        "solution": entry.solution,
+        "severity": entry.severity,
+        "is_public": entry.is_public
+    }))
+}
+
+/// Derive a knowledge entry title, problem and solution from an analysis result
+fn entry_from_analysis(analysis: &synapse_core::AnalysisResponse) -> (String, String, String) {
+    let description = analysis.root_cause.description.trim();
+    let title: String = if description.chars().count() > 120 {
+        format!("{}...", description.chars().take(117).collect::<String>())
+    } else {
+        description.to_string()
+    };
+
+    let problem = format!(
+        "{}\n\nSequence of events:\n{}",
+        description,
+        analysis.sequence_of_events.trim()
+    );
+
+    let solution = analysis.recommendations
+        .iter()
+        .map(|r| format!("- {}", r))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    (title, problem, solution)
+}
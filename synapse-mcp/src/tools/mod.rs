@@ -1,7 +1,11 @@
 pub mod projects;
 pub mod analyses;
 pub mod analyze;
+pub mod project_logs;
+pub mod knowledge;
 
 pub use projects::*;
 pub use analyses::*;
-pub use analyze::*;
\ No newline at end of file
+pub use analyze::*;
+pub use project_logs::*;
+pub use knowledge::*;
\ No newline at end of file
@@ -0,0 +1,250 @@
+use serde_json::Value;
+use crate::Database;
+use crate::sampling::{resolve_backend, AnalysisBackend};
+use crate::tools::analyze::{
+    enqueue_analysis, mark_analysis_failed, mark_analysis_running, run_analysis_on_lines,
+};
+use anyhow::{Context, Result};
+use rmcp::service::{Peer, RoleServer};
+use std::path::PathBuf;
+use std::time::Duration;
+use synapse_core::project::{
+    discover_log_files, is_command_allowed, resolve_in_root, Analysis, ProjectConfig,
+    ProjectRegistry,
+};
+
+/// Maximum time a whitelisted command may run before its output is discarded
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A project linked through the global registry, with its loaded configuration
+struct LinkedProject {
+    root_path: PathBuf,
+    config: ProjectConfig,
+}
+
+/// Look up a linked project in the global registry and load its config.toml
+async fn load_linked_project(project_id: &str) -> Result<LinkedProject> {
+    let registry = ProjectRegistry::load()?;
+    let entry = registry.get_project(project_id)
+        .ok_or_else(|| anyhow::anyhow!(
+            "Project {} is not linked. Run 'synapse link' in the project directory first.",
+            project_id
+        ))?;
+
+    let config = ProjectConfig::load(entry.synapse_config.join("config.toml")).await
+        .with_context(|| format!("Failed to load config.toml for project {}", project_id))?;
+
+    Ok(LinkedProject {
+        root_path: entry.root_path.clone(),
+        config,
+    })
+}
+
+/// Create an analysis row without an uploaded log file attached
+async fn create_analysis_record(
+    db: &Database,
+    project_id: &str,
+    analysis_type: &str,
+    provider: &str,
+    level: &str,
+    source: String,
+) -> Result<Analysis> {
+    // Validate project exists
+    if db.storage.get_project(project_id).await?.is_none() {
+        return Err(anyhow::anyhow!("Project not found: {}", project_id));
+    }
+
+    let analysis = Analysis::new(
+        project_id.to_string(),
+        None,
+        analysis_type.to_string(),
+        provider.to_string(),
+        level.to_string(),
+    )
+    .with_source(source);
+    db.storage.create_analysis(&analysis).await?;
+
+    Ok(analysis)
+}
+
+/// Queue a project log or command analysis, or run it in this process when sampling
+async fn start_source_analysis(db: &Database, analysis: Analysis, backend: AnalysisBackend) -> Result<()> {
+    match backend {
+        AnalysisBackend::Provider(_) => enqueue_analysis(db, &analysis).await,
+        AnalysisBackend::Sampling(_) => {
+            // Sampling goes through the calling client's connection, which a queued job
+            // could outlive, so it runs in this process only
+            let db = db.clone();
+
+            tokio::spawn(async move {
+                let result = async {
+                    mark_analysis_running(&db, &analysis.id).await?;
+                    let raw_lines = match read_source_lines(&analysis).await {
+                        Ok(lines) => lines,
+                        Err(e) => {
+                            mark_analysis_failed(&db, &analysis.id, &e.to_string()).await?;
+                            return Err(e);
+                        }
+                    };
+                    run_analysis_on_lines(&db, &analysis.id, raw_lines, &analysis.level_filter, &backend).await
+                }.await;
+
+                if let Err(e) = result {
+                    // Through tracing, which has no output in stdio mode, so stdout stays JSON-RPC
+                    tracing::error!("Analysis {} failed: {}", analysis.id, e);
+                }
+            });
+            Ok(())
+        }
+    }
+}
+
+/// Collect the log lines of a project log or command analysis from its recorded source
+///
+/// The project's root and command allowlist are checked again, since they may have
+/// changed while the analysis was queued.
+pub(crate) async fn read_source_lines(analysis: &Analysis) -> Result<Vec<String>> {
+    let source = analysis.source.as_deref()
+        .ok_or_else(|| anyhow::anyhow!("Analysis {} has no log file or source", analysis.id))?;
+    let project = load_linked_project(&analysis.project_id).await?;
+
+    if analysis.analysis_type == "command" {
+        if !is_command_allowed(source, &project.config.mcp.allowed_commands) {
+            return Err(anyhow::anyhow!("Command is no longer allowed for this project: {}", source));
+        }
+        capture_command_output(source, &project.root_path).await
+    } else {
+        let path = resolve_in_root(&project.root_path, source)?;
+        synapse_core::input::read_log_file(&path.display().to_string()).await
+    }
+}
+
+/// Resolve provider and level from the request, falling back to project defaults
+fn provider_and_level(params: &Value, config: &ProjectConfig) -> (String, String) {
+    let provider = params.get("provider")
+        .and_then(|v| v.as_str())
+        .unwrap_or(&config.synapse.default_provider)
+        .to_string();
+
+    let level = params.get("level")
+        .and_then(|v| v.as_str())
+        .unwrap_or(&config.synapse.default_level)
+        .to_uppercase();
+
+    (provider, level)
+}
+
+/// List log files discovered under a linked project's root
+pub async fn list_project_logs(_db: &Database, params: Value) -> Result<Value> {
+    let project_id: String = serde_json::from_value(params["project_id"].clone())
+        .map_err(|_| anyhow::anyhow!("Invalid project_id parameter"))?;
+
+    let project = load_linked_project(&project_id).await?;
+    let project_type = synapse_core::project::detect_project_type(&project.root_path).await?;
+
+    let files: Vec<Value> = discover_log_files(&project.root_path, project_type)
+        .into_iter()
+        .map(|path| {
+            let size = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+            let relative = path.strip_prefix(&project.root_path)
+                .unwrap_or(&path)
+                .display()
+                .to_string();
+            serde_json::json!({
+                "path": relative,
+                "size": size
+            })
+        })
+        .collect();
+
+    Ok(serde_json::json!({
+        "project_id": project_id,
+        "root_path": project.root_path,
+        "project_type": project_type.as_str(),
+        "files": files,
+        "allowed_commands": project.config.mcp.allowed_commands
+    }))
+}
+
+/// Trigger analysis of a log file inside a linked project's root
+pub async fn analyze_project_log(db: &Database, params: Value, peer: Option<Peer<RoleServer>>) -> Result<Value> {
+    let project_id: String = serde_json::from_value(params["project_id"].clone())
+        .map_err(|_| anyhow::anyhow!("Invalid project_id parameter"))?;
+
+    let path: String = serde_json::from_value(params["path"].clone())
+        .map_err(|_| anyhow::anyhow!("Invalid path parameter"))?;
+
+    let project = load_linked_project(&project_id).await?;
+    let resolved = resolve_in_root(&project.root_path, &path)?;
+    let (provider, level) = provider_and_level(&params, &project.config);
+    let backend = resolve_backend(&provider, peer.as_ref())?;
+
+    let analysis = create_analysis_record(db, &project_id, "file", backend.name(), &level, resolved.display().to_string()).await?;
+    let analysis_id = analysis.id.clone();
+    start_source_analysis(db, analysis, backend).await?;
+
+    Ok(serde_json::json!({
+        "analysis_id": analysis_id,
+        "status": "pending",
+        "path": resolved
+    }))
+}
+
+/// Trigger analysis of a whitelisted command's output for a linked project
+pub async fn analyze_command(db: &Database, params: Value, peer: Option<Peer<RoleServer>>) -> Result<Value> {
+    let project_id: String = serde_json::from_value(params["project_id"].clone())
+        .map_err(|_| anyhow::anyhow!("Invalid project_id parameter"))?;
+
+    let command: String = serde_json::from_value(params["command"].clone())
+        .map_err(|_| anyhow::anyhow!("Invalid command parameter"))?;
+
+    let project = load_linked_project(&project_id).await?;
+
+    if !is_command_allowed(&command, &project.config.mcp.allowed_commands) {
+        return Err(anyhow::anyhow!(
+            "Command is not allowed for this project. Add a matching prefix to [mcp].allowed_commands in .synapse/config.toml (currently: {:?})",
+            project.config.mcp.allowed_commands
+        ));
+    }
+
+    let (provider, level) = provider_and_level(&params, &project.config);
+    let backend = resolve_backend(&provider, peer.as_ref())?;
+    let analysis = create_analysis_record(db, &project_id, "command", backend.name(), &level, command.clone()).await?;
+    let analysis_id = analysis.id.clone();
+    start_source_analysis(db, analysis, backend).await?;
+
+    Ok(serde_json::json!({
+        "analysis_id": analysis_id,
+        "status": "pending",
+        "command": command
+    }))
+}
+
+/// Run a command from the project root and capture its output with a timeout
+async fn capture_command_output(command: &str, root_path: &std::path::Path) -> Result<Vec<String>> {
+    let parts: Vec<&str> = command.split_whitespace().collect();
+    let (program, args) = parts.split_first()
+        .ok_or_else(|| anyhow::anyhow!("Empty command"))?;
+
+    let mut cmd = tokio::process::Command::new(program);
+    cmd.args(args)
+        .current_dir(root_path)
+        .stdin(std::process::Stdio::null())
+        .kill_on_drop(true);
+
+    let output = tokio::time::timeout(COMMAND_TIMEOUT, cmd.output())
+        .await
+        .map_err(|_| anyhow::anyhow!("Command timed out after {} seconds", COMMAND_TIMEOUT.as_secs()))??;
+
+    let mut lines: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|s| s.to_string())
+        .collect();
+    lines.extend(String::from_utf8_lossy(&output.stderr).lines().map(|s| s.to_string()));
+
+    if !output.status.success() && lines.is_empty() {
+        return Err(anyhow::anyhow!("Command exited with {}", output.status));
+    }
+
+    Ok(lines)
+}
@@ -10,6 +10,12 @@ pub fn validate_tool_params(tool_name: &str, params: &Value) -> Result<()> {
         "get_analysis" => validate_get_analysis(params),
         "get_analysis_status" => validate_get_analysis_status(params),
         "analyze_file" => validate_analyze_file(params),
+        "list_project_logs" => validate_list_project_logs(params),
+        "analyze_project_log" => validate_analyze_local(params, "path"),
+        "analyze_command" => validate_analyze_local(params, "command"),
+        "search_knowledge" => validate_search_knowledge(params),
+        "match_error_patterns" => validate_match_error_patterns(params),
+        "create_knowledge_entry" => validate_create_knowledge_entry(params),
         _ => Err(anyhow!("Unknown tool: {}", tool_name)),
     }
 }
@@ -155,7 +161,7 @@ fn validate_analyze_file(params: &Value) -> Result<()> {
         // Validate provider (optional)
         if let Some(provider) = map.get("provider") {
             if let Some(provider_str) = provider.as_str() {
-                let valid_providers = ["openrouter", "openai", "claude", "gemini"];
+                let valid_providers = ["openrouter", "openai", "claude", "gemini", "sampling"];
                 if !valid_providers.contains(&provider_str) {
                     return Err(anyhow!(
                         "provider must be one of: {}", 
@@ -170,4 +176,213 @@ fn validate_analyze_file(params: &Value) -> Result<()> {
         return Err(anyhow!("Parameters must be an object"));
     }
     Ok(())
-}
\ No newline at end of file
+}
+
+fn validate_list_project_logs(params: &Value) -> Result<()> {
+    if let Value::Object(map) = params {
+        let project_id = map.get("project_id")
+            .ok_or_else(|| anyhow!("project_id is required"))?;
+        
+        if !project_id.is_string() {
+            return Err(anyhow!("project_id must be a string"));
+        }
+        
+        if project_id.as_str().unwrap().is_empty() {
+            return Err(anyhow!("project_id cannot be empty"));
+        }
+    } else {
+        return Err(anyhow!("Parameters must be an object"));
+    }
+    Ok(())
+}
+
+/// Validate analyze_project_log / analyze_command, which differ only in their target field
+fn validate_analyze_local(params: &Value, target_field: &str) -> Result<()> {
+    if let Value::Object(map) = params {
+        for field in ["project_id", target_field] {
+            let value = map.get(field)
+                .ok_or_else(|| anyhow!("{} is required", field))?;
+            
+            if !value.is_string() {
+                return Err(anyhow!("{} must be a string", field));
+            }
+            
+            if value.as_str().unwrap().trim().is_empty() {
+                return Err(anyhow!("{} cannot be empty", field));
+            }
+        }
+        
+        // Validate provider (optional)
+        if let Some(provider) = map.get("provider") {
+            if let Some(provider_str) = provider.as_str() {
+                let valid_providers = ["openrouter", "openai", "claude", "gemini", "sampling"];
+                if !valid_providers.contains(&provider_str) {
+                    return Err(anyhow!(
+                        "provider must be one of: {}", 
+                        valid_providers.join(", ")
+                    ));
+                }
+            } else {
+                return Err(anyhow!("provider must be a string"));
+            }
+        }
+        
+        // Validate level (optional)
+        if let Some(level) = map.get("level") {
+            if let Some(level_str) = level.as_str() {
+                let valid_levels = ["ERROR", "WARN", "INFO", "DEBUG"];
+                if !valid_levels.contains(&level_str.to_uppercase().as_str()) {
+                    return Err(anyhow!(
+                        "level must be one of: {}", 
+                        valid_levels.join(", ")
+                    ));
+                }
+            } else {
+                return Err(anyhow!("level must be a string"));
+            }
+        }
+    } else {
+        return Err(anyhow!("Parameters must be an object"));
+    }
+    Ok(())
+}
+
+fn validate_search_knowledge(params: &Value) -> Result<()> {
+    if let Value::Object(map) = params {
+        let query = map.get("query")
+            .ok_or_else(|| anyhow!("query is required"))?;
+        
+        if !query.is_string() {
+            return Err(anyhow!("query must be a string"));
+        }
+        
+        if let Some(project_id) = map.get("project_id") {
+            if !project_id.is_string() {
+                return Err(anyhow!("project_id must be a string"));
+            }
+        }
+        
+        if let Some(include_public) = map.get("include_public") {
+            if !include_public.is_boolean() {
+                return Err(anyhow!("include_public must be a boolean"));
+            }
+        }
+        
+        // Validate limit (optional)
+        if let Some(limit) = map.get("limit") {
+            if let Some(limit_num) = limit.as_i64() {
+                if !(1..=100).contains(&limit_num) {
+                    return Err(anyhow!("limit must be between 1 and 100"));
+                }
+            } else {
+                return Err(anyhow!("limit must be an integer"));
+            }
+        }
+    } else {
+        return Err(anyhow!("Parameters must be an object"));
+    }
+    Ok(())
+}
+
+fn validate_match_error_patterns(params: &Value) -> Result<()> {
+    if let Value::Object(map) = params {
+        let project_id = map.get("project_id")
+            .ok_or_else(|| anyhow!("project_id is required"))?;
+        
+        if !project_id.is_string() {
+            return Err(anyhow!("project_id must be a string"));
+        }
+        
+        if project_id.as_str().unwrap().is_empty() {
+            return Err(anyhow!("project_id cannot be empty"));
+        }
+        
+        let single = map.get("error_message");
+        let many = map.get("error_messages");
+        
+        if single.is_none() && many.is_none() {
+            return Err(anyhow!("error_message or error_messages is required"));
+        }
+        
+        if let Some(message) = single {
+            if !message.is_string() {
+                return Err(anyhow!("error_message must be a string"));
+            }
+        }
+        
+        if let Some(messages) = many {
+            if let Value::Array(messages_array) = messages {
+                for (i, message) in messages_array.iter().enumerate() {
+                    if !message.is_string() {
+                        return Err(anyhow!("error_messages[{}] must be a string, found: {}", i, message));
+                    }
+                }
+            } else {
+                return Err(anyhow!("error_messages must be an array"));
+            }
+        }
+    } else {
+        return Err(anyhow!("Parameters must be an object"));
+    }
+    Ok(())
+}
+
+fn validate_create_knowledge_entry(params: &Value) -> Result<()> {
+    if let Value::Object(map) = params {
+        for field in ["project_id", "analysis_id", "title", "problem_description", "solution"] {
+            if let Some(value) = map.get(field) {
+                if !value.is_string() {
+                    return Err(anyhow!("{} must be a string", field));
+                }
+            }
+        }
+        
+        if !map.contains_key("project_id") && !map.contains_key("analysis_id") {
+            return Err(anyhow!("project_id or analysis_id is required"));
+        }
+        
+        // Without an analysis to derive from, all content fields are required
+        if !map.contains_key("analysis_id") {
+            for field in ["title", "problem_description", "solution"] {
+                if !map.contains_key(field) {
+                    return Err(anyhow!("{} is required when analysis_id is not given", field));
+                }
+            }
+        }
+        
+        if let Some(title) = map.get("title").and_then(|v| v.as_str()) {
+            if title.len() > 255 {
+                return Err(anyhow!("title must be 255 characters or less"));
+            }
+        }
+        
+        if let Some(severity) = map.get("severity") {
+            let valid_severities = ["low", "medium", "high", "critical"];
+            if !severity.as_str().is_some_and(|s| valid_severities.contains(&s)) {
+                return Err(anyhow!(
+                    "severity must be one of: {}", 
+                    valid_severities.join(", ")
+                ));
+            }
+        }
+        
+        if let Some(tags) = map.get("tags") {
+            if let Value::Array(tags_array) = tags {
+                if tags_array.iter().any(|t| !t.is_string()) {
+                    return Err(anyhow!("tags must be an array of strings"));
+                }
+            } else {
+                return Err(anyhow!("tags must be an array"));
+            }
+        }
+        
+        if let Some(is_public) = map.get("is_public") {
+            if !is_public.is_boolean() {
+                return Err(anyhow!("is_public must be a boolean"));
+            }
+        }
+    } else {
+        return Err(anyhow!("Parameters must be an object"));
+    }
+    Ok(())
+}
@@ -15,69 +15,7 @@ async fn setup_test_db() -> (Database, TempDir) {
         .await
         .expect("Failed to create test database");
 
-    // Run migrations - create all required tables
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS projects (
-            id TEXT PRIMARY KEY,
-            name TEXT NOT NULL,
-            root_path TEXT,
-            description TEXT,
-            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
-            updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
-            metadata TEXT
-        )"
-    )
-    .execute(&db.pool)
-    .await
-    .unwrap();
-
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS files (
-            id TEXT PRIMARY KEY,
-            project_id TEXT NOT NULL,
-            path TEXT NOT NULL,
-            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
-        )"
-    )
-    .execute(&db.pool)
-    .await
-    .unwrap();
-
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS analyses (
-            id TEXT PRIMARY KEY,
-            project_id TEXT NOT NULL,
-            log_file_path TEXT NOT NULL,
-            provider TEXT NOT NULL,
-            level TEXT NOT NULL,
-            status TEXT NOT NULL,
-            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
-            started_at DATETIME,
-            completed_at DATETIME,
-            metadata TEXT,
-            FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
-        )"
-    )
-    .execute(&db.pool)
-    .await
-    .unwrap();
-
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS analysis_results (
-            analysis_id TEXT PRIMARY KEY,
-            summary TEXT,
-            full_report TEXT,
-            patterns_detected TEXT,
-            issues_found INTEGER,
-            metadata TEXT,
-            FOREIGN KEY (analysis_id) REFERENCES analyses(id) ON DELETE CASCADE
-        )"
-    )
-    .execute(&db.pool)
-    .await
-    .unwrap();
-
+    // Database::new applies the shared migrations, so all tables already exist
     (db, temp_dir)
 }
 
@@ -113,7 +51,7 @@ async fn test_list_projects_with_data() {
         "INSERT INTO projects (id, name, root_path, created_at, updated_at)
          VALUES ('test-id', 'test-project', '/test/path', CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)"
     )
-    .execute(&db.pool)
+    .execute(db.storage.sqlite_pool().unwrap())
     .await
     .unwrap();
 
@@ -173,29 +111,36 @@ async fn test_query_analyses_with_filters() {
         "INSERT INTO projects (id, name, root_path, created_at, updated_at)
          VALUES ('proj-1', 'test-project', '/test', CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)"
     )
-    .execute(&db.pool)
+    .execute(db.storage.sqlite_pool().unwrap())
     .await
     .unwrap();
 
     // Insert test analyses
     sqlx::query(
-        "INSERT INTO analyses (id, project_id, log_file_path, provider, level, status, created_at)
+        "INSERT INTO analyses (id, project_id, analysis_type, provider, level_filter, status, source, started_at)
          VALUES
-         ('analysis-1', 'proj-1', '/test/log1.log', 'openrouter', 'ERROR', 'completed', CURRENT_TIMESTAMP),
-         ('analysis-2', 'proj-1', '/test/log2.log', 'openai', 'WARN', 'pending', CURRENT_TIMESTAMP)"
+         ('analysis-1', 'proj-1', 'file', 'openrouter', 'ERROR', 2, '/test/log1.log', CURRENT_TIMESTAMP),
+         ('analysis-2', 'proj-1', 'file', 'openai', 'WARN', 0, '/test/log2.log', CURRENT_TIMESTAMP)"
     )
-    .execute(&db.pool)
+    .execute(db.storage.sqlite_pool().unwrap())
     .await
     .unwrap();
 
     // Test querying analyses
-    use synapse_core::project::queries::query_analyses;
+    use synapse_core::project::{queries::query_analyses, AnalysisStatus};
 
-    let analyses = query_analyses(&db.pool, Some("proj-1"), None, None, None)
+    let analyses = query_analyses(db.storage.sqlite_pool().unwrap(), Some("proj-1"), None, None, None)
         .await
         .unwrap();
 
     assert_eq!(analyses.len(), 2, "Should find 2 analyses");
+
+    let completed = query_analyses(db.storage.sqlite_pool().unwrap(), Some("proj-1"), Some(AnalysisStatus::Completed), None, None)
+        .await
+        .unwrap();
+
+    assert_eq!(completed.len(), 1, "Should find 1 completed analysis");
+    assert_eq!(completed[0].id, "analysis-1");
 }
 
 #[tokio::test]
@@ -207,30 +152,36 @@ async fn test_get_analysis_by_id() {
         "INSERT INTO projects (id, name, root_path, created_at, updated_at)
          VALUES ('proj-1', 'test-project', '/test', CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)"
     )
-    .execute(&db.pool)
+    .execute(db.storage.sqlite_pool().unwrap())
     .await
     .unwrap();
 
     // Insert test analysis
     sqlx::query(
-        "INSERT INTO analyses (id, project_id, log_file_path, provider, level, status, created_at)
-         VALUES ('analysis-1', 'proj-1', '/test/log.log', 'openrouter', 'ERROR', 'completed', CURRENT_TIMESTAMP)"
+        "INSERT INTO analyses (id, project_id, analysis_type, provider, level_filter, status, source, started_at)
+         VALUES ('analysis-1', 'proj-1', 'file', 'openrouter', 'ERROR', 2, '/test/log.log', CURRENT_TIMESTAMP)"
     )
-    .execute(&db.pool)
+    .execute(db.storage.sqlite_pool().unwrap())
     .await
     .unwrap();
 
     // Test retrieving analysis
     use synapse_core::project::queries::get_analysis_by_id;
 
-    let result = get_analysis_by_id(&db.pool, "analysis-1")
+    let result = get_analysis_by_id(db.storage.sqlite_pool().unwrap(), "analysis-1")
         .await
         .unwrap();
 
     assert!(result.is_some(), "Should find the analysis");
-    let (analysis, _result) = result.unwrap();
+    let analysis = result.unwrap();
     assert_eq!(analysis.id, "analysis-1");
     assert_eq!(analysis.project_id, "proj-1");
+    assert_eq!(analysis.source.as_deref(), Some("/test/log.log"));
+
+    // The MCP tool reads the same row through the shared repository
+    use synapse_mcp::tools::analyses::get_analysis;
+    let value = get_analysis(&db, json!({"analysis_id": "analysis-1"})).await.unwrap();
+    assert_eq!(value["status"], "completed");
 }
 
 #[tokio::test]
@@ -246,3 +197,141 @@ async fn test_mcp_schema_definitions() {
     assert_eq!(get_project_schema.get("type").and_then(|v| v.as_str()), Some("object"));
     assert!(get_project_schema.contains_key("required"));
 }
+
+#[tokio::test]
+async fn test_project_log_tool_validation() {
+    use synapse_mcp::validation::validate_tool_params;
+
+    assert!(validate_tool_params("list_project_logs", &json!({"project_id": "proj-1"})).is_ok());
+    assert!(validate_tool_params("list_project_logs", &json!({})).is_err());
+
+    assert!(validate_tool_params(
+        "analyze_project_log",
+        &json!({"project_id": "proj-1", "path": "logs/app.log", "level": "warn"})
+    ).is_ok());
+    assert!(validate_tool_params("analyze_project_log", &json!({"project_id": "proj-1"})).is_err());
+
+    assert!(validate_tool_params(
+        "analyze_command",
+        &json!({"project_id": "proj-1", "command": "kubectl logs api-0", "provider": "claude"})
+    ).is_ok());
+    assert!(validate_tool_params(
+        "analyze_command",
+        &json!({"project_id": "proj-1", "command": "  "})
+    ).is_err());
+    assert!(validate_tool_params(
+        "analyze_command",
+        &json!({"project_id": "proj-1", "command": "docker logs web", "level": "TRACE"})
+    ).is_err());
+}
+
+#[tokio::test]
+async fn test_analyze_command_requires_linked_project() {
+    let (db, _temp) = setup_test_db().await;
+
+    use synapse_mcp::tools::project_logs::analyze_command;
+    let result = analyze_command(
+        &db,
+        json!({"project_id": "not-a-linked-project", "command": "docker logs web"}),
+        None,
+    ).await;
+
+    assert!(result.is_err(), "Unlinked projects must not run commands");
+}
+
+#[tokio::test]
+async fn test_knowledge_entry_create_search_and_match() {
+    let (db, _temp) = setup_test_db().await;
+
+    sqlx::query(
+        "INSERT INTO projects (id, name, root_path, created_at, updated_at)
+         VALUES ('proj-kb', 'kb-project', '/test', CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)"
+    )
+    .execute(db.storage.sqlite_pool().unwrap())
+    .await
+    .unwrap();
+
+    sqlx::query(
+        "INSERT INTO error_patterns (id, project_id, pattern, category, description, suggested_solution)
+         VALUES ('pat-1', 'proj-kb', 'connection pool exhausted', 'infrastructure',
+                 'Database pool too small', 'Raise max_connections')"
+    )
+    .execute(db.storage.sqlite_pool().unwrap())
+    .await
+    .unwrap();
+
+    use synapse_mcp::tools::knowledge::{create_knowledge_entry, match_error_patterns, search_knowledge};
+
+    let created = create_knowledge_entry(&db, json!({
+        "project_id": "proj-kb",
+        "title": "Connection pool exhausted under load",
+        "problem_description": "Requests fail with connection pool exhausted",
+        "solution": "Raise max_connections and add request timeouts",
+        "tags": ["database", "pool"],
+        "severity": "high"
+    })).await.unwrap();
+    assert!(created["id"].is_string());
+
+    let found = search_knowledge(&db, json!({"query": "pool", "project_id": "proj-kb"})).await.unwrap();
+    assert_eq!(found["total"], 1);
+    assert_eq!(found["entries"][0]["tags"], json!(["database", "pool"]));
+
+    let matched = match_error_patterns(&db, json!({
+        "project_id": "proj-kb",
+        "error_message": "ERROR connection pool exhausted after 30s"
+    })).await.unwrap();
+    assert_eq!(matched["matched_patterns"], 1);
+    assert_eq!(matched["matches"][0]["suggested_solution"], "Raise max_connections");
+    assert_eq!(matched["related_knowledge"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_knowledge_entry_from_incomplete_analysis_fails() {
+    let (db, _temp) = setup_test_db().await;
+
+    use synapse_mcp::tools::knowledge::create_knowledge_entry;
+    let result = create_knowledge_entry(&db, json!({"analysis_id": "missing"})).await;
+
+    assert!(result.is_err(), "Unknown analyses cannot seed knowledge entries");
+}
+
+#[test]
+fn test_parse_sampled_analysis_strips_fences() {
+    use synapse_mcp::sampling::parse_sampled_analysis;
+
+    let content = r#"Here is the analysis:
+```json
+{
+  "sequence_of_events": "Pool exhausted, then requests timed out",
+  "root_cause": {
+    "category": "UnknownRelated",
+    "description": "Database connection pool too small",
+    "file_location": null,
+    "line_number": null,
+    "function_name": null,
+    "confidence": 0.8
+  },
+  "recommendations": ["Raise max_connections"],
+  "confidence": 0.8,
+  "related_errors": [],
+  "unrelated_errors": []
+}
+```"#;
+
+    let analysis = parse_sampled_analysis(content).unwrap();
+    assert_eq!(analysis.root_cause.description, "Database connection pool too small");
+    assert_eq!(analysis.recommendations, vec!["Raise max_connections"]);
+
+    assert!(parse_sampled_analysis("I could not analyze these logs.").is_err());
+}
+
+#[test]
+fn test_sampling_backend_requires_capable_client() {
+    use synapse_mcp::sampling::{resolve_backend, AnalysisBackend};
+
+    assert!(resolve_backend("sampling", None).is_err());
+    assert!(matches!(
+        resolve_backend("openrouter", None).unwrap(),
+        AnalysisBackend::Provider(ref name) if name == "openrouter"
+    ));
+}
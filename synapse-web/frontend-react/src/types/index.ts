@@ -227,7 +227,8 @@ export interface AppError {
 // Settings types
 export interface Settings {
   default_provider: string;
-  api_key: string;
+  api_key: string; // masked key of the default provider, e.g. ****abcd
+
   max_lines: number;
   default_level: string;
   show_timestamps: boolean;
@@ -236,6 +237,16 @@ export interface Settings {
   available_models?: string; // JSON array cache
   models_last_fetched?: string; // ISO datetime
   analysis_timeout_seconds?: number;
+  api_keys?: ProviderApiKey[];
+}
+
+// Stored provider key; the key itself is never returned
+export interface ProviderApiKey {
+  provider: string;
+  key_id: string;
+  hint: string;
+  created_at: string;
+  updated_at: string;
 }
 
 // Model types
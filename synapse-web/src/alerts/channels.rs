@@ -0,0 +1,831 @@
+//! Channel settings and the delivery of one message over each kind of channel
+//!
+//! Secrets in a channel's settings (webhook signing secrets, Slack webhook URLs, SMTP
+//! passwords) are sealed with the master key before they are stored, bound to the
+//! channel, and masked whenever settings are returned. Webhook and Slack requests may
+//! only reach loopback, private and link-local addresses the server allows.
+
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::Utc;
+use ipnet::IpNet;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use synapse_core::project::{AlertSeverity, ChannelType};
+use synapse_core::secrets::{self, Keyring};
+use tokio::io::AsyncWriteExt;
+
+use super::templates::NotificationMessage;
+
+/// Most of a failed command's stderr kept in the delivery log
+const MAX_ERROR_OUTPUT: usize = 1000;
+const MAX_COMMAND_TIMEOUT_SECS: u64 = 300;
+const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+const HTTP_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Server settings limiting what channels may reach
+#[derive(Debug, Clone, Default)]
+pub struct ChannelLimits {
+    /// Programs command channels may run
+    pub commands: Vec<String>,
+    /// Loopback, private and link-local networks webhook and Slack channels may still reach
+    pub allowed_networks: Vec<IpNet>,
+}
+
+/// How an email channel secures its SMTP connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Plain connection, for local relays only
+    None,
+    /// Upgrade a plain connection, usually on port 587
+    #[default]
+    Starttls,
+    /// TLS from the start, usually on port 465
+    Tls,
+}
+
+/// A channel's settings, stored as JSON tagged by "type"
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ChannelConfig {
+    Webhook {
+        url: String,
+        /// Signs each request with HMAC-SHA256 when set
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        secret: Option<String>,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        headers: BTreeMap<String, String>,
+    },
+    /// Slack or Mattermost incoming webhook
+    Slack {
+        webhook_url: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        channel: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        username: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        icon_emoji: Option<String>,
+    },
+    Email {
+        host: String,
+        #[serde(default = "default_smtp_port")]
+        port: u16,
+        #[serde(default)]
+        tls: SmtpTls,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        username: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        password: Option<String>,
+        from: String,
+        to: Vec<String>,
+    },
+    /// A program on the server; it must be listed in `SYNAPSE_NOTIFY_COMMANDS`
+    Command {
+        command: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        args: Vec<String>,
+        #[serde(default = "default_command_timeout")]
+        timeout_secs: u64,
+    },
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+fn default_command_timeout() -> u64 {
+    30
+}
+
+/// Why an attempt failed, and whether trying again could help
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeliveryError {
+    pub message: String,
+    /// HTTP status, SMTP reply code or exit code, when there was one
+    pub status: Option<i32>,
+    pub retryable: bool,
+}
+
+impl DeliveryError {
+    fn retryable(message: impl Into<String>, status: Option<i32>) -> Self {
+        Self { message: message.into(), status, retryable: true }
+    }
+
+    fn permanent(message: impl Into<String>, status: Option<i32>) -> Self {
+        Self { message: message.into(), status, retryable: false }
+    }
+}
+
+impl std::fmt::Display for DeliveryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Context secrets are sealed with, so they can't be moved to another channel
+pub fn secret_context(channel_id: &str) -> String {
+    format!("notification_channel:{}", channel_id)
+}
+
+impl ChannelConfig {
+    pub fn channel_type(&self) -> ChannelType {
+        match self {
+            ChannelConfig::Webhook { .. } => ChannelType::Webhook,
+            ChannelConfig::Slack { .. } => ChannelType::Slack,
+            ChannelConfig::Email { .. } => ChannelType::Email,
+            ChannelConfig::Command { .. } => ChannelType::Command,
+        }
+    }
+
+    /// Check settings before they are stored, after [`ChannelConfig::keep_masked_secrets`];
+    /// `commands` are the programs allowed to run
+    pub fn validate(&self, commands: &[String]) -> Result<(), String> {
+        if self.clone().secrets_mut().iter().any(|secret| secrets::is_masked(secret)) {
+            return Err("A masked secret can only be sent back unchanged for the channel it came from".to_string());
+        }
+        let http_url = |name: &str, url: &str| {
+            // A sealed URL was checked when it was first saved
+            if url.starts_with("http://") || url.starts_with("https://") || secrets::is_sealed_string(url) {
+                Ok(())
+            } else {
+                Err(format!("{} must be an http:// or https:// URL", name))
+            }
+        };
+
+        match self {
+            ChannelConfig::Webhook { url, .. } => http_url("url", url),
+            ChannelConfig::Slack { webhook_url, .. } => http_url("webhook_url", webhook_url),
+            ChannelConfig::Email { host, from, to, .. } => {
+                if host.trim().is_empty() {
+                    return Err("SMTP host is required".to_string());
+                }
+                if to.is_empty() {
+                    return Err("At least one recipient is required".to_string());
+                }
+                for address in std::iter::once(from).chain(to) {
+                    address
+                        .parse::<Mailbox>()
+                        .map_err(|e| format!("Invalid email address '{}': {}", address, e))?;
+                }
+                Ok(())
+            }
+            ChannelConfig::Command { command, timeout_secs, .. } => {
+                if !commands.contains(command) {
+                    return Err(format!(
+                        "Command '{}' is not allowed; list it in SYNAPSE_NOTIFY_COMMANDS to use it",
+                        command
+                    ));
+                }
+                if !(1..=MAX_COMMAND_TIMEOUT_SECS).contains(timeout_secs) {
+                    return Err(format!(
+                        "Command timeout must be between 1 and {} seconds",
+                        MAX_COMMAND_TIMEOUT_SECS
+                    ));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Check that a webhook or Slack URL only leads to addresses channels may reach
+    ///
+    /// Sealed URLs were checked when they were saved; every delivery checks again.
+    pub async fn check_destination(&self, allowed_networks: &[IpNet]) -> Result<(), String> {
+        let url = match self {
+            ChannelConfig::Webhook { url, .. } => url,
+            ChannelConfig::Slack { webhook_url, .. } => webhook_url,
+            ChannelConfig::Email { .. } | ChannelConfig::Command { .. } => return Ok(()),
+        };
+        if secrets::is_sealed_string(url) {
+            return Ok(());
+        }
+        resolve_destination(url, allowed_networks)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.message)
+    }
+
+    fn secrets_mut(&mut self) -> Vec<&mut String> {
+        match self {
+            ChannelConfig::Webhook { secret, .. } => secret.iter_mut().collect(),
+            ChannelConfig::Slack { webhook_url, .. } => vec![webhook_url],
+            ChannelConfig::Email { password, .. } => password.iter_mut().collect(),
+            ChannelConfig::Command { .. } => Vec::new(),
+        }
+    }
+
+    /// Keep the stored secret wherever a client sent back its masked form
+    pub fn keep_masked_secrets(&mut self, stored: &ChannelConfig) {
+        let mut stored = stored.clone();
+        if stored.channel_type() != self.channel_type() {
+            return;
+        }
+        for (secret, stored) in self.secrets_mut().into_iter().zip(stored.secrets_mut()) {
+            if secrets::is_masked(secret) {
+                *secret = stored.clone();
+            }
+        }
+    }
+
+    /// Seal secrets that aren't sealed yet
+    pub fn seal(&mut self, keyring: &Keyring, channel_id: &str) -> Result<()> {
+        let context = secret_context(channel_id);
+        for secret in self.secrets_mut() {
+            if !secrets::is_sealed_string(secret) {
+                *secret = keyring.seal_to_string(&context, secret)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// A copy with secrets in plaintext, for delivering
+    pub fn open(&self, keyring: &Keyring, channel_id: &str) -> Result<ChannelConfig> {
+        let context = secret_context(channel_id);
+        let mut opened = self.clone();
+        for secret in opened.secrets_mut() {
+            if secrets::is_sealed_string(secret) {
+                *secret = keyring.open_string(&context, secret)?;
+            }
+        }
+        Ok(opened)
+    }
+
+    /// A copy with secrets masked, for showing to clients
+    pub fn masked(&self, keyring: &Keyring, channel_id: &str) -> ChannelConfig {
+        let mut masked = self.open(keyring, channel_id).unwrap_or_else(|_| self.clone());
+        for secret in masked.secrets_mut() {
+            *secret = secrets::mask(secret);
+        }
+        masked
+    }
+
+    /// Re-encrypt secrets sealed with a previous master key; returns whether any were
+    pub fn reseal(&mut self, keyring: &Keyring, channel_id: &str) -> Result<bool> {
+        let context = secret_context(channel_id);
+        let mut resealed = false;
+        for secret in self.secrets_mut() {
+            if keyring.needs_reseal_string(secret) {
+                let plaintext = keyring.open_string(&context, secret)?;
+                *secret = keyring.seal_to_string(&context, &plaintext)?;
+                resealed = true;
+            }
+        }
+        Ok(resealed)
+    }
+}
+
+/// Send a message over a channel whose secrets are open; returns the response status, if any
+pub async fn deliver(
+    config: &ChannelConfig,
+    message: &NotificationMessage,
+    delivery_id: &str,
+    limits: &ChannelLimits,
+) -> Result<Option<i32>, DeliveryError> {
+    match config {
+        ChannelConfig::Webhook { url, secret, headers } => {
+            let body = serde_json::to_vec(message).map_err(|e| DeliveryError::permanent(e.to_string(), None))?;
+            let timestamp = Utc::now().timestamp().to_string();
+            let mut request = http_client(url, &limits.allowed_networks)
+                .await?
+                .post(url)
+                .header("Content-Type", "application/json")
+                .header("X-Synapse-Event", &message.event)
+                .header("X-Synapse-Delivery", delivery_id)
+                .header("X-Synapse-Timestamp", &timestamp);
+            if let Some(secret) = secret {
+                request = request.header("X-Synapse-Signature", sign(secret, &timestamp, &body));
+            }
+            for (name, value) in headers {
+                request = request.header(name, value);
+            }
+            send_http(request.body(body)).await
+        }
+        ChannelConfig::Slack { webhook_url, channel, username, icon_emoji } => {
+            let payload = slack_payload(message, channel, username, icon_emoji);
+            let client = http_client(webhook_url, &limits.allowed_networks).await?;
+            send_http(client.post(webhook_url).json(&payload)).await
+        }
+        ChannelConfig::Email { host, port, tls, username, password, from, to } => {
+            send_email(host, *port, *tls, username.as_deref(), password.as_deref(), from, to, message).await
+        }
+        ChannelConfig::Command { command, args, timeout_secs } => {
+            // Checked again here: the allowlist may have shrunk since the channel was saved
+            if !limits.commands.contains(command) {
+                return Err(DeliveryError::permanent(
+                    format!("Command '{}' is no longer allowed by SYNAPSE_NOTIFY_COMMANDS", command),
+                    None,
+                ));
+            }
+            run_command(command, args, Duration::from_secs(*timeout_secs), message).await
+        }
+    }
+}
+
+/// `sha256=<hex HMAC-SHA256 of "{timestamp}.{body}">`
+pub fn sign(secret: &str, timestamp: &str, body: &[u8]) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let mut context = hmac::Context::with_key(&key);
+    context.update(timestamp.as_bytes());
+    context.update(b".");
+    context.update(body);
+    format!("sha256={}", hex::encode(context.sign().as_ref()))
+}
+
+/// A client for one request to `url`, pinned to the addresses its host resolved to
+///
+/// Pinning keeps a second lookup from answering with an address that wasn't checked, and
+/// redirects are not followed, so a receiver can't send the request on somewhere else.
+async fn http_client(url: &str, allowed_networks: &[IpNet]) -> Result<reqwest::Client, DeliveryError> {
+    let (host, addresses) = resolve_destination(url, allowed_networks).await?;
+    reqwest::Client::builder()
+        .timeout(HTTP_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .resolve_to_addrs(&host, &addresses)
+        .build()
+        .map_err(|e| DeliveryError::retryable(format!("Failed to create HTTP client: {}", e), None))
+}
+
+/// Resolve a webhook URL's host, rejecting it when any address is internal and not allowed
+async fn resolve_destination(
+    url: &str,
+    allowed_networks: &[IpNet],
+) -> Result<(String, Vec<SocketAddr>), DeliveryError> {
+    let parsed = reqwest::Url::parse(url)
+        .map_err(|e| DeliveryError::permanent(format!("Invalid URL: {}", e), None))?;
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| DeliveryError::permanent("URL has no host", None))?
+        .to_string();
+    let port = parsed.port_or_known_default().unwrap_or(80);
+    // IPv6 hosts keep their brackets in URLs
+    let addresses: Vec<SocketAddr> = match host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        Ok(ip) => vec![SocketAddr::new(ip, port)],
+        Err(_) => tokio::net::lookup_host((host.as_str(), port))
+            .await
+            .map_err(|e| DeliveryError::retryable(format!("Failed to resolve {}: {}", host, e), None))?
+            .collect(),
+    };
+
+    for address in &addresses {
+        let ip = address.ip();
+        if is_internal_address(ip) && !allowed_networks.iter().any(|network| network.contains(&ip)) {
+            return Err(DeliveryError::permanent(
+                format!(
+                    "{} resolves to the internal address {}; list its network in SYNAPSE_NOTIFY_ALLOWED_NETWORKS to send to it",
+                    host, ip
+                ),
+                None,
+            ));
+        }
+    }
+    if addresses.is_empty() {
+        return Err(DeliveryError::retryable(format!("{} has no addresses", host), None));
+    }
+    Ok((host, addresses))
+}
+
+/// Loopback, private, link-local, shared and other addresses that aren't public hosts
+pub fn is_internal_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_internal_ipv4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_internal_ipv4(ip),
+            None => {
+                let first = ip.segments()[0];
+                ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // Unique local fc00::/7 and link-local fe80::/10
+                    || first & 0xfe00 == 0xfc00
+                    || first & 0xffc0 == 0xfe80
+            }
+        },
+    }
+}
+
+fn is_internal_ipv4(ip: Ipv4Addr) -> bool {
+    let [first, second, ..] = ip.octets();
+    ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || first == 0
+        // Carrier-grade NAT 100.64.0.0/10
+        || (first == 100 && second & 0xc0 == 64)
+}
+
+async fn send_http(request: reqwest::RequestBuilder) -> Result<Option<i32>, DeliveryError> {
+    let response = request
+        .send()
+        .await
+        .map_err(|e| DeliveryError::retryable(format!("Request failed: {}", e), None))?;
+    let status = response.status();
+    let code = Some(status.as_u16() as i32);
+    if status.is_success() {
+        return Ok(code);
+    }
+
+    let body = response.text().await.unwrap_or_default();
+    let message = format!("Receiver answered {}: {}", status, truncate(body.trim(), MAX_ERROR_OUTPUT));
+    // Redirects aren't followed, and other client errors mean the request itself is wrong,
+    // so repeating it won't help
+    if status.is_redirection() || (status.is_client_error() && status.as_u16() != 408 && status.as_u16() != 429) {
+        Err(DeliveryError::permanent(message, code))
+    } else {
+        Err(DeliveryError::retryable(message, code))
+    }
+}
+
+fn slack_payload(
+    message: &NotificationMessage,
+    channel: &Option<String>,
+    username: &Option<String>,
+    icon_emoji: &Option<String>,
+) -> serde_json::Value {
+    let color = match message.severity {
+        AlertSeverity::Critical => "#d00000",
+        AlertSeverity::Warning => "#daa038",
+        AlertSeverity::Info => "#439fe0",
+    };
+    let fields: Vec<_> = message
+        .fields
+        .iter()
+        .map(|field| json!({ "title": field.name, "value": field.value, "short": true }))
+        .collect();
+
+    let mut payload = json!({
+        "text": message.title,
+        "attachments": [{
+            "color": color,
+            "title": message.title,
+            "title_link": message.link,
+            "text": message.text,
+            "fields": fields,
+        }],
+    });
+    for (name, value) in [("channel", channel), ("username", username), ("icon_emoji", icon_emoji)] {
+        if let Some(value) = value {
+            payload[name] = json!(value);
+        }
+    }
+    payload
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn send_email(
+    host: &str,
+    port: u16,
+    tls: SmtpTls,
+    username: Option<&str>,
+    password: Option<&str>,
+    from: &str,
+    to: &[String],
+    message: &NotificationMessage,
+) -> Result<Option<i32>, DeliveryError> {
+    let invalid = |e: &dyn std::fmt::Display| DeliveryError::permanent(e.to_string(), None);
+
+    let mut builder = lettre::Message::builder()
+        .from(from.parse::<Mailbox>().map_err(|e| invalid(&e))?)
+        .subject(message.title.clone());
+    for address in to {
+        builder = builder.to(address.parse::<Mailbox>().map_err(|e| invalid(&e))?);
+    }
+    let email = builder.body(email_body(message)).map_err(|e| invalid(&e))?;
+
+    let transport = match tls {
+        SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+        SmtpTls::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host).map_err(|e| invalid(&e))?,
+        SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host).map_err(|e| invalid(&e))?,
+    };
+    let mut transport = transport.port(port).timeout(Some(SMTP_TIMEOUT));
+    if let Some(username) = username {
+        transport = transport.credentials(Credentials::new(
+            username.to_string(),
+            password.unwrap_or_default().to_string(),
+        ));
+    }
+
+    match transport.build().send(email).await {
+        Ok(response) => Ok(Some(u16::from(response.code()) as i32)),
+        Err(e) => {
+            let status = e.status().map(|code| u16::from(code) as i32);
+            let message = format!("SMTP delivery failed: {}", e);
+            if e.is_permanent() {
+                Err(DeliveryError::permanent(message, status))
+            } else {
+                Err(DeliveryError::retryable(message, status))
+            }
+        }
+    }
+}
+
+fn email_body(message: &NotificationMessage) -> String {
+    let mut body = message.text.clone();
+    if !message.fields.is_empty() {
+        body.push_str("\n\n");
+        for field in &message.fields {
+            body.push_str(&format!("{}: {}\n", field.name, field.value));
+        }
+    }
+    if let Some(link) = &message.link {
+        body.push_str(&format!("\n{}\n", link));
+    }
+    body
+}
+
+/// Run the program without a shell, with the message as JSON on stdin
+async fn run_command(
+    command: &str,
+    args: &[String],
+    timeout: Duration,
+    message: &NotificationMessage,
+) -> Result<Option<i32>, DeliveryError> {
+    let payload = serde_json::to_vec(message).map_err(|e| DeliveryError::permanent(e.to_string(), None))?;
+    let mut child = tokio::process::Command::new(command)
+        .args(args)
+        .env("SYNAPSE_EVENT", &message.event)
+        .env("SYNAPSE_TITLE", &message.title)
+        .env("SYNAPSE_SEVERITY", message.severity.as_str())
+        .env("SYNAPSE_PROJECT_ID", &message.project_id)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| DeliveryError::retryable(format!("Failed to start {}: {}", command, e), None))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // A program that ignores its input closes the pipe early; that's not a failure
+        let _ = stdin.write_all(&payload).await;
+    }
+
+    let output = tokio::time::timeout(timeout, child.wait_with_output())
+        .await
+        .map_err(|_| DeliveryError::retryable(format!("{} timed out after {:?}", command, timeout), None))?
+        .map_err(|e| DeliveryError::retryable(format!("Failed to run {}: {}", command, e), None))?;
+
+    let code = output.status.code();
+    if output.status.success() {
+        return Ok(code);
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let stderr = stderr.trim();
+    let tail = &stderr[stderr.len() - truncate(stderr, MAX_ERROR_OUTPUT).len()..];
+    Err(DeliveryError::retryable(
+        format!("{} exited with {}: {}", command, output.status, tail),
+        code,
+    ))
+}
+
+/// At most `max` bytes of `text`, cut at a character boundary
+fn truncate(text: &str, max: usize) -> &str {
+    if text.len() <= max {
+        return text;
+    }
+    let mut end = max;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use synapse_core::secrets::MasterKey;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+    use tokio::net::TcpListener;
+
+    fn message() -> NotificationMessage {
+        NotificationMessage::test("p1", "ops", "http://localhost:3000")
+    }
+
+    /// Limits that let channels reach the mock receivers on loopback
+    fn loopback() -> ChannelLimits {
+        ChannelLimits {
+            commands: Vec::new(),
+            allowed_networks: vec!["127.0.0.0/8".parse().unwrap()],
+        }
+    }
+
+    /// Answer one HTTP request with `status`, handing back its head and body
+    async fn mock_http(status: u16) -> (String, tokio::task::JoinHandle<(String, Vec<u8>)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut head = String::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).await.unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                head.push_str(&line);
+            }
+            let length = head
+                .lines()
+                .find_map(|line| line.to_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse().unwrap()))
+                .unwrap_or(0);
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).await.unwrap();
+            let response = format!("HTTP/1.1 {} Mock\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok", status);
+            reader.get_mut().write_all(response.as_bytes()).await.unwrap();
+            (head.to_lowercase(), body)
+        });
+        (url, server)
+    }
+
+    fn header<'a>(head: &'a str, name: &str) -> &'a str {
+        head.lines()
+            .find_map(|line| line.strip_prefix(&format!("{}: ", name)))
+            .unwrap()
+            .trim()
+    }
+
+    #[tokio::test]
+    async fn test_webhook_is_signed() {
+        let (url, server) = mock_http(200).await;
+        let config = ChannelConfig::Webhook {
+            url,
+            secret: Some("s3cret".to_string()),
+            headers: BTreeMap::from([("X-Team".to_string(), "ops".to_string())]),
+        };
+        let status = deliver(&config, &message(), "d1", &loopback()).await.unwrap();
+        assert_eq!(status, Some(200));
+
+        let (head, body) = server.await.unwrap();
+        assert_eq!(header(&head, "x-synapse-event"), "test");
+        assert_eq!(header(&head, "x-synapse-delivery"), "d1");
+        assert_eq!(header(&head, "x-team"), "ops");
+        let timestamp = header(&head, "x-synapse-timestamp");
+        assert_eq!(header(&head, "x-synapse-signature"), sign("s3cret", timestamp, &body));
+        let sent: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(sent["title"], "Synapse test notification");
+    }
+
+    #[tokio::test]
+    async fn test_http_errors_retry_only_when_it_can_help() {
+        // Redirects are not followed
+        for (status, retryable) in [(500, true), (429, true), (404, false), (302, false)] {
+            let (webhook_url, server) = mock_http(status).await;
+            let config = ChannelConfig::Slack { webhook_url, channel: None, username: None, icon_emoji: None };
+            let error = deliver(&config, &message(), "d1", &loopback()).await.unwrap_err();
+            assert_eq!(error.status, Some(status as i32));
+            assert_eq!(error.retryable, retryable, "status {}", status);
+
+            let (_, body) = server.await.unwrap();
+            let sent: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(sent["attachments"][0]["color"], "#439fe0");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_email_through_smtp_sink() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // Just enough SMTP to accept one message
+        let sink = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut reader = BufReader::new(stream);
+            reader.get_mut().write_all(b"220 sink ESMTP\r\n").await.unwrap();
+            let mut data = String::new();
+            let mut in_data = false;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).await.unwrap() == 0 {
+                    break;
+                }
+                if in_data {
+                    if line == ".\r\n" {
+                        in_data = false;
+                        reader.get_mut().write_all(b"250 queued\r\n").await.unwrap();
+                    } else {
+                        data.push_str(&line);
+                    }
+                    continue;
+                }
+                let reply: &[u8] = match line.get(..4).unwrap_or_default().to_uppercase().as_str() {
+                    "EHLO" => b"250-sink\r\n250 OK\r\n",
+                    "DATA" => {
+                        in_data = true;
+                        b"354 go ahead\r\n"
+                    }
+                    "QUIT" => {
+                        reader.get_mut().write_all(b"221 bye\r\n").await.unwrap();
+                        break;
+                    }
+                    _ => b"250 OK\r\n",
+                };
+                reader.get_mut().write_all(reply).await.unwrap();
+            }
+            data
+        });
+
+        let config = ChannelConfig::Email {
+            host: "127.0.0.1".to_string(),
+            port,
+            tls: SmtpTls::None,
+            username: None,
+            password: None,
+            from: "synapse@example.com".to_string(),
+            to: vec!["oncall@example.com".to_string()],
+        };
+        let status = deliver(&config, &message(), "d1", &ChannelLimits::default()).await.unwrap();
+        assert_eq!(status, Some(250));
+
+        let data = sink.await.unwrap();
+        assert!(data.contains("Subject: Synapse test notification"));
+        assert!(data.contains("To: oncall@example.com"));
+        assert!(data.contains("is set up correctly"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_command_gets_message_on_stdin() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let out = dir.path().join("out.json");
+        let limits = ChannelLimits { commands: vec!["/bin/sh".to_string()], ..Default::default() };
+        let config = ChannelConfig::Command {
+            command: "/bin/sh".to_string(),
+            args: vec!["-c".to_string(), format!("cat > {} && echo \"$SYNAPSE_EVENT\" >&2 && exit 3", out.display())],
+            timeout_secs: 5,
+        };
+
+        let error = deliver(&config, &message(), "d1", &limits).await.unwrap_err();
+        assert_eq!(error.status, Some(3));
+        assert!(error.message.ends_with("test"));
+        let sent: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&out).unwrap()).unwrap();
+        assert_eq!(sent["event"], "test");
+
+        // Off the allowlist it doesn't run at all
+        assert!(config.validate(&[]).is_err());
+        let error = deliver(&config, &message(), "d1", &ChannelLimits::default()).await.unwrap_err();
+        assert!(!error.retryable);
+    }
+
+    #[tokio::test]
+    async fn test_internal_addresses_need_an_allowed_network() {
+        let (url, server) = mock_http(200).await;
+        let config = ChannelConfig::Webhook { url: url.clone(), secret: None, headers: BTreeMap::new() };
+        let error = deliver(&config, &message(), "d1", &ChannelLimits::default()).await.unwrap_err();
+        assert!(!error.retryable);
+        assert!(error.message.contains("SYNAPSE_NOTIFY_ALLOWED_NETWORKS"));
+        assert!(config.check_destination(&[]).await.is_err());
+        assert!(config.check_destination(&loopback().allowed_networks).await.is_ok());
+
+        // Names are checked by the addresses they resolve to
+        let by_name = ChannelConfig::Webhook {
+            url: url.replace("127.0.0.1", "localhost"),
+            secret: None,
+            headers: BTreeMap::new(),
+        };
+        assert!(by_name.check_destination(&[]).await.is_err());
+        server.abort();
+
+        for internal in ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.64.0.1", "0.0.0.0", "::1", "fd00::1", "fe80::1", "::ffff:169.254.169.254"] {
+            assert!(is_internal_address(internal.parse().unwrap()), "{}", internal);
+        }
+        for public in ["93.184.216.34", "8.8.8.8", "2606:4700:4700::1111"] {
+            assert!(!is_internal_address(public.parse().unwrap()), "{}", public);
+        }
+    }
+
+    #[test]
+    fn test_secrets_are_sealed_and_masked() {
+        let keyring = Keyring::new(MasterKey::generate().unwrap(), Vec::new());
+        let mut config: ChannelConfig = serde_json::from_value(json!({
+            "type": "slack",
+            "webhook_url": "https://hooks.slack.com/services/T000/B000/XXXXXXXX",
+        }))
+        .unwrap();
+        config.seal(&keyring, "c1").unwrap();
+        let ChannelConfig::Slack { webhook_url, .. } = &config else { unreachable!() };
+        assert!(secrets::is_sealed_string(webhook_url));
+        assert!(config.open(&keyring, "c2").is_err());
+
+        let masked = config.masked(&keyring, "c1");
+        let ChannelConfig::Slack { webhook_url: shown, .. } = &masked else { unreachable!() };
+        assert!(secrets::is_masked(shown) && shown.ends_with("XXXX"));
+
+        // Saving the masked form back keeps the stored URL
+        let mut update = masked.clone();
+        update.keep_masked_secrets(&config);
+        update.seal(&keyring, "c1").unwrap();
+        assert_eq!(update, config);
+        let ChannelConfig::Slack { webhook_url, .. } = config.open(&keyring, "c1").unwrap() else { unreachable!() };
+        assert_eq!(webhook_url, "https://hooks.slack.com/services/T000/B000/XXXXXXXX");
+    }
+}
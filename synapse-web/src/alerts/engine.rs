@@ -0,0 +1,283 @@
+//! Evaluates each project's enabled alert rules against the batches flowing through
+//! the streaming hub, and records what they find
+//!
+//! A detection whose rule already has an open alert with the same fingerprint is
+//! counted on that alert, even while the rule is silenced. Otherwise a new alert
+//! fires, unless the rule is silenced or the last alert for the fingerprint was seen
+//! within the rule's cooldown.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use synapse_core::project::{Alert, AlertRule, Storage};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, info, warn};
+
+use super::rules::{AlertCondition, Detection, RuleEvaluator};
+use super::NotificationManager;
+use crate::streaming::{LogBatch, StreamingHub, StreamingMessage};
+
+struct ActiveRule {
+    rule: AlertRule,
+    evaluator: RuleEvaluator,
+}
+
+pub struct AlertEngine {
+    storage: Arc<dyn Storage>,
+    /// Enabled rules by id, with the state their conditions keep between batches
+    rules: Mutex<HashMap<String, ActiveRule>>,
+    notifications: NotificationManager,
+}
+
+impl AlertEngine {
+    pub fn new(storage: Arc<dyn Storage>, notifications: NotificationManager) -> Self {
+        Self {
+            storage,
+            rules: Mutex::new(HashMap::new()),
+            notifications,
+        }
+    }
+
+    /// Load the enabled rules and evaluate every batch the hub broadcasts from now on
+    pub async fn start(
+        storage: Arc<dyn Storage>,
+        hub: &StreamingHub,
+        notifications: NotificationManager,
+    ) -> Arc<Self> {
+        let engine = Arc::new(Self::new(storage, notifications));
+        if let Err(e) = engine.reload().await {
+            warn!("Failed to load alert rules: {}", e);
+        }
+
+        let mut receiver = hub.sender.subscribe();
+        let worker = engine.clone();
+        tokio::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(StreamingMessage::LogBatch(batch)) => {
+                        if let Err(e) = worker.process_batch(&batch).await {
+                            warn!("Failed to evaluate alert rules for batch {}: {}", batch.batch_id, e);
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Alert engine fell behind; {} streaming messages were not evaluated", skipped);
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+
+        engine
+    }
+
+    pub fn notifications(&self) -> &NotificationManager {
+        &self.notifications
+    }
+
+    /// Pick up changed rules; a rule whose condition is unchanged keeps its evaluation state
+    pub async fn reload(&self) -> Result<()> {
+        let enabled = self.storage.list_enabled_alert_rules().await?;
+
+        let mut rules = self.rules.lock().unwrap();
+        let mut previous = std::mem::take(&mut *rules);
+        for rule in enabled {
+            let evaluator = match previous.remove(&rule.id) {
+                Some(active) if active.rule.condition == rule.condition => active.evaluator,
+                _ => match AlertCondition::from_rule(&rule).and_then(|condition| condition.compile()) {
+                    Ok(evaluator) => evaluator,
+                    Err(e) => {
+                        warn!("Skipping alert rule {} ({}): {}", rule.name, rule.id, e);
+                        continue;
+                    }
+                },
+            };
+            rules.insert(rule.id.clone(), ActiveRule { rule, evaluator });
+        }
+
+        debug!("Loaded {} alert rules", rules.len());
+        Ok(())
+    }
+
+    /// Evaluate a batch against its project's rules; returns the alerts it fired
+    pub async fn process_batch(&self, batch: &LogBatch) -> Result<Vec<Alert>> {
+        let now = Utc::now();
+        let project_id = batch.project_id.to_string();
+
+        let detections: Vec<(AlertRule, Vec<Detection>)> = {
+            let mut rules = self.rules.lock().unwrap();
+            rules
+                .values_mut()
+                .filter(|active| active.rule.project_id == project_id)
+                .filter(|active| active.rule.source.as_deref().is_none_or(|source| source == batch.source))
+                .filter_map(|active| {
+                    // Silenced rules still evaluate, so windows stay accurate when the silence ends
+                    let detections = active.evaluator.evaluate(batch, now);
+                    (!detections.is_empty()).then(|| (active.rule.clone(), detections))
+                })
+                .collect()
+        };
+
+        let mut fired = Vec::new();
+        for (rule, detections) in detections {
+            for detection in detections {
+                if let Some(alert) = self.record(&rule, &batch.source, detection, now).await? {
+                    fired.push(alert);
+                }
+            }
+        }
+        Ok(fired)
+    }
+
+    async fn record(
+        &self,
+        rule: &AlertRule,
+        source: &str,
+        detection: Detection,
+        now: DateTime<Utc>,
+    ) -> Result<Option<Alert>> {
+        if let Some(latest) = self.storage.find_latest_alert(&rule.id, &detection.fingerprint).await? {
+            if latest.is_open()
+                && self
+                    .storage
+                    .record_alert_occurrences(&latest.id, detection.occurrences, now)
+                    .await?
+            {
+                return Ok(None);
+            }
+            if detection.once {
+                return Ok(None);
+            }
+            if latest.last_seen_at + Duration::seconds(rule.cooldown_secs) > now {
+                debug!(
+                    "Alert rule {} matched {} again within its cooldown; not firing",
+                    rule.id, detection.fingerprint
+                );
+                return Ok(None);
+            }
+        }
+
+        if rule.is_silenced() {
+            debug!("Alert rule {} is silenced; not firing for {}", rule.id, detection.fingerprint);
+            return Ok(None);
+        }
+
+        let alert = Alert::new(rule, detection.fingerprint, detection.summary)
+            .with_source(source)
+            .with_details(detection.details);
+        let alert = Alert { occurrences: detection.occurrences, ..alert };
+        self.storage.create_alert(&alert).await?;
+        self.storage.record_alert_rule_fired(&rule.id, now).await?;
+        if let Some(active) = self.rules.lock().unwrap().get_mut(&rule.id) {
+            active.rule.last_fired_at = Some(now);
+        }
+
+        info!(
+            "Alert fired: {} [{}] for project {}: {}",
+            rule.name, alert.severity, alert.project_id, alert.summary
+        );
+        self.notifications.alert_fired(&alert).await;
+        Ok(Some(alert))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::streaming::StreamingLogEntry;
+    use crate::config::WebConfig;
+    use synapse_core::project::{connect_storage, AlertQuery, AlertStatus, Project};
+    use synapse_core::secrets::{Keyring, MasterKey};
+    use tempfile::TempDir;
+    use tokio::sync::RwLock;
+    use uuid::Uuid;
+
+    async fn storage(dir: &TempDir) -> Arc<dyn Storage> {
+        let url = format!("sqlite:{}?mode=rwc", dir.path().join("alerts.db").display());
+        connect_storage(&url, 1).await.unwrap()
+    }
+
+    fn batch(project_id: Uuid, source: &str, levels: &[&str]) -> LogBatch {
+        LogBatch {
+            batch_id: Uuid::new_v4().to_string(),
+            timestamp: Utc::now().to_rfc3339(),
+            entries: levels
+                .iter()
+                .map(|level| StreamingLogEntry {
+                    id: Uuid::new_v4().to_string(),
+                    timestamp: None,
+                    level: Some(level.to_string()),
+                    message: format!("{} happened", level),
+                    source: source.to_string(),
+                    project_id,
+                    line_number: None,
+                    metadata: HashMap::new(),
+                })
+                .collect(),
+            source: source.to_string(),
+            project_id,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dedup_cooldown_and_silence() {
+        let dir = TempDir::new().unwrap();
+        let storage = storage(&dir).await;
+        let project = Project::new("alerts".to_string(), None);
+        storage.insert_project(&project).await.unwrap();
+        let project_id = Uuid::parse_str(&project.id).unwrap();
+
+        let mut rule = AlertRule::new(
+            project.id.clone(),
+            "errors".to_string(),
+            r#"{"type":"level_threshold","level":"ERROR"}"#.to_string(),
+        );
+        rule.source = Some("api".to_string());
+        storage.create_alert_rule(&rule).await.unwrap();
+
+        let keyring = Keyring::new(MasterKey::generate().unwrap(), Vec::new());
+        let notifications = NotificationManager::new(storage.clone(), Arc::new(RwLock::new(keyring)), &WebConfig::default());
+        let engine = AlertEngine::new(storage.clone(), notifications);
+        engine.reload().await.unwrap();
+        let mut notifications = engine.notifications().subscribe();
+
+        // Other sources and quiet batches don't fire
+        assert!(engine.process_batch(&batch(project_id, "web", &["ERROR"])).await.unwrap().is_empty());
+        assert!(engine.process_batch(&batch(project_id, "api", &["INFO"])).await.unwrap().is_empty());
+
+        let fired = engine.process_batch(&batch(project_id, "api", &["ERROR", "ERROR"])).await.unwrap();
+        assert_eq!(fired.len(), 1);
+        assert_eq!(notifications.try_recv().unwrap().alert.id, fired[0].id);
+
+        // Repeats are counted on the open alert
+        assert!(engine.process_batch(&batch(project_id, "api", &["FATAL"])).await.unwrap().is_empty());
+        let alert = storage.get_alert(&fired[0].id).await.unwrap().unwrap();
+        assert_eq!(alert.occurrences, 3);
+        assert!(notifications.try_recv().is_err());
+
+        // Within the cooldown a resolved alert doesn't fire again
+        assert!(storage.resolve_alert(&project.id, &alert.id).await.unwrap());
+        assert!(engine.process_batch(&batch(project_id, "api", &["ERROR"])).await.unwrap().is_empty());
+
+        rule.cooldown_secs = 0;
+        rule.silenced_until = Some(Utc::now() + Duration::minutes(10));
+        storage.update_alert_rule(&rule).await.unwrap();
+        engine.reload().await.unwrap();
+        assert!(engine.process_batch(&batch(project_id, "api", &["ERROR"])).await.unwrap().is_empty());
+
+        rule.silenced_until = None;
+        storage.update_alert_rule(&rule).await.unwrap();
+        engine.reload().await.unwrap();
+        assert_eq!(engine.process_batch(&batch(project_id, "api", &["ERROR"])).await.unwrap().len(), 1);
+
+        let history = storage
+            .query_alerts(&AlertQuery { project_id: Some(project.id.clone()), ..Default::default() })
+            .await
+            .unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].status, AlertStatus::Firing);
+        assert!(storage.get_alert_rule(&rule.id).await.unwrap().unwrap().last_fired_at.is_some());
+    }
+}
@@ -1,14 +1,15 @@
-/// Phase 6.3: Alert & Notification System
-/// 
-/// This module implements a comprehensive alert and notification system that builds
-/// upon the streaming infrastructure from Phase 6.1 and dashboard from Phase 6.2.
+//! Alerts on projects' streaming logs
+//!
+//! Rules are stored per project and evaluated by the engine against every batch the
+//! streaming hub broadcasts. Fired alerts are kept as history and sent, like finished
+//! analyses, to the project's notification channels.
 
+pub mod channels;
 pub mod engine;
 pub mod notifications;
 pub mod rules;
-pub mod channels;
+pub mod templates;
 
-pub use engine::{AlertEngine, AlertManager};
-pub use notifications::{NotificationManager, AlertNotification};
-pub use rules::{AlertRule, AlertCondition, AlertSeverity};
-pub use channels::{NotificationChannel, ChannelType};
\ No newline at end of file
+pub use engine::AlertEngine;
+pub use notifications::NotificationManager;
+pub use rules::{AlertCondition, AlertRule, AlertSeverity};
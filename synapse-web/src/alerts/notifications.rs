@@ -0,0 +1,466 @@
+//! Fan-out of newly fired alerts and finished analyses to notification channels
+//!
+//! Each event is rendered once per matching channel and queued in the delivery log.
+//! A worker sends due deliveries, retrying failures with exponential backoff until
+//! they succeed, the channel rejects them outright, or attempts run out. In-process
+//! subscribers also receive every fired alert.
+
+use std::sync::Arc;
+use std::time::Duration as StdDuration;
+
+use anyhow::{Context, Result};
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use synapse_core::project::{
+    Alert, Analysis, DeliveryStatus, JobEvent, JobQueue, JobStatus, NotificationChannel, NotificationDelivery,
+    Storage,
+};
+use synapse_core::secrets::Keyring;
+use synapse_core::{AnalysisResponse, IncidentDigest};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, Notify, RwLock};
+use tracing::{debug, info, warn};
+
+use super::channels::{self, ChannelConfig, ChannelLimits, DeliveryError};
+use super::templates::NotificationMessage;
+use crate::config::WebConfig;
+
+const NOTIFICATION_CAPACITY: usize = 256;
+/// How often the worker looks for due retries when nothing wakes it
+const POLL_INTERVAL: StdDuration = StdDuration::from_secs(5);
+/// Deliveries sent per pass
+const BATCH_SIZE: i64 = 50;
+/// A claimed delivery is retried after this long if the server stops mid-attempt
+const CLAIM_LEASE_SECS: i64 = 5 * 60;
+const FIRST_RETRY_SECS: i64 = 10;
+const MAX_RETRY_SECS: i64 = 30 * 60;
+
+/// A newly fired alert
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertNotification {
+    pub alert: Alert,
+}
+
+#[derive(Clone)]
+pub struct NotificationManager {
+    storage: Arc<dyn Storage>,
+    secrets: Arc<RwLock<Keyring>>,
+    max_attempts: i32,
+    /// Programs command channels may run and internal networks webhooks may reach
+    limits: Arc<ChannelLimits>,
+    /// Dashboard address used in message links
+    base_url: String,
+    wake: Arc<Notify>,
+    sender: broadcast::Sender<AlertNotification>,
+}
+
+impl NotificationManager {
+    pub fn new(storage: Arc<dyn Storage>, secrets: Arc<RwLock<Keyring>>, config: &WebConfig) -> Self {
+        let (sender, _) = broadcast::channel(NOTIFICATION_CAPACITY);
+        Self {
+            storage,
+            secrets,
+            max_attempts: config.notification_max_attempts.max(1),
+            limits: Arc::new(ChannelLimits {
+                commands: config.notification_commands.clone(),
+                allowed_networks: config.notification_allowed_networks.clone(),
+            }),
+            base_url: config.public_base_url(),
+            wake: Arc::new(Notify::new()),
+            sender,
+        }
+    }
+
+    /// Start the worker sending queued deliveries, and the retries left from before a restart
+    pub fn start(&self) {
+        let manager = self.clone();
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = manager.process_due().await {
+                    warn!("Failed to send notifications: {}", e);
+                }
+                tokio::select! {
+                    _ = manager.wake.notified() => {}
+                    _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                }
+            }
+        });
+    }
+
+    /// Notify channels of analyses the job queue completes
+    pub fn watch_jobs(&self, jobs: &JobQueue) {
+        let mut receiver = jobs.subscribe();
+        let manager = self.clone();
+        tokio::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(JobEvent::Finished { analysis_id, status: JobStatus::Completed, .. }) => {
+                        if let Err(e) = manager.notify_analysis(&analysis_id).await {
+                            warn!("Failed to queue notifications for analysis {}: {}", analysis_id, e);
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Notifications fell behind; {} job events were skipped", skipped);
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    /// Programs command channels may run and internal networks webhooks may reach
+    pub fn limits(&self) -> &ChannelLimits {
+        &self.limits
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<AlertNotification> {
+        self.sender.subscribe()
+    }
+
+    /// Hand a fired alert to subscribers and queue it for the project's alert channels
+    pub async fn alert_fired(&self, alert: &Alert) {
+        let _ = self.sender.send(AlertNotification { alert: alert.clone() });
+
+        let message = NotificationMessage::for_alert(alert, &self.base_url);
+        let queued = self
+            .queue(message, Some(&alert.id), |channel| {
+                channel.notify_alerts && alert.severity >= channel.min_severity
+            })
+            .await;
+        if let Err(e) = queued {
+            warn!("Failed to queue notifications for alert {}: {}", alert.id, e);
+        }
+    }
+
+    async fn notify_analysis(&self, analysis_id: &str) -> Result<usize> {
+        let Some(analysis) = self.storage.get_analysis(analysis_id).await? else {
+            return Ok(0);
+        };
+        let Some(result) = analysis.result.as_deref() else {
+            return Ok(0);
+        };
+        let response: AnalysisResponse = serde_json::from_str(result).context("Unreadable analysis result")?;
+        self.analysis_completed(&analysis, &response).await
+    }
+
+    /// Queue a finished analysis for the project's analysis channels
+    pub async fn analysis_completed(&self, analysis: &Analysis, response: &AnalysisResponse) -> Result<usize> {
+        let message = NotificationMessage::for_analysis(analysis, response, &self.base_url);
+        self.queue(message, Some(&analysis.id), |channel| channel.notify_analyses).await
+    }
+
+    /// Queue an incident digest for the project's analysis channels, subject to their minimum severity
+    pub async fn incident_digest(&self, project_id: &str, digest: &IncidentDigest) -> Result<usize> {
+        let message = NotificationMessage::for_digest(project_id, digest, &self.base_url);
+        let severity = message.severity;
+        self.queue(message, Some(&digest.incident_id), |channel| {
+            channel.notify_analyses && severity >= channel.min_severity
+        })
+        .await
+    }
+
+    /// Render a message for each enabled channel `wants`; returns how many were queued
+    async fn queue(
+        &self,
+        message: NotificationMessage,
+        event_id: Option<&str>,
+        wants: impl Fn(&NotificationChannel) -> bool,
+    ) -> Result<usize> {
+        let channels = self.storage.list_notification_channels(&message.project_id).await?;
+        let mut queued = 0;
+        for channel in channels.iter().filter(|channel| channel.enabled && wants(channel)) {
+            let delivery = self.render(channel, &message)?;
+            let delivery = match event_id {
+                Some(event_id) => delivery.with_event_id(event_id),
+                None => delivery,
+            };
+            self.storage.create_notification_delivery(&delivery).await?;
+            queued += 1;
+        }
+
+        if queued > 0 {
+            debug!("Queued {} notifications for {} in project {}", queued, message.event, message.project_id);
+            self.wake.notify_one();
+        }
+        Ok(queued)
+    }
+
+    fn render(&self, channel: &NotificationChannel, message: &NotificationMessage) -> Result<NotificationDelivery> {
+        let mut message = message.clone();
+        message.apply_templates(channel.title_template.as_deref(), channel.body_template.as_deref());
+        let payload = serde_json::to_string(&message)?;
+        Ok(NotificationDelivery::new(channel, &message.event, payload, self.max_attempts))
+    }
+
+    /// Send a test message to a channel right away, once; returns the logged delivery
+    ///
+    /// The channel's templates aren't applied: a test has no alert or analysis to fill them.
+    pub async fn send_test(&self, channel: &NotificationChannel) -> Result<NotificationDelivery> {
+        let message = NotificationMessage::test(&channel.project_id, &channel.name, &self.base_url);
+        let delivery = NotificationDelivery::new(channel, &message.event, serde_json::to_string(&message)?, 1);
+        self.storage.create_notification_delivery(&delivery).await?;
+
+        let now = Utc::now();
+        self.storage
+            .claim_notification_delivery(&delivery.id, now, now + Duration::seconds(CLAIM_LEASE_SECS))
+            .await?;
+        self.attempt(delivery).await
+    }
+
+    /// Send every delivery that is due; returns how many were attempted
+    pub async fn process_due(&self) -> Result<usize> {
+        let now = Utc::now();
+        let due = self.storage.list_due_notification_deliveries(now, BATCH_SIZE).await?;
+        let mut attempted = 0;
+        for delivery in due {
+            // Another server sharing the database may have taken it
+            let lease_until = now + Duration::seconds(CLAIM_LEASE_SECS);
+            if !self.storage.claim_notification_delivery(&delivery.id, now, lease_until).await? {
+                continue;
+            }
+            self.attempt(delivery).await?;
+            attempted += 1;
+        }
+        Ok(attempted)
+    }
+
+    /// Make one attempt at a claimed delivery and record how it went
+    async fn attempt(&self, mut delivery: NotificationDelivery) -> Result<NotificationDelivery> {
+        let outcome = match self.storage.get_notification_channel(&delivery.channel_id).await? {
+            Some(channel) => self.send(&channel, &delivery).await,
+            None => Err(DeliveryError {
+                message: "Channel was deleted".to_string(),
+                status: None,
+                retryable: false,
+            }),
+        };
+
+        let now = Utc::now();
+        delivery.attempts += 1;
+        delivery.updated_at = now;
+        match outcome {
+            Ok(status) => {
+                delivery.status = DeliveryStatus::Delivered;
+                delivery.response_status = status;
+                delivery.last_error = None;
+                delivery.delivered_at = Some(now);
+                debug!("Delivered notification {} to channel {}", delivery.id, delivery.channel_id);
+            }
+            Err(e) => {
+                delivery.response_status = e.status;
+                delivery.last_error = Some(e.message.clone());
+                if e.retryable && delivery.attempts < delivery.max_attempts {
+                    delivery.next_attempt_at = now + retry_delay(delivery.attempts);
+                    debug!(
+                        "Notification {} failed (attempt {} of {}), retrying at {}: {}",
+                        delivery.id, delivery.attempts, delivery.max_attempts, delivery.next_attempt_at, e
+                    );
+                } else {
+                    delivery.status = DeliveryStatus::Failed;
+                    warn!(
+                        "Giving up on notification {} to channel {} after {} attempts: {}",
+                        delivery.id, delivery.channel_id, delivery.attempts, e
+                    );
+                }
+            }
+        }
+
+        self.storage.update_notification_delivery(&delivery).await?;
+        Ok(delivery)
+    }
+
+    async fn send(
+        &self,
+        channel: &NotificationChannel,
+        delivery: &NotificationDelivery,
+    ) -> Result<Option<i32>, DeliveryError> {
+        let permanent = |message: String| DeliveryError { message, status: None, retryable: false };
+
+        if !channel.enabled {
+            return Err(permanent("Channel is disabled".to_string()));
+        }
+        let config: ChannelConfig = serde_json::from_str(&channel.config)
+            .map_err(|e| permanent(format!("Invalid channel settings: {}", e)))?;
+        let config = config
+            .open(&*self.secrets.read().await, &channel.id)
+            .map_err(|e| permanent(format!("Failed to decrypt channel secrets: {}", e)))?;
+        let message: NotificationMessage = serde_json::from_str(&delivery.payload)
+            .map_err(|e| permanent(format!("Invalid message: {}", e)))?;
+
+        channels::deliver(&config, &message, &delivery.id, &self.limits).await
+    }
+}
+
+/// Wait before the next attempt: doubling from 10 seconds, at most 30 minutes
+pub fn retry_delay(attempts: i32) -> Duration {
+    let exponent = attempts.clamp(1, 16) as u32 - 1;
+    Duration::seconds((FIRST_RETRY_SECS << exponent).min(MAX_RETRY_SECS))
+}
+
+/// Re-encrypt channel secrets sealed with a previous master key; returns how many channels changed
+pub async fn reseal_channel_secrets(storage: &dyn Storage, keyring: &Keyring) -> Result<usize> {
+    let mut resealed = 0;
+    for mut channel in storage.list_all_notification_channels().await? {
+        let mut config: ChannelConfig = match serde_json::from_str(&channel.config) {
+            Ok(config) => config,
+            Err(e) => {
+                warn!("Skipping notification channel {} with invalid settings: {}", channel.id, e);
+                continue;
+            }
+        };
+        if !config.reseal(keyring, &channel.id)? {
+            continue;
+        }
+        channel.config = serde_json::to_string(&config)?;
+        channel.updated_at = Utc::now();
+        storage.update_notification_channel(&channel).await?;
+        resealed += 1;
+    }
+    if resealed > 0 {
+        info!("Re-encrypted the secrets of {} notification channels", resealed);
+    }
+    Ok(resealed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use synapse_core::project::{connect_storage, AlertRule, AlertSeverity, ChannelType, DeliveryQuery, Project};
+    use synapse_core::secrets::MasterKey;
+    use tempfile::TempDir;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    async fn manager(dir: &TempDir) -> (NotificationManager, Project) {
+        let url = format!("sqlite:{}?mode=rwc", dir.path().join("notify.db").display());
+        let storage = connect_storage(&url, 1).await.unwrap();
+        let project = Project::new("notify".to_string(), None);
+        storage.insert_project(&project).await.unwrap();
+
+        let keyring = Keyring::new(MasterKey::generate().unwrap(), Vec::new());
+        // The mock receivers listen on loopback
+        let config = WebConfig {
+            notification_max_attempts: 2,
+            notification_allowed_networks: vec!["127.0.0.0/8".parse().unwrap()],
+            ..WebConfig::default()
+        };
+        (NotificationManager::new(storage, Arc::new(RwLock::new(keyring)), &config), project)
+    }
+
+    /// Answer every request with `status`
+    async fn mock_receiver(status: u16) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buffer = vec![0; 64 * 1024];
+                let _ = stream.read(&mut buffer).await;
+                let response = format!("HTTP/1.1 {} Mock\r\ncontent-length: 0\r\nconnection: close\r\n\r\n", status);
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        url
+    }
+
+    async fn webhook(manager: &NotificationManager, project: &Project, url: String) -> NotificationChannel {
+        let mut config = ChannelConfig::Webhook { url, secret: Some("s3cret".to_string()), headers: Default::default() };
+        let mut channel = NotificationChannel::new(project.id.clone(), "hook".to_string(), ChannelType::Webhook, String::new());
+        config.seal(&*manager.secrets.read().await, &channel.id).unwrap();
+        channel.config = serde_json::to_string(&config).unwrap();
+        channel.min_severity = AlertSeverity::Warning;
+        channel.title_template = Some("{{rule_name}} fired".to_string());
+        manager.storage.create_notification_channel(&channel).await.unwrap();
+        channel
+    }
+
+    fn alert(project: &Project, severity: AlertSeverity) -> Alert {
+        let mut rule = AlertRule::new(project.id.clone(), "errors".to_string(), "{}".to_string());
+        rule.severity = severity;
+        Alert::new(&rule, "api".to_string(), "2 errors".to_string())
+    }
+
+    #[tokio::test]
+    async fn test_alert_is_delivered_with_template() {
+        let dir = TempDir::new().unwrap();
+        let (manager, project) = manager(&dir).await;
+        let channel = webhook(&manager, &project, mock_receiver(200).await).await;
+
+        // Below the channel's minimum severity nothing is queued
+        manager.alert_fired(&alert(&project, AlertSeverity::Info)).await;
+        let alert = alert(&project, AlertSeverity::Critical);
+        manager.alert_fired(&alert).await;
+        assert_eq!(manager.process_due().await.unwrap(), 1);
+
+        let log = manager
+            .storage
+            .query_notification_deliveries(&DeliveryQuery { channel_id: Some(channel.id.clone()), ..Default::default() })
+            .await
+            .unwrap();
+        assert_eq!(log.len(), 1);
+        assert_eq!(log[0].status, DeliveryStatus::Delivered);
+        assert_eq!(log[0].response_status, Some(200));
+        assert_eq!(log[0].event_id.as_deref(), Some(alert.id.as_str()));
+        let message: NotificationMessage = serde_json::from_str(&log[0].payload).unwrap();
+        assert_eq!(message.title, "errors fired");
+    }
+
+    #[tokio::test]
+    async fn test_failures_back_off_then_fail() {
+        let dir = TempDir::new().unwrap();
+        let (manager, project) = manager(&dir).await;
+        webhook(&manager, &project, mock_receiver(503).await).await;
+
+        manager.alert_fired(&alert(&project, AlertSeverity::Critical)).await;
+        assert_eq!(manager.process_due().await.unwrap(), 1);
+        let log = manager.storage.query_notification_deliveries(&DeliveryQuery::default()).await.unwrap();
+        let delivery = &log[0];
+        assert_eq!(delivery.status, DeliveryStatus::Pending);
+        assert_eq!(delivery.attempts, 1);
+        assert_eq!(delivery.response_status, Some(503));
+        assert!(delivery.next_attempt_at > Utc::now() + Duration::seconds(5));
+
+        // Not due yet
+        assert_eq!(manager.process_due().await.unwrap(), 0);
+
+        let mut due = delivery.clone();
+        due.next_attempt_at = Utc::now() - Duration::seconds(1);
+        manager.storage.update_notification_delivery(&due).await.unwrap();
+        assert_eq!(manager.process_due().await.unwrap(), 1);
+        let delivery = manager.storage.get_notification_delivery(&due.id).await.unwrap().unwrap();
+        assert_eq!(delivery.status, DeliveryStatus::Failed);
+        assert_eq!(delivery.attempts, 2);
+        assert!(delivery.last_error.unwrap().contains("503"));
+    }
+
+    #[tokio::test]
+    async fn test_send_test_and_reseal() {
+        let dir = TempDir::new().unwrap();
+        let (manager, project) = manager(&dir).await;
+        let channel = webhook(&manager, &project, mock_receiver(404).await).await;
+
+        // Rejected outright, so a single attempt is logged as failed
+        let delivery = manager.send_test(&channel).await.unwrap();
+        assert_eq!(delivery.event, "test");
+        assert_eq!(delivery.status, DeliveryStatus::Failed);
+        assert_eq!(delivery.response_status, Some(404));
+
+        let mut keyring = manager.secrets.write().await;
+        assert_eq!(reseal_channel_secrets(manager.storage.as_ref(), &keyring).await.unwrap(), 0);
+        keyring.rotate().unwrap();
+        assert_eq!(reseal_channel_secrets(manager.storage.as_ref(), &keyring).await.unwrap(), 1);
+        let stored = manager.storage.get_notification_channel(&channel.id).await.unwrap().unwrap();
+        let config: ChannelConfig = serde_json::from_str(&stored.config).unwrap();
+        let ChannelConfig::Webhook { secret, .. } = config.open(&keyring, &channel.id).unwrap() else { unreachable!() };
+        assert_eq!(secret.as_deref(), Some("s3cret"));
+    }
+
+    #[test]
+    fn test_retry_delay_doubles_up_to_cap() {
+        assert_eq!(retry_delay(1), Duration::seconds(10));
+        assert_eq!(retry_delay(2), Duration::seconds(20));
+        assert_eq!(retry_delay(4), Duration::seconds(80));
+        assert_eq!(retry_delay(12), Duration::minutes(30));
+        assert_eq!(retry_delay(100), Duration::minutes(30));
+    }
+}
@@ -0,0 +1,581 @@
+//! Alert conditions and their evaluation against streaming log batches
+//!
+//! A rule's condition is stored as JSON tagged by `type`. Compiling it gives a
+//! [`RuleEvaluator`], which keeps whatever the condition needs between batches
+//! (sliding windows, seen templates, per-bucket history) and reports a
+//! [`Detection`] for each thing that should alert.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use chrono::{DateTime, Duration, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use synapse_core::filter::LogLevel;
+
+use crate::streaming::{LogBatch, StreamingLogEntry};
+
+pub use synapse_core::project::{AlertRule, AlertSeverity};
+
+/// Entries kept on an alert as examples of what matched
+const SAMPLE_SIZE: usize = 5;
+const SAMPLE_MESSAGE_CHARS: usize = 500;
+const MAX_WINDOW_SECS: u64 = 24 * 60 * 60;
+/// Completed buckets an anomaly rule needs before it starts scoring
+const MIN_ANOMALY_HISTORY: usize = 5;
+const MAX_ANOMALY_HISTORY: usize = 1440;
+
+/// What a rule looks for in a project's streaming logs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AlertCondition {
+    /// Any entry at or above `level`
+    LevelThreshold { level: String },
+    /// Any entry whose message matches the regular expression, optionally at or above `level`
+    Pattern {
+        pattern: String,
+        #[serde(default)]
+        level: Option<String>,
+    },
+    /// More than `per_minute` matching entries a minute, averaged over `window_secs`
+    Rate {
+        per_minute: f64,
+        #[serde(default = "default_window_secs")]
+        window_secs: u64,
+        #[serde(default)]
+        level: Option<String>,
+        #[serde(default)]
+        pattern: Option<String>,
+    },
+    /// An entry at or above `level` whose message template has not been seen before
+    NewTemplate {
+        #[serde(default = "default_template_level")]
+        level: String,
+    },
+    /// Matching entries per `bucket_secs` scoring at least `z_score` standard deviations
+    /// above the mean of the previous `history` buckets
+    Anomaly {
+        #[serde(default)]
+        level: Option<String>,
+        #[serde(default = "default_window_secs")]
+        bucket_secs: u64,
+        #[serde(default = "default_z_score")]
+        z_score: f64,
+        #[serde(default = "default_history")]
+        history: usize,
+        /// Fewer entries than this in a bucket never alert, however unusual
+        #[serde(default = "default_min_count")]
+        min_count: u64,
+    },
+}
+
+fn default_window_secs() -> u64 {
+    60
+}
+
+fn default_template_level() -> String {
+    "ERROR".to_string()
+}
+
+fn default_z_score() -> f64 {
+    3.0
+}
+
+fn default_history() -> usize {
+    30
+}
+
+fn default_min_count() -> u64 {
+    5
+}
+
+impl AlertCondition {
+    /// Parse a rule's stored condition
+    pub fn from_rule(rule: &AlertRule) -> Result<Self, String> {
+        serde_json::from_str(&rule.condition).map_err(|e| format!("Invalid alert condition: {}", e))
+    }
+
+    /// Check the condition and set up its evaluation state
+    pub fn compile(&self) -> Result<RuleEvaluator, String> {
+        let evaluator = match self {
+            AlertCondition::LevelThreshold { level } => Evaluator::Match {
+                filter: EntryFilter::new(Some(level), None)?,
+            },
+            AlertCondition::Pattern { pattern, level } => Evaluator::Match {
+                filter: EntryFilter::new(level.as_deref(), Some(pattern))?,
+            },
+            AlertCondition::Rate { per_minute, window_secs, level, pattern } => {
+                if !per_minute.is_finite() || *per_minute < 0.0 {
+                    return Err("per_minute must be zero or more".to_string());
+                }
+                Evaluator::Rate {
+                    filter: EntryFilter::new(level.as_deref(), pattern.as_deref())?,
+                    per_minute: *per_minute,
+                    window: window_duration("window_secs", *window_secs)?,
+                    windows: HashMap::new(),
+                }
+            }
+            AlertCondition::NewTemplate { level } => Evaluator::NewTemplate {
+                filter: EntryFilter::new(Some(level), None)?,
+                seen: HashSet::new(),
+            },
+            AlertCondition::Anomaly { level, bucket_secs, z_score, history, min_count } => {
+                if !z_score.is_finite() || *z_score <= 0.0 {
+                    return Err("z_score must be greater than zero".to_string());
+                }
+                if !(MIN_ANOMALY_HISTORY..=MAX_ANOMALY_HISTORY).contains(history) {
+                    return Err(format!(
+                        "history must be between {} and {} buckets",
+                        MIN_ANOMALY_HISTORY, MAX_ANOMALY_HISTORY
+                    ));
+                }
+                Evaluator::Anomaly {
+                    filter: EntryFilter::new(level.as_deref(), None)?,
+                    bucket: window_duration("bucket_secs", *bucket_secs)?,
+                    z_score: *z_score,
+                    history: *history,
+                    min_count: *min_count,
+                    series: HashMap::new(),
+                }
+            }
+        };
+        Ok(RuleEvaluator { evaluator })
+    }
+}
+
+fn window_duration(name: &str, secs: u64) -> Result<Duration, String> {
+    if !(1..=MAX_WINDOW_SECS).contains(&secs) {
+        return Err(format!("{} must be between 1 and {}", name, MAX_WINDOW_SECS));
+    }
+    Ok(Duration::seconds(secs as i64))
+}
+
+/// Parse a log level, accepting the spellings streaming sources commonly emit
+pub fn parse_level(level: &str) -> Option<LogLevel> {
+    match level.trim().to_uppercase().as_str() {
+        "WARNING" => Some(LogLevel::Warn),
+        "CRITICAL" | "CRIT" | "EMERG" | "ALERT" | "PANIC" => Some(LogLevel::Fatal),
+        "ERR" => Some(LogLevel::Error),
+        other => other.parse().ok(),
+    }
+}
+
+/// Which entries a condition counts
+#[derive(Debug)]
+struct EntryFilter {
+    level: Option<LogLevel>,
+    pattern: Option<Regex>,
+}
+
+impl EntryFilter {
+    fn new(level: Option<&str>, pattern: Option<&str>) -> Result<Self, String> {
+        let level = level
+            .map(|level| parse_level(level).ok_or_else(|| format!("Unknown log level: {}", level)))
+            .transpose()?;
+        let pattern = pattern
+            .map(|pattern| Regex::new(pattern).map_err(|e| format!("Invalid pattern: {}", e)))
+            .transpose()?;
+        Ok(Self { level, pattern })
+    }
+
+    fn matches(&self, entry: &StreamingLogEntry) -> bool {
+        let level_ok = match &self.level {
+            Some(min_level) => entry
+                .level
+                .as_deref()
+                .and_then(parse_level)
+                .is_some_and(|level| level >= *min_level),
+            None => true,
+        };
+        level_ok && self.pattern.as_ref().is_none_or(|pattern| pattern.is_match(&entry.message))
+    }
+
+    fn describe(&self) -> String {
+        match (&self.level, &self.pattern) {
+            (Some(level), Some(pattern)) => format!("{} entries matching /{}/", level, pattern),
+            (Some(level), None) => format!("entries at or above {}", level),
+            (None, Some(pattern)) => format!("entries matching /{}/", pattern),
+            (None, None) => "entries".to_string(),
+        }
+    }
+}
+
+/// Something a rule found in a batch
+#[derive(Debug, Clone)]
+pub struct Detection {
+    /// Repeat detections with the same fingerprint are counted on one open alert
+    pub fingerprint: String,
+    pub summary: String,
+    /// Matching entries in the batch
+    pub occurrences: i64,
+    /// Measured values and sample entries, stored with the alert
+    pub details: serde_json::Value,
+    /// Fire at most once per fingerprint, even after the alert is resolved
+    pub once: bool,
+}
+
+/// A compiled condition and the state it keeps between batches
+#[derive(Debug)]
+pub struct RuleEvaluator {
+    evaluator: Evaluator,
+}
+
+#[derive(Debug)]
+enum Evaluator {
+    Match {
+        filter: EntryFilter,
+    },
+    Rate {
+        filter: EntryFilter,
+        per_minute: f64,
+        window: Duration,
+        /// Matching entries per batch, by source
+        windows: HashMap<String, VecDeque<(DateTime<Utc>, u64)>>,
+    },
+    NewTemplate {
+        filter: EntryFilter,
+        seen: HashSet<String>,
+    },
+    Anomaly {
+        filter: EntryFilter,
+        bucket: Duration,
+        z_score: f64,
+        history: usize,
+        min_count: u64,
+        series: HashMap<String, BucketSeries>,
+    },
+}
+
+/// Matching entries per bucket for one source: the bucket being filled and those before it
+#[derive(Debug)]
+struct BucketSeries {
+    bucket_start: DateTime<Utc>,
+    current: u64,
+    completed: VecDeque<u64>,
+}
+
+impl BucketSeries {
+    fn new(now: DateTime<Utc>) -> Self {
+        Self { bucket_start: now, current: 0, completed: VecDeque::new() }
+    }
+
+    /// Close the buckets that ended before `now`; empty ones count as zero
+    fn advance(&mut self, now: DateTime<Utc>, bucket: Duration, history: usize) {
+        let elapsed = (now - self.bucket_start).num_milliseconds() / bucket.num_milliseconds().max(1);
+        if elapsed <= 0 {
+            return;
+        }
+        self.completed.push_back(self.current);
+        for _ in 1..elapsed.min(history as i64 + 1) {
+            self.completed.push_back(0);
+        }
+        while self.completed.len() > history {
+            self.completed.pop_front();
+        }
+        self.current = 0;
+        self.bucket_start += bucket * elapsed as i32;
+    }
+
+    /// How many standard deviations the current bucket is above the mean of the completed ones
+    fn score(&self) -> (f64, f64) {
+        let n = self.completed.len() as f64;
+        let mean = self.completed.iter().sum::<u64>() as f64 / n;
+        let variance = self.completed.iter().map(|&count| (count as f64 - mean).powi(2)).sum::<f64>() / n;
+        // A flat history would make any increase infinitely unusual; treat it as varying by one
+        ((self.current as f64 - mean) / variance.sqrt().max(1.0), mean)
+    }
+}
+
+impl RuleEvaluator {
+    /// Evaluate one batch received at `now`
+    pub fn evaluate(&mut self, batch: &LogBatch, now: DateTime<Utc>) -> Vec<Detection> {
+        let source = batch.source.as_str();
+        match &mut self.evaluator {
+            Evaluator::Match { filter } => {
+                let matched: Vec<_> = batch.entries.iter().filter(|entry| filter.matches(entry)).collect();
+                if matched.is_empty() {
+                    return Vec::new();
+                }
+                vec![Detection {
+                    fingerprint: format!("source:{}", source),
+                    summary: format!("{} {} from {}", matched.len(), filter.describe(), source),
+                    occurrences: matched.len() as i64,
+                    details: serde_json::json!({
+                        "source": source,
+                        "count": matched.len(),
+                        "sample": sample(&matched),
+                    }),
+                    once: false,
+                }]
+            }
+            Evaluator::Rate { filter, per_minute, window, windows } => {
+                let matched: Vec<_> = batch.entries.iter().filter(|entry| filter.matches(entry)).collect();
+                let counts = windows.entry(source.to_string()).or_default();
+                while counts.front().is_some_and(|(at, _)| *at <= now - *window) {
+                    counts.pop_front();
+                }
+                if !matched.is_empty() {
+                    counts.push_back((now, matched.len() as u64));
+                }
+
+                let total: u64 = counts.iter().map(|(_, count)| count).sum();
+                let rate = total as f64 * 60.0 / window.num_seconds() as f64;
+                if matched.is_empty() || rate <= *per_minute {
+                    return Vec::new();
+                }
+                vec![Detection {
+                    fingerprint: format!("source:{}", source),
+                    summary: format!(
+                        "{:.1} {} a minute from {} over the last {}s, above {}",
+                        rate,
+                        filter.describe(),
+                        source,
+                        window.num_seconds(),
+                        per_minute
+                    ),
+                    occurrences: matched.len() as i64,
+                    details: serde_json::json!({
+                        "source": source,
+                        "per_minute": rate,
+                        "threshold": per_minute,
+                        "window_secs": window.num_seconds(),
+                        "count": total,
+                        "sample": sample(&matched),
+                    }),
+                    once: false,
+                }]
+            }
+            Evaluator::NewTemplate { filter, seen } => {
+                let mut new_templates: Vec<(String, Vec<&StreamingLogEntry>)> = Vec::new();
+                for entry in batch.entries.iter().filter(|entry| filter.matches(entry)) {
+                    let template = synapse_core::extract_pattern(&entry.message);
+                    if let Some((_, entries)) = new_templates.iter_mut().find(|(known, _)| *known == template) {
+                        entries.push(entry);
+                    } else if seen.insert(template.clone()) {
+                        new_templates.push((template, vec![entry]));
+                    }
+                }
+
+                new_templates
+                    .into_iter()
+                    .map(|(template, entries)| Detection {
+                        fingerprint: format!("template:{}", &hex::encode(Sha256::digest(template.as_bytes()))[..16]),
+                        summary: format!("New {} from {}: {}", filter.describe(), source, truncate(&template, 200)),
+                        occurrences: entries.len() as i64,
+                        details: serde_json::json!({
+                            "source": source,
+                            "template": template,
+                            "sample": sample(&entries),
+                        }),
+                        once: true,
+                    })
+                    .collect()
+            }
+            Evaluator::Anomaly { filter, bucket, z_score, history, min_count, series } => {
+                let matched: Vec<_> = batch.entries.iter().filter(|entry| filter.matches(entry)).collect();
+                let counts = series.entry(source.to_string()).or_insert_with(|| BucketSeries::new(now));
+                counts.advance(now, *bucket, *history);
+                counts.current += matched.len() as u64;
+
+                if matched.is_empty()
+                    || counts.completed.len() < MIN_ANOMALY_HISTORY.min(*history)
+                    || counts.current < *min_count
+                {
+                    return Vec::new();
+                }
+                let (score, mean) = counts.score();
+                if score < *z_score {
+                    return Vec::new();
+                }
+                vec![Detection {
+                    fingerprint: format!("source:{}", source),
+                    summary: format!(
+                        "{} {} from {} in {}s, {:.1} standard deviations above the usual {:.1}",
+                        counts.current,
+                        filter.describe(),
+                        source,
+                        bucket.num_seconds(),
+                        score,
+                        mean
+                    ),
+                    occurrences: matched.len() as i64,
+                    details: serde_json::json!({
+                        "source": source,
+                        "count": counts.current,
+                        "mean": mean,
+                        "score": score,
+                        "z_score": z_score,
+                        "bucket_secs": bucket.num_seconds(),
+                        "sample": sample(&matched),
+                    }),
+                    once: false,
+                }]
+            }
+        }
+    }
+}
+
+fn sample(entries: &[&StreamingLogEntry]) -> serde_json::Value {
+    entries
+        .iter()
+        .take(SAMPLE_SIZE)
+        .map(|entry| {
+            serde_json::json!({
+                "timestamp": entry.timestamp,
+                "level": entry.level,
+                "message": truncate(&entry.message, SAMPLE_MESSAGE_CHARS),
+            })
+        })
+        .collect()
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn batch(source: &str, entries: &[(&str, &str)]) -> LogBatch {
+        let project_id = Uuid::new_v4();
+        LogBatch {
+            batch_id: Uuid::new_v4().to_string(),
+            timestamp: Utc::now().to_rfc3339(),
+            entries: entries
+                .iter()
+                .map(|(level, message)| StreamingLogEntry {
+                    id: Uuid::new_v4().to_string(),
+                    timestamp: None,
+                    level: Some(level.to_string()),
+                    message: message.to_string(),
+                    source: source.to_string(),
+                    project_id,
+                    line_number: None,
+                    metadata: HashMap::new(),
+                })
+                .collect(),
+            source: source.to_string(),
+            project_id,
+        }
+    }
+
+    fn compile(condition: serde_json::Value) -> RuleEvaluator {
+        serde_json::from_value::<AlertCondition>(condition).unwrap().compile().unwrap()
+    }
+
+    #[test]
+    fn test_level_threshold_counts_entries_at_or_above_level() {
+        let mut rule = compile(serde_json::json!({ "type": "level_threshold", "level": "error" }));
+        let now = Utc::now();
+
+        assert!(rule.evaluate(&batch("api", &[("INFO", "ok"), ("WARN", "slow")]), now).is_empty());
+        let detections = rule.evaluate(
+            &batch("api", &[("ERROR", "failed"), ("critical", "down"), ("INFO", "ok")]),
+            now,
+        );
+        assert_eq!(detections.len(), 1);
+        assert_eq!(detections[0].fingerprint, "source:api");
+        assert_eq!(detections[0].occurrences, 2);
+        assert_eq!(detections[0].details["sample"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_pattern_matches_messages() {
+        let mut rule = compile(serde_json::json!({ "type": "pattern", "pattern": "(?i)out of memory" }));
+        let detections = rule.evaluate(&batch("worker", &[("INFO", "Out Of Memory: killed"), ("INFO", "fine")]), Utc::now());
+        assert_eq!(detections[0].occurrences, 1);
+
+        let invalid = AlertCondition::Pattern { pattern: "(".to_string(), level: None };
+        assert!(invalid.compile().is_err());
+        let unknown_level = AlertCondition::LevelThreshold { level: "loud".to_string() };
+        assert!(unknown_level.compile().is_err());
+    }
+
+    #[test]
+    fn test_rate_over_sliding_window() {
+        let mut rule = compile(serde_json::json!({
+            "type": "rate", "per_minute": 4.0, "window_secs": 60, "level": "ERROR"
+        }));
+        let start = Utc::now();
+        let errors = batch("api", &[("ERROR", "a"), ("ERROR", "b"), ("ERROR", "c")]);
+
+        assert!(rule.evaluate(&errors, start).is_empty());
+        let detections = rule.evaluate(&errors, start + Duration::seconds(30));
+        assert_eq!(detections.len(), 1);
+        assert_eq!(detections[0].details["count"], 6);
+        // The first batch has left the window
+        assert!(rule.evaluate(&batch("api", &[("ERROR", "d")]), start + Duration::seconds(61)).is_empty());
+        // Sources are counted apart
+        assert!(rule.evaluate(&batch("web", &[("ERROR", "x"), ("ERROR", "y")]), start + Duration::seconds(62)).is_empty());
+    }
+
+    #[test]
+    fn test_new_template_fires_once_per_template() {
+        let mut rule = compile(serde_json::json!({ "type": "new_template" }));
+        let now = Utc::now();
+
+        let detections = rule.evaluate(
+            &batch(
+                "api",
+                &[
+                    ("ERROR", "Timeout after 30 ms calling 10.0.0.1"),
+                    ("ERROR", "Timeout after 45 ms calling 10.0.0.2"),
+                    ("ERROR", "Disk full"),
+                    ("INFO", "Started"),
+                ],
+            ),
+            now,
+        );
+        assert_eq!(detections.len(), 2);
+        assert_eq!(detections[0].occurrences, 2);
+        assert!(detections.iter().all(|detection| detection.once));
+        assert!(rule.evaluate(&batch("api", &[("ERROR", "Timeout after 99 ms calling 10.0.0.9")]), now).is_empty());
+    }
+
+    #[test]
+    fn test_anomaly_scores_against_recent_buckets() {
+        let mut rule = compile(serde_json::json!({
+            "type": "anomaly", "bucket_secs": 60, "history": 5, "z_score": 3.0, "min_count": 5
+        }));
+        let start = Utc::now();
+        let entries = |count: usize| {
+            let entries: Vec<(&str, &str)> = (0..count).map(|_| ("ERROR", "boom")).collect();
+            batch("api", &entries)
+        };
+
+        // Five quiet minutes of one or two entries each
+        for minute in 0..5 {
+            let detections = rule.evaluate(&entries(1 + minute % 2), start + Duration::seconds(60 * minute as i64));
+            assert!(detections.is_empty());
+        }
+        // A spike in the sixth minute stands out
+        let detections = rule.evaluate(&entries(12), start + Duration::seconds(300));
+        assert_eq!(detections.len(), 1);
+        assert!(detections[0].details["score"].as_f64().unwrap() >= 3.0);
+
+        assert!(AlertCondition::Anomaly {
+            level: None,
+            bucket_secs: 60,
+            z_score: 0.0,
+            history: 30,
+            min_count: 5,
+        }
+        .compile()
+        .is_err());
+    }
+
+    #[test]
+    fn test_condition_json_uses_defaults() {
+        let condition: AlertCondition = serde_json::from_str(r#"{"type":"rate","per_minute":10}"#).unwrap();
+        assert_eq!(
+            condition,
+            AlertCondition::Rate { per_minute: 10.0, window_secs: 60, level: None, pattern: None }
+        );
+        assert!(serde_json::from_str::<AlertCondition>(r#"{"type":"unknown"}"#).is_err());
+    }
+}
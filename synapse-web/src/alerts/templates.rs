@@ -0,0 +1,395 @@
+//! Messages sent to notification channels
+//!
+//! A message is rendered once, when it is queued, from the alert, analysis result or
+//! incident digest it is about. Channels may replace the default title and text with
+//! templates using `{{placeholder}}` names from [`PLACEHOLDERS`]; a placeholder the
+//! event doesn't provide is left empty.
+
+use std::collections::BTreeMap;
+
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use synapse_core::project::{Alert, AlertSeverity, Analysis};
+use synapse_core::{AnalysisResponse, ErrorCategory, IncidentDigest};
+
+/// Most items of a list (recommendations, errors) shown in a message
+const MAX_LIST_ITEMS: usize = 5;
+
+/// Names templates may use
+pub const PLACEHOLDERS: &[&str] = &[
+    "event",
+    "project_id",
+    "severity",
+    "title",
+    "text",
+    "link",
+    // Alerts
+    "alert_id",
+    "rule_name",
+    "summary",
+    "source",
+    "occurrences",
+    "first_seen_at",
+    // Analyses
+    "analysis_id",
+    "provider",
+    "root_cause",
+    "category",
+    "confidence",
+    "recommendations",
+    "sequence_of_events",
+    // Incident digests
+    "incident_id",
+    "critical_errors",
+    "recommended_actions",
+];
+
+lazy_static! {
+    static ref PLACEHOLDER: Regex = Regex::new(r"\{\{\s*([a-z_]+)\s*\}\}").unwrap();
+}
+
+/// A name and value shown alongside a message, e.g. as a Slack attachment field
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageField {
+    pub name: String,
+    pub value: String,
+}
+
+/// What a channel sends; stored as a delivery's payload and resent as is on retries
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationMessage {
+    /// `alert.fired`, `analysis.completed`, `incident.digest` or `test`
+    pub event: String,
+    pub project_id: String,
+    pub severity: AlertSeverity,
+    pub title: String,
+    pub text: String,
+    /// Where the subject can be seen in the dashboard
+    pub link: Option<String>,
+    pub fields: Vec<MessageField>,
+    /// The alert, analysis or digest itself, for receivers that want more than the text
+    #[serde(default)]
+    pub data: Value,
+    /// Values for the channel's templates
+    #[serde(skip)]
+    pub vars: BTreeMap<String, String>,
+}
+
+impl NotificationMessage {
+    fn new(event: &str, project_id: &str, severity: AlertSeverity, title: String, text: String) -> Self {
+        Self {
+            event: event.to_string(),
+            project_id: project_id.to_string(),
+            severity,
+            title,
+            text,
+            link: None,
+            fields: Vec::new(),
+            data: Value::Null,
+            vars: BTreeMap::new(),
+        }
+    }
+
+    fn field(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.fields.push(MessageField {
+            name: name.to_string(),
+            value: value.into(),
+        });
+        self
+    }
+
+    fn var(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.vars.insert(name.to_string(), value.into());
+        self
+    }
+
+    /// A newly fired alert
+    pub fn for_alert(alert: &Alert, base_url: &str) -> Self {
+        let source = alert.source.clone().unwrap_or_default();
+        let mut message = Self::new(
+            "alert.fired",
+            &alert.project_id,
+            alert.severity,
+            format!("[{}] {}", alert.severity.as_str().to_uppercase(), alert.rule_name),
+            alert.summary.clone(),
+        )
+        .field("Severity", alert.severity.as_str())
+        .field("Occurrences", alert.occurrences.to_string())
+        .var("alert_id", alert.id.clone())
+        .var("rule_name", alert.rule_name.clone())
+        .var("summary", alert.summary.clone())
+        .var("source", source.clone())
+        .var("occurrences", alert.occurrences.to_string())
+        .var("first_seen_at", alert.first_seen_at.to_rfc3339());
+        if !source.is_empty() {
+            message = message.field("Source", source);
+        }
+        message.link = Some(format!("{}/projects/{}", base_url, alert.project_id));
+        message.data = serde_json::to_value(alert).unwrap_or_default();
+        message.with_common_vars()
+    }
+
+    /// A finished analysis and what it found
+    pub fn for_analysis(analysis: &Analysis, response: &AnalysisResponse, base_url: &str) -> Self {
+        let category = category_name(&response.root_cause.category);
+        let confidence = format!("{:.0}%", response.confidence * 100.0);
+        let recommendations = bullet_list(&response.recommendations);
+
+        let mut text = response.root_cause.description.clone();
+        if !recommendations.is_empty() {
+            text.push_str("\n\nRecommendations:\n");
+            text.push_str(&recommendations);
+        }
+
+        let mut message = Self::new(
+            "analysis.completed",
+            &analysis.project_id,
+            AlertSeverity::Info,
+            format!("Analysis complete: {} issue", category),
+            text,
+        )
+        .field("Root cause", category)
+        .field("Confidence", confidence.clone())
+        .field("Provider", analysis.provider.clone())
+        .var("analysis_id", analysis.id.clone())
+        .var("provider", analysis.provider.clone())
+        .var("root_cause", response.root_cause.description.clone())
+        .var("category", category)
+        .var("confidence", confidence)
+        .var("recommendations", recommendations)
+        .var("sequence_of_events", response.sequence_of_events.clone());
+        if let Some(location) = &response.root_cause.file_location {
+            let location = match response.root_cause.line_number {
+                Some(line) => format!("{}:{}", location, line),
+                None => location.clone(),
+            };
+            message = message.field("Location", location);
+        }
+        message.link = Some(format!("{}/analysis/{}", base_url, analysis.id));
+        message.data = serde_json::json!({ "analysis_id": analysis.id, "result": response });
+        message.with_common_vars()
+    }
+
+    /// An incident digest; its CRITICAL/HIGH/MEDIUM/LOW severity maps onto alert severities
+    pub fn for_digest(project_id: &str, digest: &IncidentDigest, base_url: &str) -> Self {
+        let severity = match digest.severity.to_uppercase().as_str() {
+            "CRITICAL" | "HIGH" => AlertSeverity::Critical,
+            "MEDIUM" => AlertSeverity::Warning,
+            _ => AlertSeverity::Info,
+        };
+        let critical_errors: Vec<String> = digest
+            .critical_errors
+            .iter()
+            .map(|error| format!("{} (x{}): {}", error.error_type, error.frequency, error.message))
+            .collect();
+        let critical_errors = bullet_list(&critical_errors);
+        let recommended_actions = bullet_list(&digest.recommended_actions);
+
+        let mut text = digest.root_cause_analysis.clone();
+        for (heading, list) in [("Critical errors", &critical_errors), ("Recommended actions", &recommended_actions)] {
+            if !list.is_empty() {
+                text.push_str(&format!("\n\n{}:\n{}", heading, list));
+            }
+        }
+
+        let mut message = Self::new(
+            "incident.digest",
+            project_id,
+            severity,
+            format!(
+                "[{}] Incident digest: {} critical errors",
+                digest.severity,
+                digest.critical_errors.len()
+            ),
+            text,
+        )
+        .field("Severity", digest.severity.clone())
+        .field("Lines analyzed", digest.log_stats.total_lines.to_string())
+        .var("incident_id", digest.incident_id.clone())
+        .var("root_cause", digest.root_cause_analysis.clone())
+        .var("critical_errors", critical_errors)
+        .var("recommended_actions", recommended_actions);
+        message.link = Some(format!("{}/projects/{}", base_url, project_id));
+        message.data = serde_json::to_value(digest).unwrap_or_default();
+        message.with_common_vars()
+    }
+
+    /// Sent when someone tests a channel
+    pub fn test(project_id: &str, channel_name: &str, base_url: &str) -> Self {
+        let mut message = Self::new(
+            "test",
+            project_id,
+            AlertSeverity::Info,
+            "Synapse test notification".to_string(),
+            format!("The notification channel '{}' is set up correctly.", channel_name),
+        );
+        message.link = Some(format!("{}/projects/{}", base_url, project_id));
+        message.with_common_vars()
+    }
+
+    fn with_common_vars(self) -> Self {
+        let event = self.event.clone();
+        let project_id = self.project_id.clone();
+        let severity = self.severity.to_string();
+        let title = self.title.clone();
+        let text = self.text.clone();
+        let link = self.link.clone().unwrap_or_default();
+        self.var("event", event)
+            .var("project_id", project_id)
+            .var("severity", severity)
+            .var("title", title)
+            .var("text", text)
+            .var("link", link)
+    }
+
+    /// Replace the title and text with a channel's templates, where it has them
+    pub fn apply_templates(&mut self, title_template: Option<&str>, body_template: Option<&str>) {
+        if let Some(template) = title_template {
+            self.title = render(template, &self.vars);
+        }
+        if let Some(template) = body_template {
+            self.text = render(template, &self.vars);
+        }
+    }
+}
+
+/// Fill a template's placeholders from `vars`
+pub fn render(template: &str, vars: &BTreeMap<String, String>) -> String {
+    PLACEHOLDER
+        .replace_all(template, |captures: &Captures| {
+            vars.get(&captures[1]).cloned().unwrap_or_default()
+        })
+        .into_owned()
+}
+
+/// Check that a template only uses known placeholders
+pub fn validate_template(template: &str) -> Result<(), String> {
+    for captures in PLACEHOLDER.captures_iter(template) {
+        if !PLACEHOLDERS.contains(&&captures[1]) {
+            return Err(format!(
+                "Unknown template placeholder '{}'; available: {}",
+                &captures[1],
+                PLACEHOLDERS.join(", ")
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Short name of an error category, e.g. "configuration"
+pub fn category_name(category: &ErrorCategory) -> &'static str {
+    match category {
+        ErrorCategory::CodeRelated { .. } => "code",
+        ErrorCategory::InfrastructureRelated { .. } => "infrastructure",
+        ErrorCategory::ConfigurationRelated { .. } => "configuration",
+        ErrorCategory::ExternalServiceRelated { .. } => "external service",
+        ErrorCategory::UnknownRelated => "unknown",
+    }
+}
+
+fn bullet_list(items: &[String]) -> String {
+    let mut list: Vec<String> = items
+        .iter()
+        .take(MAX_LIST_ITEMS)
+        .map(|item| format!("- {}", item))
+        .collect();
+    if items.len() > MAX_LIST_ITEMS {
+        list.push(format!("- and {} more", items.len() - MAX_LIST_ITEMS));
+    }
+    list.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use synapse_core::project::AlertRule;
+    use synapse_core::{CriticalError, RootCauseAnalysis};
+
+    fn analysis_response() -> AnalysisResponse {
+        AnalysisResponse {
+            sequence_of_events: "The pool ran dry, then requests timed out".to_string(),
+            root_cause: RootCauseAnalysis {
+                category: ErrorCategory::ConfigurationRelated {
+                    config_file: Some("db.toml".to_string()),
+                    missing_setting: None,
+                    invalid_value: None,
+                },
+                description: "Connection pool too small".to_string(),
+                file_location: Some("src/db.rs".to_string()),
+                line_number: Some(42),
+                function_name: None,
+                confidence: 0.9,
+            },
+            recommendations: vec!["Raise max_connections".to_string()],
+            confidence: 0.85,
+            related_errors: Vec::new(),
+            unrelated_errors: Vec::new(),
+            errors_found: None,
+            patterns: None,
+            performance: None,
+            anomalies: None,
+        }
+    }
+
+    #[test]
+    fn test_alert_message_and_templates() {
+        let mut rule = AlertRule::new("p1".to_string(), "API errors".to_string(), "{}".to_string());
+        rule.severity = AlertSeverity::Critical;
+        let alert = Alert::new(&rule, "api".to_string(), "12 errors in 1m".to_string()).with_source("api");
+
+        let mut message = NotificationMessage::for_alert(&alert, "https://synapse.example");
+        assert_eq!(message.event, "alert.fired");
+        assert_eq!(message.title, "[CRITICAL] API errors");
+        assert_eq!(message.link.as_deref(), Some("https://synapse.example/projects/p1"));
+        assert!(message.fields.iter().any(|field| field.name == "Source" && field.value == "api"));
+
+        message.apply_templates(Some("{{ rule_name }} on {{source}}"), Some("{{summary}} {{root_cause}}!"));
+        assert_eq!(message.title, "API errors on api");
+        assert_eq!(message.text, "12 errors in 1m !");
+
+        // Template values aren't part of the stored payload
+        let payload = serde_json::to_value(&message).unwrap();
+        assert!(payload.get("vars").is_none());
+        assert_eq!(payload["data"]["id"], alert.id.as_str());
+    }
+
+    #[test]
+    fn test_analysis_and_digest_messages() {
+        let mut analysis = Analysis::new("p1".to_string(), None, "file".to_string(), "openai".to_string(), "ERROR".to_string());
+        analysis.id = "a1".to_string();
+        let message = NotificationMessage::for_analysis(&analysis, &analysis_response(), "http://localhost:3000");
+        assert_eq!(message.title, "Analysis complete: configuration issue");
+        assert!(message.text.contains("- Raise max_connections"));
+        assert_eq!(message.link.as_deref(), Some("http://localhost:3000/analysis/a1"));
+        assert!(message.fields.iter().any(|field| field.name == "Location" && field.value == "src/db.rs:42"));
+        assert_eq!(message.vars["confidence"], "85%");
+
+        let mut digest = IncidentDigest::new("incident-1".to_string());
+        digest.severity = "HIGH".to_string();
+        digest.root_cause_analysis = "Disk full".to_string();
+        digest.critical_errors = (0..7)
+            .map(|i| CriticalError {
+                error_type: "IoError".to_string(),
+                message: format!("write failed {}", i),
+                frequency: 3,
+                first_occurrence: None,
+                last_occurrence: None,
+                affected_components: Vec::new(),
+                confidence: 0.8,
+            })
+            .collect();
+        let message = NotificationMessage::for_digest("p1", &digest, "http://localhost:3000");
+        assert_eq!(message.severity, AlertSeverity::Critical);
+        assert!(message.text.contains("- IoError (x3): write failed 0"));
+        assert!(message.text.contains("- and 2 more"));
+    }
+
+    #[test]
+    fn test_validate_template() {
+        assert!(validate_template("{{title}}: {{ summary }}").is_ok());
+        assert!(validate_template("no placeholders").is_ok());
+        assert!(validate_template("{{password}}").unwrap_err().contains("password"));
+    }
+}
@@ -0,0 +1,15 @@
+//! Recording user and system actions in the append-only audit log
+//!
+//! Handlers build events with [`CurrentUser::audit_event`](crate::middleware::auth::CurrentUser::audit_event)
+//! and record them after the action succeeds. A failed write is logged but never fails
+//! the action that was already carried out.
+
+use synapse_core::project::AuditEvent;
+
+use crate::database::Database;
+
+pub async fn record(db: &Database, event: AuditEvent) {
+    if let Err(e) = db.storage().record_audit_event(&event).await {
+        tracing::error!("Failed to record audit event {}: {}", event.action, e);
+    }
+}
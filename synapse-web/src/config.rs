@@ -1,6 +1,9 @@
+use ipnet::IpNet;
 use serde::{Deserialize, Serialize};
 use std::env;
+use std::net::IpAddr;
 use std::path::Path;
+use synapse_core::project::ProjectRole;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WebConfig {
@@ -9,9 +12,201 @@ pub struct WebConfig {
     pub max_upload_size: usize,
     pub max_projects: usize,
     pub analysis_timeout_secs: u64,
+    /// Analyses run at the same time by the job queue's workers
+    pub job_workers: usize,
+    /// Runs of a failing analysis before it is marked failed
+    pub job_max_attempts: i32,
     pub cors_origins: Vec<String>,
     pub frontend_dir: String,
     pub upload_dir: String,
+    /// Require a login or API token on the API; off for single-user local installs
+    pub auth_enabled: bool,
+    pub session_ttl_hours: i64,
+    /// Mark session cookies `Secure` when the dashboard is served over HTTPS
+    pub secure_cookies: bool,
+    /// Single sign-on through an OpenID Connect provider, when configured
+    pub oidc: Option<OidcConfig>,
+    /// Address the dashboard is reached at from outside, used in share links;
+    /// defaults to `http://localhost:<port>`
+    pub public_url: Option<String>,
+    /// Per-caller limits on the routes a client could flood
+    pub rate_limits: RateLimits,
+    /// Reverse proxies whose `X-Forwarded-For` header is believed; from anyone else the
+    /// connecting address is the client
+    pub trusted_proxies: Vec<IpNet>,
+    /// Analyses a project may start per UTC day unless it has its own quota; 0 for no limit
+    pub daily_analysis_quota: u32,
+    /// Days stored streaming entries are kept unless the project sets its own; 0 for no limit
+    pub log_retention_days: u32,
+    /// Megabytes of stored streaming entries a project may keep unless it sets its own; 0 for no limit
+    pub log_retention_mb: u32,
+    /// Bearer token scrapers must send to read `/metrics`; open to anyone who can reach it when unset
+    pub metrics_token: Option<String>,
+    /// Tries to deliver a notification before it is marked failed
+    pub notification_max_attempts: i32,
+    /// Programs command notification channels may run; none are allowed when empty
+    pub notification_commands: Vec<String>,
+    /// Loopback, private and link-local networks webhook and Slack channels may still reach
+    pub notification_allowed_networks: Vec<IpNet>,
+}
+
+/// A token bucket: up to `burst` requests at once, refilled at `per_minute`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimit {
+    /// 0 turns the limit off
+    pub per_minute: u32,
+    pub burst: u32,
+}
+
+impl RateLimit {
+    pub fn new(per_minute: u32, burst: u32) -> Self {
+        Self { per_minute, burst }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.per_minute > 0
+    }
+
+    /// Read `<prefix>` (requests per minute) and `<prefix>_BURST`, keeping the defaults for unset ones
+    fn from_env(prefix: &str, default: Self) -> anyhow::Result<Self> {
+        let mut limit = default;
+        if let Ok(per_minute) = env::var(prefix) {
+            limit.per_minute = per_minute.parse()?;
+        }
+        if let Ok(burst) = env::var(format!("{}_BURST", prefix)) {
+            limit.burst = burst.parse()?;
+        }
+        Ok(limit)
+    }
+}
+
+/// Rate limits per account, ingest source or client address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimits {
+    /// `POST /projects/:id/streaming/ingest`
+    pub ingest: RateLimit,
+    /// Starting an analysis, over HTTP or the WebSocket
+    pub analysis: RateLimit,
+    /// `POST /projects/:id/files`
+    pub upload: RateLimit,
+    /// `POST /auth/login`, per client address and per username
+    pub login: RateLimit,
+    /// Password attempts on share links, per client address and per link
+    pub share_unlock: RateLimit,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            ingest: RateLimit::new(600, 100),
+            analysis: RateLimit::new(10, 5),
+            upload: RateLimit::new(30, 10),
+            login: RateLimit::new(10, 5),
+            share_unlock: RateLimit::new(10, 5),
+        }
+    }
+}
+
+/// OpenID Connect provider settings for authorization-code login
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OidcConfig {
+    pub issuer_url: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    /// Callback URL registered with the provider, ending in `/api/auth/oidc/callback`
+    pub redirect_url: String,
+    pub scopes: Vec<String>,
+    /// ID token claim listing the user's groups
+    pub groups_claim: String,
+    /// Members of these groups are server administrators
+    pub admin_groups: Vec<String>,
+    /// Project roles granted to members of a group
+    pub group_roles: Vec<OidcGroupRole>,
+}
+
+/// Grants `role` in `project_id` to members of `group`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OidcGroupRole {
+    pub group: String,
+    pub project_id: String,
+    pub role: ProjectRole,
+}
+
+impl OidcConfig {
+    /// Read the provider settings; `None` unless issuer, client id and redirect URL are all set
+    fn from_env() -> anyhow::Result<Option<Self>> {
+        let (Ok(issuer_url), Ok(client_id), Ok(redirect_url)) = (
+            env::var("SYNAPSE_OIDC_ISSUER"),
+            env::var("SYNAPSE_OIDC_CLIENT_ID"),
+            env::var("SYNAPSE_OIDC_REDIRECT_URL"),
+        ) else {
+            return Ok(None);
+        };
+
+        let list = |name: &str| -> Vec<String> {
+            env::var(name)
+                .map(|value| {
+                    value
+                        .split(',')
+                        .map(|item| item.trim().to_string())
+                        .filter(|item| !item.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+
+        let scopes = match list("SYNAPSE_OIDC_SCOPES") {
+            scopes if scopes.is_empty() => vec!["openid".to_string(), "profile".to_string(), "email".to_string()],
+            scopes => scopes,
+        };
+
+        Ok(Some(Self {
+            issuer_url: issuer_url.trim_end_matches('/').to_string(),
+            client_id,
+            client_secret: env::var("SYNAPSE_OIDC_CLIENT_SECRET").ok(),
+            redirect_url,
+            scopes,
+            groups_claim: env::var("SYNAPSE_OIDC_GROUPS_CLAIM").unwrap_or_else(|_| "groups".to_string()),
+            admin_groups: list("SYNAPSE_OIDC_ADMIN_GROUPS"),
+            group_roles: list("SYNAPSE_OIDC_GROUP_ROLES")
+                .iter()
+                .map(|mapping| OidcGroupRole::parse(mapping))
+                .collect::<anyhow::Result<_>>()?,
+        }))
+    }
+
+    /// Whether the groups grant server administration, and the best role they grant per project
+    pub fn grants_for(&self, groups: &[String]) -> (bool, Vec<(String, ProjectRole)>) {
+        let is_admin = groups.iter().any(|group| self.admin_groups.contains(group));
+
+        let mut project_roles: Vec<(String, ProjectRole)> = Vec::new();
+        for mapping in self.group_roles.iter().filter(|mapping| groups.contains(&mapping.group)) {
+            match project_roles.iter_mut().find(|(project_id, _)| *project_id == mapping.project_id) {
+                Some((_, role)) => *role = (*role).max(mapping.role),
+                None => project_roles.push((mapping.project_id.clone(), mapping.role)),
+            }
+        }
+
+        (is_admin, project_roles)
+    }
+}
+
+impl OidcGroupRole {
+    /// Parse `group=project_id:role`, e.g. `sre=5f0c...:analyst`
+    pub fn parse(mapping: &str) -> anyhow::Result<Self> {
+        let (group, grant) = mapping
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("Invalid OIDC group role '{}', expected group=project_id:role", mapping))?;
+        let (project_id, role) = grant
+            .rsplit_once(':')
+            .ok_or_else(|| anyhow::anyhow!("Invalid OIDC group role '{}', expected group=project_id:role", mapping))?;
+
+        Ok(Self {
+            group: group.trim().to_string(),
+            project_id: project_id.trim().to_string(),
+            role: role.trim().parse().map_err(|e: String| anyhow::anyhow!(e))?,
+        })
+    }
 }
 
 impl Default for WebConfig {
@@ -26,6 +221,8 @@ impl Default for WebConfig {
             max_upload_size: 50 * 1024 * 1024, // 50MB
             max_projects: 100,
             analysis_timeout_secs: 300, // 5 minutes
+            job_workers: 2,
+            job_max_attempts: 3,
             cors_origins: vec!["http://localhost:3000".to_string()],
             // Frontend directory - check multiple locations
             // CRITICAL: Always use absolute paths for Windows compatibility
@@ -91,11 +288,32 @@ impl Default for WebConfig {
                 }
             },
             upload_dir: "./uploads".to_string(),
+            auth_enabled: false,
+            session_ttl_hours: 24 * 7, // 1 week
+            secure_cookies: false,
+            oidc: None,
+            public_url: None,
+            rate_limits: RateLimits::default(),
+            trusted_proxies: Vec::new(),
+            daily_analysis_quota: 0,
+            log_retention_days: 7,
+            log_retention_mb: 1024,
+            metrics_token: None,
+            notification_max_attempts: 5,
+            notification_commands: Vec::new(),
+            notification_allowed_networks: Vec::new(),
         }
     }
 }
 
 impl WebConfig {
+    /// Base URL for links handed out to people outside the dashboard
+    pub fn public_base_url(&self) -> String {
+        self.public_url
+            .clone()
+            .unwrap_or_else(|| format!("http://localhost:{}", self.port))
+    }
+
     pub fn load() -> anyhow::Result<Self> {
         let mut config = Self::default();
 
@@ -123,6 +341,14 @@ impl WebConfig {
             config.analysis_timeout_secs = timeout.parse()?;
         }
 
+        if let Ok(workers) = env::var("SYNAPSE_JOB_WORKERS") {
+            config.job_workers = workers.parse()?;
+        }
+
+        if let Ok(max_attempts) = env::var("SYNAPSE_JOB_MAX_ATTEMPTS") {
+            config.job_max_attempts = max_attempts.parse()?;
+        }
+
         if let Ok(origins) = env::var("SYNAPSE_CORS_ORIGINS") {
             config.cors_origins = origins.split(',').map(|s| s.trim().to_string()).collect();
         }
@@ -135,6 +361,85 @@ impl WebConfig {
             config.upload_dir = upload_dir;
         }
 
+        if let Ok(auth_enabled) = env::var("SYNAPSE_AUTH_ENABLED") {
+            config.auth_enabled = auth_enabled.parse()?;
+        }
+
+        if let Ok(ttl) = env::var("SYNAPSE_SESSION_TTL_HOURS") {
+            config.session_ttl_hours = ttl.parse()?;
+        }
+
+        if let Ok(secure_cookies) = env::var("SYNAPSE_SECURE_COOKIES") {
+            config.secure_cookies = secure_cookies.parse()?;
+        }
+
+        config.oidc = OidcConfig::from_env()?;
+
+        if let Ok(public_url) = env::var("SYNAPSE_PUBLIC_URL") {
+            config.public_url = Some(public_url.trim_end_matches('/').to_string());
+        }
+
+        let defaults = config.rate_limits;
+        config.rate_limits = RateLimits {
+            ingest: RateLimit::from_env("SYNAPSE_RATE_LIMIT_INGEST", defaults.ingest)?,
+            analysis: RateLimit::from_env("SYNAPSE_RATE_LIMIT_ANALYSIS", defaults.analysis)?,
+            upload: RateLimit::from_env("SYNAPSE_RATE_LIMIT_UPLOAD", defaults.upload)?,
+            login: RateLimit::from_env("SYNAPSE_RATE_LIMIT_LOGIN", defaults.login)?,
+            share_unlock: RateLimit::from_env("SYNAPSE_RATE_LIMIT_SHARE_UNLOCK", defaults.share_unlock)?,
+        };
+
+        if let Ok(proxies) = env::var("SYNAPSE_TRUSTED_PROXIES") {
+            config.trusted_proxies = proxies
+                .split(',')
+                .map(str::trim)
+                .filter(|proxy| !proxy.is_empty())
+                .map(|proxy| parse_network("SYNAPSE_TRUSTED_PROXIES", proxy))
+                .collect::<anyhow::Result<_>>()?;
+        }
+
+        if let Ok(quota) = env::var("SYNAPSE_DAILY_ANALYSIS_QUOTA") {
+            config.daily_analysis_quota = quota.parse()?;
+        }
+
+        if let Ok(days) = env::var("SYNAPSE_LOG_RETENTION_DAYS") {
+            config.log_retention_days = days.parse()?;
+        }
+
+        if let Ok(mb) = env::var("SYNAPSE_LOG_RETENTION_MB") {
+            config.log_retention_mb = mb.parse()?;
+        }
+
+        config.metrics_token = env::var("SYNAPSE_METRICS_TOKEN").ok().filter(|token| !token.is_empty());
+
+        if let Ok(max_attempts) = env::var("SYNAPSE_NOTIFY_MAX_ATTEMPTS") {
+            config.notification_max_attempts = max_attempts.parse()?;
+        }
+
+        if let Ok(commands) = env::var("SYNAPSE_NOTIFY_COMMANDS") {
+            config.notification_commands = commands
+                .split(',')
+                .map(|command| command.trim().to_string())
+                .filter(|command| !command.is_empty())
+                .collect();
+        }
+
+        if let Ok(networks) = env::var("SYNAPSE_NOTIFY_ALLOWED_NETWORKS") {
+            config.notification_allowed_networks = networks
+                .split(',')
+                .map(str::trim)
+                .filter(|network| !network.is_empty())
+                .map(|network| parse_network("SYNAPSE_NOTIFY_ALLOWED_NETWORKS", network))
+                .collect::<anyhow::Result<_>>()?;
+        }
+
         Ok(config)
     }
 }
+
+/// A network (`10.0.0.0/8`) or a single address from the `variable` list
+fn parse_network(variable: &str, network: &str) -> anyhow::Result<IpNet> {
+    network
+        .parse::<IpNet>()
+        .or_else(|_| network.parse::<IpAddr>().map(IpNet::from))
+        .map_err(|_| anyhow::anyhow!("Invalid address or network {:?} in {}", network, variable))
+}
@@ -1,13 +1,28 @@
 use anyhow::Result;
 use sqlx::{migrate::MigrateDatabase, Pool, Sqlite};
+use std::sync::Arc;
+use synapse_core::project::{PostgresStorage, SqliteStorage, Storage, StorageBackend};
+
+use crate::error_handling::AppError;
 
 #[derive(Clone)]
 pub struct Database {
-    pool: Pool<Sqlite>,
+    storage: Arc<dyn Storage>,
 }
 
 impl Database {
+    /// Open the backend selected by `database_url`: `sqlite://` for a single-user
+    /// install, `postgres://` for a shared team deployment
     pub async fn new(database_url: &str) -> Result<Self> {
+        let storage: Arc<dyn Storage> = match StorageBackend::from_url(database_url)? {
+            StorageBackend::Sqlite => Arc::new(SqliteStorage::new(Self::open_sqlite(database_url).await?)),
+            StorageBackend::Postgres => Arc::new(PostgresStorage::open(database_url, 20).await?),
+        };
+
+        Ok(Self { storage })
+    }
+
+    async fn open_sqlite(database_url: &str) -> Result<Pool<Sqlite>> {
         // Ensure parent directory exists
         if let Some(db_path) = database_url.strip_prefix("sqlite://") {
             if let Some(parent) = std::path::Path::new(db_path).parent() {
@@ -32,16 +47,34 @@ impl Database {
             .connect(database_url)
             .await?;
 
-        Ok(Self { pool })
+        Ok(pool)
     }
 
     pub async fn migrate(&self) -> Result<()> {
-        // Run migrations from the migrations folder
-        sqlx::migrate!("./migrations").run(self.pool()).await?;
-        Ok(())
+        // Run the versioned migrations shared with the CLI and MCP server
+        self.storage.migrate().await
+    }
+
+    /// Projects, log files, analyses, knowledge base and settings, on either backend
+    pub fn storage(&self) -> &dyn Storage {
+        self.storage.as_ref()
+    }
+
+    /// The storage handle itself, for background workers that outlive a request
+    pub fn shared_storage(&self) -> Arc<dyn Storage> {
+        Arc::clone(&self.storage)
+    }
+
+    pub fn backend(&self) -> StorageBackend {
+        self.storage.backend()
     }
 
-    pub fn pool(&self) -> &Pool<Sqlite> {
-        &self.pool
+    /// The SQLite pool, for features that have not been ported to the storage trait
+    ///
+    /// Fails with 503 Service Unavailable when running on PostgreSQL.
+    pub fn pool(&self) -> Result<&Pool<Sqlite>, AppError> {
+        self.storage
+            .sqlite_pool()
+            .ok_or_else(|| AppError::service_unavailable("SQLite-only feature (this server runs on PostgreSQL)"))
     }
 }
@@ -1,12 +1,14 @@
 use axum::{
-    http::{StatusCode, Uri},
+    http::{header, HeaderValue, StatusCode, Uri},
     response::{IntoResponse, Response},
     Json,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use thiserror::Error;
-use tracing::{error, warn};
+use tracing::{error, warn, Instrument};
+
+use crate::telemetry;
 
 #[derive(Error, Debug)]
 pub enum AppError {
@@ -19,13 +21,14 @@ pub enum AppError {
     #[error("Not found: {resource}")]
     NotFound { resource: String },
 
-    #[allow(dead_code)]
     #[error("Unauthorized access")]
     Unauthorized,
 
-    #[allow(dead_code)]
-    #[error("Rate limit exceeded")]
-    RateLimitExceeded,
+    #[error("Forbidden: {message}")]
+    Forbidden { message: String },
+
+    #[error("Rate limit exceeded: {message}")]
+    RateLimitExceeded { message: String, retry_after_secs: u64 },
 
     #[error("File processing error: {message}")]
     FileProcessing { message: String },
@@ -79,7 +82,6 @@ impl ErrorResponse {
         self
     }
 
-    #[allow(dead_code)]
     pub fn with_trace_id(mut self, trace_id: String) -> Self {
         self.trace_id = Some(trace_id);
         self
@@ -88,6 +90,11 @@ impl ErrorResponse {
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
+        let retry_after = match self {
+            AppError::RateLimitExceeded { retry_after_secs, .. } => Some(retry_after_secs),
+            _ => None,
+        };
+
         let (status, error_response) = match self {
             AppError::Database(ref e) => {
                 error!("Database error: {}", e);
@@ -137,15 +144,31 @@ impl IntoResponse for AppError {
                 )
             }
             
-            AppError::RateLimitExceeded => {
-                warn!("Rate limit exceeded");
+            AppError::Forbidden { ref message } => {
+                warn!("Forbidden: {}", message);
+                (
+                    StatusCode::FORBIDDEN,
+                    ErrorResponse::new(
+                        "forbidden",
+                        message.clone(),
+                        "FORBIDDEN".to_string(),
+                    ),
+                )
+            }
+            
+            AppError::RateLimitExceeded { ref message, retry_after_secs } => {
+                warn!("Rate limit exceeded: {}", message);
                 (
                     StatusCode::TOO_MANY_REQUESTS,
                     ErrorResponse::new(
                         "rate_limit_exceeded",
-                        "Too many requests. Please try again later".to_string(),
+                        format!("{}. Try again in {} seconds", message, retry_after_secs),
                         "RATE_LIMIT".to_string(),
-                    ),
+                    )
+                    .with_details(HashMap::from([(
+                        "retry_after_secs".to_string(),
+                        serde_json::Value::from(retry_after_secs),
+                    )])),
                 )
             }
             
@@ -240,7 +263,19 @@ impl IntoResponse for AppError {
             }
         };
 
-        (status, Json(error_response)).into_response()
+        // Point at the request's spans and log lines
+        let error_response = match telemetry::current_trace_id() {
+            Some(trace_id) => error_response.with_trace_id(trace_id),
+            None => error_response,
+        };
+
+        let mut response = (status, Json(error_response)).into_response();
+        if let Some(retry_after_secs) = retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after_secs));
+        }
+        response
     }
 }
 
@@ -264,6 +299,12 @@ impl AppError {
         }
     }
 
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::Forbidden {
+            message: message.into(),
+        }
+    }
+
     pub fn file_processing(message: impl Into<String>) -> Self {
         Self::FileProcessing {
             message: message.into(),
@@ -291,6 +332,14 @@ impl AppError {
         }
     }
 
+    /// Too many requests; the client may retry after `retry_after`, rounded up to whole seconds
+    pub fn rate_limited(message: impl Into<String>, retry_after: std::time::Duration) -> Self {
+        Self::RateLimitExceeded {
+            message: message.into(),
+            retry_after_secs: retry_after.as_secs_f64().ceil().max(1.0) as u64,
+        }
+    }
+
     pub fn internal(message: impl Into<String>) -> Self {
         Self::Internal {
             message: message.into(),
@@ -312,6 +361,16 @@ impl From<crate::validation::ValidationError> for AppError {
     }
 }
 
+// Convert repository errors from synapse-core to AppError
+impl From<anyhow::Error> for AppError {
+    fn from(err: anyhow::Error) -> Self {
+        match err.downcast::<sqlx::Error>() {
+            Ok(db_err) => AppError::Database(db_err),
+            Err(err) => AppError::internal(err.to_string()),
+        }
+    }
+}
+
 // 404 handler
 pub async fn handle_404(uri: Uri) -> impl IntoResponse {
     let error_response = ErrorResponse::new(
@@ -438,10 +497,10 @@ pub struct ServiceHealth {
     pub last_check: String,
 }
 
-pub async fn check_database_health(pool: &sqlx::Pool<sqlx::Sqlite>) -> ServiceHealth {
+pub async fn check_database_health(storage: &dyn synapse_core::project::Storage) -> ServiceHealth {
     let start = std::time::Instant::now();
     
-    match sqlx::query("SELECT 1").fetch_one(pool).await {
+    match storage.ping().await {
         Ok(_) => ServiceHealth {
             status: "healthy".to_string(),
             response_time_ms: Some(start.elapsed().as_millis() as f64),
@@ -474,6 +533,9 @@ pub async fn check_cache_health(cache_manager: &crate::cache::CacheManager) -> S
         error_message: None,
         started_at: chrono::Utc::now(),
         completed_at: None,
+        source: None,
+        created_by: None,
+        alert_id: None,
     };
 
     cache_manager.analysis_cache.put(test_key.clone(), test_analysis, None);
@@ -498,27 +560,60 @@ pub async fn check_cache_health(cache_manager: &crate::cache::CacheManager) -> S
 }
 
 // Middleware for request tracing
-use axum::{extract::Request, middleware::Next};
+use axum::{
+    extract::{MatchedPath, Request},
+    middleware::Next,
+};
+use tracing::field::Empty;
 
 pub async fn trace_request(request: Request, next: Next) -> Response {
     let method = request.method().clone();
     let uri = request.uri().clone();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", |path| path.as_str())
+        .to_string();
     let start = std::time::Instant::now();
 
-    let trace_id = uuid::Uuid::new_v4().to_string();
+    let span = tracing::info_span!(
+        "http.request",
+        otel.name = %format!("{} {}", method, route),
+        otel.kind = "server",
+        otel.status_code = Empty,
+        http.request.method = %method,
+        http.route = %route,
+        url.path = %uri.path(),
+        http.response.status_code = Empty,
+        trace_id = Empty,
+    );
+    let trace_id = telemetry::request_trace_id(&span, request.headers());
+    span.record("trace_id", trace_id.as_str());
+
     tracing::info!(
+        parent: &span,
         trace_id = %trace_id,
         method = %method,
         uri = %uri,
         "Request started"
     );
 
-    let response = next.run(request).await;
-    
+    let mut response = telemetry::with_trace_id(trace_id.clone(), next.run(request))
+        .instrument(span.clone())
+        .await;
+
     let status = response.status();
     let duration = start.elapsed();
+    span.record("http.response.status_code", status.as_u16());
+    if status.is_server_error() {
+        span.record("otel.status_code", "ERROR");
+    }
+    if let Ok(value) = HeaderValue::from_str(&trace_id) {
+        response.headers_mut().insert(telemetry::TRACE_ID_HEADER, value);
+    }
 
     tracing::info!(
+        parent: &span,
         trace_id = %trace_id,
         method = %method,
         uri = %uri,
@@ -567,4 +662,24 @@ mod tests {
         // This would test the response in a real test environment
         assert_eq!(response.status(), StatusCode::BAD_REQUEST);
     }
+
+    #[tokio::test]
+    async fn test_error_response_carries_trace_id() {
+        let response = telemetry::with_trace_id("4bf92f3577b34da6a3ce929d0e0e4736".to_string(), async {
+            AppError::not_found("Project x").into_response()
+        })
+        .await;
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(error.trace_id.as_deref(), Some("4bf92f3577b34da6a3ce929d0e0e4736"));
+    }
+
+    #[test]
+    fn test_rate_limited_response_sets_retry_after() {
+        let error = AppError::rate_limited("Too many uploads", std::time::Duration::from_millis(1500));
+        let response = error.into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "2");
+    }
 }
\ No newline at end of file
@@ -1,14 +1,21 @@
 pub mod advanced_analysis;
+pub mod alerts;
 pub mod analysis;
+pub mod audit;
+pub mod auth;
 pub mod dashboard;
 pub mod export;
 pub mod files;
+pub mod jobs;
 pub mod knowledge;
 pub mod mcp;
 pub mod mcp_enhanced;
 pub mod models;
+pub mod notifications;
 pub mod projects;
+pub mod schedules;
 pub mod settings;
+pub mod shares;
 pub mod streaming;
 pub mod templates;
 pub mod websocket;
@@ -110,18 +110,12 @@ pub async fn analyze_correlations(
     // Get all analyses
     let mut analyses = Vec::new();
     for analysis_id in &req.analysis_ids {
-        if let Some(analysis) = sqlx::query_as::<_, Analysis>(
-            "SELECT id, project_id, log_file_id, analysis_type, provider, level_filter, status, result, error_message, started_at, completed_at
-             FROM analyses WHERE id = ? AND project_id = ?"
-        )
-        .bind(analysis_id)
-        .bind(&project_id)
-        .fetch_optional(state.db.pool())
-        .await
-        .map_err(|e: sqlx::Error| {
-            tracing::error!("Failed to fetch analysis {} for correlation: {}", analysis_id, e);
-            AppError::Database(e)
-        })?
+        if let Some(analysis) = state
+            .db
+            .storage()
+            .get_analysis(analysis_id)
+            .await?
+            .filter(|analysis| analysis.project_id == project_id)
         {
             analyses.push(analysis);
         }
@@ -174,36 +168,24 @@ pub async fn detect_anomalies(
     let lookback_hours = req.lookback_hours.unwrap_or(24);
 
     // Get the target analysis
-    let analysis = sqlx::query_as::<_, Analysis>(
-        "SELECT id, project_id, log_file_id, analysis_type, provider, level_filter, status, result, error_message, started_at, completed_at
-         FROM analyses WHERE id = ? AND project_id = ?"
-    )
-    .bind(&req.analysis_id)
-    .bind(&project_id)
-    .fetch_optional(state.db.pool())
-    .await
-    .map_err(|e: sqlx::Error| {
-        tracing::error!("Failed to fetch analysis {} for anomaly detection: {}", req.analysis_id, e);
-        AppError::Database(e)
-    })?
-    .ok_or_else(|| AppError::not_found(format!("Analysis {} not found", req.analysis_id)))?;
+    let analysis = state
+        .db
+        .storage()
+        .get_analysis(&req.analysis_id)
+        .await?
+        .filter(|analysis| analysis.project_id == project_id)
+        .ok_or_else(|| AppError::not_found(format!("Analysis {} not found", req.analysis_id)))?;
 
     // Get historical analyses for comparison
-    let historical_analyses = sqlx::query_as::<_, Analysis>(
-        "SELECT id, project_id, log_file_id, analysis_type, provider, level_filter, status, result, error_message, started_at, completed_at
-         FROM analyses
-         WHERE project_id = ? AND id != ? AND started_at >= datetime('now', '-{} hours')
-         ORDER BY started_at DESC",
-    )
-    .bind(&project_id)
-    .bind(&req.analysis_id)
-    .bind(lookback_hours)
-    .fetch_all(state.db.pool())
-    .await
-    .map_err(|e: sqlx::Error| {
-        tracing::error!("Failed to fetch historical analyses for project {}: {}", project_id, e);
-        AppError::Database(e)
-    })?;
+    let since = chrono::Utc::now() - chrono::Duration::hours(lookback_hours);
+    let historical_analyses: Vec<Analysis> = state
+        .db
+        .storage()
+        .query_analyses(Some(&project_id), None, None, Some(since))
+        .await?
+        .into_iter()
+        .filter(|historical| historical.id != req.analysis_id)
+        .collect();
 
     // Detect anomalies
     let anomalies = detect_anomalies_in_analysis(&analysis, &historical_analyses, sensitivity);
@@ -240,23 +222,13 @@ pub async fn analyze_multiple_logs(
 
     // Analyze each log file
     for file_id in &req.log_files {
-        let analyses = sqlx::query_as::<_, Analysis>(
-            "SELECT id, project_id, log_file_id, analysis_type, provider, level_filter, status, result, error_message, started_at, completed_at
-             FROM analyses WHERE project_id = ? AND log_file_id = ? AND provider = ? AND level_filter = ?
-             ORDER BY started_at DESC LIMIT 1"
-        )
-        .bind(&project_id)
-        .bind(file_id)
-        .bind(&req.provider)
-        .bind(&req.level_filter)
-        .fetch_all(state.db.pool())
-        .await
-        .map_err(|e: sqlx::Error| {
-            tracing::error!("Failed to fetch analyses for file {}: {}", file_id, e);
-            AppError::Database(e)
-        })?;
-
-        if let Some(latest_analysis) = analyses.first() {
+        let latest = state
+            .db
+            .storage()
+            .find_latest_file_analysis(&project_id, file_id, &req.provider, &req.level_filter)
+            .await?;
+
+        if let Some(latest_analysis) = latest {
             // Extract errors from analysis result
             if let Some(result_json) = &latest_analysis.result {
                 if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(result_json) {
@@ -271,17 +243,7 @@ pub async fn analyze_multiple_logs(
             }
 
             // Get performance metrics
-            let metrics = sqlx::query_as::<_, PerformanceMetric>(
-                "SELECT id, analysis_id, metric_name, metric_value, unit, threshold_value, is_bottleneck, created_at
-                 FROM performance_metrics WHERE analysis_id = ?"
-            )
-            .bind(&latest_analysis.id)
-            .fetch_all(state.db.pool())
-            .await
-            .map_err(|e: sqlx::Error| {
-                tracing::error!("Failed to fetch performance metrics for analysis {}: {}", latest_analysis.id, e);
-                AppError::Database(e)
-            })?;
+            let metrics = state.db.storage().list_performance_metrics(&latest_analysis.id).await?;
 
             file_analyses.insert(
                 file_id.clone(),
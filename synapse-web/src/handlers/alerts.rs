@@ -0,0 +1,343 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+use synapse_core::project::{Alert, AlertQuery, AlertStatus};
+
+use crate::{
+    alerts::{AlertCondition, AlertRule, AlertSeverity},
+    audit,
+    error_handling::AppError,
+    middleware::auth::CurrentUser,
+    AppState,
+};
+
+/// Alerts per page
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+const DEFAULT_COOLDOWN_SECS: i64 = 5 * 60;
+const MAX_COOLDOWN_SECS: i64 = 24 * 60 * 60;
+const MAX_SILENCE_MINUTES: i64 = 30 * 24 * 60;
+
+#[derive(Debug, Deserialize)]
+pub struct AlertRuleRequest {
+    pub name: String,
+    pub description: Option<String>,
+    pub condition: AlertCondition,
+    pub severity: Option<AlertSeverity>,
+    /// Streaming source to evaluate; every source when omitted
+    pub source: Option<String>,
+    pub enabled: Option<bool>,
+    pub cooldown_secs: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SilenceRequest {
+    pub minutes: i64,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AlertListQuery {
+    pub rule_id: Option<String>,
+    pub status: Option<AlertStatus>,
+    pub severity: Option<AlertSeverity>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// Check a rule request and apply it to `rule`; omitted fields take their defaults
+fn apply_rule_request(rule: &mut AlertRule, req: AlertRuleRequest) -> Result<(), AppError> {
+    let name = req.name.trim();
+    if name.is_empty() {
+        return Err(AppError::bad_request("Rule name is required"));
+    }
+    req.condition.compile().map_err(AppError::bad_request)?;
+    let cooldown_secs = req.cooldown_secs.unwrap_or(DEFAULT_COOLDOWN_SECS);
+    if !(0..=MAX_COOLDOWN_SECS).contains(&cooldown_secs) {
+        return Err(AppError::bad_request(format!(
+            "Cooldown must be between 0 and {} seconds",
+            MAX_COOLDOWN_SECS
+        )));
+    }
+
+    rule.name = name.to_string();
+    rule.description = req.description.filter(|description| !description.trim().is_empty());
+    rule.condition = serde_json::to_string(&req.condition).map_err(|e| AppError::internal(e.to_string()))?;
+    rule.severity = req.severity.unwrap_or_default();
+    rule.source = req.source.filter(|source| !source.is_empty());
+    rule.enabled = req.enabled.unwrap_or(true);
+    rule.cooldown_secs = cooldown_secs;
+    Ok(())
+}
+
+async fn find_rule(state: &AppState, project_id: &str, rule_id: &str) -> Result<AlertRule, AppError> {
+    state
+        .db
+        .storage()
+        .get_alert_rule(rule_id)
+        .await?
+        .filter(|rule| rule.project_id == project_id)
+        .ok_or_else(|| AppError::not_found("Alert rule not found"))
+}
+
+async fn find_alert(state: &AppState, project_id: &str, alert_id: &str) -> Result<Alert, AppError> {
+    state
+        .db
+        .storage()
+        .get_alert(alert_id)
+        .await?
+        .filter(|alert| alert.project_id == project_id)
+        .ok_or_else(|| AppError::not_found("Alert not found"))
+}
+
+/// Have the engine pick up a changed rule; the change is stored either way
+async fn reload_rules(state: &AppState) {
+    if let Err(e) = state.alerts.reload().await {
+        tracing::warn!("Failed to reload alert rules: {}", e);
+    }
+}
+
+/// List the project's alert rules, including disabled ones
+pub async fn list_alert_rules(
+    State(state): State<AppState>,
+    Path(project_id): Path<String>,
+) -> Result<Json<Vec<AlertRule>>, AppError> {
+    Ok(Json(state.db.storage().list_alert_rules(&project_id).await?))
+}
+
+pub async fn get_alert_rule(
+    State(state): State<AppState>,
+    Path((project_id, rule_id)): Path<(String, String)>,
+) -> Result<Json<AlertRule>, AppError> {
+    Ok(Json(find_rule(&state, &project_id, &rule_id).await?))
+}
+
+/// Create an alert rule; it is evaluated against batches streamed from then on
+pub async fn create_alert_rule(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Path(project_id): Path<String>,
+    Json(req): Json<AlertRuleRequest>,
+) -> Result<Json<AlertRule>, AppError> {
+    let mut rule = AlertRule::new(project_id.clone(), String::new(), String::new());
+    apply_rule_request(&mut rule, req)?;
+    rule.created_by = current_user.user_id();
+
+    state.db.storage().create_alert_rule(&rule).await?;
+    reload_rules(&state).await;
+
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("alert_rule.create")
+            .with_project(project_id)
+            .with_target("alert_rule", rule.id.clone())
+            .with_details(serde_json::json!({
+                "name": rule.name,
+                "severity": rule.severity,
+                "source": rule.source,
+            })),
+    )
+    .await;
+
+    Ok(Json(rule))
+}
+
+/// Replace an alert rule's settings; a changed condition starts evaluating afresh
+pub async fn update_alert_rule(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Path((project_id, rule_id)): Path<(String, String)>,
+    Json(req): Json<AlertRuleRequest>,
+) -> Result<Json<AlertRule>, AppError> {
+    let mut rule = find_rule(&state, &project_id, &rule_id).await?;
+    apply_rule_request(&mut rule, req)?;
+
+    if !state.db.storage().update_alert_rule(&rule).await? {
+        return Err(AppError::not_found("Alert rule not found"));
+    }
+    reload_rules(&state).await;
+
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("alert_rule.update")
+            .with_project(project_id.clone())
+            .with_target("alert_rule", rule_id.clone())
+            .with_details(serde_json::json!({
+                "name": rule.name,
+                "severity": rule.severity,
+                "enabled": rule.enabled,
+            })),
+    )
+    .await;
+
+    Ok(Json(find_rule(&state, &project_id, &rule_id).await?))
+}
+
+/// Delete an alert rule together with the alerts it fired
+pub async fn delete_alert_rule(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Path((project_id, rule_id)): Path<(String, String)>,
+) -> Result<StatusCode, AppError> {
+    if !state.db.storage().delete_alert_rule(&project_id, &rule_id).await? {
+        return Err(AppError::not_found("Alert rule not found"));
+    }
+    reload_rules(&state).await;
+
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("alert_rule.delete")
+            .with_project(project_id)
+            .with_target("alert_rule", rule_id),
+    )
+    .await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Stop a rule firing new alerts for a while; matches on open alerts are still counted
+pub async fn silence_alert_rule(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Path((project_id, rule_id)): Path<(String, String)>,
+    Json(req): Json<SilenceRequest>,
+) -> Result<Json<AlertRule>, AppError> {
+    if !(1..=MAX_SILENCE_MINUTES).contains(&req.minutes) {
+        return Err(AppError::bad_request(format!(
+            "Silence must last between 1 and {} minutes",
+            MAX_SILENCE_MINUTES
+        )));
+    }
+
+    let mut rule = find_rule(&state, &project_id, &rule_id).await?;
+    rule.silenced_until = Some(Utc::now() + Duration::minutes(req.minutes));
+    rule.silence_reason = req.reason.filter(|reason| !reason.trim().is_empty());
+    state.db.storage().update_alert_rule(&rule).await?;
+    reload_rules(&state).await;
+
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("alert_rule.silence")
+            .with_project(project_id.clone())
+            .with_target("alert_rule", rule_id.clone())
+            .with_details(serde_json::json!({
+                "silenced_until": rule.silenced_until,
+                "reason": rule.silence_reason,
+            })),
+    )
+    .await;
+
+    Ok(Json(find_rule(&state, &project_id, &rule_id).await?))
+}
+
+/// End a rule's silence early
+pub async fn unsilence_alert_rule(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Path((project_id, rule_id)): Path<(String, String)>,
+) -> Result<Json<AlertRule>, AppError> {
+    let mut rule = find_rule(&state, &project_id, &rule_id).await?;
+    rule.silenced_until = None;
+    rule.silence_reason = None;
+    state.db.storage().update_alert_rule(&rule).await?;
+    reload_rules(&state).await;
+
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("alert_rule.unsilence")
+            .with_project(project_id.clone())
+            .with_target("alert_rule", rule_id.clone()),
+    )
+    .await;
+
+    Ok(Json(find_rule(&state, &project_id, &rule_id).await?))
+}
+
+/// The project's alert history, newest first
+pub async fn list_alerts(
+    State(state): State<AppState>,
+    Path(project_id): Path<String>,
+    Query(query): Query<AlertListQuery>,
+) -> Result<Json<Vec<Alert>>, AppError> {
+    let filter = AlertQuery {
+        project_id: Some(project_id),
+        rule_id: query.rule_id,
+        status: query.status,
+        severity: query.severity,
+        since: query.since,
+        until: query.until,
+        limit: Some(query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)),
+        offset: Some(query.offset.unwrap_or(0).max(0)),
+    };
+    Ok(Json(state.db.storage().query_alerts(&filter).await?))
+}
+
+pub async fn get_alert(
+    State(state): State<AppState>,
+    Path((project_id, alert_id)): Path<(String, String)>,
+) -> Result<Json<Alert>, AppError> {
+    Ok(Json(find_alert(&state, &project_id, &alert_id).await?))
+}
+
+/// Mark a firing alert as being looked into
+pub async fn acknowledge_alert(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Path((project_id, alert_id)): Path<(String, String)>,
+) -> Result<Json<Alert>, AppError> {
+    let alert = find_alert(&state, &project_id, &alert_id).await?;
+    let user_id = current_user.user_id();
+    if !state
+        .db
+        .storage()
+        .acknowledge_alert(&project_id, &alert_id, user_id.as_deref())
+        .await?
+    {
+        return Err(AppError::bad_request(format!("Alert is already {}", alert.status)));
+    }
+
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("alert.acknowledge")
+            .with_project(project_id.clone())
+            .with_target("alert", alert_id.clone()),
+    )
+    .await;
+
+    Ok(Json(find_alert(&state, &project_id, &alert_id).await?))
+}
+
+/// Close an alert; the rule's next match fires a new one once its cooldown has passed
+pub async fn resolve_alert(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Path((project_id, alert_id)): Path<(String, String)>,
+) -> Result<Json<Alert>, AppError> {
+    find_alert(&state, &project_id, &alert_id).await?;
+    if !state.db.storage().resolve_alert(&project_id, &alert_id).await? {
+        return Err(AppError::bad_request("Alert is already resolved"));
+    }
+
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("alert.resolve")
+            .with_project(project_id.clone())
+            .with_target("alert", alert_id.clone()),
+    )
+    .await;
+
+    Ok(Json(find_alert(&state, &project_id, &alert_id).await?))
+}
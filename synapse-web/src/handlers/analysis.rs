@@ -7,8 +7,11 @@ use serde::Deserialize;
 use tokio::fs;
 
 use crate::{
+    audit,
     circuit_breaker::{CircuitBreakerConfig, CircuitBreakerRegistry, CircuitBreaker},
     error_handling::AppError,
+    jobs::{queue_analysis, JobPayload, MAX_PRIORITY},
+    middleware::{auth::CurrentUser, rate_limit::check_analysis_quota},
     models::*,
     validation::Validator,
     AppState
@@ -24,6 +27,7 @@ pub struct AnalysisQuery {
 
 pub async fn start_analysis(
     State(state): State<AppState>,
+    current_user: CurrentUser,
     Path((project_id, file_id)): Path<(String, String)>,
     Json(req): Json<AnalysisRequest>,
 ) -> Result<Json<Analysis>, AppError> {
@@ -49,37 +53,34 @@ pub async fn start_analysis(
             })?;
 
     // Validate timeout if provided
-    let timeout_seconds = if let Some(timeout) = req.timeout_seconds {
-        if !(60..=1800).contains(&timeout) { // 1 minute to 30 minutes
+    let timeout_seconds = match req.timeout_seconds {
+        Some(timeout) if !(60..=1800).contains(&timeout) => { // 1 minute to 30 minutes
             return Err(AppError::validation("Timeout must be between 60 and 1800 seconds"));
         }
-        timeout
-    } else {
-        // Get default from settings
-        let settings_timeout = sqlx::query!("SELECT analysis_timeout_seconds FROM settings WHERE id = 1")
-            .fetch_optional(state.db.pool())
-            .await
-            .map_err(AppError::Database)?
-            .and_then(|row| row.analysis_timeout_seconds)
-            .unwrap_or(300);
-        settings_timeout as u32
+        Some(timeout) => timeout as u64,
+        None => default_timeout_secs(&state).await?,
     };
 
+    let priority = req.priority.unwrap_or(0);
+    if !(-MAX_PRIORITY..=MAX_PRIORITY).contains(&priority) {
+        return Err(AppError::validation(format!(
+            "Priority must be between -{} and {}",
+            MAX_PRIORITY, MAX_PRIORITY
+        )));
+    }
+
     // Verify project and file exist
-    let log_file = sqlx::query_as::<_, LogFile>(
-        "SELECT id, project_id, filename, file_size, line_count, upload_path, created_at
-         FROM log_files WHERE id = ? AND project_id = ?",
-    )
-    .bind(&file_id)
-    .bind(&project_id)
-    .fetch_optional(state.db.pool())
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to fetch log file {} for project {}: {}", file_id, project_id, e);
-        AppError::Database(e)
-    })?
+    let log_file = state.db.storage()
+        .get_log_file(&project_id, &file_id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to fetch log file {} for project {}: {}", file_id, project_id, e);
+            AppError::from(e)
+        })?
     .ok_or_else(|| AppError::not_found(format!("Log file {} not found", file_id)))?;
 
+    check_analysis_quota(&state, &project_id).await?;
+
     // Create analysis record using sanitized values
     let analysis = Analysis::new(
         project_id,
@@ -87,180 +88,76 @@ pub async fn start_analysis(
         "file".to_string(),
         sanitized_provider.clone(),
         sanitized_level.clone(),
-    );
+    )
+    .with_created_by(current_user.user_id());
 
     // Save analysis to database
-    let status_value = analysis.status as i32;
-    sqlx::query!(
-        "INSERT INTO analyses (id, project_id, log_file_id, analysis_type, provider, level_filter, status, started_at)
-         VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
-        analysis.id,
-        analysis.project_id,
-        analysis.log_file_id,
-        analysis.analysis_type,
-        analysis.provider,
-        analysis.level_filter,
-        status_value,
-        analysis.started_at
+    state.db.storage()
+        .create_analysis(&analysis)
+        .await
+        .map_err(AppError::from)?;
+
+    let selected_model = state.db.storage()
+        .get_settings()
+        .await
+        .map_err(AppError::from)?
+        .and_then(|settings| settings.selected_model);
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("analysis.start")
+            .with_project(analysis.project_id.clone())
+            .with_target("analysis", analysis.id.clone())
+            .with_details(serde_json::json!({
+                "provider": analysis.provider,
+                "model": selected_model,
+                "level": analysis.level_filter,
+                "log_file_id": analysis.log_file_id,
+            })),
     )
-    .execute(state.db.pool())
-    .await
-    .map_err(AppError::Database)?;
-
-    // Start analysis in background with sanitized values
-    let analysis_id = analysis.id.clone();
-    let db_pool = state.db.pool().clone();
-    let file_path = log_file.upload_path.clone();
-    let provider = sanitized_provider.clone();
-    let level = sanitized_level.clone();
-    let user_context = sanitized_context.clone();
-    let circuit_breakers = state.circuit_breakers.clone();
-
-    tokio::spawn(async move {
-        tracing::info!(" Starting analysis {} for file: {}", analysis_id, file_path);
-
-        // Fetch API key and selected model from settings
-        let (api_key, selected_model) = match sqlx::query!("SELECT api_key, selected_model FROM settings LIMIT 1")
-            .fetch_optional(&db_pool)
-            .await
-        {
-            Ok(Some(settings)) if !settings.api_key.is_empty() => {
-                tracing::info!("API key found for analysis {}", analysis_id);
-                tracing::info!("Selected model from settings: {:?}", settings.selected_model);
-                tracing::info!("Raw settings object: {:#?}", settings);
-                (Some(settings.api_key), settings.selected_model)
-            },
-            Ok(_) => {
-                tracing::warn!("No API key found in settings for analysis {}", analysis_id);
-                (None, None)
-            },
-            Err(e) => {
-                tracing::error!("Failed to fetch settings for analysis {}: {}", analysis_id, e);
-                (None, None)
-            }
-        };
+    .await;
 
-        tracing::info!("Calling perform_analysis_with_context for {} with provider: {}, level: {}, timeout: {}s", analysis_id, provider, level, timeout_seconds);
-        tracing::info!("User context provided: {}", user_context.is_some());
-        tracing::info!("Using model: {:?}", selected_model);
-        
-        // Use enhanced analysis function that supports context and model selection
-        let result = perform_analysis_with_context(
-            &file_path,
-            &level,
-            &provider,
-            api_key.as_deref(),
-            &circuit_breakers,
-            timeout_seconds as u64,
-            user_context.as_deref(),
-            selected_model.as_deref(),
-        ).await;
-
-        match result {
-            Ok(analysis_result) => {
-                tracing::info!("Analysis {} completed successfully, serializing result", analysis_id);
-                match serde_json::to_string(&analysis_result) {
-                    Ok(result_json) => {
-                        tracing::info!("Serialized result for analysis {} ({} chars)", analysis_id, result_json.len());
-                        // Retry database update up to 3 times
-                        for attempt in 1..=3 {
-                            match sqlx::query!(
-                                "UPDATE analyses SET status = ?, result = ?, completed_at = CURRENT_TIMESTAMP WHERE id = ?",
-                                AnalysisStatus::Completed as i32,
-                                result_json,
-                                analysis_id
-                            ).execute(&db_pool).await {
-                                Ok(_) => {
-                                    tracing::info!("Analysis {} database update completed successfully", analysis_id);
-                                    break;
-                                }
-                                Err(e) => {
-                                    tracing::error!("Failed to update analysis {} status (attempt {}): {}", analysis_id, attempt, e);
-                                    if attempt == 3 {
-                                        tracing::error!("Failed to update analysis {} after 3 attempts, giving up", analysis_id);
-                                    } else {
-                                        tokio::time::sleep(tokio::time::Duration::from_millis(100 * attempt)).await;
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        tracing::error!("Failed to serialize analysis result for {}: {}", analysis_id, e);
-                        // Try to update with error status instead
-                        let error_msg = format!("Serialization error: {}", e);
-                        for attempt in 1..=3 {
-                            match sqlx::query!(
-                                "UPDATE analyses SET status = ?, error_message = ?, completed_at = CURRENT_TIMESTAMP WHERE id = ?",
-                                AnalysisStatus::Failed as i32,
-                                error_msg,
-                                analysis_id
-                            ).execute(&db_pool).await {
-                                Ok(_) => break,
-                                Err(db_e) => {
-                                    tracing::error!("Failed to update analysis {} with serialization error (attempt {}): {}", analysis_id, attempt, db_e);
-                                    if attempt < 3 {
-                                        tokio::time::sleep(tokio::time::Duration::from_millis(100 * attempt)).await;
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-            Err(error) => {
-                tracing::error!("Analysis {} FAILED with error: {}", analysis_id, error);
-                tracing::error!("Error chain for analysis {}: {:#}", analysis_id, error);
-
-                // Get the full error chain
-                let error_msg = format!("{:#}", error);
-                let truncated_error = if error_msg.len() > 1000 {
-                    format!("{}... (truncated)", &error_msg[..1000])
-                } else {
-                    error_msg
-                };
-
-                tracing::error!("Will store error message for analysis {}: {}", analysis_id, truncated_error);
-
-                // Retry database update up to 3 times
-                for attempt in 1..=3 {
-                    match sqlx::query!(
-                        "UPDATE analyses SET status = ?, error_message = ?, completed_at = CURRENT_TIMESTAMP WHERE id = ?",
-                        AnalysisStatus::Failed as i32,
-                        truncated_error,
-                        analysis_id
-                    ).execute(&db_pool).await {
-                        Ok(_) => {
-                            tracing::info!("Analysis {} failed and status updated in database", analysis_id);
-                            break;
-                        }
-                        Err(e) => {
-                            tracing::error!("Failed to update failed analysis {} status (attempt {}): {}", analysis_id, attempt, e);
-                            if attempt < 3 {
-                                tokio::time::sleep(tokio::time::Duration::from_millis(100 * attempt)).await;
-                            }
-                        }
-                    }
-                }
-            }
-        }
-    });
+    // Queue the analysis; a worker picks it up, retrying on failure and after restarts
+    let payload = JobPayload {
+        user_context: sanitized_context,
+        ..Default::default()
+    };
+    let job = queue_analysis(&state, &analysis, payload, None, priority, timeout_seconds)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to queue analysis {}: {}", analysis.id, e);
+            AppError::internal(format!("Failed to queue analysis: {}", e))
+        })?;
+    tracing::info!(
+        "Queued analysis {} for file {} as job {} (priority {}, timeout {}s)",
+        analysis.id,
+        log_file.filename,
+        job.id,
+        priority,
+        timeout_seconds
+    );
 
     Ok(Json(analysis))
 }
 
+/// Analysis timeout from settings, or the server's `analysis_timeout_secs`
+pub(crate) async fn default_timeout_secs(state: &AppState) -> Result<u64, AppError> {
+    let settings_timeout = state.db.storage()
+        .get_settings()
+        .await
+        .map_err(AppError::from)?
+        .and_then(|settings| settings.analysis_timeout_seconds);
+    Ok(settings_timeout.map_or(state.config.analysis_timeout_secs, |timeout| timeout as u64))
+}
+
 pub async fn get_analysis(
     State(state): State<AppState>,
     Path(analysis_id): Path<String>,
 ) -> Result<Json<serde_json::Value>, AppError> {
-    let analysis = sqlx::query_as::<_, Analysis>(
-        "SELECT id, project_id, log_file_id, analysis_type, provider, level_filter, status, result, error_message, started_at, completed_at
-         FROM analyses WHERE id = ?"
-    )
-    .bind(&analysis_id)
-    .fetch_optional(state.db.pool())
-    .await
-    .map_err(AppError::Database)?
+    let analysis = state.db.storage()
+        .get_analysis(&analysis_id)
+        .await
+        .map_err(AppError::from)?
     .ok_or_else(|| AppError::not_found(format!("Analysis {} not found", analysis_id)))?;
 
     // Transform the analysis to match frontend expectations
@@ -295,72 +192,23 @@ pub async fn list_analyses(
     let offset = params.offset.unwrap_or(0);
 
     // Get total count
-    let total_row = sqlx::query!(
-        "SELECT COUNT(*) as count FROM analyses WHERE project_id = ?",
-        project_id
-    )
-    .fetch_one(state.db.pool())
-    .await
-    .map_err(AppError::Database)?;
-    let total = total_row.count;
+    let total = state.db.storage()
+        .count_analyses(&project_id)
+        .await
+        .map_err(AppError::from)?;
 
     // Get analyses with optional file information
-    let analyses_with_files = sqlx::query!(
-        r#"
-        SELECT 
-            a.id, a.project_id, a.log_file_id, a.analysis_type, a.provider, a.level_filter,
-            a.status, a.result, a.error_message, a.started_at, a.completed_at,
-            f.filename
-        FROM analyses a
-        LEFT JOIN log_files f ON a.log_file_id = f.id
-        WHERE a.project_id = ?
-        ORDER BY a.started_at DESC
-        LIMIT ? OFFSET ?
-        "#,
-        project_id,
-        limit,
-        offset
-    )
-    .fetch_all(state.db.pool())
-    .await
-    .map_err(AppError::Database)?;
-
-    let mut analyses = Vec::new();
-    for row in analyses_with_files {
-        let analysis_id = row.id.ok_or_else(|| {
-            tracing::error!("Database corruption: analysis record missing ID");
-            AppError::internal("Database corruption: analysis record missing ID")
-        })?;
-
-        let analysis = Analysis {
-            id: analysis_id,
-            project_id: row.project_id,
-            log_file_id: row.log_file_id,
-            analysis_type: row.analysis_type,
-            provider: row.provider,
-            level_filter: row.level_filter,
-            status: match row.status {
-                0 => AnalysisStatus::Pending,
-                1 => AnalysisStatus::Running,
-                2 => AnalysisStatus::Completed,
-                3 => AnalysisStatus::Failed,
-                _ => AnalysisStatus::Failed,
-            },
-            result: row.result,
-            error_message: row.error_message,
-            started_at: row.started_at.and_utc(),
-            completed_at: row.completed_at.map(|dt: chrono::NaiveDateTime| dt.and_utc()),
-        };
-
-        analyses.push(AnalysisWithFile {
-            analysis,
-            filename: row.filename,
-        });
-    }
+    let analyses = state.db.storage()
+        .list_analyses_with_files(&project_id, limit, offset)
+        .await
+        .map_err(AppError::from)?
+        .into_iter()
+        .map(|(analysis, filename)| AnalysisWithFile { analysis, filename })
+        .collect();
 
     Ok(Json(AnalysisListResponse {
         analyses,
-        total: total.into(),
+        total,
     }))
 }
 
@@ -420,78 +268,41 @@ pub async fn perform_analysis_with_context(
         },
         Ok(Err(e)) => {
             tracing::error!("Analysis failed with error: {}", e);
-            Err(anyhow::anyhow!("Analysis failed: {}", e))
+            // Keep the source so the provider error kind can be told apart in the metrics
+            Err(e.context("Analysis failed"))
         },
-        Err(_) => {
+        Err(elapsed) => {
             tracing::error!("Analysis timed out after {} seconds", timeout_secs);
             let error_msg = format!("Analysis timed out after {} seconds", timeout_secs);
             tracing::error!("Analysis timed out for provider {} after {} seconds", provider, timeout_secs);
-            Err(anyhow::anyhow!(error_msg))
+            Err(anyhow::Error::new(elapsed).context(error_msg))
         }
     }
 }
 
-pub async fn perform_analysis(
-    file_path: &str,
+/// Analyze lines already in memory, such as a streaming source's recent entries
+pub async fn perform_analysis_of_lines(
+    lines: Vec<String>,
     level: &str,
     provider: &str,
     api_key: Option<&str>,
     circuit_breakers: &std::sync::Arc<CircuitBreakerRegistry>,
     timeout_secs: u64,
-    user_context: Option<&str>,
     selected_model: Option<&str>,
 ) -> anyhow::Result<AnalysisResponse> {
-    tracing::info!("perform_analysis called for file: {}", file_path);
-    tracing::info!("Analysis parameters - Level: {}, Provider: {}, Timeout: {}s", level, provider, timeout_secs);
-    tracing::info!("🔑 API key provided: {}", api_key.is_some());
-
-    // Check file size first to determine if we should use streaming
-    let metadata = fs::metadata(file_path).await
-        .map_err(|e| {
-            tracing::error!("Failed to read file metadata for {}: {}", file_path, e);
-            anyhow::anyhow!("Failed to read file metadata: {}", e)
-        })?;
-    let file_size = metadata.len();
-
-    tracing::info!("File size: {} bytes ({:.2} MB)", file_size, file_size as f64 / 1024.0 / 1024.0);
-
-    // If file is larger than 10MB, use streaming approach
-    if file_size > 10 * 1024 * 1024 {
-        tracing::info!("🌊 Large file detected ({} bytes), using streaming approach", file_size);
-        return perform_analysis_streaming(
-            file_path, level, provider, api_key, circuit_breakers, timeout_secs, user_context, selected_model
-        ).await;
-    }
-
-    tracing::info!("Small file detected, using standard approach");
+    tracing::info!("Analyzing {} streamed lines with {} (timeout {}s)", lines.len(), provider, timeout_secs);
+    let _circuit_breaker = create_or_get_circuit_breaker(circuit_breakers, provider, timeout_secs).await?;
 
-    // For smaller files, use the original approach
-    let _circuit_breaker = create_or_get_circuit_breaker(circuit_breakers, provider, timeout_secs).await
-        .map_err(|e| {
-            tracing::error!("Failed to create circuit breaker: {}", e);
-            e
-        })?;
-
-    tracing::info!("Starting timeout wrapper with {}s timeout", timeout_secs);
     let result = tokio::time::timeout(
         std::time::Duration::from_secs(timeout_secs),
-        analyze_large_file(file_path, level, provider, api_key, selected_model)
-    ).await;
-
+        analyze_lines(lines, level, provider, api_key, selected_model),
+    )
+    .await;
     match result {
-        Ok(Ok(analysis_result)) => {
-            tracing::info!("Analysis completed successfully");
-            Ok(analysis_result)
-        },
-        Ok(Err(e)) => {
-            tracing::error!("Analysis failed with error: {}", e);
-            Err(anyhow::anyhow!("Analysis failed: {}", e))
-        },
-        Err(_) => {
-            tracing::error!("Analysis timed out after {} seconds", timeout_secs);
-            let error_msg = format!("Analysis timed out after {} seconds", timeout_secs);
+        Ok(result) => result.map_err(|e| e.context("Analysis failed")),
+        Err(elapsed) => {
             tracing::error!("Analysis timed out for provider {} after {} seconds", provider, timeout_secs);
-            Err(anyhow::anyhow!(error_msg))
+            Err(anyhow::Error::new(elapsed).context(format!("Analysis timed out after {} seconds", timeout_secs)))
         }
     }
 }
@@ -529,11 +340,11 @@ async fn perform_analysis_streaming(
             tracing::error!("Full error chain: {:#}", e);
             Err(anyhow::anyhow!(e))
         },
-        Err(_) => {
+        Err(elapsed) => {
             let error_msg = format!("Analysis timed out after {} seconds", timeout_secs);
             tracing::error!("Analysis timed out for provider {} after {} seconds", provider, timeout_secs);
             tracing::error!("File: {}, Level: {}", file_path, level);
-            Err(anyhow::anyhow!(error_msg))
+            Err(anyhow::Error::new(elapsed).context(error_msg))
         }
     }
 }
@@ -668,78 +479,6 @@ async fn analyze_large_file_streaming(
     })
 }
 
-/// Analyze large file by reading it in chunks
-async fn analyze_large_file(
-    file_path: &str,
-    level: &str,
-    provider: &str,
-    api_key: Option<&str>,
-    selected_model: Option<&str>,
-) -> anyhow::Result<AnalysisResponse> {
-    tracing::info!("analyze_large_file started for: {}", file_path);
-    tracing::info!("Target log level: {}, Provider: {}", level, provider);
-    tracing::info!("Selected model: {:?}", selected_model);
-
-    // Check file exists and get metadata
-    let metadata = std::fs::metadata(file_path).map_err(|e| {
-        tracing::error!("Cannot access file metadata for {}: {}", file_path, e);
-        anyhow::anyhow!("Cannot access file: {}", e)
-    })?;
-
-    tracing::info!("File size: {} bytes ({:.2} MB)", metadata.len(), metadata.len() as f64 / 1024.0 / 1024.0);
-
-    // Read log file with robust encoding detection
-    tracing::info!("Reading file with encoding detection...");
-    let data = fs::read(file_path).await.map_err(|e| {
-        tracing::error!("Failed to read file {}: {}", file_path, e);
-        anyhow::anyhow!("Failed to read file: {}", e)
-    })?;
-
-    tracing::info!("File read successfully, {} bytes", data.len());
-    tracing::info!("Starting encoding detection and text parsing...");
-
-    let logs = read_file_with_encoding_detection(&data).map_err(|e| {
-        tracing::error!("Encoding detection failed for {}: {}", file_path, e);
-        tracing::error!("Error details: {:#}", e);
-        e
-    })?;
-
-    tracing::info!("Encoding detection completed successfully");
-    tracing::info!("Total lines parsed: {}", logs.len());
-
-    // Skip filtering here - let core library handle it properly with stack trace preservation
-    let _filtered_logs_count = logs.len();
-    let filtered_logs = logs;
-
-    tracing::info!("Prepared {} lines for core library filtering...", filtered_logs.len());
-
-    if filtered_logs.is_empty() {
-        let error_msg = format!("No log entries found matching level '{}' in file", level);
-        tracing::warn!("{}", error_msg);
-        return Err(anyhow::anyhow!(error_msg));
-    }
-
-    // Log first few entries for debugging
-    for (i, log) in filtered_logs.iter().take(3).enumerate() {
-        tracing::debug!("Sample log {}: {}", i + 1,
-            if log.len() > 150 {
-                format!("{}...", &log[..150])
-            } else {
-                log.clone()
-            }
-        );
-    }
-
-    tracing::info!("Sending {} log entries to AI provider '{}'...", filtered_logs.len(), provider);
-
-    // Pass selected_model to analyze_lines
-    analyze_lines(filtered_logs, level, provider, api_key, selected_model).await.map_err(|e| {
-        tracing::error!("AI analysis failed: {}", e);
-        tracing::error!("Error details: {:#}", e);
-        e
-    })
-}
-
 /// Analyze large file with context and model selection
 async fn analyze_large_file_with_context(
     file_path: &str,
@@ -0,0 +1,101 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::header,
+    response::{IntoResponse, Json, Response},
+};
+use synapse_core::project::{AuditEvent, AuditQuery};
+
+use crate::{error_handling::AppError, AppState};
+
+/// Events per page of the audit log, and per query while exporting it
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+
+/// Search the audit log across the server, newest first
+pub async fn list_audit_events(
+    State(state): State<AppState>,
+    Query(filter): Query<AuditQuery>,
+) -> Result<Json<Vec<AuditEvent>>, AppError> {
+    query_page(&state, filter).await.map(Json)
+}
+
+/// Export matching audit events as JSON Lines, newest first
+pub async fn export_audit_events(
+    State(state): State<AppState>,
+    Query(filter): Query<AuditQuery>,
+) -> Result<Response, AppError> {
+    export(&state, filter).await
+}
+
+/// Search the audit log of one project
+pub async fn list_project_audit_events(
+    State(state): State<AppState>,
+    Path(project_id): Path<String>,
+    Query(filter): Query<AuditQuery>,
+) -> Result<Json<Vec<AuditEvent>>, AppError> {
+    query_page(&state, AuditQuery { project_id: Some(project_id), ..filter })
+        .await
+        .map(Json)
+}
+
+/// Export the audit log of one project as JSON Lines
+pub async fn export_project_audit_events(
+    State(state): State<AppState>,
+    Path(project_id): Path<String>,
+    Query(filter): Query<AuditQuery>,
+) -> Result<Response, AppError> {
+    export(&state, AuditQuery { project_id: Some(project_id), ..filter }).await
+}
+
+async fn query_page(state: &AppState, filter: AuditQuery) -> Result<Vec<AuditEvent>, AppError> {
+    let filter = AuditQuery {
+        limit: Some(filter.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)),
+        offset: Some(filter.offset.unwrap_or(0).max(0)),
+        ..filter
+    };
+    Ok(state.db.storage().query_audit_events(&filter).await?)
+}
+
+async fn export(state: &AppState, filter: AuditQuery) -> Result<Response, AppError> {
+    // An explicit limit caps the export; otherwise every matching event is read page by page
+    let mut remaining = filter.limit.filter(|limit| *limit > 0);
+    let mut offset = filter.offset.unwrap_or(0).max(0);
+    let mut body = String::new();
+
+    loop {
+        let page_size = remaining.map_or(MAX_LIMIT, |remaining| remaining.min(MAX_LIMIT));
+        let page = state
+            .db
+            .storage()
+            .query_audit_events(&AuditQuery {
+                limit: Some(page_size),
+                offset: Some(offset),
+                ..filter.clone()
+            })
+            .await?;
+
+        for event in &page {
+            body.push_str(&serde_json::to_string(event).map_err(|e| {
+                AppError::internal(format!("Failed to serialize audit event: {}", e))
+            })?);
+            body.push('\n');
+        }
+
+        let fetched = page.len() as i64;
+        offset += fetched;
+        remaining = remaining.map(|remaining| remaining - fetched);
+        if fetched < page_size || remaining == Some(0) {
+            break;
+        }
+    }
+
+    let filename = format!("synapse-audit-{}.jsonl", chrono::Utc::now().format("%Y%m%d-%H%M%S"));
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/x-ndjson".to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
+        ],
+        body,
+    )
+        .into_response())
+}
@@ -0,0 +1,622 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Json, Redirect, Response},
+};
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use synapse_core::project::{ApiToken, Session, User};
+use uuid::Uuid;
+
+use crate::{
+    audit,
+    config::OidcConfig,
+    error_handling::AppError,
+    middleware::auth::{
+        authenticate, clear_session_cookie_header, cookie_header, generate_secret,
+        hash_password, hash_secret, read_cookie, session_cookie, session_cookie_header,
+        verify_password, AuthMethod, ClientIp, CurrentUser, API_TOKEN_PREFIX, DUMMY_PASSWORD_HASH,
+        OIDC_LOGIN_COOKIE,
+    },
+    middleware::rate_limit::{self, address_key, LimitedRoute},
+    oidc::{IdentityClaims, OidcClient},
+    AppState,
+};
+
+const MIN_PASSWORD_LENGTH: usize = 8;
+
+#[derive(Debug, Deserialize)]
+pub struct CredentialsRequest {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateUserRequest {
+    pub username: String,
+    pub password: String,
+    pub email: Option<String>,
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub is_admin: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateUserRequest {
+    pub is_active: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTokenRequest {
+    pub name: String,
+    /// Days until the token expires; tokens without one last until revoked
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OidcLoginQuery {
+    /// Dashboard path to return to after signing in
+    pub return_to: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OidcCallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuthStatus {
+    pub auth_enabled: bool,
+    /// Single sign-on is available at `/api/auth/oidc/login`
+    pub oidc_enabled: bool,
+    /// No account exists yet, so the first registration creates the administrator
+    pub setup_required: bool,
+    pub user: Option<User>,
+    pub method: Option<AuthMethod>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreatedToken {
+    #[serde(flatten)]
+    pub token: ApiToken,
+    /// The secret, shown only once
+    pub secret: String,
+}
+
+fn validate_credentials(username: &str, password: &str) -> Result<(), AppError> {
+    let valid_username = !username.is_empty()
+        && username.len() <= 64
+        && username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | '@'));
+    if !valid_username {
+        return Err(AppError::validation(
+            "Username must be 1-64 characters of letters, digits, '.', '_', '-' or '@'",
+        ));
+    }
+    if password.len() < MIN_PASSWORD_LENGTH {
+        return Err(AppError::validation(format!(
+            "Password must be at least {} characters",
+            MIN_PASSWORD_LENGTH
+        )));
+    }
+    Ok(())
+}
+
+async fn create_account(state: &AppState, req: CreateUserRequest) -> Result<User, AppError> {
+    let user = new_account(state, req).await?;
+
+    state.db.storage().create_user(&user).await?;
+    tracing::info!("Created user {} (admin: {})", user.username, user.is_admin);
+    Ok(user)
+}
+
+/// Validate the request and hash its password, without storing the account
+async fn new_account(state: &AppState, req: CreateUserRequest) -> Result<User, AppError> {
+    let username = req.username.trim().to_string();
+    validate_credentials(&username, &req.password)?;
+
+    if state.db.storage().get_user_by_username(&username).await?.is_some() {
+        return Err(AppError::bad_request(format!("Username {} is already taken", username)));
+    }
+
+    let password_hash = hash_password(req.password).await?;
+    let mut user = User::new(username, Some(password_hash), req.is_admin);
+    user.email = req.email;
+    user.display_name = req.display_name;
+    Ok(user)
+}
+
+/// Start a browser session and return the user with its `Set-Cookie` header
+async fn start_session(
+    state: &AppState,
+    user: User,
+    headers: &HeaderMap,
+    client_ip: ClientIp,
+) -> Result<Response, AppError> {
+    let cookie = open_session(state, &user, headers, client_ip).await?;
+
+    let mut response = Json(user).into_response();
+    response.headers_mut().insert(header::SET_COOKIE, cookie);
+    Ok(response)
+}
+
+/// Store a new session for the user, returning the `Set-Cookie` value that carries it
+async fn open_session(
+    state: &AppState,
+    user: &User,
+    headers: &HeaderMap,
+    ClientIp(ip_address): ClientIp,
+) -> Result<HeaderValue, AppError> {
+    let storage = state.db.storage();
+    let secret = generate_secret();
+    let now = Utc::now();
+
+    let session = Session {
+        id: hash_secret(&secret),
+        user_id: user.id.clone(),
+        user_agent: headers
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.chars().take(255).collect()),
+        ip_address,
+        created_at: now,
+        expires_at: now + Duration::hours(state.config.session_ttl_hours),
+        last_seen_at: now,
+    };
+
+    storage.delete_expired_sessions().await?;
+    storage.create_session(&session).await?;
+    storage.record_user_login(&user.id).await?;
+
+    Ok(session_cookie_header(&state.config, &secret))
+}
+
+/// Whether login is required and who, if anyone, is signed in
+pub async fn auth_status(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<AuthStatus>, AppError> {
+    let setup_required = state.db.storage().count_users().await? == 0;
+    let current_user = if state.config.auth_enabled {
+        authenticate(&state, &headers).await?
+    } else {
+        Some(CurrentUser::local())
+    };
+
+    Ok(Json(AuthStatus {
+        auth_enabled: state.config.auth_enabled,
+        oidc_enabled: state.config.auth_enabled && state.oidc.is_some(),
+        setup_required,
+        method: current_user.as_ref().map(|current| current.method),
+        user: current_user.and_then(|current| current.user),
+    }))
+}
+
+/// Create the first account, which becomes the administrator, and sign it in
+///
+/// Once an account exists, further accounts are created by administrators.
+pub async fn register(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    client_ip: ClientIp,
+    Json(req): Json<CredentialsRequest>,
+) -> Result<Response, AppError> {
+    let closed = || {
+        AppError::forbidden("Registration is closed; ask an administrator to create your account")
+    };
+    if state.db.storage().count_users().await? > 0 {
+        return Err(closed());
+    }
+
+    let user = new_account(
+        &state,
+        CreateUserRequest {
+            username: req.username,
+            password: req.password,
+            email: None,
+            display_name: None,
+            is_admin: true,
+        },
+    )
+    .await?;
+
+    // Checked again as part of the insert, so two racing registrations cannot both succeed
+    if !state.db.storage().create_first_user(&user).await? {
+        return Err(closed());
+    }
+    tracing::info!("Created user {} (admin: {})", user.username, user.is_admin);
+
+    start_session(&state, user, &headers, client_ip).await
+}
+
+pub async fn login(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    client_ip: ClientIp,
+    Json(req): Json<CredentialsRequest>,
+) -> Result<Response, AppError> {
+    // Guessing is limited per address, and per account for guesses spread over many addresses
+    let username = req.username.trim();
+    rate_limit::enforce(&state, LimitedRoute::Login, &address_key(&client_ip))?;
+    rate_limit::enforce(&state, LimitedRoute::Login, &format!("username:{}", username.to_lowercase()))?;
+
+    let user = state
+        .db
+        .storage()
+        .get_user_by_username(username)
+        .await?
+        .filter(|user| user.is_active);
+
+    // Same response, after the same work, for unknown users, disabled users and wrong passwords
+    let Some((user, password_hash)) =
+        user.and_then(|user| user.password_hash.clone().map(|hash| (user, hash)))
+    else {
+        verify_password(req.password, DUMMY_PASSWORD_HASH.to_string()).await?;
+        tracing::warn!("Failed login for {}", req.username);
+        return Err(AppError::Unauthorized);
+    };
+
+    if !verify_password(req.password, password_hash).await? {
+        tracing::warn!("Failed login for {}", user.username);
+        return Err(AppError::Unauthorized);
+    }
+
+    tracing::info!("User {} signed in", user.username);
+    start_session(&state, user, &headers, client_ip).await
+}
+
+// Single sign-on
+
+fn oidc_client(state: &AppState) -> Result<&Arc<OidcClient>, AppError> {
+    if !state.config.auth_enabled {
+        return Err(AppError::bad_request(
+            "User accounts are disabled on this server (SYNAPSE_AUTH_ENABLED)",
+        ));
+    }
+    state
+        .oidc
+        .as_ref()
+        .ok_or_else(|| AppError::not_found("Single sign-on is not configured"))
+}
+
+/// Only same-site paths, so the login flow can't be used as an open redirect
+fn safe_return_path(return_to: Option<String>) -> String {
+    return_to
+        .filter(|path| path.starts_with('/') && !path.starts_with("//") && !path.contains('\\'))
+        .unwrap_or_else(|| "/".to_string())
+}
+
+/// Redirect to the identity provider, remembering the login attempt in a short-lived cookie
+pub async fn oidc_login(
+    State(state): State<AppState>,
+    Query(query): Query<OidcLoginQuery>,
+) -> Result<Response, AppError> {
+    let client = oidc_client(&state)?;
+    let request = client.authorization_request().await?;
+    let return_to = hex::encode(safe_return_path(query.return_to));
+
+    let pending = format!(
+        "{}={}.{}.{}.{}; Max-Age=600",
+        OIDC_LOGIN_COOKIE, request.state, request.nonce, request.code_verifier, return_to
+    );
+
+    let mut response = Redirect::to(&request.url).into_response();
+    response
+        .headers_mut()
+        .insert(header::SET_COOKIE, cookie_header(&state.config, &pending));
+    Ok(response)
+}
+
+/// Finish a login: verify the provider's answer, provision the account and start a session
+pub async fn oidc_callback(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    client_ip: ClientIp,
+    Query(query): Query<OidcCallbackQuery>,
+) -> Result<Response, AppError> {
+    let client = oidc_client(&state)?;
+
+    if let Some(error) = query.error {
+        tracing::warn!(
+            "Identity provider rejected sign-in: {} {}",
+            error,
+            query.error_description.unwrap_or_default()
+        );
+        return Err(AppError::Unauthorized);
+    }
+
+    let pending = read_cookie(&headers, OIDC_LOGIN_COOKIE)
+        .ok_or_else(|| AppError::bad_request("Sign-in expired or was started elsewhere; please try again"))?;
+    let [expected_state, nonce, code_verifier, return_to] = pending.splitn(4, '.').collect::<Vec<_>>()[..] else {
+        return Err(AppError::bad_request("Malformed sign-in cookie; please try again"));
+    };
+
+    if query.state.as_deref() != Some(expected_state) {
+        tracing::warn!("OIDC callback state does not match the pending sign-in");
+        return Err(AppError::Unauthorized);
+    }
+    let code = query
+        .code
+        .ok_or_else(|| AppError::bad_request("Missing authorization code"))?;
+
+    let identity = client.exchange_code(&code, code_verifier, nonce).await?;
+    let user = provision_oidc_user(&state, client.config(), identity).await?;
+    let session = open_session(&state, &user, &headers, client_ip).await?;
+    tracing::info!("User {} signed in through single sign-on", user.username);
+
+    let return_to = hex::decode(return_to)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok());
+    let mut response = Redirect::to(&safe_return_path(return_to)).into_response();
+    let response_headers = response.headers_mut();
+    response_headers.append(header::SET_COOKIE, session);
+    response_headers.append(
+        header::SET_COOKIE,
+        cookie_header(&state.config, &format!("{}=; Max-Age=0", OIDC_LOGIN_COOKIE)),
+    );
+    Ok(response)
+}
+
+/// Find or create the account for a provider identity and apply its group grants
+///
+/// The first account on a fresh server becomes an administrator, as with registration.
+/// Membership of projects named in the group mappings follows the user's groups on every
+/// login; other projects are left alone.
+async fn provision_oidc_user(
+    state: &AppState,
+    config: &OidcConfig,
+    identity: IdentityClaims,
+) -> Result<User, AppError> {
+    let storage = state.db.storage();
+    let (in_admin_group, project_roles) = config.grants_for(&identity.groups);
+
+    let user = match storage
+        .get_user_by_oidc_identity(&config.issuer_url, &identity.subject)
+        .await?
+    {
+        Some(user) => {
+            if !user.is_active {
+                return Err(AppError::forbidden("This account has been disabled"));
+            }
+            // Without admin groups configured, administrator status is managed in Synapse
+            let is_admin = if config.admin_groups.is_empty() { user.is_admin } else { in_admin_group };
+            storage
+                .update_user_profile(&user.id, identity.email.as_deref(), identity.name.as_deref(), is_admin)
+                .await?;
+            storage
+                .get_user(&user.id)
+                .await?
+                .ok_or_else(|| AppError::internal("User vanished during sign-in"))?
+        }
+        None => {
+            let is_admin = in_admin_group || storage.count_users().await? == 0;
+            let mut user = User::new(
+                available_username(state, &identity).await?,
+                None,
+                is_admin,
+            )
+            .with_oidc_identity(config.issuer_url.clone(), identity.subject.clone());
+            user.email = identity.email.clone();
+            user.display_name = identity.name.clone();
+
+            storage.create_user(&user).await?;
+            tracing::info!("Provisioned user {} from single sign-on (admin: {})", user.username, is_admin);
+            user
+        }
+    };
+
+    let mut mapped_projects: Vec<&str> = config.group_roles.iter().map(|m| m.project_id.as_str()).collect();
+    mapped_projects.sort_unstable();
+    mapped_projects.dedup();
+
+    for project_id in mapped_projects {
+        let Some(project) = storage.get_project(project_id).await? else {
+            tracing::warn!("OIDC group mapping names unknown project {}", project_id);
+            continue;
+        };
+        // The owner always keeps administering their project
+        if project.created_by.as_deref() == Some(user.id.as_str()) {
+            continue;
+        }
+
+        let current = storage.get_project_role(project_id, &user.id).await?;
+        let granted = project_roles
+            .iter()
+            .find(|(id, _)| id == project_id)
+            .map(|(_, role)| *role);
+
+        match (current, granted) {
+            (current, Some(role)) if current != Some(role) => {
+                storage.set_project_member(project_id, &user.id, role, None).await?;
+            }
+            (Some(_), None) => {
+                storage.remove_project_member(project_id, &user.id, None).await?;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(user)
+}
+
+/// The provider's username if free, otherwise one made unique with the subject's digest
+async fn available_username(state: &AppState, identity: &IdentityClaims) -> Result<String, AppError> {
+    let base: String = identity
+        .username
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | '@'))
+        .take(50)
+        .collect();
+    let base = if base.is_empty() { "user".to_string() } else { base };
+
+    if state.db.storage().get_user_by_username(&base).await?.is_none() {
+        return Ok(base);
+    }
+    Ok(format!("{}-{}", base, &hash_secret(&identity.subject)[..8]))
+}
+
+pub async fn logout(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    if let Some(secret) = session_cookie(&headers) {
+        state.db.storage().delete_session(&hash_secret(secret)).await?;
+    }
+
+    let mut response = StatusCode::NO_CONTENT.into_response();
+    response
+        .headers_mut()
+        .insert(header::SET_COOKIE, clear_session_cookie_header(&state.config));
+    Ok(response)
+}
+
+pub async fn get_current_user(current_user: CurrentUser) -> Result<Json<User>, AppError> {
+    Ok(Json(current_user.account()?.clone()))
+}
+
+pub async fn change_password(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Json(req): Json<ChangePasswordRequest>,
+) -> Result<StatusCode, AppError> {
+    let user = current_user.account()?;
+    let stored_hash = user
+        .password_hash
+        .clone()
+        .ok_or_else(|| AppError::bad_request("This account signs in without a password"))?;
+
+    if !verify_password(req.current_password, stored_hash).await? {
+        return Err(AppError::forbidden("Current password is incorrect"));
+    }
+    validate_credentials(&user.username, &req.new_password)?;
+
+    let password_hash = hash_password(req.new_password).await?;
+    state.db.storage().update_user_password(&user.id, &password_hash).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn list_api_tokens(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+) -> Result<Json<Vec<ApiToken>>, AppError> {
+    let user = current_user.account()?;
+    Ok(Json(state.db.storage().list_api_tokens(&user.id).await?))
+}
+
+pub async fn create_api_token(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Json(req): Json<CreateTokenRequest>,
+) -> Result<Json<CreatedToken>, AppError> {
+    let user = current_user.account()?;
+    let name = req.name.trim().to_string();
+    if name.is_empty() || name.len() > 100 {
+        return Err(AppError::validation("Token name must be 1-100 characters"));
+    }
+    if matches!(req.expires_in_days, Some(days) if !(1..=3650).contains(&days)) {
+        return Err(AppError::validation("Token expiry must be between 1 and 3650 days"));
+    }
+
+    let secret = format!("{}{}", API_TOKEN_PREFIX, generate_secret());
+    let now = Utc::now();
+    let token = ApiToken {
+        id: Uuid::new_v4().to_string(),
+        user_id: user.id.clone(),
+        name,
+        token_hash: hash_secret(&secret),
+        token_prefix: secret.chars().take(API_TOKEN_PREFIX.len() + 8).collect(),
+        created_at: now,
+        last_used_at: None,
+        expires_at: req.expires_in_days.map(|days| now + Duration::days(days)),
+    };
+
+    state.db.storage().create_api_token(&token).await?;
+    tracing::info!("User {} created API token {}", user.username, token.token_prefix);
+    Ok(Json(CreatedToken { token, secret }))
+}
+
+pub async fn revoke_api_token(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Path(token_id): Path<String>,
+) -> Result<StatusCode, AppError> {
+    let user = current_user.account()?;
+    if !state.db.storage().delete_api_token(&user.id, &token_id).await? {
+        return Err(AppError::not_found(format!("API token {} not found", token_id)));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// User administration
+
+pub async fn list_users(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+) -> Result<Json<Vec<User>>, AppError> {
+    current_user.require_admin()?;
+    Ok(Json(state.db.storage().list_users().await?))
+}
+
+pub async fn create_user(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Json(req): Json<CreateUserRequest>,
+) -> Result<Json<User>, AppError> {
+    current_user.require_admin()?;
+    let user = create_account(&state, req).await?;
+
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("user.create")
+            .with_target("user", user.id.clone())
+            .with_details(serde_json::json!({ "username": user.username, "is_admin": user.is_admin })),
+    )
+    .await;
+    Ok(Json(user))
+}
+
+/// Enable or disable an account; disabling signs the user out everywhere
+pub async fn update_user(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Path(user_id): Path<String>,
+    Json(req): Json<UpdateUserRequest>,
+) -> Result<Json<User>, AppError> {
+    current_user.require_admin()?;
+    if !req.is_active && current_user.user_id().as_deref() == Some(user_id.as_str()) {
+        return Err(AppError::bad_request("You cannot disable your own account"));
+    }
+
+    let storage = state.db.storage();
+    if !storage.set_user_active(&user_id, req.is_active).await? {
+        return Err(AppError::not_found(format!("User {} not found", user_id)));
+    }
+
+    let user = storage
+        .get_user(&user_id)
+        .await?
+        .ok_or_else(|| AppError::not_found(format!("User {} not found", user_id)))?;
+
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("user.update")
+            .with_target("user", user.id.clone())
+            .with_details(serde_json::json!({ "username": user.username, "is_active": user.is_active })),
+    )
+    .await;
+    Ok(Json(user))
+}
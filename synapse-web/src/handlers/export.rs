@@ -8,7 +8,7 @@ use std::process::Command;
 use std::io::Write;
 use tempfile::NamedTempFile;
 
-use crate::{error_handling::AppError, models::*, AppState};
+use crate::{error_handling::AppError, AppState};
 
 #[derive(Deserialize)]
 pub struct ExportQuery {
@@ -18,23 +18,6 @@ pub struct ExportQuery {
     pub template: Option<String>, // For HTML exports
 }
 
-#[derive(Deserialize)]
-pub struct ShareRequest {
-    pub analysis_id: String,
-    pub expires_in_hours: Option<i64>, // Default 24 hours
-    pub password_protect: Option<bool>,
-    pub allow_download: Option<bool>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ShareResponse {
-    pub share_id: String,
-    pub share_url: String,
-    pub expires_at: String,
-    pub is_password_protected: bool,
-    pub created_at: String,
-}
-
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ExportMetadata {
     pub analysis_id: String,
@@ -530,110 +513,6 @@ async fn generate_pdf_report(analysis: &serde_json::Value, analysis_id: &str) ->
     }
 }
 
-// Shareable Analysis Links
-pub async fn create_share_link(
-    State(state): State<AppState>,
-    Path(project_id): Path<String>,
-    Json(req): Json<ShareRequest>,
-) -> Result<Json<ShareResponse>, AppError> {
-    // Verify analysis exists and belongs to project
-    let _analysis = sqlx::query_as::<_, Analysis>(
-        "SELECT id, project_id, log_file_id, analysis_type, provider, level_filter, status, result, error_message, started_at, completed_at
-         FROM analyses WHERE id = ? AND project_id = ?"
-    )
-    .bind(&req.analysis_id)
-    .bind(&project_id)
-    .fetch_optional(state.db.pool())
-    .await
-    .map_err(|e: sqlx::Error| { tracing::error!("Database error: {}", e); AppError::Database(e) })?
-    .ok_or_else(|| AppError::not_found("Resource not found"))?;
-
-    let share_id = uuid::Uuid::new_v4().to_string();
-    let expires_in_hours = req.expires_in_hours.unwrap_or(24);
-    let expires_at = chrono::Utc::now() + chrono::Duration::hours(expires_in_hours);
-
-    // Store share information (in a real implementation, this would go to a shares table)
-    // For now, we'll create a knowledge base entry as a placeholder
-    let solution_data = serde_json::json!({
-        "analysis_id": req.analysis_id,
-        "expires_at": expires_at,
-        "password_protected": req.password_protect.unwrap_or(false),
-        "allow_download": req.allow_download.unwrap_or(true)
-    });
-
-    let solution_json = serde_json::to_string(&solution_data).unwrap_or_default();
-    let title = format!("Share Link for Analysis {}", req.analysis_id);
-    let tags_json = serde_json::to_string(&vec!["share", "analysis"]).unwrap_or_default();
-
-    sqlx::query(
-        "INSERT INTO knowledge_base (id, project_id, title, problem_description, solution, tags, severity, is_public, usage_count, created_at, updated_at)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)"
-    )
-    .bind(&share_id)
-    .bind(&project_id)
-    .bind(&title)
-    .bind(format!("Shared analysis: {}", req.analysis_id))
-    .bind(&solution_json)
-    .bind(&tags_json)
-    .bind("low")
-    .bind(true)
-    .bind(0)
-    .bind(chrono::Utc::now())
-    .bind(chrono::Utc::now())
-    .execute(state.db.pool())
-    .await
-    .map_err(|e: sqlx::Error| { tracing::error!("Database error: {}", e); AppError::Database(e) })?;
-
-    let response = ShareResponse {
-        share_id: share_id.clone(),
-        share_url: format!("http://localhost:3000/shared/{}", share_id),
-        expires_at: expires_at.to_rfc3339(),
-        is_password_protected: req.password_protect.unwrap_or(false),
-        created_at: chrono::Utc::now().to_rfc3339(),
-    };
-
-    Ok(Json(response))
-}
-
-// Get shared analysis
-pub async fn get_shared_analysis(
-    State(state): State<AppState>,
-    Path(share_id): Path<String>,
-) -> Result<Response, AppError> {
-    // Get share information from knowledge base (placeholder implementation)
-    let share_info = sqlx::query_as::<_, KnowledgeBaseEntry>(
-        "SELECT id, project_id, title, problem_description, solution, tags, severity, is_public, usage_count, created_at, updated_at
-         FROM knowledge_base WHERE id = $1 AND tags LIKE '%\"share\"%'"
-    )
-    .bind(&share_id)
-    .fetch_optional(state.db.pool())
-    .await
-    .map_err(|e: sqlx::Error| { tracing::error!("Database error: {}", e); AppError::Database(e) })?
-    .ok_or_else(|| AppError::not_found("Resource not found"))?;
-
-    // Parse share data
-    let share_data: serde_json::Value = serde_json::from_str(&share_info.solution)
-        .map_err(|e: serde_json::Error| { tracing::error!("JSON serialization error: {}", e); AppError::internal(format!("Failed to serialize JSON: {}", e)) })?;
-
-    let analysis_id = share_data
-        .get("analysis_id")
-        .and_then(|id| id.as_str())
-        .ok_or_else(|| AppError::not_found("Resource not found"))?;
-
-    let project_id = share_info.project_id.clone();
-
-    // Get analysis data
-    let analysis = get_analysis_with_related_data(state, &project_id, analysis_id).await?;
-
-    // Generate HTML view for shared analysis
-    let html_content = generate_shared_html_view(&analysis, &share_info);
-
-    Response::builder()
-        .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
-        .body(html_content.into())
-        .map_err(|e: axum::http::Error| { tracing::error!("HTTP response error: {}", e); AppError::internal(format!("Failed to build response: {}", e)) })
-}
-
 // Export history
 pub async fn get_export_history(
     State(_state): State<AppState>,
@@ -645,43 +524,21 @@ pub async fn get_export_history(
 }
 
 // Helper functions
-async fn get_analysis_with_related_data(
+pub(crate) async fn get_analysis_with_related_data(
     state: AppState,
     project_id: &str,
     analysis_id: &str,
 ) -> Result<serde_json::Value, AppError> {
-    let _analysis = sqlx::query_as::<_, Analysis>(
-        "SELECT id, project_id, log_file_id, analysis_type, provider, level_filter, status, result, error_message, started_at, completed_at
-         FROM analyses WHERE id = ? AND project_id = ?"
-    )
-    .bind(analysis_id)
-    .bind(project_id)
-    .fetch_optional(state.db.pool())
-    .await
-    .map_err(|e: sqlx::Error| { tracing::error!("Database error: {}", e); AppError::Database(e) })?
-    .ok_or_else(|| AppError::not_found("Resource not found"))?;
-
-    let correlations = sqlx::query_as::<_, ErrorCorrelation>(
-        "SELECT id, project_id, primary_error_id, correlated_error_id, correlation_strength, correlation_type, created_at
-         FROM error_correlations 
-         WHERE project_id = ? AND (primary_error_id = ? OR correlated_error_id = ?)
-         ORDER BY correlation_strength DESC"
-    )
-    .bind(project_id)
-    .bind(analysis_id)
-    .bind(analysis_id)
-    .fetch_all(state.db.pool())
-    .await
-    .map_err(|e: sqlx::Error| { tracing::error!("Database error: {}", e); AppError::Database(e) })?;
-
-    let metrics = sqlx::query_as::<_, PerformanceMetric>(
-        "SELECT id, analysis_id, metric_name, metric_value, unit, threshold_value, is_bottleneck, created_at
-         FROM performance_metrics WHERE analysis_id = ?"
-    )
-    .bind(analysis_id)
-    .fetch_all(state.db.pool())
-    .await
-    .map_err(|e: sqlx::Error| { tracing::error!("Database error: {}", e); AppError::Database(e) })?;
+    let storage = state.db.storage();
+    let _analysis = storage
+        .get_analysis(analysis_id)
+        .await?
+        .filter(|analysis| analysis.project_id == project_id)
+        .ok_or_else(|| AppError::not_found("Resource not found"))?;
+
+    let correlations = storage.list_error_correlations(project_id, analysis_id).await?;
+
+    let metrics = storage.list_performance_metrics(analysis_id).await?;
 
     let analysis_json = serde_json::json!({
         "analysis": _analysis,
@@ -838,43 +695,3 @@ fn generate_html_report(analysis: &serde_json::Value, _include_charts: bool) ->
     html.push_str("</div></body></html>");
     html
 }
-
-fn generate_shared_html_view(
-    analysis: &serde_json::Value,
-    share_info: &KnowledgeBaseEntry,
-) -> String {
-    // Simplified shared view - in real implementation, would be more polished
-    format!(
-        r#"
-<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta charset="UTF-8">
-    <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <title>Shared Analysis - Synapse</title>
-    <style>
-        body {{ font-family: 'Segoe UI', Tahoma, Geneva, Verdana, sans-serif; margin: 0; padding: 20px; background: #f5f5f5; }}
-        .container {{ max-width: 1200px; margin: 0 auto; background: white; padding: 30px; border-radius: 10px; box-shadow: 0 2px 10px rgba(0,0,0,0.1); }}
-        h1 {{ color: #2c3e50; border-bottom: 3px solid #3498db; padding-bottom: 10px; }}
-        .shared-info {{ background: #e8f4fd; padding: 15px; border-radius: 5px; margin-bottom: 20px; }}
-        pre {{ background: #f8f9fa; padding: 15px; border-radius: 5px; overflow-x: auto; }}
-    </style>
-</head>
-<body>
-    <div class="container">
-        <h1>Shared Analysis</h1>
-        <div class="shared-info">
-            <p><strong>Shared by:</strong> Synapse</p>
-            <p><strong>Created:</strong> {}</p>
-            <p><strong>Access:</strong> Public</p>
-        </div>
-        <h2>Analysis Data</h2>
-        <pre>{}</pre>
-    </div>
-</body>
-</html>
-"#,
-        share_info.created_at,
-        serde_json::to_string_pretty(analysis).unwrap_or_default()
-    )
-}
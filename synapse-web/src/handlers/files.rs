@@ -7,9 +7,12 @@ use serde_json::Value;
 use std::path::PathBuf;
 use tokio::fs;
 
-use crate::{error_handling::AppError, models::*, validation::Validator, AppState};
+use crate::{
+    audit, error_handling::AppError, middleware::auth::CurrentUser, models::*, validation::Validator,
+    AppState,
+};
 
-/// Helper function to process file upload with transaction support
+/// Helper function to process file upload, removing the file again if it cannot be recorded
 async fn process_file_upload(
     state: &AppState,
     project_id: String,
@@ -17,18 +20,9 @@ async fn process_file_upload(
     data: Bytes,
     file_path: PathBuf,
 ) -> Result<LogFile, AppError> {
-    // Start a transaction to ensure atomicity
-    let mut tx = state.db.pool().begin().await.map_err(|e: sqlx::Error| {
-        tracing::error!("Failed to start transaction for file upload: {}", e);
-        AppError::Database(e)
-    })?;
-
     // Write file to disk asynchronously
     if let Err(e) = tokio::fs::write(&file_path, &data).await {
         tracing::error!("Failed to write file {:?}: {}", file_path, e);
-        if let Err(rollback_err) = tx.rollback().await {
-            tracing::error!("Failed to rollback transaction: {}", rollback_err);
-        }
         return Err(AppError::file_processing(format!("Failed to write file: {}", e)));
     }
 
@@ -45,39 +39,13 @@ async fn process_file_upload(
     );
 
     // Save to database
-    if let Err(e) = sqlx::query!(
-        "INSERT INTO log_files (id, project_id, filename, file_size, line_count, upload_path, created_at)
-         VALUES (?, ?, ?, ?, ?, ?, ?)",
-        log_file.id,
-        log_file.project_id,
-        log_file.filename,
-        log_file.file_size,
-        log_file.line_count,
-        log_file.upload_path,
-        log_file.created_at
-    )
-    .execute(&mut *tx)
-    .await
-    {
+    if let Err(e) = state.db.storage().insert_log_file(&log_file).await {
         tracing::error!("Failed to create file record: {}", e);
-        if let Err(rollback_err) = tx.rollback().await {
-            tracing::error!("Failed to rollback transaction: {}", rollback_err);
-        }
         // Clean up the file
         if let Err(file_err) = tokio::fs::remove_file(&file_path).await {
             tracing::error!("Failed to remove file after DB error: {}", file_err);
         }
-        return Err(AppError::Database(e));
-    }
-
-    // Commit only if everything succeeded
-    if let Err(e) = tx.commit().await {
-        tracing::error!("Failed to commit file upload transaction: {}", e);
-        // Clean up the file
-        if let Err(file_err) = tokio::fs::remove_file(&file_path).await {
-            tracing::error!("Failed to remove file after commit error: {}", file_err);
-        }
-        return Err(AppError::Database(e));
+        return Err(AppError::from(e));
     }
 
     Ok(log_file)
@@ -85,6 +53,7 @@ async fn process_file_upload(
 
 pub async fn upload_log_file(
     State(state): State<AppState>,
+    current_user: CurrentUser,
     Path(project_id): Path<String>,
     mut multipart: Multipart,
 ) -> Result<Json<LogFile>, AppError> {
@@ -95,10 +64,10 @@ pub async fn upload_log_file(
     })?;
 
     // Verify project exists
-    let _project = sqlx::query!("SELECT id FROM projects WHERE id = ?", project_id)
-        .fetch_optional(state.db.pool())
+    let _project = state.db.storage()
+        .get_project(&project_id)
         .await
-        .map_err(AppError::Database)?
+        .map_err(AppError::from)?
         .ok_or_else(|| AppError::not_found(format!("Project {} not found", project_id)))?;
 
     // Create uploads directory if it doesn't exist
@@ -167,6 +136,19 @@ pub async fn upload_log_file(
             )
             .await?;
 
+            audit::record(
+                &state.db,
+                current_user
+                    .audit_event("file.upload")
+                    .with_project(project_id.clone())
+                    .with_target("log_file", log_file.id.clone())
+                    .with_details(serde_json::json!({
+                        "filename": log_file.filename,
+                        "file_size": log_file.file_size,
+                    })),
+            )
+            .await;
+
             uploaded_file = Some(log_file);
             break;
         }
@@ -179,109 +161,89 @@ pub async fn list_log_files(
     State(state): State<AppState>,
     Path(project_id): Path<String>,
 ) -> Result<Json<Vec<LogFile>>, AppError> {
-    let log_files = sqlx::query_as::<_, LogFile>(
-        "SELECT id, project_id, filename, file_size, line_count, upload_path, created_at
-         FROM log_files WHERE project_id = ? ORDER BY created_at DESC",
-    )
-    .bind(&project_id)
-    .fetch_all(state.db.pool())
-    .await
-    .map_err(AppError::Database)?;
+    let log_files = state.db.storage()
+        .list_log_files(&project_id)
+        .await
+        .map_err(AppError::from)?;
 
     Ok(Json(log_files))
 }
 
 pub async fn delete_log_file(
     State(state): State<AppState>,
+    current_user: CurrentUser,
     Path((project_id, file_id)): Path<(String, String)>,
 ) -> Result<Json<Value>, AppError> {
     // Get file info first
-    let log_file = sqlx::query_as::<_, LogFile>(
-        "SELECT id, project_id, filename, file_size, line_count, upload_path, created_at
-         FROM log_files WHERE id = ? AND project_id = ?",
-    )
-    .bind(&file_id)
-    .bind(&project_id)
-    .fetch_optional(state.db.pool())
-    .await
-    .map_err(|e: sqlx::Error| {
-        tracing::error!("Failed to fetch log file {}: {}", file_id, e);
-        AppError::Database(e)
-    })?
-    .ok_or_else(|| AppError::not_found(format!("Log file {} not found", file_id)))?;
+    let log_file = state.db.storage()
+        .get_log_file(&project_id, &file_id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to fetch log file {}: {}", file_id, e);
+            AppError::from(e)
+        })?
+        .ok_or_else(|| AppError::not_found(format!("Log file {} not found", file_id)))?;
 
     // Check for active analyses using this file
-    let active_analyses = sqlx::query!(
-        "SELECT COUNT(*) as count FROM analyses WHERE log_file_id = ? AND status IN (0, 1)",
-        file_id
-    )
-    .fetch_one(state.db.pool())
-    .await
-    .map_err(|e: sqlx::Error| {
-        tracing::error!(
-            "Failed to check active analyses for file {}: {}",
-            file_id,
-            e
-        );
-        AppError::Database(e)
-    })?;
+    let active_analyses = state.db.storage()
+        .count_log_file_analyses(&file_id, true)
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                "Failed to check active analyses for file {}: {}",
+                file_id,
+                e
+            );
+            AppError::from(e)
+        })?;
 
-    if active_analyses.count > 0 {
+    if active_analyses > 0 {
         tracing::warn!(
             "Cannot delete file {} - has {} active analyses",
             file_id,
-            active_analyses.count
+            active_analyses
         );
         return Err(AppError::bad_request(
-            format!("Cannot delete file with {} active analyses", active_analyses.count)
+            format!("Cannot delete file with {} active analyses", active_analyses)
         ));
     }
 
     // Check for any completed analyses - warn but allow deletion
-    let completed_analyses = sqlx::query!(
-        "SELECT COUNT(*) as count FROM analyses WHERE log_file_id = ?",
-        file_id
-    )
-    .fetch_one(state.db.pool())
-    .await
-    .map_err(|e: sqlx::Error| {
-        tracing::error!("Failed to check all analyses for file {}: {}", file_id, e);
-        AppError::Database(e)
-    })?;
+    let completed_analyses = state.db.storage()
+        .count_log_file_analyses(&file_id, false)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to check all analyses for file {}: {}", file_id, e);
+            AppError::from(e)
+        })?;
 
-    if completed_analyses.count > 0 {
+    if completed_analyses > 0 {
         tracing::info!(
             "Deleting file {} that has {} analysis records - records will be orphaned",
             file_id,
-            completed_analyses.count
+            completed_analyses
         );
     }
 
-    // Start a transaction to ensure atomicity
-    let mut tx = state.db.pool().begin().await.map_err(|e: sqlx::Error| {
-        tracing::error!("Failed to start transaction for file deletion: {}", e);
-        AppError::Database(e)
-    })?;
-
     // Delete from database first
-    let result = sqlx::query!("DELETE FROM log_files WHERE id = ?", file_id)
-        .execute(&mut *tx)
-        .await
-        .map_err(|e: sqlx::Error| {
-            tracing::error!("Failed to delete log file {} from database: {}", file_id, e);
-            AppError::Database(e)
-        })?;
+    let deleted = state.db.storage().delete_log_file(&file_id).await.map_err(|e| {
+        tracing::error!("Failed to delete log file {} from database: {}", file_id, e);
+        AppError::from(e)
+    })?;
 
-    if result.rows_affected() == 0 {
-        tx.rollback().await.ok();
+    if !deleted {
         return Err(AppError::not_found(format!("Log file {} not found", file_id)));
     }
 
-    // Commit the transaction
-    tx.commit().await.map_err(|e: sqlx::Error| {
-        tracing::error!("Failed to commit file deletion transaction: {}", e);
-        AppError::Database(e)
-    })?;
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("file.delete")
+            .with_project(project_id.clone())
+            .with_target("log_file", file_id.clone())
+            .with_details(serde_json::json!({ "filename": log_file.filename })),
+    )
+    .await;
 
     // Only delete file from filesystem after successful database deletion
     if let Err(e) = fs::remove_file(&log_file.upload_path).await {
@@ -0,0 +1,106 @@
+use axum::{
+    extract::{Path, Query, State},
+    response::Json,
+};
+use serde::Deserialize;
+use synapse_core::project::{AnalysisJob, JobQuery, JobStatus};
+
+use crate::{audit, error_handling::AppError, middleware::auth::CurrentUser, AppState};
+
+/// Jobs per page
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct JobListQuery {
+    pub status: Option<JobStatus>,
+    pub queue: Option<String>,
+    pub project_id: Option<String>,
+    pub analysis_id: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// List queued, running and finished analysis jobs, newest first
+///
+/// Server administrators see every job; everyone else sees the jobs of their projects.
+pub async fn list_jobs(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Query(query): Query<JobListQuery>,
+) -> Result<Json<Vec<AnalysisJob>>, AppError> {
+    let project_ids = match &current_user.user {
+        Some(user) if !user.is_admin => Some(
+            state
+                .db
+                .storage()
+                .list_projects_for_user(&user.id)
+                .await?
+                .into_iter()
+                .map(|project| project.id)
+                .collect(),
+        ),
+        _ => None,
+    };
+
+    let filter = JobQuery {
+        status: query.status,
+        queue: query.queue,
+        project_id: query.project_id,
+        project_ids,
+        analysis_id: query.analysis_id,
+        limit: Some(query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)),
+        offset: Some(query.offset.unwrap_or(0).max(0)),
+    };
+    Ok(Json(state.db.storage().list_jobs(&filter).await?))
+}
+
+/// Retrieve one job, including its attempts and last error
+pub async fn get_job(
+    State(state): State<AppState>,
+    Path(job_id): Path<String>,
+) -> Result<Json<AnalysisJob>, AppError> {
+    let job = state
+        .db
+        .storage()
+        .get_job(&job_id)
+        .await?
+        .ok_or_else(|| AppError::not_found(format!("Job {} not found", job_id)))?;
+    Ok(Json(job))
+}
+
+/// Cancel a queued or running job; its analysis is marked failed
+pub async fn cancel_job(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Path(job_id): Path<String>,
+) -> Result<Json<AnalysisJob>, AppError> {
+    let job = state
+        .db
+        .storage()
+        .get_job(&job_id)
+        .await?
+        .ok_or_else(|| AppError::not_found(format!("Job {} not found", job_id)))?;
+
+    if !state.jobs.cancel(&job).await? {
+        return Err(AppError::bad_request(format!("Job {} has already {}", job_id, job.status)));
+    }
+
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("job.cancel")
+            .with_project(job.project_id.clone())
+            .with_target("job", job.id.clone())
+            .with_details(serde_json::json!({
+                "analysis_id": job.analysis_id,
+                "queue": job.queue,
+                "status": job.status,
+                "attempts": job.attempts,
+            })),
+    )
+    .await;
+
+    let job = state.db.storage().get_job(&job_id).await?.unwrap_or(job);
+    Ok(Json(job))
+}
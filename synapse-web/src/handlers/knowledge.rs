@@ -5,7 +5,7 @@ use axum::{
 };
 use serde::Deserialize;
 
-use crate::{error_handling::AppError, models::*, AppState};
+use crate::{error_handling::AppError, middleware::auth::CurrentUser, models::*, AppState};
 
 #[derive(Deserialize)]
 pub struct KnowledgeBaseQuery {
@@ -59,6 +59,7 @@ pub struct PatternQuery {
 // Knowledge Base Endpoints
 pub async fn create_knowledge_entry(
     State(state): State<AppState>,
+    current_user: CurrentUser,
     Path(project_id): Path<String>,
     Json(req): Json<CreateKnowledgeBaseRequest>,
 ) -> Result<Json<KnowledgeBaseEntry>, AppError> {
@@ -68,15 +69,6 @@ pub async fn create_knowledge_entry(
         return Err(AppError::bad_request("Invalid request"));
     }
 
-    let entry = KnowledgeBaseEntry::new(
-        project_id,
-        req.title,
-        req.problem_description,
-        req.solution,
-        req.severity.unwrap_or_else(|| "medium".to_string()),
-        req.is_public.unwrap_or(false),
-    );
-
     let tags_json = match req.tags {
         Some(tags) => {
             Some(serde_json::to_string(&tags)
@@ -88,26 +80,20 @@ pub async fn create_knowledge_entry(
         None => None
     };
 
-    sqlx::query!(
-        "INSERT INTO knowledge_base (id, project_id, title, problem_description, solution, tags, severity, is_public, usage_count, created_at, updated_at)
-         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
-        entry.id,
-        entry.project_id,
-        entry.title,
-        entry.problem_description,
-        entry.solution,
+    let entry = KnowledgeBaseEntry::new(
+        project_id,
+        req.title,
+        req.problem_description,
+        req.solution,
         tags_json,
-        entry.severity,
-        entry.is_public,
-        entry.usage_count,
-        entry.created_at,
-        entry.updated_at
+        req.severity.unwrap_or_else(|| "medium".to_string()),
+        req.is_public.unwrap_or(false),
     )
-    .execute(state.db.pool())
-    .await
-    .map_err(|e: sqlx::Error| {
+    .with_created_by(current_user.user_id());
+
+    state.db.storage().create_knowledge_entry(&entry).await.map_err(|e| {
         tracing::error!("Failed to create knowledge base entry: {}", e);
-        AppError::Database(e)
+        AppError::from(e)
     })?;
 
     Ok(Json(entry))
@@ -121,39 +107,19 @@ pub async fn get_knowledge_entries(
     let limit = params.limit.unwrap_or(50).min(100);
     let offset = params.offset.unwrap_or(0);
 
-    if let Some(search) = &params.search {
-        let search_pattern = format!("%{}%", search);
-        let entries = sqlx::query_as::<_, KnowledgeBaseEntry>(
-            "SELECT id, project_id, title, problem_description, solution, tags, severity, is_public, usage_count, created_at, updated_at
-             FROM knowledge_base 
-             WHERE project_id = $1 AND (title LIKE $2 OR problem_description LIKE $3 OR solution LIKE $4)
-             ORDER BY usage_count DESC, created_at DESC LIMIT $5 OFFSET $6"
-        )
-        .bind(&project_id)
-        .bind(&search_pattern)
-        .bind(&search_pattern)
-        .bind(&search_pattern)
-        .bind(limit)
-        .bind(offset)
-        .fetch_all(state.db.pool())
-        .await
-        .map_err(|e| { tracing::error!("Error: {}", e); AppError::internal(format!("{}", e)) })?;
-
-        return Ok(Json(entries));
+    let entries = match &params.search {
+        Some(search) => state.db.storage()
+            .search_knowledge(search, Some(&project_id), false, limit + offset)
+            .await,
+        None => state.db.storage().list_knowledge_entries(&project_id).await,
     }
+    .map_err(|e| { tracing::error!("Error: {}", e); AppError::from(e) })?;
 
-    let entries = sqlx::query_as::<_, KnowledgeBaseEntry>(
-        "SELECT id, project_id, title, problem_description, solution, tags, severity, is_public, usage_count, created_at, updated_at
-         FROM knowledge_base 
-         WHERE project_id = $1 
-         ORDER BY usage_count DESC, created_at DESC LIMIT $2 OFFSET $3"
-    )
-    .bind(&project_id)
-    .bind(limit)
-    .bind(offset)
-    .fetch_all(state.db.pool())
-    .await
-    .map_err(|e| { tracing::error!("Error: {}", e); AppError::internal(format!("{}", e)) })?;
+    let entries = entries
+        .into_iter()
+        .skip(offset.max(0) as usize)
+        .take(limit.max(0) as usize)
+        .collect();
 
     Ok(Json(entries))
 }
@@ -162,25 +128,18 @@ pub async fn get_knowledge_entry(
     State(state): State<AppState>,
     Path((project_id, entry_id)): Path<(String, String)>,
 ) -> Result<Json<KnowledgeBaseEntry>, AppError> {
-    let entry = sqlx::query_as::<_, KnowledgeBaseEntry>(
-        "SELECT id, project_id, title, problem_description, solution, tags, severity, is_public, usage_count, created_at, updated_at
-         FROM knowledge_base WHERE id = $1 AND project_id = $2"
-    )
-    .bind(&entry_id)
-    .bind(&project_id)
-    .fetch_optional(state.db.pool())
-    .await
-    .map_err(|e| { tracing::error!("Error: {}", e); AppError::internal(format!("{}", e)) })?
-    .ok_or_else(|| AppError::not_found("Resource not found"))?;
+    let entry = state.db.storage()
+        .get_knowledge_entry(&entry_id)
+        .await
+        .map_err(|e| { tracing::error!("Error: {}", e); AppError::from(e) })?
+        .filter(|entry| entry.project_id == project_id)
+        .ok_or_else(|| AppError::not_found("Resource not found"))?;
 
     // Increment usage count
-    sqlx::query!(
-        "UPDATE knowledge_base SET usage_count = usage_count + 1, updated_at = CURRENT_TIMESTAMP WHERE id = $1",
-        entry_id
-    )
-    .execute(state.db.pool())
-    .await
-    .map_err(|e: sqlx::Error| { tracing::error!("Database error: {}", e); AppError::Database(e) })?;
+    state.db.storage()
+        .increment_knowledge_usage(&entry_id)
+        .await
+        .map_err(|e| { tracing::error!("Database error: {}", e); AppError::from(e) })?;
 
     Ok(Json(entry))
 }
@@ -192,20 +151,13 @@ pub async fn get_public_knowledge(
 ) -> Result<Json<Vec<KnowledgeBaseEntry>>, AppError> {
     let limit = params.limit.unwrap_or(50).min(100);
 
-    let entries = sqlx::query_as::<_, KnowledgeBaseEntry>(
-        "SELECT id, project_id, title, problem_description, solution, tags, severity, is_public, usage_count, created_at, updated_at
-         FROM knowledge_base
-         WHERE is_public = true
-         ORDER BY usage_count DESC, created_at DESC
-         LIMIT ?"
-    )
-    .bind(limit)
-    .fetch_all(state.db.pool())
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to fetch public knowledge entries: {}", e);
-        AppError::Database(e)
-    })?;
+    let entries = state.db.storage()
+        .list_public_knowledge(limit)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to fetch public knowledge entries: {}", e);
+            AppError::from(e)
+        })?;
 
     tracing::debug!("Retrieved {} public knowledge entries", entries.len());
     Ok(Json(entries))
@@ -219,51 +171,17 @@ pub async fn get_error_patterns(
 ) -> Result<Json<Vec<ErrorPattern>>, AppError> {
     let limit = params.limit.unwrap_or(50).min(100);
 
-    if let Some(category) = &params.category {
-        let patterns = sqlx::query_as::<_, ErrorPattern>(
-            "SELECT id, project_id, pattern, category, description, frequency, last_seen, suggested_solution, created_at, updated_at
-             FROM error_patterns 
-             WHERE project_id = $1 AND category = $2
-             ORDER BY frequency DESC, last_seen DESC LIMIT $3"
-        )
-        .bind(&project_id)
-        .bind(category)
-        .bind(limit)
-        .fetch_all(state.db.pool())
-        .await
-        .map_err(|e| { tracing::error!("Error: {}", e); AppError::internal(format!("{}", e)) })?;
-
-        return Ok(Json(patterns));
-    }
-
-    if let Some(min_freq) = &params.min_frequency {
-        let patterns = sqlx::query_as::<_, ErrorPattern>(
-            "SELECT id, project_id, pattern, category, description, frequency, last_seen, suggested_solution, created_at, updated_at
-             FROM error_patterns 
-             WHERE project_id = $1 AND frequency >= $2
-             ORDER BY frequency DESC, last_seen DESC LIMIT $3"
-        )
-        .bind(&project_id)
-        .bind(min_freq)
-        .bind(limit)
-        .fetch_all(state.db.pool())
+    let patterns = state.db.storage()
+        .list_error_patterns(&project_id, params.category.as_deref())
         .await
-        .map_err(|e| { tracing::error!("Error: {}", e); AppError::internal(format!("{}", e)) })?;
+        .map_err(|e| { tracing::error!("Error: {}", e); AppError::from(e) })?;
 
-        return Ok(Json(patterns));
-    }
-
-    let patterns = sqlx::query_as::<_, ErrorPattern>(
-        "SELECT id, project_id, pattern, category, description, frequency, last_seen, suggested_solution, created_at, updated_at
-         FROM error_patterns 
-         WHERE project_id = $1 
-         ORDER BY frequency DESC, last_seen DESC LIMIT $2"
-    )
-    .bind(&project_id)
-    .bind(limit)
-    .fetch_all(state.db.pool())
-    .await
-    .map_err(|e| { tracing::error!("Error: {}", e); AppError::internal(format!("{}", e)) })?;
+    let min_frequency = params.min_frequency.unwrap_or(0);
+    let patterns = patterns
+        .into_iter()
+        .filter(|pattern| pattern.frequency >= min_frequency)
+        .take(limit.max(0) as usize)
+        .collect();
 
     Ok(Json(patterns))
 }
@@ -272,15 +190,12 @@ pub async fn update_pattern_frequency(
     State(state): State<AppState>,
     Path(pattern_id): Path<String>,
 ) -> Result<StatusCode, AppError> {
-    let result = sqlx::query!(
-        "UPDATE error_patterns SET frequency = frequency + 1, last_seen = CURRENT_TIMESTAMP WHERE id = $1",
-        pattern_id
-    )
-    .execute(state.db.pool())
-    .await
-    .map_err(|e: sqlx::Error| { tracing::error!("Database error: {}", e); AppError::Database(e) })?;
+    let updated = state.db.storage()
+        .record_error_pattern_match(&pattern_id)
+        .await
+        .map_err(|e| { tracing::error!("Database error: {}", e); AppError::from(e) })?;
 
-    if result.rows_affected() == 0 {
+    if !updated {
         return Err(AppError::not_found("Pattern not found"));
     }
 
@@ -293,17 +208,12 @@ pub async fn get_error_correlations(
     Path(project_id): Path<String>,
     Path(analysis_id): Path<String>,
 ) -> Result<Json<Vec<ErrorCorrelation>>, AppError> {
-    let correlations = sqlx::query_as::<_, ErrorCorrelation>(
-        "SELECT id, project_id, primary_error_id, correlated_error_id, correlation_strength, correlation_type, created_at
-         FROM error_correlations 
-         WHERE project_id = $1 AND (primary_error_id = $2 OR correlated_error_id = $2)
-         ORDER BY correlation_strength DESC"
-    )
-    .bind(&project_id)
-    .bind(&analysis_id)
-    .fetch_all(state.db.pool())
-    .await
-    .map_err(|e| { tracing::error!("Error: {}", e); AppError::internal(format!("{}", e)) })?;
+    let correlations = state
+        .db
+        .storage()
+        .list_error_correlations(&project_id, &analysis_id)
+        .await
+        .map_err(|e| { tracing::error!("Error: {}", e); AppError::internal(format!("{}", e)) })?;
 
     Ok(Json(correlations))
 }
@@ -313,16 +223,12 @@ pub async fn get_performance_metrics(
     State(state): State<AppState>,
     Path(analysis_id): Path<String>,
 ) -> Result<Json<Vec<PerformanceMetric>>, AppError> {
-    let metrics = sqlx::query_as::<_, PerformanceMetric>(
-        "SELECT id, analysis_id, metric_name, metric_value, unit, threshold_value, is_bottleneck, created_at
-         FROM performance_metrics 
-         WHERE analysis_id = $1
-         ORDER BY is_bottleneck DESC, metric_name"
-    )
-    .bind(&analysis_id)
-    .fetch_all(state.db.pool())
-    .await
-    .map_err(|e| { tracing::error!("Error: {}", e); AppError::internal(format!("{}", e)) })?;
+    let metrics = state
+        .db
+        .storage()
+        .list_performance_metrics(&analysis_id)
+        .await
+        .map_err(|e| { tracing::error!("Error: {}", e); AppError::internal(format!("{}", e)) })?;
 
     Ok(Json(metrics))
 }
@@ -332,21 +238,10 @@ pub async fn create_performance_metric(
     Path(_analysis_id): Path<String>,
     Json(metric): Json<PerformanceMetric>,
 ) -> Result<Json<PerformanceMetric>, AppError> {
-    sqlx::query!(
-        "INSERT INTO performance_metrics (id, analysis_id, metric_name, metric_value, unit, threshold_value, is_bottleneck, created_at)
-         VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
-        metric.id,
-        metric.analysis_id,
-        metric.metric_name,
-        metric.metric_value,
-        metric.unit,
-        metric.threshold_value,
-        metric.is_bottleneck,
-        metric.created_at
-    )
-    .execute(state.db.pool())
-    .await
-    .map_err(|e: sqlx::Error| { tracing::error!("Database error: {}", e); AppError::Database(e) })?;
+    state.db.storage().create_performance_metric(&metric).await.map_err(|e| {
+        tracing::error!("Database error: {}", e);
+        AppError::from(e)
+    })?;
 
     Ok(Json(metric))
 }
@@ -358,14 +253,10 @@ pub async fn recognize_patterns(
     Json(error_messages): Json<Vec<String>>,
 ) -> Result<Json<serde_json::Value>, AppError> {
     // Get existing patterns for this project
-    let existing_patterns = sqlx::query_as::<_, ErrorPattern>(
-        "SELECT id, project_id, pattern, category, description, frequency, last_seen, suggested_solution, created_at, updated_at
-         FROM error_patterns WHERE project_id = $1"
-    )
-    .bind(&project_id)
-    .fetch_all(state.db.pool())
-    .await
-    .map_err(|e| { tracing::error!("Error: {}", e); AppError::internal(format!("{}", e)) })?;
+    let existing_patterns = state.db.storage()
+        .list_error_patterns(&project_id, None)
+        .await
+        .map_err(|e| { tracing::error!("Error: {}", e); AppError::from(e) })?;
 
     let mut patterns_by_category = serde_json::Map::new();
 
@@ -26,13 +26,11 @@ pub async fn handle_mcp_request(
     Json(req): Json<McpRequest>,
 ) -> Result<Json<McpResponse>, AppError> {
     // Verify project exists
-    let _project = sqlx::query!("SELECT id FROM projects WHERE id = ?", project_id)
-        .fetch_optional(state.db.pool())
-        .await
-        .map_err(|e: sqlx::Error| {
-            tracing::error!("Failed to fetch project {}: {}", project_id, e);
-            AppError::Database(e)
-        })?
+    state
+        .db
+        .storage()
+        .get_project(&project_id)
+        .await?
         .ok_or_else(|| AppError::not_found(format!("Project {} not found", project_id)))?;
 
     // Process MCP request
@@ -54,18 +52,12 @@ pub async fn get_analysis_for_mcp(
     State(state): State<AppState>,
     Path(analysis_id): Path<String>,
 ) -> Result<Json<Value>, AppError> {
-    let analysis = sqlx::query_as::<_, Analysis>(
-        "SELECT id, project_id, log_file_id, analysis_type, provider, level_filter, status, result, error_message, started_at, completed_at
-         FROM analyses WHERE id = ?"
-    )
-    .bind(&analysis_id)
-    .fetch_optional(state.db.pool())
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to fetch analysis {}: {}", analysis_id, e);
-        AppError::Database(e)
-    })?
-    .ok_or_else(|| AppError::not_found(format!("Analysis {} not found", analysis_id)))?;
+    let analysis = state
+        .db
+        .storage()
+        .get_analysis(&analysis_id)
+        .await?
+        .ok_or_else(|| AppError::not_found(format!("Analysis {} not found", analysis_id)))?;
 
     // Format for MCP consumption
     let mcp_data = serde_json::json!({
@@ -96,17 +88,11 @@ pub async fn list_analyses_for_mcp(
     State(state): State<AppState>,
     Path(project_id): Path<String>,
 ) -> Result<Json<Value>, AppError> {
-    let analyses = sqlx::query!(
-        "SELECT id, analysis_type, provider, level_filter, status, started_at, completed_at
-         FROM analyses WHERE project_id = ? ORDER BY started_at DESC LIMIT 20",
-        project_id
-    )
-    .fetch_all(state.db.pool())
-    .await
-    .map_err(|e: sqlx::Error| {
-        tracing::error!("Failed to fetch analyses for project {}: {}", project_id, e);
-        AppError::Database(e)
-    })?;
+    let analyses = state
+        .db
+        .storage()
+        .query_analyses(Some(&project_id), None, Some(20), None)
+        .await?;
 
     let mcp_analyses: Vec<Value> = analyses
         .into_iter()
@@ -116,13 +102,7 @@ pub async fn list_analyses_for_mcp(
                 "type": a.analysis_type,
                 "provider": a.provider,
                 "level": a.level_filter,
-                "status": match a.status {
-                    0 => "pending",
-                    1 => "running",
-                    2 => "completed",
-                    3 => "failed",
-                    _ => "unknown"
-                },
+                "status": a.status.as_str(),
                 "started_at": a.started_at,
                 "completed_at": a.completed_at
             })
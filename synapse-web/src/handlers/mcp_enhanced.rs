@@ -51,19 +51,13 @@ pub async fn generate_mcp_ticket(
     Json(req): Json<MCPTicketRequest>,
 ) -> Result<Json<MCPTicketResponse>, AppError> {
     // Verify analysis exists and belongs to project
-    let analysis = sqlx::query_as::<_, Analysis>(
-        "SELECT id, project_id, log_file_id, analysis_type, provider, level_filter, status, result, error_message, started_at, completed_at
-         FROM analyses WHERE id = $1 AND project_id = $2"
-    )
-    .bind(&req.analysis_id)
-    .bind(&project_id)
-    .fetch_optional(state.db.pool())
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to fetch analysis {} for MCP ticket: {}", req.analysis_id, e);
-        AppError::Database(e)
-    })?
-    .ok_or_else(|| AppError::not_found(format!("Analysis {} not found", req.analysis_id)))?;
+    let analysis = state
+        .db
+        .storage()
+        .get_analysis(&req.analysis_id)
+        .await?
+        .filter(|analysis| analysis.project_id == project_id)
+        .ok_or_else(|| AppError::not_found(format!("Analysis {} not found", req.analysis_id)))?;
 
     // Generate ticket ID with project prefix and timestamp
     let timestamp = chrono::Utc::now().format("%Y%m%d");
@@ -117,30 +111,19 @@ pub async fn generate_mcp_ticket(
             AppError::internal(format!("Failed to serialize tags: {}", e))
         })?;
 
-    let entry_id = uuid::Uuid::new_v4().to_string();
-    let project_id_ref = project_id.clone();
-    let now = chrono::Utc::now();
-
-    sqlx::query!(
-        "INSERT INTO knowledge_base (id, project_id, title, problem_description, solution, tags, severity, is_public, usage_count, created_at, updated_at)
-         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
-        entry_id,
-        project_id_ref,
+    let entry = KnowledgeBaseEntry::new(
+        project_id.clone(),
         ticket_id,
         error_summary,
         solution_json,
-        tags_json,
-        "high",
+        Some(tags_json),
+        "high".to_string(),
         true, // Public tickets
-        0,
-        now,
-        now
-    )
-    .execute(state.db.pool())
-    .await
-    .map_err(|e: sqlx::Error| {
+    );
+
+    state.db.storage().create_knowledge_entry(&entry).await.map_err(|e| {
         tracing::error!("Failed to insert MCP ticket: {}", e);
-        AppError::Database(e)
+        AppError::from(e)
     })?;
 
     Ok(Json(response))
@@ -153,19 +136,13 @@ pub async fn get_mcp_context(
     Query(params): Query<MCPContextRequest>,
 ) -> Result<Json<MCPContextResponse>, AppError> {
     // Verify analysis exists
-    let analysis = sqlx::query_as::<_, Analysis>(
-        "SELECT id, project_id, log_file_id, analysis_type, provider, level_filter, status, result, error_message, started_at, completed_at
-         FROM analyses WHERE id = $1 AND project_id = $2"
-    )
-    .bind(&analysis_id)
-    .bind(&project_id)
-    .fetch_optional(state.db.pool())
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to fetch analysis {} for MCP context: {}", analysis_id, e);
-        AppError::Database(e)
-    })?
-    .ok_or_else(|| AppError::not_found(format!("Analysis {} not found", analysis_id)))?;
+    let analysis = state
+        .db
+        .storage()
+        .get_analysis(&analysis_id)
+        .await?
+        .filter(|analysis| analysis.project_id == project_id)
+        .ok_or_else(|| AppError::not_found(format!("Analysis {} not found", analysis_id)))?;
 
     let mut context_payload = String::new();
 
@@ -193,7 +170,7 @@ pub async fn get_mcp_context(
 
             if params.include_correlations {
                 if let Ok(correlations) =
-                    get_error_correlations_for_analysis(state.clone(), &analysis_id).await
+                    get_error_correlations_for_analysis(state.clone(), &project_id, &analysis_id).await
                 {
                     context_payload.push_str(&format!("Related Errors: {}\\n", correlations.len()));
                 }
@@ -222,7 +199,7 @@ pub async fn get_mcp_context(
             // Include correlations if requested
             if params.include_correlations {
                 if let Ok(correlations) =
-                    get_error_correlations_for_analysis(state.clone(), &analysis_id).await
+                    get_error_correlations_for_analysis(state.clone(), &project_id, &analysis_id).await
                 {
                     context_payload.push_str("\\n=== Error Correlations ===\\n");
                     for corr in correlations {
@@ -278,19 +255,18 @@ pub async fn get_mcp_tickets(
     State(state): State<AppState>,
     Path(project_id): Path<String>,
 ) -> Result<Json<Vec<MCPTicketResponse>>, AppError> {
-    let tickets = sqlx::query_as::<_, KnowledgeBaseEntry>(
-        "SELECT id, project_id, title, problem_description, solution, tags, severity, is_public, usage_count, created_at, updated_at
-         FROM knowledge_base
-         WHERE project_id = $1 AND tags LIKE '%\"mcp\"%' AND tags LIKE '%\"ticket\"%'
-         ORDER BY created_at DESC LIMIT 50"
-    )
-    .bind(&project_id)
-    .fetch_all(state.db.pool())
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to fetch MCP tickets for project {}: {}", project_id, e);
-        AppError::Database(e)
-    })?;
+    let mut tickets: Vec<KnowledgeBaseEntry> = state
+        .db
+        .storage()
+        .list_knowledge_entries(&project_id)
+        .await?
+        .into_iter()
+        .filter(|entry| {
+            entry.tags.as_deref().is_some_and(|tags| tags.contains("\"mcp\"") && tags.contains("\"ticket\""))
+        })
+        .collect();
+    tickets.sort_by_key(|ticket| std::cmp::Reverse(ticket.created_at));
+    tickets.truncate(50);
 
     let mut ticket_responses = Vec::new();
 
@@ -321,30 +297,15 @@ pub async fn get_mcp_tickets(
 // Helper functions
 async fn get_error_correlations_for_analysis(
     state: AppState,
+    project_id: &str,
     analysis_id: &str,
-) -> Result<Vec<ErrorCorrelation>, sqlx::Error> {
-    sqlx::query_as::<_, ErrorCorrelation>(
-        "SELECT id, project_id, primary_error_id, correlated_error_id, correlation_strength, correlation_type, created_at
-         FROM error_correlations
-         WHERE primary_error_id = $1 OR correlated_error_id = $1
-         ORDER BY correlation_strength DESC"
-    )
-    .bind(analysis_id)
-    .fetch_all(state.db.pool())
-    .await
+) -> Result<Vec<ErrorCorrelation>, AppError> {
+    Ok(state.db.storage().list_error_correlations(project_id, analysis_id).await?)
 }
 
 async fn get_performance_metrics_for_analysis(
     state: AppState,
     analysis_id: &str,
-) -> Result<Vec<PerformanceMetric>, sqlx::Error> {
-    sqlx::query_as::<_, PerformanceMetric>(
-        "SELECT id, analysis_id, metric_name, metric_value, unit, threshold_value, is_bottleneck, created_at
-         FROM performance_metrics
-         WHERE analysis_id = $1
-         ORDER BY is_bottleneck DESC, metric_name"
-    )
-    .bind(analysis_id)
-    .fetch_all(state.db.pool())
-    .await
+) -> Result<Vec<PerformanceMetric>, AppError> {
+    Ok(state.db.storage().list_performance_metrics(analysis_id).await?)
 }
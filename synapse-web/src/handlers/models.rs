@@ -3,7 +3,6 @@ use axum::{
     response::Json,
 };
 use serde::{Deserialize, Serialize};
-use sqlx::Row;
 use crate::{error_handling::AppError, AppState};
 use anyhow::Result;
 use synapse_core::ai_provider::{create_provider, ModelInfo, ModelListResponse};
@@ -11,7 +10,8 @@ use synapse_core::ai_provider::{create_provider, ModelInfo, ModelListResponse};
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ModelsRequest {
     pub provider: String,
-    pub api_key: String,
+    #[serde(default)]
+    pub api_key: String, // empty or masked: use the stored key
     pub force_refresh: Option<bool>,
 }
 
@@ -33,7 +33,14 @@ pub async fn get_available_models(
     }
 
     // Create provider and fetch models
-    let provider = create_provider(&request.provider, &request.api_key)
+    let api_key = if request.api_key.is_empty() || synapse_core::secrets::is_masked(&request.api_key) {
+        super::settings::provider_api_key(&state.db, &state.secrets, &request.provider)
+            .await
+            .ok_or_else(|| AppError::bad_request(format!("No API key stored for {}", request.provider)))?
+    } else {
+        request.api_key.clone()
+    };
+    let provider = create_provider(&request.provider, &api_key)
         .map_err(|e| AppError::internal(format!("Failed to create provider: {}", e)))?;
 
     let models = provider
@@ -60,18 +67,9 @@ async fn get_cached_models(
     state: &AppState,
     provider: &str,
 ) -> Result<ModelListResponse, Box<dyn std::error::Error + Send + Sync>> {
-    let pool = state.db.pool();
+    let settings = state.db.storage().get_settings().await?.unwrap_or_default();
 
-    let row = sqlx::query(
-        "SELECT available_models, models_last_fetched FROM settings WHERE id = 1"
-    )
-    .fetch_one(pool)
-    .await?;
-
-    let available_models: Option<String> = row.get("available_models");
-    let models_last_fetched: Option<String> = row.get("models_last_fetched");
-
-    if let (Some(models_json), Some(fetched_at_str)) = (available_models, models_last_fetched) {
+    if let (Some(models_json), Some(fetched_at_str)) = (settings.available_models, settings.models_last_fetched) {
         let fetched_at = chrono::DateTime::parse_from_rfc3339(&fetched_at_str)
             .map_err(|_| "Invalid datetime format")?
             .with_timezone(&chrono::Utc);
@@ -106,8 +104,6 @@ async fn cache_models(
     provider: &str,
     response: &ModelListResponse,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let pool = state.db.pool();
-
     // Get existing cached models
     let existing_models = get_existing_cached_models(state).await.unwrap_or_default();
 
@@ -122,27 +118,17 @@ async fn cache_models(
     let models_json = serde_json::to_string(&all_models)?;
     let fetched_at = response.cached_at.to_rfc3339();
 
-    sqlx::query(
-        "UPDATE settings SET available_models = ?, models_last_fetched = ? WHERE id = 1"
-    )
-    .bind(&models_json)
-    .bind(&fetched_at)
-    .execute(pool)
-    .await?;
+    state.db.storage()
+        .update_model_cache(Some(&models_json), Some(&fetched_at))
+        .await?;
 
     Ok(())
 }
 
 async fn get_existing_cached_models(state: &AppState) -> Result<Vec<ModelInfo>, Box<dyn std::error::Error + Send + Sync>> {
-    let pool = state.db.pool();
-
-    let row = sqlx::query("SELECT available_models FROM settings WHERE id = 1")
-        .fetch_one(pool)
-        .await?;
+    let settings = state.db.storage().get_settings().await?.unwrap_or_default();
 
-    let available_models: Option<String> = row.get("available_models");
-
-    if let Some(models_json) = available_models {
+    if let Some(models_json) = settings.available_models {
         let models: Vec<ModelInfo> = serde_json::from_str(&models_json)?;
         Ok(models)
     } else {
@@ -159,7 +145,7 @@ pub async fn clear_models_cache(
     State(state): State<AppState>,
     Json(request): Json<ClearCacheRequest>,
 ) -> Result<Json<&'static str>, AppError> {
-    let pool = state.db.pool();
+    let storage = state.db.storage();
 
     if let Some(provider) = &request.provider {
         // Clear cache for specific provider
@@ -172,17 +158,23 @@ pub async fn clear_models_cache(
         let models_json = serde_json::to_string(&filtered_models)
             .map_err(|e| AppError::internal(format!("Failed to serialize models: {}", e)))?;
 
-        sqlx::query("UPDATE settings SET available_models = ? WHERE id = 1")
-            .bind(&models_json)
-            .execute(pool)
+        // Keep the fetch time of the models that remain cached
+        let models_last_fetched = storage
+            .get_settings()
+            .await
+            .map_err(AppError::from)?
+            .and_then(|settings| settings.models_last_fetched);
+
+        storage
+            .update_model_cache(Some(&models_json), models_last_fetched.as_deref())
             .await
-            .map_err(|e| AppError::Database(e))?;
+            .map_err(AppError::from)?;
     } else {
         // Clear all cached models
-        sqlx::query("UPDATE settings SET available_models = NULL, models_last_fetched = NULL WHERE id = 1")
-            .execute(pool)
+        storage
+            .update_model_cache(None, None)
             .await
-            .map_err(|e| AppError::Database(e))?;
+            .map_err(AppError::from)?;
     }
 
     Ok(Json("Cache cleared"))
@@ -0,0 +1,250 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde::Deserialize;
+use synapse_core::project::{AlertSeverity, DeliveryQuery, DeliveryStatus, NotificationChannel, NotificationDelivery};
+
+use crate::{
+    alerts::{channels::ChannelConfig, templates},
+    audit,
+    error_handling::AppError,
+    middleware::auth::CurrentUser,
+    AppState,
+};
+
+/// Deliveries per page
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct ChannelRequest {
+    pub name: String,
+    /// Settings tagged by "type"; a masked secret sent back unchanged keeps the stored one
+    pub config: ChannelConfig,
+    pub notify_alerts: Option<bool>,
+    pub notify_analyses: Option<bool>,
+    pub min_severity: Option<AlertSeverity>,
+    pub title_template: Option<String>,
+    pub body_template: Option<String>,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeliveryListQuery {
+    pub channel_id: Option<String>,
+    pub status: Option<DeliveryStatus>,
+    pub event: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// Check a channel request and apply it to `channel`, sealing its secrets; omitted fields take their defaults
+async fn apply_channel_request(
+    state: &AppState,
+    channel: &mut NotificationChannel,
+    req: ChannelRequest,
+) -> Result<(), AppError> {
+    let name = req.name.trim();
+    if name.is_empty() {
+        return Err(AppError::bad_request("Channel name is required"));
+    }
+    let title_template = req.title_template.filter(|template| !template.trim().is_empty());
+    let body_template = req.body_template.filter(|template| !template.trim().is_empty());
+    for template in title_template.iter().chain(body_template.iter()) {
+        templates::validate_template(template).map_err(AppError::bad_request)?;
+    }
+
+    let mut config = req.config;
+    if let Ok(stored) = serde_json::from_str::<ChannelConfig>(&channel.config) {
+        config.keep_masked_secrets(&stored);
+    }
+    let limits = state.alerts.notifications().limits();
+    config.validate(&limits.commands).map_err(AppError::bad_request)?;
+    config
+        .check_destination(&limits.allowed_networks)
+        .await
+        .map_err(AppError::bad_request)?;
+    config
+        .seal(&*state.secrets.read().await, &channel.id)
+        .map_err(|e| AppError::internal(format!("Failed to encrypt channel secrets: {}", e)))?;
+
+    channel.name = name.to_string();
+    channel.channel_type = config.channel_type();
+    channel.config = serde_json::to_string(&config).map_err(|e| AppError::internal(e.to_string()))?;
+    channel.notify_alerts = req.notify_alerts.unwrap_or(true);
+    channel.notify_analyses = req.notify_analyses.unwrap_or(false);
+    channel.min_severity = req.min_severity.unwrap_or(AlertSeverity::Info);
+    channel.title_template = title_template;
+    channel.body_template = body_template;
+    channel.enabled = req.enabled.unwrap_or(true);
+    Ok(())
+}
+
+/// A channel as shown to clients, with its secrets masked
+async fn masked(state: &AppState, mut channel: NotificationChannel) -> NotificationChannel {
+    if let Ok(config) = serde_json::from_str::<ChannelConfig>(&channel.config) {
+        let config = config.masked(&*state.secrets.read().await, &channel.id);
+        channel.config = serde_json::to_string(&config).unwrap_or(channel.config);
+    }
+    channel
+}
+
+async fn find_channel(state: &AppState, project_id: &str, channel_id: &str) -> Result<NotificationChannel, AppError> {
+    state
+        .db
+        .storage()
+        .get_notification_channel(channel_id)
+        .await?
+        .filter(|channel| channel.project_id == project_id)
+        .ok_or_else(|| AppError::not_found("Notification channel not found"))
+}
+
+/// List the project's notification channels, including disabled ones
+pub async fn list_channels(
+    State(state): State<AppState>,
+    Path(project_id): Path<String>,
+) -> Result<Json<Vec<NotificationChannel>>, AppError> {
+    let mut channels = Vec::new();
+    for channel in state.db.storage().list_notification_channels(&project_id).await? {
+        channels.push(masked(&state, channel).await);
+    }
+    Ok(Json(channels))
+}
+
+pub async fn get_channel(
+    State(state): State<AppState>,
+    Path((project_id, channel_id)): Path<(String, String)>,
+) -> Result<Json<NotificationChannel>, AppError> {
+    let channel = find_channel(&state, &project_id, &channel_id).await?;
+    Ok(Json(masked(&state, channel).await))
+}
+
+/// Create a notification channel; it receives events from then on
+pub async fn create_channel(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Path(project_id): Path<String>,
+    Json(req): Json<ChannelRequest>,
+) -> Result<Json<NotificationChannel>, AppError> {
+    let channel_type = req.config.channel_type();
+    let mut channel = NotificationChannel::new(project_id.clone(), String::new(), channel_type, String::new());
+    apply_channel_request(&state, &mut channel, req).await?;
+    channel.created_by = current_user.user_id();
+
+    state.db.storage().create_notification_channel(&channel).await?;
+
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("notification_channel.create")
+            .with_project(project_id)
+            .with_target("notification_channel", channel.id.clone())
+            .with_details(serde_json::json!({
+                "name": channel.name,
+                "channel_type": channel.channel_type,
+            })),
+    )
+    .await;
+
+    Ok(Json(masked(&state, channel).await))
+}
+
+/// Replace a channel's settings
+pub async fn update_channel(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Path((project_id, channel_id)): Path<(String, String)>,
+    Json(req): Json<ChannelRequest>,
+) -> Result<Json<NotificationChannel>, AppError> {
+    let mut channel = find_channel(&state, &project_id, &channel_id).await?;
+    apply_channel_request(&state, &mut channel, req).await?;
+    channel.updated_at = chrono::Utc::now();
+
+    if !state.db.storage().update_notification_channel(&channel).await? {
+        return Err(AppError::not_found("Notification channel not found"));
+    }
+
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("notification_channel.update")
+            .with_project(project_id)
+            .with_target("notification_channel", channel_id)
+            .with_details(serde_json::json!({
+                "name": channel.name,
+                "channel_type": channel.channel_type,
+                "enabled": channel.enabled,
+            })),
+    )
+    .await;
+
+    Ok(Json(masked(&state, channel).await))
+}
+
+/// Delete a channel together with its delivery log
+pub async fn delete_channel(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Path((project_id, channel_id)): Path<(String, String)>,
+) -> Result<StatusCode, AppError> {
+    if !state.db.storage().delete_notification_channel(&project_id, &channel_id).await? {
+        return Err(AppError::not_found("Notification channel not found"));
+    }
+
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("notification_channel.delete")
+            .with_project(project_id)
+            .with_target("notification_channel", channel_id),
+    )
+    .await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Send a test message right away; the response is the logged delivery, failed or not
+pub async fn test_channel(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Path((project_id, channel_id)): Path<(String, String)>,
+) -> Result<Json<NotificationDelivery>, AppError> {
+    let channel = find_channel(&state, &project_id, &channel_id).await?;
+    let delivery = state
+        .alerts
+        .notifications()
+        .send_test(&channel)
+        .await
+        .map_err(|e| AppError::internal(format!("Failed to send test notification: {}", e)))?;
+
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("notification_channel.test")
+            .with_project(project_id)
+            .with_target("notification_channel", channel_id)
+            .with_details(serde_json::json!({ "status": delivery.status })),
+    )
+    .await;
+
+    Ok(Json(delivery))
+}
+
+/// The project's delivery log, newest first
+pub async fn list_deliveries(
+    State(state): State<AppState>,
+    Path(project_id): Path<String>,
+    Query(query): Query<DeliveryListQuery>,
+) -> Result<Json<Vec<NotificationDelivery>>, AppError> {
+    let filter = DeliveryQuery {
+        project_id: Some(project_id),
+        channel_id: query.channel_id,
+        status: query.status,
+        event: query.event,
+        limit: Some(query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)),
+        offset: Some(query.offset.unwrap_or(0).max(0)),
+    };
+    Ok(Json(state.db.storage().query_notification_deliveries(&filter).await?))
+}
@@ -1,55 +1,81 @@
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
+    http::StatusCode,
     response::Json,
 };
+use chrono::{DateTime, Days, Utc};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use sqlx::Row;
 use std::path::PathBuf;
+use synapse_core::project::{ProjectMember, ProjectMemberChange, ProjectRole};
 use tracing::{info, warn, error};
 
-use crate::{error_handling::AppError, models::*, validation::Validator, AppState};
+use crate::{
+    audit,
+    error_handling::AppError,
+    middleware::{auth::CurrentUser, rate_limit},
+    models::*,
+    validation::Validator,
+    AppState,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct AddMemberRequest {
+    pub username: String,
+    pub role: ProjectRole,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateMemberRequest {
+    pub role: ProjectRole,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MemberHistoryQuery {
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateQuotaRequest {
+    /// Analyses per UTC day; `null` to use the server default, 0 for no limit
+    pub daily_analysis_quota: Option<i32>,
+}
+
+/// A project's daily analysis quota and how much of it is used
+#[derive(Debug, Serialize)]
+pub struct ProjectQuota {
+    pub project_id: String,
+    /// The quota in effect; 0 means no limit
+    pub daily_analysis_quota: u32,
+    /// The project's own quota, when it overrides the server default
+    pub project_quota: Option<i32>,
+    pub used_today: i64,
+    pub resets_at: DateTime<Utc>,
+}
 
 pub async fn list_projects(
     State(state): State<AppState>,
+    current_user: CurrentUser,
 ) -> Result<Json<Vec<Project>>, AppError> {
     info!("Fetching all projects from database");
-    
-    // Try the new schema first, fallback to old schema if columns don't exist
-    let new_schema_result = sqlx::query_as::<_, Project>(
-        "SELECT id, name, description, root_path, synapse_config, project_type, last_accessed, created_at, updated_at FROM projects ORDER BY updated_at DESC"
-    )
-    .fetch_all(state.db.pool())
-    .await;
-    
-    let projects = match new_schema_result {
-        Ok(projects) => {
-            info!("Successfully fetched {} projects using new schema", projects.len());
-            projects
-        }
-        Err(e) => {
-            warn!("New schema query failed, trying fallback: {}", e);
-            
-            // Fallback to old schema without CLI integration fields
-            let old_schema_projects = sqlx::query_as::<_, Project>(
-                "SELECT id, name, description, NULL as root_path, NULL as synapse_config, 'web' as project_type, NULL as last_accessed, created_at, updated_at FROM projects ORDER BY updated_at DESC"
-            )
-            .fetch_all(state.db.pool())
-            .await
-            .map_err(|db_e| {
-                error!("Both schema queries failed. New schema error: {}. Old schema error: {}", e, db_e);
-                AppError::Database(e)
-            })?;
-            
-            info!("Successfully fetched {} projects using old schema fallback", old_schema_projects.len());
-            old_schema_projects
-        }
-    };
 
+    // Administrators see every project, everyone else only the ones they belong to
+    let projects = match &current_user.user {
+        Some(user) if !user.is_admin => state.db.storage().list_projects_for_user(&user.id).await,
+        _ => state.db.storage().list_projects().await,
+    }
+    .map_err(|e| {
+        error!("Failed to fetch projects: {}", e);
+        AppError::from(e)
+    })?;
+
+    info!("Successfully fetched {} projects", projects.len());
     Ok(Json(projects))
 }
 
 pub async fn create_project(
     State(state): State<AppState>,
+    current_user: CurrentUser,
     Json(req): Json<CreateProjectRequest>,
 ) -> Result<Json<Project>, AppError> {
     info!("Creating new project: {}", req.name);
@@ -62,59 +88,36 @@ pub async fn create_project(
                 AppError::from(e)
             })?;
 
-    let project = Project::new(sanitized_name, sanitized_description);
+    let project = Project::new(sanitized_name, sanitized_description)
+        .with_created_by(current_user.user_id());
 
     info!("Inserting project into database with ID: {}", project.id);
-    
-    // Try new schema first
-    let new_schema_result = sqlx::query(
-        "INSERT INTO projects (id, name, description, root_path, synapse_config, project_type, last_accessed, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
-    )
-    .bind(&project.id)
-    .bind(&project.name)
-    .bind(&project.description)
-    .bind(&project.root_path)
-    .bind(&project.synapse_config)
-    .bind(&project.project_type)
-    .bind(&project.last_accessed)
-    .bind(&project.created_at)
-    .bind(&project.updated_at)
-    .execute(state.db.pool())
-    .await;
 
-    match new_schema_result {
-        Ok(_) => {
-            info!("Successfully created project {} with ID {} using new schema", project.name, project.id);
-            return Ok(Json(project));
-        }
-        Err(e) => {
-            warn!("New schema insert failed, trying fallback: {}", e);
-        }
+    state.db.storage().insert_project(&project).await.map_err(|e| {
+        error!("Failed to create project: {}", e);
+        AppError::from(e)
+    })?;
+
+    // The creator owns the project and administers it
+    if let Some(user_id) = &project.created_by {
+        state.db.storage()
+            .set_project_member(&project.id, user_id, ProjectRole::Admin, Some(user_id))
+            .await
+            .map_err(AppError::from)?;
     }
-    
-    // Fallback to old schema
-    let result = sqlx::query(
-        "INSERT INTO projects (id, name, description, created_at, updated_at) VALUES (?, ?, ?, ?, ?)"
+
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("project.create")
+            .with_project(project.id.clone())
+            .with_target("project", project.id.clone())
+            .with_details(serde_json::json!({ "name": project.name })),
     )
-    .bind(&project.id)
-    .bind(&project.name)
-    .bind(&project.description)
-    .bind(&project.created_at)
-    .bind(&project.updated_at)
-    .execute(state.db.pool())
     .await;
-    
-    match result {
-        Ok(_) => {
-            info!("Successfully created project {} with ID {} using old schema fallback", project.name, project.id);
-            Ok(Json(project))
-        }
-        Err(e) => {
-            error!("Failed to create project with both schemas: {}", e);
-            error!("Database error details: {:?}", e);
-            Err(AppError::Database(e))
-        }
-    }
+
+    info!("Successfully created project {} with ID {}", project.name, project.id);
+    Ok(Json(project))
 }
 
 pub async fn get_project(
@@ -130,49 +133,24 @@ pub async fn get_project(
             AppError::from(e)
         })?;
 
-    // Try new schema first
-    let new_schema_result = sqlx::query_as::<_, Project>(
-        "SELECT id, name, description, root_path, synapse_config, project_type, last_accessed, created_at, updated_at FROM projects WHERE id = ?",
-    )
-    .bind(&project_id)
-    .fetch_optional(state.db.pool())
-    .await;
-    
-    match new_schema_result {
-        Ok(Some(project)) => {
-            info!("Successfully found project: {} using new schema", project.name);
-            return Ok(Json(project));
-        }
-        Ok(None) => {
-            warn!("Project {} not found with new schema, trying fallback", project_id);
-        }
-        Err(e) => {
-            warn!("New schema query failed for project {}, trying fallback: {}", project_id, e);
-        }
-    }
-    
-    // Fallback to old schema
-    let project = sqlx::query_as::<_, Project>(
-        "SELECT id, name, description, NULL as root_path, NULL as synapse_config, 'web' as project_type, NULL as last_accessed, created_at, updated_at FROM projects WHERE id = ?",
-    )
-    .bind(&project_id)
-    .fetch_optional(state.db.pool())
-    .await
-    .map_err(|e| {
-        error!("Both schema queries failed for project {}: {}", project_id, e);
-        AppError::Database(e)
-    })?
-    .ok_or_else(|| {
-        warn!("Project {} not found in either schema", project_id);
-        AppError::not_found(format!("Project {} not found", project_id))
-    })?;
+    let project = state.db.storage().get_project(&project_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch project {}: {}", project_id, e);
+            AppError::from(e)
+        })?
+        .ok_or_else(|| {
+            warn!("Project {} not found", project_id);
+            AppError::not_found(format!("Project {} not found", project_id))
+        })?;
 
-    info!("Successfully found project: {} using old schema fallback", project.name);
+    info!("Successfully found project: {}", project.name);
     Ok(Json(project))
 }
 
 pub async fn delete_project(
     State(state): State<AppState>,
+    current_user: CurrentUser,
     Path(project_id): Path<String>,
 ) -> Result<Json<Value>, AppError> {
     // Validate UUID format
@@ -183,82 +161,228 @@ pub async fn delete_project(
         })?;
 
     // Check for existing analyses that would be orphaned
-    let analysis_count = sqlx::query!(
-        "SELECT COUNT(*) as count FROM analyses WHERE project_id = ?",
-        project_id
-    )
-    .fetch_one(state.db.pool())
-    .await
-    .map_err(|e: sqlx::Error| {
+    let analysis_count = state.db.storage().count_analyses(&project_id).await.map_err(|e| {
         tracing::error!("Failed to check analyses for project {}: {}", project_id, e);
-        AppError::Database(e)
+        AppError::from(e)
     })?;
 
-    if analysis_count.count > 0 {
-        tracing::warn!("Cannot delete project {} - has {} analyses", project_id, analysis_count.count);
+    if analysis_count > 0 {
+        tracing::warn!("Cannot delete project {} - has {} analyses", project_id, analysis_count);
         return Err(AppError::bad_request(
-            format!("Cannot delete project with {} analyses", analysis_count.count)
+            format!("Cannot delete project with {} analyses", analysis_count)
         ));
     }
 
-    let result = sqlx::query!("DELETE FROM projects WHERE id = ?", project_id)
-        .execute(state.db.pool())
-        .await
-        .map_err(|e: sqlx::Error| {
-            tracing::error!("Failed to delete project {}: {}", project_id, e);
-            AppError::Database(e)
-        })?;
+    let project = state.db.storage().get_project(&project_id).await?;
+    let deleted = state.db.storage().delete_project(&project_id).await.map_err(|e| {
+        tracing::error!("Failed to delete project {}: {}", project_id, e);
+        AppError::from(e)
+    })?;
 
-    if result.rows_affected() == 0 {
+    if !deleted {
         return Err(AppError::not_found(format!("Project {} not found", project_id)));
     }
 
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("project.delete")
+            .with_project(project_id.clone())
+            .with_target("project", project_id.clone())
+            .with_details(serde_json::json!({ "name": project.map(|project| project.name) })),
+    )
+    .await;
+
     tracing::info!("Deleted project {}", project_id);
     Ok(Json(serde_json::json!({ "success": true })))
 }
 
-pub async fn sync_cli_projects(
+// Project membership
+
+pub async fn list_project_members(
     State(state): State<AppState>,
-) -> Result<Json<Value>, AppError> {
-    info!("Starting CLI project sync");
-    
-    // First check if CLI integration columns exist, try to create them if not
-    let schema_check = sqlx::query(
-        "SELECT COUNT(*) as count FROM pragma_table_info('projects') WHERE name = 'root_path'"
+    Path(project_id): Path<String>,
+) -> Result<Json<Vec<ProjectMember>>, AppError> {
+    Ok(Json(state.db.storage().list_project_members(&project_id).await?))
+}
+
+/// Add a user to a project by username, or change their role if already a member
+pub async fn add_project_member(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Path(project_id): Path<String>,
+    Json(req): Json<AddMemberRequest>,
+) -> Result<Json<ProjectMember>, AppError> {
+    let user = state.db.storage()
+        .get_user_by_username(req.username.trim())
+        .await?
+        .filter(|user| user.is_active)
+        .ok_or_else(|| AppError::not_found(format!("User {} not found", req.username)))?;
+
+    set_member_role(&state, &current_user, &project_id, &user.id, req.role).await
+}
+
+pub async fn update_project_member(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Path((project_id, user_id)): Path<(String, String)>,
+    Json(req): Json<UpdateMemberRequest>,
+) -> Result<Json<ProjectMember>, AppError> {
+    if state.db.storage().get_project_role(&project_id, &user_id).await?.is_none() {
+        return Err(AppError::not_found(format!("User {} is not a member of this project", user_id)));
+    }
+
+    set_member_role(&state, &current_user, &project_id, &user_id, req.role).await
+}
+
+pub async fn remove_project_member(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Path((project_id, user_id)): Path<(String, String)>,
+) -> Result<StatusCode, AppError> {
+    ensure_not_owner(&state, &project_id, &user_id).await?;
+
+    let removed = state.db.storage()
+        .remove_project_member(&project_id, &user_id, current_user.user_id().as_deref())
+        .await?;
+    if removed.is_none() {
+        return Err(AppError::not_found(format!("User {} is not a member of this project", user_id)));
+    }
+
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("project.member.remove")
+            .with_project(project_id.clone())
+            .with_target("user", user_id.clone())
+            .with_details(serde_json::json!({ "old_role": removed })),
     )
-    .fetch_one(state.db.pool())
     .await;
-    
-    match schema_check {
-        Ok(row) if row.get::<i64, usize>(0) == 0 => {
-            warn!("CLI integration columns not found, attempting to add them");
-            
-            // Try to add CLI integration columns
-            let migration_result = sqlx::query(
-                "ALTER TABLE projects ADD COLUMN root_path TEXT;
-                 ALTER TABLE projects ADD COLUMN synapse_config TEXT;
-                 ALTER TABLE projects ADD COLUMN last_accessed DATETIME;
-                 ALTER TABLE projects ADD COLUMN project_type TEXT DEFAULT 'unknown';"
-            )
-            .execute(state.db.pool())
-            .await;
-            
-            match migration_result {
-                Ok(_) => {
-                    info!("Successfully added CLI integration columns");
-                }
-                Err(e) => {
-                    warn!("Failed to add CLI integration columns: {}, continuing with existing schema", e);
-                }
-            }
-        }
-        Ok(_) => {
-            info!("CLI integration columns already exist");
-        }
-        Err(e) => {
-            warn!("Failed to check schema: {}, continuing anyway", e);
-        }
+
+    info!("Removed user {} from project {}", user_id, project_id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Who added, removed or changed the role of which member, newest first
+pub async fn get_project_member_history(
+    State(state): State<AppState>,
+    Path(project_id): Path<String>,
+    Query(params): Query<MemberHistoryQuery>,
+) -> Result<Json<Vec<ProjectMemberChange>>, AppError> {
+    let limit = params.limit.unwrap_or(100).clamp(1, 1000);
+    Ok(Json(state.db.storage().list_project_member_changes(&project_id, limit).await?))
+}
+
+async fn set_member_role(
+    state: &AppState,
+    current_user: &CurrentUser,
+    project_id: &str,
+    user_id: &str,
+    role: ProjectRole,
+) -> Result<Json<ProjectMember>, AppError> {
+    if role != ProjectRole::Admin {
+        ensure_not_owner(state, project_id, user_id).await?;
     }
+
+    let storage = state.db.storage();
+    let previous = storage
+        .set_project_member(project_id, user_id, role, current_user.user_id().as_deref())
+        .await?;
+    info!(
+        "Set role of user {} in project {} to {} (was {:?})",
+        user_id, project_id, role, previous
+    );
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("project.member.set")
+            .with_project(project_id)
+            .with_target("user", user_id)
+            .with_details(serde_json::json!({ "old_role": previous, "new_role": role })),
+    )
+    .await;
+
+    storage
+        .list_project_members(project_id)
+        .await?
+        .into_iter()
+        .find(|member| member.user_id == user_id)
+        .map(Json)
+        .ok_or_else(|| AppError::internal("Member missing after update"))
+}
+
+/// The project's daily analysis quota and today's usage
+pub async fn get_project_quota(
+    State(state): State<AppState>,
+    Path(project_id): Path<String>,
+) -> Result<Json<ProjectQuota>, AppError> {
+    project_quota(&state, &project_id).await.map(Json)
+}
+
+/// Override the server's daily analysis quota for one project
+pub async fn update_project_quota(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Path(project_id): Path<String>,
+    Json(req): Json<UpdateQuotaRequest>,
+) -> Result<Json<ProjectQuota>, AppError> {
+    if req.daily_analysis_quota.is_some_and(|quota| quota < 0) {
+        return Err(AppError::validation("daily_analysis_quota must be 0 or more"));
+    }
+
+    let updated = state.db.storage()
+        .set_project_analysis_quota(&project_id, req.daily_analysis_quota)
+        .await?;
+    if !updated {
+        return Err(AppError::not_found(format!("Project {} not found", project_id)));
+    }
+
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("project.quota.set")
+            .with_project(project_id.clone())
+            .with_details(serde_json::json!({ "daily_analysis_quota": req.daily_analysis_quota })),
+    )
+    .await;
+
+    info!("Set daily analysis quota of project {} to {:?}", project_id, req.daily_analysis_quota);
+    project_quota(&state, &project_id).await.map(Json)
+}
+
+async fn project_quota(state: &AppState, project_id: &str) -> Result<ProjectQuota, AppError> {
+    let storage = state.db.storage();
+    if storage.get_project(project_id).await?.is_none() {
+        return Err(AppError::not_found(format!("Project {} not found", project_id)));
+    }
+
+    let day_start = rate_limit::quota_day_start(Utc::now());
+    Ok(ProjectQuota {
+        project_id: project_id.to_string(),
+        daily_analysis_quota: rate_limit::daily_analysis_quota(state, project_id).await?,
+        project_quota: storage.get_project_analysis_quota(project_id).await?,
+        used_today: storage.count_project_analyses_since(project_id, day_start).await?,
+        resets_at: day_start + Days::new(1),
+    })
+}
+
+/// The owner always administers their project
+async fn ensure_not_owner(state: &AppState, project_id: &str, user_id: &str) -> Result<(), AppError> {
+    let project = state.db.storage()
+        .get_project(project_id)
+        .await?
+        .ok_or_else(|| AppError::not_found(format!("Project {} not found", project_id)))?;
+
+    if project.created_by.as_deref() == Some(user_id) {
+        return Err(AppError::bad_request("The project owner must remain a project admin"));
+    }
+    Ok(())
+}
+
+pub async fn sync_cli_projects(
+    State(state): State<AppState>,
+) -> Result<Json<Value>, AppError> {
+    info!("Starting CLI project sync");
     
     let mut synced_count = 0;
     let mut error_count = 0;
@@ -369,17 +493,12 @@ async fn import_cli_project(state: &AppState, project_path: &PathBuf) -> Result<
     let synapse_config = metadata.to_string();
     
     // Check if project already exists in web database
-    let exists = sqlx::query(
-        "SELECT COUNT(*) as count FROM projects WHERE id = ? OR root_path = ?"
-    )
-    .bind(&project_id)
-    .bind(&root_path)
-    .fetch_one(state.db.pool())
-    .await
-    .map_err(|e| AppError::Database(e))?
-    .get::<i64, usize>(0);
+    let exists = state.db.storage()
+        .project_exists(&project_id, &root_path)
+        .await
+        .map_err(AppError::from)?;
     
-    if exists > 0 {
+    if exists {
         return Ok(false); // Project already exists, skip
     }
     
@@ -393,23 +512,9 @@ async fn import_cli_project(state: &AppState, project_path: &PathBuf) -> Result<
         project_type,
     );
     
-    sqlx::query(
-        "INSERT INTO projects (id, name, description, root_path, synapse_config, project_type, last_accessed, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
-    )
-    .bind(&project.id)
-    .bind(&project.name)
-    .bind(&project.description)
-    .bind(&project.root_path)
-    .bind(&project.synapse_config)
-    .bind(&project.project_type)
-    .bind(&project.last_accessed)
-    .bind(&project.created_at)
-    .bind(&project.updated_at)
-    .execute(state.db.pool())
-    .await
-    .map_err(|e| {
+    state.db.storage().insert_project(&project).await.map_err(|e| {
         warn!("Failed to insert CLI project {} into web database: {}", project.id, e);
-        AppError::Database(e)
+        AppError::from(e)
     })?;
     
     info!("Imported CLI project '{}' from {}", project.name, project_path.display());
@@ -0,0 +1,235 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde::Deserialize;
+use synapse_core::project::{Analysis, AnalysisSchedule};
+
+use crate::{
+    audit,
+    error_handling::AppError,
+    middleware::{auth::CurrentUser, rate_limit::check_analysis_quota},
+    scheduler::{cron::CronSchedule, RunOutcome, MAX_WINDOW_SECS},
+    validation::Validator,
+    AppState,
+};
+
+const DEFAULT_WINDOW_SECS: i64 = 15 * 60;
+const MIN_WINDOW_SECS: i64 = 60;
+
+#[derive(Debug, Deserialize)]
+pub struct ScheduleRequest {
+    pub name: String,
+    /// Streaming source to analyze; every source when omitted
+    pub source: Option<String>,
+    /// Five-field cron expression, evaluated in UTC
+    pub cron: Option<String>,
+    /// Alert rule whose alerts start a run
+    pub trigger_rule_id: Option<String>,
+    pub window_secs: Option<i64>,
+    pub provider: Option<String>,
+    pub level: Option<String>,
+    pub enabled: Option<bool>,
+}
+
+/// Check a schedule request and apply it to `schedule`; omitted fields take their defaults
+async fn apply_schedule_request(
+    state: &AppState,
+    schedule: &mut AnalysisSchedule,
+    req: ScheduleRequest,
+) -> Result<(), AppError> {
+    let name = req.name.trim();
+    if name.is_empty() {
+        return Err(AppError::bad_request("Schedule name is required"));
+    }
+    let cron = req.cron.map(|cron| cron.trim().to_string()).filter(|cron| !cron.is_empty());
+    if let Some(cron) = &cron {
+        cron.parse::<CronSchedule>().map_err(AppError::bad_request)?;
+    }
+    let trigger_rule_id = req.trigger_rule_id.filter(|rule_id| !rule_id.is_empty());
+    if let Some(rule_id) = &trigger_rule_id {
+        let rule = state.db.storage().get_alert_rule(rule_id).await?;
+        if rule.is_none_or(|rule| rule.project_id != schedule.project_id) {
+            return Err(AppError::bad_request("Trigger rule not found in this project"));
+        }
+    }
+    if cron.is_none() && trigger_rule_id.is_none() {
+        return Err(AppError::bad_request("A schedule needs a cron expression, a trigger rule or both"));
+    }
+    let window_secs = req.window_secs.unwrap_or(DEFAULT_WINDOW_SECS);
+    if !(MIN_WINDOW_SECS..=MAX_WINDOW_SECS).contains(&window_secs) {
+        return Err(AppError::bad_request(format!(
+            "Window must be between {} and {} seconds",
+            MIN_WINDOW_SECS, MAX_WINDOW_SECS
+        )));
+    }
+
+    // Provider and level default to the ones chosen in settings
+    let settings = state.db.storage().get_settings().await?.unwrap_or_default();
+    let provider = req.provider.unwrap_or(settings.default_provider);
+    Validator::validate_provider(&provider)?;
+    let level = req.level.unwrap_or(settings.default_level);
+    Validator::validate_log_level(&level)?;
+
+    schedule.name = name.to_string();
+    schedule.source = req.source.filter(|source| !source.is_empty());
+    schedule.cron = cron;
+    schedule.trigger_rule_id = trigger_rule_id;
+    schedule.window_secs = window_secs;
+    schedule.provider = provider;
+    schedule.level_filter = level.to_uppercase();
+    schedule.enabled = req.enabled.unwrap_or(true);
+    Ok(())
+}
+
+async fn find_schedule(state: &AppState, project_id: &str, schedule_id: &str) -> Result<AnalysisSchedule, AppError> {
+    state
+        .db
+        .storage()
+        .get_analysis_schedule(schedule_id)
+        .await?
+        .filter(|schedule| schedule.project_id == project_id)
+        .ok_or_else(|| AppError::not_found("Analysis schedule not found"))
+}
+
+/// Have the scheduler pick up a changed schedule; the change is stored either way
+async fn reload_schedules(state: &AppState) {
+    if let Err(e) = state.scheduler.reload().await {
+        tracing::warn!("Failed to reload analysis schedules: {}", e);
+    }
+}
+
+/// List the project's analysis schedules, including disabled ones
+pub async fn list_schedules(
+    State(state): State<AppState>,
+    Path(project_id): Path<String>,
+) -> Result<Json<Vec<AnalysisSchedule>>, AppError> {
+    Ok(Json(state.db.storage().list_analysis_schedules(&project_id).await?))
+}
+
+pub async fn get_schedule(
+    State(state): State<AppState>,
+    Path((project_id, schedule_id)): Path<(String, String)>,
+) -> Result<Json<AnalysisSchedule>, AppError> {
+    Ok(Json(find_schedule(&state, &project_id, &schedule_id).await?))
+}
+
+/// Create an analysis schedule; its source's window fills from entries streamed from then on
+pub async fn create_schedule(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Path(project_id): Path<String>,
+    Json(req): Json<ScheduleRequest>,
+) -> Result<Json<AnalysisSchedule>, AppError> {
+    let mut schedule = AnalysisSchedule::new(project_id.clone(), String::new(), String::new());
+    apply_schedule_request(&state, &mut schedule, req).await?;
+    schedule.created_by = current_user.user_id();
+
+    state.db.storage().create_analysis_schedule(&schedule).await?;
+    reload_schedules(&state).await;
+
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("analysis_schedule.create")
+            .with_project(project_id)
+            .with_target("analysis_schedule", schedule.id.clone())
+            .with_details(serde_json::json!({
+                "name": schedule.name,
+                "cron": schedule.cron,
+                "trigger_rule_id": schedule.trigger_rule_id,
+            })),
+    )
+    .await;
+
+    Ok(Json(schedule))
+}
+
+/// Replace a schedule's settings
+pub async fn update_schedule(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Path((project_id, schedule_id)): Path<(String, String)>,
+    Json(req): Json<ScheduleRequest>,
+) -> Result<Json<AnalysisSchedule>, AppError> {
+    let mut schedule = find_schedule(&state, &project_id, &schedule_id).await?;
+    apply_schedule_request(&state, &mut schedule, req).await?;
+
+    if !state.db.storage().update_analysis_schedule(&schedule).await? {
+        return Err(AppError::not_found("Analysis schedule not found"));
+    }
+    reload_schedules(&state).await;
+
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("analysis_schedule.update")
+            .with_project(project_id.clone())
+            .with_target("analysis_schedule", schedule_id.clone())
+            .with_details(serde_json::json!({
+                "name": schedule.name,
+                "cron": schedule.cron,
+                "trigger_rule_id": schedule.trigger_rule_id,
+                "enabled": schedule.enabled,
+            })),
+    )
+    .await;
+
+    Ok(Json(find_schedule(&state, &project_id, &schedule_id).await?))
+}
+
+/// Delete a schedule; the analyses it started are kept
+pub async fn delete_schedule(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Path((project_id, schedule_id)): Path<(String, String)>,
+) -> Result<StatusCode, AppError> {
+    if !state.db.storage().delete_analysis_schedule(&project_id, &schedule_id).await? {
+        return Err(AppError::not_found("Analysis schedule not found"));
+    }
+    reload_schedules(&state).await;
+
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("analysis_schedule.delete")
+            .with_project(project_id)
+            .with_target("analysis_schedule", schedule_id),
+    )
+    .await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Analyze the schedule's current window right away, whatever its cron expression
+pub async fn run_schedule(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Path((project_id, schedule_id)): Path<(String, String)>,
+) -> Result<Json<Analysis>, AppError> {
+    let schedule = find_schedule(&state, &project_id, &schedule_id).await?;
+    check_analysis_quota(&state, &project_id).await?;
+
+    let analysis = match state
+        .scheduler
+        .run(&schedule, None)
+        .await
+        .map_err(|e| AppError::internal(format!("Failed to run analysis schedule: {}", e)))?
+    {
+        RunOutcome::Queued(analysis) => *analysis,
+        RunOutcome::Skipped(reason) => return Err(AppError::bad_request(reason.to_string())),
+    };
+
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("analysis_schedule.run")
+            .with_project(project_id)
+            .with_target("analysis_schedule", schedule_id)
+            .with_details(serde_json::json!({ "analysis_id": analysis.id })),
+    )
+    .await;
+
+    Ok(Json(analysis))
+}
@@ -1,87 +1,67 @@
 use axum::{
-    extract::State,
+    extract::{Path, State},
+    http::StatusCode,
     response::Json,
 };
 use serde::{Deserialize, Serialize};
-use crate::{error_handling::AppError, AppState};
+use crate::{
+    alerts, audit, database::Database, error_handling::AppError, middleware::auth::CurrentUser, AppState,
+};
 use anyhow::Result;
-use sqlx::Row;
+use synapse_core::project::ProviderSecret;
+use synapse_core::secrets::{self, KeySource, Keyring};
+use tokio::sync::RwLock;
+
+pub use synapse_core::project::Settings;
+
+/// Providers that need an API key
+const KEYED_PROVIDERS: [&str; 4] = ["openai", "claude", "gemini", "openrouter"];
+
+/// Settings as returned to clients: keys are masked, and listed per provider
+#[derive(Debug, Serialize)]
+pub struct SettingsResponse {
+    #[serde(flatten)]
+    pub settings: Settings,
+    pub api_keys: Vec<ProviderSecret>,
+}
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Settings {
-    pub default_provider: String,
+#[derive(Debug, Deserialize)]
+pub struct SetApiKeyRequest {
     pub api_key: String,
-    pub max_lines: u32,
-    pub default_level: String,
-    pub show_timestamps: bool,
-    pub show_line_numbers: bool,
-    pub selected_model: Option<String>,
-    pub available_models: Option<String>, // JSON array cache
-    pub models_last_fetched: Option<String>, // ISO datetime
-    pub analysis_timeout_seconds: Option<i32>,
 }
 
-impl Default for Settings {
-    fn default() -> Self {
-        Self {
-            default_provider: "openrouter".to_string(),
-            api_key: String::new(),
-            max_lines: 1000,
-            default_level: "ERROR".to_string(),
-            show_timestamps: true,
-            show_line_numbers: true,
-            selected_model: None,
-            available_models: None,
-            models_last_fetched: None,
-            analysis_timeout_seconds: Some(300), // 5 minutes default
-        }
-    }
+#[derive(Debug, Serialize)]
+pub struct RotationResponse {
+    pub key_id: String,
+    /// Provider API keys re-encrypted with the new key
+    pub resealed: usize,
+    /// Notification channels whose secrets were re-encrypted
+    pub resealed_channels: usize,
+    pub previous_keys: usize,
 }
 
 pub async fn get_settings(
     State(state): State<AppState>,
-) -> Result<Json<Settings>, AppError> {
-    let pool = state.db.pool();
-    
+) -> Result<Json<SettingsResponse>, AppError> {
     // Query settings from database
-    let result = sqlx::query(
-        "SELECT default_provider, api_key, max_lines, default_level, show_timestamps, show_line_numbers,
-                selected_model, available_models, models_last_fetched, analysis_timeout_seconds
-         FROM settings WHERE id = 1"
-    )
-    .fetch_one(pool)
-    .await;
-    
-    match result {
-        Ok(row) => {
-            let settings = Settings {
-                default_provider: row.get("default_provider"),
-                api_key: row.get("api_key"),
-                max_lines: row.get("max_lines"),
-                default_level: row.get("default_level"),
-                show_timestamps: row.get("show_timestamps"),
-                show_line_numbers: row.get("show_line_numbers"),
-                selected_model: row.get("selected_model"),
-                available_models: row.get("available_models"),
-                models_last_fetched: row.get("models_last_fetched"),
-                analysis_timeout_seconds: row.get("analysis_timeout_seconds"),
-            };
-            Ok(Json(settings))
-        }
+    let settings = match state.db.storage().get_settings().await {
+        Ok(Some(settings)) => settings,
+        Ok(None) => Settings::default(),
         Err(e) => {
             tracing::error!("Failed to fetch settings: {}", e);
             // Return default settings if database query fails
-            Ok(Json(Settings::default()))
+            Settings::default()
         }
-    }
+    };
+
+    masked_settings(&state, settings).await.map(Json)
 }
 
 pub async fn update_settings(
     State(state): State<AppState>,
-    Json(settings): Json<Settings>,
-) -> Result<Json<Settings>, AppError> {
-    let pool = state.db.pool();
-
+    current_user: CurrentUser,
+    Json(mut settings): Json<Settings>,
+) -> Result<Json<SettingsResponse>, AppError> {
     // Validate the provider
     let valid_providers = ["openai", "claude", "gemini", "openrouter", "mock"];
     if !valid_providers.contains(&settings.default_provider.as_str()) {
@@ -106,36 +86,199 @@ pub async fn update_settings(
         }
     }
 
+    // A new key is stored for the selected provider; the masked key we sent out means "unchanged"
+    let api_key = std::mem::take(&mut settings.api_key);
+    let api_key = api_key.trim();
+    let api_key_changed = !api_key.is_empty() && !secrets::is_masked(api_key);
+    if api_key_changed {
+        if !KEYED_PROVIDERS.contains(&settings.default_provider.as_str()) {
+            return Err(AppError::bad_request(format!(
+                "Provider {} does not use an API key",
+                settings.default_provider
+            )));
+        }
+        store_key(&state, &current_user, &settings.default_provider, api_key).await?;
+    }
+
     // Update settings in database
-    let result = sqlx::query(
-        "UPDATE settings
-         SET default_provider = ?, api_key = ?, max_lines = ?, default_level = ?,
-             show_timestamps = ?, show_line_numbers = ?, selected_model = ?,
-             available_models = ?, models_last_fetched = ?, analysis_timeout_seconds = ?,
-             updated_at = CURRENT_TIMESTAMP
-         WHERE id = 1"
+    if let Err(e) = state.db.storage().update_settings(&settings).await {
+        tracing::error!("Failed to update settings: {}", e);
+        return Err(AppError::from(e));
+    }
+
+    audit::record(
+        &state.db,
+        current_user.audit_event("settings.update").with_details(serde_json::json!({
+            "default_provider": settings.default_provider,
+            "selected_model": settings.selected_model,
+            "default_level": settings.default_level,
+            "max_lines": settings.max_lines,
+            "analysis_timeout_seconds": settings.analysis_timeout_seconds,
+            "api_key_changed": api_key_changed,
+        })),
     )
-    .bind(&settings.default_provider)
-    .bind(&settings.api_key)
-    .bind(settings.max_lines)
-    .bind(&settings.default_level)
-    .bind(settings.show_timestamps)
-    .bind(settings.show_line_numbers)
-    .bind(&settings.selected_model)
-    .bind(&settings.available_models)
-    .bind(&settings.models_last_fetched)
-    .bind(settings.analysis_timeout_seconds)
-    .execute(pool)
     .await;
-    
-    match result {
-        Ok(_) => {
-            // Return the updated settings
-            Ok(Json(settings))
-        }
+
+    masked_settings(&state, settings).await.map(Json)
+}
+
+/// List the stored provider keys, masked
+pub async fn list_api_keys(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ProviderSecret>>, AppError> {
+    Ok(Json(state.db.storage().list_provider_secrets().await?))
+}
+
+/// Store or replace the API key for one provider
+pub async fn set_api_key(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Path(provider): Path<String>,
+    Json(request): Json<SetApiKeyRequest>,
+) -> Result<Json<ProviderSecret>, AppError> {
+    if !KEYED_PROVIDERS.contains(&provider.as_str()) {
+        return Err(AppError::bad_request(format!("Provider {} does not use an API key", provider)));
+    }
+    let api_key = request.api_key.trim();
+    if api_key.is_empty() || secrets::is_masked(api_key) {
+        return Err(AppError::bad_request("API key is required"));
+    }
+
+    store_key(&state, &current_user, &provider, api_key).await.map(Json)
+}
+
+/// Delete the API key for one provider
+pub async fn delete_api_key(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Path(provider): Path<String>,
+) -> Result<StatusCode, AppError> {
+    if !state.db.storage().delete_provider_secret(&provider).await? {
+        return Err(AppError::not_found(format!("No API key stored for {}", provider)));
+    }
+
+    audit::record(
+        &state.db,
+        current_user.audit_event("settings.api_key.delete").with_target("provider", provider.clone()),
+    )
+    .await;
+
+    tracing::info!("{} deleted the {} API key", actor(&current_user), provider);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Re-encrypt every provider key under a new master key
+///
+/// With a key file, a new key is generated and the old one kept in the file for opening
+/// values sealed elsewhere (such as config files). With `SYNAPSE_MASTER_KEY`, the operator
+/// has already swapped the key and this re-encrypts everything under it.
+pub async fn rotate_master_key(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+) -> Result<Json<RotationResponse>, AppError> {
+    let mut keyring = state.secrets.write().await;
+
+    if *keyring.source() != KeySource::Environment {
+        keyring
+            .rotate()
+            .map_err(|e| AppError::internal(format!("Failed to rotate the master key: {}", e)))?;
+    }
+    let resealed = secrets::reseal_api_keys(state.db.storage(), &keyring)
+        .await
+        .map_err(|e| AppError::internal(format!("Failed to re-encrypt API keys: {}", e)))?;
+    let resealed_channels = alerts::notifications::reseal_channel_secrets(state.db.storage(), &keyring)
+        .await
+        .map_err(|e| AppError::internal(format!("Failed to re-encrypt notification channel secrets: {}", e)))?;
+
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("settings.master_key.rotate")
+            .with_details(serde_json::json!({
+                "key_id": keyring.key_id(),
+                "resealed": resealed,
+                "resealed_channels": resealed_channels,
+            })),
+    )
+    .await;
+
+    tracing::info!(
+        "{} rotated the master key to {}, re-encrypting {} API keys",
+        actor(&current_user),
+        keyring.key_id(),
+        resealed
+    );
+    Ok(Json(RotationResponse {
+        key_id: keyring.key_id().to_string(),
+        resealed,
+        resealed_channels,
+        previous_keys: keyring.previous_key_count(),
+    }))
+}
+
+/// Load the master keys and move any plaintext key left in settings into the encrypted store
+pub async fn load_secrets(db: &Database) -> anyhow::Result<Keyring> {
+    let keyring = Keyring::load()?;
+    tracing::info!("Loaded master key {} for provider API keys", keyring.key_id());
+
+    if secrets::migrate_settings_api_key(db.storage(), &keyring).await? {
+        tracing::info!("Moved the API key in settings to encrypted storage");
+    }
+    Ok(keyring)
+}
+
+/// Decrypt the stored API key for a provider, for handing to the AI client
+///
+/// Failures are logged and treated as "no key", so the analysis falls back to the
+/// `<PROVIDER>_API_KEY` environment variable or the config file.
+pub async fn provider_api_key(db: &Database, keyring: &RwLock<Keyring>, provider: &str) -> Option<String> {
+    let keyring = keyring.read().await;
+    match secrets::load_api_key(db.storage(), &keyring, provider).await {
+        Ok(api_key) => api_key,
         Err(e) => {
-            tracing::error!("Failed to update settings: {}", e);
-            Err(AppError::Database(e))
+            tracing::error!("Failed to load the {} API key: {}", provider, e);
+            None
         }
     }
-}
\ No newline at end of file
+}
+
+async fn store_key(
+    state: &AppState,
+    current_user: &CurrentUser,
+    provider: &str,
+    api_key: &str,
+) -> Result<ProviderSecret, AppError> {
+    let keyring = state.secrets.read().await;
+    let secret = secrets::store_api_key(state.db.storage(), &keyring, provider, api_key).await?;
+
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("settings.api_key.set")
+            .with_target("provider", provider)
+            .with_details(serde_json::json!({ "key_id": secret.key_id, "hint": secret.hint })),
+    )
+    .await;
+
+    tracing::info!("{} stored a new {} API key", actor(current_user), provider);
+    Ok(secret)
+}
+
+async fn masked_settings(state: &AppState, mut settings: Settings) -> Result<SettingsResponse, AppError> {
+    let api_keys = state.db.storage().list_provider_secrets().await?;
+    settings.api_key = api_keys
+        .iter()
+        .find(|secret| secret.provider == settings.default_provider)
+        .map(|secret| secret.hint.clone())
+        .unwrap_or_default();
+
+    Ok(SettingsResponse { settings, api_keys })
+}
+
+fn actor(current_user: &CurrentUser) -> String {
+    current_user
+        .user
+        .as_ref()
+        .map(|user| format!("User {}", user.username))
+        .unwrap_or_else(|| "Local operator".to_string())
+}
@@ -0,0 +1,612 @@
+//! Share links: public, read-only views of one analysis
+//!
+//! Project admins create and revoke links. Anyone holding a link can open it
+//! without signing in, so revocation, expiry, the password and the download
+//! permission are all checked here on every access.
+
+use std::sync::LazyLock;
+
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Json, Response},
+    Form,
+};
+use rand::{distributions::Alphanumeric, rngs::OsRng, Rng};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use synapse_core::project::{AuditEvent, Share};
+
+use crate::{
+    audit,
+    error_handling::{AppError, ErrorResponse},
+    handlers::export::get_analysis_with_related_data,
+    middleware::auth::{hash_password, verify_password, ClientIp, CurrentUser},
+    middleware::rate_limit::{self, address_key, LimitedRoute},
+    AppState,
+};
+
+const DEFAULT_EXPIRY_HOURS: i64 = 24;
+const MAX_EXPIRY_HOURS: i64 = 24 * 90;
+const MIN_PASSWORD_LENGTH: usize = 8;
+const GENERATED_PASSWORD_LENGTH: usize = 16;
+/// Header API clients send the password of a protected link in
+const PASSWORD_HEADER: &str = "x-share-password";
+
+static EMAIL_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}")
+        .expect("Failed to compile email regex")
+});
+
+static IP_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\b(?:\d{1,3}\.){3}\d{1,3}\b|\b(?:[0-9A-Fa-f]{1,4}:){7}[0-9A-Fa-f]{1,4}\b")
+        .expect("Failed to compile IP address regex")
+});
+
+// `password=...`, `"api_key": "..."` and the like, also inside JSON-escaped text
+static SECRET_ASSIGNMENT_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"(?i)\b(password|passwd|pwd|secret|token|api[_-]?key|access[_-]?key)(\\?["']?\s*[:=]\s*\\?["']?)[^\s"'\\,;&]+"#,
+    )
+    .expect("Failed to compile secret assignment regex")
+});
+
+static BEARER_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\bbearer\s+[A-Za-z0-9._~+/=-]+").expect("Failed to compile bearer token regex")
+});
+
+static KEY_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\b(?:sk-[A-Za-z0-9_-]{16,}|AKIA[0-9A-Z]{16}|gh[pousr]_[A-Za-z0-9]{30,})\b")
+        .expect("Failed to compile API key regex")
+});
+
+#[derive(Deserialize)]
+pub struct ShareRequest {
+    pub analysis_id: String,
+    pub expires_in_hours: Option<i64>, // Default 24 hours
+    /// Protect the link with a generated password, unless `password` is given
+    pub password_protect: Option<bool>,
+    pub password: Option<String>,
+    pub allow_download: Option<bool>,
+    /// Mask emails, IP addresses and secrets in the shared view; on by default
+    pub redact_sensitive: Option<bool>,
+    pub include_correlations: Option<bool>,
+    pub include_metrics: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ShareResponse {
+    #[serde(flatten)]
+    pub share: Share,
+    pub share_url: String,
+    pub is_password_protected: bool,
+    /// The generated password; only returned when the link is created
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SharePasswordForm {
+    pub password: String,
+}
+
+/// Why a share link could not be opened
+enum ShareDenied {
+    NotFound,
+    Revoked,
+    Expired,
+    PasswordRequired,
+    WrongPassword,
+    DownloadDisabled,
+    Failed(AppError),
+}
+
+impl From<AppError> for ShareDenied {
+    fn from(error: AppError) -> Self {
+        ShareDenied::Failed(error)
+    }
+}
+
+impl ShareDenied {
+    fn status(&self) -> StatusCode {
+        match self {
+            ShareDenied::NotFound => StatusCode::NOT_FOUND,
+            ShareDenied::Revoked | ShareDenied::Expired => StatusCode::GONE,
+            ShareDenied::PasswordRequired | ShareDenied::WrongPassword => StatusCode::UNAUTHORIZED,
+            ShareDenied::DownloadDisabled => StatusCode::FORBIDDEN,
+            ShareDenied::Failed(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn message(&self) -> &'static str {
+        match self {
+            ShareDenied::NotFound => "This share link does not exist",
+            ShareDenied::Revoked => "This share link has been revoked",
+            ShareDenied::Expired => "This share link has expired",
+            ShareDenied::PasswordRequired => "This share link is password protected",
+            ShareDenied::WrongPassword => "Incorrect password",
+            ShareDenied::DownloadDisabled => "Downloads are not allowed for this share link",
+            ShareDenied::Failed(_) => "The shared analysis could not be loaded",
+        }
+    }
+
+    /// Page shown in the browser: the password form, or why the link can't be opened
+    fn into_page(self, share_id: &str) -> Response {
+        let status = self.status();
+        let body = match self {
+            ShareDenied::PasswordRequired | ShareDenied::WrongPassword => {
+                password_form(share_id, matches!(self, ShareDenied::WrongPassword))
+            }
+            ShareDenied::Failed(error) => return error.into_response(),
+            denied => format!("<p>{}</p>", denied.message()),
+        };
+        (status, Html(page("Shared Analysis", &body))).into_response()
+    }
+
+    /// JSON error for API clients
+    fn into_json(self) -> Response {
+        let code = match self {
+            ShareDenied::NotFound => "SHARE_NOT_FOUND",
+            ShareDenied::Revoked => "SHARE_REVOKED",
+            ShareDenied::Expired => "SHARE_EXPIRED",
+            ShareDenied::PasswordRequired => "SHARE_PASSWORD_REQUIRED",
+            ShareDenied::WrongPassword => "SHARE_PASSWORD_INCORRECT",
+            ShareDenied::DownloadDisabled => "SHARE_DOWNLOAD_DISABLED",
+            ShareDenied::Failed(error) => return error.into_response(),
+        };
+        let error = ErrorResponse::new("share_unavailable", self.message().to_string(), code.to_string());
+        (self.status(), Json(error)).into_response()
+    }
+}
+
+/// Create a share link for one of the project's analyses
+pub async fn create_share_link(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Path(project_id): Path<String>,
+    Json(req): Json<ShareRequest>,
+) -> Result<Json<ShareResponse>, AppError> {
+    // Verify analysis exists and belongs to project
+    state
+        .db
+        .storage()
+        .get_analysis(&req.analysis_id)
+        .await?
+        .filter(|analysis| analysis.project_id == project_id)
+        .ok_or_else(|| AppError::not_found("Analysis not found"))?;
+
+    let expires_in_hours = req.expires_in_hours.unwrap_or(DEFAULT_EXPIRY_HOURS);
+    if !(1..=MAX_EXPIRY_HOURS).contains(&expires_in_hours) {
+        return Err(AppError::bad_request(format!(
+            "Expiry must be between 1 and {} hours",
+            MAX_EXPIRY_HOURS
+        )));
+    }
+
+    // An explicit password protects the link; otherwise one is generated on request
+    let (password, generated) = match req.password.filter(|password| !password.is_empty()) {
+        Some(password) if password.len() < MIN_PASSWORD_LENGTH => {
+            return Err(AppError::bad_request(format!(
+                "Password must be at least {} characters",
+                MIN_PASSWORD_LENGTH
+            )));
+        }
+        Some(password) => (Some(password), false),
+        None if req.password_protect.unwrap_or(false) => (Some(generate_password()), true),
+        None => (None, false),
+    };
+
+    let mut share = Share::new(
+        project_id.clone(),
+        req.analysis_id.clone(),
+        current_user.user_id(),
+        chrono::Utc::now() + chrono::Duration::hours(expires_in_hours),
+    );
+    share.allow_download = req.allow_download.unwrap_or(true);
+    share.redact_sensitive = req.redact_sensitive.unwrap_or(true);
+    share.include_correlations = req.include_correlations.unwrap_or(true);
+    share.include_metrics = req.include_metrics.unwrap_or(true);
+    if let Some(password) = &password {
+        share.password_hash = Some(hash_password(password.clone()).await?);
+    }
+
+    state.db.storage().create_share(&share).await?;
+
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("share.create")
+            .with_project(project_id)
+            .with_target("share", share.id.clone())
+            .with_details(serde_json::json!({
+                "analysis_id": share.analysis_id,
+                "expires_at": share.expires_at,
+                "password_protected": share.is_password_protected(),
+                "allow_download": share.allow_download,
+                "redact_sensitive": share.redact_sensitive,
+            })),
+    )
+    .await;
+
+    let mut response = share_response(&state, share);
+    response.password = password.filter(|_| generated);
+    Ok(Json(response))
+}
+
+/// List the project's share links, including expired and revoked ones
+pub async fn list_share_links(
+    State(state): State<AppState>,
+    Path(project_id): Path<String>,
+) -> Result<Json<Vec<ShareResponse>>, AppError> {
+    let shares = state.db.storage().list_shares(&project_id).await?;
+    Ok(Json(shares.into_iter().map(|share| share_response(&state, share)).collect()))
+}
+
+/// Revoke a share link; it stops working immediately
+pub async fn revoke_share_link(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Path((project_id, share_id)): Path<(String, String)>,
+) -> Result<StatusCode, AppError> {
+    if !state.db.storage().revoke_share(&project_id, &share_id).await? {
+        return Err(AppError::not_found("Share link not found"));
+    }
+
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("share.revoke")
+            .with_project(project_id)
+            .with_target("share", share_id),
+    )
+    .await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Open a shared analysis in the browser
+///
+/// Password-protected links show a password form, or accept the password in the
+/// `X-Share-Password` header.
+pub async fn view_shared_analysis(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    client_ip: ClientIp,
+    Path(share_id): Path<String>,
+) -> Response {
+    let password = header_password(&headers);
+    render_shared_view(&state, client_ip, &share_id, password).await
+}
+
+/// Open a password-protected shared analysis from the password form
+pub async fn unlock_shared_analysis(
+    State(state): State<AppState>,
+    client_ip: ClientIp,
+    Path(share_id): Path<String>,
+    Form(form): Form<SharePasswordForm>,
+) -> Response {
+    render_shared_view(&state, client_ip, &share_id, Some(form.password)).await
+}
+
+/// Download a shared analysis as JSON, when the link allows it
+pub async fn download_shared_analysis(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    client_ip: ClientIp,
+    Path(share_id): Path<String>,
+) -> Response {
+    let password = header_password(&headers);
+    shared_download(&state, client_ip, &share_id, password).await
+}
+
+/// Download a password-protected shared analysis from the shared view
+pub async fn unlock_shared_download(
+    State(state): State<AppState>,
+    client_ip: ClientIp,
+    Path(share_id): Path<String>,
+    Form(form): Form<SharePasswordForm>,
+) -> Response {
+    shared_download(&state, client_ip, &share_id, Some(form.password)).await
+}
+
+async fn render_shared_view(
+    state: &AppState,
+    client_ip: ClientIp,
+    share_id: &str,
+    password: Option<String>,
+) -> Response {
+    let result = async {
+        let share = open_share(state, &client_ip, share_id, password.clone()).await?;
+        let analysis = shared_analysis(state, client_ip, &share, false).await?;
+        Ok::<_, ShareDenied>((share, analysis))
+    }
+    .await;
+
+    match result {
+        Ok((share, analysis)) => {
+            Html(page("Shared Analysis", &shared_view(&share, &analysis, password.as_deref()))).into_response()
+        }
+        Err(denied) => denied.into_page(share_id),
+    }
+}
+
+async fn shared_download(
+    state: &AppState,
+    client_ip: ClientIp,
+    share_id: &str,
+    password: Option<String>,
+) -> Response {
+    let result = async {
+        let share = open_share(state, &client_ip, share_id, password).await?;
+        if !share.allow_download {
+            return Err(ShareDenied::DownloadDisabled);
+        }
+        let analysis = shared_analysis(state, client_ip, &share, true).await?;
+        Ok::<_, ShareDenied>((share, analysis))
+    }
+    .await;
+
+    match result {
+        Ok((share, analysis)) => (
+            [
+                (header::CONTENT_TYPE, "application/json".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"synapse-analysis-{}.json\"", share.analysis_id),
+                ),
+            ],
+            serde_json::to_string_pretty(&analysis).unwrap_or_default(),
+        )
+            .into_response(),
+        Err(denied) => denied.into_json(),
+    }
+}
+
+/// Check that a link can be opened with the given password
+///
+/// Password attempts are rate limited per client address, and per link for guesses
+/// spread over many addresses.
+async fn open_share(
+    state: &AppState,
+    client_ip: &ClientIp,
+    share_id: &str,
+    password: Option<String>,
+) -> Result<Share, ShareDenied> {
+    let share = state
+        .db
+        .storage()
+        .get_share(share_id)
+        .await
+        .map_err(AppError::from)?
+        .ok_or(ShareDenied::NotFound)?;
+
+    if share.revoked_at.is_some() {
+        return Err(ShareDenied::Revoked);
+    }
+    if share.is_expired() {
+        return Err(ShareDenied::Expired);
+    }
+
+    if let Some(password_hash) = &share.password_hash {
+        let password = password
+            .filter(|password| !password.is_empty())
+            .ok_or(ShareDenied::PasswordRequired)?;
+        rate_limit::enforce(state, LimitedRoute::ShareUnlock, &address_key(client_ip))?;
+        rate_limit::enforce(state, LimitedRoute::ShareUnlock, &format!("share:{}", share.id))?;
+        if !verify_password(password, password_hash.clone()).await? {
+            tracing::warn!("Incorrect password for share link {}", share.id);
+            return Err(ShareDenied::WrongPassword);
+        }
+    }
+
+    Ok(share)
+}
+
+/// Load the analysis for an opened link, count the access and apply the link's redaction
+async fn shared_analysis(
+    state: &AppState,
+    ClientIp(ip_address): ClientIp,
+    share: &Share,
+    download: bool,
+) -> Result<serde_json::Value, AppError> {
+    let mut analysis =
+        get_analysis_with_related_data(state.clone(), &share.project_id, &share.analysis_id).await?;
+
+    state.db.storage().record_share_access(&share.id).await?;
+
+    // Share links are public, so the access is recorded without an actor
+    audit::record(
+        &state.db,
+        AuditEvent::new("share.access")
+            .with_project(share.project_id.clone())
+            .with_target("share", share.id.clone())
+            .with_details(serde_json::json!({ "analysis_id": share.analysis_id, "download": download }))
+            .with_ip_address(ip_address),
+    )
+    .await;
+
+    if let Some(fields) = analysis.as_object_mut() {
+        if !share.include_correlations {
+            fields.remove("correlations");
+        }
+        if !share.include_metrics {
+            fields.remove("performance_metrics");
+        }
+    }
+    if share.redact_sensitive {
+        redact_value(&mut analysis);
+    }
+
+    Ok(analysis)
+}
+
+fn share_response(state: &AppState, share: Share) -> ShareResponse {
+    ShareResponse {
+        share_url: format!("{}/api/shared/{}", state.config.public_base_url(), share.id),
+        is_password_protected: share.is_password_protected(),
+        password: None,
+        share,
+    }
+}
+
+fn header_password(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(PASSWORD_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+fn generate_password() -> String {
+    OsRng
+        .sample_iter(&Alphanumeric)
+        .take(GENERATED_PASSWORD_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+/// Mask every string in a shared analysis
+fn redact_value(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::String(text) => *text = redact_text(text),
+        serde_json::Value::Array(items) => items.iter_mut().for_each(redact_value),
+        serde_json::Value::Object(fields) => fields.values_mut().for_each(redact_value),
+        _ => {}
+    }
+}
+
+/// Mask emails, IP addresses, credentials and well-known API key formats
+fn redact_text(text: &str) -> String {
+    let text = SECRET_ASSIGNMENT_REGEX.replace_all(text, "${1}${2}[redacted]");
+    let text = BEARER_REGEX.replace_all(&text, "Bearer [redacted]");
+    let text = KEY_REGEX.replace_all(&text, "[redacted]");
+    let text = EMAIL_REGEX.replace_all(&text, "[email]");
+    IP_REGEX.replace_all(&text, "[ip]").into_owned()
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn password_form(share_id: &str, incorrect: bool) -> String {
+    format!(
+        r#"<p>This analysis is password protected.</p>
+        {}<form method="post" action="/api/shared/{}">
+            <input type="password" name="password" placeholder="Password" autofocus required>
+            <button type="submit">Open</button>
+        </form>"#,
+        if incorrect { r#"<p class="error">Incorrect password</p>"# } else { "" },
+        escape_html(share_id)
+    )
+}
+
+fn shared_view(share: &Share, analysis: &serde_json::Value, password: Option<&str>) -> String {
+    let download = if share.allow_download {
+        format!(
+            r#"<form method="post" action="/api/shared/{}/download">
+            <input type="hidden" name="password" value="{}">
+            <button type="submit">Download JSON</button>
+        </form>"#,
+            escape_html(&share.id),
+            escape_html(password.unwrap_or_default())
+        )
+    } else {
+        String::new()
+    };
+
+    format!(
+        r#"<div class="shared-info">
+            <p><strong>Shared by:</strong> Synapse</p>
+            <p><strong>Created:</strong> {}</p>
+            <p><strong>Expires:</strong> {}</p>
+            <p><strong>Access:</strong> {}</p>{}
+        </div>
+        {}
+        <h2>Analysis Data</h2>
+        <pre>{}</pre>"#,
+        share.created_at.to_rfc3339(),
+        share.expires_at.to_rfc3339(),
+        if share.is_password_protected() { "Password protected" } else { "Public" },
+        if share.redact_sensitive {
+            "\n            <p>Emails, IP addresses and credentials are masked.</p>"
+        } else {
+            ""
+        },
+        download,
+        escape_html(&serde_json::to_string_pretty(analysis).unwrap_or_default())
+    )
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <meta name="robots" content="noindex">
+    <title>{} - Synapse</title>
+    <style>
+        body {{ font-family: 'Segoe UI', Tahoma, Geneva, Verdana, sans-serif; margin: 0; padding: 20px; background: #f5f5f5; }}
+        .container {{ max-width: 1200px; margin: 0 auto; background: white; padding: 30px; border-radius: 10px; box-shadow: 0 2px 10px rgba(0,0,0,0.1); }}
+        h1 {{ color: #2c3e50; border-bottom: 3px solid #3498db; padding-bottom: 10px; }}
+        .shared-info {{ background: #e8f4fd; padding: 15px; border-radius: 5px; margin-bottom: 20px; }}
+        .error {{ color: #c0392b; }}
+        pre {{ background: #f8f9fa; padding: 15px; border-radius: 5px; overflow-x: auto; }}
+    </style>
+</head>
+<body>
+    <div class="container">
+        <h1>{}</h1>
+        {}
+    </div>
+</body>
+</html>
+"#,
+        title, title, body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_text() {
+        assert_eq!(
+            redact_text("login failed for alice@example.com from 10.0.0.12"),
+            "login failed for [email] from [ip]"
+        );
+        assert_eq!(redact_text("db password=hunter2; retrying"), "db password=[redacted]; retrying");
+        assert_eq!(
+            redact_text(r#"{\"api_key\": \"abc123\", \"level\": \"ERROR\"}"#),
+            r#"{\"api_key\": \"[redacted]\", \"level\": \"ERROR\"}"#
+        );
+        assert_eq!(redact_text("Authorization: Bearer eyJhbGciOi.x.y"), "Authorization: Bearer [redacted]");
+        assert_eq!(redact_text("using sk-abcdefghijklmnop1234"), "using [redacted]");
+        assert_eq!(redact_text("token expired at 12:00"), "token expired at 12:00");
+    }
+
+    #[test]
+    fn test_redact_value_masks_nested_strings() {
+        let mut value = serde_json::json!({
+            "analysis": { "result": "user bob@example.org", "count": 3 },
+            "correlations": ["192.168.1.1"],
+        });
+        redact_value(&mut value);
+        assert_eq!(value["analysis"]["result"], "user [email]");
+        assert_eq!(value["analysis"]["count"], 3);
+        assert_eq!(value["correlations"][0], "[ip]");
+    }
+
+    #[test]
+    fn test_shared_view_escapes_html() {
+        let share = Share::new("p".to_string(), "a".to_string(), None, chrono::Utc::now());
+        let html = shared_view(&share, &serde_json::json!({ "result": "<script>" }), None);
+        assert!(html.contains("&lt;script&gt;"));
+        assert!(!html.contains("<script>"));
+    }
+}
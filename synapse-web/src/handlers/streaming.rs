@@ -1,22 +1,50 @@
 use axum::{
+    body::Bytes,
     extract::{Path, Query, State},
-    http::StatusCode,
-    response::Json,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
 };
 use serde::{Deserialize, Serialize};
-use sqlx::Row;
+use chrono::{DateTime, Utc};
+use synapse_core::project::{LogCursor, LogRetention, StreamingLogQuery, StreamingSource};
 use uuid::Uuid;
 
 use crate::{
+    alerts::rules::parse_level,
+    audit,
     error_handling::AppError,
-    streaming::{StreamingLogEntry, sources::{StreamingSourceConfig, StreamingSourceType, ParserConfig, LogFormat}},
+    middleware::{
+        auth::{bearer_token, generate_secret, hash_secret, ClientIp, CurrentUser},
+        rate_limit::{self, address_key, LimitedRoute},
+    },
+    streaming::{
+        docker::DockerConfig,
+        file_tail::FileTailConfig,
+        fluent_forward::ForwardConfig,
+        http_endpoint::{HttpEndpoint, PayloadFormat, SubmitError},
+        kubernetes::{KubernetesConfig, KubernetesRuntime},
+        otlp::{self, OtlpEncoding},
+        sources::{LogFormat, ParserConfig, StreamingSourceConfig, StreamingSourceType},
+        store::{self, CountBucket, Retention},
+        syslog::{self, SyslogConfig},
+        LogFilter, StreamingLogEntry, RECENT_ENTRIES,
+    },
     AppState,
 };
-use std::path::PathBuf;
 use tokio::time::Duration;
 
-// Note: For now we'll use a simplified approach without persistent streaming managers
-// In a production system, this would be properly integrated into the AppState
+/// Header an HTTP endpoint source's token may be sent in instead of `Authorization: Bearer`
+const INGEST_TOKEN_HEADER: &str = "x-synapse-ingest-token";
+/// Longest ingest path an HTTP endpoint source may use
+const MAX_INGEST_PATH_LEN: usize = 128;
+/// Source types that run commands, read the server's files and container runtimes, open
+/// listening sockets on the server or read its standard input
+const HOST_SOURCE_TYPES: [&str; 8] =
+    ["command", "file", "docker", "kubernetes", "syslog", "fluent_forward", "tcp", "stdin"];
+/// Most stored entries one search returns
+const MAX_SEARCH_RESULTS: i64 = 1000;
+/// Most intervals one count query returns
+const MAX_COUNT_BUCKETS: i64 = 10_000;
 
 #[derive(Debug, Deserialize)]
 pub struct CreateStreamingSourceRequest {
@@ -47,6 +75,17 @@ pub struct StreamingSourceResponse {
     pub project_id: Uuid,
     pub status: String,
     pub created_at: String,
+    /// Where an HTTP endpoint source accepts log data
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ingest_url: Option<String>,
+    /// An HTTP endpoint source's token; only returned when the source is created
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ingest_token: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IngestResponse {
+    pub accepted: usize,
 }
 
 #[derive(Debug, Serialize)]
@@ -61,15 +100,40 @@ pub struct StreamingStatsResponse {
 pub async fn create_streaming_source(
     Path(project_id): Path<Uuid>,
     State(state): State<AppState>,
+    current_user: CurrentUser,
     Json(request): Json<CreateStreamingSourceRequest>,
 ) -> Result<Json<StreamingSourceResponse>, AppError> {
     // Validate request
     if request.source_type.is_empty() || request.name.is_empty() {
         return Err(AppError::bad_request("Invalid request: source_type and name are required"));
     }
+    if needs_server_admin(&request.source_type) {
+        current_user.require_admin()?;
+    }
+
+    // HTTP endpoint and OTLP sources get a token of their own, of which only the digest is kept
+    let ingest_token = matches!(request.source_type.as_str(), "http" | "otlp").then(generate_secret);
+    let token_hash = ingest_token.as_deref().map(hash_secret);
 
     // Parse source type and create config
-    let source_type = parse_source_type(&request.source_type, &request.config)?;
+    let source_type = parse_source_type(&request.source_type, &request.config, token_hash.clone())?;
+    let ingest_url = match &source_type {
+        StreamingSourceType::HttpEndpoint { path, .. } => {
+            validate_ingest_path(path)?;
+            if state.streaming_manager.read().await.http_endpoint(path).is_some() {
+                return Err(AppError::bad_request(format!("Ingest path '{}' is already in use", path)));
+            }
+            Some(format!("{}/api/ingest/{}", state.config.public_base_url(), path))
+        }
+        StreamingSourceType::Otlp { path, .. } => {
+            validate_ingest_path(path)?;
+            if state.streaming_manager.read().await.http_endpoint(path).is_some() {
+                return Err(AppError::bad_request(format!("Ingest path '{}' is already in use", path)));
+            }
+            Some(format!("{}/api/otlp/{}/v1/logs", state.config.public_base_url(), path))
+        }
+        _ => None,
+    };
     let parser_config = parse_parser_config(request.parser_config.clone());
 
     let config = StreamingSourceConfig {
@@ -92,8 +156,14 @@ pub async fn create_streaming_source(
         })?;
     drop(manager); // Release lock
 
-    // Persist source config to database
-    persist_source_config(&state, &source_id, project_id, &request).await?;
+    // Persist source config to database, stopping the source again if that fails so it
+    // neither runs untracked nor keeps its ingest path
+    if let Err(e) = persist_source_config(&state, &source_id, project_id, &request, token_hash.as_deref()).await {
+        if let Err(stop_err) = state.streaming_manager.write().await.stop_source(&source_id).await {
+            tracing::error!("Failed to stop unsaved streaming source {}: {}", source_id, stop_err);
+        }
+        return Err(e);
+    }
 
     let response = StreamingSourceResponse {
         source_id,
@@ -102,17 +172,39 @@ pub async fn create_streaming_source(
         project_id,
         status: "active".to_string(),
         created_at: chrono::Utc::now().to_rfc3339(),
+        ingest_url,
+        ingest_token,
     };
 
+    // The source config can hold credentials, so only its name and type are recorded
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("streaming_source.create")
+            .with_project(project_id.to_string())
+            .with_target("streaming_source", response.source_id.clone())
+            .with_details(serde_json::json!({
+                "name": response.name,
+                "source_type": response.source_type,
+            })),
+    )
+    .await;
+
     tracing::info!("Created streaming source {} for project {}", response.source_id, project_id);
     Ok(Json(response))
 }
 
 /// Stop a streaming source
 pub async fn stop_streaming_source(
-    Path((_project_id, source_id)): Path<(Uuid, String)>,
+    Path((project_id, source_id)): Path<(Uuid, String)>,
     State(state): State<AppState>,
+    current_user: CurrentUser,
 ) -> Result<StatusCode, AppError> {
+    // Access is checked against the project in the path, so the source must belong to it
+    if state.db.storage().get_streaming_source(&project_id.to_string(), &source_id).await?.is_none() {
+        return Err(AppError::not_found(format!("Streaming source {} not found", source_id)));
+    }
+
     // Stop source via manager
     let mut manager = state.streaming_manager.write().await;
     manager.stop_source(&source_id).await
@@ -123,7 +215,17 @@ pub async fn stop_streaming_source(
     drop(manager); // Release lock
 
     // Remove from database
-    delete_source_config(&state, &source_id).await?;
+    state.db.storage().delete_streaming_source(&project_id.to_string(), &source_id).await?;
+    tracing::info!("Deleted streaming source {} from database", source_id);
+
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("streaming_source.delete")
+            .with_project(project_id.to_string())
+            .with_target("streaming_source", source_id.clone()),
+    )
+    .await;
 
     tracing::info!("Stopped streaming source {}", source_id);
     Ok(StatusCode::NO_CONTENT)
@@ -134,36 +236,14 @@ pub async fn list_streaming_sources(
     Path(project_id): Path<Uuid>,
     State(state): State<AppState>,
 ) -> Result<Json<Vec<StreamingSourceResponse>>, AppError> {
-    // Note: We query sources from the database rather than the in-memory manager
-    // to ensure we only return sources for this specific project
-
-    // Convert to response format with project filtering
-    // Note: The manager returns all sources, so we need to filter by project_id from database
-    let rows = sqlx::query(
-        "SELECT id, name, source_type, project_id, status, created_at
-         FROM streaming_sources
-         WHERE project_id = ? AND status = 'active'"
-    )
-    .bind(project_id.to_string())
-    .fetch_all(state.db.pool())
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to fetch streaming sources for project {}: {}", project_id, e);
-        AppError::Database(e)
-    })?;
-
-    let source_responses: Vec<StreamingSourceResponse> = rows
+    // Sources are read from the database rather than the in-memory manager, which holds every project's
+    let source_responses: Vec<StreamingSourceResponse> = state
+        .db
+        .storage()
+        .list_streaming_sources(&project_id.to_string())
+        .await?
         .into_iter()
-        .filter_map(|row| {
-            Some(StreamingSourceResponse {
-                source_id: row.try_get("id").ok()?,
-                name: row.try_get("name").ok()?,
-                source_type: row.try_get("source_type").ok()?,
-                project_id: Uuid::parse_str(&row.try_get::<String, _>("project_id").ok()?).ok()?,
-                status: row.try_get::<String, _>("status").ok()?,
-                created_at: row.try_get::<String, _>("created_at").ok()?,
-            })
-        })
+        .filter_map(source_response)
         .collect();
 
     tracing::debug!("Retrieved {} active streaming sources for project {}", source_responses.len(), project_id);
@@ -178,31 +258,13 @@ pub async fn get_streaming_stats(
     let connection_count = state.streaming_hub.get_connection_count(project_id).await;
 
     // Get active sources from database for this project
-    let rows = sqlx::query(
-        "SELECT id, name, source_type, project_id, status, created_at
-         FROM streaming_sources
-         WHERE project_id = ? AND status = 'active'"
-    )
-    .bind(project_id.to_string())
-    .fetch_all(state.db.pool())
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to fetch streaming sources for project {}: {}", project_id, e);
-        AppError::Database(e)
-    })?;
-
-    let source_responses: Vec<StreamingSourceResponse> = rows
+    let source_responses: Vec<StreamingSourceResponse> = state
+        .db
+        .storage()
+        .list_streaming_sources(&project_id.to_string())
+        .await?
         .into_iter()
-        .filter_map(|row| {
-            Some(StreamingSourceResponse {
-                source_id: row.try_get("id").ok()?,
-                name: row.try_get("name").ok()?,
-                source_type: row.try_get("source_type").ok()?,
-                project_id: Uuid::parse_str(&row.try_get::<String, _>("project_id").ok()?).ok()?,
-                status: row.try_get::<String, _>("status").ok()?,
-                created_at: row.try_get::<String, _>("created_at").ok()?,
-            })
-        })
+        .filter_map(source_response)
         .collect();
 
     let active_sources = source_responses.len();
@@ -265,6 +327,7 @@ pub async fn ingest_logs(
                 source: "http-ingest".to_string(),
                 project_id,
                 line_number: None,
+                metadata,
             })
         })
         .collect();
@@ -283,6 +346,123 @@ pub async fn ingest_logs(
     Ok(StatusCode::ACCEPTED)
 }
 
+/// Accept log data POSTed to an HTTP endpoint source's ingest URL
+///
+/// The body is newline-delimited text, a JSON array or record, or NDJSON, chosen by its
+/// `Content-Type`. Answers 429 with `Retry-After` while the source's queue is full.
+pub async fn ingest_to_endpoint(
+    Path(path): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    client_ip: ClientIp,
+    body: String,
+) -> Result<(StatusCode, Json<IngestResponse>), AppError> {
+    let endpoint = state
+        .streaming_manager
+        .read()
+        .await
+        .http_endpoint(&path)
+        .filter(|endpoint| !endpoint.is_otlp())
+        .ok_or_else(|| AppError::not_found(format!("Ingest endpoint {}", path)));
+    let endpoint = authorize_ingest(&state, endpoint, &headers, &client_ip)?;
+
+    let format = PayloadFormat::from_content_type(
+        headers.get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok()),
+    );
+    let entries = endpoint.parse(&body, format).map_err(AppError::bad_request)?;
+    let accepted = entries.len();
+    if accepted > 0 {
+        endpoint.try_submit(entries).map_err(|e| match e {
+            SubmitError::Full => AppError::rate_limited(
+                format!("Ingest queue for source {} is full", endpoint.source_id),
+                std::time::Duration::from_secs(1),
+            ),
+            SubmitError::Closed => AppError::not_found(format!("Ingest endpoint {}", path)),
+        })?;
+    }
+
+    Ok((StatusCode::ACCEPTED, Json(IngestResponse { accepted })))
+}
+
+/// Accept an OTLP/HTTP logs export POSTed to an OTLP source's `/v1/logs` URL
+///
+/// Answers with an empty `ExportLogsServiceResponse` in the request's encoding, or 429 with
+/// `Retry-After` while the source's queue is full, which OTLP exporters retry.
+pub async fn ingest_otlp_logs(
+    Path(path): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    client_ip: ClientIp,
+    body: Bytes,
+) -> Result<Response, AppError> {
+    let endpoint = state
+        .streaming_manager
+        .read()
+        .await
+        .http_endpoint(&path)
+        .filter(|endpoint| endpoint.is_otlp())
+        .ok_or_else(|| AppError::not_found(format!("OTLP endpoint {}", path)));
+    let endpoint = authorize_ingest(&state, endpoint, &headers, &client_ip)?;
+
+    let content_type = headers.get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok());
+    let encoding = OtlpEncoding::from_content_type(content_type).ok_or_else(|| {
+        AppError::bad_request("OTLP requests must be application/x-protobuf or application/json")
+    })?;
+    let request = encoding.decode(&body).map_err(AppError::bad_request)?;
+    let entries = otlp::into_entries(request, endpoint.project_id, &endpoint.source_id).map_err(AppError::bad_request)?;
+    if !entries.is_empty() {
+        endpoint.try_submit(entries).map_err(|e| match e {
+            SubmitError::Full => AppError::rate_limited(
+                format!("Ingest queue for source {} is full", endpoint.source_id),
+                std::time::Duration::from_secs(1),
+            ),
+            SubmitError::Closed => AppError::not_found(format!("OTLP endpoint {}", path)),
+        })?;
+    }
+
+    Ok(([(header::CONTENT_TYPE, encoding.content_type())], encoding.success_body()).into_response())
+}
+
+/// Check an ingest request's token, counting it against the source's rate limit
+///
+/// Requests for unknown sources or without a valid token count against the client's
+/// address instead, so made-up tokens never get buckets of their own.
+fn authorize_ingest(
+    state: &AppState,
+    endpoint: Result<HttpEndpoint, AppError>,
+    headers: &HeaderMap,
+    client_ip: &ClientIp,
+) -> Result<HttpEndpoint, AppError> {
+    let authorized = endpoint.and_then(|endpoint| {
+        if ingest_token(headers).is_some_and(|token| endpoint.authorize(token)) {
+            Ok(endpoint)
+        } else {
+            Err(AppError::Unauthorized)
+        }
+    });
+
+    match authorized {
+        Ok(endpoint) => {
+            rate_limit::enforce(state, LimitedRoute::Ingest, &format!("source:{}", endpoint.source_id))?;
+            Ok(endpoint)
+        }
+        Err(e) => {
+            rate_limit::enforce(state, LimitedRoute::Ingest, &address_key(client_ip))?;
+            Err(e)
+        }
+    }
+}
+
+/// A source's ingest token, as a bearer token or in the ingest token header
+fn ingest_token(headers: &HeaderMap) -> Option<&str> {
+    bearer_token(headers).or_else(|| {
+        headers
+            .get(INGEST_TOKEN_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+    })
+}
+
 /// Force flush all buffers for a project
 pub async fn flush_project_buffers(
     Path(project_id): Path<Uuid>,
@@ -304,30 +484,258 @@ pub struct StreamingFiltersQuery {
     pub level: Option<String>,
     pub source: Option<String>,
     pub since: Option<String>,
+    pub service: Option<String>,
+    pub host: Option<String>,
+    pub trace_id: Option<String>,
+    pub span_id: Option<String>,
+    pub limit: Option<usize>,
 }
 
-/// Get recent streaming logs (for debugging/testing)
+impl StreamingFiltersQuery {
+    fn into_filter(self) -> Result<LogFilter, AppError> {
+        let level = self
+            .level
+            .as_deref()
+            .map(|level| parse_level(level).ok_or_else(|| AppError::bad_request(format!("Unknown level '{}'", level))))
+            .transpose()?;
+        let since = parse_time("since", self.since.as_deref())?;
+
+        Ok(LogFilter {
+            level,
+            source: self.source,
+            since,
+            service: self.service,
+            host: self.host,
+            trace_id: self.trace_id,
+            span_id: self.span_id,
+        })
+    }
+}
+
+/// Get a project's most recent streaming logs, oldest first
 pub async fn get_recent_logs(
-    Path(_project_id): Path<Uuid>,
-    Query(_filters): Query<StreamingFiltersQuery>,
-    State(_state): State<AppState>,
+    Path(project_id): Path<Uuid>,
+    Query(filters): Query<StreamingFiltersQuery>,
+    State(state): State<AppState>,
 ) -> Result<Json<Vec<StreamingLogEntry>>, AppError> {
-    // This would require storing recent logs in memory or database
-    // For now, return empty array as this is primarily for the streaming interface
-    let logs = Vec::new();
-    Ok(Json(logs))
+    let limit = filters.limit.unwrap_or(100).min(RECENT_ENTRIES);
+    let filter = filters.into_filter()?;
+    Ok(Json(state.streaming_hub.recent_logs(project_id, &filter, limit)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LogSearchQuery {
+    pub level: Option<String>,
+    pub source: Option<String>,
+    /// Words that must all appear in the message
+    pub q: Option<String>,
+    pub since: Option<String>,
+    pub until: Option<String>,
+    /// `next_cursor` of the previous page
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LogSearchResponse {
+    pub entries: Vec<StreamingLogEntry>,
+    /// Pass as `cursor` for the next page; absent on the last one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LogCountsQuery {
+    pub source: Option<String>,
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub interval_minutes: Option<i64>,
+}
+
+/// A project's log retention and how much it has stored
+#[derive(Debug, Serialize)]
+pub struct LogRetentionResponse {
+    pub project_id: String,
+    /// Days entries are kept; 0 means no limit
+    pub max_age_days: i64,
+    /// Megabytes of entries kept; 0 means no limit
+    pub max_size_mb: i64,
+    /// The project's own limits, where they override the server defaults
+    pub project_retention: LogRetention,
+    pub stored_entries: i64,
+    pub stored_bytes: i64,
+    /// Start of the oldest hour still stored
+    pub oldest_partition: Option<DateTime<Utc>>,
+}
+
+/// Search a project's stored streaming logs, newest first
+pub async fn search_logs(
+    Path(project_id): Path<Uuid>,
+    Query(query): Query<LogSearchQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<LogSearchResponse>, AppError> {
+    let limit = query.limit.unwrap_or(100).clamp(1, MAX_SEARCH_RESULTS);
+    let min_severity = query
+        .level
+        .as_deref()
+        .map(|level| parse_level(level).ok_or_else(|| AppError::bad_request(format!("Unknown level '{}'", level))))
+        .transpose()?
+        .map(|level| level as i32);
+    let before = query
+        .cursor
+        .as_deref()
+        .map(|cursor| store::decode_cursor(cursor).ok_or_else(|| AppError::bad_request("Invalid cursor")))
+        .transpose()?;
+
+    let stored = state
+        .db
+        .storage()
+        .search_streaming_logs(&StreamingLogQuery {
+            project_id: project_id.to_string(),
+            source: query.source,
+            min_severity,
+            text: query.q.filter(|text| !text.trim().is_empty()),
+            since: parse_time("since", query.since.as_deref())?,
+            until: parse_time("until", query.until.as_deref())?,
+            before,
+            limit,
+        })
+        .await?;
+
+    let next_cursor = (stored.len() as i64 == limit)
+        .then(|| stored.last())
+        .flatten()
+        .map(|last| store::encode_cursor(LogCursor { timestamp: last.timestamp, seq: last.seq }));
+    Ok(Json(LogSearchResponse {
+        entries: stored.into_iter().map(store::from_stored).collect(),
+        next_cursor,
+    }))
+}
+
+/// Count a project's stored streaming logs by level over fixed intervals, oldest first
+pub async fn get_log_counts(
+    Path(project_id): Path<Uuid>,
+    Query(query): Query<LogCountsQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<CountBucket>>, AppError> {
+    let until = parse_time("until", query.until.as_deref())?.unwrap_or_else(Utc::now);
+    let since = parse_time("since", query.since.as_deref())?.unwrap_or(until - chrono::Duration::hours(1));
+    let interval = query.interval_minutes.unwrap_or(1);
+    if !(1..=1440).contains(&interval) {
+        return Err(AppError::bad_request("interval_minutes must be between 1 and 1440"));
+    }
+    if since >= until {
+        return Err(AppError::bad_request("'since' must be before 'until'"));
+    }
+    if (until - since).num_minutes() / interval >= MAX_COUNT_BUCKETS {
+        return Err(AppError::bad_request(format!(
+            "At most {} intervals can be counted at once",
+            MAX_COUNT_BUCKETS
+        )));
+    }
+
+    let interval = chrono::Duration::minutes(interval);
+    let counts = state
+        .db
+        .storage()
+        .count_streaming_logs_by_minute(&project_id.to_string(), query.source.as_deref(), since, until)
+        .await?;
+    Ok(Json(store::bucket_counts(&counts, since, until, interval)))
+}
+
+/// The project's log retention and stored entries
+pub async fn get_log_retention(
+    Path(project_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<LogRetentionResponse>, AppError> {
+    log_retention(&state, &project_id.to_string()).await.map(Json)
+}
+
+/// Override the server's log retention for one project
+pub async fn update_log_retention(
+    Path(project_id): Path<Uuid>,
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Json(retention): Json<LogRetention>,
+) -> Result<Json<LogRetentionResponse>, AppError> {
+    if retention.max_age_days.is_some_and(|days| days < 0) || retention.max_size_mb.is_some_and(|mb| mb < 0) {
+        return Err(AppError::validation("max_age_days and max_size_mb must be 0 or more"));
+    }
+
+    let project_id = project_id.to_string();
+    let updated = state.db.storage().set_project_log_retention(&project_id, &retention).await?;
+    if !updated {
+        return Err(AppError::not_found(format!("Project {} not found", project_id)));
+    }
+
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("project.log_retention.set")
+            .with_project(project_id.clone())
+            .with_details(serde_json::json!(retention)),
+    )
+    .await;
+
+    tracing::info!("Set log retention of project {} to {:?}", project_id, retention);
+    log_retention(&state, &project_id).await.map(Json)
+}
+
+async fn log_retention(state: &AppState, project_id: &str) -> Result<LogRetentionResponse, AppError> {
+    let storage = state.db.storage();
+    let project_retention = storage
+        .get_project_log_retention(project_id)
+        .await?
+        .ok_or_else(|| AppError::not_found(format!("Project {} not found", project_id)))?;
+    let retention = Retention::for_project(project_retention, Retention::from_config(&state.config));
+    let partitions: Vec<_> = storage
+        .list_streaming_log_partitions()
+        .await?
+        .into_iter()
+        .filter(|partition| partition.project_id == project_id)
+        .collect();
+
+    Ok(LogRetentionResponse {
+        project_id: project_id.to_string(),
+        max_age_days: retention.max_age.map_or(0, |max_age| max_age.num_days()),
+        max_size_mb: retention.max_bytes.map_or(0, |max_bytes| max_bytes / (1024 * 1024)),
+        project_retention,
+        stored_entries: partitions.iter().map(|partition| partition.entries).sum(),
+        stored_bytes: partitions.iter().map(|partition| partition.size_bytes).sum(),
+        oldest_partition: partitions.first().map(|partition| partition.partition_start),
+    })
+}
+
+/// Whether the source would act with the server's own access, which project admins are not
+/// given; this covers the Docker and Kubernetes options naming the server's sockets and files,
+/// the ports syslog, Fluent Forward and TCP sources bind and the syslog TLS key and certificate
+fn needs_server_admin(source_type: &str) -> bool {
+    HOST_SOURCE_TYPES.contains(&source_type)
+}
+
+/// Parse an optional RFC 3339 query parameter
+fn parse_time(name: &str, value: Option<&str>) -> Result<Option<DateTime<Utc>>, AppError> {
+    value
+        .map(|value| {
+            DateTime::parse_from_rfc3339(value)
+                .map(|time| time.with_timezone(&Utc))
+                .map_err(|_| AppError::bad_request(format!("'{}' must be an RFC 3339 timestamp", name)))
+        })
+        .transpose()
 }
 
 // Helper functions for parsing source configurations
 
-/// Parse source type from request
-fn parse_source_type(source_type: &str, config: &serde_json::Value) -> Result<StreamingSourceType, AppError> {
+/// Parse source type from request; HTTP endpoint sources take the digest of their token
+fn parse_source_type(
+    source_type: &str,
+    config: &serde_json::Value,
+    token_hash: Option<String>,
+) -> Result<StreamingSourceType, AppError> {
     match source_type {
         "file" => {
-            let path = config.get("path")
-                .and_then(|v| v.as_str())
-                .ok_or_else(|| AppError::bad_request("Missing 'path' for file source"))?;
-            Ok(StreamingSourceType::File { path: PathBuf::from(path) })
+            let tail_config = FileTailConfig::from_json(config).map_err(AppError::bad_request)?;
+            Ok(StreamingSourceType::File(tail_config))
         }
         "command" => {
             let command = config.get("command")
@@ -350,12 +758,55 @@ fn parse_source_type(source_type: &str, config: &serde_json::Value) -> Result<St
             let path = config.get("path")
                 .and_then(|v| v.as_str())
                 .ok_or_else(|| AppError::bad_request("Missing 'path' for HTTP source"))?;
-            Ok(StreamingSourceType::HttpEndpoint { path: path.to_string() })
+            let token_hash = token_hash.ok_or_else(|| AppError::internal("HTTP source has no ingest token"))?;
+            Ok(StreamingSourceType::HttpEndpoint { path: path.to_string(), token_hash })
+        }
+        "otlp" => {
+            let path = config.get("path")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| AppError::bad_request("Missing 'path' for OTLP source"))?;
+            let token_hash = token_hash.ok_or_else(|| AppError::internal("OTLP source has no ingest token"))?;
+            Ok(StreamingSourceType::Otlp { path: path.to_string(), token_hash })
+        }
+        "syslog" => {
+            let syslog_config = SyslogConfig::from_json(config).map_err(AppError::bad_request)?;
+            if let Some(tls) = &syslog_config.tls {
+                syslog::tls_acceptor(tls).map_err(|e| AppError::bad_request(e.to_string()))?;
+            }
+            Ok(StreamingSourceType::Syslog(syslog_config))
+        }
+        "fluent_forward" => {
+            let forward_config = ForwardConfig::from_json(config).map_err(AppError::bad_request)?;
+            Ok(StreamingSourceType::FluentForward(forward_config))
+        }
+        "docker" => {
+            let docker_config = DockerConfig::from_json(config).map_err(AppError::bad_request)?;
+            Ok(StreamingSourceType::Docker(docker_config))
+        }
+        "kubernetes" => {
+            let kubernetes_config = KubernetesConfig::from_json(config).map_err(AppError::bad_request)?;
+            KubernetesRuntime::new(kubernetes_config.clone()).map_err(|e| AppError::bad_request(e.to_string()))?;
+            Ok(StreamingSourceType::Kubernetes(kubernetes_config))
         }
         _ => Err(AppError::bad_request(format!("Unknown source type: {}", source_type)))
     }
 }
 
+/// Ingest paths are one URL segment of letters, digits, `.`, `_` and `-`
+fn validate_ingest_path(path: &str) -> Result<(), AppError> {
+    let valid = !path.is_empty()
+        && path.len() <= MAX_INGEST_PATH_LEN
+        && !path.starts_with('.')
+        && path.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+    if !valid {
+        return Err(AppError::bad_request(format!(
+            "Ingest path must be up to {} letters, digits, '.', '_' or '-', not starting with '.'",
+            MAX_INGEST_PATH_LEN
+        )));
+    }
+    Ok(())
+}
+
 /// Parse parser config from request
 fn parse_parser_config(req: Option<ParserConfigRequest>) -> ParserConfig {
     let req = match req {
@@ -379,12 +830,27 @@ fn parse_parser_config(req: Option<ParserConfigRequest>) -> ParserConfig {
     }
 }
 
+/// A stored source as listed to clients, without its ingest URL or token
+fn source_response(source: StreamingSource) -> Option<StreamingSourceResponse> {
+    Some(StreamingSourceResponse {
+        source_id: source.id,
+        name: source.name,
+        source_type: source.source_type,
+        project_id: Uuid::parse_str(&source.project_id).ok()?,
+        status: source.status,
+        created_at: source.created_at,
+        ingest_url: None,
+        ingest_token: None,
+    })
+}
+
 /// Persist streaming source configuration to database
 async fn persist_source_config(
     state: &AppState,
     source_id: &str,
     project_id: Uuid,
     request: &CreateStreamingSourceRequest,
+    ingest_token_hash: Option<&str>,
 ) -> Result<(), AppError> {
     let config_json = serde_json::to_string(&request.config)
         .map_err(|e| AppError::internal(format!("Failed to serialize config: {}", e)))?;
@@ -395,48 +861,43 @@ async fn persist_source_config(
         .map_err(|e| AppError::internal(format!("Failed to serialize parser config: {}", e)))?;
 
     let now = chrono::Utc::now().to_rfc3339();
+    let source = StreamingSource {
+        id: source_id.to_string(),
+        project_id: project_id.to_string(),
+        name: request.name.clone(),
+        source_type: request.source_type.clone(),
+        config: config_json,
+        parser_config: parser_config_json,
+        buffer_size: request.buffer_size.map(|s| s as i32),
+        batch_timeout_seconds: request.batch_timeout_seconds.map(|s| s as i32),
+        restart_on_error: request.restart_on_error,
+        max_restarts: request.max_restarts.map(|m| m as i32),
+        ingest_token_hash: ingest_token_hash.map(String::from),
+        status: "active".to_string(),
+        created_at: now.clone(),
+        updated_at: now,
+    };
 
-    sqlx::query(
-        "INSERT INTO streaming_sources
-         (id, project_id, name, source_type, config, parser_config,
-          buffer_size, batch_timeout_seconds, restart_on_error, max_restarts,
-          status, created_at, updated_at)
-         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, 'active', ?, ?)"
-    )
-    .bind(source_id)
-    .bind(project_id.to_string())
-    .bind(&request.name)
-    .bind(&request.source_type)
-    .bind(&config_json)
-    .bind(parser_config_json.as_ref())
-    .bind(request.buffer_size.map(|s| s as i64))
-    .bind(request.batch_timeout_seconds.map(|s| s as i64))
-    .bind(request.restart_on_error)
-    .bind(request.max_restarts.map(|m| m as i64))
-    .bind(&now)
-    .bind(&now)
-    .execute(state.db.pool())
-    .await
-    .map_err(|e| {
+    state.db.storage().create_streaming_source(&source).await.map_err(|e| {
         tracing::error!("Failed to persist streaming source {}: {}", source_id, e);
-        AppError::Database(e)
+        AppError::from(e)
     })?;
 
     tracing::info!("Persisted streaming source {} to database", source_id);
     Ok(())
 }
 
-/// Delete streaming source configuration from database
-async fn delete_source_config(state: &AppState, source_id: &str) -> Result<(), AppError> {
-    sqlx::query("DELETE FROM streaming_sources WHERE id = ?")
-        .bind(source_id)
-        .execute(state.db.pool())
-        .await
-        .map_err(|e| {
-            tracing::error!("Failed to delete streaming source {}: {}", source_id, e);
-            AppError::Database(e)
-        })?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    tracing::info!("Deleted streaming source {} from database", source_id);
-    Ok(())
-}
\ No newline at end of file
+    #[test]
+    fn test_host_sources_need_server_admin() {
+        for source_type in ["command", "file", "docker", "kubernetes", "syslog", "fluent_forward", "tcp", "stdin"] {
+            assert!(needs_server_admin(source_type), "{}", source_type);
+        }
+        for source_type in ["http", "otlp"] {
+            assert!(!needs_server_admin(source_type), "{}", source_type);
+        }
+    }
+}
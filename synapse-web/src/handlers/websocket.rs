@@ -6,52 +6,90 @@ use axum::{
 use futures::{sink::SinkExt, stream::StreamExt};
 use synapse_core::{
     analyzer::Analyzer, create_provider_with_model, filter_logs_by_level, parse_log_lines, slim_logs,
+    project::{AnalysisJob, JobEvent, JobProgress, JobStatus},
     AnalysisResponse,
 };
 use serde::{Deserialize, Serialize};
 use std::time::Instant;
 use tokio::sync::broadcast;
-use uuid::Uuid;
 use serde_json::json;
 use tokio::time::Duration;
-
-use crate::{error_handling::AppError, models::*, AppState};
+use tracing::Instrument;
+
+use crate::{
+    audit,
+    error_handling::AppError,
+    jobs::{self, JobPayload, LIVE_PRIORITY},
+    middleware::{auth::CurrentUser, rate_limit::check_analysis_quota},
+    models::*,
+    validation::Validator,
+    AppState,
+};
 
 /// WebSocket handler for real-time log analysis
-/// Provides live progress updates, cancellation support, and streaming results
+/// Queues the analysis ahead of others and relays its progress, cancellation and result
 pub async fn websocket_analysis_handler(
     ws: WebSocketUpgrade,
     State(state): State<AppState>,
+    current_user: CurrentUser,
     Path((project_id, file_id)): Path<(String, String)>,
     Query(params): Query<AnalysisWebSocketParams>,
 ) -> Result<Response, AppError> {
+    let (provider, level, user_context) =
+        Validator::validate_analysis_request(&params.provider, &params.level, params.user_context.as_ref())?;
+
     // Validate project and file exist
-    let _project = sqlx::query_as::<_, Project>(
-        "SELECT id, name, description, created_at, updated_at FROM projects WHERE id = ?",
-    )
-    .bind(&project_id)
-    .fetch_optional(state.db.pool())
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to fetch project {}: {}", project_id, e);
-        AppError::Database(e)
-    })?
-    .ok_or_else(|| AppError::not_found(format!("Project {} not found", project_id)))?;
-
-    let log_file = sqlx::query_as::<_, LogFile>(
-        "SELECT id, project_id, filename, file_size, created_at FROM log_files WHERE id = ? AND project_id = ?",
+    let log_file = state
+        .db
+        .storage()
+        .get_log_file(&project_id, &file_id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to fetch log file {}: {}", file_id, e);
+            AppError::from(e)
+        })?
+        .ok_or_else(|| AppError::not_found(format!("Log file {} not found", file_id)))?;
+
+    check_analysis_quota(&state, &project_id).await?;
+
+    let analysis = Analysis::new(project_id, Some(log_file.id), "file".to_string(), provider, level)
+        .with_created_by(current_user.user_id());
+    state.db.storage().create_analysis(&analysis).await?;
+
+    let model = match &params.model {
+        Some(model) => Some(model.clone()),
+        None => state.db.storage().get_settings().await?.and_then(|settings| settings.selected_model),
+    };
+    audit::record(
+        &state.db,
+        current_user
+            .audit_event("analysis.start")
+            .with_project(analysis.project_id.clone())
+            .with_target("analysis", analysis.id.clone())
+            .with_details(json!({
+                "provider": analysis.provider,
+                "model": model,
+                "level": analysis.level_filter,
+                "log_file_id": analysis.log_file_id,
+                "live": true,
+            })),
     )
-    .bind(&file_id)
-    .bind(&project_id)
-    .fetch_optional(state.db.pool())
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to fetch log file {}: {}", file_id, e);
-        AppError::Database(e)
-    })?
-    .ok_or_else(|| AppError::not_found(format!("Log file {} not found", file_id)))?;
-
-    Ok(ws.on_upgrade(move |socket| websocket_analysis_task(socket, state, log_file, params)))
+    .await;
+
+    // Subscribe before queueing so no progress is missed
+    let events = state.jobs.subscribe();
+    let payload = JobPayload {
+        user_context,
+        model: params.model.clone(),
+        live: true,
+        ..Default::default()
+    };
+    let timeout_secs = super::analysis::default_timeout_secs(&state).await?;
+    let job = jobs::queue_analysis(&state, &analysis, payload, params.api_key.as_deref(), LIVE_PRIORITY, timeout_secs)
+        .await
+        .map_err(|e| AppError::internal(format!("Failed to queue analysis: {}", e)))?;
+
+    Ok(ws.on_upgrade(move |socket| websocket_analysis_task(socket, state, job, events)))
 }
 
 #[derive(Debug, Deserialize)]
@@ -92,7 +130,7 @@ pub enum WebSocketMessage {
     Heartbeat { timestamp: u64 },
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct AnalysisStats {
     pub total_lines: usize,
     pub parsed_entries: usize,
@@ -105,266 +143,184 @@ pub struct AnalysisStats {
 async fn websocket_analysis_task(
     socket: WebSocket,
     state: AppState,
-    log_file: LogFile,
-    params: AnalysisWebSocketParams,
+    job: AnalysisJob,
+    mut events: broadcast::Receiver<JobEvent>,
 ) {
+    let _connection = state.metrics_collector.track_websocket("analysis");
     let start_time = Instant::now();
+    let elapsed_ms = || start_time.elapsed().as_millis() as u64;
     let (mut sender, mut receiver) = socket.split();
-    let analysis_id = Uuid::new_v4().to_string();
+    let mut cancel_reason = None;
 
-    // Send initial progress
-    let _ = send_progress_split(
+    let _ = send_message(
         &mut sender,
-        "starting",
-        0.0,
-        "Initializing analysis",
-        start_time.elapsed().as_millis() as u64,
+        &WebSocketMessage::Progress {
+            stage: "queued".to_string(),
+            progress: 0.0,
+            message: "Waiting for an analysis worker".to_string(),
+            elapsed_ms: elapsed_ms(),
+        },
     )
     .await;
 
-    // Create cancellation channel
-    let (cancel_tx, mut cancel_rx) = broadcast::channel(1);
-
-    // Handle incoming messages for cancellation
-    let cancel_tx_clone = cancel_tx.clone();
-    tokio::spawn(async move {
-        while let Some(msg) = receiver.next().await {
-            match msg {
-                Ok(Message::Text(text)) => {
-                    if text == "cancel" {
-                        let _ = cancel_tx_clone.send("user_requested".to_string());
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                let event = match event {
+                    Ok(event) if event.job_id() == job.id => event,
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+
+                match event {
+                    JobEvent::Progress { stage, progress, message, .. } => {
+                        let progress_msg = WebSocketMessage::Progress { stage, progress, message, elapsed_ms: elapsed_ms() };
+                        let _ = send_message(&mut sender, &progress_msg).await;
+                    }
+                    JobEvent::Finished { status, error, details, .. } => {
+                        let outcome = match status {
+                            JobStatus::Completed => completed_message(&state, &job, details, elapsed_ms()).await,
+                            JobStatus::Cancelled => WebSocketMessage::Cancelled {
+                                reason: cancel_reason.unwrap_or("cancelled").to_string(),
+                                elapsed_ms: elapsed_ms(),
+                            },
+                            _ => WebSocketMessage::Error {
+                                error: error.unwrap_or_else(|| "Analysis failed".to_string()),
+                                stage: "analysis".to_string(),
+                                elapsed_ms: elapsed_ms(),
+                            },
+                        };
+                        let _ = send_message(&mut sender, &outcome).await;
                         break;
                     }
                 }
-                Ok(Message::Close(_)) => {
-                    let _ = cancel_tx_clone.send("connection_closed".to_string());
+            }
+            msg = receiver.next() => {
+                let reason = match msg {
+                    Some(Ok(Message::Text(text))) if text == "cancel" => "user_requested",
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => "connection_closed",
+                    _ => continue,
+                };
+                cancel_reason = Some(reason);
+                if let Err(e) = state.jobs.cancel(&job).await {
+                    tracing::error!("Failed to cancel job {}: {}", job.id, e);
+                }
+                if reason == "connection_closed" {
                     break;
                 }
-                _ => {}
             }
         }
-    });
-
-    // Start analysis
-    let analysis_result = perform_streaming_analysis_split(
-        &state,
-        &log_file,
-        &params,
-        &analysis_id,
-        start_time,
-        &mut sender,
-        &mut cancel_rx,
-    )
-    .await;
-
-    match analysis_result {
-        Ok(analysis_data) => {
-            // Store analysis result in database first
-            let _ = store_analysis_result(&state, &log_file, &analysis_id, &analysis_data).await;
-
-            let complete_msg = WebSocketMessage::Complete {
-                analysis: analysis_data.analysis,
-                analysis_id: analysis_id.clone(),
-                elapsed_ms: start_time.elapsed().as_millis() as u64,
-                stats: analysis_data.stats,
-            };
-
-            let _ = sender
-                .send(Message::Text(serde_json::to_string(&complete_msg).unwrap()))
-                .await;
-        }
-        Err(error) => {
-            let error_msg = WebSocketMessage::Error {
-                error: error.to_string(),
-                stage: "analysis".to_string(),
-                elapsed_ms: start_time.elapsed().as_millis() as u64,
-            };
-
-            let _ = sender
-                .send(Message::Text(serde_json::to_string(&error_msg).unwrap()))
-                .await;
-        }
     }
 
     let _ = sender.send(Message::Close(None)).await;
 }
 
-struct AnalysisData {
-    analysis: AnalysisResponse,
-    stats: AnalysisStats,
+/// Build the completion message from the stored result and the pipeline statistics
+async fn completed_message(
+    state: &AppState,
+    job: &AnalysisJob,
+    details: Option<serde_json::Value>,
+    elapsed_ms: u64,
+) -> WebSocketMessage {
+    let result = async {
+        let analysis = state
+            .db
+            .storage()
+            .get_analysis(&job.analysis_id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Analysis {} no longer exists", job.analysis_id))?;
+        let response: AnalysisResponse = serde_json::from_str(analysis.result.as_deref().unwrap_or("null"))?;
+        let stats: AnalysisStats = serde_json::from_value(details.unwrap_or_default())?;
+        anyhow::Ok((response, stats))
+    }
+    .await;
+
+    match result {
+        Ok((analysis, stats)) => WebSocketMessage::Complete {
+            analysis,
+            analysis_id: job.analysis_id.clone(),
+            elapsed_ms,
+            stats,
+        },
+        Err(e) => WebSocketMessage::Error {
+            error: format!("Failed to read the analysis result: {}", e),
+            stage: "finalizing".to_string(),
+            elapsed_ms,
+        },
+    }
 }
 
-async fn perform_streaming_analysis_split(
-    state: &AppState,
+/// Run the analysis pipeline stage by stage, reporting progress along the way
+///
+/// Used by the job queue for live analyses; the caller stores the result.
+pub async fn run_live_analysis(
     log_file: &LogFile,
-    params: &AnalysisWebSocketParams,
-    _analysis_id: &str,
-    start_time: Instant,
-    sender: &mut futures::stream::SplitSink<WebSocket, Message>,
-    cancel_rx: &mut broadcast::Receiver<String>,
-) -> Result<AnalysisData, anyhow::Error> {
-    // Check for cancellation
-    if cancel_rx.try_recv().is_ok() {
-        send_cancelled_split(sender, "cancelled_before_start".to_string(), start_time).await;
-        return Err(anyhow::anyhow!("Analysis cancelled"));
-    }
+    level: &str,
+    provider: &str,
+    api_key: Option<String>,
+    model: Option<String>,
+    progress: &JobProgress,
+) -> Result<(AnalysisResponse, AnalysisStats), anyhow::Error> {
+    let start_time = Instant::now();
 
     // Step 1: Read log file
-    {
-        let _ = send_progress_split(
-            sender,
-            "reading_file",
-            0.1,
-            &format!("Reading log file: {}", log_file.filename),
-            start_time.elapsed().as_millis() as u64,
-        )
-        .await;
-    }
+    progress.report("reading_file", 0.1, format!("Reading log file: {}", log_file.filename));
 
     let file_path = &log_file.upload_path;
-    let raw_lines = match tokio::fs::read_to_string(&file_path).await {
-        Ok(content) => content.lines().map(String::from).collect::<Vec<_>>(),
-        Err(_) => {
-            // Try reading from database if file not found
-            sqlx::query_scalar::<_, String>(
-                "SELECT content FROM log_file_content WHERE file_id = ?",
-            )
-            .bind(&log_file.id)
-            .fetch_one(state.db.pool())
-            .await
-            .map_err(|e| anyhow::anyhow!("Failed to read log file: {}", e))?
-            .lines()
-            .map(String::from)
-            .collect()
-        }
-    };
-
-    // Check for cancellation
-    if cancel_rx.try_recv().is_ok() {
-        send_cancelled_split(sender, "cancelled_after_reading".to_string(), start_time).await;
-        return Err(anyhow::anyhow!("Analysis cancelled"));
-    }
+    let read_span = tracing::info_span!("file.read", bytes = log_file.file_size);
+    let raw_lines = tokio::fs::read_to_string(&file_path)
+        .instrument(read_span)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to read log file: {}", e))?
+        .lines()
+        .map(String::from)
+        .collect::<Vec<_>>();
 
     // Step 2: Parse logs
-    {
-        let _ = send_progress_split(
-            sender,
-            "parsing",
-            0.2,
-            &format!("Parsing {} log lines", raw_lines.len()),
-            start_time.elapsed().as_millis() as u64,
-        )
-        .await;
-    }
+    progress.report("parsing", 0.2, format!("Parsing {} log lines", raw_lines.len()));
 
-    let parsed_entries = parse_log_lines(&raw_lines);
+    let parsed_entries = tracing::info_span!("logs.parse", lines = raw_lines.len())
+        .in_scope(|| parse_log_lines(&raw_lines));
 
     // Step 3: Filter logs
-    {
-        let _ = send_progress_split(
-            sender,
-            "filtering",
-            0.3,
-            &format!("Filtering by {} level", params.level),
-            start_time.elapsed().as_millis() as u64,
-        )
-        .await;
-    }
+    progress.report("filtering", 0.3, format!("Filtering by {} level", level));
 
-    let filtered_entries = filter_logs_by_level(parsed_entries.clone(), &params.level)?;
+    let filtered_entries = tracing::info_span!("logs.filter", level, entries = parsed_entries.len())
+        .in_scope(|| filter_logs_by_level(parsed_entries.clone(), level))?;
 
     if filtered_entries.is_empty() {
         return Err(anyhow::anyhow!(
             "No log entries found matching level: {}",
-            params.level
+            level
         ));
     }
 
-    // Check for cancellation
-    if cancel_rx.try_recv().is_ok() {
-        send_cancelled_split(sender, "cancelled_after_filtering".to_string(), start_time).await;
-        return Err(anyhow::anyhow!("Analysis cancelled"));
-    }
-
     // Step 4: Slim logs
-    {
-        let _ = send_progress_split(
-            sender,
-            "slimming",
-            0.4,
-            &format!(
-                "Optimizing {} entries for AI analysis",
-                filtered_entries.len()
-            ),
-            start_time.elapsed().as_millis() as u64,
-        )
-        .await;
-    }
+    progress.report(
+        "slimming",
+        0.4,
+        format!("Optimizing {} entries for AI analysis", filtered_entries.len()),
+    );
 
-    let slimmed_entries = slim_logs(filtered_entries.clone());
+    let slimmed_entries = tracing::info_span!("logs.slim", entries = filtered_entries.len())
+        .in_scope(|| slim_logs(filtered_entries.clone()));
 
     // Step 5: AI Analysis
-    {
-        let _ = send_progress_split(
-            sender,
-            "ai_analysis",
-            0.5,
-            &format!("Analyzing with {} provider", params.provider),
-            start_time.elapsed().as_millis() as u64,
-        )
-        .await;
-    }
+    progress.report("ai_analysis", 0.5, format!("Analyzing with {} provider", provider));
 
     let ai_start_time = Instant::now();
-
-    // Get API key and model from params or database settings
-    let (api_key, model) = match (&params.api_key, &params.model) {
-        (Some(key), Some(model)) => (key.clone(), Some(model.clone())),
-        _ => {
-            // Fetch from database settings
-            match sqlx::query!("SELECT api_key, selected_model FROM settings LIMIT 1")
-                .fetch_optional(state.db.pool())
-                .await
-            {
-                Ok(Some(settings)) if !settings.api_key.is_empty() => {
-                    let api_key = params.api_key.clone().unwrap_or(settings.api_key);
-                    let model = params.model.clone().or(settings.selected_model);
-                    (api_key, model)
-                },
-                _ => {
-                    let api_key = params.api_key.clone()
-                        .ok_or_else(|| anyhow::anyhow!("API key required for provider {}", params.provider))?;
-                    (api_key, params.model.clone())
-                }
-            }
-        }
-    };
+    let api_key = api_key.ok_or_else(|| anyhow::anyhow!("API key required for provider {}", provider))?;
 
     // Create provider and analyzer
-    let provider = create_provider_with_model(&params.provider, &api_key, model)?;
-    let mut analyzer = Analyzer::new(provider);
-
-    // Check for cancellation before expensive AI call
-    if cancel_rx.try_recv().is_ok() {
-        send_cancelled_split(sender, "cancelled_before_ai".to_string(), start_time).await;
-        return Err(anyhow::anyhow!("Analysis cancelled"));
-    }
+    let ai_provider = create_provider_with_model(provider, &api_key, model)?;
+    let mut analyzer = Analyzer::new(ai_provider);
 
     // Perform AI analysis
     let analysis = analyzer.analyze_logs(slimmed_entries.clone()).await?;
     let ai_analysis_time = ai_start_time.elapsed().as_millis() as u64;
 
     // Step 6: Finalization
-    {
-        let _ = send_progress_split(
-            sender,
-            "finalizing",
-            0.9,
-            "Finalizing analysis results",
-            start_time.elapsed().as_millis() as u64,
-        )
-        .await;
-    }
+    progress.report("finalizing", 0.9, "Finalizing analysis results");
 
     let stats = AnalysisStats {
         total_lines: raw_lines.len(),
@@ -375,73 +331,17 @@ async fn perform_streaming_analysis_split(
         ai_analysis_time_ms: ai_analysis_time,
     };
 
-    Ok(AnalysisData { analysis, stats })
+    Ok((analysis, stats))
 }
 
-async fn send_progress_split(
+async fn send_message(
     sender: &mut futures::stream::SplitSink<WebSocket, Message>,
-    stage: &str,
-    progress: f32,
-    message: &str,
-    elapsed_ms: u64,
+    message: &WebSocketMessage,
 ) -> Result<(), anyhow::Error> {
-    let progress_msg = WebSocketMessage::Progress {
-        stage: stage.to_string(),
-        progress,
-        message: message.to_string(),
-        elapsed_ms,
-    };
-
     sender
-        .send(Message::Text(serde_json::to_string(&progress_msg)?))
+        .send(Message::Text(serde_json::to_string(message)?))
         .await
-        .map_err(|e| anyhow::anyhow!("Failed to send progress: {}", e))?;
-
-    Ok(())
-}
-
-async fn send_cancelled_split(
-    sender: &mut futures::stream::SplitSink<WebSocket, Message>,
-    reason: String,
-    start_time: Instant,
-) {
-    let cancel_msg = WebSocketMessage::Cancelled {
-        reason,
-        elapsed_ms: start_time.elapsed().as_millis() as u64,
-    };
-
-    let _ = sender
-        .send(Message::Text(serde_json::to_string(&cancel_msg).unwrap()))
-        .await;
-}
-
-async fn store_analysis_result(
-    state: &AppState,
-    log_file: &LogFile,
-    analysis_id: &str,
-    analysis_data: &AnalysisData,
-) -> Result<(), anyhow::Error> {
-    let analysis_json = serde_json::to_string(&analysis_data.analysis)?;
-    let _stats_json = serde_json::to_string(&analysis_data.stats)?;
-
-    sqlx::query!(
-        r#"
-        INSERT INTO analyses (
-            id, project_id, log_file_id,
-            analysis_type, provider, level_filter,
-            status, result, error_message,
-            started_at, completed_at
-        ) VALUES (?, ?, ?, 'file', ?, ?, 'completed', ?, NULL, datetime('now'), datetime('now'))
-        "#,
-        analysis_id,
-        log_file.project_id,
-        log_file.id,
-        "synapse", // provider
-        "INFO",    // level_filter
-        analysis_json
-    )
-    .execute(state.db.pool())
-    .await?;
+        .map_err(|e| anyhow::anyhow!("Failed to send message: {}", e))?;
 
     Ok(())
 }
@@ -453,22 +353,18 @@ pub async fn websocket_monitor_handler(
     Path(analysis_id): Path<String>,
 ) -> Result<Response, AppError> {
     // Validate analysis exists
-    let _analysis = sqlx::query_as::<_, Analysis>(
-        "SELECT id, project_id, log_file_id, status, created_at FROM analyses WHERE id = ?",
-    )
-    .bind(&analysis_id)
-    .fetch_optional(state.db.pool())
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to fetch analysis {}: {}", analysis_id, e);
-        AppError::Database(e)
-    })?
-    .ok_or_else(|| AppError::not_found(format!("Analysis {} not found", analysis_id)))?;
+    state
+        .db
+        .storage()
+        .get_analysis(&analysis_id)
+        .await?
+        .ok_or_else(|| AppError::not_found(format!("Analysis {} not found", analysis_id)))?;
 
     Ok(ws.on_upgrade(move |socket| websocket_monitor_task(socket, state, analysis_id)))
 }
 
 async fn websocket_monitor_task(mut socket: WebSocket, state: AppState, analysis_id: String) {
+    let _connection = state.metrics_collector.track_websocket("monitor");
     let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(1));
 
     loop {
@@ -521,12 +417,7 @@ async fn check_analysis_status(
     state: &AppState,
     analysis_id: &str,
 ) -> Result<Option<String>, anyhow::Error> {
-    let analysis = sqlx::query_as::<_, Analysis>(
-        "SELECT id, project_id, log_file_id, status, created_at FROM analyses WHERE id = ?",
-    )
-    .bind(analysis_id)
-    .fetch_optional(state.db.pool())
-    .await?;
+    let analysis = state.db.storage().get_analysis(analysis_id).await?;
 
     match analysis {
         Some(analysis) => {
@@ -563,12 +454,14 @@ impl crate::config::WebConfig {
 // Status WebSocket handler for system status updates
 pub async fn status_ws_handler(
     ws: WebSocketUpgrade,
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
 ) -> Result<Response, AppError> {
-    Ok(ws.on_upgrade(handle_status_ws))
+    Ok(ws.on_upgrade(move |socket| handle_status_ws(socket, state)))
 }
 
-async fn handle_status_ws(mut socket: WebSocket) {
+async fn handle_status_ws(mut socket: WebSocket, state: AppState) {
+    let _connection = state.metrics_collector.track_websocket("status");
+
     // Send initial status
     let initial_msg = serde_json::to_string(&json!({
         "type": "system_status",
@@ -0,0 +1,257 @@
+// Runs the dashboard's analyses through the persistent job queue
+//
+// Uploaded-file analyses, live WebSocket analyses and the scheduler's realtime
+// analyses are queued in `analysis_jobs` and run by this server's workers, so they
+// are retried on failure and picked up again after a restart. The MCP server runs
+// its own queue.
+
+use std::{sync::Arc, time::Instant};
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use synapse_core::project::{
+    Analysis, AnalysisJob, JobHandler, JobOutput, JobProgress, JobQueue, JobQueueConfig, LogFile,
+};
+use synapse_core::secrets::Keyring;
+use tokio::sync::RwLock;
+use tracing::Span;
+
+use crate::{
+    circuit_breaker::CircuitBreakerRegistry,
+    config::WebConfig,
+    database::Database,
+    handlers,
+    middleware::metrics::{provider_error_kind, MetricsCollector},
+    telemetry, AppState,
+};
+
+/// Queue this server's workers run
+pub const WEB_QUEUE: &str = "web";
+
+/// Priority of analyses someone is watching over a WebSocket; requests may ask for
+/// priorities between -`MAX_PRIORITY` and `MAX_PRIORITY`
+pub const LIVE_PRIORITY: i32 = 100;
+pub const MAX_PRIORITY: i32 = 100;
+
+/// Options for one run of an analysis, stored with its job
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct JobPayload {
+    pub user_context: Option<String>,
+    /// Model to use instead of the one selected in settings
+    pub model: Option<String>,
+    /// API key supplied with the request, sealed with the master key
+    pub api_key: Option<String>,
+    /// Run the staged pipeline that reports progress, for live analyses
+    #[serde(default)]
+    pub live: bool,
+    /// W3C `traceparent` of the request that queued the analysis, when spans are exported
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub traceparent: Option<String>,
+    /// Streamed entries a realtime analysis reads instead of an uploaded log file
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lines: Vec<String>,
+}
+
+/// Start this server's workers, first recovering jobs interrupted by a restart
+pub fn start_job_queue(
+    db: &Database,
+    secrets: Arc<RwLock<Keyring>>,
+    circuit_breakers: Arc<CircuitBreakerRegistry>,
+    metrics: Arc<MetricsCollector>,
+    config: &WebConfig,
+) -> Arc<JobQueue> {
+    let handler = AnalysisJobHandler {
+        db: db.clone(),
+        secrets,
+        circuit_breakers,
+        metrics,
+    };
+    let queue = JobQueue::new(
+        db.shared_storage(),
+        Arc::new(handler),
+        JobQueueConfig::new(WEB_QUEUE, config.job_workers),
+    );
+    queue.start();
+    queue
+}
+
+/// Queue a run of an analysis; an API key supplied with the request is sealed to the job
+pub async fn queue_analysis(
+    state: &AppState,
+    analysis: &Analysis,
+    mut payload: JobPayload,
+    api_key: Option<&str>,
+    priority: i32,
+    timeout_secs: u64,
+) -> Result<AnalysisJob> {
+    let job = analysis_job(analysis, state.config.job_max_attempts, priority, timeout_secs);
+
+    if let Some(api_key) = api_key {
+        let keyring = state.secrets.read().await;
+        payload.api_key = Some(keyring.seal_to_string(&secret_context(&job.id), api_key)?);
+    }
+
+    payload.traceparent = telemetry::current_traceparent();
+    let job = job.with_payload(serde_json::to_string(&payload)?);
+    state.jobs.enqueue(job).await
+}
+
+/// A job running `analysis` on this server's queue, without its payload
+pub fn analysis_job(analysis: &Analysis, max_attempts: i32, priority: i32, timeout_secs: u64) -> AnalysisJob {
+    AnalysisJob::new(
+        analysis.id.clone(),
+        analysis.project_id.clone(),
+        WEB_QUEUE,
+        timeout_secs as i64,
+    )
+    .with_priority(priority)
+    .with_max_attempts(max_attempts)
+    .with_created_by(analysis.created_by.clone())
+}
+
+/// Sealed values are bound to their job, so they can't be replayed into another one
+fn secret_context(job_id: &str) -> String {
+    format!("job:{}", job_id)
+}
+
+struct AnalysisJobHandler {
+    db: Database,
+    secrets: Arc<RwLock<Keyring>>,
+    circuit_breakers: Arc<CircuitBreakerRegistry>,
+    metrics: Arc<MetricsCollector>,
+}
+
+impl AnalysisJobHandler {
+    /// The uploaded log file an analysis reads
+    async fn log_file(&self, analysis: &Analysis) -> Result<LogFile> {
+        let log_file_id = analysis
+            .log_file_id
+            .as_deref()
+            .ok_or_else(|| anyhow!("Analysis {} has no log file", analysis.id))?;
+        self.db
+            .storage()
+            .get_log_file(&analysis.project_id, log_file_id)
+            .await?
+            .ok_or_else(|| anyhow!("Log file {} was deleted", log_file_id))
+    }
+}
+
+#[async_trait]
+impl JobHandler for AnalysisJobHandler {
+    fn link_span(&self, job: &AnalysisJob, span: &Span) {
+        let traceparent = job
+            .payload
+            .as_deref()
+            .and_then(|payload| serde_json::from_str::<JobPayload>(payload).ok())
+            .and_then(|payload| payload.traceparent);
+        telemetry::set_parent(span, traceparent.as_deref());
+    }
+
+    async fn run(&self, job: &AnalysisJob, progress: &JobProgress) -> Result<JobOutput> {
+        let storage = self.db.storage();
+        let analysis = storage
+            .get_analysis(&job.analysis_id)
+            .await?
+            .ok_or_else(|| anyhow!("Analysis {} no longer exists", job.analysis_id))?;
+        let mut payload: JobPayload = match &job.payload {
+            Some(payload) => serde_json::from_str(payload).context("Invalid job payload")?,
+            None => JobPayload::default(),
+        };
+
+        // Fetch the provider's API key and the selected model from settings, unless the
+        // request supplied them
+        let api_key = match &payload.api_key {
+            Some(sealed) => Some(
+                self.secrets
+                    .read()
+                    .await
+                    .open_string(&secret_context(&job.id), sealed)
+                    .context("Failed to decrypt the API key queued with the analysis")?,
+            ),
+            None => handlers::settings::provider_api_key(&self.db, &self.secrets, &analysis.provider).await,
+        };
+        if api_key.is_none() {
+            tracing::warn!("No {} API key stored for analysis {}", analysis.provider, analysis.id);
+        }
+        let model = match payload.model {
+            Some(model) => Some(model),
+            None => storage.get_settings().await?.and_then(|settings| settings.selected_model),
+        };
+
+        let started = Instant::now();
+        let output = if !payload.lines.is_empty() {
+            let lines = std::mem::take(&mut payload.lines);
+            progress.report(
+                "analyzing",
+                0.1,
+                format!("Analyzing {} streamed entries with {}", lines.len(), analysis.provider),
+            );
+            handlers::analysis::perform_analysis_of_lines(
+                lines,
+                &analysis.level_filter,
+                &analysis.provider,
+                api_key.as_deref(),
+                &self.circuit_breakers,
+                job.timeout_secs as u64,
+                model.as_deref(),
+            )
+            .await
+            .and_then(|response| {
+                Ok(JobOutput {
+                    result_json: serde_json::to_string(&response)?,
+                    details: None,
+                })
+            })
+        } else if payload.live {
+            let log_file = self.log_file(&analysis).await?;
+            handlers::websocket::run_live_analysis(
+                &log_file,
+                &analysis.level_filter,
+                &analysis.provider,
+                api_key,
+                model.clone(),
+                progress,
+            )
+            .await
+            .and_then(|(response, stats)| {
+                Ok(JobOutput {
+                    result_json: serde_json::to_string(&response)?,
+                    details: Some(serde_json::to_value(&stats)?),
+                })
+            })
+        } else {
+            let log_file = self.log_file(&analysis).await?;
+            progress.report(
+                "analyzing",
+                0.1,
+                format!("Analyzing {} with {}", log_file.filename, analysis.provider),
+            );
+            handlers::analysis::perform_analysis_with_context(
+                &log_file.upload_path,
+                &analysis.level_filter,
+                &analysis.provider,
+                api_key.as_deref(),
+                &self.circuit_breakers,
+                job.timeout_secs as u64,
+                payload.user_context.as_deref(),
+                model.as_deref(),
+            )
+            .await
+            .and_then(|response| {
+                Ok(JobOutput {
+                    result_json: serde_json::to_string(&response)?,
+                    details: None,
+                })
+            })
+        };
+
+        self.metrics.record_analysis(
+            &analysis.provider,
+            model.as_deref().unwrap_or("default"),
+            started.elapsed(),
+            output.as_ref().map(|_| ()).map_err(provider_error_kind),
+        );
+        output
+    }
+}
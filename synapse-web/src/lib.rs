@@ -1,17 +1,23 @@
 // Synapse Web Backend Library
 // High-performance Rust web backend for intelligent log analysis
 
+pub mod alerts;
+pub mod audit;
 pub mod cache;
 pub mod circuit_breaker;
 pub mod config;
 pub mod database;
 pub mod error_handling;
 pub mod handlers;
+pub mod jobs;
 pub mod middleware;
 pub mod models;
+pub mod oidc;
 pub mod performance;
 pub mod routes;
+pub mod scheduler;
 pub mod streaming;
+pub mod telemetry;
 pub mod validation;
 
 // Re-export commonly used types and functions
@@ -36,9 +42,15 @@ pub struct AppState {
     pub circuit_breakers: Arc<CircuitBreakerRegistry>,
     pub cache_manager: Arc<CacheManager>,
     pub streaming_hub: Arc<crate::streaming::StreamingHub>,
+    pub alerts: Arc<alerts::AlertEngine>,
+    pub scheduler: Arc<scheduler::AnalysisScheduler>,
     pub streaming_manager: Arc<tokio::sync::RwLock<crate::streaming::sources::StreamingSourceManager>>,
     pub optimized_db: Arc<OptimizedDbOps>,
     pub metrics_collector: Arc<crate::middleware::metrics::MetricsCollector>,
+    pub oidc: Option<Arc<oidc::OidcClient>>,
+    pub secrets: Arc<tokio::sync::RwLock<synapse_core::secrets::Keyring>>,
+    pub jobs: Arc<synapse_core::project::JobQueue>,
+    pub rate_limiter: Arc<middleware::rate_limit::RateLimiter>,
 }
 
 impl AppState {
@@ -47,19 +59,63 @@ impl AppState {
         let db = Database::new(&config.database_url).await?;
         db.migrate().await?;
 
-        // Create performance indexes
-        if let Err(e) = performance::create_performance_indexes(db.pool()).await {
-            tracing::warn!("Failed to create performance indexes: {}", e);
+        // Create performance indexes (the PostgreSQL migrations already include them)
+        if let Ok(pool) = db.pool() {
+            if let Err(e) = performance::create_performance_indexes(pool).await {
+                tracing::warn!("Failed to create performance indexes: {}", e);
+            }
         }
 
+        let secrets = Arc::new(tokio::sync::RwLock::new(handlers::settings::load_secrets(&db).await?));
+
         // Initialize cache manager
         let cache_manager = Arc::new(CacheManager::new());
 
         // Initialize circuit breaker registry
         let circuit_breakers = Arc::new(CircuitBreakerRegistry::new());
 
-        // Initialize streaming hub
-        let streaming_hub = Arc::new(streaming::StreamingHub::new());
+        // Initialize metrics collector
+        let metrics_collector = Arc::new(middleware::metrics::MetricsCollector::new());
+        metrics_collector.clone().start_background_tasks();
+
+        // Fail analyses that stopped with the last run and have no job to finish them
+        if let Err(e) = synapse_core::project::fail_interrupted_analyses(db.storage()).await {
+            tracing::warn!("Failed to mark interrupted analyses as failed: {}", e);
+        }
+
+        // Start the analysis workers, resuming jobs interrupted by a restart
+        let jobs = jobs::start_job_queue(
+            &db,
+            secrets.clone(),
+            circuit_breakers.clone(),
+            metrics_collector.clone(),
+            &config,
+        );
+
+        // Initialize streaming hub, storing what it accepts and dropping entries past retention
+        let log_store = streaming::store::start(db.shared_storage(), &config);
+        let streaming_hub = Arc::new(streaming::StreamingHub::new().with_store(log_store));
+        if let Err(e) = streaming::store::restore_recent(db.storage(), &streaming_hub).await {
+            tracing::warn!("Failed to restore recent streaming logs: {}", e);
+        }
+
+        // Send alerts and finished analyses to notification channels, retrying failed deliveries
+        let notifications = alerts::NotificationManager::new(db.shared_storage(), secrets.clone(), &config);
+        notifications.start();
+        notifications.watch_jobs(&jobs);
+
+        // Run scheduled and alert-triggered analyses over recent streaming entries
+        let scheduler = scheduler::AnalysisScheduler::start(
+            db.shared_storage(),
+            jobs.clone(),
+            &streaming_hub,
+            &notifications,
+            &config,
+        )
+        .await;
+
+        // Evaluate alert rules against everything the hub broadcasts
+        let alerts = alerts::AlertEngine::start(db.shared_storage(), &streaming_hub, notifications).await;
 
         // Initialize streaming source manager
         let streaming_manager = Arc::new(tokio::sync::RwLock::new(
@@ -67,19 +123,22 @@ impl AppState {
         ));
 
         // Restore active streaming sources from database
-        if let Err(e) = restore_streaming_sources(&db, &streaming_manager).await {
+        if let Err(e) = streaming::restore::restore_streaming_sources(&db, &streaming_manager).await {
             tracing::warn!("Failed to restore streaming sources: {}", e);
         }
 
         // Initialize optimized database operations
         let optimized_db = Arc::new(OptimizedDbOps::new(
-            db.pool().clone(),
+            db.pool().ok().cloned(),
             Arc::clone(&cache_manager)
         ));
 
-        // Initialize metrics collector
-        let metrics_collector = Arc::new(middleware::metrics::MetricsCollector::new());
-        metrics_collector.clone().start_background_tasks();
+        let rate_limiter = Arc::new(middleware::rate_limit::RateLimiter::new(
+            config.rate_limits,
+            metrics_collector.clone(),
+        ));
+
+        let oidc = config.oidc.clone().map(|oidc| Arc::new(oidc::OidcClient::new(oidc)));
 
         Ok(Self {
             db,
@@ -87,9 +146,15 @@ impl AppState {
             circuit_breakers,
             cache_manager,
             streaming_hub,
+            alerts,
+            scheduler,
             streaming_manager,
             optimized_db,
             metrics_collector,
+            oidc,
+            secrets,
+            jobs,
+            rate_limiter,
         })
     }
 
@@ -123,7 +188,7 @@ impl AppState {
         let mut services = std::collections::HashMap::new();
         
         // Check database health
-        services.insert("database".to_string(), error_handling::check_database_health(self.db.pool()).await);
+        services.insert("database".to_string(), error_handling::check_database_health(self.db.storage()).await);
         
         // Check cache health
         services.insert("cache".to_string(), error_handling::check_cache_health(&self.cache_manager).await);
@@ -144,162 +209,6 @@ impl AppState {
     }
 }
 
-/// Stored parser configuration (for deserialization from database)
-#[derive(Debug, serde::Deserialize)]
-struct StoredParserConfig {
-    log_format: String,
-    timestamp_format: Option<String>,
-    level_field: Option<String>,
-    message_field: Option<String>,
-    metadata_fields: Option<Vec<String>>,
-}
-
-/// Restore active streaming sources from database on startup
-async fn restore_streaming_sources(
-    db: &Database,
-    streaming_manager: &Arc<tokio::sync::RwLock<crate::streaming::sources::StreamingSourceManager>>,
-) -> anyhow::Result<()> {
-    use sqlx::Row;
-
-    tracing::info!("Restoring active streaming sources from database...");
-
-    // Query all active sources
-    let rows = sqlx::query("SELECT * FROM streaming_sources WHERE status = 'active'")
-        .fetch_all(db.pool())
-        .await?;
-
-    let mut manager = streaming_manager.write().await;
-    let mut restored_count = 0;
-
-    for row in rows {
-        match restore_source_from_row(&row, &mut manager).await {
-            Ok(source_id) => {
-                tracing::info!("Restored streaming source: {}", source_id);
-                restored_count += 1;
-            }
-            Err(e) => {
-                let id: String = row.try_get("id").unwrap_or_else(|_| "unknown".to_string());
-                tracing::error!("Failed to restore streaming source {}: {}", id, e);
-            }
-        }
-    }
-
-    drop(manager);
-    tracing::info!("Restored {} streaming sources", restored_count);
-    Ok(())
-}
-
-/// Helper to restore a single source from a database row
-async fn restore_source_from_row(
-    row: &sqlx::sqlite::SqliteRow,
-    manager: &mut crate::streaming::sources::StreamingSourceManager,
-) -> anyhow::Result<String> {
-    use sqlx::Row;
-    use uuid::Uuid;
-
-    let source_id: String = row.try_get("id")?;
-    let project_id: String = row.try_get("project_id")?;
-    let project_id = Uuid::parse_str(&project_id)?;
-    let name: String = row.try_get("name")?;
-    let source_type_str: String = row.try_get("source_type")?;
-    let config_json: String = row.try_get("config")?;
-    let parser_config_json: Option<String> = row.try_get("parser_config").ok();
-    let buffer_size: Option<i64> = row.try_get("buffer_size").ok();
-    let batch_timeout_seconds: Option<i64> = row.try_get("batch_timeout_seconds").ok();
-    let restart_on_error: Option<bool> = row.try_get("restart_on_error").ok();
-    let max_restarts: Option<i64> = row.try_get("max_restarts").ok();
-
-    // Parse source type
-    let config_value: serde_json::Value = serde_json::from_str(&config_json)?;
-    let source_type = parse_source_type_from_config(&source_type_str, &config_value)?;
-
-    // Parse parser config
-    let parser_config = if let Some(json_str) = parser_config_json {
-        let stored_config: StoredParserConfig = serde_json::from_str(&json_str)?;
-        parse_stored_parser_config(stored_config)
-    } else {
-        crate::streaming::sources::ParserConfig::default()
-    };
-
-    // Create source config
-    let config = crate::streaming::sources::StreamingSourceConfig {
-        source_type,
-        project_id,
-        name,
-        parser_config,
-        buffer_size: buffer_size.map(|s| s as usize).unwrap_or(100),
-        batch_timeout: tokio::time::Duration::from_secs(batch_timeout_seconds.map(|s| s as u64).unwrap_or(2)),
-        restart_on_error: restart_on_error.unwrap_or(true),
-        max_restarts: max_restarts.map(|m| m as u32),
-    };
-
-    // Start the source
-    manager.start_source(config).await?;
-
-    Ok(source_id)
-}
-
-/// Parse source type from stored config
-fn parse_source_type_from_config(
-    source_type: &str,
-    config: &serde_json::Value,
-) -> anyhow::Result<crate::streaming::sources::StreamingSourceType> {
-    use std::path::PathBuf;
-
-    match source_type {
-        "file" => {
-            let path = config.get("path")
-                .and_then(|v| v.as_str())
-                .ok_or_else(|| anyhow::anyhow!("Missing 'path' for file source"))?;
-            Ok(crate::streaming::sources::StreamingSourceType::File { path: PathBuf::from(path) })
-        }
-        "command" => {
-            let command = config.get("command")
-                .and_then(|v| v.as_str())
-                .ok_or_else(|| anyhow::anyhow!("Missing 'command'"))?;
-            let args = config.get("args")
-                .and_then(|v| v.as_array())
-                .map(|arr| arr.iter().filter_map(|v| v.as_str().map(String::from)).collect())
-                .unwrap_or_default();
-            Ok(crate::streaming::sources::StreamingSourceType::Command { command: command.to_string(), args })
-        }
-        "tcp" => {
-            let port = config.get("port")
-                .and_then(|v| v.as_u64())
-                .ok_or_else(|| anyhow::anyhow!("Missing 'port' for TCP source"))?;
-            Ok(crate::streaming::sources::StreamingSourceType::TcpListener { port: port as u16 })
-        }
-        "stdin" => Ok(crate::streaming::sources::StreamingSourceType::Stdin),
-        "http" => {
-            let path = config.get("path")
-                .and_then(|v| v.as_str())
-                .ok_or_else(|| anyhow::anyhow!("Missing 'path' for HTTP source"))?;
-            Ok(crate::streaming::sources::StreamingSourceType::HttpEndpoint { path: path.to_string() })
-        }
-        _ => Err(anyhow::anyhow!("Unknown source type: {}", source_type))
-    }
-}
-
-/// Parse parser config from stored configuration
-fn parse_stored_parser_config(
-    stored: StoredParserConfig,
-) -> crate::streaming::sources::ParserConfig {
-    let log_format = match stored.log_format.as_str() {
-        "json" => crate::streaming::sources::LogFormat::Json,
-        "syslog" => crate::streaming::sources::LogFormat::Syslog,
-        "common" => crate::streaming::sources::LogFormat::CommonLog,
-        _ => crate::streaming::sources::LogFormat::Text,
-    };
-
-    crate::streaming::sources::ParserConfig {
-        log_format,
-        timestamp_format: stored.timestamp_format,
-        level_field: stored.level_field,
-        message_field: stored.message_field,
-        metadata_fields: stored.metadata_fields.unwrap_or_default(),
-    }
-}
-
 // Version information
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 pub const NAME: &str = env!("CARGO_PKG_NAME");
@@ -318,10 +227,8 @@ pub async fn start_server(port: u16) -> anyhow::Result<()> {
     dotenv::dotenv().ok();
 
     // Initialize tracing (only if not already initialized)
-    // Use try_init() to avoid panic when called from CLI which already initializes tracing
-    let _ = tracing_subscriber::fmt()
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-        .try_init();
+    // The CLI installs its own subscriber first, in which case spans aren't exported
+    let _telemetry = telemetry::init().ok();
 
     tracing::info!("Starting Synapse web server on port {}", port);
 
@@ -360,13 +267,20 @@ pub async fn start_server(port: u16) -> anyhow::Result<()> {
     let serve_dir = ServeDir::new(&frontend_path)
         .not_found_service(ServeFile::new(&index_path));
 
+    let metrics_collector = state.metrics_collector.clone();
+
     // Build router with proper SPA fallback
     let app = Router::new()
         .route("/api/health", get(handlers::dashboard::get_dashboard_stats))
-        .nest("/api", routes::api_routes())
+        .route("/metrics", get(middleware::prometheus::metrics_handler))
+        .nest("/api", routes::api_routes(state.clone()))
         // Serve static files and handle SPA routing with fallback
         .fallback_service(serve_dir)
         .layer(ServiceBuilder::new()
+            .layer(axum::middleware::from_fn(move |req, next| {
+                middleware::metrics::metrics_middleware(metrics_collector.clone(), req, next)
+            }))
+            .layer(axum::middleware::from_fn(error_handling::trace_request))
             .layer(TraceLayer::new_for_http())
             .layer(CorsLayer::permissive())
             .layer(DefaultBodyLimit::max(50 * 1024 * 1024)) // 50MB
@@ -380,7 +294,8 @@ pub async fn start_server(port: u16) -> anyhow::Result<()> {
     tracing::info!("Server listening on http://{}", addr);
     
     // Start server
-    axum::serve(listener, app).await?;
+    // Connection info gives rate limits and audit records the peer address
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
     
     Ok(())
 }
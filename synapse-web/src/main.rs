@@ -7,17 +7,23 @@ use tokio::net::TcpListener;
 use tower::ServiceBuilder;
 use tower_http::{cors::CorsLayer, trace::TraceLayer, services::{ServeDir, ServeFile}};
 
+mod alerts;
+mod audit;
 mod cache;
 mod circuit_breaker;
 mod config;
 mod database;
 mod error_handling;
 mod handlers;
+mod jobs;
 mod middleware;
 mod models;
+mod oidc;
 mod performance;
 mod routes;
+mod scheduler;
 mod streaming;
+mod telemetry;
 mod validation;
 
 use cache::CacheManager;
@@ -35,10 +41,8 @@ async fn main() -> anyhow::Result<()> {
     // Load .env file if it exists
     dotenv::dotenv().ok();
 
-    // Initialize tracing with environment filter
-    tracing_subscriber::fmt()
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-        .init();
+    // Initialize tracing with environment filter, exporting spans when configured
+    let _telemetry = telemetry::init()?;
     
     tracing::info!("Starting Synapse web server");
 
@@ -47,6 +51,12 @@ async fn main() -> anyhow::Result<()> {
     let config = match WebConfig::load() {
         Ok(config) => {
             tracing::info!("Configuration loaded successfully, port: {}", config.port);
+            if config.oidc.is_some() && !config.auth_enabled {
+                tracing::warn!("Single sign-on is configured but SYNAPSE_AUTH_ENABLED is off; it will not be offered");
+            }
+            if !config.auth_enabled {
+                tracing::warn!("Authentication is disabled; set SYNAPSE_AUTH_ENABLED=true before exposing the server beyond localhost");
+            }
             config
         }
         Err(e) => {
@@ -75,13 +85,24 @@ async fn main() -> anyhow::Result<()> {
         tracing::info!("Database migrations completed");
     }
 
-    // Create performance indexes
-    if let Err(e) = create_performance_indexes(db.pool()).await {
-        tracing::warn!("Failed to create performance indexes: {}", e);
-    } else {
-        tracing::info!("Performance indexes created successfully");
+    // Create performance indexes (the PostgreSQL migrations already include them)
+    if let Ok(pool) = db.pool() {
+        if let Err(e) = create_performance_indexes(pool).await {
+            tracing::warn!("Failed to create performance indexes: {}", e);
+        } else {
+            tracing::info!("Performance indexes created successfully");
+        }
     }
 
+    // Load the master key that encrypts provider API keys
+    let secrets = match handlers::settings::load_secrets(&db).await {
+        Ok(keyring) => Arc::new(tokio::sync::RwLock::new(keyring)),
+        Err(e) => {
+            tracing::error!("Failed to load the master key: {}", e);
+            return Err(e);
+        }
+    };
+
     // Initialize cache manager
     tracing::debug!("Initializing cache manager");
     let cache_manager = Arc::new(CacheManager::new());
@@ -90,18 +111,55 @@ async fn main() -> anyhow::Result<()> {
     tracing::debug!("Initializing circuit breaker registry");
     let circuit_breakers = Arc::new(CircuitBreakerRegistry::new());
 
-    // Start background task to process pending analyses
-    tracing::debug!("Starting background analysis processing task");
-    let db_pool = db.pool().clone();
-    let circuit_breakers_clone = circuit_breakers.clone();
-    let config_clone = config.clone();
-    tokio::spawn(async move {
-        process_pending_analyses_task(db_pool, circuit_breakers_clone, config_clone.analysis_timeout_secs).await;
-    });
+    // Initialize metrics collector
+    tracing::debug!("Initializing metrics collector");
+    let metrics_collector = Arc::new(middleware::metrics::MetricsCollector::new());
+    metrics_collector.clone().start_background_tasks();
+    tracing::info!("Metrics collector initialized and background tasks started");
 
-    // Initialize streaming hub for real-time log streaming
+    // Fail analyses that stopped with the last run and have no job to finish them
+    if let Err(e) = synapse_core::project::fail_interrupted_analyses(db.storage()).await {
+        tracing::warn!("Failed to mark interrupted analyses as failed: {}", e);
+    }
+
+    // Start the analysis workers, resuming jobs interrupted by a restart
+    tracing::debug!("Starting analysis job queue");
+    let jobs = jobs::start_job_queue(
+        &db,
+        secrets.clone(),
+        circuit_breakers.clone(),
+        metrics_collector.clone(),
+        &config,
+    );
+
+    // Initialize streaming hub, storing what it accepts and dropping entries past retention
     tracing::debug!("Initializing streaming hub");
-    let streaming_hub = Arc::new(StreamingHub::new());
+    let log_store = streaming::store::start(db.shared_storage(), &config);
+    let streaming_hub = Arc::new(StreamingHub::new().with_store(log_store));
+    if let Err(e) = streaming::store::restore_recent(db.storage(), &streaming_hub).await {
+        tracing::warn!("Failed to restore recent streaming logs: {}", e);
+    }
+
+    // Send alerts and finished analyses to notification channels, retrying failed deliveries
+    tracing::debug!("Starting notification delivery");
+    let notifications = alerts::NotificationManager::new(db.shared_storage(), secrets.clone(), &config);
+    notifications.start();
+    notifications.watch_jobs(&jobs);
+
+    // Run scheduled and alert-triggered analyses over recent streaming entries
+    tracing::debug!("Starting analysis scheduler");
+    let scheduler = scheduler::AnalysisScheduler::start(
+        db.shared_storage(),
+        jobs.clone(),
+        &streaming_hub,
+        &notifications,
+        &config,
+    )
+    .await;
+
+    // Evaluate alert rules against everything the hub broadcasts
+    tracing::debug!("Starting alert engine");
+    let alerts = alerts::AlertEngine::start(db.shared_storage(), &streaming_hub, notifications).await;
 
     // Initialize streaming source manager
     tracing::debug!("Initializing streaming source manager");
@@ -109,19 +167,18 @@ async fn main() -> anyhow::Result<()> {
         streaming::sources::StreamingSourceManager::new(Arc::clone(&streaming_hub))
     ));
 
+    // Restore active streaming sources from database
+    if let Err(e) = streaming::restore::restore_streaming_sources(&db, &streaming_manager).await {
+        tracing::warn!("Failed to restore streaming sources: {}", e);
+    }
+
     // Initialize optimized database operations
     tracing::debug!("Initializing optimized database operations");
     let optimized_db = Arc::new(OptimizedDbOps::new(
-        db.pool().clone(),
+        db.pool().ok().cloned(),
         Arc::clone(&cache_manager),
     ));
 
-    // Initialize metrics collector
-    tracing::debug!("Initializing metrics collector");
-    let metrics_collector = Arc::new(middleware::metrics::MetricsCollector::new());
-    metrics_collector.clone().start_background_tasks();
-    tracing::info!("Metrics collector initialized and background tasks started");
-
     // Build application router
     tracing::debug!("Building application router");
     let app = create_app(
@@ -129,9 +186,13 @@ async fn main() -> anyhow::Result<()> {
         cache_manager,
         circuit_breakers,
         streaming_hub,
+        alerts,
+        scheduler,
         streaming_manager,
         optimized_db,
         metrics_collector,
+        secrets,
+        jobs,
         config.clone(),
     )
     .await;
@@ -147,7 +208,8 @@ async fn main() -> anyhow::Result<()> {
     );
 
     let listener = TcpListener::bind(&addr).await?;
-    axum::serve(listener, app).await?;
+    // Connection info gives rate limits and audit records the peer address
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
 
     Ok(())
 }
@@ -157,9 +219,13 @@ pub async fn create_app(
     cache_manager: Arc<CacheManager>,
     circuit_breakers: Arc<CircuitBreakerRegistry>,
     streaming_hub: Arc<StreamingHub>,
+    alerts: Arc<alerts::AlertEngine>,
+    scheduler: Arc<scheduler::AnalysisScheduler>,
     streaming_manager: Arc<tokio::sync::RwLock<streaming::sources::StreamingSourceManager>>,
     optimized_db: Arc<OptimizedDbOps>,
     metrics_collector: Arc<middleware::metrics::MetricsCollector>,
+    secrets: Arc<tokio::sync::RwLock<synapse_core::secrets::Keyring>>,
+    jobs: Arc<synapse_core::project::JobQueue>,
     config: WebConfig,
 ) -> Router {
     // Determine frontend directory path
@@ -198,6 +264,27 @@ pub async fn create_app(
     }
 
     let metrics_collector_clone = metrics_collector.clone();
+    let rate_limiter = Arc::new(middleware::rate_limit::RateLimiter::new(
+        config.rate_limits,
+        metrics_collector.clone(),
+    ));
+
+    let state = AppState {
+        db,
+        config: config.clone(),
+        circuit_breakers,
+        cache_manager,
+        streaming_hub,
+        alerts,
+        scheduler,
+        streaming_manager,
+        optimized_db,
+        metrics_collector,
+        oidc: config.oidc.clone().map(|oidc| Arc::new(oidc::OidcClient::new(oidc))),
+        secrets,
+        jobs,
+        rate_limiter,
+    };
 
     // Create SPA-compatible static file service
     let serve_dir = ServeDir::new(&frontend_path)
@@ -205,7 +292,8 @@ pub async fn create_app(
 
     Router::new()
         .route("/health", get(enhanced_health_check))
-        .nest("/api", routes::api_routes())
+        .route("/metrics", get(middleware::prometheus::metrics_handler))
+        .nest("/api", routes::api_routes(state.clone()))
         .route("/ws", get(status_ws_handler))
         // Serve static files and handle SPA routing with fallback
         .fallback_service(serve_dir)
@@ -219,16 +307,7 @@ pub async fn create_app(
                 .layer(CorsLayer::permissive())
                 .layer(DefaultBodyLimit::max(config.max_upload_size)),
         )
-        .with_state(AppState {
-            db,
-            config: config.clone(),
-            circuit_breakers,
-            cache_manager,
-            streaming_hub,
-            streaming_manager,
-            optimized_db,
-            metrics_collector,
-        })
+        .with_state(state)
 }
 
 async fn health_check() -> Result<Json<serde_json::Value>, StatusCode> {
@@ -248,7 +327,7 @@ async fn enhanced_health_check(
     // Check database health
     services.insert(
         "database".to_string(),
-        check_database_health(state.db.pool()).await,
+        check_database_health(state.db.storage()).await,
     );
 
     // Check cache health
@@ -281,139 +360,13 @@ pub struct AppState {
     pub circuit_breakers: Arc<CircuitBreakerRegistry>,
     pub cache_manager: Arc<CacheManager>,
     pub streaming_hub: Arc<StreamingHub>,
+    pub alerts: Arc<alerts::AlertEngine>,
+    pub scheduler: Arc<scheduler::AnalysisScheduler>,
     pub streaming_manager: Arc<tokio::sync::RwLock<streaming::sources::StreamingSourceManager>>,
     pub optimized_db: Arc<OptimizedDbOps>,
     pub metrics_collector: Arc<middleware::metrics::MetricsCollector>,
-}
-
-async fn process_pending_analyses_task(
-    db_pool: sqlx::SqlitePool,
-    circuit_breakers: Arc<CircuitBreakerRegistry>,
-    timeout_secs: u64,
-) {
-    use models::AnalysisStatus;
-    use handlers::analysis::perform_analysis;
-    
-    // Wait a bit for server to fully start
-    tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
-    
-    tracing::info!("Starting pending analyses processing task");
-    
-    let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(30));
-    
-    loop {
-        interval.tick().await;
-        
-        // Get all pending analyses
-        let pending_analyses = match sqlx::query!(
-            "SELECT a.id, a.provider, a.level_filter, lf.upload_path 
-             FROM analyses a 
-             JOIN log_files lf ON a.log_file_id = lf.id 
-             WHERE a.status = ? AND a.log_file_id IS NOT NULL",
-            AnalysisStatus::Pending as i32
-        )
-        .fetch_all(&db_pool)
-        .await
-        {
-            Ok(analyses) => analyses,
-            Err(e) => {
-                tracing::error!("Failed to fetch pending analyses: {}", e);
-                continue;
-            }
-        };
-        
-        if !pending_analyses.is_empty() {
-            tracing::info!("Found {} pending analyses to process", pending_analyses.len());
-        }
-        
-        for analysis in pending_analyses {
-            let analysis_id = match analysis.id {
-                Some(id) => id,
-                None => continue, // Skip if no ID
-            };
-            let db_pool_clone = db_pool.clone();
-            let circuit_breakers_clone = circuit_breakers.clone();
-            
-            // Process each analysis in a separate task
-            tokio::spawn(async move {
-                // Update status to running
-                if let Err(e) = sqlx::query!(
-                    "UPDATE analyses SET status = ? WHERE id = ?",
-                    AnalysisStatus::Running as i32,
-                    analysis_id
-                ).execute(&db_pool_clone).await {
-                    tracing::error!("Failed to update analysis {} to running: {}", analysis_id, e);
-                    return;
-                }
-                
-                // Fetch API key and selected model from settings
-                let (api_key, selected_model) = match sqlx::query!("SELECT api_key, selected_model FROM settings LIMIT 1")
-                    .fetch_optional(&db_pool_clone)
-                    .await
-                {
-                    Ok(Some(settings)) if !settings.api_key.is_empty() => (Some(settings.api_key), settings.selected_model),
-                    Ok(_) => (None, None),
-                    Err(e) => {
-                        tracing::error!("Failed to fetch settings from database: {}", e);
-                        (None, None)
-                    }
-                };
-                
-                // Perform analysis
-                let result = perform_analysis(
-                    &analysis.upload_path,
-                    &analysis.level_filter,
-                    &analysis.provider,
-                    api_key.as_deref(),
-                    &circuit_breakers_clone,
-                    timeout_secs,
-                    None, // user_context - not available in background task
-                    selected_model.as_deref(),
-                ).await;
-                
-                match result {
-                    Ok(analysis_result) => {
-                        match serde_json::to_string(&analysis_result) {
-                            Ok(result_json) => {
-                                if let Err(e) = sqlx::query!(
-                                    "UPDATE analyses SET status = ?, result = ?, completed_at = CURRENT_TIMESTAMP WHERE id = ?",
-                                    AnalysisStatus::Completed as i32,
-                                    result_json,
-                                    analysis_id
-                                ).execute(&db_pool_clone).await {
-                                    tracing::error!("Failed to update analysis {} with result: {}", analysis_id, e);
-                                } else {
-                                    tracing::info!("Analysis {} completed successfully", analysis_id);
-                                }
-                            }
-                            Err(e) => {
-                                let error_msg = format!("Serialization error: {}", e);
-                                if let Err(e) = sqlx::query!(
-                                    "UPDATE analyses SET status = ?, error_message = ?, completed_at = CURRENT_TIMESTAMP WHERE id = ?",
-                                    AnalysisStatus::Failed as i32,
-                                    error_msg,
-                                    analysis_id
-                                ).execute(&db_pool_clone).await {
-                                    tracing::error!("Failed to update analysis {} with serialization error: {}", analysis_id, e);
-                                }
-                            }
-                        }
-                    }
-                    Err(error) => {
-                        let error_msg = error.to_string();
-                        if let Err(e) = sqlx::query!(
-                            "UPDATE analyses SET status = ?, error_message = ?, completed_at = CURRENT_TIMESTAMP WHERE id = ?",
-                            AnalysisStatus::Failed as i32,
-                            error_msg,
-                            analysis_id
-                        ).execute(&db_pool_clone).await {
-                            tracing::error!("Failed to update analysis {} with error: {}", analysis_id, e);
-                        } else {
-                            tracing::info!("Analysis {} failed: {}", analysis_id, error_msg);
-                        }
-                    }
-                }
-            });
-        }
-    }
+    pub oidc: Option<Arc<oidc::OidcClient>>,
+    pub secrets: Arc<tokio::sync::RwLock<synapse_core::secrets::Keyring>>,
+    pub jobs: Arc<synapse_core::project::JobQueue>,
+    pub rate_limiter: Arc<middleware::rate_limit::RateLimiter>,
 }
@@ -0,0 +1,149 @@
+use axum::{
+    extract::{RawPathParams, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use synapse_core::project::ProjectRole;
+
+use crate::{error_handling::AppError, middleware::auth::CurrentUser, AppState};
+
+/// Path parameters that name the project a route acts on
+const PROJECT_PARAMS: [&str; 2] = ["project_id", "id"];
+
+/// Allow members with at least the viewer role in the route's project
+pub async fn require_project_viewer(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    params: RawPathParams,
+    req: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    authorize_route(&state, &current_user, &params, ProjectRole::Viewer).await?;
+    Ok(next.run(req).await)
+}
+
+/// Allow members with at least the analyst role in the route's project
+pub async fn require_project_analyst(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    params: RawPathParams,
+    req: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    authorize_route(&state, &current_user, &params, ProjectRole::Analyst).await?;
+    Ok(next.run(req).await)
+}
+
+/// Allow administrators of the route's project
+pub async fn require_project_admin(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    params: RawPathParams,
+    req: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    authorize_route(&state, &current_user, &params, ProjectRole::Admin).await?;
+    Ok(next.run(req).await)
+}
+
+/// Allow server administrators only, for settings that affect every project
+pub async fn require_server_admin(
+    current_user: CurrentUser,
+    req: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    current_user.require_admin()?;
+    Ok(next.run(req).await)
+}
+
+/// Resolve the project from the path (directly or through `analysis_id` or `job_id`) and check the caller's role
+async fn authorize_route(
+    state: &AppState,
+    current_user: &CurrentUser,
+    params: &RawPathParams,
+    required: ProjectRole,
+) -> Result<(), AppError> {
+    let param = |name: &str| {
+        params
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.to_string())
+    };
+
+    let mut project_id = PROJECT_PARAMS.iter().find_map(|name| param(name));
+
+    // Analyses are addressed on their own or under a project; either way they must belong to it
+    if let Some(analysis_id) = param("analysis_id") {
+        let analysis = state
+            .db
+            .storage()
+            .get_analysis(&analysis_id)
+            .await?
+            .ok_or_else(|| AppError::not_found(format!("Analysis {} not found", analysis_id)))?;
+
+        match &project_id {
+            Some(id) if *id != analysis.project_id => {
+                return Err(AppError::not_found(format!("Analysis {} not found", analysis_id)));
+            }
+            Some(_) => {}
+            None => project_id = Some(analysis.project_id),
+        }
+    }
+
+    // Jobs are addressed on their own and belong to their analysis's project
+    if let Some(job_id) = param("job_id") {
+        let job = state
+            .db
+            .storage()
+            .get_job(&job_id)
+            .await?
+            .ok_or_else(|| AppError::not_found(format!("Job {} not found", job_id)))?;
+        project_id = Some(job.project_id);
+    }
+
+    let project_id = project_id.ok_or_else(|| {
+        AppError::internal("Project-scoped route without a project in its path")
+    })?;
+
+    authorize_project(state, current_user, &project_id, required).await?;
+    Ok(())
+}
+
+/// Check that the caller holds at least `required` in a project, returning their role
+///
+/// Server administrators, and the local operator when authentication is disabled, act as
+/// project administrators everywhere. Non-members get a 404 so project ids don't leak.
+pub async fn authorize_project(
+    state: &AppState,
+    current_user: &CurrentUser,
+    project_id: &str,
+    required: ProjectRole,
+) -> Result<ProjectRole, AppError> {
+    if current_user.is_admin() {
+        return Ok(ProjectRole::Admin);
+    }
+
+    let user = current_user.account()?;
+    let role = state
+        .db
+        .storage()
+        .get_project_role(project_id, &user.id)
+        .await?
+        .ok_or_else(|| AppError::not_found(format!("Project {} not found", project_id)))?;
+
+    if role < required {
+        tracing::warn!(
+            "User {} ({}) denied {} access to project {}",
+            user.username,
+            role,
+            required,
+            project_id
+        );
+        return Err(AppError::forbidden(format!(
+            "This action requires the {} role in the project",
+            required
+        )));
+    }
+
+    Ok(role)
+}
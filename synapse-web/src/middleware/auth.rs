@@ -0,0 +1,359 @@
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use std::{
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+};
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts, Request, State},
+    http::{header, request::Parts, Extensions, HeaderMap, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use ipnet::IpNet;
+use rand::RngCore;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use synapse_core::project::{AuditEvent, User};
+
+use crate::{config::WebConfig, error_handling::AppError, AppState};
+
+/// Cookie carrying the browser session secret
+pub const SESSION_COOKIE: &str = "synapse_session";
+
+/// Cookie holding the state, nonce and PKCE verifier of a single sign-on login in progress
+pub const OIDC_LOGIN_COOKIE: &str = "synapse_oidc";
+
+/// Prefix of personal API tokens, so leaked tokens are easy to recognise
+pub const API_TOKEN_PREFIX: &str = "syn_";
+
+/// Argon2id hash of a throwaway password with the default parameters, checked on logins
+/// to unknown accounts so they take as long to refuse as wrong passwords
+pub const DUMMY_PASSWORD_HASH: &str =
+    "$argon2id$v=19$m=19456,t=2,p=1$H/SN7jfw39MZ0u2GQtOaXQ$27NERT/MOo8ZbLWOUkO4P1hxXVLCymRWJGcpvycK1GI";
+
+/// How a request was authenticated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMethod {
+    /// Authentication is disabled; the request runs as the local operator
+    Local,
+    Session,
+    ApiToken,
+}
+
+/// The caller of an API request, inserted by [`require_auth`]
+#[derive(Debug, Clone)]
+pub struct CurrentUser {
+    pub user: Option<User>,
+    pub method: AuthMethod,
+    pub ip_address: Option<String>,
+}
+
+impl CurrentUser {
+    /// The implicit operator of a single-user install, with full access
+    pub fn local() -> Self {
+        Self {
+            user: None,
+            method: AuthMethod::Local,
+            ip_address: None,
+        }
+    }
+
+    /// Id to attribute new records to; `None` when authentication is disabled
+    pub fn user_id(&self) -> Option<String> {
+        self.user.as_ref().map(|user| user.id.clone())
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.user.as_ref().is_none_or(|user| user.is_admin)
+    }
+
+    /// The signed-in account, for endpoints that only make sense with one
+    pub fn account(&self) -> Result<&User, AppError> {
+        self.user.as_ref().ok_or_else(|| {
+            AppError::bad_request("User accounts are disabled on this server (SYNAPSE_AUTH_ENABLED)")
+        })
+    }
+
+    pub fn require_admin(&self) -> Result<(), AppError> {
+        if self.is_admin() {
+            Ok(())
+        } else {
+            Err(AppError::forbidden("Administrator access required"))
+        }
+    }
+
+    /// An audit log event attributed to this caller
+    pub fn audit_event(&self, action: &str) -> AuditEvent {
+        AuditEvent::new(action)
+            .with_actor(self.user_id(), self.user.as_ref().map(|user| user.username.clone()))
+            .with_ip_address(self.ip_address.clone())
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for CurrentUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<CurrentUser>()
+            .cloned()
+            .ok_or(AppError::Unauthorized)
+    }
+}
+
+/// Reject API requests without a valid session cookie or `Authorization: Bearer` token
+pub async fn require_auth(
+    State(state): State<AppState>,
+    mut req: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let mut current_user = if state.config.auth_enabled {
+        authenticate(&state, req.headers())
+            .await?
+            .ok_or(AppError::Unauthorized)?
+    } else {
+        CurrentUser::local()
+    };
+    current_user.ip_address = ClientIp::of(&state.config, req.headers(), req.extensions()).0;
+
+    req.extensions_mut().insert(current_user);
+    Ok(next.run(req).await)
+}
+
+/// Resolve the caller from an API token or session cookie, if either is present and valid
+///
+/// The caller's address is left for [`require_auth`] to fill in.
+pub async fn authenticate(state: &AppState, headers: &HeaderMap) -> Result<Option<CurrentUser>, AppError> {
+    let storage = state.db.storage();
+
+    if let Some(token) = bearer_token(headers) {
+        let Some(api_token) = storage.find_api_token(&hash_secret(token)).await? else {
+            return Ok(None);
+        };
+        let Some(user) = active_user(state, &api_token.user_id).await? else {
+            return Ok(None);
+        };
+
+        storage.touch_api_token(&api_token.id).await?;
+        return Ok(Some(CurrentUser {
+            user: Some(user),
+            method: AuthMethod::ApiToken,
+            ip_address: None,
+        }));
+    }
+
+    if let Some(secret) = session_cookie(headers) {
+        let session_id = hash_secret(secret);
+        let Some(session) = storage.get_session(&session_id).await? else {
+            return Ok(None);
+        };
+        let Some(user) = active_user(state, &session.user_id).await? else {
+            return Ok(None);
+        };
+
+        storage.touch_session(&session_id).await?;
+        return Ok(Some(CurrentUser {
+            user: Some(user),
+            method: AuthMethod::Session,
+            ip_address: None,
+        }));
+    }
+
+    Ok(None)
+}
+
+async fn active_user(state: &AppState, user_id: &str) -> Result<Option<User>, AppError> {
+    let user = state.db.storage().get_user(user_id).await?;
+    Ok(user.filter(|user| user.is_active))
+}
+
+pub(crate) fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+}
+
+/// The address a request came from, as resolved by [`client_ip`]
+///
+/// `None` when the server was not started with connection info, as in tests.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientIp(pub Option<String>);
+
+impl ClientIp {
+    pub fn of(config: &WebConfig, headers: &HeaderMap, extensions: &Extensions) -> Self {
+        let peer = extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(address)| address.ip());
+        Self(client_ip(headers, peer, &config.trusted_proxies).map(|ip| ip.to_string()))
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for ClientIp {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        Ok(Self::of(&state.config, &parts.headers, &parts.extensions))
+    }
+}
+
+/// The client address of a request whose connection came from `peer`
+///
+/// `X-Forwarded-For` is only believed while the hop that added an entry is a trusted
+/// proxy, reading from the right, so clients cannot pick their own address by sending it.
+pub fn client_ip(headers: &HeaderMap, peer: Option<IpAddr>, trusted_proxies: &[IpNet]) -> Option<IpAddr> {
+    let is_trusted = |ip: &IpAddr| trusted_proxies.iter().any(|proxy| proxy.contains(ip));
+
+    let forwarded: Vec<&str> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+
+    let mut client = peer?;
+    for hop in forwarded.into_iter().rev() {
+        if !is_trusted(&client) {
+            break;
+        }
+        match hop.parse() {
+            Ok(ip) => client = ip,
+            Err(_) => break,
+        }
+    }
+    Some(client)
+}
+
+/// The session secret from the `Cookie` header
+pub fn session_cookie(headers: &HeaderMap) -> Option<&str> {
+    read_cookie(headers, SESSION_COOKIE)
+}
+
+/// A cookie value from the `Cookie` header
+pub fn read_cookie<'a>(headers: &'a HeaderMap, cookie_name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, _)| *name == cookie_name)
+        .map(|(_, value)| value)
+        .filter(|value| !value.is_empty())
+}
+
+/// `Set-Cookie` value that stores a new session secret
+pub fn session_cookie_header(config: &WebConfig, secret: &str) -> HeaderValue {
+    let max_age = config.session_ttl_hours * 60 * 60;
+    cookie_header(config, &format!("{}={}; Max-Age={}", SESSION_COOKIE, secret, max_age))
+}
+
+/// `Set-Cookie` value that removes the session cookie
+pub fn clear_session_cookie_header(config: &WebConfig) -> HeaderValue {
+    cookie_header(config, &format!("{}=; Max-Age=0", SESSION_COOKIE))
+}
+
+/// `Set-Cookie` value for an HttpOnly cookie, given `name=value` and any extra attributes
+pub fn cookie_header(config: &WebConfig, cookie: &str) -> HeaderValue {
+    let secure = if config.secure_cookies { "; Secure" } else { "" };
+    HeaderValue::from_str(&format!("{}; Path=/; HttpOnly; SameSite=Lax{}", cookie, secure))
+        .expect("cookie contains only visible ASCII")
+}
+
+/// Generate a random secret for a session cookie or API token (hex, 256 bits)
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Digest under which a session or API token secret is stored
+pub fn hash_secret(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+/// Hash a password with argon2id on a blocking thread
+pub async fn hash_password(password: String) -> Result<String, AppError> {
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|e| AppError::internal(format!("Failed to hash password: {}", e)))
+    })
+    .await
+    .map_err(|e| AppError::internal(format!("Password hashing task failed: {}", e)))?
+}
+
+/// Check a password against a stored argon2 hash on a blocking thread
+pub async fn verify_password(password: String, password_hash: String) -> Result<bool, AppError> {
+    tokio::task::spawn_blocking(move || {
+        let parsed = PasswordHash::new(&password_hash)
+            .map_err(|e| AppError::internal(format!("Invalid stored password hash: {}", e)))?;
+        Ok(Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok())
+    })
+    .await
+    .map_err(|e| AppError::internal(format!("Password verification task failed: {}", e)))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_password_round_trip() {
+        let hash = hash_password("correct horse".to_string()).await.unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify_password("correct horse".to_string(), hash.clone()).await.unwrap());
+        assert!(!verify_password("wrong horse".to_string(), hash.clone()).await.unwrap());
+
+        // The stand-in for unknown accounts costs the same to check as a real hash
+        let params = |hash: &str| PasswordHash::new(hash).unwrap().params.to_string();
+        assert_eq!(params(DUMMY_PASSWORD_HASH), params(&hash));
+        assert!(!verify_password("correct horse".to_string(), DUMMY_PASSWORD_HASH.to_string()).await.unwrap());
+    }
+
+    #[test]
+    fn test_credentials_from_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, HeaderValue::from_static("theme=dark; synapse_session=abc123"));
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("Bearer syn_token"));
+
+        assert_eq!(session_cookie(&headers), Some("abc123"));
+        assert_eq!(bearer_token(&headers), Some("syn_token"));
+        assert_ne!(hash_secret("abc123"), hash_secret("abc124"));
+        assert_eq!(generate_secret().len(), 64);
+    }
+
+    #[test]
+    fn test_client_ip_trusts_forwarded_for_only_from_proxies() {
+        let proxies: Vec<IpNet> = vec!["10.0.0.0/8".parse().unwrap()];
+        let peer: IpAddr = "10.0.0.1".parse().unwrap();
+        let client: IpAddr = "203.0.113.7".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", HeaderValue::from_static("198.51.100.1, 203.0.113.7, 10.0.0.2"));
+
+        // Through two trusted proxies, the first untrusted hop from the right is the client
+        assert_eq!(client_ip(&headers, Some(peer), &proxies), Some(client));
+        // A direct client cannot claim another address
+        assert_eq!(client_ip(&headers, Some(client), &proxies), Some(client));
+        assert_eq!(client_ip(&headers, Some(peer), &[]), Some(peer));
+        assert_eq!(client_ip(&HeaderMap::new(), Some(peer), &proxies), Some(peer));
+        assert_eq!(client_ip(&headers, None, &proxies), None);
+    }
+}
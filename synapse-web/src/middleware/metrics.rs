@@ -11,8 +11,14 @@ use std::{
     },
     time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
+use synapse_core::ai_provider::AIError;
 use tokio::sync::broadcast;
 
+use crate::middleware::prometheus::Exposition;
+
+/// Method, matched route and status code of a response
+type HttpResponseKey = (String, String, u16);
+
 /// Performance metrics collection and monitoring middleware
 /// Tracks request latency, throughput, error rates, and resource usage
 pub struct MetricsCollector {
@@ -21,6 +27,17 @@ pub struct MetricsCollector {
     response_times: Arc<RwLock<Vec<Duration>>>,
     endpoint_metrics: Arc<RwLock<HashMap<String, EndpointMetrics>>>,
     quality_metrics: Arc<RwLock<QualityMetrics>>,
+    rate_limits: Arc<RwLock<HashMap<String, RateLimitMetrics>>>,
+    /// Latency per method and matched route
+    http_latency: Arc<RwLock<HashMap<(String, String), Histogram>>>,
+    /// Responses per method, matched route and status code
+    http_responses: Arc<RwLock<HashMap<HttpResponseKey, u64>>>,
+    /// Analyses per provider and model
+    analyses: Arc<RwLock<HashMap<(String, String), AnalysisMetrics>>>,
+    /// Failed provider calls per provider and error kind
+    provider_errors: Arc<RwLock<HashMap<(String, &'static str), u64>>>,
+    /// Open WebSocket connections per kind of socket
+    websocket_connections: Arc<RwLock<HashMap<&'static str, u64>>>,
     start_time: SystemTime,
     alerts: broadcast::Sender<QualityAlert>,
 }
@@ -56,6 +73,97 @@ where
     }
 }
 
+/// Requests let through and rejected by one rate limit or quota
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct RateLimitMetrics {
+    pub name: String,
+    /// Configured refill rate, for token-bucket limits
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub per_minute: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub burst: Option<u32>,
+    pub allowed: u64,
+    pub rejected: u64,
+}
+
+/// Upper bounds of the HTTP latency buckets, in seconds
+pub const HTTP_LATENCY_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Upper bounds of the analysis duration buckets, in seconds
+pub const ANALYSIS_DURATION_BUCKETS: &[f64] = &[1.0, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0];
+
+/// Observations counted into fixed buckets, the way Prometheus exposes histograms
+#[derive(Clone, Debug)]
+pub struct Histogram {
+    bounds: &'static [f64],
+    /// Observations per bucket, with one more for those above the last bound
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    pub fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            counts: vec![0; bounds.len() + 1],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    pub fn observe(&mut self, value: f64) {
+        let bucket = self.bounds.iter().position(|bound| value <= *bound).unwrap_or(self.bounds.len());
+        self.counts[bucket] += 1;
+        self.sum += value;
+        self.count += 1;
+    }
+
+    /// Observations at or below each bound, ending with `None` for +Inf
+    pub fn cumulative_buckets(&self) -> Vec<(Option<f64>, u64)> {
+        let mut total = 0;
+        self.counts
+            .iter()
+            .enumerate()
+            .map(|(i, count)| {
+                total += count;
+                (self.bounds.get(i).copied(), total)
+            })
+            .collect()
+    }
+
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+/// Durations and outcomes of the analyses run with one provider and model
+#[derive(Clone, Debug)]
+pub struct AnalysisMetrics {
+    pub duration: Histogram,
+    pub succeeded: u64,
+    pub failed: u64,
+}
+
+/// Keeps a WebSocket counted as open until it is dropped
+pub struct WebSocketGuard {
+    collector: Arc<MetricsCollector>,
+    kind: &'static str,
+}
+
+impl Drop for WebSocketGuard {
+    fn drop(&mut self) {
+        let mut connections = self.collector.websocket_connections.write().unwrap();
+        if let Some(count) = connections.get_mut(self.kind) {
+            *count = count.saturating_sub(1);
+        }
+    }
+}
+
 #[derive(Clone, Debug, serde::Serialize)]
 pub struct QualityMetrics {
     pub analysis_accuracy: f64,
@@ -113,6 +221,12 @@ impl MetricsCollector {
             response_times: Arc::new(RwLock::new(Vec::new())),
             endpoint_metrics: Arc::new(RwLock::new(HashMap::new())),
             quality_metrics: Arc::new(RwLock::new(QualityMetrics::default())),
+            rate_limits: Arc::new(RwLock::new(HashMap::new())),
+            http_latency: Arc::new(RwLock::new(HashMap::new())),
+            http_responses: Arc::new(RwLock::new(HashMap::new())),
+            analyses: Arc::new(RwLock::new(HashMap::new())),
+            provider_errors: Arc::new(RwLock::new(HashMap::new())),
+            websocket_connections: Arc::new(RwLock::new(HashMap::new())),
             start_time: SystemTime::now(),
             alerts: alerts_tx,
         }
@@ -298,9 +412,7 @@ impl MetricsCollector {
     }
 
     async fn get_active_connections(&self) -> u64 {
-        // Track active WebSocket connections
-        // In a real implementation, maintain a connection counter
-        42 // Placeholder
+        self.websocket_connections.read().unwrap().values().sum()
     }
 
     pub fn record_request(&self, path: &str, duration: Duration, status_code: u16) {
@@ -352,6 +464,62 @@ impl MetricsCollector {
         }
     }
 
+    /// Record a request in the per-route latency histograms and response counts
+    pub fn record_http(&self, method: &str, route: &str, duration: Duration, status_code: u16) {
+        self.http_latency
+            .write()
+            .unwrap()
+            .entry((method.to_string(), route.to_string()))
+            .or_insert_with(|| Histogram::new(HTTP_LATENCY_BUCKETS))
+            .observe(duration.as_secs_f64());
+        *self
+            .http_responses
+            .write()
+            .unwrap()
+            .entry((method.to_string(), route.to_string(), status_code))
+            .or_insert(0) += 1;
+    }
+
+    /// Record an analysis run
+    ///
+    /// A failure carries the provider error kind when the provider call itself failed.
+    pub fn record_analysis(&self, provider: &str, model: &str, duration: Duration, outcome: Result<(), Option<&'static str>>) {
+        {
+            let mut analyses = self.analyses.write().unwrap();
+            let metrics = analyses
+                .entry((provider.to_string(), model.to_string()))
+                .or_insert_with(|| AnalysisMetrics {
+                    duration: Histogram::new(ANALYSIS_DURATION_BUCKETS),
+                    succeeded: 0,
+                    failed: 0,
+                });
+            metrics.duration.observe(duration.as_secs_f64());
+            if outcome.is_ok() {
+                metrics.succeeded += 1;
+            } else {
+                metrics.failed += 1;
+            }
+        }
+
+        if let Err(Some(kind)) = outcome {
+            *self
+                .provider_errors
+                .write()
+                .unwrap()
+                .entry((provider.to_string(), kind))
+                .or_insert(0) += 1;
+        }
+    }
+
+    /// Count a WebSocket of the given kind as open until the guard is dropped
+    pub fn track_websocket(self: &Arc<Self>, kind: &'static str) -> WebSocketGuard {
+        *self.websocket_connections.write().unwrap().entry(kind).or_insert(0) += 1;
+        WebSocketGuard {
+            collector: self.clone(),
+            kind,
+        }
+    }
+
     pub fn record_analysis_result(&self, accuracy: f64, confidence: f64, success: bool) {
         let mut quality_metrics = self.quality_metrics.write().unwrap();
 
@@ -366,6 +534,24 @@ impl MetricsCollector {
         }
     }
 
+    /// Report a limit's configuration, so it's listed before anything hits it
+    pub fn register_rate_limit(&self, name: &str, per_minute: Option<u32>, burst: Option<u32>) {
+        let mut rate_limits = self.rate_limits.write().unwrap();
+        let metrics = rate_limits.entry(name.to_string()).or_insert_with(|| RateLimitMetrics::new(name));
+        metrics.per_minute = per_minute;
+        metrics.burst = burst;
+    }
+
+    pub fn record_rate_limit(&self, name: &str, allowed: bool) {
+        let mut rate_limits = self.rate_limits.write().unwrap();
+        let metrics = rate_limits.entry(name.to_string()).or_insert_with(|| RateLimitMetrics::new(name));
+        if allowed {
+            metrics.allowed += 1;
+        } else {
+            metrics.rejected += 1;
+        }
+    }
+
     pub fn get_metrics_summary(&self) -> MetricsSummary {
         let request_count = self.request_count.load(Ordering::Relaxed);
         let error_count = self.error_count.load(Ordering::Relaxed);
@@ -380,6 +566,8 @@ impl MetricsCollector {
 
         let quality_metrics = self.quality_metrics.read().unwrap().clone();
         let endpoint_metrics: Vec<EndpointMetrics> = self.endpoint_metrics.read().unwrap().values().cloned().collect();
+        let mut rate_limits: Vec<RateLimitMetrics> = self.rate_limits.read().unwrap().values().cloned().collect();
+        rate_limits.sort_by(|a, b| a.name.cmp(&b.name));
 
         MetricsSummary {
             request_count,
@@ -389,6 +577,7 @@ impl MetricsCollector {
             uptime,
             quality_metrics,
             endpoint_metrics,
+            rate_limits,
         }
     }
 
@@ -416,6 +605,86 @@ impl MetricsCollector {
     pub fn subscribe_to_alerts(&self) -> broadcast::Receiver<QualityAlert> {
         self.alerts.subscribe()
     }
+
+    /// Write the request, analysis, rate limit and WebSocket metrics
+    pub fn write_prometheus(&self, out: &mut Exposition) {
+        let uptime = SystemTime::now().duration_since(self.start_time).unwrap_or_default();
+        out.header("synapse_uptime_seconds", "gauge", "Seconds since the server started");
+        out.sample("synapse_uptime_seconds", &[], uptime.as_secs_f64());
+
+        out.header("synapse_http_requests_total", "counter", "HTTP responses by method, route and status code");
+        for ((method, route, status), count) in sorted(&self.http_responses.read().unwrap()) {
+            let status = status.to_string();
+            out.sample(
+                "synapse_http_requests_total",
+                &[("method", method), ("route", route), ("status", &status)],
+                *count as f64,
+            );
+        }
+
+        out.header("synapse_http_request_duration_seconds", "histogram", "HTTP request latency by method and route");
+        for ((method, route), histogram) in sorted(&self.http_latency.read().unwrap()) {
+            out.histogram("synapse_http_request_duration_seconds", &[("method", method), ("route", route)], histogram);
+        }
+
+        out.header("synapse_analysis_duration_seconds", "histogram", "Analysis duration by provider and model");
+        let analyses = self.analyses.read().unwrap();
+        for ((provider, model), metrics) in sorted(&analyses) {
+            out.histogram("synapse_analysis_duration_seconds", &[("provider", provider), ("model", model)], &metrics.duration);
+        }
+        out.header("synapse_analyses_total", "counter", "Analyses run by provider, model and outcome");
+        for ((provider, model), metrics) in sorted(&analyses) {
+            for (outcome, count) in [("success", metrics.succeeded), ("failure", metrics.failed)] {
+                out.sample(
+                    "synapse_analyses_total",
+                    &[("provider", provider), ("model", model), ("outcome", outcome)],
+                    count as f64,
+                );
+            }
+        }
+        drop(analyses);
+
+        out.header("synapse_provider_errors_total", "counter", "Failed AI provider calls by provider and error kind");
+        for ((provider, kind), count) in sorted(&self.provider_errors.read().unwrap()) {
+            out.sample("synapse_provider_errors_total", &[("provider", provider), ("kind", kind)], *count as f64);
+        }
+
+        out.header("synapse_rate_limit_requests_total", "counter", "Requests checked against each rate limit or quota");
+        for metrics in self.get_metrics_summary().rate_limits {
+            for (outcome, count) in [("allowed", metrics.allowed), ("rejected", metrics.rejected)] {
+                out.sample(
+                    "synapse_rate_limit_requests_total",
+                    &[("limit", &metrics.name), ("outcome", outcome)],
+                    count as f64,
+                );
+            }
+        }
+
+        out.header("synapse_websocket_connections", "gauge", "Open WebSocket connections by kind");
+        for (kind, count) in sorted(&self.websocket_connections.read().unwrap()) {
+            out.sample("synapse_websocket_connections", &[("kind", kind)], *count as f64);
+        }
+    }
+}
+
+/// Kind of provider failure behind an analysis error, when a provider call failed or timed out
+pub fn provider_error_kind(error: &anyhow::Error) -> Option<&'static str> {
+    error.chain().find_map(|cause| {
+        if let Some(error) = cause.downcast_ref::<AIError>() {
+            Some(error.kind())
+        } else if cause.is::<tokio::time::error::Elapsed>() {
+            Some("timeout")
+        } else {
+            None
+        }
+    })
+}
+
+/// Entries in key order, so scrapes list series consistently
+fn sorted<K: Ord, V>(map: &HashMap<K, V>) -> Vec<(&K, &V)> {
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    entries
 }
 
 #[derive(Clone, Debug, serde::Serialize)]
@@ -429,6 +698,7 @@ pub struct MetricsSummary {
     pub uptime: Duration,
     pub quality_metrics: QualityMetrics,
     pub endpoint_metrics: Vec<EndpointMetrics>,
+    pub rate_limits: Vec<RateLimitMetrics>,
 }
 
 fn serialize_duration_as_millis<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
@@ -445,6 +715,18 @@ where
     serializer.serialize_u64(duration.as_secs())
 }
 
+impl RateLimitMetrics {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            per_minute: None,
+            burst: None,
+            allowed: 0,
+            rejected: 0,
+        }
+    }
+}
+
 impl Default for QualityMetrics {
     fn default() -> Self {
         Self {
@@ -469,6 +751,7 @@ pub async fn metrics_middleware(
     next: Next,
 ) -> Response {
     let start = Instant::now();
+    let method = request.method().to_string();
     let path = request.extensions()
         .get::<MatchedPath>()
         .map(|p| p.as_str())
@@ -481,6 +764,7 @@ pub async fn metrics_middleware(
 
     // Record metrics
     collector.record_request(&path, duration, status);
+    collector.record_http(&method, &path, duration, status);
 
     tracing::debug!(
         "Request: {} - {}ms - Status: {}",
@@ -544,4 +828,49 @@ pub struct HealthResponse {
     pub error_rate: f64,
     pub avg_response_time_ms: u64,
     pub quality_score: f64,
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_provider_error_kind_looks_through_context() {
+        let error = anyhow::Error::new(AIError::RateLimited).context("Analysis failed");
+        assert_eq!(provider_error_kind(&error), Some("rate_limited"));
+        assert_eq!(provider_error_kind(&anyhow::anyhow!("API key required")), None);
+    }
+
+    #[tokio::test]
+    async fn test_provider_error_kind_counts_timeouts() {
+        let elapsed = tokio::time::timeout(Duration::ZERO, std::future::pending::<()>()).await.unwrap_err();
+        let error = anyhow::Error::new(elapsed).context("Analysis timed out after 0 seconds");
+        assert_eq!(provider_error_kind(&error), Some("timeout"));
+    }
+
+    #[test]
+    fn test_prometheus_output_covers_analyses_and_websockets() {
+        let collector = Arc::new(MetricsCollector::new());
+        collector.record_http("GET", "/api/projects", Duration::from_millis(20), 200);
+        collector.record_analysis("openai", "gpt-4o", Duration::from_secs(12), Ok(()));
+        collector.record_analysis("openai", "gpt-4o", Duration::from_secs(3), Err(Some("authentication")));
+        collector.record_analysis("openai", "gpt-4o", Duration::from_secs(1), Err(None));
+
+        let stream = collector.track_websocket("stream");
+        let _analysis = collector.track_websocket("analysis");
+        let _second_stream = collector.track_websocket("stream");
+        drop(stream);
+
+        let mut out = Exposition::new();
+        collector.write_prometheus(&mut out);
+        let text = out.finish();
+
+        assert!(text.contains("synapse_http_requests_total{method=\"GET\",route=\"/api/projects\",status=\"200\"} 1\n"));
+        assert!(text.contains("synapse_http_request_duration_seconds_bucket{method=\"GET\",route=\"/api/projects\",le=\"0.025\"} 1\n"));
+        assert!(text.contains("synapse_analysis_duration_seconds_count{provider=\"openai\",model=\"gpt-4o\"} 3\n"));
+        assert!(text.contains("synapse_analysis_duration_seconds_bucket{provider=\"openai\",model=\"gpt-4o\",le=\"10\"} 2\n"));
+        assert!(text.contains("synapse_analyses_total{provider=\"openai\",model=\"gpt-4o\",outcome=\"failure\"} 2\n"));
+        assert!(text.contains("synapse_provider_errors_total{provider=\"openai\",kind=\"authentication\"} 1\n"));
+        assert!(text.contains("synapse_websocket_connections{kind=\"analysis\"} 1\n"));
+        assert!(text.contains("synapse_websocket_connections{kind=\"stream\"} 1\n"));
+    }
+}
@@ -1,2 +1,5 @@
+pub mod access;
+pub mod auth;
 pub mod metrics;
-
+pub mod prometheus;
+pub mod rate_limit;
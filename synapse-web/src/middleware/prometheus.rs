@@ -0,0 +1,213 @@
+use std::fmt::Write;
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+};
+
+use crate::{
+    circuit_breaker::CircuitBreakerState,
+    error_handling::AppError,
+    middleware::{
+        auth::{bearer_token, hash_secret},
+        metrics::Histogram,
+    },
+    AppState,
+};
+
+/// Content type of the Prometheus text exposition format
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Metrics written in the Prometheus text exposition format
+#[derive(Debug, Default)]
+pub struct Exposition {
+    out: String,
+}
+
+impl Exposition {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a metric family; its samples must follow before the next header
+    pub fn header(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.out, "# HELP {} {}", name, help);
+        let _ = writeln!(self.out, "# TYPE {} {}", name, kind);
+    }
+
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        self.out.push_str(name);
+        write_labels(&mut self.out, labels, None);
+        let _ = writeln!(self.out, " {}", format_value(value));
+    }
+
+    /// Write a histogram's cumulative buckets, sum and count
+    pub fn histogram(&mut self, name: &str, labels: &[(&str, &str)], histogram: &Histogram) {
+        for (bound, count) in histogram.cumulative_buckets() {
+            let le = bound.map(format_value).unwrap_or_else(|| "+Inf".to_string());
+            let _ = write!(self.out, "{}_bucket", name);
+            write_labels(&mut self.out, labels, Some(&le));
+            let _ = writeln!(self.out, " {}", count);
+        }
+        self.sample(&format!("{}_sum", name), labels, histogram.sum());
+        self.sample(&format!("{}_count", name), labels, histogram.count() as f64);
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+}
+
+fn write_labels(out: &mut String, labels: &[(&str, &str)], le: Option<&str>) {
+    let labels = labels.iter().copied().chain(le.map(|le| ("le", le)));
+    let mut first = true;
+    for (name, value) in labels {
+        out.push(if first { '{' } else { ',' });
+        first = false;
+        let _ = write!(out, "{}=\"{}\"", name, escape_label(value));
+    }
+    if !first {
+        out.push('}');
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Render every metric the server keeps
+pub async fn render(state: &AppState) -> String {
+    let mut out = Exposition::new();
+    state.metrics_collector.write_prometheus(&mut out);
+
+    let breakers = {
+        let mut breakers = state.circuit_breakers.list_breakers();
+        breakers.sort_by(|a, b| a.0.cmp(&b.0));
+        breakers
+    };
+    out.header("synapse_circuit_breaker_state", "gauge", "1 for the state each circuit breaker is in");
+    for (name, current, _, _) in &breakers {
+        for (label, breaker_state) in [
+            ("closed", CircuitBreakerState::Closed),
+            ("open", CircuitBreakerState::Open),
+            ("half_open", CircuitBreakerState::HalfOpen),
+        ] {
+            let value = if *current == breaker_state { 1.0 } else { 0.0 };
+            out.sample("synapse_circuit_breaker_state", &[("breaker", name), ("state", label)], value);
+        }
+    }
+    out.header("synapse_circuit_breaker_failures", "gauge", "Failures counted towards opening each circuit breaker");
+    for (name, _, failures, _) in &breakers {
+        out.sample("synapse_circuit_breaker_failures", &[("breaker", name)], *failures as f64);
+    }
+    out.header("synapse_circuit_breaker_successes", "gauge", "Successes counted towards closing each half-open circuit breaker");
+    for (name, _, _, successes) in &breakers {
+        out.sample("synapse_circuit_breaker_successes", &[("breaker", name)], *successes as f64);
+    }
+
+    let mut caches: Vec<_> = state.cache_manager.get_cache_stats().into_iter().collect();
+    caches.sort_by(|a, b| a.0.cmp(&b.0));
+    out.header("synapse_cache_hits_total", "counter", "Cache lookups that found an entry");
+    for (cache, stats) in &caches {
+        out.sample("synapse_cache_hits_total", &[("cache", cache)], stats.hits as f64);
+    }
+    out.header("synapse_cache_misses_total", "counter", "Cache lookups that found nothing");
+    for (cache, stats) in &caches {
+        out.sample("synapse_cache_misses_total", &[("cache", cache)], stats.misses as f64);
+    }
+    out.header("synapse_cache_evictions_total", "counter", "Entries evicted to make room");
+    for (cache, stats) in &caches {
+        out.sample("synapse_cache_evictions_total", &[("cache", cache)], stats.evictions as f64);
+    }
+    out.header("synapse_cache_hit_ratio", "gauge", "Share of cache lookups that found an entry");
+    for (cache, stats) in &caches {
+        out.sample("synapse_cache_hit_ratio", &[("cache", cache)], stats.hit_rate());
+    }
+    out.header("synapse_cache_entries", "gauge", "Entries held in each cache");
+    for (cache, stats) in &caches {
+        out.sample("synapse_cache_entries", &[("cache", cache)], stats.current_size as f64);
+    }
+
+    out.header("synapse_streaming_sources_active", "gauge", "Streaming sources running");
+    let active_sources = state.streaming_manager.read().await.active_source_count();
+    out.sample("synapse_streaming_sources_active", &[], active_sources as f64);
+    out.header("synapse_streaming_entries_received_total", "counter", "Log entries received by streaming sources");
+    for ((project_id, source), count) in state.streaming_hub.entries_received() {
+        let project_id = project_id.to_string();
+        out.sample(
+            "synapse_streaming_entries_received_total",
+            &[("project", &project_id), ("source", &source)],
+            count as f64,
+        );
+    }
+
+    out.finish()
+}
+
+/// `GET /metrics`: every metric in the Prometheus text format
+///
+/// Needs `Authorization: Bearer <SYNAPSE_METRICS_TOKEN>` when a metrics token is configured.
+pub async fn metrics_handler(State(state): State<AppState>, headers: HeaderMap) -> Result<Response, AppError> {
+    if let Some(expected) = &state.config.metrics_token {
+        // Compare digests so the check doesn't leak how much of the token matched
+        let authorized = bearer_token(&headers).is_some_and(|token| hash_secret(token) == hash_secret(expected));
+        if !authorized {
+            return Err(AppError::Unauthorized);
+        }
+    }
+
+    Ok(([(header::CONTENT_TYPE, CONTENT_TYPE)], render(&state).await).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::metrics::HTTP_LATENCY_BUCKETS;
+
+    #[test]
+    fn test_samples_escape_labels() {
+        let mut out = Exposition::new();
+        out.header("synapse_test_total", "counter", "Test counter");
+        out.sample("synapse_test_total", &[], 3.0);
+        out.sample("synapse_test_total", &[("route", "/a\"b\\c\nd"), ("status", "200")], 0.5);
+
+        assert_eq!(
+            out.finish(),
+            "# HELP synapse_test_total Test counter\n\
+             # TYPE synapse_test_total counter\n\
+             synapse_test_total 3\n\
+             synapse_test_total{route=\"/a\\\"b\\\\c\\nd\",status=\"200\"} 0.5\n"
+        );
+    }
+
+    #[test]
+    fn test_histogram_buckets_are_cumulative() {
+        let mut histogram = Histogram::new(HTTP_LATENCY_BUCKETS);
+        histogram.observe(0.003);
+        histogram.observe(0.2);
+        histogram.observe(42.0);
+
+        let mut out = Exposition::new();
+        out.histogram("synapse_latency_seconds", &[("route", "/x")], &histogram);
+        let text = out.finish();
+
+        assert!(text.contains("synapse_latency_seconds_bucket{route=\"/x\",le=\"0.005\"} 1\n"));
+        assert!(text.contains("synapse_latency_seconds_bucket{route=\"/x\",le=\"0.1\"} 1\n"));
+        assert!(text.contains("synapse_latency_seconds_bucket{route=\"/x\",le=\"0.25\"} 2\n"));
+        assert!(text.contains("synapse_latency_seconds_bucket{route=\"/x\",le=\"10\"} 2\n"));
+        assert!(text.contains("synapse_latency_seconds_bucket{route=\"/x\",le=\"+Inf\"} 3\n"));
+        assert!(text.contains("synapse_latency_seconds_sum{route=\"/x\"} 42.203\n"));
+        assert!(text.contains("synapse_latency_seconds_count{route=\"/x\"} 3\n"));
+    }
+}
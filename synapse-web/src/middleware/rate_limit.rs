@@ -0,0 +1,360 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Days, Utc};
+
+use crate::{
+    config::{RateLimit, RateLimits},
+    error_handling::AppError,
+    middleware::{
+        auth::{ClientIp, CurrentUser},
+        metrics::MetricsCollector,
+    },
+    AppState,
+};
+
+/// Buckets kept before the least recently used ones are dropped
+const MAX_BUCKETS: usize = 10_000;
+
+/// Buckets dropped at once when the limit is reached, so a flood of new callers
+/// does not scan every bucket on each request
+const EVICTED_BUCKETS: usize = MAX_BUCKETS / 10;
+
+/// Name daily analysis quotas are reported under in the metrics
+pub const ANALYSIS_QUOTA_METRIC: &str = "analysis_quota";
+
+/// Groups of routes with their own rate limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LimitedRoute {
+    Ingest,
+    Analysis,
+    Upload,
+    Login,
+    ShareUnlock,
+}
+
+impl LimitedRoute {
+    pub const ALL: [LimitedRoute; 5] = [
+        LimitedRoute::Ingest,
+        LimitedRoute::Analysis,
+        LimitedRoute::Upload,
+        LimitedRoute::Login,
+        LimitedRoute::ShareUnlock,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LimitedRoute::Ingest => "ingest",
+            LimitedRoute::Analysis => "analysis",
+            LimitedRoute::Upload => "upload",
+            LimitedRoute::Login => "login",
+            LimitedRoute::ShareUnlock => "share_unlock",
+        }
+    }
+}
+
+impl fmt::Display for LimitedRoute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn full(limit: RateLimit, now: Instant) -> Self {
+        Self {
+            tokens: burst(limit),
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, limit: RateLimit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * refill_rate(limit)).min(burst(limit));
+        self.updated = now;
+    }
+
+    /// Take a token, or return how long until one is available
+    fn take(&mut self, limit: RateLimit, now: Instant) -> Result<(), Duration> {
+        self.refill(limit, now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / refill_rate(limit)))
+        }
+    }
+}
+
+/// Tokens per second
+fn refill_rate(limit: RateLimit) -> f64 {
+    f64::from(limit.per_minute) / 60.0
+}
+
+fn burst(limit: RateLimit) -> f64 {
+    f64::from(limit.burst.max(1))
+}
+
+/// Token buckets per caller and route group, kept in memory
+pub struct RateLimiter {
+    limits: RateLimits,
+    buckets: Mutex<HashMap<(LimitedRoute, String), Bucket>>,
+    metrics: Arc<MetricsCollector>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits, metrics: Arc<MetricsCollector>) -> Self {
+        for route in LimitedRoute::ALL {
+            let limit = limits.get(route);
+            if limit.is_enabled() {
+                metrics.register_rate_limit(route.as_str(), Some(limit.per_minute), Some(limit.burst.max(1)));
+            }
+        }
+
+        Self {
+            limits,
+            buckets: Mutex::new(HashMap::new()),
+            metrics,
+        }
+    }
+
+    /// Count a request from `caller` against its bucket for `route`
+    ///
+    /// Returns how long the caller has to wait when the bucket is empty.
+    pub fn check(&self, route: LimitedRoute, caller: &str) -> Result<(), Duration> {
+        let limit = self.limits.get(route);
+        if !limit.is_enabled() {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let key = (route, caller.to_string());
+        let result = {
+            let mut buckets = self.buckets.lock().unwrap();
+            if buckets.len() >= MAX_BUCKETS && !buckets.contains_key(&key) {
+                evict_least_recent(&mut buckets, EVICTED_BUCKETS);
+            }
+
+            buckets
+                .entry(key)
+                .or_insert_with(|| Bucket::full(limit, now))
+                .take(limit, now)
+        };
+
+        self.metrics.record_rate_limit(route.as_str(), result.is_ok());
+        result
+    }
+}
+
+/// Drop at least `count` of the buckets used longest ago
+fn evict_least_recent<K: Eq + Hash>(buckets: &mut HashMap<K, Bucket>, count: usize) {
+    if count == 0 || buckets.is_empty() {
+        return;
+    }
+    let mut used: Vec<Instant> = buckets.values().map(|bucket| bucket.updated).collect();
+    let index = count.min(used.len()) - 1;
+    let (_, &mut cutoff, _) = used.select_nth_unstable(index);
+    buckets.retain(|_, bucket| bucket.updated > cutoff);
+}
+
+impl RateLimits {
+    pub fn get(&self, route: LimitedRoute) -> RateLimit {
+        match route {
+            LimitedRoute::Ingest => self.ingest,
+            LimitedRoute::Analysis => self.analysis,
+            LimitedRoute::Upload => self.upload,
+            LimitedRoute::Login => self.login,
+            LimitedRoute::ShareUnlock => self.share_unlock,
+        }
+    }
+}
+
+/// Who a request counts against: its account, else its address
+///
+/// A user's API tokens and sessions share one bucket, and unchecked credentials are never
+/// used as keys, so a caller cannot get fresh buckets by sending made-up tokens.
+pub fn caller_key(current_user: Option<&CurrentUser>, client_ip: &ClientIp) -> String {
+    match current_user.and_then(CurrentUser::user_id) {
+        Some(user_id) => format!("user:{}", user_id),
+        None => address_key(client_ip),
+    }
+}
+
+/// Bucket key of a client address
+pub fn address_key(client_ip: &ClientIp) -> String {
+    format!("ip:{}", client_ip.0.as_deref().unwrap_or("unknown"))
+}
+
+/// Reject the request with 429 once the caller has used up its limit for the route group
+///
+/// Layered on individual routes after [`crate::middleware::auth::require_auth`].
+pub async fn rate_limit(
+    State((state, route)): State<(AppState, LimitedRoute)>,
+    req: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let client_ip = ClientIp::of(&state.config, req.headers(), req.extensions());
+    let caller = caller_key(req.extensions().get::<CurrentUser>(), &client_ip);
+    enforce(&state, route, &caller)?;
+    Ok(next.run(req).await)
+}
+
+/// Count a request from `caller` against its limit for the route group, answering 429 once
+/// it is used up
+pub fn enforce(state: &AppState, route: LimitedRoute, caller: &str) -> Result<(), AppError> {
+    state
+        .rate_limiter
+        .check(route, caller)
+        .map_err(|retry_after| AppError::rate_limited(format!("Too many {} requests", route), retry_after))
+}
+
+/// The project's cap on analyses per UTC day: its own quota, else the server default; 0 for none
+pub async fn daily_analysis_quota(state: &AppState, project_id: &str) -> Result<u32, AppError> {
+    let quota = state.db.storage().get_project_analysis_quota(project_id).await?;
+    Ok(match quota {
+        Some(quota) => quota.max(0) as u32,
+        None => state.config.daily_analysis_quota,
+    })
+}
+
+/// Start of the current UTC day, when daily quotas reset
+pub fn quota_day_start(now: DateTime<Utc>) -> DateTime<Utc> {
+    now.date_naive().and_hms_opt(0, 0, 0).unwrap().and_utc()
+}
+
+/// Reject a new analysis with 429 once the project has used its quota for the day
+pub async fn check_analysis_quota(state: &AppState, project_id: &str) -> Result<(), AppError> {
+    let quota = daily_analysis_quota(state, project_id).await?;
+    if quota == 0 {
+        return Ok(());
+    }
+
+    let now = Utc::now();
+    let day_start = quota_day_start(now);
+    let used = state.db.storage().count_project_analyses_since(project_id, day_start).await?;
+    let allowed = used < i64::from(quota);
+    state.metrics_collector.record_rate_limit(ANALYSIS_QUOTA_METRIC, allowed);
+
+    if allowed {
+        Ok(())
+    } else {
+        let resets_at = day_start + Days::new(1);
+        Err(AppError::rate_limited(
+            format!("Project {} has used its {} analyses for today", project_id, quota),
+            (resets_at - now).to_std().unwrap_or_default(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use synapse_core::project::User;
+
+    use super::*;
+    use crate::middleware::auth::AuthMethod;
+
+    fn limiter(limit: RateLimit) -> RateLimiter {
+        let limits = RateLimits {
+            ingest: limit,
+            analysis: limit,
+            upload: RateLimit::new(0, 0),
+            login: limit,
+            share_unlock: limit,
+        };
+        RateLimiter::new(limits, Arc::new(MetricsCollector::new()))
+    }
+
+    #[test]
+    fn test_bucket_allows_burst_then_refills() {
+        let limit = RateLimit::new(60, 3);
+        let start = Instant::now();
+        let mut bucket = Bucket::full(limit, start);
+
+        for _ in 0..3 {
+            assert!(bucket.take(limit, start).is_ok());
+        }
+        let wait = bucket.take(limit, start).unwrap_err();
+        assert!(wait <= Duration::from_secs(1) && wait > Duration::from_millis(900));
+
+        assert!(bucket.take(limit, start + Duration::from_secs(1)).is_ok());
+        assert!(bucket.take(limit, start + Duration::from_secs(1)).is_err());
+        // Refilling stops at the burst size
+        bucket.refill(limit, start + Duration::from_secs(3600));
+        assert_eq!(bucket.tokens, 3.0);
+    }
+
+    #[test]
+    fn test_limiter_keeps_callers_and_routes_apart() {
+        let limiter = limiter(RateLimit::new(1, 2));
+
+        assert!(limiter.check(LimitedRoute::Ingest, "user:a").is_ok());
+        assert!(limiter.check(LimitedRoute::Ingest, "user:a").is_ok());
+        assert!(limiter.check(LimitedRoute::Ingest, "user:a").is_err());
+        assert!(limiter.check(LimitedRoute::Ingest, "user:b").is_ok());
+        assert!(limiter.check(LimitedRoute::Analysis, "user:a").is_ok());
+        // A limit of 0 per minute is off
+        for _ in 0..10 {
+            assert!(limiter.check(LimitedRoute::Upload, "user:a").is_ok());
+        }
+
+        let summary = limiter.metrics.get_metrics_summary();
+        let ingest = summary.rate_limits.iter().find(|metrics| metrics.name == "ingest").unwrap();
+        assert_eq!((ingest.allowed, ingest.rejected), (3, 1));
+        assert_eq!((ingest.per_minute, ingest.burst), (Some(1), Some(2)));
+        assert!(!summary.rate_limits.iter().any(|metrics| metrics.name == "upload"));
+    }
+
+    #[test]
+    fn test_eviction_drops_least_recently_used_buckets() {
+        let limit = RateLimit::new(60, 3);
+        let start = Instant::now();
+        let mut buckets: HashMap<&str, Bucket> = ["a", "b", "c", "d"]
+            .into_iter()
+            .enumerate()
+            .map(|(age, caller)| (caller, Bucket::full(limit, start + Duration::from_secs(age as u64))))
+            .collect();
+        // "a" is the oldest, but using it again makes "b" the least recent
+        buckets.get_mut("a").unwrap().take(limit, start + Duration::from_secs(10)).unwrap();
+
+        evict_least_recent(&mut buckets, 2);
+        let mut kept: Vec<_> = buckets.keys().copied().collect();
+        kept.sort();
+        assert_eq!(kept, ["a", "d"]);
+    }
+
+    #[test]
+    fn test_caller_key_prefers_account_then_address() {
+        let client_ip = ClientIp(Some("198.51.100.1".to_string()));
+        assert_eq!(caller_key(Some(&CurrentUser::local()), &client_ip), "ip:198.51.100.1");
+        assert_eq!(caller_key(None, &ClientIp(None)), "ip:unknown");
+
+        let user = User::new("alice".to_string(), None, false);
+        let current_user = CurrentUser {
+            user: Some(user.clone()),
+            method: AuthMethod::ApiToken,
+            ip_address: client_ip.0.clone(),
+        };
+        assert_eq!(caller_key(Some(&current_user), &client_ip), format!("user:{}", user.id));
+    }
+
+    #[test]
+    fn test_quota_day_starts_at_utc_midnight() {
+        let now = DateTime::parse_from_rfc3339("2025-03-04T17:45:12Z").unwrap().with_timezone(&Utc);
+        assert_eq!(quota_day_start(now).to_rfc3339(), "2025-03-04T00:00:00+00:00");
+    }
+}
@@ -0,0 +1,561 @@
+//! OpenID Connect authorization-code login (with PKCE) against an external identity provider
+
+use std::time::{Duration, Instant};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tokio::sync::RwLock;
+
+use crate::{
+    config::OidcConfig,
+    error_handling::AppError,
+    middleware::auth::generate_secret,
+};
+
+/// Allowed clock difference between us and the provider when checking token expiry
+const CLOCK_SKEW_SECS: i64 = 60;
+
+/// Minimum time between key set refreshes triggered by unknown key ids
+const JWKS_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Jwk {
+    kty: String,
+    kid: Option<String>,
+    // RSA
+    n: Option<String>,
+    e: Option<String>,
+    // EC
+    crv: Option<String>,
+    x: Option<String>,
+    y: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JwtHeader {
+    alg: String,
+    kid: Option<String>,
+}
+
+/// A login redirect to the provider and the values needed to finish it
+#[derive(Debug, Clone)]
+pub struct AuthorizationRequest {
+    pub url: String,
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+}
+
+/// The verified identity from an ID token
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdentityClaims {
+    pub subject: String,
+    /// `preferred_username`, falling back to the email address and then the subject
+    pub username: String,
+    pub email: Option<String>,
+    pub name: Option<String>,
+    pub groups: Vec<String>,
+}
+
+/// Client for one provider, caching its discovery document and signing keys
+pub struct OidcClient {
+    config: OidcConfig,
+    http: reqwest::Client,
+    metadata: RwLock<Option<ProviderMetadata>>,
+    keys: RwLock<(Vec<Jwk>, Option<Instant>)>,
+}
+
+impl OidcClient {
+    pub fn new(config: OidcConfig) -> Self {
+        Self {
+            config,
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap_or_default(),
+            metadata: RwLock::new(None),
+            keys: RwLock::new((Vec::new(), None)),
+        }
+    }
+
+    pub fn config(&self) -> &OidcConfig {
+        &self.config
+    }
+
+    /// Build the provider login URL with fresh state, nonce and PKCE verifier
+    pub async fn authorization_request(&self) -> Result<AuthorizationRequest, AppError> {
+        let metadata = self.metadata().await?;
+        let state = generate_secret();
+        let nonce = generate_secret();
+        let code_verifier = generate_secret();
+        let code_challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+
+        let mut url = reqwest::Url::parse(&metadata.authorization_endpoint)
+            .map_err(|e| AppError::internal(format!("Invalid OIDC authorization endpoint: {}", e)))?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("redirect_uri", &self.config.redirect_url)
+            .append_pair("scope", &self.config.scopes.join(" "))
+            .append_pair("state", &state)
+            .append_pair("nonce", &nonce)
+            .append_pair("code_challenge", &code_challenge)
+            .append_pair("code_challenge_method", "S256");
+
+        Ok(AuthorizationRequest {
+            url: url.into(),
+            state,
+            nonce,
+            code_verifier,
+        })
+    }
+
+    /// Redeem an authorization code and verify the returned ID token
+    pub async fn exchange_code(
+        &self,
+        code: &str,
+        code_verifier: &str,
+        nonce: &str,
+    ) -> Result<IdentityClaims, AppError> {
+        let metadata = self.metadata().await?;
+
+        let mut request = self.http.post(&metadata.token_endpoint).form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.config.redirect_url.as_str()),
+            ("client_id", self.config.client_id.as_str()),
+            ("code_verifier", code_verifier),
+        ]);
+        if let Some(secret) = &self.config.client_secret {
+            request = request.basic_auth(&self.config.client_id, Some(secret));
+        }
+
+        let response = request.send().await.map_err(|e| {
+            tracing::error!("OIDC token request failed: {}", e);
+            AppError::service_unavailable("identity provider")
+        })?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            tracing::warn!("OIDC token endpoint returned {}: {}", status, body);
+            return Err(AppError::Unauthorized);
+        }
+
+        let token: TokenResponse = response.json().await.map_err(|e| {
+            tracing::error!("Invalid OIDC token response: {}", e);
+            AppError::service_unavailable("identity provider")
+        })?;
+        let id_token = token.id_token.ok_or_else(|| {
+            tracing::warn!("OIDC token response has no id_token; is the 'openid' scope requested?");
+            AppError::Unauthorized
+        })?;
+
+        self.verify_id_token(&id_token, nonce).await
+    }
+
+    /// Check an ID token's signature, issuer, audience, expiry and nonce
+    async fn verify_id_token(&self, token: &str, nonce: &str) -> Result<IdentityClaims, AppError> {
+        let invalid = |reason: &str| {
+            tracing::warn!("Rejected OIDC ID token: {}", reason);
+            AppError::Unauthorized
+        };
+
+        let mut parts = token.split('.');
+        let (Some(header), Some(payload), Some(signature), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid("not a JWS compact token"));
+        };
+
+        let header: JwtHeader = decode_json(header).ok_or_else(|| invalid("malformed header"))?;
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| invalid("malformed signature"))?;
+        let signed = &token[..header_and_payload_len(token)];
+
+        let key = self.signing_key(header.kid.as_deref()).await?;
+        verify_signature(&key, &header.alg, signed.as_bytes(), &signature)
+            .map_err(|reason| invalid(&reason))?;
+
+        let claims: Value = decode_json(payload).ok_or_else(|| invalid("malformed claims"))?;
+        let metadata = self.metadata().await?;
+        validate_claims(&claims, &metadata.issuer, &self.config.client_id, nonce)
+            .map_err(|reason| invalid(&reason))?;
+
+        identity_from_claims(&claims, &self.config.groups_claim).ok_or_else(|| invalid("no subject"))
+    }
+
+    async fn metadata(&self) -> Result<ProviderMetadata, AppError> {
+        if let Some(metadata) = self.metadata.read().await.clone() {
+            return Ok(metadata);
+        }
+
+        let url = format!("{}/.well-known/openid-configuration", self.config.issuer_url);
+        let metadata: ProviderMetadata = self.fetch_json(&url).await?;
+        if metadata.issuer.trim_end_matches('/') != self.config.issuer_url {
+            tracing::error!(
+                "OIDC discovery issuer {} does not match configured issuer {}",
+                metadata.issuer,
+                self.config.issuer_url
+            );
+            return Err(AppError::internal("OIDC issuer mismatch"));
+        }
+
+        *self.metadata.write().await = Some(metadata.clone());
+        Ok(metadata)
+    }
+
+    /// Find the key for `kid`, refreshing the key set when the provider may have rotated keys
+    async fn signing_key(&self, kid: Option<&str>) -> Result<Jwk, AppError> {
+        let find = |keys: &[Jwk]| {
+            keys.iter()
+                .find(|key| kid.is_none() || key.kid.as_deref() == kid)
+                .cloned()
+        };
+
+        {
+            let keys = self.keys.read().await;
+            if let Some(key) = find(&keys.0) {
+                return Ok(key);
+            }
+            if keys.1.is_some_and(|fetched| fetched.elapsed() < JWKS_REFRESH_INTERVAL) {
+                tracing::warn!("OIDC ID token signed with unknown key {:?}", kid);
+                return Err(AppError::Unauthorized);
+            }
+        }
+
+        let metadata = self.metadata().await?;
+        let jwks: JwkSet = self.fetch_json(&metadata.jwks_uri).await?;
+        let key = find(&jwks.keys);
+        *self.keys.write().await = (jwks.keys, Some(Instant::now()));
+
+        key.ok_or_else(|| {
+            tracing::warn!("OIDC ID token signed with unknown key {:?}", kid);
+            AppError::Unauthorized
+        })
+    }
+
+    async fn fetch_json<T: serde::de::DeserializeOwned>(&self, url: &str) -> Result<T, AppError> {
+        let response = self
+            .http
+            .get(url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| {
+                tracing::error!("Failed to fetch {}: {}", url, e);
+                AppError::service_unavailable("identity provider")
+            })?;
+
+        response.json().await.map_err(|e| {
+            tracing::error!("Invalid JSON from {}: {}", url, e);
+            AppError::service_unavailable("identity provider")
+        })
+    }
+}
+
+fn decode_json<T: serde::de::DeserializeOwned>(segment: &str) -> Option<T> {
+    let bytes = URL_SAFE_NO_PAD.decode(segment).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+/// Length of the `header.payload` prefix that the signature covers
+fn header_and_payload_len(token: &str) -> usize {
+    token.rfind('.').unwrap_or(token.len())
+}
+
+fn verify_signature(key: &Jwk, alg: &str, message: &[u8], signature: &[u8]) -> Result<(), String> {
+    let decode = |value: &Option<String>| {
+        value
+            .as_deref()
+            .and_then(|value| URL_SAFE_NO_PAD.decode(value).ok())
+            .ok_or_else(|| format!("incomplete {} key", key.kty))
+    };
+
+    let verified = match (alg, key.kty.as_str()) {
+        ("RS256", "RSA") => RsaPublicKeyComponents {
+            n: decode(&key.n)?,
+            e: decode(&key.e)?,
+        }
+        .verify(&signature::RSA_PKCS1_2048_8192_SHA256, message, signature),
+        ("ES256", "EC") if key.crv.as_deref() == Some("P-256") => {
+            let mut point = vec![0x04];
+            point.extend(decode(&key.x)?);
+            point.extend(decode(&key.y)?);
+            UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, point).verify(message, signature)
+        }
+        _ => return Err(format!("unsupported algorithm {} for {} key", alg, key.kty)),
+    };
+
+    verified.map_err(|_| "bad signature".to_string())
+}
+
+fn validate_claims(claims: &Value, issuer: &str, client_id: &str, nonce: &str) -> Result<(), String> {
+    if claims["iss"].as_str() != Some(issuer) {
+        return Err(format!("issuer {} is not {}", claims["iss"], issuer));
+    }
+
+    let audience_ok = match &claims["aud"] {
+        Value::String(aud) => aud == client_id,
+        Value::Array(auds) => auds.iter().any(|aud| aud.as_str() == Some(client_id)),
+        _ => false,
+    };
+    if !audience_ok {
+        return Err(format!("audience {} does not include {}", claims["aud"], client_id));
+    }
+    if claims["azp"].as_str().is_some_and(|azp| azp != client_id) {
+        return Err("token was issued to another client".to_string());
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    match claims["exp"].as_i64() {
+        Some(exp) if exp + CLOCK_SKEW_SECS > now => {}
+        _ => return Err("token has expired".to_string()),
+    }
+
+    if claims["nonce"].as_str() != Some(nonce) {
+        return Err("nonce mismatch".to_string());
+    }
+
+    Ok(())
+}
+
+fn identity_from_claims(claims: &Value, groups_claim: &str) -> Option<IdentityClaims> {
+    let subject = claims["sub"].as_str().filter(|sub| !sub.is_empty())?.to_string();
+    let string = |name: &str| {
+        claims[name]
+            .as_str()
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(str::to_string)
+    };
+
+    let groups = match &claims[groups_claim] {
+        Value::Array(groups) => groups.iter().filter_map(|group| group.as_str().map(str::to_string)).collect(),
+        Value::String(group) => vec![group.clone()],
+        _ => Vec::new(),
+    };
+
+    Some(IdentityClaims {
+        username: string("preferred_username")
+            .or_else(|| string("email"))
+            .unwrap_or_else(|| subject.clone()),
+        email: string("email"),
+        name: string("name"),
+        subject,
+        groups,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::OidcGroupRole;
+    use axum::{extract::State, routing::{get, post}, Form, Json, Router};
+    use ring::{
+        rand::SystemRandom,
+        signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+    };
+    use std::{collections::HashMap, sync::Arc};
+    use synapse_core::project::ProjectRole;
+
+    const CLIENT_ID: &str = "synapse";
+
+    /// A local identity provider that signs ID tokens with a fresh P-256 key.
+    /// The authorization code doubles as the nonce to embed in the token.
+    struct MockIssuer {
+        issuer: String,
+        key_pair: EcdsaKeyPair,
+    }
+
+    impl MockIssuer {
+        fn sign(&self, claims: Value) -> String {
+            let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"ES256","kid":"test-key"}"#);
+            let payload = URL_SAFE_NO_PAD.encode(claims.to_string());
+            let message = format!("{}.{}", header, payload);
+            let signature = self.key_pair.sign(&SystemRandom::new(), message.as_bytes()).unwrap();
+            format!("{}.{}", message, URL_SAFE_NO_PAD.encode(signature.as_ref()))
+        }
+
+        fn claims(&self, nonce: &str) -> Value {
+            serde_json::json!({
+                "iss": self.issuer,
+                "sub": "user-123",
+                "aud": CLIENT_ID,
+                "exp": chrono::Utc::now().timestamp() + 300,
+                "nonce": nonce,
+                "preferred_username": "jdoe",
+                "email": "jdoe@example.com",
+                "groups": ["sre", "everyone"],
+            })
+        }
+
+        fn jwk(&self) -> Value {
+            let point = self.key_pair.public_key().as_ref();
+            serde_json::json!({
+                "kty": "EC",
+                "crv": "P-256",
+                "kid": "test-key",
+                "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+                "y": URL_SAFE_NO_PAD.encode(&point[33..]),
+            })
+        }
+    }
+
+    async fn start_mock_issuer() -> Arc<MockIssuer> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let mock = Arc::new(MockIssuer {
+            issuer: issuer.clone(),
+            key_pair: EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng)
+                .unwrap(),
+        });
+
+        let app = Router::new()
+            .route(
+                "/.well-known/openid-configuration",
+                get(|State(mock): State<Arc<MockIssuer>>| async move {
+                    Json(serde_json::json!({
+                        "issuer": mock.issuer,
+                        "authorization_endpoint": format!("{}/authorize", mock.issuer),
+                        "token_endpoint": format!("{}/token", mock.issuer),
+                        "jwks_uri": format!("{}/jwks", mock.issuer),
+                    }))
+                }),
+            )
+            .route(
+                "/jwks",
+                get(|State(mock): State<Arc<MockIssuer>>| async move {
+                    Json(serde_json::json!({ "keys": [mock.jwk()] }))
+                }),
+            )
+            .route(
+                "/token",
+                post(
+                    |State(mock): State<Arc<MockIssuer>>, Form(form): Form<HashMap<String, String>>| async move {
+                        assert_eq!(form["grant_type"], "authorization_code");
+                        assert!(form.contains_key("code_verifier"));
+                        let id_token = mock.sign(mock.claims(&form["code"]));
+                        Json(serde_json::json!({ "access_token": "opaque", "id_token": id_token }))
+                    },
+                ),
+            )
+            .with_state(mock.clone());
+
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        mock
+    }
+
+    fn config(issuer: &str) -> OidcConfig {
+        OidcConfig {
+            issuer_url: issuer.to_string(),
+            client_id: CLIENT_ID.to_string(),
+            client_secret: Some("secret".to_string()),
+            redirect_url: "http://localhost:3000/api/auth/oidc/callback".to_string(),
+            scopes: vec!["openid".to_string(), "profile".to_string()],
+            groups_claim: "groups".to_string(),
+            admin_groups: vec!["platform-admins".to_string()],
+            group_roles: vec![
+                OidcGroupRole::parse("everyone=project-1:viewer").unwrap(),
+                OidcGroupRole::parse("sre=project-1:analyst").unwrap(),
+                OidcGroupRole::parse("dba=project-2:admin").unwrap(),
+            ],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_login_against_mock_issuer() {
+        let mock = start_mock_issuer().await;
+        let client = OidcClient::new(config(&mock.issuer));
+
+        let request = client.authorization_request().await.unwrap();
+        assert!(request.url.starts_with(&format!("{}/authorize?", mock.issuer)));
+        assert!(request.url.contains("code_challenge_method=S256"));
+        assert!(request.url.contains(&format!("nonce={}", request.nonce)));
+
+        let identity = client
+            .exchange_code(&request.nonce, &request.code_verifier, &request.nonce)
+            .await
+            .unwrap();
+        assert_eq!(identity.subject, "user-123");
+        assert_eq!(identity.username, "jdoe");
+        assert_eq!(identity.email.as_deref(), Some("jdoe@example.com"));
+        assert_eq!(identity.groups, vec!["sre".to_string(), "everyone".to_string()]);
+
+        // A token minted for another login attempt is rejected
+        assert!(client
+            .exchange_code("other-nonce", &request.code_verifier, &request.nonce)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rejects_tampered_and_foreign_tokens() {
+        let mock = start_mock_issuer().await;
+        let client = OidcClient::new(config(&mock.issuer));
+
+        let token = mock.sign(mock.claims("n"));
+        assert!(client.verify_id_token(&token, "n").await.is_ok());
+
+        // Payload swapped after signing
+        let mut forged = mock.claims("n");
+        forged["sub"] = "admin".into();
+        let parts: Vec<&str> = token.split('.').collect();
+        let tampered = format!(
+            "{}.{}.{}",
+            parts[0],
+            URL_SAFE_NO_PAD.encode(forged.to_string()),
+            parts[2]
+        );
+        assert!(client.verify_id_token(&tampered, "n").await.is_err());
+
+        let mut other_audience = mock.claims("n");
+        other_audience["aud"] = "another-app".into();
+        assert!(client.verify_id_token(&mock.sign(other_audience), "n").await.is_err());
+
+        let mut expired = mock.claims("n");
+        expired["exp"] = (chrono::Utc::now().timestamp() - 3600).into();
+        assert!(client.verify_id_token(&mock.sign(expired), "n").await.is_err());
+    }
+
+    #[test]
+    fn test_group_grants() {
+        let config = config("https://idp.example.com");
+
+        let (is_admin, roles) = config.grants_for(&["sre".to_string(), "everyone".to_string()]);
+        assert!(!is_admin);
+        assert_eq!(roles, vec![("project-1".to_string(), ProjectRole::Analyst)]);
+
+        let (is_admin, roles) = config.grants_for(&["platform-admins".to_string()]);
+        assert!(is_admin);
+        assert!(roles.is_empty());
+
+        assert!(OidcGroupRole::parse("sre=project-1").is_err());
+        assert!(OidcGroupRole::parse("sre=project-1:owner").is_err());
+    }
+}
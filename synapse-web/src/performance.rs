@@ -105,13 +105,14 @@ pub struct PerformanceSummary {
 
 // Optimized database operations
 pub struct OptimizedDbOps {
-    pool: Pool<Sqlite>,
+    // Only present on SQLite deployments
+    pool: Option<Pool<Sqlite>>,
     cache: Arc<CacheManager>,
     monitor: Arc<PerformanceMonitor>,
 }
 
 impl OptimizedDbOps {
-    pub fn new(pool: Pool<Sqlite>, cache: Arc<CacheManager>) -> Self {
+    pub fn new(pool: Option<Pool<Sqlite>>, cache: Arc<CacheManager>) -> Self {
         let monitor = Arc::new(PerformanceMonitor::new(Arc::clone(&cache)));
         Self {
             pool,
@@ -120,6 +121,12 @@ impl OptimizedDbOps {
         }
     }
 
+    fn pool(&self) -> Result<&Pool<Sqlite>, sqlx::Error> {
+        self.pool.as_ref().ok_or_else(|| {
+            sqlx::Error::Configuration("optimized queries require the SQLite backend".into())
+        })
+    }
+
     // Optimized analysis retrieval with caching
     pub async fn get_analysis_cached(
         &self,
@@ -144,7 +151,7 @@ impl OptimizedDbOps {
         )
         .bind(analysis_id)
         .bind(project_id)
-        .fetch_optional(&self.pool)
+        .fetch_optional(self.pool()?)
         .await?;
 
         let execution_time = start.elapsed();
@@ -197,7 +204,7 @@ impl OptimizedDbOps {
         .bind(project_id)
         .bind(limit)
         .bind(offset)
-        .fetch_all(&self.pool)
+        .fetch_all(self.pool()?)
         .await?;
 
         let execution_time = start.elapsed();
@@ -244,7 +251,7 @@ impl OptimizedDbOps {
              ORDER BY is_bottleneck DESC, metric_name"
         )
         .bind(analysis_id)
-        .fetch_all(&self.pool)
+        .fetch_all(self.pool()?)
         .await?;
 
         let execution_time = start.elapsed();
@@ -299,7 +306,7 @@ impl OptimizedDbOps {
         .bind(project_id)
         .bind(analysis_id)
         .bind(analysis_id)
-        .fetch_all(&self.pool)
+        .fetch_all(self.pool()?)
         .await?;
 
         let execution_time = start.elapsed();
@@ -344,7 +351,7 @@ impl OptimizedDbOps {
             .bind(project_id)
             .bind(limit)
             .bind(offset)
-            .fetch_all(&self.pool)
+            .fetch_all(self.pool()?)
             .await?
         } else {
             let search_pattern = format!("%{}%", search_term);
@@ -374,7 +381,7 @@ impl OptimizedDbOps {
             .bind(&search_pattern)
             .bind(limit)
             .bind(offset)
-            .fetch_all(&self.pool)
+            .fetch_all(self.pool()?)
             .await?
         };
 
@@ -404,7 +411,7 @@ impl OptimizedDbOps {
 
         let start = Instant::now();
 
-        let mut transaction = self.pool.begin().await?;
+        let mut transaction = self.pool()?.begin().await?;
 
         for metric in &metrics {
             sqlx::query(
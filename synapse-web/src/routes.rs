@@ -1,53 +1,133 @@
 use axum::{
     response::IntoResponse,
-    routing::{delete, get, post},
+    routing::{delete, get, patch, post, put},
     Router,
 };
 
 use crate::{handlers, middleware, streaming, AppState};
 
-pub fn api_routes() -> Router<AppState> {
+/// All `/api` routes; everything outside [`public_routes`] goes through
+/// [`middleware::auth::require_auth`]
+pub fn api_routes(state: AppState) -> Router<AppState> {
+    public_routes()
+        .merge(ingest_routes())
+        .merge(protected_routes(state.clone()).route_layer(axum::middleware::from_fn_with_state(
+            state,
+            middleware::auth::require_auth,
+        )))
+}
+
+/// Routes reachable without signing in
+fn public_routes() -> Router<AppState> {
+    Router::new()
+        // Authentication routes
+        .route("/auth/status", get(handlers::auth::auth_status))
+        .route("/auth/register", post(handlers::auth::register))
+        .route("/auth/login", post(handlers::auth::login))
+        .route("/auth/logout", post(handlers::auth::logout))
+        .route("/auth/oidc/login", get(handlers::auth::oidc_login))
+        .route("/auth/oidc/callback", get(handlers::auth::oidc_callback))
+        // Shared analysis access
+        .route("/shared/:share_id", get(handlers::shares::view_shared_analysis))
+        .route("/shared/:share_id", post(handlers::shares::unlock_shared_analysis))
+        .route("/shared/:share_id/download", get(handlers::shares::download_shared_analysis))
+        .route("/shared/:share_id/download", post(handlers::shares::unlock_shared_download))
+}
+
+/// Ingest URLs of HTTP endpoint and OTLP streaming sources, which check the source's own token
+///
+/// The handlers rate limit each source once its token is checked, and other requests per
+/// client address. OTLP exporters may gzip their requests.
+fn ingest_routes() -> Router<AppState> {
     Router::new()
+        .route("/ingest/:path", post(handlers::streaming::ingest_to_endpoint))
+        .route(
+            "/otlp/:path/v1/logs",
+            post(handlers::streaming::ingest_otlp_logs).layer(tower_http::decompression::RequestDecompressionLayer::new()),
+        )
+}
+
+/// Routes for any signed-in user; project routes are further limited by the caller's role
+fn protected_routes(state: AppState) -> Router<AppState> {
+    Router::new()
+        // Account routes
+        .route("/auth/me", get(handlers::auth::get_current_user))
+        .route("/auth/password", post(handlers::auth::change_password))
+        .route("/auth/tokens", get(handlers::auth::list_api_tokens))
+        .route("/auth/tokens", post(handlers::auth::create_api_token))
+        .route("/auth/tokens/:id", delete(handlers::auth::revoke_api_token))
+        // User administration routes
+        .route("/users", get(handlers::auth::list_users))
+        .route("/users", post(handlers::auth::create_user))
+        .route("/users/:id", patch(handlers::auth::update_user))
         // Dashboard routes
         .route("/dashboard/stats", get(handlers::get_dashboard_stats))
-        // Project routes
+        // Project routes (listing is filtered to the caller's projects)
         .route("/projects", get(handlers::list_projects))
         .route("/projects", post(handlers::create_project))
+        .route("/knowledge/public", get(handlers::get_public_knowledge))
+        // Analysis job queue (listing is filtered to the caller's projects)
+        .route("/jobs", get(handlers::jobs::list_jobs))
+        // Model configuration routes
+        .route("/models/available", post(handlers::models::get_available_models))
+        // Metrics routes
+        .route("/metrics", get(metrics_handler_wrapper))
+        .route("/health/metrics", get(health_with_metrics_handler_wrapper))
+        .merge(server_admin_routes())
+        .merge(project_viewer_routes().route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            middleware::access::require_project_viewer,
+        )))
+        .merge(project_analyst_routes(state.clone()).route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            middleware::access::require_project_analyst,
+        )))
+        .merge(project_admin_routes().route_layer(axum::middleware::from_fn_with_state(
+            state,
+            middleware::access::require_project_admin,
+        )))
+}
+
+/// Server-wide configuration, limited to server administrators
+fn server_admin_routes() -> Router<AppState> {
+    Router::new()
         .route("/projects/sync", post(handlers::sync_cli_projects))
+        // Settings routes
+        .route("/settings", get(handlers::settings::get_settings))
+        .route("/settings", patch(handlers::settings::update_settings))
+        .route("/settings/keys", get(handlers::settings::list_api_keys))
+        .route("/settings/keys/rotate", post(handlers::settings::rotate_master_key))
+        .route("/settings/keys/:provider", put(handlers::settings::set_api_key))
+        .route("/settings/keys/:provider", delete(handlers::settings::delete_api_key))
+        .route("/models/cache/clear", post(handlers::models::clear_models_cache))
+        .route("/projects/:id/quota", put(handlers::update_project_quota))
+        .route("/projects/:project_id/streaming/retention", put(handlers::streaming::update_log_retention))
+        // Audit log routes
+        .route("/audit", get(handlers::audit::list_audit_events))
+        .route("/audit/export", get(handlers::audit::export_audit_events))
+        .route_layer(axum::middleware::from_fn(middleware::access::require_server_admin))
+}
+
+/// Read-only project routes
+fn project_viewer_routes() -> Router<AppState> {
+    Router::new()
         .route("/projects/:id", get(handlers::get_project))
-        .route("/projects/:id", delete(handlers::delete_project))
-        // File routes
+        .route("/projects/:id/members", get(handlers::list_project_members))
+        .route("/projects/:id/quota", get(handlers::get_project_quota))
         .route("/projects/:id/files", get(handlers::list_log_files))
-        .route("/projects/:id/files", post(handlers::upload_log_file))
-        .route(
-            "/projects/:project_id/files/:file_id",
-            delete(handlers::delete_log_file),
-        )
         // Analysis routes
-        .route(
-            "/projects/:project_id/files/:file_id/analyze",
-            post(handlers::start_analysis),
-        )
-        .route(
-            "/projects/:project_id/files/:file_id/analyze/ws",
-            get(handlers::websocket::websocket_analysis_handler),
-        )
-        .route("/analyses/:id", get(handlers::get_analysis))
+        .route("/analyses/:analysis_id", get(handlers::get_analysis))
         .route("/projects/:id/analyses", get(handlers::list_analyses))
-        .route("/analyses/:id/performance-metrics", get(handlers::get_performance_metrics))
+        .route("/analyses/:analysis_id/performance-metrics", get(handlers::get_performance_metrics))
+        .route("/jobs/:job_id", get(handlers::jobs::get_job))
         .route("/projects/:id/error-correlations", get(handlers::get_error_correlations))
         // MCP integration routes
-        .route("/projects/:id/mcp", post(handlers::handle_mcp_request))
-        .route("/analyses/:id/mcp", get(handlers::get_analysis_for_mcp))
+        .route("/analyses/:analysis_id/mcp", get(handlers::get_analysis_for_mcp))
         .route(
             "/projects/:id/mcp/analyses",
             get(handlers::list_analyses_for_mcp),
         )
         // Knowledge Base routes (Phase 4.1)
-        .route(
-            "/projects/:id/knowledge",
-            post(handlers::create_knowledge_entry),
-        )
         .route(
             "/projects/:id/knowledge",
             get(handlers::get_knowledge_entries),
@@ -56,36 +136,13 @@ pub fn api_routes() -> Router<AppState> {
             "/projects/:id/knowledge/:entry_id",
             get(handlers::get_knowledge_entry),
         )
-        .route("/knowledge/public", get(handlers::get_public_knowledge))
         .route("/projects/:id/patterns", get(handlers::get_error_patterns))
-        .route(
-            "/projects/:id/patterns/:pattern_id",
-            post(handlers::update_pattern_frequency),
-        )
-        .route(
-            "/projects/:id/recognize-patterns",
-            post(handlers::recognize_patterns),
-        )
         // Enhanced MCP routes (Phase 4.2)
-        .route(
-            "/projects/:id/mcp/tickets",
-            post(handlers::generate_mcp_ticket),
-        )
         .route("/projects/:id/mcp/tickets", get(handlers::get_mcp_tickets))
         .route(
             "/projects/:id/mcp/context/:analysis_id",
             get(handlers::get_mcp_context),
         )
-        // Advanced Analysis routes (Phase 4.3)
-        .route(
-            "/projects/:id/correlations",
-            post(handlers::analyze_correlations),
-        )
-        .route("/projects/:id/anomalies", post(handlers::detect_anomalies))
-        .route(
-            "/projects/:id/multi-log",
-            post(handlers::analyze_multiple_logs),
-        )
         // Export and Reporting routes (Phase 4.4)
         .route(
             "/projects/:id/analyses/:analysis_id/export/html",
@@ -107,31 +164,157 @@ pub fn api_routes() -> Router<AppState> {
             "/projects/:id/analyses/:analysis_id/export/md",
             get(handlers::export_markdown_report),
         )
-        .route("/projects/:id/share", post(handlers::create_share_link))
         .route("/projects/:id/exports", get(handlers::get_export_history))
-        // Shared analysis access
-        .route("/shared/:share_id", get(handlers::get_shared_analysis))
         // Streaming dashboard template
         .route("/projects/:project_id/dashboard", get(handlers::streaming_dashboard))
-        // Settings routes
-        .route("/settings", get(handlers::settings::get_settings))
-        .route("/settings", axum::routing::patch(handlers::settings::update_settings))
-        // Model configuration routes
-        .route("/models/available", post(handlers::models::get_available_models))
-        .route("/models/cache/clear", post(handlers::models::clear_models_cache))
         // Real-time streaming WebSocket endpoint (Phase 6.1)
         .route("/projects/:project_id/stream", get(streaming::websocket_handler))
+        .route("/projects/:project_id/streaming/sources", get(handlers::streaming::list_streaming_sources))
+        .route("/projects/:project_id/streaming/stats", get(handlers::streaming::get_streaming_stats))
+        .route("/projects/:project_id/streaming/logs", get(handlers::streaming::get_recent_logs))
+        .route("/projects/:project_id/streaming/logs/search", get(handlers::streaming::search_logs))
+        .route("/projects/:project_id/streaming/logs/counts", get(handlers::streaming::get_log_counts))
+        .route("/projects/:project_id/streaming/retention", get(handlers::streaming::get_log_retention))
+        // Alert rules and history
+        .route("/projects/:id/alerts/rules", get(handlers::alerts::list_alert_rules))
+        .route("/projects/:id/alerts/rules/:rule_id", get(handlers::alerts::get_alert_rule))
+        .route("/projects/:id/alerts", get(handlers::alerts::list_alerts))
+        .route("/projects/:id/alerts/:alert_id", get(handlers::alerts::get_alert))
+        // Analysis schedules
+        .route("/projects/:id/schedules", get(handlers::schedules::list_schedules))
+        .route("/projects/:id/schedules/:schedule_id", get(handlers::schedules::get_schedule))
+}
+
+/// Project routes that add data or run analyses
+///
+/// Uploads, analysis starts and ingest are also rate limited per caller.
+fn project_analyst_routes(state: AppState) -> Router<AppState> {
+    use middleware::rate_limit::{rate_limit, LimitedRoute};
+
+    Router::new()
+        // File routes
+        .route(
+            "/projects/:id/files",
+            post(handlers::upload_log_file).route_layer(axum::middleware::from_fn_with_state(
+                (state.clone(), LimitedRoute::Upload),
+                rate_limit,
+            )),
+        )
+        // Analysis routes
+        .route(
+            "/projects/:project_id/files/:file_id/analyze",
+            post(handlers::start_analysis).route_layer(axum::middleware::from_fn_with_state(
+                (state.clone(), LimitedRoute::Analysis),
+                rate_limit,
+            )),
+        )
+        .route(
+            "/projects/:project_id/files/:file_id/analyze/ws",
+            get(handlers::websocket::websocket_analysis_handler).route_layer(
+                axum::middleware::from_fn_with_state((state.clone(), LimitedRoute::Analysis), rate_limit),
+            ),
+        )
+        .route(
+            "/projects/:id/schedules/:schedule_id/run",
+            post(handlers::schedules::run_schedule).route_layer(axum::middleware::from_fn_with_state(
+                (state.clone(), LimitedRoute::Analysis),
+                rate_limit,
+            )),
+        )
+        .route("/jobs/:job_id/cancel", post(handlers::jobs::cancel_job))
+        // Alert triage routes
+        .route("/projects/:id/alerts/:alert_id/acknowledge", post(handlers::alerts::acknowledge_alert))
+        .route("/projects/:id/alerts/:alert_id/resolve", post(handlers::alerts::resolve_alert))
+        // MCP integration routes
+        .route("/projects/:id/mcp", post(handlers::handle_mcp_request))
+        // Knowledge Base routes (Phase 4.1)
+        .route(
+            "/projects/:id/knowledge",
+            post(handlers::create_knowledge_entry),
+        )
+        .route(
+            "/projects/:id/patterns/:pattern_id",
+            post(handlers::update_pattern_frequency),
+        )
+        .route(
+            "/projects/:id/recognize-patterns",
+            post(handlers::recognize_patterns),
+        )
+        // Enhanced MCP routes (Phase 4.2)
+        .route(
+            "/projects/:id/mcp/tickets",
+            post(handlers::generate_mcp_ticket),
+        )
+        // Advanced Analysis routes (Phase 4.3)
+        .route(
+            "/projects/:id/correlations",
+            post(handlers::analyze_correlations),
+        )
+        .route("/projects/:id/anomalies", post(handlers::detect_anomalies))
+        .route(
+            "/projects/:id/multi-log",
+            post(handlers::analyze_multiple_logs),
+        )
+        .route(
+            "/projects/:project_id/streaming/ingest",
+            post(handlers::streaming::ingest_logs).route_layer(axum::middleware::from_fn_with_state(
+                (state, LimitedRoute::Ingest),
+                rate_limit,
+            )),
+        )
+}
+
+/// Project routes that delete data, publish it or change who can access it
+fn project_admin_routes() -> Router<AppState> {
+    Router::new()
+        .route("/projects/:id", delete(handlers::delete_project))
+        // Membership routes
+        .route("/projects/:id/members", post(handlers::add_project_member))
+        .route("/projects/:id/members/history", get(handlers::get_project_member_history))
+        .route("/projects/:id/audit", get(handlers::audit::list_project_audit_events))
+        .route("/projects/:id/audit/export", get(handlers::audit::export_project_audit_events))
+        .route("/projects/:id/members/:user_id", patch(handlers::update_project_member))
+        .route("/projects/:id/members/:user_id", delete(handlers::remove_project_member))
+        .route(
+            "/projects/:project_id/files/:file_id",
+            delete(handlers::delete_log_file),
+        )
+        // Share link routes
+        .route("/projects/:id/share", post(handlers::shares::create_share_link))
+        .route("/projects/:id/shares", get(handlers::shares::list_share_links))
+        .route("/projects/:id/shares/:share_id", delete(handlers::shares::revoke_share_link))
         // Streaming source management routes (Phase 6.1)
         .route("/projects/:project_id/streaming/sources", post(handlers::streaming::create_streaming_source))
-        .route("/projects/:project_id/streaming/sources", get(handlers::streaming::list_streaming_sources))
         .route("/projects/:project_id/streaming/sources/:source_id", delete(handlers::streaming::stop_streaming_source))
-        .route("/projects/:project_id/streaming/stats", get(handlers::streaming::get_streaming_stats))
-        .route("/projects/:project_id/streaming/ingest", post(handlers::streaming::ingest_logs))
         .route("/projects/:project_id/streaming/flush", post(handlers::streaming::flush_project_buffers))
-        .route("/projects/:project_id/streaming/logs", get(handlers::streaming::get_recent_logs))
-        // Metrics routes
-        .route("/metrics", get(metrics_handler_wrapper))
-        .route("/health/metrics", get(health_with_metrics_handler_wrapper))
+        // Alert rule management routes
+        .route("/projects/:id/alerts/rules", post(handlers::alerts::create_alert_rule))
+        .route("/projects/:id/alerts/rules/:rule_id", put(handlers::alerts::update_alert_rule))
+        .route("/projects/:id/alerts/rules/:rule_id", delete(handlers::alerts::delete_alert_rule))
+        .route("/projects/:id/alerts/rules/:rule_id/silence", post(handlers::alerts::silence_alert_rule))
+        .route("/projects/:id/alerts/rules/:rule_id/silence", delete(handlers::alerts::unsilence_alert_rule))
+        // Analysis schedule management routes
+        .route("/projects/:id/schedules", post(handlers::schedules::create_schedule))
+        .route(
+            "/projects/:id/schedules/:schedule_id",
+            put(handlers::schedules::update_schedule).delete(handlers::schedules::delete_schedule),
+        )
+        // Notification channels and their delivery log
+        .route(
+            "/projects/:id/notifications/channels",
+            get(handlers::notifications::list_channels).post(handlers::notifications::create_channel),
+        )
+        .route(
+            "/projects/:id/notifications/channels/:channel_id",
+            get(handlers::notifications::get_channel)
+                .put(handlers::notifications::update_channel)
+                .delete(handlers::notifications::delete_channel),
+        )
+        .route(
+            "/projects/:id/notifications/channels/:channel_id/test",
+            post(handlers::notifications::test_channel),
+        )
+        .route("/projects/:id/notifications/deliveries", get(handlers::notifications::list_deliveries))
 }
 
 /// Wrapper to extract metrics_collector from AppState for metrics endpoint
@@ -0,0 +1,581 @@
+//! Scheduled and alert-triggered AI analysis of projects' streaming logs
+//!
+//! The scheduler keeps a window of recent entries for every streaming source of the
+//! projects that have enabled schedules. A schedule runs on its cron expression, each
+//! time its trigger rule fires an alert, or both; a run queues a realtime analysis of
+//! the window on the job queue, linked to the alert that started it. Projects whose
+//! `.synapse/config.toml` turns `auto_analyze` off are skipped, as are projects that
+//! have used up their daily analysis quota.
+
+pub mod cron;
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration as StdDuration;
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use synapse_core::project::{Alert, Analysis, AnalysisSchedule, JobQueue, ProjectConfig, Storage};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::alerts::rules::parse_level;
+use crate::alerts::NotificationManager;
+use crate::config::WebConfig;
+use crate::jobs::{self, JobPayload};
+use crate::middleware::rate_limit::quota_day_start;
+use crate::streaming::{LogBatch, StreamingHub, StreamingLogEntry, StreamingMessage};
+use crate::telemetry;
+use cron::CronSchedule;
+
+/// Longest window a schedule may analyze
+pub const MAX_WINDOW_SECS: i64 = 6 * 60 * 60;
+/// Entries kept per source, however short the windows
+const MAX_WINDOW_ENTRIES: usize = 10_000;
+/// Most recent entries a single analysis reads
+const MAX_ANALYSIS_ENTRIES: usize = 2_000;
+/// How often cron schedules are checked
+const TICK_INTERVAL: StdDuration = StdDuration::from_secs(15);
+
+/// Why a schedule's run did not queue an analysis
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The project's config.toml sets `auto_analyze = false`
+    AutoAnalyzeDisabled,
+    QuotaExhausted,
+    /// Nothing at or above the schedule's level arrived within its window
+    NoEntries,
+}
+
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            SkipReason::AutoAnalyzeDisabled => "Automatic analysis is turned off in the project's config.toml",
+            SkipReason::QuotaExhausted => "The project has used its analyses for today",
+            SkipReason::NoEntries => "No entries were streamed within the schedule's window",
+        })
+    }
+}
+
+/// What came of running a schedule
+#[derive(Debug)]
+pub enum RunOutcome {
+    Queued(Box<Analysis>),
+    Skipped(SkipReason),
+}
+
+impl RunOutcome {
+    pub fn skipped(self) -> Option<SkipReason> {
+        match self {
+            RunOutcome::Queued(_) => None,
+            RunOutcome::Skipped(reason) => Some(reason),
+        }
+    }
+}
+
+struct ActiveSchedule {
+    schedule: AnalysisSchedule,
+    cron: Option<CronSchedule>,
+    next_run: Option<DateTime<Utc>>,
+}
+
+struct WindowEntry {
+    received_at: DateTime<Utc>,
+    entry: StreamingLogEntry,
+}
+
+pub struct AnalysisScheduler {
+    storage: Arc<dyn Storage>,
+    jobs: Arc<JobQueue>,
+    job_max_attempts: i32,
+    timeout_secs: u64,
+    /// Server-wide analyses per day, for projects without a quota of their own; 0 for none
+    daily_quota: u32,
+    /// Enabled schedules by id, with when each cron schedule runs next
+    schedules: Mutex<HashMap<String, ActiveSchedule>>,
+    /// Recent entries by project and source, kept only for projects with enabled schedules
+    windows: Mutex<HashMap<(Uuid, String), VecDeque<WindowEntry>>>,
+}
+
+impl AnalysisScheduler {
+    pub fn new(storage: Arc<dyn Storage>, jobs: Arc<JobQueue>, config: &WebConfig) -> Self {
+        Self {
+            storage,
+            jobs,
+            job_max_attempts: config.job_max_attempts,
+            timeout_secs: config.analysis_timeout_secs,
+            daily_quota: config.daily_analysis_quota,
+            schedules: Mutex::new(HashMap::new()),
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Load the enabled schedules, then keep windows of what the hub broadcasts, run cron
+    /// schedules when due and triggered schedules when their rule fires
+    pub async fn start(
+        storage: Arc<dyn Storage>,
+        jobs: Arc<JobQueue>,
+        hub: &StreamingHub,
+        notifications: &NotificationManager,
+        config: &WebConfig,
+    ) -> Arc<Self> {
+        let scheduler = Arc::new(Self::new(storage, jobs, config));
+        if let Err(e) = scheduler.reload().await {
+            warn!("Failed to load analysis schedules: {}", e);
+        }
+
+        let mut batches = hub.sender.subscribe();
+        let worker = scheduler.clone();
+        tokio::spawn(async move {
+            loop {
+                match batches.recv().await {
+                    Ok(StreamingMessage::LogBatch(batch)) => worker.record_batch(&batch),
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Analysis scheduler fell behind; {} streaming messages are missing from windows", skipped);
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+
+        let mut alerts = notifications.subscribe();
+        let worker = scheduler.clone();
+        tokio::spawn(async move {
+            loop {
+                match alerts.recv().await {
+                    Ok(notification) => {
+                        worker.alert_fired(&notification.alert).await;
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Analysis scheduler fell behind; {} fired alerts were not checked", skipped);
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+
+        let worker = scheduler.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(TICK_INTERVAL);
+            loop {
+                interval.tick().await;
+                worker.run_due(Utc::now()).await;
+            }
+        });
+
+        scheduler
+    }
+
+    /// Pick up changed schedules; a schedule whose cron expression is unchanged keeps its next run
+    pub async fn reload(&self) -> Result<()> {
+        let enabled = self.storage.list_enabled_analysis_schedules().await?;
+        let now = Utc::now();
+
+        let mut schedules = self.schedules.lock().unwrap();
+        let mut previous = std::mem::take(&mut *schedules);
+        for schedule in enabled {
+            let cron = match schedule.cron.as_deref().map(str::parse::<CronSchedule>).transpose() {
+                Ok(cron) => cron,
+                Err(e) => {
+                    warn!("Skipping analysis schedule {} ({}): {}", schedule.name, schedule.id, e);
+                    continue;
+                }
+            };
+            let next_run = match previous.remove(&schedule.id) {
+                Some(active) if active.schedule.cron == schedule.cron => active.next_run,
+                _ => cron.as_ref().and_then(|cron| cron.next_after(now)),
+            };
+            schedules.insert(schedule.id.clone(), ActiveSchedule { schedule, cron, next_run });
+        }
+
+        // Stop keeping windows for projects left without schedules
+        let projects: HashSet<&str> = schedules.values().map(|active| active.schedule.project_id.as_str()).collect();
+        self.windows
+            .lock()
+            .unwrap()
+            .retain(|(project_id, _), _| projects.contains(project_id.to_string().as_str()));
+
+        debug!("Loaded {} analysis schedules", schedules.len());
+        Ok(())
+    }
+
+    /// Add a batch to its source's window, if the project has schedules
+    pub fn record_batch(&self, batch: &LogBatch) {
+        let project_id = batch.project_id.to_string();
+        let scheduled = self
+            .schedules
+            .lock()
+            .unwrap()
+            .values()
+            .any(|active| active.schedule.project_id == project_id);
+        if !scheduled {
+            return;
+        }
+
+        let now = Utc::now();
+        let mut windows = self.windows.lock().unwrap();
+        let window = windows.entry((batch.project_id, batch.source.clone())).or_default();
+        window.extend(batch.entries.iter().map(|entry| WindowEntry { received_at: now, entry: entry.clone() }));
+
+        let oldest = now - Duration::seconds(MAX_WINDOW_SECS);
+        while window
+            .front()
+            .is_some_and(|front| front.received_at < oldest || window.len() > MAX_WINDOW_ENTRIES)
+        {
+            window.pop_front();
+        }
+    }
+
+    /// The most recent entries received within the last `window_secs`, oldest first
+    pub fn recent_entries(&self, project_id: Uuid, source: Option<&str>, window_secs: i64) -> Vec<StreamingLogEntry> {
+        let since = Utc::now() - Duration::seconds(window_secs);
+        let windows = self.windows.lock().unwrap();
+        let mut entries: Vec<&WindowEntry> = windows
+            .iter()
+            .filter(|((project, name), _)| *project == project_id && source.is_none_or(|source| source == name))
+            .flat_map(|(_, window)| window.iter().filter(|entry| entry.received_at >= since))
+            .collect();
+        entries.sort_by_key(|entry| entry.received_at);
+
+        let skip = entries.len().saturating_sub(MAX_ANALYSIS_ENTRIES);
+        entries.into_iter().skip(skip).map(|entry| entry.entry.clone()).collect()
+    }
+
+    /// Run the cron schedules due at `now`; returns the analyses queued
+    pub async fn run_due(&self, now: DateTime<Utc>) -> Vec<Analysis> {
+        let due: Vec<AnalysisSchedule> = {
+            let mut schedules = self.schedules.lock().unwrap();
+            schedules
+                .values_mut()
+                .filter(|active| active.next_run.is_some_and(|next_run| next_run <= now))
+                .map(|active| {
+                    active.next_run = active.cron.as_ref().and_then(|cron| cron.next_after(now));
+                    active.schedule.clone()
+                })
+                .collect()
+        };
+
+        let mut queued = Vec::new();
+        for schedule in due {
+            if let Some(analysis) = self.run_logged(&schedule, None).await {
+                queued.push(analysis);
+            }
+        }
+        queued
+    }
+
+    /// Run the schedules triggered by a newly fired alert's rule
+    pub async fn alert_fired(&self, alert: &Alert) -> Vec<Analysis> {
+        let triggered: Vec<AnalysisSchedule> = self
+            .schedules
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|active| active.schedule.trigger_rule_id.as_deref() == Some(alert.rule_id.as_str()))
+            .map(|active| active.schedule.clone())
+            .collect();
+
+        let mut queued = Vec::new();
+        for schedule in triggered {
+            if let Some(analysis) = self.run_logged(&schedule, Some(alert)).await {
+                queued.push(analysis);
+            }
+        }
+        queued
+    }
+
+    async fn run_logged(&self, schedule: &AnalysisSchedule, alert: Option<&Alert>) -> Option<Analysis> {
+        match self.run(schedule, alert).await {
+            Ok(RunOutcome::Queued(analysis)) => Some(*analysis),
+            Ok(RunOutcome::Skipped(reason)) => {
+                debug!("Analysis schedule {} ({}) skipped: {}", schedule.name, schedule.id, reason);
+                None
+            }
+            Err(e) => {
+                warn!("Analysis schedule {} ({}) failed to run: {}", schedule.name, schedule.id, e);
+                None
+            }
+        }
+    }
+
+    /// Queue a realtime analysis of the schedule's window
+    ///
+    /// A run started by an alert reads the alert's source when the schedule reads every
+    /// source, and the analysis records the alert.
+    pub async fn run(&self, schedule: &AnalysisSchedule, alert: Option<&Alert>) -> Result<RunOutcome> {
+        if !self.auto_analyze(&schedule.project_id).await {
+            return Ok(RunOutcome::Skipped(SkipReason::AutoAnalyzeDisabled));
+        }
+        if !self.quota_left(&schedule.project_id).await? {
+            return Ok(RunOutcome::Skipped(SkipReason::QuotaExhausted));
+        }
+
+        let source = schedule.source.as_deref().or(alert.and_then(|alert| alert.source.as_deref()));
+        let min_level = parse_level(&schedule.level_filter);
+        let lines: Vec<String> = self
+            .recent_entries(Uuid::parse_str(&schedule.project_id)?, source, schedule.window_secs)
+            .iter()
+            // Entries without a recognizable level are left to the analyzer's own filtering
+            .filter(|entry| match (entry.level.as_deref().and_then(parse_level), &min_level) {
+                (Some(level), Some(min_level)) => level >= *min_level,
+                _ => true,
+            })
+            .map(entry_line)
+            .collect();
+        if lines.is_empty() {
+            return Ok(RunOutcome::Skipped(SkipReason::NoEntries));
+        }
+
+        let analysis = Analysis::new(
+            schedule.project_id.clone(),
+            None,
+            "realtime".to_string(),
+            schedule.provider.clone(),
+            schedule.level_filter.clone(),
+        )
+        .with_source(format!("stream:{}", source.unwrap_or("*")))
+        .with_created_by(schedule.created_by.clone())
+        .with_alert_id(alert.map(|alert| alert.id.clone()));
+        self.storage.create_analysis(&analysis).await?;
+
+        let entries = lines.len();
+        let payload = JobPayload {
+            lines,
+            traceparent: telemetry::current_traceparent(),
+            ..Default::default()
+        };
+        // A timeout chosen in settings overrides the server's
+        let timeout_secs = self
+            .storage
+            .get_settings()
+            .await?
+            .and_then(|settings| settings.analysis_timeout_seconds)
+            .map_or(self.timeout_secs, |timeout| timeout as u64);
+        let job = jobs::analysis_job(&analysis, self.job_max_attempts, 0, timeout_secs)
+            .with_payload(serde_json::to_string(&payload)?);
+        self.jobs.enqueue(job).await?;
+        self.storage
+            .record_analysis_schedule_run(&schedule.id, &analysis.id, Utc::now())
+            .await?;
+
+        info!(
+            "Analysis schedule {} queued realtime analysis {} of {} entries{}",
+            schedule.name,
+            analysis.id,
+            entries,
+            alert.map(|alert| format!(" for alert {}", alert.id)).unwrap_or_default()
+        );
+        Ok(RunOutcome::Queued(Box::new(analysis)))
+    }
+
+    /// Whether the project's config.toml, when it has one, leaves automatic analysis on
+    async fn auto_analyze(&self, project_id: &str) -> bool {
+        let root_path = match self.storage.get_project(project_id).await {
+            Ok(project) => project.and_then(|project| project.root_path),
+            Err(e) => {
+                warn!("Failed to look up project {}: {}", project_id, e);
+                None
+            }
+        };
+        let Some(root_path) = root_path else {
+            return true;
+        };
+        match ProjectConfig::load_from_root(&root_path).await {
+            Ok(config) => config.is_none_or(|config| config.synapse.auto_analyze),
+            Err(e) => {
+                warn!("Failed to read the config.toml of project {} in {}: {}", project_id, root_path, e);
+                true
+            }
+        }
+    }
+
+    /// Whether the project may start another analysis today
+    async fn quota_left(&self, project_id: &str) -> Result<bool> {
+        let quota = match self.storage.get_project_analysis_quota(project_id).await? {
+            Some(quota) => quota.max(0) as u32,
+            None => self.daily_quota,
+        };
+        if quota == 0 {
+            return Ok(true);
+        }
+        let day_start = quota_day_start(Utc::now());
+        let used = self.storage.count_project_analyses_since(project_id, day_start).await?;
+        debug!("Project {} has used {} of {} analyses since {}", project_id, used, quota, day_start);
+        Ok(used < i64::from(quota))
+    }
+}
+
+/// A streamed entry as a log line, keeping a level that only arrived as a field
+fn entry_line(entry: &StreamingLogEntry) -> String {
+    match entry.level.as_deref() {
+        Some(level) if !entry.message.to_uppercase().contains(&level.to_uppercase()) => {
+            format!("{} {}", level.to_uppercase(), entry.message)
+        }
+        _ => entry.message.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use synapse_core::project::{
+        connect_storage, AlertRule, AnalysisJob, JobHandler, JobOutput, JobProgress, JobQuery, JobQueueConfig,
+        Project,
+    };
+    use tempfile::TempDir;
+
+    struct NoopHandler;
+
+    #[async_trait]
+    impl JobHandler for NoopHandler {
+        async fn run(&self, _job: &AnalysisJob, _progress: &JobProgress) -> Result<JobOutput> {
+            anyhow::bail!("not run in tests")
+        }
+    }
+
+    async fn scheduler(dir: &TempDir) -> (Arc<dyn Storage>, AnalysisScheduler) {
+        let url = format!("sqlite:{}?mode=rwc", dir.path().join("scheduler.db").display());
+        let storage = connect_storage(&url, 1).await.unwrap();
+        // Workers are not started, so queued jobs stay put for inspection
+        let jobs = JobQueue::new(storage.clone(), Arc::new(NoopHandler), JobQueueConfig::new("test", 1));
+        let scheduler = AnalysisScheduler::new(storage.clone(), jobs, &WebConfig::default());
+        (storage, scheduler)
+    }
+
+    fn batch(project_id: Uuid, source: &str, entries: &[(Option<&str>, &str)]) -> LogBatch {
+        LogBatch {
+            batch_id: Uuid::new_v4().to_string(),
+            timestamp: Utc::now().to_rfc3339(),
+            entries: entries
+                .iter()
+                .map(|(level, message)| StreamingLogEntry {
+                    id: Uuid::new_v4().to_string(),
+                    timestamp: None,
+                    level: level.map(str::to_string),
+                    message: message.to_string(),
+                    source: source.to_string(),
+                    project_id,
+                    line_number: None,
+                    metadata: HashMap::new(),
+                })
+                .collect(),
+            source: source.to_string(),
+            project_id,
+        }
+    }
+
+    async fn queued_lines(storage: &Arc<dyn Storage>, analysis: &Analysis) -> Vec<String> {
+        let filter = JobQuery { analysis_id: Some(analysis.id.clone()), ..Default::default() };
+        let job = storage.list_jobs(&filter).await.unwrap().remove(0);
+        serde_json::from_str::<JobPayload>(job.payload.as_deref().unwrap()).unwrap().lines
+    }
+
+    #[tokio::test]
+    async fn test_triggered_and_cron_runs() {
+        let dir = TempDir::new().unwrap();
+        let (storage, scheduler) = scheduler(&dir).await;
+        let project = Project::new("scheduled".to_string(), None);
+        storage.insert_project(&project).await.unwrap();
+        let project_id = Uuid::parse_str(&project.id).unwrap();
+        let rule = AlertRule::new(
+            project.id.clone(),
+            "Error rate".to_string(),
+            r#"{"type":"rate","per_minute":1,"level":"ERROR"}"#.to_string(),
+        );
+        storage.create_alert_rule(&rule).await.unwrap();
+
+        let mut triggered = AnalysisSchedule::new(project.id.clone(), "On errors".to_string(), "openrouter".to_string());
+        triggered.trigger_rule_id = Some(rule.id.clone());
+        storage.create_analysis_schedule(&triggered).await.unwrap();
+        let mut hourly = AnalysisSchedule::new(project.id.clone(), "Hourly".to_string(), "openrouter".to_string());
+        hourly.cron = Some("0 * * * *".to_string());
+        hourly.source = Some("worker".to_string());
+        storage.create_analysis_schedule(&hourly).await.unwrap();
+        scheduler.reload().await.unwrap();
+
+        // Projects without schedules keep no window
+        scheduler.record_batch(&batch(Uuid::new_v4(), "api", &[(Some("ERROR"), "elsewhere")]));
+        assert_eq!(scheduler.windows.lock().unwrap().len(), 0);
+        scheduler.record_batch(&batch(
+            project_id,
+            "api",
+            &[(Some("INFO"), "INFO started"), (Some("ERROR"), "db timeout"), (None, "ERROR pool exhausted")],
+        ));
+        scheduler.record_batch(&batch(project_id, "web", &[(Some("ERROR"), "ERROR bad gateway")]));
+
+        // An alert from the trigger rule analyzes its own source and is linked to the analysis
+        let alert = Alert::new(&rule, "api".to_string(), "2 ERROR entries a minute".to_string()).with_source("api");
+        storage.create_alert(&alert).await.unwrap();
+        let queued = scheduler.alert_fired(&alert).await;
+        assert_eq!(queued.len(), 1);
+        let analysis = storage.get_analysis(&queued[0].id).await.unwrap().unwrap();
+        assert_eq!(analysis.analysis_type, "realtime");
+        assert_eq!(analysis.alert_id.as_deref(), Some(alert.id.as_str()));
+        assert_eq!(analysis.source.as_deref(), Some("stream:api"));
+        assert_eq!(queued_lines(&storage, &analysis).await, vec!["ERROR db timeout", "ERROR pool exhausted"]);
+        let stored = storage.get_analysis_schedule(&triggered.id).await.unwrap().unwrap();
+        assert_eq!(stored.last_analysis_id.as_deref(), Some(analysis.id.as_str()));
+
+        // Alerts from other rules don't trigger it
+        let other = AlertRule::new(project.id.clone(), "Other".to_string(), "{}".to_string());
+        assert!(scheduler.alert_fired(&Alert::new(&other, "x".to_string(), "x".to_string())).await.is_empty());
+
+        // The cron schedule is not due yet, and has nothing from its source when it is
+        assert!(scheduler.run_due(Utc::now()).await.is_empty());
+        let next_hour = Utc::now() + Duration::hours(1);
+        assert!(scheduler.run_due(next_hour).await.is_empty());
+        assert_eq!(
+            scheduler.run(&hourly, None).await.unwrap().skipped(),
+            Some(SkipReason::NoEntries)
+        );
+        scheduler.record_batch(&batch(project_id, "worker", &[(Some("FATAL"), "FATAL out of memory")]));
+        let queued = scheduler.run_due(next_hour + Duration::hours(1)).await;
+        assert_eq!(queued.len(), 1);
+        assert!(queued[0].alert_id.is_none());
+        assert_eq!(queued_lines(&storage, &queued[0]).await, vec!["FATAL out of memory"]);
+
+        // Disabling the schedules drops the windows
+        hourly.enabled = false;
+        storage.update_analysis_schedule(&hourly).await.unwrap();
+        assert!(storage.delete_analysis_schedule(&project.id, &triggered.id).await.unwrap());
+        scheduler.reload().await.unwrap();
+        assert_eq!(scheduler.windows.lock().unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_auto_analyze_off_skips_runs() {
+        let dir = TempDir::new().unwrap();
+        let (storage, scheduler) = scheduler(&dir).await;
+        let root = dir.path().join("app");
+        let mut config = ProjectConfig::new("app".to_string(), "rust".to_string(), root.display().to_string());
+        config.synapse.auto_analyze = false;
+        tokio::fs::create_dir_all(root.join(".synapse")).await.unwrap();
+        config.save(root.join(".synapse").join("config.toml")).await.unwrap();
+
+        let project_id = Uuid::new_v4();
+        let project = Project::new_cli_project(
+            project_id.to_string(),
+            "app".to_string(),
+            None,
+            root.display().to_string(),
+            None,
+            "rust".to_string(),
+        );
+        storage.insert_project(&project).await.unwrap();
+        let mut schedule = AnalysisSchedule::new(project.id.clone(), "Hourly".to_string(), "openrouter".to_string());
+        schedule.cron = Some("@hourly".to_string());
+        storage.create_analysis_schedule(&schedule).await.unwrap();
+        scheduler.reload().await.unwrap();
+        scheduler.record_batch(&batch(project_id, "api", &[(Some("ERROR"), "ERROR failed")]));
+
+        let outcome = scheduler.run(&schedule, None).await.unwrap();
+        assert_eq!(outcome.skipped(), Some(SkipReason::AutoAnalyzeDisabled));
+
+        config.synapse.auto_analyze = true;
+        config.save(root.join(".synapse").join("config.toml")).await.unwrap();
+        assert!(scheduler.run(&schedule, None).await.unwrap().skipped().is_none());
+    }
+}
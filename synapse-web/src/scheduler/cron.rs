@@ -0,0 +1,198 @@
+//! Five-field cron expressions, evaluated in UTC
+//!
+//! Fields are minute, hour, day of month, month and day of week. Each takes `*`, a
+//! value, a range `a-b`, a step `*/n` or `a-b/n`, or a comma-separated list of those.
+//! Months and weekdays also accept three-letter names, and Sunday is 0 or 7. As in
+//! classic cron, when both day fields are restricted a time matching either runs.
+//! `@hourly`, `@daily`, `@weekly`, `@monthly` and `@yearly` are shorthands.
+
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Timelike, Utc};
+
+const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+/// How far ahead to look for the next run before deciding there is none (e.g. 30 February)
+const SEARCH_YEARS: i64 = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether each day field was restricted, which decides how the two combine
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+impl FromStr for CronSchedule {
+    type Err = String;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let expression = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!(
+                "Cron expression must have 5 fields (minute hour day month weekday), got {}",
+                fields.len()
+            ));
+        };
+
+        // Sunday may be written 7; fold it onto 0
+        let mut weekdays = parse_field("weekday", weekday, 0, 7, &WEEKDAYS)?;
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays & !(1 << 7)) | 1;
+        }
+        Ok(Self {
+            minutes: parse_field("minute", minute, 0, 59, &[])?,
+            hours: parse_field("hour", hour, 0, 23, &[])?,
+            days: parse_field("day", day, 1, 31, &[])?,
+            months: parse_field("month", month, 1, 12, &MONTHS)?,
+            weekdays,
+            days_restricted: day != "*",
+            weekdays_restricted: weekday != "*",
+        })
+    }
+}
+
+/// Parse one field into a bit set of the values it allows
+fn parse_field(name: &str, field: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, String> {
+    let value = |text: &str| -> Result<u32, String> {
+        let lower = text.to_lowercase();
+        let parsed = match names.iter().position(|candidate| *candidate == lower) {
+            // Named months start at 1, named weekdays at 0
+            Some(index) => index as u32 + min.min(1),
+            None => text.parse().map_err(|_| format!("Invalid {} value: {}", name, text))?,
+        };
+        if !(min..=max).contains(&parsed) {
+            return Err(format!("{} must be between {} and {}, got {}", name, min, max, parsed));
+        }
+        Ok(parsed)
+    };
+
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step.parse().map_err(|_| format!("Invalid {} step: {}", name, step))?;
+                if step == 0 {
+                    return Err(format!("{} step must be at least 1", name));
+                }
+                (range, Some(step))
+            }
+            None => (part, None),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (value(start)?, value(end)?),
+                // A single value with a step runs from it to the end of the field
+                None if step.is_some() => (value(range)?, max),
+                None => {
+                    let single = value(range)?;
+                    (single, single)
+                }
+            },
+        };
+        if start > end {
+            return Err(format!("Invalid {} range: {}", name, range));
+        }
+        for allowed in (start..=end).step_by(step.unwrap_or(1) as usize) {
+            bits |= 1 << allowed;
+        }
+    }
+    Ok(bits)
+}
+
+impl CronSchedule {
+    /// The first minute strictly after `after` that the schedule runs in
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut time = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let limit = time + Duration::days(366 * SEARCH_YEARS);
+
+        while time < limit {
+            if !self.month_matches(time) {
+                let (year, month) = match time.month() {
+                    12 => (time.year() + 1, 1),
+                    month => (time.year(), month + 1),
+                };
+                time = NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)?.and_utc();
+            } else if !self.day_matches(time) {
+                time = (time.date_naive() + Duration::days(1)).and_hms_opt(0, 0, 0)?.and_utc();
+            } else if self.hours & (1 << time.hour()) == 0 {
+                time = time.with_minute(0)? + Duration::hours(1);
+            } else if self.minutes & (1 << time.minute()) == 0 {
+                time += Duration::minutes(1);
+            } else {
+                return Some(time);
+            }
+        }
+        None
+    }
+
+    fn month_matches(&self, time: DateTime<Utc>) -> bool {
+        self.months & (1 << time.month()) != 0
+    }
+
+    fn day_matches(&self, time: DateTime<Utc>) -> bool {
+        let day = self.days & (1 << time.day()) != 0;
+        let weekday = self.weekdays & (1 << time.weekday().num_days_from_sunday()) != 0;
+        match (self.days_restricted, self.weekdays_restricted) {
+            (true, true) => day || weekday,
+            (true, false) => day,
+            (false, true) => weekday,
+            (false, false) => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text).unwrap().with_timezone(&Utc)
+    }
+
+    fn next(expression: &str, after: &str) -> String {
+        let schedule: CronSchedule = expression.parse().unwrap();
+        schedule.next_after(at(after)).unwrap().to_rfc3339()
+    }
+
+    #[test]
+    fn test_next_run() {
+        assert_eq!(next("*/15 * * * *", "2025-03-04T10:07:30Z"), "2025-03-04T10:15:00+00:00");
+        assert_eq!(next("0 * * * *", "2025-03-04T10:00:00Z"), "2025-03-04T11:00:00+00:00");
+        assert_eq!(next("30 2 * * *", "2025-03-04T10:00:00Z"), "2025-03-05T02:30:00+00:00");
+        assert_eq!(next("@monthly", "2025-12-15T00:00:00Z"), "2026-01-01T00:00:00+00:00");
+        assert_eq!(next("0 9 * * mon-fri", "2025-03-07T09:00:00Z"), "2025-03-10T09:00:00+00:00");
+        assert_eq!(next("0 0 29 feb *", "2025-03-01T00:00:00Z"), "2028-02-29T00:00:00+00:00");
+        // Sunday as 7, and either day field matching when both are set
+        assert_eq!(next("0 0 * * 7", "2025-03-04T00:00:00Z"), "2025-03-09T00:00:00+00:00");
+        assert_eq!(next("0 0 13 * fri", "2025-03-04T00:00:00Z"), "2025-03-07T00:00:00+00:00");
+        assert_eq!(next("5-10/5,45 8 * * *", "2025-03-04T08:06:00Z"), "2025-03-04T08:10:00+00:00");
+
+        let schedule: CronSchedule = "0 0 31 feb *".parse().unwrap();
+        assert!(schedule.next_after(at("2025-01-01T00:00:00Z")).is_none());
+    }
+
+    #[test]
+    fn test_invalid_expressions() {
+        let invalid = [
+            "", "* * * *", "60 * * * *", "* 24 * * *", "* * 0 * *", "* * * 13 *", "*/0 * * * *", "5-1 * * * *",
+            "* * * foo *",
+        ];
+        for expression in invalid {
+            assert!(expression.parse::<CronSchedule>().is_err(), "{:?} should not parse", expression);
+        }
+    }
+}
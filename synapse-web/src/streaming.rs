@@ -3,18 +3,32 @@ use axum::extract::{Path, State};
 use axum::response::Response;
 use futures::{sink::SinkExt, stream::StreamExt};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
 use tokio::sync::{broadcast, RwLock};
 use tokio::time::{Duration, Instant};
 use tracing::{error, info, warn};
 use uuid::Uuid;
 
+mod containers;
+pub mod docker;
+pub mod file_tail;
+pub mod fluent_forward;
+pub mod http_endpoint;
+pub mod kubernetes;
+mod listeners;
+pub mod restore;
+pub mod otlp;
 pub mod sources;
 pub mod sources_simple;
+pub mod store;
+pub mod syslog;
 
 
+use crate::alerts::rules::parse_level;
 use crate::AppState;
+use synapse_core::filter::LogLevel;
 use synapse_core::LogEntry;
 
 /// Maximum number of log entries to buffer before forcing a batch send
@@ -23,6 +37,10 @@ const MAX_BUFFER_SIZE: usize = 100;
 const MAX_BUFFER_TIME: u64 = 2;
 /// Channel capacity for broadcasting log entries
 const BROADCAST_CAPACITY: usize = 1000;
+/// Batches waiting for subscribers beyond which the hub is congested
+const CONGESTED_BATCHES: usize = BROADCAST_CAPACITY * 3 / 4;
+/// Most recent entries kept per project for recent-log queries
+pub const RECENT_ENTRIES: usize = 1000;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StreamingLogEntry {
@@ -33,6 +51,9 @@ pub struct StreamingLogEntry {
     pub source: String,
     pub project_id: Uuid,
     pub line_number: Option<usize>,
+    /// Structured fields carried alongside the message, such as syslog facility or hostname
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub metadata: HashMap<String, serde_json::Value>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -57,6 +78,50 @@ pub enum StreamingMessage {
     SubscriptionStatus { subscribed: bool, project_id: Uuid },
 }
 
+/// Which entries a recent-log query returns; unset fields match everything
+#[derive(Debug, Clone, Default)]
+pub struct LogFilter {
+    /// Entries at or above this level
+    pub level: Option<LogLevel>,
+    /// Entries from this source ID
+    pub source: Option<String>,
+    /// Entries stamped at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Entries whose `service.name` attribute is this service
+    pub service: Option<String>,
+    /// Entries from this host, by `host.name` attribute or syslog hostname
+    pub host: Option<String>,
+    pub trace_id: Option<String>,
+    pub span_id: Option<String>,
+}
+
+impl LogFilter {
+    pub fn matches(&self, entry: &StreamingLogEntry) -> bool {
+        let metadata = |key: &str| entry.metadata.get(key).and_then(|value| value.as_str());
+        let entry_level = || entry.level.as_deref().and_then(parse_level);
+        let stamped = || {
+            entry
+                .timestamp
+                .as_deref()
+                .and_then(|timestamp| DateTime::parse_from_rfc3339(timestamp).ok())
+        };
+
+        self.level.as_ref().is_none_or(|level| entry_level().is_some_and(|entry_level| entry_level >= *level))
+            && self.since.as_ref().is_none_or(|since| stamped().is_some_and(|stamped| stamped >= *since))
+            && self.source.as_ref().is_none_or(|source| entry.source == *source)
+            && self.service.as_ref().is_none_or(|service| metadata("service.name") == Some(service.as_str()))
+            && self.host.as_ref().is_none_or(|host| {
+                metadata("host.name").or_else(|| metadata("hostname")) == Some(host.as_str())
+            })
+            && self.trace_id.as_ref().is_none_or(|trace_id| {
+                metadata("trace_id").is_some_and(|id| id.eq_ignore_ascii_case(trace_id))
+            })
+            && self.span_id.as_ref().is_none_or(|span_id| {
+                metadata("span_id").is_some_and(|id| id.eq_ignore_ascii_case(span_id))
+            })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct StreamingSource {
     pub id: String,
@@ -76,6 +141,12 @@ pub struct StreamingHub {
     pub sources: Arc<RwLock<HashMap<Uuid, Vec<StreamingSource>>>>,
     /// Active WebSocket connections by project ID
     pub connections: Arc<RwLock<HashMap<Uuid, usize>>>,
+    /// Entries received per project and source name, for throughput metrics
+    received: Arc<std::sync::Mutex<HashMap<(Uuid, String), u64>>>,
+    /// Most recent entries per project, oldest first
+    recent: Arc<std::sync::Mutex<HashMap<Uuid, VecDeque<StreamingLogEntry>>>>,
+    /// Queue to durable storage, when entries are stored
+    store: Option<store::LogStoreSender>,
 }
 
 impl Default for StreamingHub {
@@ -92,12 +163,27 @@ impl StreamingHub {
             sender,
             sources: Arc::new(RwLock::new(HashMap::new())),
             connections: Arc::new(RwLock::new(HashMap::new())),
+            received: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            recent: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            store: None,
         }
     }
 
+    /// Store every entry the hub accepts through the given queue
+    pub fn with_store(mut self, store: store::LogStoreSender) -> Self {
+        self.store = Some(store);
+        self
+    }
+
     /// Register a new streaming source for a project
     pub async fn register_source(&self, project_id: Uuid, source_name: String) -> String {
         let source_id = Uuid::new_v4().to_string();
+        self.register_source_with_id(project_id, source_id.clone(), source_name).await;
+        source_id
+    }
+
+    /// Register a streaming source under an id chosen by the caller
+    pub async fn register_source_with_id(&self, project_id: Uuid, source_id: String, source_name: String) {
         let source = StreamingSource {
             id: source_id.clone(),
             name: source_name,
@@ -111,24 +197,49 @@ impl StreamingHub {
         sources.entry(project_id).or_insert_with(Vec::new).push(source);
         
         info!("Registered streaming source {} for project {}", source_id, project_id);
-        source_id
     }
 
     /// Add log entries to a streaming source's buffer
     pub async fn add_logs(&self, project_id: Uuid, source_id: &str, entries: Vec<StreamingLogEntry>) -> anyhow::Result<()> {
+        // Queue for storage before taking the lock, so a slow database holds back only this source
+        if let Some(store) = self.store.as_ref().filter(|_| !entries.is_empty()) {
+            if store.send(entries.clone()).await.is_err() {
+                warn!("Streaming log store has stopped; entries are not stored");
+            }
+        }
+
         let mut sources = self.sources.write().await;
-        
-        if let Some(project_sources) = sources.get_mut(&project_id) {
-            if let Some(source) = project_sources.iter_mut().find(|s| s.id == source_id) {
-                source.buffer.extend(entries);
-                
-                // Check if we should flush the buffer
-                let should_flush = source.buffer.len() >= MAX_BUFFER_SIZE ||
-                    source.last_flush.elapsed() >= Duration::from_secs(MAX_BUFFER_TIME);
-                
-                if should_flush {
-                    self.flush_source_buffer(source).await?;
-                }
+        let source = sources
+            .get_mut(&project_id)
+            .and_then(|project_sources| project_sources.iter_mut().find(|s| s.id == source_id));
+
+        // Count under the source's name; entries for an unregistered source under its id
+        let source_name = source.as_ref().map_or(source_id, |source| source.name.as_str());
+        *self
+            .received
+            .lock()
+            .unwrap()
+            .entry((project_id, source_name.to_string()))
+            .or_insert(0) += entries.len() as u64;
+
+        {
+            let mut recent = self.recent.lock().unwrap();
+            let project_recent = recent.entry(project_id).or_default();
+            let skip = entries.len().saturating_sub(RECENT_ENTRIES);
+            project_recent.extend(entries.iter().skip(skip).cloned());
+            let excess = project_recent.len().saturating_sub(RECENT_ENTRIES);
+            project_recent.drain(..excess);
+        }
+
+        if let Some(source) = source {
+            source.buffer.extend(entries);
+
+            // Check if we should flush the buffer
+            let should_flush = source.buffer.len() >= MAX_BUFFER_SIZE ||
+                source.last_flush.elapsed() >= Duration::from_secs(MAX_BUFFER_TIME);
+
+            if should_flush {
+                self.flush_source_buffer(source).await?;
             }
         }
         
@@ -159,6 +270,25 @@ impl StreamingHub {
         Ok(())
     }
 
+    /// Force flush one source's buffer
+    pub async fn flush_source(&self, project_id: Uuid, source_id: &str) -> anyhow::Result<()> {
+        let mut sources = self.sources.write().await;
+        let source = sources
+            .get_mut(&project_id)
+            .and_then(|project_sources| project_sources.iter_mut().find(|s| s.id == source_id));
+
+        if let Some(source) = source {
+            self.flush_source_buffer(source).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether subscribers have fallen far enough behind that sources should hold back
+    pub fn is_congested(&self) -> bool {
+        self.sender.len() >= CONGESTED_BATCHES
+    }
+
     /// Force flush all buffers for a project
     pub async fn flush_project_buffers(&self, project_id: Uuid) -> anyhow::Result<()> {
         let mut sources = self.sources.write().await;
@@ -186,6 +316,46 @@ impl StreamingHub {
         info!("Removed streaming source {} for project {}", source_id, project_id);
     }
 
+    /// Entries received so far per project and source name, in order
+    pub fn entries_received(&self) -> Vec<((Uuid, String), u64)> {
+        let mut received: Vec<_> = self
+            .received
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(key, count)| (key.clone(), *count))
+            .collect();
+        received.sort();
+        received
+    }
+
+    /// Put stored entries, oldest first, ahead of the project's recent entries
+    pub fn restore_recent(&self, project_id: Uuid, entries: Vec<StreamingLogEntry>) {
+        let mut recent = self.recent.lock().unwrap();
+        let project_recent = recent.entry(project_id).or_default();
+        let skip = entries.len().saturating_sub(RECENT_ENTRIES.saturating_sub(project_recent.len()));
+        for entry in entries.into_iter().skip(skip).rev() {
+            project_recent.push_front(entry);
+        }
+    }
+
+    /// The project's most recent entries that match `filter`, oldest first
+    pub fn recent_logs(&self, project_id: Uuid, filter: &LogFilter, limit: usize) -> Vec<StreamingLogEntry> {
+        let recent = self.recent.lock().unwrap();
+        let Some(entries) = recent.get(&project_id) else {
+            return Vec::new();
+        };
+        let mut matching: Vec<_> = entries
+            .iter()
+            .rev()
+            .filter(|entry| filter.matches(entry))
+            .take(limit)
+            .cloned()
+            .collect();
+        matching.reverse();
+        matching
+    }
+
     /// Get connection count for a project
     pub async fn get_connection_count(&self, project_id: Uuid) -> usize {
         let connections = self.connections.read().await;
@@ -238,6 +408,7 @@ pub async fn websocket_handler(
 
 async fn handle_websocket(socket: WebSocket, project_id: Uuid, state: AppState) {
     info!("WebSocket connected for project {}", project_id);
+    let _connection = state.metrics_collector.track_websocket("stream");
     
     // Get streaming hub from state (we'll add this to AppState)
     let streaming_hub = &state.streaming_hub;
@@ -367,6 +538,7 @@ impl From<LogEntry> for StreamingLogEntry {
             source: "unknown".to_string(), // Will be set by the source
             project_id: Uuid::nil(), // Will be set by the source
             line_number: entry.line_number,
+            metadata: HashMap::new(),
         }
     }
 }
@@ -410,4 +582,92 @@ mod tests {
         hub.decrement_connections(project_id).await;
         assert_eq!(hub.get_connection_count(project_id).await, 0);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_entries_received_by_source() {
+        let hub = StreamingHub::new();
+        let project_id = Uuid::new_v4();
+        let source_id = hub.register_source(project_id, "api-logs".to_string()).await;
+        let entries = |count: usize| -> Vec<StreamingLogEntry> {
+            (0..count)
+                .map(|i| StreamingLogEntry {
+                    id: i.to_string(),
+                    timestamp: None,
+                    level: None,
+                    message: format!("line {}", i),
+                    source: "api-logs".to_string(),
+                    project_id,
+                    line_number: None,
+                    metadata: HashMap::new(),
+                })
+                .collect()
+        };
+
+        hub.add_logs(project_id, &source_id, entries(3)).await.unwrap();
+        hub.add_logs(project_id, &source_id, entries(2)).await.unwrap();
+        hub.add_logs(project_id, "http-ingest", entries(4)).await.unwrap();
+
+        assert_eq!(
+            hub.entries_received(),
+            vec![
+                ((project_id, "api-logs".to_string()), 5),
+                ((project_id, "http-ingest".to_string()), 4),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_recent_logs_filtering() {
+        let hub = StreamingHub::new();
+        let project_id = Uuid::new_v4();
+        let entry = |message: &str, level: &str, timestamp: &str, metadata: serde_json::Value| StreamingLogEntry {
+            id: Uuid::new_v4().to_string(),
+            timestamp: Some(timestamp.to_string()),
+            level: Some(level.to_string()),
+            message: message.to_string(),
+            source: "otlp".to_string(),
+            project_id,
+            line_number: None,
+            metadata: serde_json::from_value(metadata).unwrap(),
+        };
+        let entries = vec![
+            entry("booted", "INFO", "2024-01-01T00:00:00Z", serde_json::json!({"service.name": "checkout"})),
+            entry(
+                "card declined",
+                "ERROR",
+                "2024-01-01T00:01:00Z",
+                serde_json::json!({"service.name": "checkout", "host.name": "web-1", "trace_id": "5b8efff798038103d269b633813fc60c"}),
+            ),
+            entry("link down", "ERR", "2024-01-01T00:02:00Z", serde_json::json!({"hostname": "edge-1"})),
+        ];
+        hub.add_logs(project_id, "otlp", entries).await.unwrap();
+
+        let messages = |filter: LogFilter, limit: usize| -> Vec<String> {
+            hub.recent_logs(project_id, &filter, limit).into_iter().map(|entry| entry.message).collect()
+        };
+        assert_eq!(messages(LogFilter::default(), 10), ["booted", "card declined", "link down"]);
+        assert_eq!(messages(LogFilter::default(), 2), ["card declined", "link down"]);
+        assert_eq!(
+            messages(LogFilter { level: Some(LogLevel::Error), ..Default::default() }, 10),
+            ["card declined", "link down"]
+        );
+        assert_eq!(
+            messages(LogFilter { service: Some("checkout".to_string()), ..Default::default() }, 10),
+            ["booted", "card declined"]
+        );
+        assert_eq!(messages(LogFilter { host: Some("edge-1".to_string()), ..Default::default() }, 10), ["link down"]);
+        assert_eq!(
+            messages(
+                LogFilter { trace_id: Some("5B8EFFF798038103D269B633813FC60C".to_string()), ..Default::default() },
+                10
+            ),
+            ["card declined"]
+        );
+        let since = DateTime::parse_from_rfc3339("2024-01-01T00:01:00Z").unwrap().with_timezone(&Utc);
+        assert_eq!(
+            messages(LogFilter { since: Some(since), ..Default::default() }, 10),
+            ["card declined", "link down"]
+        );
+        assert!(hub.recent_logs(Uuid::new_v4(), &LogFilter::default(), 10).is_empty());
+    }
+}
@@ -0,0 +1,279 @@
+//! Shared plumbing for Docker and Kubernetes sources
+//!
+//! Both poll their runtime for the running containers a source selects and follow each one's
+//! log stream in its own task, so containers started after the source are picked up on the next
+//! poll. A container whose stream ends is followed again while it keeps running, resuming after
+//! the last timestamp read from it. Lines go through the source's parser config and carry the
+//! container's metadata.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tokio::time::{Duration, MissedTickBehavior};
+use tracing::{debug, warn};
+
+use crate::streaming::listeners::{self, LISTENER_QUEUE};
+use crate::streaming::sources::{StreamingSourceConfig, StreamingSourceManager};
+use crate::streaming::{StreamingHub, StreamingLogEntry};
+
+/// Longest line kept while waiting for its newline; longer lines are split
+const MAX_LINE_LEN: usize = 1024 * 1024;
+/// How long the resume point of a container that's no longer listed is kept, in case it restarts
+const RESUME_WINDOW: chrono::Duration = chrono::Duration::hours(1);
+
+/// An optional list of strings in a source's JSON config
+pub(crate) fn string_list(config: &serde_json::Value, key: &str) -> Result<Vec<String>, String> {
+    match config.get(key) {
+        None | Some(serde_json::Value::Null) => Ok(Vec::new()),
+        Some(serde_json::Value::Array(items)) => items
+            .iter()
+            .map(|item| item.as_str().map(str::to_string))
+            .collect::<Option<_>>()
+            .ok_or_else(|| format!("'{}' must be a list of strings", key)),
+        Some(_) => Err(format!("'{}' must be a list of strings", key)),
+    }
+}
+
+/// An optional `poll_interval_seconds` in a source's JSON config
+pub(crate) fn poll_interval(config: &serde_json::Value) -> Result<Option<Duration>, String> {
+    match config.get("poll_interval_seconds") {
+        None | Some(serde_json::Value::Null) => Ok(None),
+        Some(seconds) => seconds
+            .as_u64()
+            .filter(|seconds| *seconds > 0)
+            .map(|seconds| Some(Duration::from_secs(seconds)))
+            .ok_or_else(|| "'poll_interval_seconds' must be a positive integer".to_string()),
+    }
+}
+
+/// A running container a source follows
+pub(crate) struct Container<T> {
+    /// Identifies the container across polls
+    pub key: String,
+    /// Added to the metadata of every entry from the container
+    pub metadata: HashMap<String, serde_json::Value>,
+    pub target: T,
+}
+
+/// A container runtime's API, as Docker and Kubernetes sources use it
+#[async_trait]
+pub(crate) trait ContainerRuntime: Send + Sync + 'static {
+    type Target: Send + Sync + 'static;
+
+    /// The running containers the source selects
+    async fn containers(&self) -> anyhow::Result<Vec<Container<Self::Target>>>;
+
+    /// Send a container's log lines written after `log.since` until its stream ends
+    async fn follow(&self, target: &Self::Target, log: &mut ContainerLog) -> anyhow::Result<()>;
+}
+
+/// One container's log stream as it is followed
+pub(crate) struct ContainerLog {
+    /// Timestamp of the last line read; earlier lines are skipped when the stream is resumed
+    pub since: DateTime<Utc>,
+    metadata: HashMap<String, serde_json::Value>,
+    pending: Vec<u8>,
+    sink: LineSink,
+}
+
+impl ContainerLog {
+    /// Send each complete line of a chunk of output, holding back a trailing partial line
+    ///
+    /// Returns false once the source has stopped.
+    pub async fn push_bytes(&mut self, bytes: &[u8]) -> bool {
+        self.pending.extend_from_slice(bytes);
+        let mut start = 0;
+        while let Some(end) = self.pending[start..].iter().position(|b| *b == b'\n') {
+            let line = String::from_utf8_lossy(&self.pending[start..start + end]).into_owned();
+            start += end + 1;
+            if !self.push_line(&line, None).await {
+                return false;
+            }
+        }
+        self.pending.drain(..start);
+
+        if self.pending.len() > MAX_LINE_LEN {
+            let line = String::from_utf8_lossy(&std::mem::take(&mut self.pending)).into_owned();
+            return self.push_line(&line, None).await;
+        }
+        true
+    }
+
+    /// Send one line, read as `<RFC 3339 timestamp> <message>` when it starts with a timestamp
+    ///
+    /// Returns false once the source has stopped.
+    pub async fn push_line(&mut self, line: &str, stream: Option<&str>) -> bool {
+        let (time, message) = split_timestamp(line.trim_end_matches('\r'));
+        if let Some(time) = time {
+            if time <= self.since {
+                return true;
+            }
+            self.since = time;
+        }
+
+        let Some(mut entry) = StreamingSourceManager::parse_log_line(message, &self.sink.config, &self.sink.source_id)
+        else {
+            return true;
+        };
+        if entry.timestamp.is_none() {
+            entry.timestamp = time.map(|time| time.to_rfc3339());
+        }
+        entry.metadata.extend(self.metadata.iter().map(|(key, value)| (key.clone(), value.clone())));
+        if let Some(stream) = stream {
+            entry.metadata.insert("stream".to_string(), serde_json::json!(stream));
+        }
+        self.sink.entries.send(entry).await.is_ok()
+    }
+}
+
+/// Split the timestamp Docker and Kubernetes put before each line when asked to
+fn split_timestamp(line: &str) -> (Option<DateTime<Utc>>, &str) {
+    line.split_once(' ')
+        .and_then(|(time, message)| {
+            DateTime::parse_from_rfc3339(time)
+                .ok()
+                .map(|time| (Some(time.with_timezone(&Utc)), message))
+        })
+        .unwrap_or((None, line))
+}
+
+/// Where followers send their entries
+#[derive(Clone)]
+struct LineSink {
+    config: Arc<StreamingSourceConfig>,
+    source_id: String,
+    entries: mpsc::Sender<StreamingLogEntry>,
+}
+
+/// Run a container source until it is cancelled
+pub(crate) async fn run<R: ContainerRuntime>(
+    streaming_hub: &Arc<StreamingHub>,
+    config: &StreamingSourceConfig,
+    source_id: &str,
+    runtime: R,
+    poll_interval: Duration,
+    cancel_rx: &mut mpsc::UnboundedReceiver<()>,
+) -> anyhow::Result<()> {
+    let (entries, entry_rx) = mpsc::channel(LISTENER_QUEUE);
+    let sink = LineSink { config: Arc::new(config.clone()), source_id: source_id.to_string(), entries };
+
+    let mut watchers = JoinSet::new();
+    watchers.spawn(follow_containers(Arc::new(runtime), poll_interval, sink));
+
+    listeners::forward_from_listeners(streaming_hub, config, source_id, entry_rx, watchers, cancel_rx).await
+}
+
+/// Poll for containers and follow each new one, from when the source started
+async fn follow_containers<R: ContainerRuntime>(
+    runtime: Arc<R>,
+    poll_interval: Duration,
+    sink: LineSink,
+) -> anyhow::Result<()> {
+    let started = Utc::now();
+    let mut following = HashSet::new();
+    let mut resume_from: HashMap<String, DateTime<Utc>> = HashMap::new();
+    let mut followers = JoinSet::new();
+    let mut poll = tokio::time::interval(poll_interval);
+    poll.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = poll.tick() => {
+                let containers = match runtime.containers().await {
+                    Ok(containers) => containers,
+                    Err(e) => {
+                        warn!("Failed to list containers for source {}: {}", sink.source_id, e);
+                        continue;
+                    }
+                };
+
+                let listed: HashSet<_> = containers.iter().map(|container| container.key.clone()).collect();
+                let expired = Utc::now() - RESUME_WINDOW;
+                resume_from.retain(|key, since| listed.contains(key) || *since > expired);
+
+                for Container { key, metadata, target } in containers {
+                    if !following.insert(key.clone()) {
+                        continue;
+                    }
+                    debug!("Following container {} for source {}", key, sink.source_id);
+
+                    let since = resume_from.get(&key).copied().unwrap_or(started);
+                    let mut log = ContainerLog { since, metadata, pending: Vec::new(), sink: sink.clone() };
+                    let runtime = Arc::clone(&runtime);
+                    followers.spawn(async move {
+                        if let Err(e) = runtime.follow(&target, &mut log).await {
+                            warn!("Stopped following container {}: {}", key, e);
+                        }
+                        (key, log.since)
+                    });
+                }
+            }
+
+            Some(joined) = followers.join_next(), if !followers.is_empty() => {
+                let (key, since) = joined?;
+                following.remove(&key);
+                resume_from.insert(key, since);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::streaming::sources::{ParserConfig, StreamingSourceType};
+    use uuid::Uuid;
+
+    fn container_log(since: DateTime<Utc>) -> (ContainerLog, mpsc::Receiver<StreamingLogEntry>) {
+        let (entries, entry_rx) = mpsc::channel(16);
+        let config = StreamingSourceConfig {
+            source_type: StreamingSourceType::Stdin,
+            project_id: Uuid::new_v4(),
+            name: "containers".to_string(),
+            parser_config: ParserConfig::default(),
+            buffer_size: 10,
+            batch_timeout: Duration::from_secs(1),
+            restart_on_error: false,
+            max_restarts: None,
+        };
+        let log = ContainerLog {
+            since,
+            metadata: HashMap::from([("container".to_string(), serde_json::json!("web"))]),
+            pending: Vec::new(),
+            sink: LineSink { config: Arc::new(config), source_id: "source".to_string(), entries },
+        };
+        (log, entry_rx)
+    }
+
+    #[tokio::test]
+    async fn test_lines_resume_after_last_timestamp() {
+        let since = DateTime::parse_from_rfc3339("2024-06-01T12:00:00Z").unwrap().with_timezone(&Utc);
+        let (mut log, mut entry_rx) = container_log(since);
+
+        assert!(log.push_bytes(b"2024-06-01T11:59:59.000000000Z ERROR before the source started\n").await);
+        assert!(log.push_bytes(b"2024-06-01T12:00:01.500000000Z ERROR disk ").await);
+        assert!(log.push_bytes(b"full\r\n2024-06-01T12:00:02Z plain line\n").await);
+
+        let entry = entry_rx.recv().await.unwrap();
+        assert_eq!(entry.message, "disk full");
+        assert_eq!(entry.level.as_deref(), Some("ERROR"));
+        assert_eq!(entry.timestamp.as_deref(), Some("2024-06-01T12:00:01.500+00:00"));
+        assert_eq!(entry.metadata["container"], "web");
+        let entry = entry_rx.recv().await.unwrap();
+        assert_eq!(entry.message, "plain line");
+        assert_eq!(entry.timestamp.as_deref(), Some("2024-06-01T12:00:02+00:00"));
+        assert!(entry_rx.try_recv().is_err());
+        assert_eq!(log.since.to_rfc3339(), "2024-06-01T12:00:02+00:00");
+
+        // A resumed stream repeats lines up to the last one read
+        assert!(log.push_line("2024-06-01T12:00:02Z plain line", Some("stderr")).await);
+        assert!(log.push_line("no timestamp", Some("stderr")).await);
+        let entry = entry_rx.recv().await.unwrap();
+        assert_eq!(entry.message, "no timestamp");
+        assert_eq!(entry.metadata["stream"], "stderr");
+    }
+}
@@ -0,0 +1,78 @@
+//! Docker sources
+//!
+//! A Docker source follows container logs through the Docker Engine API on its unix socket,
+//! the same stream `docker logs -f` reads, without running the CLI. Containers are selected by
+//! name or ID and by label, and entries are tagged with the container's name, ID and image.
+//! The Engine API is only reached over a unix socket, so Docker sources are unix only.
+
+use std::path::PathBuf;
+
+use tokio::time::Duration;
+
+use crate::streaming::containers::{poll_interval, string_list};
+
+#[cfg(unix)]
+mod engine;
+
+#[cfg(unix)]
+pub use engine::DockerRuntime;
+
+const DEFAULT_SOCKET: &str = "/var/run/docker.sock";
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(10);
+/// Why a Docker source can't run here
+pub const UNSUPPORTED: &str = "Docker sources are not supported on this platform";
+
+/// Which containers a Docker source follows
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DockerConfig {
+    pub socket_path: PathBuf,
+    /// Container names or ID prefixes; empty follows every running container
+    pub containers: Vec<String>,
+    /// `key` or `key=value` labels a container must all have
+    pub labels: Vec<String>,
+    pub poll_interval: Duration,
+}
+
+impl DockerConfig {
+    /// Read a Docker source's settings from its JSON config
+    pub fn from_json(config: &serde_json::Value) -> Result<Self, String> {
+        if cfg!(not(unix)) {
+            return Err(UNSUPPORTED.to_string());
+        }
+
+        Ok(Self {
+            socket_path: config
+                .get("socket_path")
+                .and_then(serde_json::Value::as_str)
+                .unwrap_or(DEFAULT_SOCKET)
+                .into(),
+            containers: string_list(config, "containers")?,
+            labels: string_list(config, "labels")?,
+            poll_interval: poll_interval(config)?.unwrap_or(DEFAULT_POLL_INTERVAL),
+        })
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_config_from_json() {
+        let config = DockerConfig::from_json(&json!({})).unwrap();
+        assert_eq!(config.socket_path, PathBuf::from(DEFAULT_SOCKET));
+        assert_eq!(config.poll_interval, DEFAULT_POLL_INTERVAL);
+
+        let config = DockerConfig::from_json(&json!({
+            "containers": ["web"], "labels": ["team=checkout"], "poll_interval_seconds": 2
+        }))
+        .unwrap();
+        assert_eq!(config.containers, ["web"]);
+        assert_eq!(config.labels, ["team=checkout"]);
+        assert_eq!(config.poll_interval, Duration::from_secs(2));
+
+        assert!(DockerConfig::from_json(&json!({"labels": "team=checkout"})).is_err());
+        assert!(DockerConfig::from_json(&json!({"poll_interval_seconds": 0})).is_err());
+    }
+}
@@ -0,0 +1,328 @@
+//! The Docker Engine API on its unix socket
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use bytes::{Buf, Bytes, BytesMut};
+use http_body_util::{BodyExt, Empty};
+use hyper::body::Incoming;
+use hyper::{Request, Response};
+use hyper_util::rt::TokioIo;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::net::UnixStream;
+use tracing::debug;
+
+use super::DockerConfig;
+use crate::streaming::containers::{Container, ContainerLog, ContainerRuntime};
+
+/// Bytes in the header Docker puts before each frame of a non-TTY container's output
+const FRAME_HEADER_LEN: usize = 8;
+
+/// A container as `GET /containers/json` lists it
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ContainerSummary {
+    id: String,
+    #[serde(default)]
+    names: Vec<String>,
+    #[serde(default)]
+    image: String,
+}
+
+impl ContainerSummary {
+    fn name(&self) -> &str {
+        self.names.first().map(|name| name.trim_start_matches('/')).unwrap_or(&self.id)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ContainerInspect {
+    config: InspectConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct InspectConfig {
+    #[serde(default)]
+    tty: bool,
+}
+
+/// The Docker Engine API on its unix socket
+pub struct DockerRuntime {
+    config: DockerConfig,
+}
+
+impl DockerRuntime {
+    pub fn new(config: DockerConfig) -> Self {
+        Self { config }
+    }
+
+    /// Send a GET over a new connection, failing on non-2xx responses
+    async fn get(&self, path_and_query: &str) -> anyhow::Result<Response<Incoming>> {
+        let stream = UnixStream::connect(&self.config.socket_path)
+            .await
+            .map_err(|e| anyhow::anyhow!("Cannot connect to {}: {}", self.config.socket_path.display(), e))?;
+        let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                debug!("Docker API connection closed: {}", e);
+            }
+        });
+
+        let request = Request::get(path_and_query)
+            .header(hyper::header::HOST, "docker")
+            .body(Empty::<Bytes>::new())?;
+        let response = sender.send_request(request).await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.into_body().collect().await?.to_bytes();
+            anyhow::bail!("Docker API returned {} for {}: {}", status, path_and_query, String::from_utf8_lossy(&body).trim());
+        }
+        Ok(response)
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, path_and_query: &str) -> anyhow::Result<T> {
+        let body = self.get(path_and_query).await?.into_body().collect().await?.to_bytes();
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    fn selects(&self, container: &ContainerSummary) -> bool {
+        self.config.containers.is_empty()
+            || self
+                .config
+                .containers
+                .iter()
+                .any(|wanted| wanted == container.name() || container.id.starts_with(wanted.as_str()))
+    }
+}
+
+/// A path and query built with proper escaping
+fn api_path(path: &str, query: &[(&str, &str)]) -> String {
+    let mut url = reqwest::Url::parse("http://docker").expect("static URL");
+    url.set_path(path);
+    if query.is_empty() {
+        return url.path().to_string();
+    }
+    url.query_pairs_mut().extend_pairs(query);
+    format!("{}?{}", url.path(), url.query().unwrap_or_default())
+}
+
+#[async_trait]
+impl ContainerRuntime for DockerRuntime {
+    type Target = String;
+
+    async fn containers(&self) -> anyhow::Result<Vec<Container<String>>> {
+        let filters = json!({ "label": self.config.labels, "status": ["running"] }).to_string();
+        let containers: Vec<ContainerSummary> =
+            self.get_json(&api_path("/containers/json", &[("filters", &filters)])).await?;
+
+        Ok(containers
+            .into_iter()
+            .filter(|container| self.selects(container))
+            .map(|container| Container {
+                key: container.id.clone(),
+                metadata: HashMap::from([
+                    ("container".to_string(), json!(container.name())),
+                    ("container_id".to_string(), json!(container.id)),
+                    ("image".to_string(), json!(container.image)),
+                ]),
+                target: container.id,
+            })
+            .collect())
+    }
+
+    async fn follow(&self, id: &String, log: &mut ContainerLog) -> anyhow::Result<()> {
+        let inspect: ContainerInspect = self.get_json(&api_path(&format!("/containers/{}/json", id), &[])).await?;
+        let since = format!("{}.{:09}", log.since.timestamp(), log.since.timestamp_subsec_nanos());
+        let path = api_path(
+            &format!("/containers/{}/logs", id),
+            &[("follow", "1"), ("stdout", "1"), ("stderr", "1"), ("timestamps", "1"), ("since", &since)],
+        );
+        let mut body = self.get(&path).await?.into_body();
+
+        // Without a TTY, stdout and stderr are multiplexed into frames
+        let mut frames = BytesMut::new();
+        while let Some(frame) = body.frame().await {
+            let Ok(data) = frame?.into_data() else { continue };
+            if inspect.config.tty {
+                if !log.push_bytes(&data).await {
+                    return Ok(());
+                }
+                continue;
+            }
+
+            frames.extend_from_slice(&data);
+            while let Some((stream, payload)) = next_frame(&mut frames) {
+                let text = String::from_utf8_lossy(&payload);
+                for line in text.lines() {
+                    if !log.push_line(line, Some(stream)).await {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Take the next complete `[stream, 0, 0, 0, length (u32 BE), payload]` frame off the buffer
+fn next_frame(buffer: &mut BytesMut) -> Option<(&'static str, Bytes)> {
+    if buffer.len() < FRAME_HEADER_LEN {
+        return None;
+    }
+    let length = u32::from_be_bytes([buffer[4], buffer[5], buffer[6], buffer[7]]) as usize;
+    if buffer.len() < FRAME_HEADER_LEN + length {
+        return None;
+    }
+    let stream = match buffer[0] {
+        2 => "stderr",
+        _ => "stdout",
+    };
+    buffer.advance(FRAME_HEADER_LEN);
+    Some((stream, buffer.split_to(length).freeze()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::streaming::sources::{ParserConfig, StreamingSourceConfig, StreamingSourceType};
+    use crate::streaming::{LogFilter, StreamingHub};
+    use hyper::service::service_fn;
+    use std::convert::Infallible;
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+    use tokio::net::UnixListener;
+    use tokio::sync::mpsc;
+    use tokio::time::Duration;
+    use uuid::Uuid;
+
+    fn frame(stream: u8, text: &str) -> Vec<u8> {
+        let mut frame = vec![stream, 0, 0, 0];
+        frame.extend((text.len() as u32).to_be_bytes());
+        frame.extend(text.as_bytes());
+        frame
+    }
+
+    #[test]
+    fn test_frames_split_across_reads() {
+        let mut buffer = BytesMut::new();
+        let first = frame(1, "one\n");
+        buffer.extend_from_slice(&first[..6]);
+        assert!(next_frame(&mut buffer).is_none());
+        buffer.extend_from_slice(&first[6..]);
+        buffer.extend_from_slice(&frame(2, "two\n"));
+
+        assert_eq!(next_frame(&mut buffer), Some(("stdout", Bytes::from("one\n"))));
+        assert_eq!(next_frame(&mut buffer), Some(("stderr", Bytes::from("two\n"))));
+        assert!(buffer.is_empty());
+    }
+
+    /// Just enough of the Engine API to list containers and stream their logs
+    async fn mock_engine(socket: PathBuf, running: Arc<Mutex<Vec<(&'static str, &'static str)>>>) {
+        let listener = UnixListener::bind(socket).unwrap();
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let running = Arc::clone(&running);
+            tokio::spawn(async move {
+                let service = service_fn(move |request: Request<Incoming>| {
+                    let running = Arc::clone(&running);
+                    async move {
+                        let uri = request.uri().to_string();
+                        let body = if uri.starts_with("/containers/json") {
+                            assert!(uri.contains("filters="), "{}", uri);
+                            let running = running.lock().unwrap().clone();
+                            let containers: Vec<_> = running
+                                .iter()
+                                .map(|(id, name)| json!({"Id": id, "Names": [format!("/{}", name)], "Image": "app:1"}))
+                                .collect();
+                            json!(containers).to_string().into_bytes()
+                        } else if uri.ends_with("/json") {
+                            json!({"Config": {"Tty": false}}).to_string().into_bytes()
+                        } else {
+                            assert!(uri.contains("follow=1") && uri.contains("timestamps=1"), "{}", uri);
+                            let id = uri.trim_start_matches("/containers/").split('/').next().unwrap();
+                            // Fixed times after the source starts, so resumed streams repeat nothing
+                            let mut body = frame(1, &format!("2099-01-01T00:00:00Z started {}\n", id));
+                            body.extend(frame(2, &format!("2099-01-01T00:00:01Z ERROR failed {}\n", id)));
+                            body
+                        };
+                        Ok::<_, Infallible>(Response::new(http_body_util::Full::new(Bytes::from(body))))
+                    }
+                });
+                let _ = hyper::server::conn::http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await;
+            });
+        }
+    }
+
+    #[tokio::test]
+    async fn test_follows_new_containers_through_mock_engine() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("docker.sock");
+        let running = Arc::new(Mutex::new(vec![("aaa111", "web")]));
+        tokio::spawn(mock_engine(socket.clone(), Arc::clone(&running)));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let streaming_hub = Arc::new(StreamingHub::new());
+        let project_id = Uuid::new_v4();
+        let docker_config = DockerConfig {
+            socket_path: socket,
+            containers: Vec::new(),
+            labels: vec!["team=checkout".to_string()],
+            poll_interval: Duration::from_millis(50),
+        };
+        let config = StreamingSourceConfig {
+            source_type: StreamingSourceType::Docker(docker_config.clone()),
+            project_id,
+            name: "docker".to_string(),
+            parser_config: ParserConfig::default(),
+            buffer_size: 1,
+            batch_timeout: Duration::from_millis(50),
+            restart_on_error: false,
+            max_restarts: None,
+        };
+        let (_cancel_tx, mut cancel_rx) = mpsc::unbounded_channel();
+        let hub = Arc::clone(&streaming_hub);
+        tokio::spawn(async move {
+            let runtime = DockerRuntime::new(docker_config.clone());
+            crate::streaming::containers::run(&hub, &config, "docker-source", runtime, docker_config.poll_interval, &mut cancel_rx)
+                .await
+        });
+
+        let recent = || streaming_hub.recent_logs(project_id, &LogFilter::default(), 100);
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        while recent().len() < 2 {
+            assert!(tokio::time::Instant::now() < deadline, "no logs from the first container");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        // Start a second container once the first is being followed
+        running.lock().unwrap().push(("bbb222", "worker"));
+        while recent().len() < 4 {
+            assert!(tokio::time::Instant::now() < deadline, "no logs from the new container");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let mut seen: Vec<_> = recent()
+            .into_iter()
+            .map(|entry| {
+                let tag = |key: &str| entry.metadata[key].as_str().unwrap().to_string();
+                (tag("container"), tag("stream"), entry.level.clone(), entry.message.clone())
+            })
+            .collect();
+        seen.sort();
+        assert_eq!(
+            seen,
+            [
+                ("web".to_string(), "stderr".to_string(), Some("ERROR".to_string()), "failed aaa111".to_string()),
+                ("web".to_string(), "stdout".to_string(), None, "started aaa111".to_string()),
+                ("worker".to_string(), "stderr".to_string(), Some("ERROR".to_string()), "failed bbb222".to_string()),
+                ("worker".to_string(), "stdout".to_string(), None, "started bbb222".to_string()),
+            ]
+        );
+    }
+}
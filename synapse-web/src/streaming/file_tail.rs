@@ -0,0 +1,834 @@
+//! File sources
+//!
+//! A file source tails every file matching a path or glob pattern, like `tail -F` across a
+//! directory. Files are told apart by device and inode plus a fingerprint of their first bytes,
+//! so the source notices rename rotation (the old file is read to its end through the handle it
+//! holds) and copytruncate (the file shrinks or its first bytes change, and is read again from
+//! the start). Where there are no inodes, as on Windows, the fingerprint alone tells them apart.
+//! Read offsets are saved next to the database, and a restarted source resumes where it
+//! stopped, first reading what was left in a file rotated to `.1` or `.1.gz` meanwhile.
+//! Lines can be joined into multi-line events, such as stack traces, per file.
+
+use std::io::{Read, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use flate2::read::MultiGzDecoder;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::sync::mpsc;
+use tokio::time::{Duration, Instant, MissedTickBehavior};
+use tracing::{info, warn};
+
+use crate::streaming::sources::{StreamingSourceConfig, StreamingSourceManager};
+use crate::streaming::StreamingHub;
+
+/// How often files are checked for new data and the pattern for new files
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// How often read offsets are saved while data keeps arriving
+const SAVE_INTERVAL: Duration = Duration::from_secs(1);
+const READ_CHUNK: usize = 64 * 1024;
+/// Most bytes read from one file per poll, so one busy file can't starve the others
+const MAX_READ_PER_POLL: u64 = 4 * 1024 * 1024;
+/// Leading bytes hashed to recognise a file
+const FINGERPRINT_LEN: usize = 1024;
+/// Longest line kept while waiting for its newline; longer lines are split
+const MAX_LINE_LEN: usize = 1024 * 1024;
+/// Most bytes read from what a rotated file had left
+const MAX_REMAINDER_LEN: u64 = 64 * 1024 * 1024;
+const DEFAULT_MAX_LINES: usize = 500;
+const DEFAULT_FLUSH_AFTER: Duration = Duration::from_secs(1);
+
+/// Where a file source starts in files it has no saved offset for when it first runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StartAt {
+    Beginning,
+    End,
+}
+
+/// How lines are joined into multi-line events
+#[derive(Debug, Clone)]
+pub struct MultilineConfig {
+    /// Lines matching this start a new event; other lines continue the current one
+    pub start_pattern: Regex,
+    pub max_lines: usize,
+    /// How long an event waits for more lines once its file has none
+    pub flush_after: Duration,
+}
+
+/// Which files a file source tails and how
+#[derive(Debug, Clone)]
+pub struct FileTailConfig {
+    /// A file path or glob pattern such as `/var/log/app/*.log`
+    pub path: String,
+    pub start_at: StartAt,
+    pub multiline: Option<MultilineConfig>,
+}
+
+impl FileTailConfig {
+    /// Read a file source's settings from its JSON config
+    pub fn from_json(config: &serde_json::Value) -> Result<Self, String> {
+        let path = config
+            .get("path")
+            .and_then(serde_json::Value::as_str)
+            .ok_or("Missing 'path' for file source")?;
+        glob::Pattern::new(path).map_err(|e| format!("Invalid path pattern '{}': {}", path, e))?;
+
+        let start_at = match config.get("start_at").and_then(serde_json::Value::as_str) {
+            None | Some("beginning") => StartAt::Beginning,
+            Some("end") => StartAt::End,
+            Some(other) => return Err(format!("Unknown start_at '{}' (expected beginning or end)", other)),
+        };
+
+        let multiline = match config.get("multiline") {
+            None | Some(serde_json::Value::Null) => None,
+            Some(multiline) => {
+                let pattern = multiline
+                    .get("start_pattern")
+                    .and_then(serde_json::Value::as_str)
+                    .ok_or("Missing 'start_pattern' for multiline")?;
+                let positive = |key: &str| match multiline.get(key) {
+                    None | Some(serde_json::Value::Null) => Ok(None),
+                    Some(value) => value
+                        .as_u64()
+                        .filter(|value| *value > 0)
+                        .map(Some)
+                        .ok_or_else(|| format!("'{}' must be a positive integer", key)),
+                };
+                Some(MultilineConfig {
+                    start_pattern: Regex::new(pattern).map_err(|e| format!("Invalid start_pattern: {}", e))?,
+                    max_lines: positive("max_lines")?.map_or(DEFAULT_MAX_LINES, |lines| lines as usize),
+                    flush_after: positive("flush_after_ms")?.map_or(DEFAULT_FLUSH_AFTER, Duration::from_millis),
+                })
+            }
+        };
+
+        Ok(Self {
+            path: path.to_string(),
+            start_at,
+            multiline,
+        })
+    }
+}
+
+/// A hash of a file's leading bytes
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct Fingerprint {
+    len: usize,
+    sha256: String,
+}
+
+impl Fingerprint {
+    fn of(bytes: &[u8]) -> Self {
+        Self { len: bytes.len(), sha256: hex::encode(Sha256::digest(bytes)) }
+    }
+}
+
+/// A file's device and inode
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct FileId {
+    dev: u64,
+    inode: u64,
+}
+
+impl FileId {
+    #[cfg(unix)]
+    fn of(metadata: &std::fs::Metadata) -> Option<Self> {
+        use std::os::unix::fs::MetadataExt;
+        Some(Self { dev: metadata.dev(), inode: metadata.ino() })
+    }
+
+    /// Files have no inode to tell them apart by here
+    #[cfg(not(unix))]
+    fn of(_metadata: &std::fs::Metadata) -> Option<Self> {
+        None
+    }
+}
+
+/// How far a file was read, as saved between runs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct SavedOffset {
+    path: PathBuf,
+    #[serde(flatten)]
+    id: FileId,
+    offset: u64,
+    fingerprint: Fingerprint,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct OffsetFile {
+    files: Vec<SavedOffset>,
+}
+
+/// Read offsets saved as JSON, written to a temporary file and renamed into place
+struct OffsetRegistry {
+    path: PathBuf,
+}
+
+impl OffsetRegistry {
+    /// Saved offsets, or `None` when the source has never saved any
+    async fn load(&self) -> Option<Vec<SavedOffset>> {
+        let json = tokio::fs::read(&self.path).await.ok()?;
+        match serde_json::from_slice::<OffsetFile>(&json) {
+            Ok(saved) => Some(saved.files),
+            Err(e) => {
+                warn!("Ignoring unreadable file offsets {}: {}", self.path.display(), e);
+                Some(Vec::new())
+            }
+        }
+    }
+
+    async fn save(&self, files: Vec<SavedOffset>) -> std::io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let temp = self.path.with_extension("json.tmp");
+        tokio::fs::write(&temp, serde_json::to_vec(&OffsetFile { files })?).await?;
+        tokio::fs::rename(&temp, &self.path).await
+    }
+
+    async fn remove(&self) {
+        if let Err(e) = tokio::fs::remove_file(&self.path).await {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!("Failed to remove file offsets {}: {}", self.path.display(), e);
+            }
+        }
+    }
+}
+
+/// Splits bytes into lines, tracking the file offset just past each
+#[derive(Default)]
+struct LineSplitter {
+    partial: Vec<u8>,
+}
+
+impl LineSplitter {
+    /// Split bytes that end at `end_offset` in the file
+    fn push(&mut self, bytes: &[u8], end_offset: u64) -> Vec<(String, u64)> {
+        self.partial.extend_from_slice(bytes);
+        let partial_start = end_offset - self.partial.len() as u64;
+        let mut lines = Vec::new();
+        let mut start = 0;
+        while let Some(end) = self.partial[start..].iter().position(|b| *b == b'\n') {
+            let line = &self.partial[start..start + end];
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            start += end + 1;
+            lines.push((String::from_utf8_lossy(line).into_owned(), partial_start + start as u64));
+        }
+        self.partial.drain(..start);
+
+        if self.partial.len() > MAX_LINE_LEN {
+            lines.push((String::from_utf8_lossy(&std::mem::take(&mut self.partial)).into_owned(), end_offset));
+        }
+        lines
+    }
+
+    /// The trailing line without a newline, once no more will be written to it
+    fn finish(&mut self, end_offset: u64) -> Option<(String, u64)> {
+        (!self.partial.is_empty())
+            .then(|| (String::from_utf8_lossy(&std::mem::take(&mut self.partial)).into_owned(), end_offset))
+    }
+}
+
+/// A log event and the offset just past its last line
+#[derive(Debug, PartialEq)]
+struct Event {
+    text: String,
+    end_offset: u64,
+}
+
+/// Joins lines into events by the multiline config, or passes them through
+struct Joiner {
+    multiline: Option<MultilineConfig>,
+    lines: Vec<String>,
+    end_offset: u64,
+    last_line: Instant,
+}
+
+impl Joiner {
+    fn new(multiline: Option<MultilineConfig>) -> Self {
+        Self { multiline, lines: Vec::new(), end_offset: 0, last_line: Instant::now() }
+    }
+
+    fn push(&mut self, line: String, end_offset: u64) -> Option<Event> {
+        let Some(multiline) = &self.multiline else {
+            return Some(Event { text: line, end_offset });
+        };
+        let (starts_event, max_lines) = (multiline.start_pattern.is_match(&line), multiline.max_lines);
+
+        let done = if starts_event { self.take() } else { None };
+        self.lines.push(line);
+        self.end_offset = end_offset;
+        self.last_line = Instant::now();
+        if self.lines.len() >= max_lines {
+            return done.or_else(|| self.take());
+        }
+        done
+    }
+
+    /// The event being joined, if its file has had nothing more for a while
+    fn expired(&mut self) -> Option<Event> {
+        let flush_after = self.multiline.as_ref()?.flush_after;
+        if self.last_line.elapsed() >= flush_after {
+            self.take()
+        } else {
+            None
+        }
+    }
+
+    fn take(&mut self) -> Option<Event> {
+        (!self.lines.is_empty()).then(|| Event { text: std::mem::take(&mut self.lines).join("\n"), end_offset: self.end_offset })
+    }
+}
+
+/// One file being tailed
+struct TailedFile {
+    path: PathBuf,
+    file: File,
+    id: FileId,
+    /// Bytes read so far
+    read_offset: u64,
+    /// Offset just past the last line in an entry that was produced
+    emitted_offset: u64,
+    fingerprint: Fingerprint,
+    last_len: u64,
+    lines: LineSplitter,
+    joiner: Joiner,
+    /// The path now names another file or none; this one is read to its end and dropped
+    rotated: bool,
+}
+
+impl TailedFile {
+    async fn open(path: &Path, offset: u64, multiline: Option<MultilineConfig>) -> std::io::Result<Self> {
+        let mut file = File::open(path).await?;
+        let metadata = file.metadata().await?;
+        let fingerprint_len = metadata.len().min(FINGERPRINT_LEN as u64) as usize;
+        let fingerprint = read_fingerprint(&mut file, fingerprint_len).await?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            id: FileId::of(&metadata).unwrap_or_default(),
+            read_offset: offset,
+            emitted_offset: offset,
+            fingerprint,
+            last_len: metadata.len(),
+            lines: LineSplitter::default(),
+            joiner: Joiner::new(multiline),
+            rotated: false,
+        })
+    }
+
+    fn saved(&self) -> SavedOffset {
+        SavedOffset {
+            path: self.path.clone(),
+            id: self.id,
+            offset: self.emitted_offset,
+            fingerprint: self.fingerprint.clone(),
+        }
+    }
+
+    /// Whether the path still names this file rather than one that replaced it
+    async fn path_is_same_file(&mut self) -> bool {
+        let Ok(metadata) = tokio::fs::metadata(&self.path).await else { return false };
+        match FileId::of(&metadata) {
+            Some(id) => id == self.id,
+            // Replaced when the path's first bytes differ while the open file still has its own;
+            // when both changed, the file was rewritten in place
+            None => {
+                fingerprint_matches(&self.path, &self.fingerprint).await
+                    || read_fingerprint(&mut self.file, self.fingerprint.len).await.ok().as_ref() != Some(&self.fingerprint)
+            }
+        }
+    }
+
+    /// Whether `path` names this file
+    async fn is_at(&self, path: &Path, metadata: &std::fs::Metadata) -> bool {
+        match FileId::of(metadata) {
+            Some(id) => id == self.id,
+            None => self.fingerprint.len > 0 && fingerprint_matches(path, &self.fingerprint).await,
+        }
+    }
+
+    /// Read what was written since the last poll; returns whether the end of the file was reached
+    async fn poll(&mut self, events: &mut Vec<Event>) -> std::io::Result<bool> {
+        let len = self.file.metadata().await?.len();
+        if len != self.last_len {
+            let rewritten = self.fingerprint.len > 0
+                && read_fingerprint(&mut self.file, self.fingerprint.len).await.ok().as_ref() != Some(&self.fingerprint);
+            if len < self.read_offset || rewritten {
+                info!("{} was truncated, reading it from the start", self.path.display());
+                let pending = self.joiner.take();
+                self.emit(events, pending);
+                self.read_offset = 0;
+                self.emitted_offset = 0;
+                self.lines = LineSplitter::default();
+                self.fingerprint = Fingerprint::default();
+            }
+            if self.fingerprint.len < FINGERPRINT_LEN && len > self.fingerprint.len as u64 {
+                self.fingerprint = read_fingerprint(&mut self.file, len.min(FINGERPRINT_LEN as u64) as usize).await?;
+            }
+            self.last_len = len;
+        }
+
+        self.file.seek(SeekFrom::Start(self.read_offset)).await?;
+        let mut chunk = vec![0; READ_CHUNK];
+        let mut read = 0;
+        let at_end = loop {
+            if read >= MAX_READ_PER_POLL {
+                break false;
+            }
+            let n = self.file.read(&mut chunk).await?;
+            if n == 0 {
+                break true;
+            }
+            read += n as u64;
+            self.read_offset += n as u64;
+            for (line, end_offset) in self.lines.push(&chunk[..n], self.read_offset) {
+                let event = self.joiner.push(line, end_offset);
+                self.emit(events, event);
+            }
+        };
+
+        if at_end && self.rotated {
+            if let Some((line, end_offset)) = self.lines.finish(self.read_offset) {
+                let event = self.joiner.push(line, end_offset);
+                self.emit(events, event);
+            }
+            let pending = self.joiner.take();
+            self.emit(events, pending);
+        } else {
+            let event = self.joiner.expired();
+            self.emit(events, event);
+        }
+        Ok(at_end)
+    }
+
+    fn emit(&mut self, events: &mut Vec<Event>, event: Option<Event>) {
+        if let Some(event) = event {
+            self.emitted_offset = event.end_offset;
+            events.push(event);
+        }
+    }
+}
+
+/// Hash the first `len` bytes of a file
+async fn read_fingerprint(file: &mut File, len: usize) -> std::io::Result<Fingerprint> {
+    file.seek(SeekFrom::Start(0)).await?;
+    let mut head = vec![0; len];
+    file.read_exact(&mut head).await?;
+    Ok(Fingerprint::of(&head))
+}
+
+/// Whether a file's first bytes match a saved fingerprint
+async fn fingerprint_matches(path: &Path, fingerprint: &Fingerprint) -> bool {
+    let Ok(mut file) = File::open(path).await else { return false };
+    read_fingerprint(&mut file, fingerprint.len).await.ok().as_ref() == Some(fingerprint)
+}
+
+/// Whether `path` names the file an offset was saved for
+async fn is_saved_file(path: &Path, metadata: &std::fs::Metadata, saved: &SavedOffset) -> bool {
+    // An inode can be reused by a new file, so the fingerprint has to match too
+    FileId::of(metadata).is_none_or(|id| id == saved.id) && fingerprint_matches(path, &saved.fingerprint).await
+}
+
+/// What a file rotated while the source was stopped had left after its saved offset
+///
+/// Looks for the file as `<path>.1` (by inode) or `<path>.1.gz` (by fingerprint).
+async fn rotated_remainder(saved: &SavedOffset) -> Option<(PathBuf, Vec<u8>)> {
+    let rotated = PathBuf::from(format!("{}.1", saved.path.display()));
+    if let Ok(metadata) = tokio::fs::metadata(&rotated).await {
+        if is_saved_file(&rotated, &metadata, saved).await {
+            let mut file = File::open(&rotated).await.ok()?;
+            file.seek(SeekFrom::Start(saved.offset)).await.ok()?;
+            let mut remainder = Vec::new();
+            file.take(MAX_REMAINDER_LEN).read_to_end(&mut remainder).await.ok()?;
+            return Some((rotated, remainder));
+        }
+    }
+
+    let compressed = PathBuf::from(format!("{}.1.gz", saved.path.display()));
+    let saved = saved.clone();
+    let path = compressed.clone();
+    let remainder = tokio::task::spawn_blocking(move || -> Option<Vec<u8>> {
+        let mut decoder = MultiGzDecoder::new(std::fs::File::open(&path).ok()?);
+        let mut head = vec![0; saved.fingerprint.len];
+        decoder.read_exact(&mut head).ok()?;
+        if Fingerprint::of(&head) != saved.fingerprint {
+            return None;
+        }
+        let skip = saved.offset.checked_sub(head.len() as u64)?;
+        if std::io::copy(&mut (&mut decoder).take(skip), &mut std::io::sink()).ok()? != skip {
+            return None;
+        }
+        let mut remainder = Vec::new();
+        decoder.take(MAX_REMAINDER_LEN).read_to_end(&mut remainder).ok()?;
+        Some(remainder)
+    })
+    .await
+    .ok()??;
+    Some((compressed, remainder))
+}
+
+/// Files matching the pattern now, leaving out compressed archives
+fn matching_files(pattern: &str) -> Vec<PathBuf> {
+    let Ok(paths) = glob::glob(pattern) else { return Vec::new() };
+    paths
+        .filter_map(Result::ok)
+        .filter(|path| path.extension().is_none_or(|extension| extension != "gz") && path.is_file())
+        .collect()
+}
+
+/// Every file a source tails and their offsets
+struct Tailer {
+    config: FileTailConfig,
+    registry: OffsetRegistry,
+    files: Vec<TailedFile>,
+    /// Events from rotated remainders found at startup, sent with the first poll
+    startup_events: Vec<(PathBuf, Event)>,
+    /// Offsets changed since they were last saved
+    dirty: bool,
+}
+
+impl Tailer {
+    /// Open the matching files, resuming from saved offsets
+    async fn start(config: FileTailConfig, registry: OffsetRegistry) -> Self {
+        let saved = registry.load().await;
+        let mut tailer = Self { config, registry, files: Vec::new(), startup_events: Vec::new(), dirty: false };
+        let mut unclaimed = saved.clone().unwrap_or_default();
+
+        for path in matching_files(&tailer.config.path) {
+            let Ok(metadata) = tokio::fs::metadata(&path).await else { continue };
+            let mut resume = None;
+            for index in 0..unclaimed.len() {
+                if is_saved_file(&path, &metadata, &unclaimed[index]).await {
+                    let saved = unclaimed.remove(index);
+                    resume = Some(if metadata.len() < saved.offset { 0 } else { saved.offset });
+                    break;
+                }
+            }
+            let offset = match resume {
+                Some(offset) => offset,
+                None if saved.is_none() && tailer.config.start_at == StartAt::End => metadata.len(),
+                None => 0,
+            };
+            tailer.open(&path, offset).await;
+        }
+
+        // Files rotated away while the source was stopped
+        for saved in unclaimed {
+            if let Some((rotated, remainder)) = rotated_remainder(&saved).await {
+                info!("Reading the rest of {} from {}", saved.path.display(), rotated.display());
+                let mut lines = LineSplitter::default();
+                let mut joiner = Joiner::new(tailer.config.multiline.clone());
+                let end = saved.offset + remainder.len() as u64;
+                let mut split = lines.push(&remainder, end);
+                split.extend(lines.finish(end));
+                let mut events: Vec<_> =
+                    split.into_iter().filter_map(|(line, end_offset)| joiner.push(line, end_offset)).collect();
+                events.extend(joiner.take());
+                tailer.startup_events.extend(events.into_iter().map(|event| (rotated.clone(), event)));
+            }
+        }
+
+        tailer.dirty = true;
+        tailer
+    }
+
+    async fn open(&mut self, path: &Path, offset: u64) {
+        match TailedFile::open(path, offset, self.config.multiline.clone()).await {
+            Ok(file) => {
+                info!("Tailing {} from offset {}", path.display(), offset);
+                self.files.push(file);
+            }
+            Err(e) => warn!("Failed to open {}: {}", path.display(), e),
+        }
+    }
+
+    /// Check for rotated and new files, then read each file's new lines
+    async fn poll(&mut self) -> Vec<(PathBuf, Event)> {
+        let mut events = std::mem::take(&mut self.startup_events);
+
+        for file in self.files.iter_mut().filter(|file| !file.rotated) {
+            if !file.path_is_same_file().await {
+                info!("{} was rotated, reading the old file to its end", file.path.display());
+                file.rotated = true;
+            }
+        }
+
+        for path in matching_files(&self.config.path) {
+            if self.files.iter().any(|file| !file.rotated && file.path == path) {
+                continue;
+            }
+            // A rotated file renamed to a name the pattern matches is already being read
+            let Ok(metadata) = tokio::fs::metadata(&path).await else { continue };
+            let mut tailed = false;
+            for file in &self.files {
+                if file.is_at(&path, &metadata).await {
+                    tailed = true;
+                    break;
+                }
+            }
+            if !tailed {
+                self.open(&path, 0).await;
+            }
+        }
+
+        let mut finished = Vec::new();
+        for (index, file) in self.files.iter_mut().enumerate() {
+            let mut file_events = Vec::new();
+            let before = file.emitted_offset;
+            match file.poll(&mut file_events).await {
+                Ok(at_end) if at_end && file.rotated => finished.push(index),
+                Ok(_) => {}
+                Err(e) => {
+                    warn!("Failed to read {}: {}", file.path.display(), e);
+                    finished.push(index);
+                }
+            }
+            self.dirty |= file.emitted_offset != before || !file_events.is_empty();
+            events.extend(file_events.into_iter().map(|event| (file.path.clone(), event)));
+        }
+        for index in finished.into_iter().rev() {
+            self.files.remove(index);
+            self.dirty = true;
+        }
+        events
+    }
+
+    /// Save the offsets of every file, the current one first where a rotated file shares its path
+    async fn save(&mut self) {
+        let mut saved: Vec<_> = self.files.iter().filter(|file| !file.rotated).map(TailedFile::saved).collect();
+        saved.extend(self.files.iter().filter(|file| file.rotated).map(TailedFile::saved));
+        match self.registry.save(saved).await {
+            Ok(()) => self.dirty = false,
+            Err(e) => warn!("Failed to save file offsets {}: {}", self.registry.path.display(), e),
+        }
+    }
+}
+
+/// Where a source's offsets are saved
+fn offsets_path(source_id: &str) -> PathBuf {
+    synapse_core::db_path::get_data_dir()
+        .join("streaming-offsets")
+        .join(format!("{}.json", source_id))
+}
+
+/// Tail a file source's files until it is cancelled
+///
+/// Offsets are saved once the entries read up to them have reached the hub. A cancelled source
+/// has been deleted, so its offsets are removed rather than saved.
+pub async fn run(
+    streaming_hub: &Arc<StreamingHub>,
+    config: &StreamingSourceConfig,
+    tail: &FileTailConfig,
+    source_id: &str,
+    cancel_rx: &mut mpsc::UnboundedReceiver<()>,
+) -> anyhow::Result<()> {
+    let registry = OffsetRegistry { path: offsets_path(source_id) };
+    let mut tailer = Tailer::start(tail.clone(), registry).await;
+    info!("Monitoring files matching {}", tail.path);
+
+    let mut buffer = Vec::new();
+    let mut batch_started = Instant::now();
+    let mut last_save = Instant::now();
+    let mut poll = tokio::time::interval(POLL_INTERVAL);
+    poll.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = cancel_rx.recv() => {
+                info!("File source {} cancelled", source_id);
+                if !buffer.is_empty() {
+                    streaming_hub.add_logs(config.project_id, source_id, buffer).await?;
+                }
+                tailer.registry.remove().await;
+                return Ok(());
+            }
+
+            _ = poll.tick() => {
+                for (path, event) in tailer.poll().await {
+                    let Some(mut entry) = StreamingSourceManager::parse_log_line(&event.text, config, source_id) else {
+                        continue;
+                    };
+                    entry.metadata.insert("file".to_string(), serde_json::json!(path));
+                    if buffer.is_empty() {
+                        batch_started = Instant::now();
+                    }
+                    buffer.push(entry);
+                }
+
+                if buffer.len() >= config.buffer_size || (!buffer.is_empty() && batch_started.elapsed() >= config.batch_timeout) {
+                    streaming_hub.add_logs(config.project_id, source_id, std::mem::take(&mut buffer)).await?;
+                }
+                // Offsets only move past entries that have reached the hub
+                if buffer.is_empty() && tailer.dirty && last_save.elapsed() >= SAVE_INTERVAL {
+                    tailer.save().await;
+                    last_save = Instant::now();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use serde_json::json;
+    use std::io::Write;
+
+    fn config(pattern: &Path, multiline: Option<serde_json::Value>) -> FileTailConfig {
+        FileTailConfig::from_json(&json!({"path": pattern, "multiline": multiline})).unwrap()
+    }
+
+    async fn start(config: &FileTailConfig, offsets: &Path) -> Tailer {
+        Tailer::start(config.clone(), OffsetRegistry { path: offsets.to_path_buf() }).await
+    }
+
+    fn append(path: &Path, text: &str) {
+        std::fs::OpenOptions::new().create(true).append(true).open(path).unwrap().write_all(text.as_bytes()).unwrap();
+    }
+
+    async fn texts(tailer: &mut Tailer) -> Vec<String> {
+        tailer.poll().await.into_iter().map(|(_, event)| event.text).collect()
+    }
+
+    #[test]
+    fn test_config_from_json() {
+        let config = FileTailConfig::from_json(&json!({"path": "/var/log/app/*.log"})).unwrap();
+        assert_eq!(config.start_at, StartAt::Beginning);
+        assert!(config.multiline.is_none());
+
+        let config = FileTailConfig::from_json(&json!({
+            "path": "/var/log/app.log",
+            "start_at": "end",
+            "multiline": {"start_pattern": "^\\d{4}-", "max_lines": 50}
+        }))
+        .unwrap();
+        assert_eq!(config.start_at, StartAt::End);
+        let multiline = config.multiline.unwrap();
+        assert_eq!(multiline.max_lines, 50);
+        assert_eq!(multiline.flush_after, DEFAULT_FLUSH_AFTER);
+
+        assert!(FileTailConfig::from_json(&json!({})).is_err());
+        assert!(FileTailConfig::from_json(&json!({"path": "/var/log/[.log"})).is_err());
+        assert!(FileTailConfig::from_json(&json!({"path": "/a", "start_at": "middle"})).is_err());
+        assert!(FileTailConfig::from_json(&json!({"path": "/a", "multiline": {"start_pattern": "("}})).is_err());
+    }
+
+    #[test]
+    fn test_multiline_events_and_offsets() {
+        let multiline = config(Path::new("/a"), Some(json!({"start_pattern": "^\\d{4}-", "max_lines": 3})))
+            .multiline;
+        let mut lines = LineSplitter::default();
+        let mut joiner = Joiner::new(multiline);
+        let text = "2024-06-01 ERROR boom\r\n  at a\n  at b\n2024-06-01 INFO ok\nx\ny\nz\n2024-06-01 tail";
+
+        let split = lines.push(text.as_bytes(), text.len() as u64);
+        assert_eq!(split[0], ("2024-06-01 ERROR boom".to_string(), 23));
+        let events: Vec<_> = split.into_iter().filter_map(|(line, end)| joiner.push(line, end)).collect();
+        assert_eq!(
+            events,
+            [
+                Event { text: "2024-06-01 ERROR boom\n  at a\n  at b".to_string(), end_offset: 37 },
+                // max_lines cuts an event short
+                Event { text: "2024-06-01 INFO ok\nx\ny".to_string(), end_offset: 60 },
+            ]
+        );
+        assert_eq!(joiner.take(), Some(Event { text: "z".to_string(), end_offset: 62 }));
+        assert_eq!(lines.finish(text.len() as u64), Some(("2024-06-01 tail".to_string(), text.len() as u64)));
+    }
+
+    #[tokio::test]
+    async fn test_globs_rotation_and_truncation() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = dir.path().join("app.log");
+        let worker = dir.path().join("worker.log");
+        append(&app, "one\ntwo\n");
+        let config = config(&dir.path().join("*.log"), None);
+        let mut tailer = start(&config, &dir.path().join("offsets.json")).await;
+        assert_eq!(texts(&mut tailer).await, ["one", "two"]);
+
+        // Rename rotation: the old file is read to its end, then the new one from the start
+        std::fs::rename(&app, dir.path().join("app.log.1")).unwrap();
+        append(&dir.path().join("app.log.1"), "three\n");
+        append(&app, "four\n");
+        assert_eq!(texts(&mut tailer).await, ["three", "four"]);
+        assert_eq!(tailer.files.len(), 1);
+
+        // New files matching the pattern are picked up from the start
+        append(&worker, "started\n");
+        assert_eq!(texts(&mut tailer).await, ["started"]);
+
+        // copytruncate, whether the file is caught shorter or already rewritten past the offset
+        std::fs::write(&worker, "").unwrap();
+        assert!(texts(&mut tailer).await.is_empty());
+        append(&worker, "restarted\n");
+        assert_eq!(texts(&mut tailer).await, ["restarted"]);
+        std::fs::write(&app, "rewritten after truncation\n").unwrap();
+        assert_eq!(texts(&mut tailer).await, ["rewritten after truncation"]);
+    }
+
+    #[tokio::test]
+    async fn test_offsets_survive_restarts_and_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = dir.path().join("app.log");
+        let offsets = dir.path().join("state").join("offsets.json");
+        append(&app, "one\n");
+        let config = config(&app, None);
+
+        let mut tailer = start(&config, &offsets).await;
+        assert_eq!(texts(&mut tailer).await, ["one"]);
+        tailer.save().await;
+        drop(tailer);
+
+        // Lines written while the source was stopped are read once
+        append(&app, "two\n");
+        let mut tailer = start(&config, &offsets).await;
+        assert_eq!(texts(&mut tailer).await, ["two"]);
+        tailer.save().await;
+        drop(tailer);
+
+        // Rotated and compressed while stopped: the rest comes from app.log.1.gz
+        append(&app, "three\n");
+        let mut gzip = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(&std::fs::read(&app).unwrap()).unwrap();
+        std::fs::write(dir.path().join("app.log.1.gz"), gzip.finish().unwrap()).unwrap();
+        std::fs::remove_file(&app).unwrap();
+        append(&app, "four\n");
+        let mut tailer = start(&config, &offsets).await;
+        let events = tailer.poll().await;
+        assert_eq!(events[0].0, dir.path().join("app.log.1.gz"));
+        assert_eq!(events.into_iter().map(|(_, event)| event.text).collect::<Vec<_>>(), ["three", "four"]);
+        tailer.save().await;
+
+        // Rotated by rename while stopped: the rest comes from app.log.1
+        append(&app, "five\n");
+        drop(tailer);
+        std::fs::rename(&app, dir.path().join("app.log.1")).unwrap();
+        append(&app, "six\n");
+        let mut tailer = start(&config, &offsets).await;
+        assert_eq!(texts(&mut tailer).await, ["five", "six"]);
+
+        tailer.registry.remove().await;
+        assert!(!offsets.exists());
+    }
+
+    #[tokio::test]
+    async fn test_start_at_end_only_without_saved_offsets() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = dir.path().join("app.log");
+        append(&app, "old\n");
+        let config = FileTailConfig {
+            start_at: StartAt::End,
+            ..config(&app, None)
+        };
+
+        let mut tailer = start(&config, &dir.path().join("offsets.json")).await;
+        append(&app, "new\n");
+        assert_eq!(texts(&mut tailer).await, ["new"]);
+    }
+}
@@ -0,0 +1,513 @@
+//! Fluent Forward sources
+//!
+//! A Fluent Forward source listens on TCP for the msgpack protocol Fluent Bit's and Fluentd's
+//! `forward` outputs speak, so existing log pipelines can send to Synapse unchanged. Message,
+//! Forward and (optionally gzipped) PackedForward modes are accepted, and a message whose
+//! options carry a `chunk` ID is acknowledged once its events are queued. Records are mapped
+//! with the source's parser config and keep the event's tag in their metadata.
+
+use std::io::{Cursor, Read};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use flate2::read::MultiGzDecoder;
+use rmpv::Value;
+use serde_json::json;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tokio::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::streaming::listeners::{self, LISTENER_QUEUE};
+use crate::streaming::sources::{StreamingSourceConfig, StreamingSourceManager};
+use crate::streaming::{StreamingHub, StreamingLogEntry};
+
+/// Port Fluent Bit and Fluentd forward to unless told otherwise
+const DEFAULT_PORT: u16 = 24224;
+/// Most bytes a connection buffers while waiting for the rest of a message
+const MAX_MESSAGE_LEN: usize = 16 * 1024 * 1024;
+/// Most bytes a compressed PackedForward message may inflate to
+const MAX_DECOMPRESSED_LEN: usize = 64 * 1024 * 1024;
+/// Bytes read from a connection at a time
+const READ_CHUNK: usize = 64 * 1024;
+/// msgpack extension type of Fluent's nanosecond `EventTime`
+const EVENT_TIME_EXT: i8 = 0;
+
+/// Where a Fluent Forward source listens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForwardConfig {
+    pub bind_address: String,
+    pub port: u16,
+}
+
+impl ForwardConfig {
+    /// Read a Fluent Forward source's settings from its JSON config
+    pub fn from_json(config: &serde_json::Value) -> Result<Self, String> {
+        let port = match config.get("port") {
+            None | Some(serde_json::Value::Null) => DEFAULT_PORT,
+            Some(port) => port
+                .as_u64()
+                .and_then(|port| u16::try_from(port).ok())
+                .filter(|port| *port != 0)
+                .ok_or_else(|| format!("Invalid port {} for Fluent Forward source", port))?,
+        };
+        let bind_address = config
+            .get("bind_address")
+            .and_then(serde_json::Value::as_str)
+            .unwrap_or("0.0.0.0")
+            .to_string();
+
+        Ok(Self { bind_address, port })
+    }
+}
+
+/// One record and the time Fluent stamped it with
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForwardEvent {
+    pub time: Option<DateTime<Utc>>,
+    pub record: Value,
+}
+
+/// A decoded Forward protocol message in any of its modes
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForwardMessage {
+    pub tag: String,
+    pub events: Vec<ForwardEvent>,
+    /// The chunk ID to acknowledge, when the sender asked for an ack
+    pub chunk: Option<String>,
+}
+
+/// Decode `[tag, time, record, option?]`, `[tag, [[time, record], ...], option?]` or
+/// `[tag, packed entries, option?]`
+pub fn decode_message(value: Value) -> Result<ForwardMessage, String> {
+    let Value::Array(items) = value else {
+        return Err("Forward messages must be msgpack arrays".to_string());
+    };
+    let mut items = items.into_iter();
+    let tag = match items.next() {
+        Some(Value::String(tag)) => tag.into_str().ok_or("Tag is not valid UTF-8")?,
+        _ => return Err("Forward messages must start with a string tag".to_string()),
+    };
+
+    let (events, option) = match items.next() {
+        Some(Value::Array(entries)) => {
+            let events = entries.into_iter().map(decode_entry).collect::<Result<_, _>>()?;
+            (events, items.next())
+        }
+        Some(Value::Binary(packed)) => {
+            let option = items.next();
+            (decode_packed(&packed, option.as_ref())?, option)
+        }
+        Some(Value::String(packed)) => {
+            let option = items.next();
+            (decode_packed(packed.as_bytes(), option.as_ref())?, option)
+        }
+        Some(time) => {
+            let record = items.next().ok_or("Message mode needs a record after the time")?;
+            (vec![ForwardEvent { time: decode_time(&time)?, record }], items.next())
+        }
+        None => return Err("Forward messages need entries after the tag".to_string()),
+    };
+
+    let chunk = option
+        .as_ref()
+        .and_then(|option| option_value(option, "chunk"))
+        .and_then(Value::as_str)
+        .map(str::to_string);
+    Ok(ForwardMessage { tag, events, chunk })
+}
+
+fn option_value<'a>(option: &'a Value, key: &str) -> Option<&'a Value> {
+    option
+        .as_map()?
+        .iter()
+        .find(|(name, _)| name.as_str() == Some(key))
+        .map(|(_, value)| value)
+}
+
+fn decode_entry(entry: Value) -> Result<ForwardEvent, String> {
+    let Value::Array(parts) = entry else {
+        return Err("Forward entries must be [time, record] arrays".to_string());
+    };
+    let mut parts = parts.into_iter();
+    match (parts.next(), parts.next()) {
+        (Some(time), Some(record)) => Ok(ForwardEvent { time: decode_time(&time)?, record }),
+        _ => Err("Forward entries must be [time, record] arrays".to_string()),
+    }
+}
+
+/// Entries concatenated as msgpack, gzipped when the option says `compressed: "gzip"`
+fn decode_packed(packed: &[u8], option: Option<&Value>) -> Result<Vec<ForwardEvent>, String> {
+    let compression = option.and_then(|option| option_value(option, "compressed")).and_then(Value::as_str);
+    let inflated;
+    let packed = match compression {
+        None | Some("text") => packed,
+        Some("gzip") => {
+            let mut buf = Vec::new();
+            MultiGzDecoder::new(packed)
+                .take(MAX_DECOMPRESSED_LEN as u64 + 1)
+                .read_to_end(&mut buf)
+                .map_err(|e| format!("PackedForward entries are not valid gzip: {}", e))?;
+            if buf.len() > MAX_DECOMPRESSED_LEN {
+                return Err(format!("PackedForward entries inflate past {} bytes", MAX_DECOMPRESSED_LEN));
+            }
+            inflated = buf;
+            &inflated[..]
+        }
+        Some(other) => return Err(format!("Unsupported compression '{}'", other)),
+    };
+
+    let mut cursor = Cursor::new(packed);
+    let mut events = Vec::new();
+    while (cursor.position() as usize) < packed.len() {
+        let entry = rmpv::decode::read_value(&mut cursor).map_err(|e| format!("Invalid packed entry: {}", e))?;
+        events.push(decode_entry(entry)?);
+    }
+    Ok(events)
+}
+
+/// Seconds as an integer or float, or an `EventTime` extension with nanoseconds
+fn decode_time(time: &Value) -> Result<Option<DateTime<Utc>>, String> {
+    match time {
+        Value::Nil => Ok(None),
+        Value::Integer(seconds) => Ok(seconds
+            .as_i64()
+            .and_then(|seconds| DateTime::from_timestamp(seconds, 0))),
+        Value::F32(_) | Value::F64(_) => {
+            let seconds = time.as_f64().unwrap_or_default();
+            Ok(DateTime::from_timestamp(seconds.trunc() as i64, (seconds.fract() * 1e9) as u32))
+        }
+        Value::Ext(EVENT_TIME_EXT, data) if data.len() == 8 => {
+            let seconds = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+            let nanos = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+            Ok(DateTime::from_timestamp(i64::from(seconds), nanos))
+        }
+        other => Err(format!("Invalid event time {}", other)),
+    }
+}
+
+/// A msgpack value as JSON; binary is read as (lossy) UTF-8 text since Fluent sends log lines that way
+pub fn to_json(value: &Value) -> serde_json::Value {
+    match value {
+        Value::Nil | Value::Ext(..) => serde_json::Value::Null,
+        Value::Boolean(flag) => json!(flag),
+        Value::Integer(number) => number
+            .as_i64()
+            .map(|number| json!(number))
+            .or_else(|| number.as_u64().map(|number| json!(number)))
+            .unwrap_or(serde_json::Value::Null),
+        Value::F32(number) => json!(number),
+        Value::F64(number) => json!(number),
+        Value::String(text) => json!(String::from_utf8_lossy(text.as_bytes())),
+        Value::Binary(bytes) => json!(String::from_utf8_lossy(bytes)),
+        Value::Array(items) => serde_json::Value::Array(items.iter().map(to_json).collect()),
+        Value::Map(pairs) => serde_json::Value::Object(
+            pairs
+                .iter()
+                .map(|(key, value)| {
+                    let key = match key {
+                        Value::String(key) => String::from_utf8_lossy(key.as_bytes()).into_owned(),
+                        other => other.to_string(),
+                    };
+                    (key, to_json(value))
+                })
+                .collect(),
+        ),
+    }
+}
+
+/// Build an entry from an event's record with the source's parser config, preferring the event time
+pub fn event_entry(
+    event: &ForwardEvent,
+    tag: &str,
+    config: &StreamingSourceConfig,
+    source_id: &str,
+) -> StreamingLogEntry {
+    let record = to_json(&event.record);
+    let mut entry = StreamingSourceManager::parse_json_value(&record, &record.to_string(), config, source_id);
+    if let Some(time) = event.time {
+        entry.timestamp = Some(time.to_rfc3339());
+    }
+    entry.metadata.insert("tag".to_string(), json!(tag));
+    entry
+}
+
+/// Hands decoded events from connections to the source's batching loop
+#[derive(Clone)]
+struct EventSink {
+    config: Arc<StreamingSourceConfig>,
+    source_id: String,
+    entries: mpsc::Sender<StreamingLogEntry>,
+}
+
+/// Run a Fluent Forward source's listener until it is cancelled or its socket fails
+pub async fn run(
+    streaming_hub: &Arc<StreamingHub>,
+    config: &StreamingSourceConfig,
+    forward: &ForwardConfig,
+    source_id: &str,
+    cancel_rx: &mut mpsc::UnboundedReceiver<()>,
+) -> anyhow::Result<()> {
+    let (entries, entry_rx) = mpsc::channel(LISTENER_QUEUE);
+    let sink = EventSink { config: Arc::new(config.clone()), source_id: source_id.to_string(), entries };
+
+    let listener = TcpListener::bind((forward.bind_address.as_str(), forward.port)).await?;
+    info!("Fluent Forward listener started on {}", listener.local_addr()?);
+    let mut listeners = JoinSet::new();
+    listeners.spawn(accept_connections(listener, sink));
+
+    listeners::forward_from_listeners(streaming_hub, config, source_id, entry_rx, listeners, cancel_rx).await
+}
+
+async fn accept_connections(listener: TcpListener, sink: EventSink) -> anyhow::Result<()> {
+    let mut connections = JoinSet::new();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (socket, peer) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        warn!("Fluent Forward accept error: {}", e);
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
+                debug!("Fluent Forward connection from {}", peer);
+
+                let sink = sink.clone();
+                connections.spawn(async move {
+                    if let Err(e) = read_messages(socket, peer, &sink).await {
+                        warn!("Fluent Forward connection from {} closed: {}", peer, e);
+                    }
+                });
+            }
+
+            // Reap finished connections
+            Some(_) = connections.join_next(), if !connections.is_empty() => {}
+        }
+    }
+}
+
+/// Decode messages from a connection as they complete, acknowledging chunks once queued
+async fn read_messages<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    peer: SocketAddr,
+    sink: &EventSink,
+) -> anyhow::Result<()> {
+    let mut buffer = Vec::new();
+    loop {
+        let mut consumed = 0;
+        loop {
+            let mut cursor = Cursor::new(&buffer[consumed..]);
+            let value = match rmpv::decode::read_value(&mut cursor) {
+                Ok(value) => value,
+                Err(e) if is_incomplete(&e) => break,
+                Err(e) => anyhow::bail!("invalid msgpack: {}", e),
+            };
+            consumed += cursor.position() as usize;
+
+            let message = match decode_message(value) {
+                Ok(message) => message,
+                Err(e) => {
+                    warn!("Skipping Fluent Forward message from {}: {}", peer, e);
+                    continue;
+                }
+            };
+            for event in &message.events {
+                let entry = event_entry(event, &message.tag, &sink.config, &sink.source_id);
+                if sink.entries.send(entry).await.is_err() {
+                    return Ok(());
+                }
+            }
+            if let Some(chunk) = message.chunk {
+                let mut ack = Vec::new();
+                rmpv::encode::write_value(&mut ack, &Value::Map(vec![(Value::from("ack"), Value::from(chunk))]))?;
+                stream.write_all(&ack).await?;
+            }
+        }
+        buffer.drain(..consumed);
+
+        if buffer.len() > MAX_MESSAGE_LEN {
+            anyhow::bail!("message exceeds {} bytes", MAX_MESSAGE_LEN);
+        }
+        buffer.reserve(READ_CHUNK);
+        if stream.read_buf(&mut buffer).await? == 0 {
+            return Ok(());
+        }
+    }
+}
+
+/// Whether decoding stopped because the rest of the message hasn't arrived yet
+fn is_incomplete(error: &rmpv::decode::Error) -> bool {
+    match error {
+        rmpv::decode::Error::InvalidMarkerRead(e) | rmpv::decode::Error::InvalidDataRead(e) => {
+            e.kind() == std::io::ErrorKind::UnexpectedEof
+        }
+        rmpv::decode::Error::DepthLimitExceeded => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::streaming::sources::{ParserConfig, StreamingSourceType};
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+    use uuid::Uuid;
+
+    fn encode(value: &Value) -> Vec<u8> {
+        let mut buf = Vec::new();
+        rmpv::encode::write_value(&mut buf, value).unwrap();
+        buf
+    }
+
+    fn record(pairs: &[(&str, &str)]) -> Value {
+        Value::Map(pairs.iter().map(|(key, value)| (Value::from(*key), Value::from(*value))).collect())
+    }
+
+    fn event_time(seconds: u32, nanos: u32) -> Value {
+        let mut data = seconds.to_be_bytes().to_vec();
+        data.extend(nanos.to_be_bytes());
+        Value::Ext(EVENT_TIME_EXT, data)
+    }
+
+    fn source_config() -> StreamingSourceConfig {
+        StreamingSourceConfig {
+            source_type: StreamingSourceType::FluentForward(ForwardConfig::from_json(&json!({})).unwrap()),
+            project_id: Uuid::new_v4(),
+            name: "fluent-bit".to_string(),
+            parser_config: ParserConfig {
+                level_field: Some("lvl".to_string()),
+                message_field: Some("log".to_string()),
+                metadata_fields: vec!["kubernetes".to_string()],
+                ..ParserConfig::default()
+            },
+            buffer_size: 10,
+            batch_timeout: Duration::from_secs(1),
+            restart_on_error: false,
+            max_restarts: None,
+        }
+    }
+
+    #[test]
+    fn test_decode_modes() {
+        let message = decode_message(Value::Array(vec![
+            Value::from("app.web"),
+            Value::from(1_700_000_000),
+            record(&[("log", "started")]),
+        ]))
+        .unwrap();
+        assert_eq!(message.tag, "app.web");
+        assert_eq!(message.events.len(), 1);
+        assert_eq!(message.events[0].time, DateTime::from_timestamp(1_700_000_000, 0));
+        assert_eq!(message.chunk, None);
+
+        let entries = Value::Array(vec![
+            Value::Array(vec![event_time(1_700_000_000, 250_000_000), record(&[("log", "one")])]),
+            Value::Array(vec![event_time(1_700_000_001, 0), record(&[("log", "two")])]),
+        ]);
+        let forward = decode_message(Value::Array(vec![
+            Value::from("app.web"),
+            entries.clone(),
+            Value::Map(vec![(Value::from("chunk"), Value::from("p8n9gmxTQVC8/nh2wlKKeQ=="))]),
+        ]))
+        .unwrap();
+        assert_eq!(forward.events.len(), 2);
+        assert_eq!(forward.events[0].time, DateTime::from_timestamp(1_700_000_000, 250_000_000));
+        assert_eq!(forward.chunk.as_deref(), Some("p8n9gmxTQVC8/nh2wlKKeQ=="));
+
+        let packed: Vec<u8> = match &entries {
+            Value::Array(entries) => entries.iter().flat_map(encode).collect(),
+            _ => unreachable!(),
+        };
+        let packed_forward =
+            decode_message(Value::Array(vec![Value::from("app.web"), Value::Binary(packed.clone())])).unwrap();
+        assert_eq!(packed_forward.events, forward.events);
+
+        let mut gzip = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(&packed).unwrap();
+        let compressed = decode_message(Value::Array(vec![
+            Value::from("app.web"),
+            Value::Binary(gzip.finish().unwrap()),
+            Value::Map(vec![(Value::from("compressed"), Value::from("gzip"))]),
+        ]))
+        .unwrap();
+        assert_eq!(compressed.events, forward.events);
+
+        assert!(decode_message(Value::from("not an array")).is_err());
+        assert!(decode_message(Value::Array(vec![Value::from("tag")])).is_err());
+        assert!(decode_message(Value::Array(vec![Value::from("tag"), Value::from("x"), Value::Nil])).is_err());
+    }
+
+    #[test]
+    fn test_event_entry_uses_parser_config() {
+        let config = source_config();
+        let event = ForwardEvent {
+            time: DateTime::from_timestamp(1_700_000_000, 0),
+            record: Value::Map(vec![
+                (Value::from("log"), Value::Binary(b"GET /health 200".to_vec())),
+                (Value::from("lvl"), Value::from("warn")),
+                (Value::from("kubernetes"), record(&[("pod_name", "web-1")])),
+                (Value::from("stream"), Value::from("stdout")),
+            ]),
+        };
+
+        let entry = event_entry(&event, "kube.var.log", &config, "forward-source");
+        assert_eq!(entry.message, "GET /health 200");
+        assert_eq!(entry.level.as_deref(), Some("warn"));
+        assert_eq!(entry.timestamp.as_deref(), Some("2023-11-14T22:13:20+00:00"));
+        assert_eq!(entry.metadata["tag"], "kube.var.log");
+        assert_eq!(entry.metadata["kubernetes"]["pod_name"], "web-1");
+        assert!(!entry.metadata.contains_key("stream"));
+    }
+
+    #[tokio::test]
+    async fn test_connection_acks_chunks() {
+        let (entries, mut entry_rx) = mpsc::channel(8);
+        let sink = EventSink { config: Arc::new(source_config()), source_id: "forward".to_string(), entries };
+        let (mut client, server) = tokio::io::duplex(64 * 1024);
+        let peer: SocketAddr = "192.0.2.1:40000".parse().unwrap();
+        let reader = tokio::spawn(async move { read_messages(server, peer, &sink).await });
+
+        let message = encode(&Value::Array(vec![
+            Value::from("app"),
+            Value::Array(vec![Value::Array(vec![Value::from(1_700_000_000), record(&[("log", "hello")])])]),
+            Value::Map(vec![(Value::from("chunk"), Value::from("chunk-1"))]),
+        ]));
+        // Split the message so the connection has to wait for the rest of it
+        let (head, tail) = message.split_at(message.len() / 2);
+        client.write_all(head).await.unwrap();
+        tokio::task::yield_now().await;
+        client.write_all(tail).await.unwrap();
+
+        let mut ack = vec![0u8; 64];
+        let read = client.read(&mut ack).await.unwrap();
+        let ack = rmpv::decode::read_value(&mut &ack[..read]).unwrap();
+        assert_eq!(ack, Value::Map(vec![(Value::from("ack"), Value::from("chunk-1"))]));
+        assert_eq!(entry_rx.recv().await.unwrap().message, "hello");
+
+        // Messages of the wrong shape are skipped without closing the connection
+        client.write_all(&encode(&Value::from("not a message"))).await.unwrap();
+        client
+            .write_all(&encode(&Value::Array(vec![Value::from("app"), Value::from(1), record(&[("log", "after")])])))
+            .await
+            .unwrap();
+        assert_eq!(entry_rx.recv().await.unwrap().message, "after");
+
+        drop(client);
+        assert!(reader.await.unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_config_from_json() {
+        let config = ForwardConfig::from_json(&json!({})).unwrap();
+        assert_eq!(config.port, DEFAULT_PORT);
+        assert_eq!(config.bind_address, "0.0.0.0");
+        assert_eq!(ForwardConfig::from_json(&json!({"port": 24225})).unwrap().port, 24225);
+        assert!(ForwardConfig::from_json(&json!({"port": 0})).is_err());
+        assert!(ForwardConfig::from_json(&json!({"port": "24224"})).is_err());
+    }
+}
@@ -0,0 +1,329 @@
+//! Ingest URLs for HTTP endpoint streaming sources
+//!
+//! Each HTTP endpoint source is mounted at `/api/ingest/{path}` and accepts newline-delimited
+//! text, a JSON array or object, or NDJSON, authenticated with the source's own token. Parsed
+//! entries wait in a small bounded queue that the source's task drains into the
+//! [`StreamingHub`]; while the hub's subscribers lag behind the task holds back, so the queue
+//! fills and the ingest handler answers with a retryable 429 instead of dropping entries.
+
+use std::sync::Arc;
+
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::time::{Duration, Instant};
+use tracing::{debug, info};
+use uuid::Uuid;
+
+use crate::middleware::auth::hash_secret;
+use crate::streaming::sources::{StreamingSourceConfig, StreamingSourceManager, StreamingSourceType};
+use crate::streaming::{StreamingHub, StreamingLogEntry};
+
+/// Ingest requests a source queues before it answers with backpressure
+const INGEST_QUEUE_REQUESTS: usize = 16;
+/// Most entries a single ingest request may carry
+pub const MAX_INGEST_ENTRIES: usize = 10_000;
+/// How long the source waits for subscribers to catch up before checking again
+const CONGESTION_BACKOFF: Duration = Duration::from_millis(50);
+/// Longest the source holds back for subscribers before forwarding anyway
+const MAX_CONGESTION_WAIT: Duration = Duration::from_secs(5);
+
+/// How an ingest request's body is laid out, from its `Content-Type`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadFormat {
+    /// One log line per line, parsed with the source's log format
+    Text,
+    /// A JSON array of records or lines, or a single record
+    Json,
+    /// One JSON record or line per line
+    Ndjson,
+}
+
+impl PayloadFormat {
+    pub fn from_content_type(content_type: Option<&str>) -> Self {
+        let media_type = content_type
+            .and_then(|content_type| content_type.split(';').next())
+            .map(|media_type| media_type.trim().to_lowercase());
+        match media_type.as_deref() {
+            Some("application/json") => PayloadFormat::Json,
+            Some("application/x-ndjson" | "application/ndjson" | "application/jsonl" | "application/jsonlines") => {
+                PayloadFormat::Ndjson
+            }
+            _ => PayloadFormat::Text,
+        }
+    }
+}
+
+/// Why an ingest request's entries were not queued
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubmitError {
+    /// The source's queue is full; the client should retry shortly
+    Full,
+    /// The source was stopped
+    Closed,
+}
+
+/// A running HTTP endpoint source, as the ingest handler sees it
+#[derive(Clone)]
+pub struct HttpEndpoint {
+    pub source_id: String,
+    pub project_id: Uuid,
+    token_hash: String,
+    config: StreamingSourceConfig,
+    sender: mpsc::Sender<Vec<StreamingLogEntry>>,
+}
+
+impl HttpEndpoint {
+    /// An endpoint and the queue its source drains
+    pub fn new(
+        source_id: String,
+        token_hash: String,
+        config: StreamingSourceConfig,
+    ) -> (Self, mpsc::Receiver<Vec<StreamingLogEntry>>) {
+        let (sender, receiver) = mpsc::channel(INGEST_QUEUE_REQUESTS);
+        let endpoint = Self {
+            source_id,
+            project_id: config.project_id,
+            token_hash,
+            config,
+            sender,
+        };
+        (endpoint, receiver)
+    }
+
+    /// Whether the source receives OTLP export requests rather than plain log payloads
+    pub fn is_otlp(&self) -> bool {
+        matches!(self.config.source_type, StreamingSourceType::Otlp { .. })
+    }
+
+    /// Whether `token` is this source's ingest token
+    pub fn authorize(&self, token: &str) -> bool {
+        hash_secret(token) == self.token_hash
+    }
+
+    /// Parse a request body into entries with the source's parser config
+    pub fn parse(&self, body: &str, format: PayloadFormat) -> Result<Vec<StreamingLogEntry>, String> {
+        match format {
+            PayloadFormat::Text => {
+                check_entry_count(body.lines().filter(|line| !line.trim().is_empty()).count())?;
+                Ok(body
+                    .lines()
+                    .filter_map(|line| StreamingSourceManager::parse_log_line(line, &self.config, &self.source_id))
+                    .collect())
+            }
+            PayloadFormat::Json => {
+                let value: serde_json::Value =
+                    serde_json::from_str(body).map_err(|e| format!("Body is not valid JSON: {}", e))?;
+                match value {
+                    serde_json::Value::Array(records) => {
+                        check_entry_count(records.len())?;
+                        Ok(records.iter().filter_map(|record| self.parse_record(record)).collect())
+                    }
+                    record => Ok(self.parse_record(&record).into_iter().collect()),
+                }
+            }
+            PayloadFormat::Ndjson => {
+                check_entry_count(body.lines().filter(|line| !line.trim().is_empty()).count())?;
+                let mut entries = Vec::new();
+                for (index, line) in body.lines().enumerate() {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let record: serde_json::Value = serde_json::from_str(line)
+                        .map_err(|e| format!("Line {} is not valid JSON: {}", index + 1, e))?;
+                    entries.extend(self.parse_record(&record));
+                }
+                Ok(entries)
+            }
+        }
+    }
+
+    /// A JSON record is read by its fields; a JSON string is a log line
+    fn parse_record(&self, record: &serde_json::Value) -> Option<StreamingLogEntry> {
+        match record {
+            serde_json::Value::Object(_) => Some(StreamingSourceManager::parse_json_value(
+                record,
+                &record.to_string(),
+                &self.config,
+                &self.source_id,
+            )),
+            serde_json::Value::String(line) => {
+                StreamingSourceManager::parse_log_line(line, &self.config, &self.source_id)
+            }
+            serde_json::Value::Null => None,
+            other => StreamingSourceManager::parse_log_line(&other.to_string(), &self.config, &self.source_id),
+        }
+    }
+
+    /// Queue entries for the source without waiting
+    pub fn try_submit(&self, entries: Vec<StreamingLogEntry>) -> Result<(), SubmitError> {
+        self.sender.try_send(entries).map_err(|e| match e {
+            TrySendError::Full(_) => SubmitError::Full,
+            TrySendError::Closed(_) => SubmitError::Closed,
+        })
+    }
+}
+
+/// Reject a request carrying more than [`MAX_INGEST_ENTRIES`] before parsing it
+pub(crate) fn check_entry_count(count: usize) -> Result<(), String> {
+    if count > MAX_INGEST_ENTRIES {
+        return Err(format!("A request may carry at most {} entries, got {}", MAX_INGEST_ENTRIES, count));
+    }
+    Ok(())
+}
+
+/// Feed queued ingest requests into the hub until the source is stopped
+///
+/// Waits while the hub is congested, which lets the queue fill up, and flushes the
+/// source whenever the queue runs dry so entries never linger in the hub's buffer.
+pub async fn forward_entries(
+    streaming_hub: &Arc<StreamingHub>,
+    config: &StreamingSourceConfig,
+    source_id: &str,
+    ingest_rx: &mut mpsc::Receiver<Vec<StreamingLogEntry>>,
+    cancel_rx: &mut mpsc::UnboundedReceiver<()>,
+) -> anyhow::Result<()> {
+    info!("HTTP endpoint source {} accepting entries", source_id);
+
+    loop {
+        tokio::select! {
+            _ = cancel_rx.recv() => {
+                info!("HTTP endpoint source cancelled");
+                break;
+            }
+
+            entries = ingest_rx.recv() => {
+                let Some(entries) = entries else {
+                    break;
+                };
+
+                // Lagging subscribers are skipped ahead by the hub, so don't wait on them forever
+                let waiting_since = Instant::now();
+                while streaming_hub.is_congested() && waiting_since.elapsed() < MAX_CONGESTION_WAIT {
+                    debug!("Streaming hub congested; HTTP endpoint source {} holding back", source_id);
+                    tokio::time::sleep(CONGESTION_BACKOFF).await;
+                }
+
+                streaming_hub.add_logs(config.project_id, source_id, entries).await?;
+                if ingest_rx.is_empty() {
+                    streaming_hub.flush_source(config.project_id, source_id).await?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::streaming::sources::{LogFormat, ParserConfig, StreamingSourceType};
+    use crate::streaming::StreamingMessage;
+
+    fn endpoint(parser_config: ParserConfig) -> (HttpEndpoint, mpsc::Receiver<Vec<StreamingLogEntry>>) {
+        let token_hash = hash_secret("secret-token");
+        let config = StreamingSourceConfig {
+            source_type: StreamingSourceType::HttpEndpoint { path: "api".to_string(), token_hash: token_hash.clone() },
+            project_id: Uuid::new_v4(),
+            name: "api".to_string(),
+            parser_config,
+            buffer_size: 100,
+            batch_timeout: Duration::from_secs(2),
+            restart_on_error: true,
+            max_restarts: None,
+        };
+        HttpEndpoint::new("source-1".to_string(), token_hash, config)
+    }
+
+    #[test]
+    fn test_payload_format_from_content_type() {
+        assert_eq!(PayloadFormat::from_content_type(Some("application/json; charset=utf-8")), PayloadFormat::Json);
+        assert_eq!(PayloadFormat::from_content_type(Some("application/x-ndjson")), PayloadFormat::Ndjson);
+        assert_eq!(PayloadFormat::from_content_type(Some("text/plain")), PayloadFormat::Text);
+        assert_eq!(PayloadFormat::from_content_type(None), PayloadFormat::Text);
+    }
+
+    #[test]
+    fn test_parse_payloads() {
+        let (endpoint, _rx) = endpoint(ParserConfig::default());
+        assert!(endpoint.authorize("secret-token"));
+        assert!(!endpoint.authorize("other-token"));
+
+        let entries = endpoint
+            .parse("2024-01-01T00:00:00Z [ERROR] db down\n\n[INFO] recovered\n", PayloadFormat::Text)
+            .unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].level.as_deref(), Some("ERROR"));
+        assert_eq!(entries[1].source, "source-1");
+
+        let entries = endpoint
+            .parse(r#"[{"level":"warn","msg":"slow query"}, "[ERROR] timeout", null]"#, PayloadFormat::Json)
+            .unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!((entries[0].level.as_deref(), entries[0].message.as_str()), (Some("warn"), "slow query"));
+        assert_eq!(entries[1].level.as_deref(), Some("ERROR"));
+        assert_eq!(endpoint.parse(r#"{"message":"one"}"#, PayloadFormat::Json).unwrap().len(), 1);
+
+        let entries = endpoint
+            .parse("{\"message\":\"a\"}\n\n{\"message\":\"b\"}\n", PayloadFormat::Ndjson)
+            .unwrap();
+        assert_eq!(entries.iter().map(|e| e.message.as_str()).collect::<Vec<_>>(), vec!["a", "b"]);
+
+        assert!(endpoint.parse("{not json", PayloadFormat::Json).is_err());
+        let error = endpoint.parse("{\"message\":\"a\"}\nnope\n", PayloadFormat::Ndjson).unwrap_err();
+        assert!(error.contains("Line 2"), "{}", error);
+        let too_many = "x\n".repeat(MAX_INGEST_ENTRIES + 1);
+        assert!(endpoint.parse(&too_many, PayloadFormat::Text).is_err());
+    }
+
+    #[test]
+    fn test_parser_config_fields() {
+        let (endpoint, _rx) = endpoint(ParserConfig {
+            log_format: LogFormat::Json,
+            level_field: Some("lvl".to_string()),
+            message_field: Some("text".to_string()),
+            ..ParserConfig::default()
+        });
+
+        let entries = endpoint
+            .parse("{\"lvl\":\"ERROR\",\"text\":\"disk full\",\"message\":\"ignored\"}", PayloadFormat::Ndjson)
+            .unwrap();
+        assert_eq!((entries[0].level.as_deref(), entries[0].message.as_str()), (Some("ERROR"), "disk full"));
+        // Text lines are parsed with the source's log format
+        let entries = endpoint.parse("{\"level\":\"INFO\",\"message\":\"started\"}", PayloadFormat::Text).unwrap();
+        assert_eq!(entries[0].message, "started");
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_pushes_back_and_entries_reach_the_hub() {
+        let (endpoint, mut ingest_rx) = endpoint(ParserConfig::default());
+        let entries = endpoint.parse("[ERROR] failed", PayloadFormat::Text).unwrap();
+
+        // Nothing drains the queue yet, so it fills up
+        for _ in 0..INGEST_QUEUE_REQUESTS {
+            endpoint.try_submit(entries.clone()).unwrap();
+        }
+        assert_eq!(endpoint.try_submit(entries.clone()), Err(SubmitError::Full));
+
+        let hub = Arc::new(StreamingHub::new());
+        hub.register_source_with_id(endpoint.project_id, endpoint.source_id.clone(), "api".to_string()).await;
+        let mut batches = hub.sender.subscribe();
+        let (cancel_tx, mut cancel_rx) = mpsc::unbounded_channel();
+        let config = endpoint.config.clone();
+        let forward_hub = hub.clone();
+        let task = tokio::spawn(async move {
+            forward_entries(&forward_hub, &config, "source-1", &mut ingest_rx, &mut cancel_rx).await
+        });
+
+        let mut received = 0;
+        while received < INGEST_QUEUE_REQUESTS {
+            if let StreamingMessage::LogBatch(batch) = batches.recv().await.unwrap() {
+                assert_eq!(batch.source, "api");
+                received += batch.entries.len();
+            }
+        }
+        assert!(endpoint.try_submit(entries).is_ok());
+
+        cancel_tx.send(()).unwrap();
+        task.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,431 @@
+//! Kubernetes sources
+//!
+//! A Kubernetes source follows pod logs through the API server, the same stream
+//! `kubectl logs -f` reads, without running kubectl. Pods are selected by namespace and label
+//! selector, every running container in them is followed unless the source names some, and
+//! pods created later are picked up on the next poll. Entries are tagged with the namespace,
+//! pod, container and node.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::SecondsFormat;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::time::Duration;
+
+use crate::streaming::containers::{poll_interval, string_list, Container, ContainerLog, ContainerRuntime};
+
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(10);
+const SERVICE_ACCOUNT_TOKEN: &str = "/var/run/secrets/kubernetes.io/serviceaccount/token";
+const SERVICE_ACCOUNT_CA: &str = "/var/run/secrets/kubernetes.io/serviceaccount/ca.crt";
+
+/// Which pods a Kubernetes source follows and how it reaches the API server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KubernetesConfig {
+    pub api_server: String,
+    /// `None` follows pods in every namespace
+    pub namespace: Option<String>,
+    pub label_selector: Option<String>,
+    /// Container names to follow; empty follows every container in a selected pod
+    pub containers: Vec<String>,
+    /// Bearer token file, re-read for each request so rotated tokens are picked up
+    pub token_path: Option<PathBuf>,
+    /// PEM CA certificate the API server's certificate is checked against
+    pub ca_cert_path: Option<PathBuf>,
+    pub poll_interval: Duration,
+}
+
+impl KubernetesConfig {
+    /// Read a Kubernetes source's settings from its JSON config
+    ///
+    /// Inside a cluster the API server defaults to the cluster's own, and the token and CA to
+    /// the pod's service account. The service account is only used with the cluster's own API
+    /// server, so its token is never sent anywhere else.
+    pub fn from_json(config: &serde_json::Value) -> Result<Self, String> {
+        Self::from_json_in(config, InCluster::detect())
+    }
+
+    fn from_json_in(config: &serde_json::Value, in_cluster: Option<InCluster>) -> Result<Self, String> {
+        let string = |key: &str| config.get(key).and_then(serde_json::Value::as_str).map(str::to_string);
+        let api_server = string("api_server")
+            .or_else(|| in_cluster.as_ref().map(|cluster| cluster.api_server.clone()))
+            .ok_or("Missing 'api_server' for Kubernetes source outside a cluster")?
+            .trim_end_matches('/')
+            .to_string();
+        let service_account = in_cluster.filter(|cluster| cluster.api_server == api_server);
+
+        Ok(Self {
+            api_server,
+            namespace: string("namespace"),
+            label_selector: string("label_selector"),
+            containers: string_list(config, "containers")?,
+            token_path: string("token_path")
+                .map(PathBuf::from)
+                .or_else(|| service_account.as_ref().and_then(|cluster| cluster.token_path.clone())),
+            ca_cert_path: string("ca_cert_path")
+                .map(PathBuf::from)
+                .or_else(|| service_account.as_ref().and_then(|cluster| cluster.ca_cert_path.clone())),
+            poll_interval: poll_interval(config)?.unwrap_or(DEFAULT_POLL_INTERVAL),
+        })
+    }
+}
+
+/// The cluster Synapse runs in, as Kubernetes describes it to every pod
+#[derive(Debug, Clone)]
+struct InCluster {
+    api_server: String,
+    token_path: Option<PathBuf>,
+    ca_cert_path: Option<PathBuf>,
+}
+
+impl InCluster {
+    fn detect() -> Option<Self> {
+        let host = std::env::var("KUBERNETES_SERVICE_HOST").ok()?;
+        let port = std::env::var("KUBERNETES_SERVICE_PORT").unwrap_or_else(|_| "443".to_string());
+        let api_server = if host.contains(':') {
+            format!("https://[{}]:{}", host, port)
+        } else {
+            format!("https://{}:{}", host, port)
+        };
+        let existing = |path: &str| Path::new(path).exists().then(|| PathBuf::from(path));
+        Some(Self {
+            api_server,
+            token_path: existing(SERVICE_ACCOUNT_TOKEN),
+            ca_cert_path: existing(SERVICE_ACCOUNT_CA),
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PodList {
+    items: Vec<Pod>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Pod {
+    metadata: PodMetadata,
+    #[serde(default)]
+    spec: PodSpec,
+    #[serde(default)]
+    status: PodStatus,
+}
+
+#[derive(Debug, Deserialize)]
+struct PodMetadata {
+    name: String,
+    namespace: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PodSpec {
+    node_name: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PodStatus {
+    #[serde(default)]
+    container_statuses: Vec<ContainerStatus>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ContainerStatus {
+    name: String,
+    #[serde(default)]
+    state: ContainerState,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ContainerState {
+    running: Option<serde_json::Value>,
+}
+
+/// A container in a pod, as the log endpoint addresses it
+pub struct PodContainer {
+    namespace: String,
+    pod: String,
+    container: String,
+}
+
+/// The Kubernetes API server
+pub struct KubernetesRuntime {
+    config: KubernetesConfig,
+    client: reqwest::Client,
+}
+
+impl KubernetesRuntime {
+    /// Build a client trusting the configured CA
+    pub fn new(config: KubernetesConfig) -> anyhow::Result<Self> {
+        let mut builder = reqwest::Client::builder().connect_timeout(std::time::Duration::from_secs(10));
+        if let Some(ca_cert_path) = &config.ca_cert_path {
+            let pem = std::fs::read(ca_cert_path)
+                .with_context(|| format!("Cannot read CA certificate {}", ca_cert_path.display()))?;
+            builder = builder.add_root_certificate(reqwest::Certificate::from_pem(&pem)?);
+        }
+        Ok(Self { config, client: builder.build()? })
+    }
+
+    /// Send an authenticated GET, failing on non-2xx responses
+    async fn get(&self, path: &str, query: &[(&str, &str)]) -> anyhow::Result<reqwest::Response> {
+        let mut request = self.client.get(format!("{}{}", self.config.api_server, path)).query(query);
+        if let Some(token_path) = &self.config.token_path {
+            let token = tokio::fs::read_to_string(token_path)
+                .await
+                .with_context(|| format!("Cannot read token {}", token_path.display()))?;
+            request = request.bearer_auth(token.trim());
+        }
+
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            anyhow::bail!("Kubernetes API returned {} for {}: {}", status, path, body.trim());
+        }
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl ContainerRuntime for KubernetesRuntime {
+    type Target = PodContainer;
+
+    async fn containers(&self) -> anyhow::Result<Vec<Container<PodContainer>>> {
+        let path = match &self.config.namespace {
+            Some(namespace) => format!("/api/v1/namespaces/{}/pods", namespace),
+            None => "/api/v1/pods".to_string(),
+        };
+        let mut query = vec![("fieldSelector", "status.phase=Running")];
+        if let Some(label_selector) = &self.config.label_selector {
+            query.push(("labelSelector", label_selector));
+        }
+        let pods: PodList = self.get(&path, &query).await?.json().await?;
+
+        let mut containers = Vec::new();
+        for pod in pods.items {
+            let running = pod.status.container_statuses.into_iter().filter(|status| {
+                status.state.running.is_some()
+                    && (self.config.containers.is_empty() || self.config.containers.contains(&status.name))
+            });
+            for status in running {
+                let PodMetadata { name, namespace } = &pod.metadata;
+                containers.push(Container {
+                    key: format!("{}/{}/{}", namespace, name, status.name),
+                    metadata: HashMap::from([
+                        ("namespace".to_string(), json!(namespace)),
+                        ("pod".to_string(), json!(name)),
+                        ("container".to_string(), json!(status.name)),
+                        ("node".to_string(), json!(pod.spec.node_name)),
+                    ]),
+                    target: PodContainer { namespace: namespace.clone(), pod: name.clone(), container: status.name },
+                });
+            }
+        }
+        Ok(containers)
+    }
+
+    async fn follow(&self, target: &PodContainer, log: &mut ContainerLog) -> anyhow::Result<()> {
+        let path = format!("/api/v1/namespaces/{}/pods/{}/log", target.namespace, target.pod);
+        // sinceTime has whole seconds; lines up to `since` are skipped as they arrive
+        let since = log.since.to_rfc3339_opts(SecondsFormat::Secs, true);
+        let query = [
+            ("container", target.container.as_str()),
+            ("follow", "true"),
+            ("timestamps", "true"),
+            ("sinceTime", &since),
+        ];
+        let mut response = self.get(&path, &query).await?;
+
+        while let Some(chunk) = response.chunk().await? {
+            if !log.push_bytes(&chunk).await {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::streaming::sources::{ParserConfig, StreamingSourceConfig, StreamingSourceType};
+    use crate::streaming::{LogFilter, StreamingHub};
+    use axum::extract::{Path as UrlPath, Query, State};
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::get;
+    use axum::{Json, Router};
+    use std::future::IntoFuture;
+    use std::sync::{Arc, Mutex};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+    use uuid::Uuid;
+
+    type Pods = Arc<Mutex<Vec<serde_json::Value>>>;
+
+    fn pod(name: &str, containers: &[(&str, bool)]) -> serde_json::Value {
+        let statuses: Vec<_> = containers
+            .iter()
+            .map(|(name, running)| {
+                let state = if *running { json!({"running": {}}) } else { json!({"waiting": {}}) };
+                json!({"name": name, "state": state})
+            })
+            .collect();
+        json!({
+            "metadata": {"name": name, "namespace": "shop"},
+            "spec": {"nodeName": "node-a"},
+            "status": {"phase": "Running", "containerStatuses": statuses}
+        })
+    }
+
+    /// Just enough of the API server to list pods and stream container logs
+    fn mock_api_server(pods: Pods) -> Router {
+        fn authorized(headers: &HeaderMap) -> Result<(), StatusCode> {
+            match headers.get("authorization").and_then(|value| value.to_str().ok()) {
+                Some("Bearer test-token") => Ok(()),
+                _ => Err(StatusCode::UNAUTHORIZED),
+            }
+        }
+
+        Router::new()
+            .route(
+                "/api/v1/namespaces/:namespace/pods",
+                get(|State(pods): State<Pods>, headers: HeaderMap, Query(query): Query<HashMap<String, String>>| async move {
+                    authorized(&headers)?;
+                    assert_eq!(query["labelSelector"], "app=shop");
+                    assert_eq!(query["fieldSelector"], "status.phase=Running");
+                    Ok::<_, StatusCode>(Json(json!({"items": *pods.lock().unwrap()})))
+                }),
+            )
+            .route(
+                "/api/v1/namespaces/:namespace/pods/:pod/log",
+                get(|UrlPath((_, pod)): UrlPath<(String, String)>, headers: HeaderMap, Query(query): Query<HashMap<String, String>>| async move {
+                    authorized(&headers)?;
+                    assert_eq!(query["follow"], "true");
+                    assert!(query.contains_key("sinceTime"));
+                    let container = &query["container"];
+                    // Fixed times after the source starts, so resumed streams repeat nothing
+                    Ok::<_, StatusCode>(format!(
+                        "2099-01-01T00:00:00.000000001Z hello from {pod}/{container}\n2099-01-01T00:00:01Z WARN slow {pod}/{container}\n"
+                    ))
+                }),
+            )
+            .with_state(pods)
+    }
+
+    #[tokio::test]
+    async fn test_follows_new_pods_through_mock_api_server() {
+        let pods: Pods = Arc::new(Mutex::new(vec![pod("web-1", &[("app", true), ("init", false)])]));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api_server = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(axum::serve(listener, mock_api_server(Arc::clone(&pods))).into_future());
+
+        let dir = tempfile::tempdir().unwrap();
+        let token_path = dir.path().join("token");
+        std::fs::write(&token_path, "test-token\n").unwrap();
+        let kubernetes_config = KubernetesConfig::from_json(&json!({
+            "api_server": api_server,
+            "namespace": "shop",
+            "label_selector": "app=shop",
+            "token_path": token_path,
+            "ca_cert_path": null,
+        }))
+        .unwrap();
+        let kubernetes_config = KubernetesConfig { poll_interval: Duration::from_millis(50), ..kubernetes_config };
+
+        let streaming_hub = Arc::new(StreamingHub::new());
+        let project_id = Uuid::new_v4();
+        let config = StreamingSourceConfig {
+            source_type: StreamingSourceType::Kubernetes(kubernetes_config.clone()),
+            project_id,
+            name: "shop-pods".to_string(),
+            parser_config: ParserConfig::default(),
+            buffer_size: 1,
+            batch_timeout: Duration::from_millis(50),
+            restart_on_error: false,
+            max_restarts: None,
+        };
+        let (_cancel_tx, mut cancel_rx) = mpsc::unbounded_channel();
+        let hub = Arc::clone(&streaming_hub);
+        tokio::spawn(async move {
+            let runtime = KubernetesRuntime::new(kubernetes_config.clone()).unwrap();
+            crate::streaming::containers::run(&hub, &config, "k8s", runtime, kubernetes_config.poll_interval, &mut cancel_rx)
+                .await
+        });
+
+        let recent = || streaming_hub.recent_logs(project_id, &LogFilter::default(), 100);
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        while recent().len() < 2 {
+            assert!(tokio::time::Instant::now() < deadline, "no logs from the first pod");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        pods.lock().unwrap().push(pod("web-2", &[("app", true), ("proxy", true)]));
+        while recent().len() < 6 {
+            assert!(tokio::time::Instant::now() < deadline, "no logs from the new pod");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let logs = recent();
+        assert_eq!(logs.len(), 6);
+        let first = &logs[0];
+        assert_eq!(first.message, "hello from web-1/app");
+        assert_eq!(first.timestamp.as_deref(), Some("2099-01-01T00:00:00.000000001+00:00"));
+        assert_eq!(first.metadata["namespace"], "shop");
+        assert_eq!(first.metadata["pod"], "web-1");
+        assert_eq!(first.metadata["container"], "app");
+        assert_eq!(first.metadata["node"], "node-a");
+        assert_eq!(logs[1].level.as_deref(), Some("WARN"));
+
+        let mut followed: Vec<_> = logs
+            .iter()
+            .map(|entry| format!("{}/{}", entry.metadata["pod"].as_str().unwrap(), entry.metadata["container"].as_str().unwrap()))
+            .collect();
+        followed.sort();
+        followed.dedup();
+        assert_eq!(followed, ["web-1/app", "web-2/app", "web-2/proxy"]);
+    }
+
+    #[test]
+    fn test_config_from_json() {
+        let config = KubernetesConfig::from_json(&json!({
+            "api_server": "https://10.0.0.1:6443/",
+            "containers": ["app"],
+            "token_path": "/etc/synapse/k8s-token",
+            "poll_interval_seconds": 30
+        }))
+        .unwrap();
+        assert_eq!(config.api_server, "https://10.0.0.1:6443");
+        assert_eq!(config.namespace, None);
+        assert_eq!(config.containers, ["app"]);
+        assert_eq!(config.token_path, Some(PathBuf::from("/etc/synapse/k8s-token")));
+        assert_eq!(config.poll_interval, Duration::from_secs(30));
+
+        assert!(KubernetesConfig::from_json(&json!({"api_server": "https://k8s", "containers": "app"})).is_err());
+    }
+
+    #[test]
+    fn test_service_account_only_for_own_cluster() {
+        let cluster = InCluster {
+            api_server: "https://10.96.0.1:443".to_string(),
+            token_path: Some(PathBuf::from(SERVICE_ACCOUNT_TOKEN)),
+            ca_cert_path: Some(PathBuf::from(SERVICE_ACCOUNT_CA)),
+        };
+
+        let config = KubernetesConfig::from_json_in(&json!({}), Some(cluster.clone())).unwrap();
+        assert_eq!(config.api_server, cluster.api_server);
+        assert_eq!(config.token_path, cluster.token_path);
+        assert_eq!(config.ca_cert_path, cluster.ca_cert_path);
+        let config = KubernetesConfig::from_json_in(&json!({"api_server": "https://10.96.0.1:443/"}), Some(cluster.clone()));
+        assert_eq!(config.unwrap().token_path, cluster.token_path);
+
+        // Another API server never gets the pod's token
+        let config = KubernetesConfig::from_json_in(&json!({"api_server": "https://elsewhere"}), Some(cluster)).unwrap();
+        assert_eq!(config.token_path, None);
+        assert_eq!(config.ca_cert_path, None);
+
+        assert!(KubernetesConfig::from_json_in(&json!({}), None).is_err());
+    }
+}
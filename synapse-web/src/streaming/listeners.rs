@@ -0,0 +1,71 @@
+//! Shared plumbing for sources that run their own network listeners
+//!
+//! Syslog and Fluent Forward sources spawn a task per socket, and Docker and Kubernetes sources
+//! a task that follows containers, which parse what they receive and queue entries. [`forward_from_listeners`] batches that queue into the [`StreamingHub`] by the
+//! source's buffer size and batch timeout, and stops the listeners when the source stops.
+
+use std::sync::Arc;
+
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+use tracing::info;
+
+use crate::streaming::sources::StreamingSourceConfig;
+use crate::streaming::{StreamingHub, StreamingLogEntry};
+
+/// Parsed entries listener tasks queue before they wait on the batching loop
+pub(crate) const LISTENER_QUEUE: usize = 1024;
+
+/// Batch entries from a source's listeners into the hub until it is cancelled or a listener fails
+///
+/// Listener tasks should only return when their socket fails; the source then restarts. Dropping
+/// the set when this returns closes every socket and connection the listeners hold.
+pub(crate) async fn forward_from_listeners(
+    streaming_hub: &Arc<StreamingHub>,
+    config: &StreamingSourceConfig,
+    source_id: &str,
+    mut entry_rx: mpsc::Receiver<StreamingLogEntry>,
+    mut listeners: JoinSet<anyhow::Result<()>>,
+    cancel_rx: &mut mpsc::UnboundedReceiver<()>,
+) -> anyhow::Result<()> {
+    let mut buffer = Vec::new();
+    let mut batch_started = Instant::now();
+    let result = loop {
+        tokio::select! {
+            _ = cancel_rx.recv() => {
+                info!("Source {} cancelled", source_id);
+                break Ok(());
+            }
+
+            entry = entry_rx.recv() => {
+                let Some(entry) = entry else { break Ok(()) };
+                if buffer.is_empty() {
+                    batch_started = Instant::now();
+                }
+                buffer.push(entry);
+                if buffer.len() >= config.buffer_size {
+                    streaming_hub.add_logs(config.project_id, source_id, std::mem::take(&mut buffer)).await?;
+                }
+            }
+
+            _ = tokio::time::sleep_until(batch_started + config.batch_timeout), if !buffer.is_empty() => {
+                streaming_hub.add_logs(config.project_id, source_id, std::mem::take(&mut buffer)).await?;
+            }
+
+            Some(joined) = listeners.join_next() => {
+                break match joined {
+                    Ok(result) => result,
+                    Err(e) => Err(e.into()),
+                };
+            }
+        }
+    };
+
+    // Flush remaining buffer
+    if !buffer.is_empty() {
+        streaming_hub.add_logs(config.project_id, source_id, buffer).await?;
+    }
+
+    result
+}
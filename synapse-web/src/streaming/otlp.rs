@@ -0,0 +1,306 @@
+//! OTLP/HTTP logs receiver
+//!
+//! An OTLP source is an HTTP endpoint source mounted at `/api/otlp/{path}/v1/logs`, so an
+//! OpenTelemetry SDK or collector pointed at `/api/otlp/{path}` exports its logs straight to
+//! Synapse. Requests are protobuf or JSON `ExportLogsServiceRequest`s; each log record becomes
+//! an entry whose metadata carries its resource, scope and record attributes (such as
+//! `service.name` and `host.name`) along with its trace and span IDs.
+
+use base64::Engine;
+use chrono::DateTime;
+use opentelemetry_proto::tonic::collector::logs::v1::{ExportLogsServiceRequest, ExportLogsServiceResponse};
+use opentelemetry_proto::tonic::common::v1::{any_value, AnyValue, KeyValue};
+use opentelemetry_proto::tonic::logs::v1::LogRecord;
+use prost::Message;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::streaming::http_endpoint::check_entry_count;
+use crate::streaming::StreamingLogEntry;
+
+/// How an export request's body is encoded, from its `Content-Type`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtlpEncoding {
+    Protobuf,
+    Json,
+}
+
+impl OtlpEncoding {
+    /// The encoding for a `Content-Type`, or `None` if OTLP/HTTP doesn't define it
+    pub fn from_content_type(content_type: Option<&str>) -> Option<Self> {
+        let media_type = content_type
+            .and_then(|content_type| content_type.split(';').next())
+            .map(|media_type| media_type.trim().to_lowercase());
+        match media_type.as_deref() {
+            Some("application/x-protobuf") | None => Some(OtlpEncoding::Protobuf),
+            Some("application/json") => Some(OtlpEncoding::Json),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            OtlpEncoding::Protobuf => "application/x-protobuf",
+            OtlpEncoding::Json => "application/json",
+        }
+    }
+
+    /// Decode an export request
+    pub fn decode(self, body: &[u8]) -> Result<ExportLogsServiceRequest, String> {
+        match self {
+            OtlpEncoding::Protobuf => ExportLogsServiceRequest::decode(body)
+                .map_err(|e| format!("Body is not a protobuf ExportLogsServiceRequest: {}", e)),
+            OtlpEncoding::Json => serde_json::from_slice(body)
+                .map_err(|e| format!("Body is not a JSON ExportLogsServiceRequest: {}", e)),
+        }
+    }
+
+    /// An empty `ExportLogsServiceResponse`, which tells the exporter every record was accepted
+    pub fn success_body(self) -> Vec<u8> {
+        let response = ExportLogsServiceResponse::default();
+        match self {
+            OtlpEncoding::Protobuf => response.encode_to_vec(),
+            OtlpEncoding::Json => b"{}".to_vec(),
+        }
+    }
+}
+
+/// Convert every log record in an export request into a streaming entry
+pub fn into_entries(
+    request: ExportLogsServiceRequest,
+    project_id: Uuid,
+    source_id: &str,
+) -> Result<Vec<StreamingLogEntry>, String> {
+    let record_count = request
+        .resource_logs
+        .iter()
+        .flat_map(|resource_logs| &resource_logs.scope_logs)
+        .map(|scope_logs| scope_logs.log_records.len())
+        .sum();
+    check_entry_count(record_count)?;
+
+    let mut entries = Vec::with_capacity(record_count);
+    for resource_logs in request.resource_logs {
+        let mut resource_metadata = HashMap::new();
+        if let Some(resource) = &resource_logs.resource {
+            insert_attributes(&mut resource_metadata, &resource.attributes);
+        }
+
+        for scope_logs in resource_logs.scope_logs {
+            let mut scope_metadata = resource_metadata.clone();
+            if let Some(scope) = scope_logs.scope.as_ref().filter(|scope| !scope.name.is_empty()) {
+                scope_metadata.insert("scope.name".to_string(), json!(scope.name));
+                if !scope.version.is_empty() {
+                    scope_metadata.insert("scope.version".to_string(), json!(scope.version));
+                }
+            }
+
+            for record in scope_logs.log_records {
+                entries.push(record_entry(record, scope_metadata.clone(), project_id, source_id));
+            }
+        }
+    }
+    Ok(entries)
+}
+
+fn record_entry(
+    record: LogRecord,
+    mut metadata: HashMap<String, Value>,
+    project_id: Uuid,
+    source_id: &str,
+) -> StreamingLogEntry {
+    // Record attributes take precedence over resource and scope attributes of the same name
+    insert_attributes(&mut metadata, &record.attributes);
+    if let Some(trace_id) = hex_id(&record.trace_id) {
+        metadata.insert("trace_id".to_string(), json!(trace_id));
+    }
+    if let Some(span_id) = hex_id(&record.span_id) {
+        metadata.insert("span_id".to_string(), json!(span_id));
+    }
+    if !record.severity_text.is_empty() {
+        metadata.insert("severity_text".to_string(), json!(record.severity_text));
+    }
+
+    let nanos = [record.time_unix_nano, record.observed_time_unix_nano]
+        .into_iter()
+        .find(|nanos| *nanos != 0);
+    let timestamp = nanos
+        .and_then(|nanos| i64::try_from(nanos).ok())
+        .map(|nanos| DateTime::from_timestamp_nanos(nanos).to_rfc3339());
+
+    let level = severity_level(record.severity_number)
+        .map(str::to_string)
+        .or_else(|| (!record.severity_text.is_empty()).then(|| record.severity_text.to_uppercase()));
+
+    let message = match record.body.as_ref().and_then(|body| body.value.as_ref()) {
+        Some(any_value::Value::StringValue(text)) => text.clone(),
+        Some(_) => record.body.as_ref().map(any_value_to_json).unwrap_or(Value::Null).to_string(),
+        None => String::new(),
+    };
+
+    StreamingLogEntry {
+        id: Uuid::new_v4().to_string(),
+        timestamp,
+        level,
+        message,
+        source: source_id.to_string(),
+        project_id,
+        line_number: None,
+        metadata,
+    }
+}
+
+/// The Synapse log level for an OTLP `SeverityNumber`, whose ranges of four map onto the levels
+fn severity_level(severity_number: i32) -> Option<&'static str> {
+    match severity_number {
+        1..=4 => Some("TRACE"),
+        5..=8 => Some("DEBUG"),
+        9..=12 => Some("INFO"),
+        13..=16 => Some("WARN"),
+        17..=20 => Some("ERROR"),
+        21..=24 => Some("FATAL"),
+        _ => None,
+    }
+}
+
+/// Lowercase hex for a trace or span ID, or `None` for an empty or all-zero (invalid) one
+fn hex_id(id: &[u8]) -> Option<String> {
+    id.iter().any(|byte| *byte != 0).then(|| hex::encode(id))
+}
+
+fn insert_attributes(metadata: &mut HashMap<String, Value>, attributes: &[KeyValue]) {
+    for attribute in attributes {
+        let value = attribute.value.as_ref().map(any_value_to_json).unwrap_or(Value::Null);
+        metadata.insert(attribute.key.clone(), value);
+    }
+}
+
+fn any_value_to_json(value: &AnyValue) -> Value {
+    match &value.value {
+        Some(any_value::Value::StringValue(text)) => json!(text),
+        Some(any_value::Value::BoolValue(flag)) => json!(flag),
+        Some(any_value::Value::IntValue(number)) => json!(number),
+        Some(any_value::Value::DoubleValue(number)) => json!(number),
+        Some(any_value::Value::ArrayValue(array)) => Value::Array(array.values.iter().map(any_value_to_json).collect()),
+        Some(any_value::Value::KvlistValue(list)) => Value::Object(
+            list.values
+                .iter()
+                .map(|kv| (kv.key.clone(), kv.value.as_ref().map(any_value_to_json).unwrap_or(Value::Null)))
+                .collect::<Map<_, _>>(),
+        ),
+        Some(any_value::Value::BytesValue(bytes)) => json!(base64::engine::general_purpose::STANDARD.encode(bytes)),
+        None => Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry_proto::tonic::common::v1::InstrumentationScope;
+    use opentelemetry_proto::tonic::logs::v1::{ResourceLogs, ScopeLogs};
+    use opentelemetry_proto::tonic::resource::v1::Resource;
+
+    fn string_attribute(key: &str, value: &str) -> KeyValue {
+        KeyValue {
+            key: key.to_string(),
+            value: Some(AnyValue { value: Some(any_value::Value::StringValue(value.to_string())) }),
+        }
+    }
+
+    fn export_request() -> ExportLogsServiceRequest {
+        ExportLogsServiceRequest {
+            resource_logs: vec![ResourceLogs {
+                resource: Some(Resource {
+                    attributes: vec![
+                        string_attribute("service.name", "checkout"),
+                        string_attribute("host.name", "web-1"),
+                    ],
+                    dropped_attributes_count: 0,
+                }),
+                scope_logs: vec![ScopeLogs {
+                    scope: Some(InstrumentationScope {
+                        name: "checkout.payments".to_string(),
+                        ..Default::default()
+                    }),
+                    log_records: vec![LogRecord {
+                        time_unix_nano: 1_700_000_000_500_000_000,
+                        severity_number: 17,
+                        severity_text: "Error".to_string(),
+                        body: Some(AnyValue { value: Some(any_value::Value::StringValue("card declined".to_string())) }),
+                        attributes: vec![string_attribute("http.route", "/pay")],
+                        trace_id: vec![0x5b, 0x8e, 0xff, 0xf7, 0x98, 0x03, 0x81, 0x03, 0xd2, 0x69, 0xb6, 0x33, 0x81, 0x3f, 0xc6, 0x0c],
+                        span_id: vec![0xee, 0xe1, 0x9b, 0x7e, 0xc3, 0xc1, 0xb1, 0x74],
+                        ..Default::default()
+                    }],
+                    schema_url: String::new(),
+                }],
+                schema_url: String::new(),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_protobuf_records_become_entries() {
+        let body = export_request().encode_to_vec();
+        let encoding = OtlpEncoding::from_content_type(Some("application/x-protobuf")).unwrap();
+        let entries = into_entries(encoding.decode(&body).unwrap(), Uuid::nil(), "otlp-source").unwrap();
+
+        assert_eq!(entries.len(), 1);
+        let entry = &entries[0];
+        assert_eq!(entry.message, "card declined");
+        assert_eq!(entry.level.as_deref(), Some("ERROR"));
+        assert_eq!(entry.timestamp.as_deref(), Some("2023-11-14T22:13:20.500+00:00"));
+        assert_eq!(entry.metadata["service.name"], "checkout");
+        assert_eq!(entry.metadata["host.name"], "web-1");
+        assert_eq!(entry.metadata["scope.name"], "checkout.payments");
+        assert_eq!(entry.metadata["http.route"], "/pay");
+        assert_eq!(entry.metadata["trace_id"], "5b8efff798038103d269b633813fc60c");
+        assert_eq!(entry.metadata["span_id"], "eee19b7ec3c1b174");
+        assert_eq!(entry.metadata["severity_text"], "Error");
+    }
+
+    #[test]
+    fn test_json_records_become_entries() {
+        let body = r#"{
+          "resourceLogs": [{
+            "resource": {"attributes": [{"key": "service.name", "value": {"stringValue": "inventory"}}]},
+            "scopeLogs": [{
+              "logRecords": [
+                {
+                  "observedTimeUnixNano": "1700000000000000000",
+                  "severityText": "warn",
+                  "traceId": "5B8EFFF798038103D269B633813FC60C",
+                  "spanId": "0000000000000000",
+                  "body": {"kvlistValue": {"values": [{"key": "sku", "value": {"intValue": "42"}}]}}
+                },
+                {"severityNumber": 9}
+              ]
+            }]
+          }]
+        }"#;
+        let encoding = OtlpEncoding::from_content_type(Some("application/json; charset=utf-8")).unwrap();
+        let entries = into_entries(encoding.decode(body.as_bytes()).unwrap(), Uuid::nil(), "otlp-source").unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].level.as_deref(), Some("WARN"));
+        assert_eq!(entries[0].timestamp.as_deref(), Some("2023-11-14T22:13:20+00:00"));
+        assert_eq!(entries[0].message, r#"{"sku":42}"#);
+        assert_eq!(entries[0].metadata["service.name"], "inventory");
+        assert_eq!(entries[0].metadata["trace_id"], "5b8efff798038103d269b633813fc60c");
+        assert!(!entries[0].metadata.contains_key("span_id"));
+        assert_eq!(entries[1].level.as_deref(), Some("INFO"));
+        assert_eq!(entries[1].timestamp, None);
+        assert_eq!(entries[1].message, "");
+    }
+
+    #[test]
+    fn test_encodings() {
+        assert_eq!(OtlpEncoding::from_content_type(None), Some(OtlpEncoding::Protobuf));
+        assert_eq!(OtlpEncoding::from_content_type(Some("text/plain")), None);
+        assert!(OtlpEncoding::Protobuf.decode(b"\xff\xff\xff").is_err());
+        assert!(OtlpEncoding::Json.decode(b"not json").is_err());
+        assert!(OtlpEncoding::Protobuf.success_body().is_empty());
+        assert_eq!(OtlpEncoding::Json.success_body(), b"{}");
+    }
+}
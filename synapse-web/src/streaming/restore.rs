@@ -0,0 +1,171 @@
+//! Restoring streaming sources on startup
+//!
+//! Sources still marked active in the database are started again under their stored ids,
+//! so they can still be stopped and their ingest URLs keep working after a restart.
+
+use std::sync::Arc;
+
+use synapse_core::project::StreamingSource;
+use uuid::Uuid;
+
+use crate::database::Database;
+
+/// Stored parser configuration (for deserialization from database)
+#[derive(Debug, serde::Deserialize)]
+struct StoredParserConfig {
+    log_format: String,
+    timestamp_format: Option<String>,
+    level_field: Option<String>,
+    message_field: Option<String>,
+    metadata_fields: Option<Vec<String>>,
+}
+
+/// Restore active streaming sources from database on startup
+pub async fn restore_streaming_sources(
+    db: &Database,
+    streaming_manager: &Arc<tokio::sync::RwLock<crate::streaming::sources::StreamingSourceManager>>,
+) -> anyhow::Result<()> {
+    tracing::info!("Restoring active streaming sources from database...");
+
+    // Query all active sources
+    let sources = db.storage().list_active_streaming_sources().await?;
+
+    let mut manager = streaming_manager.write().await;
+    let mut restored_count = 0;
+
+    for source in sources {
+        let source_id = source.id.clone();
+        match restore_source(source, &mut manager).await {
+            Ok(()) => {
+                tracing::info!("Restored streaming source: {}", source_id);
+                restored_count += 1;
+            }
+            Err(e) => {
+                tracing::error!("Failed to restore streaming source {}: {}", source_id, e);
+            }
+        }
+    }
+
+    drop(manager);
+    tracing::info!("Restored {} streaming sources", restored_count);
+    Ok(())
+}
+
+/// Helper to restore a single stored source
+async fn restore_source(
+    source: StreamingSource,
+    manager: &mut crate::streaming::sources::StreamingSourceManager,
+) -> anyhow::Result<()> {
+    let project_id = Uuid::parse_str(&source.project_id)?;
+
+    // Parse source type
+    let config_value: serde_json::Value = serde_json::from_str(&source.config)?;
+    let source_type = parse_source_type_from_config(&source.source_type, &config_value, source.ingest_token_hash)?;
+
+    // Parse parser config
+    let parser_config = if let Some(json_str) = source.parser_config {
+        let stored_config: StoredParserConfig = serde_json::from_str(&json_str)?;
+        parse_stored_parser_config(stored_config)
+    } else {
+        crate::streaming::sources::ParserConfig::default()
+    };
+
+    // Create source config
+    let config = crate::streaming::sources::StreamingSourceConfig {
+        source_type,
+        project_id,
+        name: source.name,
+        parser_config,
+        buffer_size: source.buffer_size.map(|s| s as usize).unwrap_or(100),
+        batch_timeout: tokio::time::Duration::from_secs(source.batch_timeout_seconds.map(|s| s as u64).unwrap_or(2)),
+        restart_on_error: source.restart_on_error.unwrap_or(true),
+        max_restarts: source.max_restarts.map(|m| m as u32),
+    };
+
+    // Start the source under its stored id, so it can still be stopped and its ingest URL still works
+    manager.restore_source(source.id, config).await?;
+
+    Ok(())
+}
+
+/// Parse source type from stored config
+fn parse_source_type_from_config(
+    source_type: &str,
+    config: &serde_json::Value,
+    ingest_token_hash: Option<String>,
+) -> anyhow::Result<crate::streaming::sources::StreamingSourceType> {
+    match source_type {
+        "file" => {
+            let tail_config = crate::streaming::file_tail::FileTailConfig::from_json(config).map_err(anyhow::Error::msg)?;
+            Ok(crate::streaming::sources::StreamingSourceType::File(tail_config))
+        }
+        "command" => {
+            let command = config.get("command")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Missing 'command'"))?;
+            let args = config.get("args")
+                .and_then(|v| v.as_array())
+                .map(|arr| arr.iter().filter_map(|v| v.as_str().map(String::from)).collect())
+                .unwrap_or_default();
+            Ok(crate::streaming::sources::StreamingSourceType::Command { command: command.to_string(), args })
+        }
+        "tcp" => {
+            let port = config.get("port")
+                .and_then(|v| v.as_u64())
+                .ok_or_else(|| anyhow::anyhow!("Missing 'port' for TCP source"))?;
+            Ok(crate::streaming::sources::StreamingSourceType::TcpListener { port: port as u16 })
+        }
+        "stdin" => Ok(crate::streaming::sources::StreamingSourceType::Stdin),
+        "http" => {
+            let path = config.get("path")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Missing 'path' for HTTP source"))?;
+            let token_hash = ingest_token_hash.ok_or_else(|| anyhow::anyhow!("HTTP source has no ingest token"))?;
+            Ok(crate::streaming::sources::StreamingSourceType::HttpEndpoint { path: path.to_string(), token_hash })
+        }
+        "otlp" => {
+            let path = config.get("path")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Missing 'path' for OTLP source"))?;
+            let token_hash = ingest_token_hash.ok_or_else(|| anyhow::anyhow!("OTLP source has no ingest token"))?;
+            Ok(crate::streaming::sources::StreamingSourceType::Otlp { path: path.to_string(), token_hash })
+        }
+        "syslog" => {
+            let syslog_config = crate::streaming::syslog::SyslogConfig::from_json(config).map_err(anyhow::Error::msg)?;
+            Ok(crate::streaming::sources::StreamingSourceType::Syslog(syslog_config))
+        }
+        "fluent_forward" => {
+            let forward_config = crate::streaming::fluent_forward::ForwardConfig::from_json(config).map_err(anyhow::Error::msg)?;
+            Ok(crate::streaming::sources::StreamingSourceType::FluentForward(forward_config))
+        }
+        "docker" => {
+            let docker_config = crate::streaming::docker::DockerConfig::from_json(config).map_err(anyhow::Error::msg)?;
+            Ok(crate::streaming::sources::StreamingSourceType::Docker(docker_config))
+        }
+        "kubernetes" => {
+            let kubernetes_config = crate::streaming::kubernetes::KubernetesConfig::from_json(config).map_err(anyhow::Error::msg)?;
+            Ok(crate::streaming::sources::StreamingSourceType::Kubernetes(kubernetes_config))
+        }
+        _ => Err(anyhow::anyhow!("Unknown source type: {}", source_type))
+    }
+}
+
+/// Parse parser config from stored configuration
+fn parse_stored_parser_config(
+    stored: StoredParserConfig,
+) -> crate::streaming::sources::ParserConfig {
+    let log_format = match stored.log_format.as_str() {
+        "json" => crate::streaming::sources::LogFormat::Json,
+        "syslog" => crate::streaming::sources::LogFormat::Syslog,
+        "common" => crate::streaming::sources::LogFormat::CommonLog,
+        _ => crate::streaming::sources::LogFormat::Text,
+    };
+
+    crate::streaming::sources::ParserConfig {
+        log_format,
+        timestamp_format: stored.timestamp_format,
+        level_field: stored.level_field,
+        message_field: stored.message_field,
+        metadata_fields: stored.metadata_fields.unwrap_or_default(),
+    }
+}
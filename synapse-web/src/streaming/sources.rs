@@ -1,7 +1,5 @@
 use std::collections::HashMap;
-use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::fs::File;
 use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::process::Command;
 use tokio::sync::mpsc;
@@ -9,22 +7,41 @@ use tokio::time::{Duration, Instant};
 use tracing::{error, info, warn};
 use uuid::Uuid;
 
+use crate::streaming::containers;
+use crate::streaming::docker::DockerConfig;
+#[cfg(unix)]
+use crate::streaming::docker::DockerRuntime;
+use crate::streaming::file_tail::{self, FileTailConfig};
+use crate::streaming::fluent_forward::{self, ForwardConfig};
+use crate::streaming::http_endpoint::{self, HttpEndpoint};
+use crate::streaming::kubernetes::{KubernetesConfig, KubernetesRuntime};
+use crate::streaming::syslog::{self, SyslogConfig};
 use crate::streaming::{StreamingHub, StreamingLogEntry};
 use synapse_core::parser::parse_single_log_line;
 
 /// Different types of streaming log sources
 #[derive(Debug, Clone)]
 pub enum StreamingSourceType {
-    /// Monitor a file for changes (tail -f behavior)
-    File { path: PathBuf },
+    /// Tail the files matching a path or glob pattern, following rotation
+    File(FileTailConfig),
     /// Execute a command and stream its output
     Command { command: String, args: Vec<String> },
     /// Listen on a TCP port for incoming log data
     TcpListener { port: u16 },
     /// Read from stdin
     Stdin,
-    /// Custom HTTP endpoint for log ingestion
-    HttpEndpoint { path: String },
+    /// Accept log data POSTed to `/api/ingest/{path}`, authenticated with the source's token
+    HttpEndpoint { path: String, token_hash: String },
+    /// Accept OTLP/HTTP log exports POSTed to `/api/otlp/{path}/v1/logs`, authenticated like `HttpEndpoint`
+    Otlp { path: String, token_hash: String },
+    /// Receive syslog messages over UDP, TCP or TLS
+    Syslog(SyslogConfig),
+    /// Receive Fluent Bit / Fluentd `forward` output over TCP
+    FluentForward(ForwardConfig),
+    /// Follow container logs through the Docker Engine API
+    Docker(DockerConfig),
+    /// Follow pod logs through the Kubernetes API
+    Kubernetes(KubernetesConfig),
 }
 
 /// Configuration for a streaming source
@@ -78,6 +95,8 @@ impl Default for ParserConfig {
 /// Manager for streaming log sources
 pub struct StreamingSourceManager {
     sources: HashMap<String, StreamingSourceHandle>,
+    /// Ingest URLs of the running HTTP endpoint sources, by path
+    http_endpoints: HashMap<String, HttpEndpoint>,
     streaming_hub: Arc<StreamingHub>,
 }
 
@@ -93,17 +112,45 @@ impl StreamingSourceManager {
     pub fn new(streaming_hub: Arc<StreamingHub>) -> Self {
         Self {
             sources: HashMap::new(),
+            http_endpoints: HashMap::new(),
             streaming_hub,
         }
     }
 
     /// Start a new streaming source
     pub async fn start_source(&mut self, config: StreamingSourceConfig) -> anyhow::Result<String> {
-        let source_id = self.streaming_hub
-            .register_source(config.project_id, config.name.clone())
+        self.start_source_with_id(Uuid::new_v4().to_string(), config).await
+    }
+
+    /// Start a source stored under `source_id`, such as one restored after a restart
+    pub async fn restore_source(&mut self, source_id: String, config: StreamingSourceConfig) -> anyhow::Result<String> {
+        self.start_source_with_id(source_id, config).await
+    }
+
+    async fn start_source_with_id(&mut self, source_id: String, config: StreamingSourceConfig) -> anyhow::Result<String> {
+        if let StreamingSourceType::HttpEndpoint { path, .. } | StreamingSourceType::Otlp { path, .. } =
+            &config.source_type
+        {
+            if self.http_endpoints.contains_key(path) {
+                anyhow::bail!("Ingest path '{}' is already used by another source", path);
+            }
+        }
+
+        self.streaming_hub
+            .register_source_with_id(config.project_id, source_id.clone(), config.name.clone())
             .await;
 
         let (cancel_tx, cancel_rx) = mpsc::unbounded_channel();
+
+        // HTTP endpoint and OTLP sources are fed by their ingest handler through a bounded queue
+        let ingest_rx = match &config.source_type {
+            StreamingSourceType::HttpEndpoint { path, token_hash } | StreamingSourceType::Otlp { path, token_hash } => {
+                let (endpoint, ingest_rx) = HttpEndpoint::new(source_id.clone(), token_hash.clone(), config.clone());
+                self.http_endpoints.insert(path.clone(), endpoint);
+                Some(ingest_rx)
+            }
+            _ => None,
+        };
         
         // Clone necessary data for the async task
         let streaming_hub = Arc::clone(&self.streaming_hub);
@@ -112,7 +159,7 @@ impl StreamingSourceManager {
 
         // Start the source processing task
         tokio::spawn(async move {
-            if let Err(e) = Self::run_source(streaming_hub, config_clone, source_id_clone, cancel_rx).await {
+            if let Err(e) = Self::run_source(streaming_hub, config_clone, source_id_clone, cancel_rx, ingest_rx).await {
                 error!("Streaming source error: {}", e);
             }
         });
@@ -134,6 +181,7 @@ impl StreamingSourceManager {
     pub async fn stop_source(&mut self, source_id: &str) -> anyhow::Result<()> {
         if let Some(handle) = self.sources.remove(source_id) {
             let _ = handle.cancel_tx.send(());
+            self.http_endpoints.retain(|_, endpoint| endpoint.source_id != source_id);
             self.streaming_hub.remove_source(handle.config.project_id, source_id).await;
             info!("Stopped streaming source: {}", source_id);
         }
@@ -153,12 +201,18 @@ impl StreamingSourceManager {
             .collect()
     }
 
+    /// The running HTTP endpoint or OTLP source mounted at `path`
+    pub fn http_endpoint(&self, path: &str) -> Option<HttpEndpoint> {
+        self.http_endpoints.get(path).cloned()
+    }
+
     /// Main source processing loop
     async fn run_source(
         streaming_hub: Arc<StreamingHub>,
         config: StreamingSourceConfig,
         source_id: String,
         mut cancel_rx: mpsc::UnboundedReceiver<()>,
+        mut ingest_rx: Option<mpsc::Receiver<Vec<StreamingLogEntry>>>,
     ) -> anyhow::Result<()> {
         let mut restart_count = 0;
         
@@ -170,8 +224,8 @@ impl StreamingSourceManager {
             }
 
             let result = match &config.source_type {
-                StreamingSourceType::File { path } => {
-                    Self::handle_file_source(&streaming_hub, &config, &source_id, path, &mut cancel_rx).await
+                StreamingSourceType::File(tail_config) => {
+                    file_tail::run(&streaming_hub, &config, tail_config, &source_id, &mut cancel_rx).await
                 }
                 StreamingSourceType::Command { command, args } => {
                     Self::handle_command_source(&streaming_hub, &config, &source_id, command, args, &mut cancel_rx).await
@@ -182,11 +236,34 @@ impl StreamingSourceManager {
                 StreamingSourceType::TcpListener { port } => {
                     Self::handle_tcp_source(&streaming_hub, &config, &source_id, *port, &mut cancel_rx).await
                 }
-                StreamingSourceType::HttpEndpoint { .. } => {
-                    // HTTP endpoint sources are handled by the web server directly
-                    tokio::time::sleep(Duration::from_secs(1)).await;
-                    continue;
+                StreamingSourceType::HttpEndpoint { .. } | StreamingSourceType::Otlp { .. } => match ingest_rx.as_mut() {
+                    Some(ingest_rx) => {
+                        http_endpoint::forward_entries(&streaming_hub, &config, &source_id, ingest_rx, &mut cancel_rx)
+                            .await
+                    }
+                    None => Err(anyhow::anyhow!("HTTP endpoint source {} has no ingest queue", source_id)),
+                },
+                StreamingSourceType::Syslog(syslog_config) => {
+                    syslog::run(&streaming_hub, &config, syslog_config, &source_id, &mut cancel_rx).await
+                }
+                StreamingSourceType::FluentForward(forward_config) => {
+                    fluent_forward::run(&streaming_hub, &config, forward_config, &source_id, &mut cancel_rx).await
                 }
+                #[cfg(unix)]
+                StreamingSourceType::Docker(docker_config) => {
+                    let runtime = DockerRuntime::new(docker_config.clone());
+                    containers::run(&streaming_hub, &config, &source_id, runtime, docker_config.poll_interval, &mut cancel_rx)
+                        .await
+                }
+                #[cfg(not(unix))]
+                StreamingSourceType::Docker(_) => Err(anyhow::anyhow!(crate::streaming::docker::UNSUPPORTED)),
+                StreamingSourceType::Kubernetes(kubernetes_config) => match KubernetesRuntime::new(kubernetes_config.clone()) {
+                    Ok(runtime) => {
+                        let poll_interval = kubernetes_config.poll_interval;
+                        containers::run(&streaming_hub, &config, &source_id, runtime, poll_interval, &mut cancel_rx).await
+                    }
+                    Err(e) => Err(e),
+                },
             };
 
             if let Err(e) = result {
@@ -217,63 +294,6 @@ impl StreamingSourceManager {
         Ok(())
     }
 
-    /// Handle file tailing source
-    async fn handle_file_source(
-        streaming_hub: &Arc<StreamingHub>,
-        config: &StreamingSourceConfig,
-        source_id: &str,
-        file_path: &PathBuf,
-        cancel_rx: &mut mpsc::UnboundedReceiver<()>,
-    ) -> anyhow::Result<()> {
-        let file = File::open(file_path).await?;
-        let mut reader = BufReader::new(file);
-        let mut line = String::new();
-        let mut buffer = Vec::new();
-        let mut last_flush = Instant::now();
-
-        info!("Monitoring file: {:?}", file_path);
-
-        loop {
-            tokio::select! {
-                _ = cancel_rx.recv() => {
-                    info!("File source cancelled");
-                    break;
-                }
-                
-                result = reader.read_line(&mut line) => {
-                    match result {
-                        Ok(0) => {
-                            // End of file, wait a bit and continue (tail behavior)
-                            tokio::time::sleep(Duration::from_millis(100)).await;
-                            continue;
-                        }
-                        Ok(_) => {
-                            if let Some(entry) = Self::parse_log_line(&line, config, source_id) {
-                                buffer.push(entry);
-                                line.clear();
-
-                                // Check if we should flush the buffer
-                                if buffer.len() >= config.buffer_size ||
-                                   last_flush.elapsed() >= config.batch_timeout {
-                                    streaming_hub.add_logs(config.project_id, source_id, std::mem::take(&mut buffer)).await?;
-                                    last_flush = Instant::now();
-                                }
-                            }
-                        }
-                        Err(e) => return Err(e.into()),
-                    }
-                }
-            }
-        }
-
-        // Flush remaining buffer
-        if !buffer.is_empty() {
-            streaming_hub.add_logs(config.project_id, source_id, buffer).await?;
-        }
-
-        Ok(())
-    }
-
     /// Handle command output source
     async fn handle_command_source(
         streaming_hub: &Arc<StreamingHub>,
@@ -484,7 +504,7 @@ impl StreamingSourceManager {
     }
 
     /// Parse a log line according to the configured format
-    fn parse_log_line(
+    pub(crate) fn parse_log_line(
         line: &str,
         config: &StreamingSourceConfig,
         source_id: &str,
@@ -529,6 +549,7 @@ impl StreamingSourceManager {
             source: source_id.to_string(),
             project_id: config.project_id,
             line_number: log_entry.line_number,
+            metadata: HashMap::new(),
         })
     }
 
@@ -537,34 +558,43 @@ impl StreamingSourceManager {
         config: &StreamingSourceConfig,
         source_id: &str,
     ) -> Option<StreamingLogEntry> {
-        if let Ok(json_value) = serde_json::from_str::<serde_json::Value>(line) {
-            let message = json_value.get("message")
-                .or_else(|| json_value.get("msg"))
-                .and_then(|v| v.as_str())
-                .unwrap_or(line)
-                .to_string();
+        let json_value = serde_json::from_str::<serde_json::Value>(line).ok()?;
+        Some(Self::parse_json_value(&json_value, line, config, source_id))
+    }
 
-            let level = json_value.get("level")
-                .or_else(|| json_value.get("severity"))
+    /// Build an entry from a structured record, preferring the parser config's field names
+    /// and keeping its configured metadata fields
+    pub(crate) fn parse_json_value(
+        json_value: &serde_json::Value,
+        line: &str,
+        config: &StreamingSourceConfig,
+        source_id: &str,
+    ) -> StreamingLogEntry {
+        let field = |configured: &Option<String>, defaults: [&str; 2]| {
+            configured
+                .as_deref()
+                .and_then(|name| json_value.get(name))
+                .or_else(|| defaults.iter().find_map(|name| json_value.get(*name)))
                 .and_then(|v| v.as_str())
-                .map(|s| s.to_string());
+                .map(|s| s.to_string())
+        };
 
-            let timestamp = json_value.get("timestamp")
-                .or_else(|| json_value.get("time"))
-                .and_then(|v| v.as_str())
-                .map(|s| s.to_string());
-
-            Some(StreamingLogEntry {
-                id: Uuid::new_v4().to_string(),
-                timestamp,
-                level,
-                message,
-                source: source_id.to_string(),
-                project_id: config.project_id,
-                line_number: None,
-            })
-        } else {
-            None
+        let parser_config = &config.parser_config;
+        let metadata = parser_config
+            .metadata_fields
+            .iter()
+            .filter_map(|name| json_value.get(name).map(|value| (name.clone(), value.clone())))
+            .collect();
+
+        StreamingLogEntry {
+            id: Uuid::new_v4().to_string(),
+            timestamp: field(&None, ["timestamp", "time"]),
+            level: field(&parser_config.level_field, ["level", "severity"]),
+            message: field(&parser_config.message_field, ["message", "msg"]).unwrap_or_else(|| line.to_string()),
+            source: source_id.to_string(),
+            project_id: config.project_id,
+            line_number: None,
+            metadata,
         }
     }
 
@@ -573,18 +603,7 @@ impl StreamingSourceManager {
         config: &StreamingSourceConfig,
         source_id: &str,
     ) -> Option<StreamingLogEntry> {
-        // Basic syslog parsing - could be enhanced with proper RFC 3164/5424 parsing
-        let log_entry = parse_single_log_line(line);
-        
-        Some(StreamingLogEntry {
-            id: Uuid::new_v4().to_string(),
-            timestamp: log_entry.timestamp,
-            level: log_entry.level,
-            message: log_entry.message,
-            source: source_id.to_string(),
-            project_id: config.project_id,
-            line_number: log_entry.line_number,
-        })
+        Some(syslog::parse_message(line).into_entry(config.project_id, source_id, None))
     }
 
     fn parse_common_log(
@@ -602,6 +621,7 @@ impl StreamingSourceManager {
             source: source_id.to_string(),
             project_id: config.project_id,
             line_number: None,
+            metadata: HashMap::new(),
         })
     }
 
@@ -634,6 +654,7 @@ impl StreamingSourceManager {
                     source: source_id.to_string(),
                     project_id: config.project_id,
                     line_number: None,
+                    metadata: HashMap::new(),
                 });
             }
         }
@@ -91,6 +91,7 @@ impl StreamingSourceManager {
             source: source_id,
             project_id,
             line_number: None,
+            metadata: HashMap::new(),
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,474 @@
+//! Durable storage of streaming entries
+//!
+//! Every batch the hub accepts is queued for a writer task, which stores it through the
+//! storage backend in hourly partitions by received time, along with per-minute counts
+//! by source and level. A full queue holds back the hub, and with it the sources feeding
+//! it, rather than losing entries. A retention task drops whole partitions, oldest first,
+//! once they are past a project's maximum age or its stored entries exceed its maximum
+//! size; size alone never drops the partition still being written. Projects without
+//! retention of their own use the server defaults.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration as StdDuration;
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, DurationRound, SecondsFormat, TimeDelta, Utc};
+use serde::Serialize;
+use synapse_core::project::{
+    LogCount, LogCursor, LogPartition, LogRetention, Storage, StoredLogEntry, StreamingLogQuery,
+};
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use super::{StreamingHub, StreamingLogEntry, RECENT_ENTRIES};
+use crate::alerts::rules::parse_level;
+use crate::config::WebConfig;
+
+/// Batches queued for the writer before the hub waits for it
+const QUEUE_BATCHES: usize = 256;
+/// Most entries the writer gathers from the queue for one write
+const WRITE_ENTRIES: usize = 2000;
+/// Tries to store entries before they are given up on
+const WRITE_ATTEMPTS: u32 = 3;
+const RETRY_DELAY: StdDuration = StdDuration::from_secs(1);
+/// How often partitions past retention are dropped
+const RETENTION_INTERVAL: StdDuration = StdDuration::from_secs(5 * 60);
+
+/// Queue of entries for the writer
+pub type LogStoreSender = mpsc::Sender<Vec<StreamingLogEntry>>;
+
+/// How long and how much of a project's entries are kept; `None` for no limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retention {
+    pub max_age: Option<Duration>,
+    pub max_bytes: Option<i64>,
+}
+
+impl Retention {
+    /// The server defaults
+    pub fn from_config(config: &WebConfig) -> Self {
+        Self {
+            max_age: (config.log_retention_days > 0).then(|| Duration::days(config.log_retention_days.into())),
+            max_bytes: (config.log_retention_mb > 0).then(|| i64::from(config.log_retention_mb) * 1024 * 1024),
+        }
+    }
+
+    /// The project's own limits where it sets them, the server's otherwise; 0 means no limit
+    pub fn for_project(project: LogRetention, server: Self) -> Self {
+        Self {
+            max_age: project
+                .max_age_days
+                .map_or(server.max_age, |days| (days > 0).then(|| Duration::days(days.into()))),
+            max_bytes: project
+                .max_size_mb
+                .map_or(server.max_bytes, |mb| (mb > 0).then(|| i64::from(mb) * 1024 * 1024)),
+        }
+    }
+}
+
+/// Start the writer and retention tasks; the hub sends to the returned queue
+pub fn start(storage: Arc<dyn Storage>, config: &WebConfig) -> LogStoreSender {
+    let (sender, receiver) = mpsc::channel(QUEUE_BATCHES);
+    tokio::spawn(write_entries(Arc::clone(&storage), receiver));
+
+    let server = Retention::from_config(config);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(RETENTION_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = enforce_retention(storage.as_ref(), server, Utc::now()).await {
+                warn!("Failed to enforce streaming log retention: {}", e);
+            }
+        }
+    });
+
+    sender
+}
+
+async fn write_entries(storage: Arc<dyn Storage>, mut receiver: mpsc::Receiver<Vec<StreamingLogEntry>>) {
+    while let Some(entries) = receiver.recv().await {
+        let received_at = Utc::now();
+        let mut pending: Vec<_> = entries.iter().map(|entry| to_stored(entry, received_at)).collect();
+        // Take whatever else is already queued, so a busy hub is stored in fewer transactions
+        while pending.len() < WRITE_ENTRIES {
+            let Ok(entries) = receiver.try_recv() else {
+                break;
+            };
+            pending.extend(entries.iter().map(|entry| to_stored(entry, received_at)));
+        }
+
+        // One write per project, so a project deleted meanwhile cannot fail the others
+        let mut by_project: BTreeMap<String, Vec<StoredLogEntry>> = BTreeMap::new();
+        for entry in pending {
+            by_project.entry(entry.project_id.clone()).or_default().push(entry);
+        }
+        for (project_id, entries) in by_project {
+            store(storage.as_ref(), &project_id, &entries).await;
+        }
+    }
+}
+
+async fn store(storage: &dyn Storage, project_id: &str, entries: &[StoredLogEntry]) {
+    for attempt in 1..=WRITE_ATTEMPTS {
+        match storage.insert_streaming_logs(entries).await {
+            Ok(()) => return,
+            Err(e) if attempt < WRITE_ATTEMPTS => {
+                debug!("Failed to store streaming entries of project {}, retrying: {}", project_id, e);
+                tokio::time::sleep(RETRY_DELAY).await;
+            }
+            Err(e) => warn!(
+                "Dropped {} streaming entries of project {} after {} attempts: {}",
+                entries.len(),
+                project_id,
+                WRITE_ATTEMPTS,
+                e
+            ),
+        }
+    }
+}
+
+/// An entry as stored, received at `received_at`
+pub fn to_stored(entry: &StreamingLogEntry, received_at: DateTime<Utc>) -> StoredLogEntry {
+    let timestamp = entry
+        .timestamp
+        .as_deref()
+        .and_then(|timestamp| DateTime::parse_from_rfc3339(timestamp).ok())
+        .map_or(received_at, |timestamp| timestamp.with_timezone(&Utc));
+    // PostgreSQL keeps microseconds, and cursors compare against the stored time
+    let timestamp = timestamp.duration_trunc(TimeDelta::microseconds(1)).unwrap_or(timestamp);
+    let metadata = (!entry.metadata.is_empty())
+        .then(|| serde_json::to_string(&entry.metadata).ok())
+        .flatten();
+    // The entry's text; index overhead is left out
+    let size_bytes = entry.id.len()
+        + entry.source.len()
+        + entry.level.as_deref().map_or(0, str::len)
+        + entry.message.len()
+        + metadata.as_deref().map_or(0, str::len);
+
+    StoredLogEntry {
+        seq: 0,
+        id: entry.id.clone(),
+        project_id: entry.project_id.to_string(),
+        source: entry.source.clone(),
+        partition_start: StoredLogEntry::partition_for(received_at),
+        timestamp,
+        level: entry.level.clone(),
+        severity: entry.level.as_deref().and_then(parse_level).map(|level| level as i32),
+        message: entry.message.clone(),
+        line_number: entry.line_number.and_then(|line| i64::try_from(line).ok()),
+        metadata,
+        size_bytes: size_bytes as i64,
+    }
+}
+
+/// A stored entry as the hub hands entries out
+pub fn from_stored(entry: StoredLogEntry) -> StreamingLogEntry {
+    StreamingLogEntry {
+        id: entry.id,
+        timestamp: Some(entry.timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true)),
+        level: entry.level,
+        message: entry.message,
+        source: entry.source,
+        project_id: Uuid::parse_str(&entry.project_id).unwrap_or_default(),
+        line_number: entry.line_number.and_then(|line| usize::try_from(line).ok()),
+        metadata: entry
+            .metadata
+            .as_deref()
+            .and_then(|metadata| serde_json::from_str(metadata).ok())
+            .unwrap_or_default(),
+    }
+}
+
+/// A cursor as handed to clients: the entry's time in microseconds and its sequence number
+pub fn encode_cursor(cursor: LogCursor) -> String {
+    format!("{}_{}", cursor.timestamp.timestamp_micros(), cursor.seq)
+}
+
+pub fn decode_cursor(cursor: &str) -> Option<LogCursor> {
+    let (micros, seq) = cursor.split_once('_')?;
+    Some(LogCursor {
+        timestamp: DateTime::from_timestamp_micros(micros.parse().ok()?)?,
+        seq: seq.parse().ok()?,
+    })
+}
+
+/// Partitions to drop, oldest first, from one project's partitions in order
+pub fn expired_partitions(
+    partitions: &[LogPartition],
+    retention: Retention,
+    now: DateTime<Utc>,
+) -> Vec<DateTime<Utc>> {
+    let mut total: i64 = partitions.iter().map(|partition| partition.size_bytes).sum();
+    let mut expired = Vec::new();
+
+    for (index, partition) in partitions.iter().enumerate() {
+        // A partition holds an hour of entries, so it goes once that whole hour is past the maximum age
+        let too_old = retention
+            .max_age
+            .is_some_and(|max_age| partition.partition_start + Duration::hours(1) <= now - max_age);
+        let too_big = index + 1 < partitions.len() && retention.max_bytes.is_some_and(|max_bytes| total > max_bytes);
+        if !too_old && !too_big {
+            break;
+        }
+        total -= partition.size_bytes;
+        expired.push(partition.partition_start);
+    }
+
+    expired
+}
+
+/// Drop every project's partitions and counts past its retention; returns the entries dropped
+pub async fn enforce_retention(storage: &dyn Storage, server: Retention, now: DateTime<Utc>) -> Result<u64> {
+    let mut partitions: HashMap<String, Vec<LogPartition>> = HashMap::new();
+    for partition in storage.list_streaming_log_partitions().await? {
+        partitions.entry(partition.project_id.clone()).or_default().push(partition);
+    }
+
+    let mut dropped = 0;
+    for project in storage.list_projects().await? {
+        let own = storage.get_project_log_retention(&project.id).await?.unwrap_or_default();
+        let retention = Retention::for_project(own, server);
+
+        if let Some(project_partitions) = partitions.get(&project.id) {
+            for partition_start in expired_partitions(project_partitions, retention, now) {
+                dropped += storage.drop_streaming_log_partition(&project.id, partition_start).await?;
+            }
+        }
+        if let Some(max_age) = retention.max_age {
+            storage.delete_streaming_log_counts_before(&project.id, now - max_age).await?;
+        }
+    }
+
+    if dropped > 0 {
+        info!("Dropped {} stored streaming entries past retention", dropped);
+    }
+    Ok(dropped)
+}
+
+/// Fill the hub's recent entries from storage, so recent-log queries survive a restart
+pub async fn restore_recent(storage: &dyn Storage, hub: &StreamingHub) -> Result<()> {
+    let mut project_ids: Vec<_> = storage
+        .list_streaming_log_partitions()
+        .await?
+        .into_iter()
+        .map(|partition| partition.project_id)
+        .collect();
+    project_ids.dedup();
+
+    for project_id in project_ids {
+        let Ok(id) = Uuid::parse_str(&project_id) else {
+            continue;
+        };
+        let newest = storage
+            .search_streaming_logs(&StreamingLogQuery {
+                project_id,
+                limit: RECENT_ENTRIES as i64,
+                ..Default::default()
+            })
+            .await?;
+        hub.restore_recent(id, newest.into_iter().rev().map(from_stored).collect());
+    }
+
+    Ok(())
+}
+
+/// Entries counted over one interval
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CountBucket {
+    pub start: DateTime<Utc>,
+    pub total: i64,
+    /// Entries by normalised level; levels without entries are left out
+    pub levels: BTreeMap<String, i64>,
+}
+
+/// Per-minute counts summed into intervals covering `since..until`, empty ones included
+pub fn bucket_counts(
+    counts: &[LogCount],
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+    interval: Duration,
+) -> Vec<CountBucket> {
+    let start = since.duration_trunc(interval).unwrap_or(since);
+    let mut buckets = Vec::new();
+    let mut bucket_start = start;
+    while bucket_start < until {
+        buckets.push(CountBucket { start: bucket_start, total: 0, levels: BTreeMap::new() });
+        bucket_start += interval;
+    }
+
+    for count in counts {
+        let index = (count.minute - start).num_minutes() / interval.num_minutes();
+        let Some(bucket) = usize::try_from(index).ok().and_then(|index| buckets.get_mut(index)) else {
+            continue;
+        };
+        bucket.total += count.entries;
+        *bucket.levels.entry(count.level.clone()).or_insert(0) += count.entries;
+    }
+
+    buckets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use synapse_core::project::{connect_storage, Project};
+    use tempfile::TempDir;
+
+    fn entry(project_id: Uuid, level: Option<&str>, message: &str) -> StreamingLogEntry {
+        StreamingLogEntry {
+            id: Uuid::new_v4().to_string(),
+            timestamp: None,
+            level: level.map(str::to_string),
+            message: message.to_string(),
+            source: "api".to_string(),
+            project_id,
+            line_number: None,
+            metadata: HashMap::new(),
+        }
+    }
+
+    fn partition(hours_ago: i64, size_bytes: i64, now: DateTime<Utc>) -> LogPartition {
+        LogPartition {
+            project_id: "p".to_string(),
+            partition_start: StoredLogEntry::partition_for(now - Duration::hours(hours_ago)),
+            entries: 1,
+            size_bytes,
+        }
+    }
+
+    #[test]
+    fn test_stored_round_trip() {
+        let project_id = Uuid::new_v4();
+        let received_at = Utc::now();
+        let mut original = entry(project_id, Some("warning"), "disk almost full");
+        original.timestamp = Some("2025-01-21T10:15:30.123456789+02:00".to_string());
+        original.line_number = Some(7);
+        original.metadata.insert("hostname".to_string(), serde_json::json!("web-1"));
+
+        let stored = to_stored(&original, received_at);
+        assert_eq!(stored.severity, Some(3));
+        assert_eq!(stored.normalized_level(), "WARN");
+        assert_eq!(stored.partition_start, StoredLogEntry::partition_for(received_at));
+        assert_eq!(stored.timestamp.to_rfc3339(), "2025-01-21T08:15:30.123456+00:00");
+        assert!(stored.size_bytes > original.message.len() as i64);
+
+        let restored = from_stored(stored);
+        assert_eq!(restored.project_id, project_id);
+        assert_eq!(restored.timestamp.as_deref(), Some("2025-01-21T08:15:30.123456Z"));
+        assert_eq!(restored.line_number, Some(7));
+        assert_eq!(restored.metadata.get("hostname"), Some(&serde_json::json!("web-1")));
+
+        // Entries without a usable time of their own are stamped when received
+        let unstamped = to_stored(&entry(project_id, Some("verbose"), "hello"), received_at);
+        assert_eq!(unstamped.severity, None);
+        assert_eq!(unstamped.timestamp, received_at.duration_trunc(TimeDelta::microseconds(1)).unwrap());
+
+        let cursor = LogCursor { timestamp: unstamped.timestamp, seq: 42 };
+        assert_eq!(decode_cursor(&encode_cursor(cursor)), Some(cursor));
+        assert_eq!(decode_cursor("yesterday"), None);
+    }
+
+    #[test]
+    fn test_expired_partitions() {
+        let now = Utc::now();
+        let partitions = [partition(30, 400, now), partition(20, 300, now), partition(2, 200, now), partition(0, 100, now)];
+        let start = |index: usize| partitions[index].partition_start;
+
+        let retention = |days: Option<i64>, bytes: Option<i64>| Retention { max_age: days.map(Duration::days), max_bytes: bytes };
+        assert!(expired_partitions(&partitions, retention(None, None), now).is_empty());
+        assert_eq!(expired_partitions(&partitions, retention(Some(1), None), now), vec![start(0)]);
+        assert_eq!(expired_partitions(&partitions, retention(None, Some(300)), now), vec![start(0), start(1)]);
+        assert_eq!(expired_partitions(&partitions, retention(Some(1), Some(600)), now), vec![start(0)]);
+
+        // Size alone never drops the newest partition, age does
+        assert_eq!(expired_partitions(&partitions, retention(None, Some(1)), now).len(), 3);
+        let stale = [partition(50, 100, now)];
+        assert_eq!(expired_partitions(&stale, retention(None, Some(1)), now), Vec::<DateTime<Utc>>::new());
+        assert_eq!(expired_partitions(&stale, retention(Some(1), None), now), vec![stale[0].partition_start]);
+
+        // A project's own limits win, 0 turning a limit off
+        let server = Retention::from_config(&WebConfig::default());
+        assert_eq!(server, retention(Some(7), Some(1024 * 1024 * 1024)));
+        let own = LogRetention { max_age_days: Some(0), max_size_mb: None };
+        assert_eq!(Retention::for_project(own, server), retention(None, server.max_bytes));
+        assert_eq!(Retention::for_project(LogRetention::default(), server), server);
+    }
+
+    #[test]
+    fn test_bucket_counts() {
+        let since = "2025-01-21T10:07:00Z".parse::<DateTime<Utc>>().unwrap();
+        let count = |minute: &str, level: &str, entries: i64| LogCount {
+            minute: minute.parse().unwrap(),
+            level: level.to_string(),
+            entries,
+        };
+        let counts = [
+            count("2025-01-21T10:07:00Z", "INFO", 3),
+            count("2025-01-21T10:09:00Z", "ERROR", 1),
+            count("2025-01-21T10:09:00Z", "INFO", 2),
+            count("2025-01-21T10:21:00Z", "INFO", 4),
+        ];
+
+        let buckets = bucket_counts(&counts, since, since + Duration::minutes(20), Duration::minutes(5));
+        let starts: Vec<_> = buckets.iter().map(|bucket| bucket.start.format("%H:%M").to_string()).collect();
+        assert_eq!(starts, ["10:05", "10:10", "10:15", "10:20", "10:25"]);
+        assert_eq!(buckets[0].total, 6);
+        assert_eq!(buckets[0].levels, BTreeMap::from([("ERROR".to_string(), 1), ("INFO".to_string(), 5)]));
+        assert_eq!(buckets[1].total, 0);
+        assert_eq!(buckets[3].total, 4);
+    }
+
+    #[tokio::test]
+    async fn test_hub_entries_are_stored_and_restored() {
+        let dir = TempDir::new().unwrap();
+        let url = format!("sqlite:{}?mode=rwc", dir.path().join("store.db").display());
+        let storage = connect_storage(&url, 1).await.unwrap();
+        let project = Project::new("stored".to_string(), None);
+        storage.insert_project(&project).await.unwrap();
+        let project_id = Uuid::parse_str(&project.id).unwrap();
+
+        let hub = StreamingHub::new().with_store(start(Arc::clone(&storage), &WebConfig::default()));
+        let entries = vec![
+            entry(project_id, Some("ERROR"), "payment gateway timeout"),
+            entry(project_id, Some("INFO"), "request served"),
+        ];
+        hub.add_logs(project_id, "http-ingest", entries).await.unwrap();
+
+        let query = StreamingLogQuery {
+            project_id: project.id.clone(),
+            text: Some("gateway".to_string()),
+            limit: 10,
+            ..Default::default()
+        };
+        let mut found = Vec::new();
+        for _ in 0..50 {
+            found = storage.search_streaming_logs(&query).await.unwrap();
+            if !found.is_empty() {
+                break;
+            }
+            tokio::time::sleep(StdDuration::from_millis(20)).await;
+        }
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].severity, Some(4));
+
+        // A fresh hub starts with what was stored
+        let restarted = StreamingHub::new();
+        restore_recent(storage.as_ref(), &restarted).await.unwrap();
+        let recent = restarted.recent_logs(project_id, &Default::default(), 10);
+        let messages: Vec<_> = recent.iter().map(|entry| entry.message.as_str()).collect();
+        assert_eq!(messages.len(), 2);
+        assert!(messages.contains(&"payment gateway timeout"));
+
+        // Nothing is past the default retention yet
+        let server = Retention::from_config(&WebConfig::default());
+        assert_eq!(enforce_retention(storage.as_ref(), server, Utc::now()).await.unwrap(), 0);
+        let own = LogRetention { max_age_days: Some(1), max_size_mb: None };
+        storage.set_project_log_retention(&project.id, &own).await.unwrap();
+        let later = Utc::now() + Duration::days(2);
+        assert_eq!(enforce_retention(storage.as_ref(), server, later).await.unwrap(), 2);
+        assert!(storage.search_streaming_logs(&query).await.unwrap().is_empty());
+    }
+}
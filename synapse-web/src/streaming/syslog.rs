@@ -0,0 +1,703 @@
+//! Syslog server sources
+//!
+//! A syslog source listens on UDP, TCP or both so rsyslog, syslog-ng and network devices can
+//! forward to Synapse directly. TCP streams may use RFC 6587 octet counting or newline framing,
+//! detected per message, and can be wrapped in TLS (RFC 5425). Messages are parsed as RFC 5424
+//! when they carry a version, otherwise as RFC 3164, and their facility, severity, hostname,
+//! app-name and structured data land in the entry's metadata.
+
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use chrono::{DateTime, Datelike, NaiveDateTime, Utc};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader};
+use tokio::net::{TcpListener, UdpSocket};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tokio::time::Duration;
+use tokio_rustls::rustls;
+use tokio_rustls::TlsAcceptor;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::streaming::sources::StreamingSourceConfig;
+use crate::streaming::listeners::{self, LISTENER_QUEUE};
+use crate::streaming::{StreamingHub, StreamingLogEntry};
+
+/// Largest message accepted over TCP, octet-counted or not
+const MAX_MESSAGE_LEN: usize = 64 * 1024;
+/// Largest UDP datagram
+const MAX_DATAGRAM_LEN: usize = 65_535;
+/// Longest octet count prefix, including its trailing space
+const MAX_OCTET_COUNT_LEN: u64 = 8;
+/// PRI assumed for messages without one: user.notice (RFC 3164 section 4.3.3)
+const DEFAULT_PRI: u8 = 13;
+
+const FACILITIES: [&str; 24] = [
+    "kern", "user", "mail", "daemon", "auth", "syslog", "lpr", "news", "uucp", "cron", "authpriv", "ftp", "ntp",
+    "security", "console", "solaris-cron", "local0", "local1", "local2", "local3", "local4", "local5", "local6",
+    "local7",
+];
+const SEVERITIES: [&str; 8] = ["emerg", "alert", "crit", "err", "warning", "notice", "info", "debug"];
+
+/// Transports a syslog source listens on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyslogProtocol {
+    Udp,
+    Tcp,
+    Both,
+}
+
+impl SyslogProtocol {
+    fn udp(self) -> bool {
+        matches!(self, SyslogProtocol::Udp | SyslogProtocol::Both)
+    }
+
+    fn tcp(self) -> bool {
+        matches!(self, SyslogProtocol::Tcp | SyslogProtocol::Both)
+    }
+}
+
+/// PEM files for a TLS syslog listener
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyslogTls {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+/// Where and how a syslog source listens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyslogConfig {
+    pub bind_address: String,
+    pub port: u16,
+    pub protocol: SyslogProtocol,
+    pub tls: Option<SyslogTls>,
+}
+
+impl SyslogConfig {
+    /// Read a syslog source's settings from its JSON config
+    pub fn from_json(config: &Value) -> Result<Self, String> {
+        let port = config
+            .get("port")
+            .and_then(Value::as_u64)
+            .ok_or("Missing 'port' for syslog source")?;
+        let port = u16::try_from(port)
+            .ok()
+            .filter(|port| *port != 0)
+            .ok_or_else(|| format!("Invalid port {} for syslog source", port))?;
+
+        let protocol = match config.get("protocol").and_then(Value::as_str).unwrap_or("udp") {
+            "udp" => SyslogProtocol::Udp,
+            "tcp" => SyslogProtocol::Tcp,
+            "both" => SyslogProtocol::Both,
+            other => return Err(format!("Unknown syslog protocol '{}', expected udp, tcp or both", other)),
+        };
+
+        let bind_address = config
+            .get("bind_address")
+            .and_then(Value::as_str)
+            .unwrap_or("0.0.0.0")
+            .to_string();
+
+        let tls = match config.get("tls") {
+            None | Some(Value::Null) => None,
+            Some(tls) => {
+                let path = |key: &str| {
+                    tls.get(key)
+                        .and_then(Value::as_str)
+                        .map(PathBuf::from)
+                        .ok_or_else(|| format!("Missing 'tls.{}' for syslog source", key))
+                };
+                Some(SyslogTls { cert_path: path("cert_path")?, key_path: path("key_path")? })
+            }
+        };
+        if tls.is_some() && protocol != SyslogProtocol::Tcp {
+            return Err("TLS syslog sources must use the 'tcp' protocol".to_string());
+        }
+
+        Ok(Self { bind_address, port, protocol, tls })
+    }
+}
+
+/// A syslog message split into its RFC 3164 or RFC 5424 parts
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyslogMessage {
+    pub facility: u8,
+    pub severity: u8,
+    /// RFC 3339 timestamp; RFC 3164 timestamps are read as UTC in the most recent matching year
+    pub timestamp: Option<String>,
+    pub hostname: Option<String>,
+    pub app_name: Option<String>,
+    pub procid: Option<String>,
+    pub msgid: Option<String>,
+    /// Structured data elements by SD-ID, each with its parameters
+    pub structured_data: BTreeMap<String, BTreeMap<String, String>>,
+    pub message: String,
+}
+
+impl SyslogMessage {
+    pub fn facility_name(&self) -> &'static str {
+        FACILITIES.get(self.facility as usize).copied().unwrap_or("unknown")
+    }
+
+    pub fn severity_name(&self) -> &'static str {
+        SEVERITIES.get(self.severity as usize).copied().unwrap_or("unknown")
+    }
+
+    /// The Synapse log level for the message's severity
+    pub fn level(&self) -> &'static str {
+        match self.severity {
+            0..=2 => "FATAL",
+            3 => "ERROR",
+            4 => "WARN",
+            5 | 6 => "INFO",
+            _ => "DEBUG",
+        }
+    }
+
+    /// Convert into a streaming entry, stamping it with the receive time if the message had none
+    pub fn into_entry(self, project_id: Uuid, source_id: &str, peer: Option<SocketAddr>) -> StreamingLogEntry {
+        let mut metadata = HashMap::new();
+        metadata.insert("facility".to_string(), json!(self.facility_name()));
+        metadata.insert("severity".to_string(), json!(self.severity_name()));
+        let optional = [
+            ("hostname", &self.hostname),
+            ("app_name", &self.app_name),
+            ("procid", &self.procid),
+            ("msgid", &self.msgid),
+        ];
+        for (key, value) in optional {
+            if let Some(value) = value {
+                metadata.insert(key.to_string(), json!(value));
+            }
+        }
+        if !self.structured_data.is_empty() {
+            metadata.insert("structured_data".to_string(), json!(self.structured_data));
+        }
+        if let Some(peer) = peer {
+            metadata.insert("peer".to_string(), json!(peer.to_string()));
+        }
+
+        StreamingLogEntry {
+            id: Uuid::new_v4().to_string(),
+            level: Some(self.level().to_string()),
+            timestamp: Some(self.timestamp.unwrap_or_else(|| Utc::now().to_rfc3339())),
+            message: self.message,
+            source: source_id.to_string(),
+            project_id,
+            line_number: None,
+            metadata,
+        }
+    }
+}
+
+/// Parse one syslog message, falling back to user.notice and the raw text for anything unrecognised
+pub fn parse_message(raw: &str) -> SyslogMessage {
+    let raw = raw.trim_end_matches(['\r', '\n', '\0']);
+    let (pri, rest) = split_pri(raw).unwrap_or((DEFAULT_PRI, raw));
+    let mut message = match rest.strip_prefix("1 ") {
+        Some(rest) => parse_rfc5424(rest),
+        None => parse_rfc3164(rest),
+    };
+    message.facility = pri >> 3;
+    message.severity = pri & 0x07;
+    message
+}
+
+fn split_pri(raw: &str) -> Option<(u8, &str)> {
+    let rest = raw.strip_prefix('<')?;
+    let end = rest.find('>')?;
+    let digits = &rest[..end];
+    if digits.is_empty() || digits.len() > 3 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let pri: u8 = digits.parse().ok()?;
+    (pri <= 191).then_some((pri, &rest[end + 1..]))
+}
+
+fn nil_value(field: Option<&str>) -> Option<String> {
+    field.filter(|field| !field.is_empty() && *field != "-").map(str::to_string)
+}
+
+/// `TIMESTAMP HOSTNAME APP-NAME PROCID MSGID STRUCTURED-DATA [MSG]`, after `<PRI>1 `
+fn parse_rfc5424(rest: &str) -> SyslogMessage {
+    let mut fields = rest.splitn(6, ' ');
+    let timestamp = nil_value(fields.next())
+        .map(|timestamp| DateTime::parse_from_rfc3339(&timestamp).map(|ts| ts.to_rfc3339()).unwrap_or(timestamp));
+    let hostname = nil_value(fields.next());
+    let app_name = nil_value(fields.next());
+    let procid = nil_value(fields.next());
+    let msgid = nil_value(fields.next());
+    let (structured_data, message) = parse_structured_data(fields.next().unwrap_or(""));
+
+    SyslogMessage {
+        timestamp,
+        hostname,
+        app_name,
+        procid,
+        msgid,
+        structured_data,
+        message: message.strip_prefix('\u{feff}').unwrap_or(message).to_string(),
+        ..Default::default()
+    }
+}
+
+/// Structured data elements and the message after them; malformed data is left in the message
+fn parse_structured_data(input: &str) -> (BTreeMap<String, BTreeMap<String, String>>, &str) {
+    let mut elements = BTreeMap::new();
+    if let Some(message) = input.strip_prefix('-') {
+        return (elements, message.strip_prefix(' ').unwrap_or(message));
+    }
+
+    let mut rest = input;
+    while let Some(element) = rest.strip_prefix('[') {
+        let Some((id, params, after)) = parse_sd_element(element) else {
+            break;
+        };
+        elements.insert(id, params);
+        rest = after;
+    }
+    (elements, rest.strip_prefix(' ').unwrap_or(rest))
+}
+
+/// `SD-ID *(SP PARAM-NAME="PARAM-VALUE")]`, with `\"`, `\\` and `\]` escaped in values
+fn parse_sd_element(input: &str) -> Option<(String, BTreeMap<String, String>, &str)> {
+    let id_end = input.find([' ', ']'])?;
+    let id = input[..id_end].to_string();
+    let mut rest = &input[id_end..];
+    let mut params = BTreeMap::new();
+
+    loop {
+        if let Some(after) = rest.strip_prefix(']') {
+            return Some((id, params, after));
+        }
+        rest = rest.strip_prefix(' ')?;
+        let (name, value) = rest.split_once('=')?;
+        let value = value.strip_prefix('"')?;
+
+        let mut unescaped = String::new();
+        let mut chars = value.char_indices();
+        let end = loop {
+            match chars.next()? {
+                (i, '"') => break i,
+                (_, '\\') => {
+                    let (_, c) = chars.next()?;
+                    if !matches!(c, '"' | '\\' | ']') {
+                        unescaped.push('\\');
+                    }
+                    unescaped.push(c);
+                }
+                (_, c) => unescaped.push(c),
+            }
+        };
+        params.insert(name.to_string(), unescaped);
+        rest = &value[end + 1..];
+    }
+}
+
+/// `TIMESTAMP HOSTNAME TAG[PID]: MSG`, tolerating the parts devices commonly leave out
+fn parse_rfc3164(rest: &str) -> SyslogMessage {
+    let mut message = SyslogMessage::default();
+    let mut rest = rest;
+
+    if let Some((timestamp, after)) = parse_bsd_timestamp(rest) {
+        message.timestamp = Some(timestamp);
+        rest = after;
+        // Some devices skip the hostname and go straight to the tag
+        if let Some((hostname, after)) = rest.split_once(' ') {
+            if !hostname.is_empty() && !hostname.ends_with(':') && !hostname.contains('[') {
+                message.hostname = Some(hostname.to_string());
+                rest = after;
+            }
+        }
+    }
+
+    let (app_name, procid, text) = split_tag(rest);
+    message.app_name = app_name;
+    message.procid = procid;
+    message.message = text.to_string();
+    message
+}
+
+/// An RFC 3164 `Mmm dd hh:mm:ss` timestamp, or an RFC 3339 one as rsyslog sends by default
+fn parse_bsd_timestamp(rest: &str) -> Option<(String, &str)> {
+    if let Some((token, after)) = rest.split_once(' ') {
+        if let Ok(timestamp) = DateTime::parse_from_rfc3339(token) {
+            return Some((timestamp.to_rfc3339(), after));
+        }
+    }
+
+    let stamp = rest.get(..15)?.replace("  ", " ");
+    let after = rest[15..].strip_prefix(' ').unwrap_or(&rest[15..]);
+    let now = Utc::now();
+    let parse = |year: i32| NaiveDateTime::parse_from_str(&format!("{} {}", year, stamp), "%Y %b %d %H:%M:%S").ok();
+    let mut timestamp = parse(now.year())?.and_utc();
+    // The year is implied, so a date well in the future was sent last year
+    if timestamp > now + chrono::Duration::days(1) {
+        timestamp = parse(now.year() - 1)?.and_utc();
+    }
+    Some((timestamp.to_rfc3339(), after))
+}
+
+/// Split `TAG[PID]: MSG` into its parts, or leave the text alone if it has no tag
+fn split_tag(rest: &str) -> (Option<String>, Option<String>, &str) {
+    let tag_end = rest
+        .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '/')))
+        .unwrap_or(rest.len());
+    if tag_end == 0 || tag_end > 48 {
+        return (None, None, rest);
+    }
+
+    let (tag, after) = rest.split_at(tag_end);
+    let (procid, after) = match after.strip_prefix('[').and_then(|after| after.split_once(']')) {
+        Some((procid, after)) => (Some(procid.to_string()), after),
+        None => (None, after),
+    };
+    match after.strip_prefix(':') {
+        Some(text) => (Some(tag.to_string()), procid, text.strip_prefix(' ').unwrap_or(text)),
+        None => (None, None, rest),
+    }
+}
+
+/// Read the next message from a stream into `frame`, returning false once the stream ends
+///
+/// Each frame is either RFC 6587 octet-counted (`MSG-LEN SP MSG`) or terminated by a newline;
+/// a leading number that isn't a valid length is read as the start of a newline-framed message.
+pub async fn read_frame<R: AsyncBufRead + Unpin>(reader: &mut R, frame: &mut Vec<u8>) -> std::io::Result<bool> {
+    frame.clear();
+
+    // Skip delimiters left between frames
+    let first = loop {
+        let buf = reader.fill_buf().await?;
+        match buf.first() {
+            None => return Ok(false),
+            Some(b'\n' | b'\r' | b'\0') => reader.consume(1),
+            Some(&first) => break first,
+        }
+    };
+
+    if first.is_ascii_digit() {
+        (&mut *reader).take(MAX_OCTET_COUNT_LEN).read_until(b' ', frame).await?;
+        let length = frame
+            .strip_suffix(b" ")
+            .filter(|digits| digits.iter().all(u8::is_ascii_digit))
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| digits.parse::<usize>().ok());
+        if let Some(length) = length {
+            if length > MAX_MESSAGE_LEN {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("syslog message of {} bytes exceeds the {} byte limit", length, MAX_MESSAGE_LEN),
+                ));
+            }
+            frame.clear();
+            frame.resize(length, 0);
+            reader.read_exact(frame).await?;
+            return Ok(true);
+        }
+    }
+
+    let limit = (MAX_MESSAGE_LEN + 1).saturating_sub(frame.len()) as u64;
+    (&mut *reader).take(limit).read_until(b'\n', frame).await?;
+    if frame.len() > MAX_MESSAGE_LEN && frame.last() != Some(&b'\n') {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("syslog message exceeds the {} byte limit", MAX_MESSAGE_LEN),
+        ));
+    }
+    while matches!(frame.last(), Some(b'\n' | b'\r')) {
+        frame.pop();
+    }
+    Ok(true)
+}
+
+/// Build a TLS acceptor from a PEM certificate chain and private key
+pub fn tls_acceptor(tls: &SyslogTls) -> anyhow::Result<TlsAcceptor> {
+    use rustls::pki_types::pem::PemObject;
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+    let certs = CertificateDer::pem_file_iter(&tls.cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| anyhow::anyhow!("Failed to read certificate {:?}: {}", tls.cert_path, e))?;
+    if certs.is_empty() {
+        anyhow::bail!("No certificates found in {:?}", tls.cert_path);
+    }
+    let key = PrivateKeyDer::from_pem_file(&tls.key_path)
+        .map_err(|e| anyhow::anyhow!("Failed to read private key {:?}: {}", tls.key_path, e))?;
+
+    let config = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Hands parsed messages from the listeners to the source's batching loop
+#[derive(Clone)]
+struct MessageSink {
+    project_id: Uuid,
+    source_id: String,
+    entries: mpsc::Sender<StreamingLogEntry>,
+}
+
+impl MessageSink {
+    /// Queue a raw message, returning false once the source has stopped
+    async fn deliver(&self, raw: &[u8], peer: SocketAddr) -> bool {
+        let raw = String::from_utf8_lossy(raw);
+        if raw.trim().is_empty() {
+            return true;
+        }
+        let entry = parse_message(&raw).into_entry(self.project_id, &self.source_id, Some(peer));
+        self.entries.send(entry).await.is_ok()
+    }
+}
+
+/// Run a syslog source's listeners until it is cancelled or a socket fails
+pub async fn run(
+    streaming_hub: &Arc<StreamingHub>,
+    config: &StreamingSourceConfig,
+    syslog: &SyslogConfig,
+    source_id: &str,
+    cancel_rx: &mut mpsc::UnboundedReceiver<()>,
+) -> anyhow::Result<()> {
+    let (entries, entry_rx) = mpsc::channel(LISTENER_QUEUE);
+    let sink = MessageSink { project_id: config.project_id, source_id: source_id.to_string(), entries };
+    let address = (syslog.bind_address.as_str(), syslog.port);
+
+    let mut listeners = JoinSet::new();
+    if syslog.protocol.udp() {
+        let socket = UdpSocket::bind(address).await?;
+        info!("Syslog UDP listener started on {}", socket.local_addr()?);
+        listeners.spawn(receive_datagrams(socket, sink.clone()));
+    }
+    if syslog.protocol.tcp() {
+        let acceptor = syslog.tls.as_ref().map(tls_acceptor).transpose()?;
+        let listener = TcpListener::bind(address).await?;
+        info!(
+            "Syslog {} listener started on {}",
+            if acceptor.is_some() { "TLS" } else { "TCP" },
+            listener.local_addr()?
+        );
+        listeners.spawn(accept_connections(listener, acceptor, sink.clone()));
+    }
+    drop(sink);
+
+    listeners::forward_from_listeners(streaming_hub, config, source_id, entry_rx, listeners, cancel_rx).await
+}
+
+async fn receive_datagrams(socket: UdpSocket, sink: MessageSink) -> anyhow::Result<()> {
+    let mut datagram = vec![0u8; MAX_DATAGRAM_LEN];
+    loop {
+        let (len, peer) = socket.recv_from(&mut datagram).await?;
+        if !sink.deliver(&datagram[..len], peer).await {
+            return Ok(());
+        }
+    }
+}
+
+async fn accept_connections(listener: TcpListener, acceptor: Option<TlsAcceptor>, sink: MessageSink) -> anyhow::Result<()> {
+    let mut connections = JoinSet::new();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (socket, peer) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        warn!("Syslog accept error: {}", e);
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
+                debug!("Syslog connection from {}", peer);
+
+                let acceptor = acceptor.clone();
+                let sink = sink.clone();
+                connections.spawn(async move {
+                    let result = match acceptor {
+                        Some(acceptor) => match acceptor.accept(socket).await {
+                            Ok(stream) => read_messages(stream, peer, &sink).await,
+                            Err(e) => Err(e),
+                        },
+                        None => read_messages(socket, peer, &sink).await,
+                    };
+                    if let Err(e) = result {
+                        warn!("Syslog connection from {} closed: {}", peer, e);
+                    }
+                });
+            }
+
+            // Reap finished connections
+            Some(_) = connections.join_next(), if !connections.is_empty() => {}
+        }
+    }
+}
+
+async fn read_messages<S: AsyncRead + Unpin>(stream: S, peer: SocketAddr, sink: &MessageSink) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut frame = Vec::new();
+    while read_frame(&mut reader, &mut frame).await? {
+        if !sink.deliver(&frame, peer).await {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+
+    #[test]
+    fn test_parse_rfc5424() {
+        let message = parse_message(
+            "<165>1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog - ID47 \
+             [exampleSDID@32473 iut=\"3\" eventSource=\"Application\" note=\"a \\\"quoted\\\" \\] value\"]\
+             [examplePriority@32473 class=\"high\"] \u{feff}An application event log entry...",
+        );
+
+        assert_eq!(message.facility_name(), "local4");
+        assert_eq!(message.severity_name(), "notice");
+        assert_eq!(message.level(), "INFO");
+        assert_eq!(message.timestamp.as_deref(), Some("2003-10-11T22:14:15.003+00:00"));
+        assert_eq!(message.hostname.as_deref(), Some("mymachine.example.com"));
+        assert_eq!(message.app_name.as_deref(), Some("evntslog"));
+        assert_eq!(message.procid, None);
+        assert_eq!(message.msgid.as_deref(), Some("ID47"));
+        assert_eq!(message.structured_data["exampleSDID@32473"]["eventSource"], "Application");
+        assert_eq!(message.structured_data["exampleSDID@32473"]["note"], "a \"quoted\" ] value");
+        assert_eq!(message.structured_data["examplePriority@32473"]["class"], "high");
+        assert_eq!(message.message, "An application event log entry...");
+
+        let bare = parse_message("<34>1 - - - - - -");
+        assert_eq!(bare.severity_name(), "crit");
+        assert!(bare.structured_data.is_empty());
+        assert_eq!(bare.message, "");
+    }
+
+    #[test]
+    fn test_parse_rfc3164() {
+        let message = parse_message("<34>Oct 11 22:14:15 mymachine su[231]: 'su root' failed for lonvick on /dev/pts/8");
+        assert_eq!(message.facility_name(), "auth");
+        assert_eq!(message.level(), "FATAL");
+        assert!(message.timestamp.as_deref().unwrap().ends_with("-10-11T22:14:15+00:00"));
+        assert_eq!(message.hostname.as_deref(), Some("mymachine"));
+        assert_eq!(message.app_name.as_deref(), Some("su"));
+        assert_eq!(message.procid.as_deref(), Some("231"));
+        assert_eq!(message.message, "'su root' failed for lonvick on /dev/pts/8");
+
+        let no_hostname = parse_message("<13>Feb  5 17:32:18 sshd: Connection closed");
+        assert_eq!(no_hostname.hostname, None);
+        assert_eq!(no_hostname.app_name.as_deref(), Some("sshd"));
+        assert_eq!(no_hostname.message, "Connection closed");
+
+        let iso = parse_message("<11>2024-06-01T08:00:00+02:00 web01 nginx: upstream timed out");
+        assert_eq!(iso.timestamp.as_deref(), Some("2024-06-01T08:00:00+02:00"));
+        assert_eq!(iso.hostname.as_deref(), Some("web01"));
+        assert_eq!(iso.level(), "ERROR");
+
+        let plain = parse_message("just some text");
+        assert_eq!(plain.facility_name(), "user");
+        assert_eq!(plain.severity_name(), "notice");
+        assert_eq!(plain.message, "just some text");
+    }
+
+    #[test]
+    fn test_entry_metadata() {
+        let peer: SocketAddr = "10.0.0.5:514".parse().unwrap();
+        let entry = parse_message("<86>1 2024-01-01T00:00:00Z host sshd 42 - [origin ip=\"10.0.0.5\"] Accepted key")
+            .into_entry(Uuid::nil(), "syslog-source", Some(peer));
+
+        assert_eq!(entry.level.as_deref(), Some("INFO"));
+        assert_eq!(entry.message, "Accepted key");
+        assert_eq!(entry.metadata["facility"], "authpriv");
+        assert_eq!(entry.metadata["severity"], "info");
+        assert_eq!(entry.metadata["hostname"], "host");
+        assert_eq!(entry.metadata["app_name"], "sshd");
+        assert_eq!(entry.metadata["procid"], "42");
+        assert_eq!(entry.metadata["structured_data"]["origin"]["ip"], "10.0.0.5");
+        assert_eq!(entry.metadata["peer"], "10.0.0.5:514");
+        assert!(!entry.metadata.contains_key("msgid"));
+    }
+
+    #[test]
+    fn test_config_from_json() {
+        let config = SyslogConfig::from_json(&json!({"port": 5514})).unwrap();
+        assert_eq!(config.protocol, SyslogProtocol::Udp);
+        assert_eq!(config.bind_address, "0.0.0.0");
+
+        let tls = SyslogConfig::from_json(&json!({
+            "port": 6514,
+            "protocol": "tcp",
+            "tls": {"cert_path": "/etc/synapse/syslog.crt", "key_path": "/etc/synapse/syslog.key"}
+        }))
+        .unwrap();
+        assert_eq!(tls.tls.unwrap().key_path, PathBuf::from("/etc/synapse/syslog.key"));
+
+        assert!(SyslogConfig::from_json(&json!({})).is_err());
+        assert!(SyslogConfig::from_json(&json!({"port": 70000})).is_err());
+        assert!(SyslogConfig::from_json(&json!({"port": 514, "protocol": "sctp"})).is_err());
+        assert!(SyslogConfig::from_json(&json!({
+            "port": 514,
+            "protocol": "both",
+            "tls": {"cert_path": "a", "key_path": "b"}
+        }))
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_read_frames() {
+        let stream: &[u8] = b"<13>newline framed\r\n\n11 <13>counted<14>no trailing newline\n2024-06-01 is not a length\n";
+        let mut reader = BufReader::new(stream);
+        let mut frame = Vec::new();
+        let mut frames = Vec::new();
+        while read_frame(&mut reader, &mut frame).await.unwrap() {
+            frames.push(String::from_utf8(frame.clone()).unwrap());
+        }
+        assert_eq!(
+            frames,
+            ["<13>newline framed", "<13>counted", "<14>no trailing newline", "2024-06-01 is not a length"]
+        );
+
+        let oversized = format!("{} <13>", MAX_MESSAGE_LEN + 1);
+        let mut reader = BufReader::new(oversized.as_bytes());
+        assert!(read_frame(&mut reader, &mut frame).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_receivers_deliver_entries() {
+        let (entries, mut entry_rx) = mpsc::channel(8);
+        let sink = MessageSink { project_id: Uuid::new_v4(), source_id: "syslog".to_string(), entries };
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = socket.local_addr().unwrap();
+        tokio::spawn(receive_datagrams(socket, sink.clone()));
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.send_to(b"<11>1 - router - - - - link down", address).await.unwrap();
+        let entry = entry_rx.recv().await.unwrap();
+        assert_eq!(entry.message, "link down");
+        assert_eq!(entry.metadata["hostname"], "router");
+        assert_eq!(entry.metadata["peer"], client.local_addr().unwrap().to_string());
+
+        let (mut client, server) = tokio::io::duplex(1024);
+        let peer: SocketAddr = "192.0.2.1:40000".parse().unwrap();
+        let reader = tokio::spawn(async move { read_messages(server, peer, &sink).await });
+        client.write_all(b"9 <12>first<12>second\n").await.unwrap();
+        drop(client);
+        reader.await.unwrap().unwrap();
+        assert_eq!(entry_rx.recv().await.unwrap().message, "first");
+        let second = entry_rx.recv().await.unwrap();
+        assert_eq!(second.message, "second");
+        assert_eq!(second.level.as_deref(), Some("WARN"));
+    }
+
+    #[test]
+    fn test_tls_acceptor_requires_readable_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let tls = SyslogTls { cert_path: dir.path().join("missing.crt"), key_path: dir.path().join("missing.key") };
+        assert!(tls_acceptor(&tls).is_err());
+    }
+}
@@ -0,0 +1,336 @@
+// Tracing setup and request trace ids
+//
+// Spans always wrap requests and the analysis pipeline so log lines carry their context.
+// Built with the `otel` feature and given an OTLP endpoint, the server also exports them
+// to an OpenTelemetry collector. Either way each request gets a trace id, returned in the
+// `x-trace-id` header and in error responses.
+
+use std::future::Future;
+
+use axum::http::HeaderMap;
+use tracing::Span;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+/// Response header carrying the request's trace id
+pub const TRACE_ID_HEADER: &str = "x-trace-id";
+
+tokio::task_local! {
+    static TRACE_ID: String;
+}
+
+/// Flushes spans still waiting to be exported when dropped; keep it for the life of the server
+pub struct Telemetry {
+    #[cfg(feature = "otel")]
+    provider: Option<opentelemetry_sdk::trace::TracerProvider>,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        #[cfg(feature = "otel")]
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Failed to flush trace spans: {}", e);
+            }
+        }
+    }
+}
+
+/// Install the global subscriber: log lines filtered by `RUST_LOG`, plus OTLP span export
+/// when built with `otel` and `OTEL_EXPORTER_OTLP_ENDPOINT` is set
+///
+/// Fails when a subscriber is already installed.
+pub fn init() -> anyhow::Result<Telemetry> {
+    let registry = tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_filter(EnvFilter::from_default_env()));
+
+    #[cfg(feature = "otel")]
+    {
+        let provider = otel::provider_from_env()?;
+        registry.with(provider.as_ref().map(otel::layer)).try_init()?;
+        if provider.is_some() {
+            tracing::info!("Exporting trace spans over OTLP");
+        }
+        Ok(Telemetry { provider })
+    }
+
+    #[cfg(not(feature = "otel"))]
+    {
+        registry.try_init()?;
+        Ok(Telemetry {})
+    }
+}
+
+/// Trace id for a request span: its OpenTelemetry trace id when spans are exported, else
+/// the one from an incoming `traceparent` header, else a new one
+///
+/// With export on, the span also joins the caller's trace.
+pub fn request_trace_id(span: &Span, headers: &HeaderMap) -> String {
+    let traceparent = headers.get("traceparent").and_then(|value| value.to_str().ok());
+
+    #[cfg(feature = "otel")]
+    if let Some(trace_id) = otel::join_trace(span, traceparent) {
+        return trace_id;
+    }
+    #[cfg(not(feature = "otel"))]
+    let _ = span;
+
+    match traceparent.and_then(parse_traceparent) {
+        Some(trace_id) => trace_id.to_string(),
+        None => uuid::Uuid::new_v4().simple().to_string(),
+    }
+}
+
+/// Run a request with its trace id available to [`current_trace_id`]
+pub async fn with_trace_id<F: Future>(trace_id: String, future: F) -> F::Output {
+    TRACE_ID.scope(trace_id, future).await
+}
+
+/// Trace id of the request being handled, if any
+pub fn current_trace_id() -> Option<String> {
+    TRACE_ID.try_with(Clone::clone).ok()
+}
+
+/// W3C `traceparent` of the current span while spans are exported, so a queued job can
+/// continue the request's trace
+pub fn current_traceparent() -> Option<String> {
+    #[cfg(feature = "otel")]
+    return otel::traceparent(&Span::current());
+
+    #[cfg(not(feature = "otel"))]
+    None
+}
+
+/// Make `span` a child of the span a `traceparent` was taken from
+pub fn set_parent(span: &Span, traceparent: Option<&str>) {
+    #[cfg(feature = "otel")]
+    otel::join_trace(span, traceparent);
+
+    #[cfg(not(feature = "otel"))]
+    let _ = (span, traceparent);
+}
+
+/// The trace id of a W3C `traceparent` header, when it's well formed
+pub fn parse_traceparent(value: &str) -> Option<&str> {
+    let is_hex = |part: &str, len: usize| {
+        part.len() == len && part.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+    };
+
+    let mut parts = value.trim().split('-');
+    let (version, trace_id, parent_id, flags) = (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+    let valid = is_hex(version, 2)
+        && version != "ff"
+        && is_hex(trace_id, 32)
+        && trace_id.bytes().any(|b| b != b'0')
+        && is_hex(parent_id, 16)
+        && parent_id.bytes().any(|b| b != b'0')
+        && is_hex(flags, 2)
+        && (version != "00" || parts.next().is_none());
+    valid.then_some(trace_id)
+}
+
+#[cfg(feature = "otel")]
+mod otel {
+    use std::collections::HashMap;
+
+    use opentelemetry::{
+        propagation::TextMapPropagator,
+        trace::{TraceContextExt, TracerProvider as _},
+        KeyValue,
+    };
+    use opentelemetry_otlp::SpanExporter;
+    use opentelemetry_sdk::{
+        propagation::TraceContextPropagator, runtime, trace::TracerProvider, Resource,
+    };
+    use tracing::Span;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+    use tracing_subscriber::{filter::Targets, registry::LookupSpan, Layer};
+
+    /// Exporter for the collector named by the standard `OTEL_EXPORTER_OTLP_*` variables
+    pub fn provider_from_env() -> anyhow::Result<Option<TracerProvider>> {
+        let configured = ["OTEL_EXPORTER_OTLP_ENDPOINT", "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT"]
+            .iter()
+            .any(|name| std::env::var_os(name).is_some_and(|value| !value.is_empty()));
+        if !configured {
+            return Ok(None);
+        }
+
+        let exporter = SpanExporter::builder().with_http().build()?;
+        Ok(Some(provider(exporter)))
+    }
+
+    /// Exporter sending spans to a collector's OTLP/HTTP traces endpoint
+    #[cfg(test)]
+    pub fn provider_for(endpoint: &str) -> anyhow::Result<TracerProvider> {
+        use opentelemetry_otlp::WithExportConfig;
+
+        let exporter = SpanExporter::builder().with_http().with_endpoint(endpoint).build()?;
+        Ok(provider(exporter))
+    }
+
+    fn provider(exporter: SpanExporter) -> TracerProvider {
+        let service_name = std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| "synapse-web".to_string());
+        TracerProvider::builder()
+            .with_batch_exporter(exporter, runtime::Tokio)
+            .with_resource(Resource::new([
+                KeyValue::new("service.name", service_name),
+                KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+            ]))
+            .build()
+    }
+
+    /// Export Synapse's own spans; dependencies' spans, including the exporter's HTTP
+    /// client, stay out of the traces
+    pub fn layer<S>(provider: &TracerProvider) -> impl Layer<S>
+    where
+        S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+    {
+        let targets = Targets::new()
+            .with_target("synapse_web", tracing::Level::INFO)
+            .with_target("synapse_core", tracing::Level::INFO);
+        tracing_opentelemetry::layer()
+            .with_tracer(provider.tracer("synapse-web"))
+            .with_filter(targets)
+    }
+
+    /// Join the trace a `traceparent` belongs to, and return the span's trace id
+    ///
+    /// `None` when no spans are exported.
+    pub fn join_trace(span: &Span, traceparent: Option<&str>) -> Option<String> {
+        if let Some(traceparent) = traceparent {
+            let carrier = HashMap::from([("traceparent".to_string(), traceparent.to_string())]);
+            let parent = TraceContextPropagator::new().extract(&carrier);
+            if parent.span().span_context().is_valid() {
+                span.set_parent(parent);
+            }
+        }
+
+        let context = span.context();
+        let span_context = context.span().span_context().clone();
+        span_context.is_valid().then(|| span_context.trace_id().to_string())
+    }
+
+    pub fn traceparent(span: &Span) -> Option<String> {
+        let context = span.context();
+        if !context.span().span_context().is_valid() {
+            return None;
+        }
+        let mut carrier = HashMap::new();
+        TraceContextPropagator::new().inject_context(&context, &mut carrier);
+        carrier.remove("traceparent")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_traceparent() {
+        let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+        assert_eq!(parse_traceparent(&format!("00-{}-00f067aa0ba902b7-01", trace_id)), Some(trace_id));
+        // Unknown versions may carry more fields
+        assert_eq!(parse_traceparent(&format!("01-{}-00f067aa0ba902b7-01-extra", trace_id)), Some(trace_id));
+
+        assert_eq!(parse_traceparent(&format!("00-{}-00f067aa0ba902b7-01-extra", trace_id)), None);
+        assert_eq!(parse_traceparent("00-00000000000000000000000000000000-00f067aa0ba902b7-01"), None);
+        assert_eq!(parse_traceparent(&format!("00-{}-0000000000000000-01", trace_id)), None);
+        assert_eq!(parse_traceparent(&format!("ff-{}-00f067aa0ba902b7-01", trace_id)), None);
+        assert_eq!(parse_traceparent("00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01"), None);
+        assert_eq!(parse_traceparent("not a traceparent"), None);
+    }
+
+    #[tokio::test]
+    async fn test_trace_id_is_scoped_to_the_request() {
+        assert_eq!(current_trace_id(), None);
+        let seen = with_trace_id("abc".to_string(), async { current_trace_id() }).await;
+        assert_eq!(seen.as_deref(), Some("abc"));
+    }
+
+    #[test]
+    fn test_request_trace_id_reuses_incoming_trace() {
+        let mut headers = HeaderMap::new();
+        let fresh = request_trace_id(&Span::none(), &headers);
+        assert_eq!(fresh.len(), 32);
+        assert!(fresh.bytes().all(|b| b.is_ascii_hexdigit()));
+
+        headers.insert(
+            "traceparent",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01".parse().unwrap(),
+        );
+        assert_eq!(request_trace_id(&Span::none(), &headers), "4bf92f3577b34da6a3ce929d0e0e4736");
+    }
+
+    /// Accept one OTLP/HTTP export and answer it, standing in for a collector
+    #[cfg(feature = "otel")]
+    async fn collect_one_export(listener: tokio::net::TcpListener) -> (String, Vec<u8>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 8192];
+        let (head, body_start) = loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            assert!(n > 0, "connection closed before the request was complete");
+            request.extend_from_slice(&buf[..n]);
+            if let Some(end) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+                break (String::from_utf8_lossy(&request[..end]).to_lowercase(), end + 4);
+            }
+        };
+        let content_length: usize = head
+            .lines()
+            .find_map(|line| line.strip_prefix("content-length:"))
+            .map(|value| value.trim().parse().unwrap())
+            .unwrap_or(0);
+        while request.len() < body_start + content_length {
+            let n = stream.read(&mut buf).await.unwrap();
+            assert!(n > 0, "connection closed before the body was complete");
+            request.extend_from_slice(&buf[..n]);
+        }
+        stream.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n").await.unwrap();
+        (head, request[body_start..body_start + content_length].to_vec())
+    }
+
+    #[cfg(feature = "otel")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_spans_are_exported_to_the_collector() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}/v1/traces", listener.local_addr().unwrap());
+        let collector = tokio::spawn(collect_one_export(listener));
+
+        let provider = otel::provider_for(&endpoint).unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "traceparent",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01".parse().unwrap(),
+        );
+        let trace_id = {
+            let subscriber = tracing_subscriber::registry().with(otel::layer(&provider));
+            let _guard = tracing::subscriber::set_default(subscriber);
+            let span = tracing::info_span!("http.request");
+            let trace_id = request_trace_id(&span, &headers);
+            span.in_scope(|| {
+                assert!(current_traceparent().unwrap().contains(&trace_id));
+                tracing::info_span!("ai.provider.analyze", provider = "openai").in_scope(|| {});
+            });
+            trace_id
+        };
+        // The request joined the caller's trace
+        assert_eq!(trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+
+        tokio::task::spawn_blocking(move || provider.shutdown()).await.unwrap().unwrap();
+        let (head, body) = tokio::time::timeout(std::time::Duration::from_secs(10), collector)
+            .await
+            .expect("no spans were exported")
+            .unwrap();
+
+        assert!(head.starts_with("post /v1/traces"));
+        assert!(head.contains("content-type: application/x-protobuf"));
+        let contains = |needle: &[u8]| body.windows(needle.len()).any(|window| window == needle);
+        assert!(contains(b"http.request"));
+        assert!(contains(b"ai.provider.analyze"));
+        assert!(contains(b"synapse-web"));
+        assert!(contains(&hex::decode(&trace_id).unwrap()));
+    }
+}
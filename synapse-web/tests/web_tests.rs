@@ -14,4 +14,54 @@ async fn test_web_server_startup() {
     let mut config = HashMap::new();
     config.insert("port", "8080");
     assert_eq!(config.get("port"), Some(&"8080"));
-}
\ No newline at end of file
+}
+#[tokio::test]
+async fn test_share_password_attempts_are_rate_limited() {
+    use axum::{
+        extract::{Path, State},
+        http::{header, StatusCode},
+        Form,
+    };
+    use synapse_core::project::{Analysis, Project, Share};
+    use synapse_web::{
+        config::{RateLimit, WebConfig},
+        handlers::shares::{unlock_shared_analysis, SharePasswordForm},
+        middleware::auth::{hash_password, ClientIp},
+        AppState,
+    };
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut config = WebConfig {
+        database_url: format!("sqlite://{}", dir.path().join("synapse.db").display()),
+        ..WebConfig::default()
+    };
+    config.rate_limits.share_unlock = RateLimit::new(1, 2);
+    let state = AppState::new(config).await.unwrap();
+
+    let storage = state.db.storage();
+    let project = Project::new("shared".to_string(), None);
+    storage.insert_project(&project).await.unwrap();
+    let analysis = Analysis::new(project.id.clone(), None, "file".to_string(), "mock".to_string(), "ERROR".to_string());
+    storage.create_analysis(&analysis).await.unwrap();
+    let mut share = Share::new(project.id, analysis.id, None, chrono::Utc::now() + chrono::Duration::hours(1));
+    share.password_hash = Some(hash_password("correct horse".to_string()).await.unwrap());
+    storage.create_share(&share).await.unwrap();
+
+    let attempt = |address: &str, password: &str| {
+        unlock_shared_analysis(
+            State(state.clone()),
+            ClientIp(Some(address.to_string())),
+            Path(share.id.clone()),
+            Form(SharePasswordForm { password: password.to_string() }),
+        )
+    };
+
+    assert_eq!(attempt("192.0.2.1", "wrong").await.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(attempt("192.0.2.1", "wrong").await.status(), StatusCode::UNAUTHORIZED);
+    let limited = attempt("192.0.2.1", "correct horse").await;
+    assert_eq!(limited.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(limited.headers().contains_key(header::RETRY_AFTER));
+
+    // Another address has attempts of its own, but the link's are used up
+    assert_eq!(attempt("192.0.2.2", "wrong").await.status(), StatusCode::TOO_MANY_REQUESTS);
+}